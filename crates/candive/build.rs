@@ -0,0 +1,391 @@
+//! Generates the protocol constants, the Wireshark dissector and the DBC
+//! export from protocol.toml, see `candive::protocol`.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::{env, fs};
+
+use serde::Deserialize;
+
+const DEFINITIONS: &str = "protocol.toml";
+/// Extended-id prefix every DiveCAN frame carries
+const DIVECAN_PREFIX: u32 = 0x0D00_0000;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definitions {
+    #[serde(rename = "message")]
+    messages: Vec<Message>,
+    #[serde(rename = "did")]
+    dids: Vec<Did>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Message {
+    name: String,
+    kind: u8,
+    min_dlc: u8,
+    description: String,
+    category: Category,
+    direction: Direction,
+    /// `"name: Type"` of each named field
+    #[serde(default)]
+    fields: Vec<String>,
+    /// Type of the single unnamed field
+    tuple: Option<String>,
+    #[serde(default, rename = "signal")]
+    signals: Vec<Signal>,
+}
+
+/// Variant names match `MsgCategory`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Category {
+    Identity,
+    Sensor,
+    Control,
+    Calibration,
+    Diagnostic,
+    Undocumented,
+}
+
+/// Variant names match `MsgDirection`
+#[derive(Debug, Deserialize)]
+enum Direction {
+    #[serde(rename = "solo")]
+    SoloToBus,
+    #[serde(rename = "handset")]
+    HandsetToSolo,
+    #[serde(rename = "any")]
+    Any,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Signal {
+    name: String,
+    byte: u8,
+    bits: u8,
+    scale: f64,
+    unit: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Did {
+    name: String,
+    did: u16,
+    len: usize,
+    access: Access,
+    since: Option<String>,
+    #[serde(default)]
+    timeout: Timeout,
+    description: String,
+}
+
+/// Variant names match `DidAccess`
+#[derive(Debug, Deserialize)]
+enum Access {
+    #[serde(rename = "r")]
+    Read,
+    #[serde(rename = "w")]
+    Write,
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+/// Variant names match `DidTimeout`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Timeout {
+    #[default]
+    Normal,
+    Slow,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", DEFINITIONS);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let source = fs::read_to_string(DEFINITIONS).expect("read protocol.toml");
+    let defs: Definitions =
+        toml::from_str(&source).unwrap_or_else(|e| panic!("{}: {}", DEFINITIONS, e));
+
+    let out = env::var("OUT_DIR").unwrap();
+    let out = Path::new(&out);
+    fs::write(out.join("protocol.rs"), rust(&defs)).unwrap();
+    fs::write(out.join("msg.rs"), msg(&defs)).unwrap();
+    fs::write(out.join("divecan.lua"), dissector(&defs)).unwrap();
+    fs::write(out.join("divecan.dbc"), dbc(&defs)).unwrap();
+}
+
+/// Shortest decimal form, without the float noise of `0.01 * 255`
+fn number(v: f64) -> String {
+    let s = format!("{:.6}", v);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// `CellPpo2` -> `CELL_PPO2`, `UndocumentedC3` -> `UNDOCUMENTED_C3`,
+/// `Undocumented30` -> `UNDOCUMENTED_30`. A number of more than one digit
+/// is a word of its own, a single digit stays with its word as in `CO2`.
+fn screaming(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1);
+        let word = match prev {
+            Some(p) if c.is_ascii_uppercase() => p.is_ascii_lowercase() || p.is_ascii_digit(),
+            Some(p) if c.is_ascii_digit() => {
+                p.is_ascii_lowercase() && next.is_some_and(|n| n.is_ascii_digit())
+            }
+            _ => false,
+        };
+        if word {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+fn rust(defs: &Definitions) -> String {
+    let mut s = String::new();
+    s.push_str("/// Message kinds\npub mod kind {\n");
+    for m in &defs.messages {
+        writeln!(s, "    /// {}", m.description).unwrap();
+        writeln!(
+            s,
+            "    pub const {}: u8 = 0x{:02X};",
+            screaming(&m.name),
+            m.kind
+        )
+        .unwrap();
+    }
+    s.push_str("}\n\n/// Data identifiers\npub mod did {\n");
+    for d in &defs.dids {
+        writeln!(s, "    /// {}", d.description).unwrap();
+        writeln!(
+            s,
+            "    pub const {}: u16 = 0x{:04X};",
+            screaming(&d.name),
+            d.did
+        )
+        .unwrap();
+    }
+    s.push_str("}\n\npub const MESSAGES: &[MessageDef] = &[\n");
+    for m in &defs.messages {
+        writeln!(
+            s,
+            "    MessageDef {{\n        kind: kind::{},\n        name: {:?},\n        min_dlc: {},\n        description: {:?},\n        category: MsgCategory::{:?},\n        direction: MsgDirection::{:?},\n        signals: &[",
+            screaming(&m.name),
+            m.name,
+            m.min_dlc,
+            m.description,
+            m.category,
+            m.direction,
+        )
+        .unwrap();
+        for sig in &m.signals {
+            writeln!(
+                s,
+                "            SignalDef {{ name: {:?}, byte: {}, bits: {}, scale: {:?}, unit: {:?} }},",
+                sig.name, sig.byte, sig.bits, sig.scale as f32, sig.unit,
+            )
+            .unwrap();
+        }
+        s.push_str("        ],\n    },\n");
+    }
+    s.push_str("];\n\npub const DIDS: &[DidDef] = &[\n");
+    for d in &defs.dids {
+        writeln!(
+            s,
+            "    DidDef {{ did: did::{}, name: {:?}, len: {}, access: DidAccess::{:?}, since: {:?}, timeout: DidTimeout::{:?}, description: {:?} }},",
+            screaming(&d.name),
+            d.name,
+            d.len,
+            d.access,
+            d.since,
+            d.timeout,
+            d.description,
+        )
+        .unwrap();
+    }
+    s.push_str("];\n\n#[cfg(feature = \"diagnostics\")]\nconst _: () = {\n    use crate::diag::did::{solo::*, *};\n");
+    for d in &defs.dids {
+        writeln!(s, "    check_did::<{}>();", d.name).unwrap();
+    }
+    s.push_str("};\n");
+    s
+}
+
+/// The `Msg` enum and, by variant name, the kind and minimum DLC the codec
+/// table in `divecan` looks up
+fn msg(defs: &Definitions) -> String {
+    let mut s = String::from(
+        "#[derive(Debug, Clone, Copy, PartialEq)]\n\
+         #[cfg_attr(feature = \"defmt\", derive(defmt::Format))]\n\
+         pub enum Msg {\n",
+    );
+    for m in &defs.messages {
+        writeln!(s, "    /// {}", m.description).unwrap();
+        match (&m.tuple, m.fields.as_slice()) {
+            (Some(_), [_, ..]) => panic!(
+                "{}: {} has both fields and a tuple type",
+                DEFINITIONS, m.name
+            ),
+            (Some(ty), []) => writeln!(s, "    {}({}),", m.name, ty).unwrap(),
+            (None, []) => writeln!(s, "    {},", m.name).unwrap(),
+            (None, fields) => {
+                writeln!(s, "    {} {{", m.name).unwrap();
+                for field in fields {
+                    let (name, ty) = field.split_once(':').unwrap_or_else(|| {
+                        panic!(
+                            "{}: {}: expected \"name: Type\", got {:?}",
+                            DEFINITIONS, m.name, field
+                        )
+                    });
+                    writeln!(s, "        {}: {},", name.trim(), ty.trim()).unwrap();
+                }
+                s.push_str("    },\n");
+            }
+        }
+    }
+    s.push_str(
+        "}\n\n#[allow(non_upper_case_globals)]\nmod kind_of {\n    use crate::protocol::kind;\n",
+    );
+    for m in &defs.messages {
+        writeln!(
+            s,
+            "    pub const {}: u8 = kind::{};",
+            m.name,
+            screaming(&m.name)
+        )
+        .unwrap();
+    }
+    s.push_str("}\n\n#[allow(non_upper_case_globals)]\nmod min_dlc_of {\n");
+    for m in &defs.messages {
+        writeln!(s, "    pub const {}: u8 = {};", m.name, m.min_dlc).unwrap();
+    }
+    s.push_str("}\n");
+    s
+}
+
+fn signals_for(m: &Message) -> impl Iterator<Item = (String, &Signal)> {
+    let msg = m.name.to_ascii_lowercase();
+    m.signals
+        .iter()
+        .map(move |sig| (format!("{}_{}", msg, sig.name), sig))
+}
+
+fn dissector(defs: &Definitions) -> String {
+    let mut s = String::from(
+        "-- DiveCAN dissector for Wireshark, generated from candive's protocol.toml\n\
+         local divecan = Proto(\"divecan\", \"DiveCAN\")\n\n\
+         local kinds = {\n",
+    );
+    for m in &defs.messages {
+        writeln!(s, "    [0x{:02X}] = \"{}\",", m.kind, m.name).unwrap();
+    }
+    s.push_str(
+        "}\n\n\
+         local f_kind = ProtoField.uint8(\"divecan.kind\", \"Kind\", base.HEX, kinds)\n\
+         local f_src = ProtoField.uint8(\"divecan.src\", \"Source\", base.HEX)\n\
+         local f_dst = ProtoField.uint8(\"divecan.dst\", \"Destination\", base.HEX)\n",
+    );
+    let mut fields = vec!["f_kind".to_string(), "f_src".into(), "f_dst".into()];
+    for m in &defs.messages {
+        for (id, sig) in signals_for(m) {
+            let unit = match sig.unit.as_str() {
+                "" => String::new(),
+                unit => format!(" ({} {})", sig.scale, unit),
+            };
+            writeln!(
+                s,
+                "local f_{id} = ProtoField.uint{bits}(\"divecan.{id}\", \"{name}{unit}\", base.DEC)",
+                bits = sig.bits,
+                name = sig.name,
+            )
+            .unwrap();
+            fields.push(format!("f_{}", id));
+        }
+    }
+    writeln!(s, "\ndivecan.fields = {{ {} }}\n", fields.join(", ")).unwrap();
+    writeln!(
+        s,
+        "local can_id = Field.new(\"can.id\")\n\n\
+         function divecan.dissector(buf, pinfo, tree)\n\
+         \x20   local id = can_id()\n\
+         \x20   if not id or bit.band(id.value, 0x1F000000) ~= 0x{:08X} then\n\
+         \x20       return false\n\
+         \x20   end\n\
+         \x20   id = id.value\n\
+         \x20   local kind = bit.band(bit.rshift(id, 16), 0xFF)\n\
+         \x20   pinfo.cols.protocol = \"DiveCAN\"\n\
+         \x20   pinfo.cols.info = kinds[kind] or string.format(\"Unknown 0x%02X\", kind)\n\
+         \x20   local t = tree:add(divecan, buf())\n\
+         \x20   t:add(f_kind, kind)\n\
+         \x20   t:add(f_src, bit.band(id, 0xFF))\n\
+         \x20   t:add(f_dst, bit.band(bit.rshift(id, 8), 0xFF))",
+        DIVECAN_PREFIX
+    )
+    .unwrap();
+    for m in defs.messages.iter().filter(|m| !m.signals.is_empty()) {
+        writeln!(s, "    if kind == 0x{:02X} then", m.kind).unwrap();
+        for (id, sig) in signals_for(m) {
+            let (byte, len) = (sig.byte, sig.bits / 8);
+            writeln!(
+                s,
+                "        if buf:len() >= {} then t:add(f_{}, buf({}, {})) end",
+                byte + len,
+                id,
+                byte,
+                len
+            )
+            .unwrap();
+        }
+        s.push_str("    end\n");
+    }
+    s.push_str("    return true\nend\n\ndivecan:register_heuristic(\"can\", divecan.dissector)\n");
+    s
+}
+
+fn dbc(defs: &Definitions) -> String {
+    let mut s = String::from("VERSION \"\"\n\nNS_ :\n\nBS_:\n\nBU_:\n\n");
+    let frame_id = |m: &Message| 0x8000_0000 | DIVECAN_PREFIX | (m.kind as u32) << 16;
+    for m in &defs.messages {
+        writeln!(
+            s,
+            "BO_ {} {}: {} Vector__XXX",
+            frame_id(m),
+            m.name,
+            m.min_dlc
+        )
+        .unwrap();
+        for sig in &m.signals {
+            let (bits, scale) = (sig.bits, sig.scale);
+            // Big endian, start bit is the MSB of the first byte
+            writeln!(
+                s,
+                " SG_ {} : {}|{}@0+ ({},0) [0|{}] \"{}\" Vector__XXX",
+                sig.name,
+                sig.byte * 8 + 7,
+                bits,
+                scale,
+                number(scale * ((1u64 << bits) - 1) as f64),
+                sig.unit
+            )
+            .unwrap();
+        }
+        s.push('\n');
+    }
+    s.push_str(
+        "CM_ \"DiveCAN, generated from candive's protocol.toml. Source and destination \
+         addresses sit in the low 16 bits of the id, the ids here are for src = dst = 0.\";\n",
+    );
+    for m in &defs.messages {
+        writeln!(s, "CM_ BO_ {} \"{}\";", frame_id(m), m.description).unwrap();
+    }
+    s
+}
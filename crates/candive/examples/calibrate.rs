@@ -1,3 +1,4 @@
+use candive::calibration::CalibrationOutcome;
 use candive::divecan;
 use candive::divecan::DiveCanFrame;
 use socketcan::CanFrame;
@@ -20,13 +21,12 @@ fn main() -> anyhow::Result<()> {
     println!("Using can0");
 
     let socket = CanSocket::open("can0")?;
-    let msg = Msg::Ppo2CalibrationRequest {
-        fo2: 99.into(),
-        pressure: 1003.into(),
-    };
+    let msg = Msg::ppo2_calibration_request(99.into(), 1003.into())
+        .map_err(|e| anyhow::anyhow!("Invalid calibration input: {:?}", e))?;
     let id = DiveCanId::new(1, 4, msg.kind());
     let frame = to_can_frame(id, msg);
     socket.write_frame(&frame).unwrap();
+    let mut outcome = CalibrationOutcome::default();
     loop {
         let frame = socket.read_frame()?;
 
@@ -43,5 +43,9 @@ fn main() -> anyhow::Result<()> {
         let dc_frame = DiveCanFrame::new(id.kind, frame.dlc() as u8, payload).unwrap();
         let msg = Msg::try_from_frame(&dc_frame).unwrap();
         println!("msg {:?}", msg);
+        if outcome.push(&msg) && outcome.is_final() {
+            println!("calibration {:?}", outcome);
+            return Ok(());
+        }
     }
 }
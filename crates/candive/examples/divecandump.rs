@@ -1,5 +1,6 @@
 use candive::divecan;
 use candive::divecan::DiveCanFrame;
+use candive::divecan::DlcPolicy;
 use candive::uds::isotp::IsoTpRx;
 use candive::uds::isotp::IsoTpRxEvent;
 use socketcan::CanSocket;
@@ -7,6 +8,7 @@ use socketcan::EmbeddedFrame;
 use socketcan::Id;
 use socketcan::Socket;
 
+use crate::divecan::BusTraffic;
 use crate::divecan::DiveCanId;
 use crate::divecan::Msg;
 
@@ -16,6 +18,7 @@ use std::fmt;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::time::Instant;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct SessionKey {
@@ -58,21 +61,45 @@ fn b2(two: &[u8]) -> u8 {
     (hb(two[0]) << 4) | hb(two[1])
 }
 
+/// Drops half-received UDS transfers whose sender went quiet
+fn expire_sessions(now_ms: u64, sessions: &mut HashMap<SessionKey, IsoTpRx>) {
+    for (key, rx) in sessions.iter_mut() {
+        if let Err(err) = rx.expire(now_ms) {
+            println!("{:x} -> {:x} UDS: {:?}", key.src, key.dst, err);
+        }
+    }
+}
+
 fn handle_frame(
+    now_ms: u64,
     id: DiveCanId,
     dlc: u8,
     payload: &[u8; 8],
     sessions: &mut HashMap<SessionKey, IsoTpRx>,
 ) {
     let dc_frame = DiveCanFrame::new(id.kind, dlc, *payload).unwrap();
-    let msg = Msg::try_from_frame(&dc_frame).unwrap();
+    let decoded = match Msg::try_from_frame_with(&dc_frame, DlcPolicy::ZeroPad) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            println!("{:x} -> {:x} {:02x}, {:?}", id.src, id.dst, id.kind, err);
+            return;
+        }
+    };
+    let msg = decoded.msg;
+    let padded = if decoded.padded {
+        " (short, padded)"
+    } else {
+        ""
+    };
 
     match msg {
         Msg::Uds { dlc, data } => {
             let session_key: SessionKey = id.into();
-            let rx = sessions.entry(session_key).or_insert_with(IsoTpRx::new);
+            let rx = sessions.entry(session_key).or_insert_with(|| {
+                IsoTpRx::with_timeout(IsoTpRx::DEFAULT_TIMEOUT_MS).with_duplicate_tolerance(true)
+            });
 
-            match rx.on_frame(&data[..dlc as usize]) {
+            match rx.on_frame_at(now_ms, &data[..dlc as usize]) {
                 Ok(IsoTpRxEvent::Completed(total_len)) => {
                     let mut out = vec![0u8; total_len];
                     out.copy_from_slice(&rx.payload()[..total_len]);
@@ -94,7 +121,10 @@ fn handle_frame(
             }
         }
         _ => {
-            println!("{:x} -> {:x} {:02x}, {:?}", id.src, id.dst, id.kind, msg);
+            println!(
+                "{:x} -> {:x} {:02x}, {:?}{}",
+                id.src, id.dst, id.kind, msg, padded
+            );
         }
     }
 }
@@ -103,23 +133,32 @@ fn dumplive() -> anyhow::Result<()> {
     let socket = CanSocket::open("can0")?;
     println!("Listening on can0...");
     let mut sessions = HashMap::new();
+    let start = Instant::now();
 
     loop {
         let frame = socket.read_frame()?;
+        let now_ms = start.elapsed().as_millis() as u64;
+        expire_sessions(now_ms, &mut sessions);
 
         let Id::Extended(extended_id) = frame.id() else {
             println!("Standard IDs not supported");
             continue;
         };
 
-        let id: DiveCanId = extended_id.as_raw().into();
+        let id = match BusTraffic::classify(extended_id.as_raw()) {
+            BusTraffic::DiveCan(id) => id,
+            BusTraffic::Other(raw) => {
+                println!("{:08X} not DiveCAN, {:02X?}", raw, frame.data());
+                continue;
+            }
+        };
 
         let data = frame.data();
         let mut payload = [0u8; 8];
         let len = data.len().min(8);
         payload[..len].copy_from_slice(&data[..len]);
 
-        handle_frame(id, frame.dlc() as u8, &payload, &mut sessions);
+        handle_frame(now_ms, id, frame.dlc() as u8, &payload, &mut sessions);
     }
 }
 
@@ -134,7 +173,13 @@ fn dumpfile(path: String) {
         }
 
         // "(030.026910) can0 0D010004#432D696E61746F72"
-        let (_ts_part, rest) = s.split_once(')').unwrap();
+        let (ts_part, rest) = s.split_once(')').unwrap();
+        let now_ms = ts_part
+            .trim_start_matches('(')
+            .parse::<f64>()
+            .map(|secs| (secs * 1000.0) as u64)
+            .unwrap_or(0);
+        expire_sessions(now_ms, &mut sessions);
 
         let mut it = rest.trim().split_whitespace();
         let _iface = it.next().unwrap();
@@ -149,9 +194,12 @@ fn dumpfile(path: String) {
         for i in 0..dlc {
             data[i] = b2(&db[i * 2..i * 2 + 2]);
         }
-        let did: DiveCanId = id.into();
+        let Some(did) = DiveCanId::try_from_u32(id) else {
+            println!("{:08X} not DiveCAN, {}", id, data_hex);
+            continue;
+        };
 
-        handle_frame(did, dlc as u8, &data, &mut sessions);
+        handle_frame(now_ms, did, dlc as u8, &data, &mut sessions);
     }
 }
 
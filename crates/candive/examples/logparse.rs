@@ -1,13 +1,17 @@
-use candive::diag::solo::LogEntryIterator;
-use candive::divecan::{DiveCanFrame, DiveCanId, Msg};
+use candive::diag::solo::{LogEntryIterator, LogProfile};
+use candive::divecan::Msg;
 use std::env;
 use std::fs::File;
 use std::io::Read;
 
 fn main() -> std::io::Result<()> {
     let args: Vec<String> = env::args().collect();
+    if args.iter().any(|a| a == "--kinds") {
+        print_kinds();
+        return Ok(());
+    }
     if args.len() < 2 {
-        eprintln!("Usage: {} [--divecan] <log.bin>", args[0]);
+        eprintln!("Usage: {} [--divecan] <log.bin> | --kinds", args[0]);
         std::process::exit(1);
     }
 
@@ -20,73 +24,37 @@ fn main() -> std::io::Result<()> {
     let mut data = Vec::new();
     file.read_to_end(&mut data)?;
 
+    let profile = LogProfile::SOLO;
     for entry in LogEntryIterator::new(&data) {
-        let can_id = 0x0D000000u32 | ((entry.kind as u32) << 16) | 0x0004;
-        let dlc = Msg::dlc_min_size(entry.kind).unwrap_or(8);
+        let (id, frame) = entry.to_frame(&profile);
 
         if divecan_mode {
-            if let Ok(frame) = DiveCanFrame::new(entry.kind, dlc, entry.payload) {
-                if let Ok(msg) = Msg::try_from_frame(&frame) {
-                    let id: DiveCanId = can_id.into();
-                    println!("{:02x} -> {:02x}: {:?}", id.src, id.dst, msg);
-                }
+            if let Ok(msg) = Msg::try_from_frame(&frame) {
+                println!("{:02x} -> {:02x}: {:?}", id.src, id.dst, msg);
             }
         } else {
-            let payload_str = entry.payload[..dlc as usize]
+            let payload_str = frame
+                .bytes()
                 .iter()
                 .map(|b| format!("{:02X}", b))
                 .collect::<Vec<_>>()
                 .join(" ");
-            println!("  can0  {:08X}   [{}]  {}", can_id, dlc, payload_str);
+            println!(
+                "  can0  {:08X}   [{}]  {}",
+                id.to_u32(),
+                frame.dlc(),
+                payload_str
+            );
         }
     }
 
     Ok(())
 }
 
-/*
-
-#!/usr/bin/env python3
-import sys
-
-DLC = {
-    0x00:3, # Id
-    0x01:8, # DeviceName
-    0x02:3, # Alert
-    0x03:1, # ShutdownInit
-    0x04:4, # CellPpo2
-    0x07:5, # OboeStatus
-    0x08:5, # AmbientPressure
-    0x0A:8, # Uds
-    0x0B:3, # TankPressure
-    0x10:8, # Nop
-    0x11:7, # CellVoltages
-    0x12:8, # Ppo2CalibrationResponse
-    0x13:3, # Ppo2CalibrationRequest
-    0x20:1, # Co2Enabled
-    0x21:3, # Co2
-    0x22:3, # Co2CalibrationResponse
-    0x23:2, # Co2CalibrationRequest
-    0x30:3, # Undocumented30
-    0x37:3, # BusInit
-    0xC1:3, # TempProbe
-    0xC3:6, # UndocumentedC3
-    0xC4:1, # TempProbeEnabled
-    0xC9:1, # Setpoint
-    0xCA:2, # CellStatus
-    0xCB:8, # SoloStatus
-    0xCC:7, # Diving
-    0xD2:8  # Serial
+/// Kind, name and the DLC entries are printed with, for scripts that turn
+/// raw log slots into frames themselves
+fn print_kinds() {
+    for info in Msg::KINDS {
+        println!("0x{:02X} {} {}", info.kind, info.name, info.min_dlc);
+    }
 }
-
-data = open(sys.argv[1], 'rb').read()
-kind = 0x00
-for i in range(len(data) // 12):
-    entry = data[i*12:(i+1)*12]
-    if entry != b'\xff'*12 and entry != b'\x00'*12:
-        can_id = 0x0D000000 | (kind << 16) | 0x0004
-        dlc = DLC.get(kind, 8)
-        payload = ' '.join(f'{b:02X}' for b in entry[0:dlc])
-        print(f"  can0  {can_id:08X}   [{dlc}]  {payload}")
-    kind = entry[10]
-*/
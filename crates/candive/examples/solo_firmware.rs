@@ -0,0 +1,575 @@
+//! Skeleton of a Solo-like DiveCAN node built on candive.
+//!
+//! Everything inside `mod firmware` only uses `core` and candive, so it can be
+//! moved as-is into a `#![no_std]` firmware crate. The hardware is reached through
+//! the small `CanBus` trait: on an STM32 implement it on top of the bxCAN driver
+//! (extended ids, one mailbox per call) and call `Node::on_frame` from the RX
+//! interrupt and `Node::poll` from a millisecond tick.
+//!
+//! `main` runs the same code on the host with a loopback bus that prints frames
+//! in candump format. With `--selftest` it instead runs a tester client
+//! against the node over a virtual bus, going through every service and
+//! message kind the node implements, and prints what worked: a smoke test
+//! of the whole stack after changes. `--tx-dlc pad8` makes the node pad
+//! every frame it sends to DLC 8, for adapters that need it.
+
+mod firmware {
+    use candive::alerts::SoloAlert;
+    use candive::diag::did::{DataIdentifier, FirmwareVersionAscii, SerialNumberAscii};
+    use candive::divecan::identity::{DeviceType, IdentityBurst};
+    use candive::divecan::{
+        BROADCAST_ADDR, Consensus, DiveCanFrame, DiveCanId, Msg, SoloStatusFields, TxDlcPolicy,
+    };
+    use candive::uds::isotp::{
+        FlowControl, FlowStatus, IsoTpFrame, IsoTpRx, IsoTpRxEvent, IsoTpTx, make_flow_control_cts,
+    };
+    use candive::uds::{
+        ReadByIdentifierCodec, ReadByIdentifierResp, ServiceCodec, UdsErrorCode, UdsPduView,
+        UdsPduWriter,
+    };
+    use candive::units::{Decivolt, PpO2Deci};
+
+    /// What the firmware needs from the CAN peripheral.
+    pub trait CanBus {
+        type Error;
+
+        fn transmit(&mut self, id: u32, data: &[u8]) -> Result<(), Self::Error>;
+    }
+
+    pub const NODE_ADDR: u8 = 0x04;
+
+    const SERIAL: [u8; 8] = *b"00000042";
+
+    /// Solo raises SetpointTimeout when the handset stops broadcasting a setpoint
+    const SETPOINT_TIMEOUT_MS: u64 = 10_000;
+
+    #[derive(Clone, Copy)]
+    enum Broadcast {
+        Identity,
+        CellPpo2,
+    }
+
+    /// Periodic broadcasts and their period in milliseconds
+    const SCHEDULE: [(Broadcast, u64); 2] =
+        [(Broadcast::Identity, 5_000), (Broadcast::CellPpo2, 500)];
+
+    pub struct Node {
+        next_due_ms: [u64; SCHEDULE.len()],
+        isotp_rx: IsoTpRx,
+        /// Response waiting for the tester's flow control
+        tx_buf: [u8; 64],
+        tx_len: usize,
+        /// Frames of the response already sent
+        tx_sent: usize,
+        tx_peer: u8,
+        cells: [PpO2Deci; 3],
+        setpoint: PpO2Deci,
+        last_setpoint_ms: u64,
+        setpoint_alert_sent: bool,
+        tx_dlc: TxDlcPolicy,
+    }
+
+    impl Node {
+        pub const fn new() -> Self {
+            Self {
+                next_due_ms: [0; SCHEDULE.len()],
+                isotp_rx: IsoTpRx::new(),
+                tx_buf: [0; 64],
+                tx_len: 0,
+                tx_sent: 0,
+                tx_peer: 0,
+                cells: [PpO2Deci::new(0); 3],
+                setpoint: PpO2Deci::new(0),
+                last_setpoint_ms: 0,
+                setpoint_alert_sent: false,
+                tx_dlc: TxDlcPolicy::Minimal,
+            }
+        }
+
+        /// Frames go out with their minimal DLC unless told otherwise
+        pub const fn with_tx_dlc(mut self, policy: TxDlcPolicy) -> Self {
+            self.tx_dlc = policy;
+            self
+        }
+
+        pub fn set_cells(&mut self, cells: [PpO2Deci; 3]) {
+            self.cells = cells;
+        }
+
+        /// Call from a timer tick. Sends due broadcasts and raises alerts.
+        pub fn poll<B: CanBus>(&mut self, now_ms: u64, bus: &mut B) -> Result<(), B::Error> {
+            for (i, (broadcast, period_ms)) in SCHEDULE.iter().enumerate() {
+                if now_ms >= self.next_due_ms[i] {
+                    self.next_due_ms[i] = now_ms + period_ms;
+                    match broadcast {
+                        Broadcast::Identity => self.announce(bus)?,
+                        Broadcast::CellPpo2 => {
+                            self.send(bus, BROADCAST_ADDR, &Msg::CellPpo2(self.cells.into()))?
+                        }
+                    }
+                }
+            }
+
+            if !self.setpoint_alert_sent
+                && now_ms.saturating_sub(self.last_setpoint_ms) >= SETPOINT_TIMEOUT_MS
+            {
+                self.setpoint_alert_sent = true;
+                self.raise_alert(bus, SoloAlert::SoloSetpointTimeout)?;
+            }
+            Ok(())
+        }
+
+        /// Call for every received extended-id frame.
+        pub fn on_frame<B: CanBus>(
+            &mut self,
+            now_ms: u64,
+            raw_id: u32,
+            data: &[u8],
+            bus: &mut B,
+        ) -> Result<(), B::Error> {
+            let id = DiveCanId::from_u32(raw_id);
+            if id.dst != NODE_ADDR && id.dst != BROADCAST_ADDR {
+                return Ok(());
+            }
+
+            let mut payload = [0u8; 8];
+            let len = data.len().min(8);
+            payload[..len].copy_from_slice(&data[..len]);
+            let Ok(frame) = DiveCanFrame::new(id.kind, len as u8, payload) else {
+                return Ok(());
+            };
+
+            match Msg::try_from_frame(&frame) {
+                Ok(Msg::Setpoint(setpoint)) => {
+                    self.setpoint = setpoint;
+                    self.last_setpoint_ms = now_ms;
+                    self.setpoint_alert_sent = false;
+                    Ok(())
+                }
+                // The handset enumerating the bus
+                Ok(Msg::BusInit { .. }) => self.announce(bus),
+                Ok(Msg::Uds { dlc, data }) if id.dst == NODE_ADDR => {
+                    self.on_uds_frame(id.src, &data[..dlc as usize], bus)
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn announce<B: CanBus>(&self, bus: &mut B) -> Result<(), B::Error> {
+            let status = SoloStatusFields {
+                voltage: Decivolt::new(72),
+                current: 0.into(),
+                injection_duration: 0.into(),
+                setpoint: self.setpoint,
+                consensus: Consensus::PpO2(self.cells[0]),
+                voltage_alert: None,
+                current_alert: None,
+            };
+            let burst = IdentityBurst::new(DeviceType::Solo(status), 0x01, 0x01, *b"SKELETON")
+                .with_serial(SERIAL);
+            for (id, frame) in burst.frames(NODE_ADDR) {
+                self.transmit(bus, id, &frame)?;
+            }
+            Ok(())
+        }
+
+        fn raise_alert<B: CanBus>(&self, bus: &mut B, alert: SoloAlert) -> Result<(), B::Error> {
+            self.send(bus, BROADCAST_ADDR, &Msg::alert_from(alert))
+        }
+
+        fn on_uds_frame<B: CanBus>(
+            &mut self,
+            peer: u8,
+            data: &[u8],
+            bus: &mut B,
+        ) -> Result<(), B::Error> {
+            let fc = IsoTpFrame::new(data).and_then(|f| FlowControl::try_from(&f).ok());
+            if let Some(fc) = fc {
+                if fc.status == FlowStatus::Overflow {
+                    self.tx_len = 0;
+                    return Ok(());
+                }
+                // Resume after what went out, one block per CTS
+                let mut tx = IsoTpTx::new(&self.tx_buf[..self.tx_len]);
+                tx.by_ref().take(self.tx_sent).for_each(drop);
+                for frame in tx.block(&fc) {
+                    self.send_uds(bus, self.tx_peer, frame.as_slice())?;
+                    self.tx_sent += 1;
+                }
+                if tx.is_done() {
+                    self.tx_len = 0;
+                }
+                return Ok(());
+            }
+
+            match self.isotp_rx.on_frame(data) {
+                Ok(IsoTpRxEvent::FlowControlRequired) => {
+                    self.send_uds(bus, peer, make_flow_control_cts(0, 0).as_slice())
+                }
+                Ok(IsoTpRxEvent::Completed(_)) => {
+                    let mut request = [0u8; 64];
+                    let payload = self.isotp_rx.payload();
+                    let len = payload.len().min(request.len());
+                    request[..len].copy_from_slice(&payload[..len]);
+
+                    self.tx_len =
+                        handle_request(UdsPduView::new(&request[..len]), &mut self.tx_buf);
+                    self.tx_peer = peer;
+
+                    let mut frames = IsoTpTx::new(&self.tx_buf[..self.tx_len]);
+                    if let Some(first) = frames.next() {
+                        self.send_uds(bus, self.tx_peer, first.as_slice())?;
+                    }
+                    self.tx_sent = 1;
+                    Ok(())
+                }
+                Ok(IsoTpRxEvent::None) => Ok(()),
+                Err(_) => {
+                    self.isotp_rx.reset();
+                    Ok(())
+                }
+            }
+        }
+
+        fn send<B: CanBus>(&self, bus: &mut B, dst: u8, msg: &Msg) -> Result<(), B::Error> {
+            let id = DiveCanId::new(NODE_ADDR, dst, msg.kind());
+            self.transmit(bus, id, &msg.to_frame())
+        }
+
+        /// `data` is one ISO-TP frame, at most 8 bytes
+        fn send_uds<B: CanBus>(&self, bus: &mut B, dst: u8, data: &[u8]) -> Result<(), B::Error> {
+            let mut frame = [0u8; 8];
+            frame[..data.len()].copy_from_slice(data);
+            let msg = Msg::Uds {
+                dlc: data.len() as u8,
+                data: frame,
+            };
+            self.send(bus, dst, &msg)
+        }
+
+        /// Every frame goes out through here, padded per `tx_dlc`
+        fn transmit<B: CanBus>(
+            &self,
+            bus: &mut B,
+            id: DiveCanId,
+            frame: &DiveCanFrame,
+        ) -> Result<(), B::Error> {
+            bus.transmit(id.to_u32(), frame.with_tx_dlc(self.tx_dlc).bytes())
+        }
+    }
+
+    /// Builds the response PDU for a request, returns its length.
+    fn handle_request(req: UdsPduView<'_>, out: &mut [u8]) -> usize {
+        let sid = req.sid().unwrap_or(0);
+        let result = match sid {
+            ReadByIdentifierCodec::REQ_SID => match ReadByIdentifierCodec::decode_request(req) {
+                Ok(rdbi) => read_did(rdbi.did, out),
+                Err(_) => Err(UdsErrorCode::IncorrectMessageLengthOrInvalidFormat),
+            },
+            _ => Err(UdsErrorCode::GeneralReject),
+        };
+
+        match result {
+            Ok(len) => len,
+            Err(code) => UdsPduWriter::make_negative_response(out, sid, code)
+                .map(|w| w.len())
+                .unwrap_or(0),
+        }
+    }
+
+    fn read_did(did: u16, out: &mut [u8]) -> Result<usize, UdsErrorCode> {
+        let serial = SerialNumberAscii {
+            serial_ascii: SERIAL,
+        };
+        let version = FirmwareVersionAscii {
+            firmware_version_ascii: *b"010",
+        };
+
+        let data: &[u8] = match did {
+            SerialNumberAscii::DID => &serial.to_bytes(),
+            FirmwareVersionAscii::DID => &version.to_bytes(),
+            _ => return Err(UdsErrorCode::RequestOutOfRange),
+        };
+
+        let mut writer = UdsPduWriter::new(out);
+        ReadByIdentifierCodec::encode_response(&ReadByIdentifierResp { did, data }, &mut writer)
+            .map_err(|_| UdsErrorCode::GeneralReject)?;
+        Ok(writer.len())
+    }
+}
+
+use candive::diag::did::{DataIdentifier, FirmwareVersionAscii, SerialNumberAscii};
+use candive::divecan::{DiveCanFrame, DiveCanId, Msg, TxDlcPolicy};
+use candive::protocol::kind;
+use candive::uds::UdsErrorCode;
+use candive::uds::client::{self, UdsClientError, UdsTransport};
+use candive::uds::isotp::{IsoTpRx, IsoTpRxEvent, IsoTpTx, make_flow_control_cts};
+use firmware::{CanBus, NODE_ADDR, Node};
+
+const HANDSET: u8 = 0x01;
+
+/// Host stand-in for bxCAN, prints every transmitted frame
+struct LoopbackBus {
+    sent: Vec<(u32, Vec<u8>)>,
+}
+
+impl CanBus for LoopbackBus {
+    type Error = core::convert::Infallible;
+
+    fn transmit(&mut self, id: u32, data: &[u8]) -> Result<(), Self::Error> {
+        let payload_str = data
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!("  vcan0  {:08X}   [{}]  {}", id, data.len(), payload_str);
+        self.sent.push((id, data.to_vec()));
+        Ok(())
+    }
+}
+
+/// Collects what the node transmits without printing it
+#[derive(Default)]
+struct VirtualBus {
+    sent: Vec<(DiveCanId, Vec<u8>)>,
+}
+
+impl CanBus for VirtualBus {
+    type Error = core::convert::Infallible;
+
+    fn transmit(&mut self, id: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.sent.push((DiveCanId::from_u32(id), data.to_vec()));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum TesterError {
+    /// The node sent nothing back, or a frame that doesn't reassemble
+    NoResponse,
+}
+
+/// UDS client on the handset address, talking ISO-TP to the node in-process
+struct Tester<'a> {
+    node: &'a mut Node,
+    bus: VirtualBus,
+    now_ms: u64,
+}
+
+impl Tester<'_> {
+    /// Hands a frame from the handset to the node, returns the UDS frames
+    /// the node answered the handset with
+    fn exchange(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let id = DiveCanId::new(HANDSET, NODE_ADDR, kind::UDS).to_u32();
+        let before = self.bus.sent.len();
+        let _ = self.node.on_frame(self.now_ms, id, data, &mut self.bus);
+        self.bus.sent[before..]
+            .iter()
+            .filter(|(id, _)| id.dst == HANDSET && id.kind == kind::UDS)
+            .map(|(_, data)| data.clone())
+            .collect()
+    }
+}
+
+impl UdsTransport for Tester<'_> {
+    type Error = TesterError;
+
+    fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, TesterError> {
+        // Requests here are single frames, the node's answers may not be
+        let mut answer = Vec::new();
+        for frame in IsoTpTx::new(req) {
+            answer.extend(self.exchange(frame.as_slice()));
+        }
+        let mut rx = IsoTpRx::new();
+        let mut queue = std::collections::VecDeque::from(answer);
+        while let Some(frame) = queue.pop_front() {
+            match rx.on_frame(&frame).map_err(|_| TesterError::NoResponse)? {
+                IsoTpRxEvent::FlowControlRequired => {
+                    queue.extend(self.exchange(make_flow_control_cts(0, 0).as_slice()))
+                }
+                IsoTpRxEvent::Completed(_) => {
+                    let payload = rx.payload();
+                    resp_buf[..payload.len()].copy_from_slice(payload);
+                    return Ok(payload.len());
+                }
+                IsoTpRxEvent::None => {}
+            }
+        }
+        Err(TesterError::NoResponse)
+    }
+
+    /// Whatever the node answers is dropped, as on a bus
+    fn send(&mut self, req: &[u8]) -> Result<(), TesterError> {
+        for frame in IsoTpTx::new(req) {
+            self.exchange(frame.as_slice());
+        }
+        Ok(())
+    }
+}
+
+/// Passes if the node refused with `code`
+fn expect_nrc<T>(
+    result: Result<T, UdsClientError<TesterError>>,
+    code: UdsErrorCode,
+) -> Result<(), String> {
+    match result {
+        Err(UdsClientError::NegativeResponse(neg)) if neg.code == code => Ok(()),
+        other => Err(format!("expected {:?}, got {:?}", code, other.map(drop))),
+    }
+}
+
+/// Runs the node against [`Tester`] and prints a line per service and
+/// message kind. Returns whether everything passed.
+fn selftest(tx_dlc: TxDlcPolicy) -> bool {
+    let mut node = Node::new().with_tx_dlc(tx_dlc);
+    node.set_cells([98.into(), 99.into(), 97.into()]);
+    let mut tester = Tester {
+        node: &mut node,
+        bus: VirtualBus::default(),
+        now_ms: 0,
+    };
+    let mut rows: Vec<(String, Result<(), String>)> = Vec::new();
+
+    let mut buf = [0u8; 64];
+    let serial = client::rdbi_into(&mut tester, SerialNumberAscii::DID, &mut buf)
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|len| SerialNumberAscii::try_from(&buf[..len]).map_err(|e| format!("{:?}", e)));
+    rows.push((
+        "ReadDataByIdentifier SerialNumberAscii (multi-frame)".into(),
+        serial.map(drop),
+    ));
+    let version = client::rdbi_into(&mut tester, FirmwareVersionAscii::DID, &mut buf)
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|len| {
+            FirmwareVersionAscii::try_from(&buf[..len]).map_err(|e| format!("{:?}", e))
+        });
+    rows.push((
+        "ReadDataByIdentifier FirmwareVersionAscii".into(),
+        version.map(drop),
+    ));
+    rows.push((
+        "ReadDataByIdentifier unknown DID".into(),
+        expect_nrc(
+            client::rdbi_into(&mut tester, 0xF1F1, &mut buf),
+            UdsErrorCode::RequestOutOfRange,
+        ),
+    ));
+    let mut rx_buf = [0u8; 64];
+    rows.push((
+        "Unsupported service (WriteDataByIdentifier)".into(),
+        expect_nrc(
+            client::wdbi(&mut tester, 0x8010, &[0], &mut [0u8; 16], &mut rx_buf),
+            UdsErrorCode::GeneralReject,
+        ),
+    ));
+
+    // Broadcasts: a handset setpoint and bus enumeration, then silence long
+    // enough for the setpoint timeout alert
+    let bus_init = Msg::BusInit { unused: [0; 3] };
+    for msg in [Msg::Setpoint(7.into()), bus_init] {
+        let id = DiveCanId::new(HANDSET, 0xFF, msg.kind()).to_u32();
+        let _ = tester
+            .node
+            .on_frame(0, id, msg.to_frame().bytes(), &mut tester.bus);
+    }
+    for now_ms in (0..=12_000).step_by(100) {
+        let _ = tester.node.poll(now_ms, &mut tester.bus);
+    }
+    let mut seen: Vec<(u8, Result<(), String>)> = Vec::new();
+    for (id, data) in &tester.bus.sent {
+        if id.kind == kind::UDS || seen.iter().any(|(k, r)| *k == id.kind && r.is_ok()) {
+            continue;
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..data.len()].copy_from_slice(data);
+        let decoded = DiveCanFrame::new(id.kind, data.len() as u8, bytes)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|f| Msg::try_from_frame(&f).map_err(|e| format!("{:?}", e)));
+        seen.retain(|(k, _)| *k != id.kind);
+        seen.push((id.kind, decoded.map(drop)));
+    }
+    let expected = [
+        kind::ID,
+        kind::DEVICE_NAME,
+        kind::SERIAL,
+        kind::SOLO_STATUS,
+        kind::CELL_PPO2,
+        kind::ALERT,
+    ];
+    for kind in expected {
+        let name = candive::protocol::message(kind).map_or("?", |m| m.name);
+        let result = seen
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map_or(Err("never sent".to_string()), |(_, r)| r.clone());
+        rows.push((format!("Message {} (0x{:02X})", name, kind), result));
+    }
+    if tx_dlc == TxDlcPolicy::PadTo8 {
+        let short = tester
+            .bus
+            .sent
+            .iter()
+            .filter(|(_, data)| data.len() != 8)
+            .count();
+        rows.push((
+            "Frames padded to DLC 8".into(),
+            match short {
+                0 => Ok(()),
+                n => Err(format!("{} frames shorter", n)),
+            },
+        ));
+    }
+
+    let mut passed = true;
+    for (what, result) in &rows {
+        match result {
+            Ok(()) => println!("  ok    {}", what),
+            Err(e) => {
+                passed = false;
+                println!("  FAIL  {}: {}", what, e);
+            }
+        }
+    }
+    let failed = rows.iter().filter(|(_, r)| r.is_err()).count();
+    println!("{} of {} passed", rows.len() - failed, rows.len());
+    passed
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let tx_dlc = if args
+        .windows(2)
+        .any(|w| w[0] == "--tx-dlc" && w[1] == "pad8")
+    {
+        TxDlcPolicy::PadTo8
+    } else {
+        TxDlcPolicy::Minimal
+    };
+    if args.iter().any(|a| a == "--selftest") {
+        std::process::exit(if selftest(tx_dlc) { 0 } else { 1 });
+    }
+
+    let mut bus = LoopbackBus { sent: Vec::new() };
+    let mut node = Node::new().with_tx_dlc(tx_dlc);
+    node.set_cells([98.into(), 99.into(), 97.into()]);
+
+    let setpoint = Msg::Setpoint(7.into());
+    let setpoint_id = DiveCanId::new(HANDSET, 0xFF, setpoint.kind()).to_u32();
+    let uds_id = DiveCanId::new(HANDSET, NODE_ADDR, kind::UDS).to_u32();
+
+    for now_ms in (0..12_000).step_by(100) {
+        // Handset broadcasts a setpoint for the first second, then goes quiet
+        if now_ms < 1_000 {
+            let _ = node.on_frame(now_ms, setpoint_id, setpoint.to_frame().bytes(), &mut bus);
+        }
+        // Read serial (0x8010): single-frame request, multi-frame response
+        if now_ms == 2_000 {
+            println!("-- RDBI 0x8010");
+            let _ = node.on_frame(now_ms, uds_id, &[0x04, 0x00, 0x22, 0x80, 0x10], &mut bus);
+            let _ = node.on_frame(now_ms, uds_id, &[0x30, 0x00, 0x00], &mut bus);
+        }
+        let _ = node.poll(now_ms, &mut bus);
+    }
+
+    println!("{} frames sent", bus.sent.len());
+}
@@ -147,4 +147,61 @@ impl SoloAlert {
     }
 }
 
-//TODO: There is a error case 0x302 handled by handset
+/// Any known alert, for code that raises alerts without caring which node
+/// type defines them, see [`Msg::alert_from`](crate::divecan::Msg::alert_from).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyAlert {
+    Handset(HandsetAlert),
+    Solo(SoloAlert),
+    Temp(TempAlert),
+}
+
+impl AnyAlert {
+    pub fn from_u16(v: u16) -> Option<Self> {
+        HandsetAlert::from_u16(v)
+            .map(Self::Handset)
+            .or_else(|| SoloAlert::from_u16(v).map(Self::Solo))
+            .or_else(|| TempAlert::from_u16(v).map(Self::Temp))
+    }
+
+    pub fn to_u16(self) -> u16 {
+        match self {
+            Self::Handset(a) => a.to_u16(),
+            Self::Solo(a) => a.to_u16(),
+            Self::Temp(a) => a.to_u16(),
+        }
+    }
+
+    /// First byte of the alert frame. Captures show 1 and 2 there without a
+    /// known meaning; the Solo sends 1, handset shutdown alerts have been
+    /// seen with 2.
+    pub fn default_unknown(self) -> u8 {
+        match self {
+            Self::Handset(_) => 2,
+            Self::Solo(_) | Self::Temp(_) => 1,
+        }
+    }
+}
+
+impl From<HandsetAlert> for AnyAlert {
+    fn from(a: HandsetAlert) -> Self {
+        Self::Handset(a)
+    }
+}
+
+impl From<SoloAlert> for AnyAlert {
+    fn from(a: SoloAlert) -> Self {
+        Self::Solo(a)
+    }
+}
+
+impl From<TempAlert> for AnyAlert {
+    fn from(a: TempAlert) -> Self {
+        Self::Temp(a)
+    }
+}
+
+//TODO: There is a error case 0x302 handled by handset. How a handset
+// acknowledges or clears an alert is still unknown, no ack message or DID has
+// been identified yet. Until then alerts only clear on the device side, and
+// monitor::EventStream reports them cleared once they stop repeating.
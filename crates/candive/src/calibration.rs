@@ -0,0 +1,162 @@
+use crate::cells::CellArray;
+use crate::divecan::{CalStatusCode, CellsActive, Msg};
+use crate::units::{Fo2, Millibar, Millivolt};
+
+/// Lowest FO₂ (%) accepted for an O₂ cell calibration
+pub const FO2_MIN_PERCENT: u32 = 70;
+/// Highest FO₂ (%) accepted for an O₂ cell calibration
+pub const FO2_MAX_PERCENT: u32 = 100;
+/// Lowest atmospheric pressure (mbar) accepted for an O₂ cell calibration
+pub const PRESSURE_MIN_MBAR: u32 = 600;
+/// Highest atmospheric pressure (mbar) accepted for an O₂ cell calibration
+pub const PRESSURE_MAX_MBAR: u32 = 1050;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationError {
+    O2OutOfRange(u32),
+    PressureOutOfRange(u32),
+}
+
+/// Plausibility check shared by the UDS (DID 0x8204) and DiveCAN calibration paths.
+pub fn validate_calibration_inputs(
+    fo2_percent: u32,
+    pressure_mbar: u32,
+) -> Result<(), CalibrationError> {
+    if !(FO2_MIN_PERCENT..=FO2_MAX_PERCENT).contains(&fo2_percent) {
+        return Err(CalibrationError::O2OutOfRange(fo2_percent));
+    }
+    if !(PRESSURE_MIN_MBAR..=PRESSURE_MAX_MBAR).contains(&pressure_mbar) {
+        return Err(CalibrationError::PressureOutOfRange(pressure_mbar));
+    }
+    Ok(())
+}
+
+/// Same as [`validate_calibration_inputs`], for bus unit types.
+pub fn validate_calibration_units(fo2: Fo2, pressure: Millibar) -> Result<(), CalibrationError> {
+    validate_calibration_inputs(fo2.raw() as u32, pressure.raw() as u32)
+}
+
+/// Where a DiveCAN ppO₂ calibration stands, summarized from the
+/// `Ppo2CalibrationResponse` frames seen so far: `Ack` while it runs, then
+/// one final status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationOutcome {
+    /// No response yet
+    #[default]
+    Pending,
+    /// Acknowledged, the unit is calibrating
+    Running,
+    Succeeded {
+        cell_voltages: CellArray<Millivolt>,
+        cells_active: CellsActive,
+        fo2: Fo2,
+        pressure: Millibar,
+    },
+    /// Refused or failed, the reason is never [`CalStatusCode::Ack`] or `Success`
+    Failed(CalStatusCode),
+}
+
+impl CalibrationOutcome {
+    /// Feeds a message, returns whether the outcome changed. Other messages
+    /// and responses after a final status are ignored.
+    pub fn push(&mut self, msg: &Msg) -> bool {
+        let Msg::Ppo2CalibrationResponse {
+            status,
+            cell_voltages,
+            fo2,
+            pressure,
+            cells_active,
+        } = *msg
+        else {
+            return false;
+        };
+        let next = match status {
+            _ if self.is_final() => return false,
+            CalStatusCode::Ack => CalibrationOutcome::Running,
+            CalStatusCode::Success => CalibrationOutcome::Succeeded {
+                cell_voltages,
+                cells_active,
+                fo2,
+                pressure,
+            },
+            failed => CalibrationOutcome::Failed(failed),
+        };
+        let changed = *self != next;
+        *self = next;
+        changed
+    }
+
+    /// Outcome after all of `msgs`
+    pub fn from_msgs<'a>(msgs: impl IntoIterator<Item = &'a Msg>) -> Self {
+        let mut outcome = Self::default();
+        for msg in msgs {
+            outcome.push(msg);
+        }
+        outcome
+    }
+
+    /// Succeeded or failed, nothing more will arrive
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            CalibrationOutcome::Succeeded { .. } | CalibrationOutcome::Failed(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_limits() {
+        assert!(validate_calibration_inputs(FO2_MIN_PERCENT, PRESSURE_MIN_MBAR).is_ok());
+        assert!(validate_calibration_inputs(FO2_MAX_PERCENT, PRESSURE_MAX_MBAR).is_ok());
+        assert_eq!(
+            validate_calibration_inputs(69, 1013),
+            Err(CalibrationError::O2OutOfRange(69))
+        );
+        assert_eq!(
+            validate_calibration_units(Fo2::new(98), Millibar::new(1051)),
+            Err(CalibrationError::PressureOutOfRange(1051))
+        );
+    }
+
+    #[test]
+    fn calibration_outcome() {
+        let response = |status| Msg::Ppo2CalibrationResponse {
+            status,
+            cell_voltages: CellArray::new([48.into(), 50.into(), 52.into()]),
+            fo2: Fo2::new(98),
+            pressure: Millibar::new(1013),
+            cells_active: CellsActive::new([true, true, true]),
+        };
+        assert!(!CalStatusCode::Ack.is_final());
+        assert!(CalStatusCode::Success.is_final() && !CalStatusCode::Success.is_error());
+        assert!(CalStatusCode::Unknown(0x42).is_error());
+
+        let mut outcome = CalibrationOutcome::default();
+        assert!(!outcome.push(&Msg::Nop));
+        assert!(outcome.push(&response(CalStatusCode::Ack)));
+        assert!(!outcome.push(&response(CalStatusCode::Ack)));
+        assert_eq!(outcome, CalibrationOutcome::Running);
+        assert!(outcome.push(&response(CalStatusCode::Success)));
+        assert!(matches!(
+            outcome,
+            CalibrationOutcome::Succeeded { fo2, .. } if fo2 == Fo2::new(98)
+        ));
+        // Final, a late response doesn't change it
+        assert!(!outcome.push(&response(CalStatusCode::Rejected)));
+
+        let msgs = [
+            response(CalStatusCode::Ack),
+            response(CalStatusCode::Fo2RangeError),
+        ];
+        assert_eq!(
+            CalibrationOutcome::from_msgs(&msgs),
+            CalibrationOutcome::Failed(CalStatusCode::Fo2RangeError)
+        );
+    }
+}
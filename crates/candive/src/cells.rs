@@ -0,0 +1,172 @@
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
+
+use core::ops::{Deref, DerefMut};
+
+/// O₂ cells on current DiveCAN hardware, and the cell count of every bus
+/// message and DID that carries per-cell values.
+pub const DIVECAN_CELLS: usize = 3;
+
+/// Most cells a [`CellsActive`](crate::divecan::CellsActive) bitmask can hold
+pub const MAX_CELLS: usize = 8;
+
+/// One value per O₂ cell. `N` is the cell count of the device profile the
+/// value belongs to, so wire formats for other cell counts only need a new
+/// `N` rather than another copy of every struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CellArray<T, const N: usize = DIVECAN_CELLS>(pub [T; N]);
+
+impl<T, const N: usize> CellArray<T, N> {
+    pub const LEN: usize = N;
+
+    pub const fn new(cells: [T; N]) -> Self {
+        Self(cells)
+    }
+
+    pub fn from_fn(f: impl FnMut(usize) -> T) -> Self {
+        Self(core::array::from_fn(f))
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> CellArray<U, N> {
+        CellArray(self.0.map(f))
+    }
+
+    pub fn into_inner(self) -> [T; N] {
+        self.0
+    }
+
+    /// Writes each cell as `W` bytes starting at `out[0]`, returns the
+    /// `N * W` bytes written. `None`, with nothing written, if `out` is
+    /// shorter than that.
+    pub fn encode<const W: usize>(
+        &self,
+        out: &mut [u8],
+        f: impl Fn(&T) -> [u8; W],
+    ) -> Option<usize> {
+        let out = out.get_mut(..N * W)?;
+        for (chunk, cell) in out.chunks_exact_mut(W).zip(&self.0) {
+            chunk.copy_from_slice(&f(cell));
+        }
+        Some(N * W)
+    }
+
+    /// Reads `N` cells of `W` bytes each from the start of `bytes`, `None` if
+    /// there are fewer than `N * W` bytes.
+    pub fn decode<const W: usize>(bytes: &[u8], f: impl Fn([u8; W]) -> T) -> Option<Self> {
+        let bytes = bytes.get(..N * W)?;
+        Some(Self::from_fn(|i| {
+            let mut cell = [0u8; W];
+            cell.copy_from_slice(&bytes[i * W..(i + 1) * W]);
+            f(cell)
+        }))
+    }
+}
+
+impl<const N: usize> CellArray<bool, N> {
+    /// Bit `i` set for each true cell `i`, the encoding of
+    /// [`CellsActive`](crate::divecan::CellsActive).
+    pub fn to_mask(&self) -> u8 {
+        const { assert!(N <= MAX_CELLS) };
+        self.0
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (i, &on)| mask | ((on as u8) << i))
+    }
+
+    /// Inverse of [`Self::to_mask`], bits above `N` are ignored.
+    pub fn from_mask(mask: u8) -> Self {
+        const { assert!(N <= MAX_CELLS) };
+        Self::from_fn(|i| mask & (1 << i) != 0)
+    }
+}
+
+impl<T: Default, const N: usize> Default for CellArray<T, N> {
+    fn default() -> Self {
+        Self::from_fn(|_| T::default())
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for CellArray<T, N> {
+    fn from(cells: [T; N]) -> Self {
+        Self(cells)
+    }
+}
+
+impl<T, const N: usize> From<CellArray<T, N>> for [T; N] {
+    fn from(cells: CellArray<T, N>) -> Self {
+        cells.0
+    }
+}
+
+impl<T, const N: usize> Deref for CellArray<T, N> {
+    type Target = [T; N];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T, const N: usize> DerefMut for CellArray<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T, const N: usize> IntoIterator for CellArray<T, N> {
+    type Item = T;
+    type IntoIter = core::array::IntoIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a CellArray<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let cells = CellArray::new([0xB1u32, 0xB2, 0xA3]);
+        let mut out = [0u8; 13];
+        assert_eq!(cells.encode(&mut out, |c| c.to_be_bytes()), Some(12));
+        assert_eq!(cells.encode(&mut [0u8; 11], |c| c.to_be_bytes()), None);
+        assert_eq!(&out[..4], &[0, 0, 0, 0xB1]);
+        assert_eq!(&out[8..12], &[0, 0, 0, 0xA3]);
+        assert_eq!(CellArray::decode(&out, u32::from_be_bytes), Some(cells));
+        assert_eq!(
+            CellArray::<u32>::decode(&out[..11], u32::from_be_bytes),
+            None
+        );
+
+        let four: CellArray<u16, 4> =
+            CellArray::decode(&[0, 1, 0, 2, 0, 3, 0, 4], u16::from_be_bytes).unwrap();
+        assert_eq!(four.into_inner(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn masks() {
+        assert_eq!(CellArray::new([true, false, true]).to_mask(), 0b101);
+        assert_eq!(CellArray::<bool>::from_mask(0xFF).into_inner(), [true; 3]);
+        let four = CellArray::<bool, 4>::from_mask(0b1000);
+        assert_eq!(four.into_inner(), [false, false, false, true]);
+        assert_eq!(four.to_mask(), 0b1000);
+    }
+}
@@ -0,0 +1,184 @@
+//! Counts the frames the decoder couldn't fully handle, with an example
+//! payload for each, so real traffic shows which kinds still need work.
+
+use crate::divecan::{DecodeError, Decoded, DiveCanFrame, DiveCanId, DlcPolicy, Msg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeIssue {
+    UnknownKind,
+    /// Rejected as shorter than the kind's minimum DLC
+    DlcMismatch,
+    /// Short, but accepted under [`DlcPolicy::ZeroPad`]
+    Padded,
+}
+
+/// One issue on one kind, with the first frame that showed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IssueCount {
+    pub issue: DecodeIssue,
+    pub kind: u8,
+    pub count: u32,
+    /// Sender of the example frame
+    pub src: u8,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+impl IssueCount {
+    pub fn example(&self) -> &[u8] {
+        &self.data[..self.dlc as usize]
+    }
+}
+
+/// Collects [`DecodeIssue`]s, either by decoding through
+/// [`DecodeStats::decode`] or by handing results to [`DecodeStats::record`].
+///
+/// Holds `N` distinct (issue, kind) pairs. Later pairs only add to
+/// [`DecodeStats::overflow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DecodeStats<const N: usize = 32> {
+    entries: [Option<IssueCount>; N],
+    decoded: u32,
+    overflow: u32,
+}
+
+impl<const N: usize> Default for DecodeStats<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> DecodeStats<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: [None; N],
+            decoded: 0,
+            overflow: 0,
+        }
+    }
+
+    /// [`Msg::try_from_frame_with`], recording the outcome
+    pub fn decode(
+        &mut self,
+        id: DiveCanId,
+        frame: &DiveCanFrame,
+        policy: DlcPolicy,
+    ) -> Result<Decoded, DecodeError> {
+        let result = Msg::try_from_frame_with(frame, policy);
+        self.record(id, frame, &result);
+        result
+    }
+
+    pub fn record(
+        &mut self,
+        id: DiveCanId,
+        frame: &DiveCanFrame,
+        result: &Result<Decoded, DecodeError>,
+    ) {
+        let issue = match result {
+            Ok(decoded) => {
+                self.decoded = self.decoded.saturating_add(1);
+                if !decoded.padded {
+                    return;
+                }
+                DecodeIssue::Padded
+            }
+            Err(DecodeError::UnknownKind { .. }) => DecodeIssue::UnknownKind,
+            Err(DecodeError::DlcMismatch) => DecodeIssue::DlcMismatch,
+        };
+
+        let kind = frame.kind();
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .flatten()
+            .find(|e| e.issue == issue && e.kind == kind)
+        {
+            entry.count = entry.count.saturating_add(1);
+            return;
+        }
+        match self.entries.iter_mut().find(|e| e.is_none()) {
+            Some(slot) => {
+                let mut data = [0u8; 8];
+                data[..frame.bytes().len()].copy_from_slice(frame.bytes());
+                *slot = Some(IssueCount {
+                    issue,
+                    kind,
+                    count: 1,
+                    src: id.src,
+                    dlc: frame.dlc(),
+                    data,
+                });
+            }
+            None => self.overflow = self.overflow.saturating_add(1),
+        }
+    }
+
+    /// Issues in the order they were first seen
+    pub fn issues(&self) -> impl Iterator<Item = &IssueCount> {
+        self.entries.iter().flatten()
+    }
+
+    /// Frames that decoded, padded or not
+    pub fn decoded(&self) -> u32 {
+        self.decoded
+    }
+
+    /// Frames with an issue that didn't fit in the table
+    pub fn overflow(&self) -> u32 {
+        self.overflow
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(Option::is_none) && self.overflow == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: u8, bytes: &[u8]) -> DiveCanFrame {
+        let mut data = [0u8; 8];
+        data[..bytes.len()].copy_from_slice(bytes);
+        DiveCanFrame::new(kind, bytes.len() as u8, data).unwrap()
+    }
+
+    #[test]
+    fn counts_issues() {
+        let id = DiveCanId::new(0x04, 0x01, 0x00);
+        let mut stats = DecodeStats::<2>::new();
+        assert!(stats.is_empty());
+
+        let setpoint = frame(0xC9, &[0x82]);
+        assert!(stats.decode(id, &setpoint, DlcPolicy::Strict).is_ok());
+        assert!(stats.is_empty());
+
+        let unknown = frame(0x99, &[1, 2, 3]);
+        stats.decode(id, &unknown, DlcPolicy::Strict).unwrap_err();
+        stats
+            .decode(id, &frame(0x99, &[4]), DlcPolicy::Strict)
+            .unwrap_err();
+        let short = frame(0x04, &[0, 0x14]);
+        assert!(stats.decode(id, &short, DlcPolicy::ZeroPad).is_ok());
+        stats.decode(id, &short, DlcPolicy::Strict).unwrap_err();
+
+        let issues: Vec<_> = stats.issues().collect();
+        assert_eq!(
+            (issues[0].issue, issues[0].kind, issues[0].count),
+            (DecodeIssue::UnknownKind, 0x99, 2)
+        );
+        assert_eq!(issues[0].example(), [1, 2, 3]);
+        assert_eq!(issues[0].src, 0x04);
+        assert_eq!(
+            (issues[1].issue, issues[1].kind, issues[1].count),
+            (DecodeIssue::Padded, 0x04, 1)
+        );
+        assert_eq!(stats.overflow(), 1);
+        assert_eq!(stats.decoded(), 2);
+        assert!(!stats.is_empty());
+    }
+}
@@ -0,0 +1,40 @@
+//! Comparisons for secret or integrity-checked values (derived keys,
+//! security-access responses, digests) whose running time does not depend on
+//! where the inputs first differ.
+
+use core::hint::black_box;
+
+/// Constant-time equality of two byte strings.
+///
+/// The length check is not constant time, lengths are assumed public.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(0u8, |acc, (&x, &y)| black_box(acc | (x ^ y)));
+    black_box(diff) == 0
+}
+
+/// [`ct_eq`] for CRCs and other 32-bit digests.
+pub fn ct_eq_u32(a: u32, b: u32) -> bool {
+    ct_eq(&a.to_be_bytes(), &b.to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares() {
+        assert!(ct_eq(&[], &[]));
+        assert!(ct_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!ct_eq(&[0x80, 2, 3], &[0, 2, 3]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2]));
+        assert!(ct_eq_u32(0x4fcaf787, 0x4fcaf787));
+        assert!(!ct_eq_u32(0x4fcaf787, 0x4fcaf786));
+    }
+}
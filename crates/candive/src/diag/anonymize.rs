@@ -0,0 +1,398 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::diag::solo::{LOG_ENTRY_SIZE, LogEntry, LogProfile, is_blank_slot};
+use crate::divecan::{DiveCanFrame, DiveCanId, Msg};
+use crate::protocol::{did, kind};
+use crate::uds::isotp::IsoTpPciType;
+use crate::uds::{SID_RDBI_RESP, SID_WDBI_REQ};
+use crate::units::Millibar;
+
+/// Rebased timestamps start here, 2000-01-01 00:00 UTC
+pub const ANON_EPOCH: u32 = 946_684_800;
+/// Surface pressure every log is shifted to
+pub const ANON_SURFACE: Millibar = Millibar::new(1013);
+
+/// DIDs whose data identifies the device, zeroed in UDS traffic
+const IDENTITY_DIDS: [u16; 3] = [did::SERIAL_NUMBER_ASCII, did::SERIAL_NUMBER, did::DEVICE_ID];
+
+/// Redacts identifying data from decoded messages while keeping the log
+/// usable for debugging:
+///
+/// - serial numbers become `00000001`, `00000002`, ... in order of appearance
+/// - the serial number and device id DIDs are zeroed where a capture reads
+///   or writes them over UDS
+/// - Diving timestamps, and capture timestamps passed to
+///   [`Self::rebase_time_ms`], are shifted so the first one lands on
+///   [`ANON_EPOCH`], keeping the time between them
+/// - AmbientPressure is shifted so the first surface pressure reads
+///   [`ANON_SURFACE`], hiding the dive site's altitude but keeping depth
+///
+/// Keep one instance per log so the shifts and pseudonyms stay consistent.
+#[derive(Debug, Clone, Default)]
+pub struct Anonymizer {
+    serials: Vec<[u8; 8]>,
+    time_offset: Option<i64>,
+    pressure_offset: Option<i32>,
+    /// Kind carried by the last slot [`Self::anonymize_log`] saw
+    next_kind: u8,
+    /// Identity DID transfers in progress, by destination address. The
+    /// handset sends consecutive frames from 0xFF, the destination stays put.
+    transfers: BTreeMap<u8, Transfer>,
+}
+
+/// Payload offsets of an ISO-TP message whose data from [`DID_DATA`] on is
+/// being zeroed
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    /// Offset of the next consecutive frame's first byte
+    next: usize,
+    len: usize,
+}
+
+/// Offset of the DID data in a UDS payload: address, SID, DID
+const DID_DATA: usize = 4;
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redacts `msg` in place, returns whether anything was changed.
+    pub fn anonymize(&mut self, msg: &mut Msg) -> bool {
+        let before = *msg;
+        match msg {
+            Msg::Serial(serial) => *serial = self.pseudonym(serial),
+            Msg::Diving { timestamp, .. } if *timestamp != 0 => {
+                let offset = *self
+                    .time_offset
+                    .get_or_insert(ANON_EPOCH as i64 - *timestamp as i64);
+                *timestamp = (*timestamp as i64 + offset).clamp(1, u32::MAX as i64) as u32;
+            }
+            Msg::AmbientPressure {
+                surface, current, ..
+            } => {
+                let offset = *self
+                    .pressure_offset
+                    .get_or_insert(ANON_SURFACE.raw() as i32 - surface.raw() as i32);
+                let shift = |p: Millibar| {
+                    Millibar::new((p.raw() as i32 + offset).clamp(0, u16::MAX as i32) as u16)
+                };
+                *surface = shift(*surface);
+                *current = shift(*current);
+            }
+            _ => {}
+        }
+        *msg != before
+    }
+
+    /// Redacts a captured frame in place, returns whether anything was
+    /// changed. Besides what [`Self::anonymize`] does, this zeroes identity
+    /// DIDs in UDS traffic, which needs the frames of a capture in order.
+    pub fn anonymize_frame(&mut self, id: DiveCanId, frame: &mut DiveCanFrame) -> bool {
+        if frame.kind() == kind::UDS {
+            return self.redact_uds(id.dst, frame);
+        }
+        let Ok(mut msg) = Msg::try_from_frame(frame) else {
+            return false;
+        };
+        if !self.anonymize(&mut msg) {
+            return false;
+        }
+        *frame = msg.to_frame();
+        true
+    }
+
+    /// `unix_ms` shifted like the Diving timestamps
+    pub fn rebase_time_ms(&mut self, unix_ms: u64) -> u64 {
+        let secs = (unix_ms / 1000) as i64;
+        let offset = *self.time_offset.get_or_insert(ANON_EPOCH as i64 - secs);
+        (secs + offset).max(0) as u64 * 1000 + unix_ms % 1000
+    }
+
+    fn redact_uds(&mut self, dst: u8, frame: &mut DiveCanFrame) -> bool {
+        let mut data = [0u8; 8];
+        let len = frame.bytes().len();
+        if len < 2 {
+            return false;
+        }
+        data[..len].copy_from_slice(frame.bytes());
+        // Where this frame's bytes start in the UDS payload and how long
+        // the payload is
+        let (header, start, total) = match IsoTpPciType::from_u8(data[0]) {
+            Some(IsoTpPciType::Single) => {
+                self.transfers.remove(&dst);
+                (1, 0, usize::from(data[0] & 0x0F))
+            }
+            Some(IsoTpPciType::First) => {
+                self.transfers.remove(&dst);
+                let total = usize::from(data[0] & 0x0F) << 8 | usize::from(data[1]);
+                if is_identity_data(&data[2..len]) {
+                    self.transfers.insert(
+                        dst,
+                        Transfer {
+                            next: 6,
+                            len: total,
+                        },
+                    );
+                }
+                (2, 0, total)
+            }
+            Some(IsoTpPciType::Consecutive) => match self.transfers.get_mut(&dst) {
+                Some(transfer) => {
+                    let start = transfer.next;
+                    transfer.next += 7;
+                    let total = transfer.len;
+                    if transfer.next >= total {
+                        self.transfers.remove(&dst);
+                    }
+                    (1, start, total)
+                }
+                None => return false,
+            },
+            _ => return false,
+        };
+        if start == 0 && !is_identity_data(&data[header..len]) {
+            return false;
+        }
+
+        let mut changed = false;
+        for (i, byte) in data[header..len].iter_mut().enumerate() {
+            let offset = start + i;
+            if (DID_DATA..total).contains(&offset) && *byte != 0 {
+                *byte = 0;
+                changed = true;
+            }
+        }
+        if changed {
+            *frame = Msg::Uds {
+                dlc: frame.dlc(),
+                data,
+            }
+            .to_frame();
+        }
+        changed
+    }
+
+    /// Redacts decrypted log data in place, returns the number of entries changed.
+    /// Entries that don't decode are left as they are.
+    ///
+    /// A large log can be passed in consecutive pieces, each a whole number
+    /// of slots.
+    pub fn anonymize_log(&mut self, data: &mut [u8]) -> usize {
+        let mut changed = 0;
+        // Same walk as LogEntryIterator: each slot carries the next entry's kind
+        let mut kind = self.next_kind;
+        for slot in data.chunks_exact_mut(LOG_ENTRY_SIZE as usize) {
+            let next_kind = slot[10];
+            if !is_blank_slot(slot) {
+                let mut payload = [0u8; 8];
+                payload.copy_from_slice(&slot[..8]);
+                let (_, frame) = LogEntry { kind, payload }.to_frame(&LogProfile::SOLO);
+                if let Ok(mut msg) = Msg::try_from_frame(&frame)
+                    && self.anonymize(&mut msg)
+                {
+                    let redacted = msg.to_frame();
+                    slot[..redacted.bytes().len()].copy_from_slice(redacted.bytes());
+                    changed += 1;
+                }
+            }
+            kind = next_kind;
+        }
+        self.next_kind = kind;
+        changed
+    }
+
+    fn pseudonym(&mut self, serial: &[u8; 8]) -> [u8; 8] {
+        let index = match self.serials.iter().position(|s| s == serial) {
+            Some(i) => i,
+            None => {
+                self.serials.push(*serial);
+                self.serials.len() - 1
+            }
+        };
+
+        let mut out = *b"00000000";
+        let mut n = index + 1;
+        for digit in out.iter_mut().rev() {
+            *digit = b"0123456789ABCDEF"[n % 16];
+            n /= 16;
+        }
+        out
+    }
+}
+
+/// Whether the start of a UDS payload reads or writes an identity DID
+fn is_identity_data(payload: &[u8]) -> bool {
+    match payload {
+        [_, sid, hi, lo, ..] => {
+            matches!(*sid, SID_RDBI_RESP | SID_WDBI_REQ)
+                && IDENTITY_DIDS.contains(&u16::from_be_bytes([*hi, *lo]))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::divecan::DiveState;
+
+    #[test]
+    fn redacts_messages() {
+        let mut anon = Anonymizer::new();
+
+        let mut serial = Msg::Serial(*b"A005D007");
+        assert!(anon.anonymize(&mut serial));
+        assert_eq!(serial, Msg::Serial(*b"00000001"));
+        let mut other = Msg::Serial(*b"B1234567");
+        anon.anonymize(&mut other);
+        assert_eq!(other, Msg::Serial(*b"00000002"));
+        let mut again = Msg::Serial(*b"A005D007");
+        anon.anonymize(&mut again);
+        assert_eq!(again, Msg::Serial(*b"00000001"));
+
+        let dive = |timestamp| Msg::Diving {
+            status: DiveState::Diving,
+            dive_number: 7,
+            timestamp,
+        };
+        let mut start = dive(1_700_000_000);
+        let mut end = dive(1_700_003_600);
+        anon.anonymize(&mut start);
+        anon.anonymize(&mut end);
+        assert_eq!(start, dive(ANON_EPOCH));
+        assert_eq!(end, dive(ANON_EPOCH + 3600));
+
+        let mut ambient = Msg::AmbientPressure {
+            surface: Millibar::new(850),
+            current: Millibar::new(1850),
+            depth_comp: true,
+        };
+        anon.anonymize(&mut ambient);
+        assert_eq!(
+            ambient,
+            Msg::AmbientPressure {
+                surface: ANON_SURFACE,
+                current: Millibar::new(2013),
+                depth_comp: true,
+            }
+        );
+
+        let mut nop = Msg::Nop;
+        assert!(!anon.anonymize(&mut nop));
+    }
+
+    #[test]
+    fn redacts_log_in_place() {
+        let slot = LOG_ENTRY_SIZE as usize;
+        // Slot 0 announces a Serial entry in slot 1, which announces a Nop
+        let serial = Msg::Serial(*b"A005D007").to_frame();
+        let mut data = [0u8; 36];
+        data[10] = serial.kind();
+        data[slot..slot + 8].copy_from_slice(serial.bytes());
+        data[slot + 8] = 0x42;
+        data[slot + 10] = 0x10;
+        data[2 * slot] = 0x01;
+
+        let mut anon = Anonymizer::new();
+        assert_eq!(anon.anonymize_log(&mut data), 1);
+        assert_eq!(&data[slot..slot + 8], b"00000001");
+        assert_eq!(data[slot + 8], 0x42);
+        assert_eq!(data[slot + 10], 0x10);
+
+        // Split after the slot announcing the Serial, the kind carries over
+        data[slot..slot + 8].copy_from_slice(serial.bytes());
+        let mut anon = Anonymizer::new();
+        let (head, tail) = data.split_at_mut(slot);
+        assert_eq!(anon.anonymize_log(head), 0);
+        assert_eq!(anon.anonymize_log(tail), 1);
+        assert_eq!(&data[slot..slot + 8], b"00000001");
+    }
+
+    #[test]
+    fn pseudonyms_stay_distinct() {
+        let mut anon = Anonymizer::new();
+        let mut seen = Vec::new();
+        for i in 0..40 {
+            let mut msg = Msg::Serial([b'A', i, 0, 0, 0, 0, 0, 0]);
+            anon.anonymize(&mut msg);
+            let Msg::Serial(pseudonym) = msg else {
+                unreachable!()
+            };
+            assert!(!seen.contains(&pseudonym));
+            seen.push(pseudonym);
+        }
+        assert_eq!(seen[39], *b"00000028");
+    }
+
+    #[test]
+    fn zeroes_identity_dids() {
+        let uds = |bytes: [u8; 8]| DiveCanFrame::new(kind::UDS, 8, bytes).unwrap();
+        let to_handset = DiveCanId {
+            src: 0x04,
+            dst: 0x02,
+            kind: kind::UDS,
+        };
+        let mut anon = Anonymizer::new();
+
+        // SerialNumberAscii answer, 12 byte payload over two frames
+        let mut first = uds([0x10, 0x0C, 0x00, 0x62, 0x80, 0x10, b'A', b'0']);
+        let mut next = uds([0x21, b'0', b'5', b'D', b'0', b'0', b'7', 0xAA]);
+        assert!(anon.anonymize_frame(to_handset, &mut first));
+        assert!(anon.anonymize_frame(to_handset, &mut next));
+        assert_eq!(first.bytes(), [0x10, 0x0C, 0x00, 0x62, 0x80, 0x10, 0, 0]);
+        assert_eq!(next.bytes(), [0x21, 0, 0, 0, 0, 0, 0, 0xAA]);
+
+        // Other DIDs and the frames after the transfer stay
+        let mut other = uds([0x10, 0x0C, 0x00, 0x62, 0x80, 0x11, 0x01, 0x02]);
+        let mut next = uds([0x21, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09]);
+        assert!(!anon.anonymize_frame(to_handset, &mut other));
+        assert!(!anon.anonymize_frame(to_handset, &mut next));
+        let mut single = uds([0x03, 0x00, 0x6E, 0x82, 0, 0, 0, 0]);
+        assert!(!anon.anonymize_frame(to_handset, &mut single));
+
+        // A written SerialNumber, consecutive frames from the handset's 0xFF
+        let to_solo = DiveCanId {
+            src: 0x02,
+            dst: 0x04,
+            kind: kind::UDS,
+        };
+        let mut first = uds([0x10, 0x08, 0x00, 0x2E, 0x82, 0x00, 0x12, 0x34]);
+        let mut next = uds([0x21, 0x56, 0x78, 0, 0, 0, 0, 0]);
+        assert!(anon.anonymize_frame(to_solo, &mut first));
+        assert!(anon.anonymize_frame(
+            DiveCanId {
+                src: 0xFF,
+                ..to_solo
+            },
+            &mut next
+        ));
+        assert_eq!(first.bytes()[6..], [0, 0]);
+        assert_eq!(next.bytes()[1..3], [0, 0]);
+    }
+
+    #[test]
+    fn redacts_captured_frames() {
+        let mut anon = Anonymizer::new();
+        let id = DiveCanId {
+            src: 0x04,
+            dst: 0xFF,
+            kind: kind::SERIAL,
+        };
+        let mut serial = Msg::Serial(*b"A005D007").to_frame();
+        assert!(anon.anonymize_frame(id, &mut serial));
+        assert_eq!(serial.bytes(), b"00000001");
+        let mut nop = Msg::Nop.to_frame();
+        assert!(!anon.anonymize_frame(id, &mut nop));
+
+        assert_eq!(
+            anon.rebase_time_ms(1_700_000_000_250),
+            ANON_EPOCH as u64 * 1000 + 250
+        );
+        assert_eq!(
+            anon.rebase_time_ms(1_700_000_003_500),
+            ANON_EPOCH as u64 * 1000 + 3500
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::cells::{CellArray, DIVECAN_CELLS};
+use crate::divecan::Msg;
+use crate::stats::Mean;
+use crate::units::{CentiMillivolt, Fo2, Millibar};
+
+/// FO₂ of dry air in per mille, the reference point of a linearity test
+pub const AIR_FO2_PERMILLE: u32 = 209;
+
+/// Most FO₂ points a [`LinearityReport`] keeps deviations for
+pub const MAX_LINEARITY_POINTS: usize = 8;
+
+/// Averages `CellVoltages` broadcasts, like
+/// [`AmbientPressureAverage`](crate::divecan::AmbientPressureAverage) does for pressure.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CellVoltageAverage {
+    cells: [Mean; DIVECAN_CELLS],
+}
+
+impl CellVoltageAverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a decoded message, returns true if it was a `CellVoltages` sample.
+    pub fn push(&mut self, msg: &Msg) -> bool {
+        let Msg::CellVoltages { cell_voltages, .. } = msg else {
+            return false;
+        };
+        for (mean, mv) in self.cells.iter_mut().zip(cell_voltages) {
+            mean.push(mv.raw() as u32);
+        }
+        true
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.cells[0].samples()
+    }
+
+    pub fn average(&self) -> Option<CellArray<CentiMillivolt>> {
+        if self.samples() == 0 {
+            return None;
+        }
+        // Every cell has as many samples, so none of them is None
+        Some(CellArray::from_fn(|i| {
+            CentiMillivolt::new(self.cells[i].average().unwrap_or_default() as u16)
+        }))
+    }
+}
+
+/// Cell output measured at one known gas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinearityPoint {
+    pub fo2_permille: u32,
+    pub pressure: Millibar,
+    pub cell_mv: CellArray<CentiMillivolt>,
+}
+
+impl LinearityPoint {
+    pub fn air(pressure: Millibar, cell_mv: CellArray<CentiMillivolt>) -> Self {
+        Self {
+            fo2_permille: AIR_FO2_PERMILLE,
+            pressure,
+            cell_mv,
+        }
+    }
+
+    pub fn new(fo2: Fo2, pressure: Millibar, cell_mv: CellArray<CentiMillivolt>) -> Self {
+        Self {
+            fo2_permille: fo2.raw() as u32 * 10,
+            pressure,
+            cell_mv,
+        }
+    }
+
+    /// ppO₂ in millibar
+    pub fn ppo2_mbar(&self) -> u32 {
+        self.fo2_permille * self.pressure.raw() as u32 / 1000
+    }
+}
+
+/// Pass limits for [`evaluate_linearity`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinearityTolerance {
+    /// Largest allowed deviation from the air-extrapolated reading, per mille
+    pub max_deviation_permille: u32,
+    /// Plausible cell output in air; outside it the cell is worn or miswired
+    pub air_mv_min: CentiMillivolt,
+    pub air_mv_max: CentiMillivolt,
+}
+
+impl Default for LinearityTolerance {
+    fn default() -> Self {
+        Self {
+            max_deviation_permille: 50,
+            air_mv_min: CentiMillivolt::new(800),
+            air_mv_max: CentiMillivolt::new(1400),
+        }
+    }
+}
+
+/// Linearity of one cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CellLinearity {
+    pub air_mv: CentiMillivolt,
+    /// Deviation of each point from the straight line through zero and the
+    /// air reading, per mille, positive when the cell reads high. `None`
+    /// when the cell read nothing in air, so there is no line to compare to.
+    pub deviations_permille: [Option<i32>; MAX_LINEARITY_POINTS],
+    pub air_ok: bool,
+    pub linear: bool,
+}
+
+impl CellLinearity {
+    pub fn passed(&self) -> bool {
+        self.air_ok && self.linear
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinearityReport {
+    pub cells: CellArray<CellLinearity>,
+    /// Points evaluated, at most [`MAX_LINEARITY_POINTS`]
+    pub point_count: usize,
+}
+
+impl LinearityReport {
+    pub fn passed(&self) -> bool {
+        self.cells.iter().all(CellLinearity::passed)
+    }
+
+    /// Largest absolute deviation of `cell`, per mille, `None` if no point
+    /// has one
+    pub fn worst_deviation_permille(&self, cell: usize) -> Option<i32> {
+        self.cells[cell].deviations_permille[..self.point_count]
+            .iter()
+            .flatten()
+            .copied()
+            .max_by_key(|d| d.unsigned_abs())
+    }
+}
+
+/// Compares each of `points` with the reading a linear cell would give,
+/// extrapolated from `air` through zero (a galvanic cell's output is
+/// proportional to ppO₂). Points past [`MAX_LINEARITY_POINTS`] are ignored.
+pub fn evaluate_linearity(
+    air: &LinearityPoint,
+    points: &[LinearityPoint],
+    tolerance: &LinearityTolerance,
+) -> LinearityReport {
+    let points = &points[..points.len().min(MAX_LINEARITY_POINTS)];
+    let air_ppo2 = air.ppo2_mbar() as i64;
+
+    let cells = CellArray::from_fn(|cell| {
+        let air_mv = air.cell_mv[cell];
+        let mut result = CellLinearity {
+            air_mv,
+            air_ok: (tolerance.air_mv_min..=tolerance.air_mv_max).contains(&air_mv),
+            linear: true,
+            deviations_permille: [None; MAX_LINEARITY_POINTS],
+        };
+        for (deviation, point) in result.deviations_permille.iter_mut().zip(points) {
+            let expected = air_mv.raw() as i64 * point.ppo2_mbar() as i64;
+            let measured = point.cell_mv[cell].raw() as i64 * air_ppo2;
+            *deviation = (expected != 0).then(|| {
+                ((measured - expected) * 1000 / expected).clamp(i32::MIN.into(), i32::MAX.into())
+                    as i32
+            });
+            match deviation {
+                Some(d) if d.unsigned_abs() <= tolerance.max_deviation_permille => {}
+                _ => result.linear = false,
+            }
+        }
+        result
+    });
+
+    LinearityReport {
+        cells,
+        point_count: points.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(cells: [u16; 3]) -> CellArray<CentiMillivolt> {
+        CellArray::new(cells).map(CentiMillivolt::new)
+    }
+
+    #[test]
+    fn linear_and_current_limited_cells() {
+        let p = Millibar::new(1000);
+        // 10 mV in air is 47.85 mV at 100 % for a linear cell
+        let air = LinearityPoint::air(p, mv([1000, 1000, 500]));
+        let o2 = LinearityPoint::new(Fo2::new(100), p, mv([4785, 4300, 2392]));
+
+        let report = evaluate_linearity(&air, &[o2], &LinearityTolerance::default());
+        assert_eq!(report.point_count, 1);
+        assert!(report.cells[0].passed());
+        assert_eq!(report.worst_deviation_permille(0), Some(0));
+        // Current limited, reads about 10 % low at high ppO₂
+        assert!(!report.cells[1].linear);
+        assert_eq!(report.worst_deviation_permille(1), Some(-101));
+        // Linear but weak in air
+        assert!(report.cells[2].linear);
+        assert!(!report.cells[2].air_ok);
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn dead_cell_has_no_deviation() {
+        let p = Millibar::new(1000);
+        let air = LinearityPoint::air(p, mv([1000, 0, 1000]));
+        let o2 = LinearityPoint::new(Fo2::new(100), p, mv([4785, 0, 4785]));
+
+        let report = evaluate_linearity(&air, &[o2], &LinearityTolerance::default());
+        assert_eq!(report.cells[1].deviations_permille[0], None);
+        assert_eq!(report.worst_deviation_permille(1), None);
+        assert!(!report.cells[1].linear);
+        assert!(report.cells[0].passed() && report.cells[2].passed());
+    }
+
+    #[test]
+    fn averages_cell_voltages() {
+        let mut avg = CellVoltageAverage::new();
+        assert_eq!(avg.average(), None);
+        for v in [1000, 1003] {
+            assert!(avg.push(&Msg::CellVoltages {
+                cell_voltages: mv([v, v + 10, v + 20]),
+                unused: 0,
+            }));
+        }
+        assert!(!avg.push(&Msg::Nop));
+        assert_eq!(avg.samples(), 2);
+        assert_eq!(avg.average(), Some(mv([1002, 1012, 1022])));
+    }
+}
@@ -0,0 +1,295 @@
+use core::fmt;
+
+use super::did::solo::{CalibrationProcedure, CellMode, ControlConfig, PPO2ControlMode};
+
+/// A value of a [`ConfigField`], either one of its named choices or a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigValue {
+    Choice(&'static str),
+    /// `value` counts steps of 10^-`decimals` of the field's [`FieldUnit`]
+    Number {
+        value: u16,
+        decimals: u8,
+    },
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ConfigValue::Choice(name) => f.write_str(name),
+            ConfigValue::Number { value, decimals: 0 } => write!(f, "{}", value),
+            ConfigValue::Number { value, decimals } => {
+                let step = 10u32.pow(u32::from(decimals));
+                let value = u32::from(value);
+                write!(
+                    f,
+                    "{}.{:0width$}",
+                    value / step,
+                    value % step,
+                    width = usize::from(decimals)
+                )
+            }
+        }
+    }
+}
+
+/// Display unit of a numeric [`ConfigField`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FieldUnit {
+    pub symbol: &'static str,
+    /// Decimal places of the unit in the stored value, 1 for a field
+    /// stored in dV and shown in V
+    pub decimals: u8,
+}
+
+const MILLIAMP: FieldUnit = FieldUnit {
+    symbol: "mA",
+    decimals: 0,
+};
+const VOLT: FieldUnit = FieldUnit {
+    symbol: "V",
+    decimals: 1,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigFieldError {
+    /// Value is not a number or not one of the field's choices
+    InvalidValue,
+}
+
+/// Conversion between a control config field type and its textual form.
+/// `decimals` is the field's [`FieldUnit::decimals`], 0 for choice fields.
+pub trait FieldValue: Sized {
+    /// Accepted names, empty for numeric fields
+    const CHOICES: &'static [&'static str];
+
+    fn to_value(&self, decimals: u8) -> ConfigValue;
+    fn parse(s: &str, decimals: u8) -> Option<Self>;
+}
+
+/// Implements [`FieldValue`] for a type with a fixed set of names.
+macro_rules! choice_field_value {
+    ($ty:ty { $($($variant:ident)::+ => $name:literal),+ $(,)? }) => {
+        impl FieldValue for $ty {
+            const CHOICES: &'static [&'static str] = &[$($name),+];
+
+            fn to_value(&self, _decimals: u8) -> ConfigValue {
+                match *self {
+                    $($($variant)::+ => ConfigValue::Choice($name),)+
+                }
+            }
+
+            fn parse(s: &str, _decimals: u8) -> Option<Self> {
+                $(if s.eq_ignore_ascii_case($name) {
+                    return Some($($variant)::+);
+                })+
+                None
+            }
+        }
+    };
+}
+
+choice_field_value!(bool { true => "on", false => "off" });
+choice_field_value!(CalibrationProcedure {
+    CalibrationProcedure::Direct => "direct",
+    CalibrationProcedure::Monitored => "monitored",
+});
+choice_field_value!(PPO2ControlMode {
+    PPO2ControlMode::UserSelect => "user",
+    PPO2ControlMode::Manual => "manual",
+    PPO2ControlMode::OneSec => "1sec",
+    PPO2ControlMode::FiveSec => "5sec",
+});
+choice_field_value!(CellMode {
+    CellMode::TwoCell => "two",
+    CellMode::ThreeCell => "three",
+});
+
+impl FieldValue for u16 {
+    const CHOICES: &'static [&'static str] = &[];
+
+    fn to_value(&self, decimals: u8) -> ConfigValue {
+        ConfigValue::Number {
+            value: *self,
+            decimals,
+        }
+    }
+
+    /// `"6.2"` with one decimal is 62. More decimal places than the field
+    /// has are rejected rather than rounded.
+    fn parse(s: &str, decimals: u8) -> Option<Self> {
+        let (int, frac) = match s.split_once('.') {
+            Some((_, "")) => return None,
+            Some(parts) => parts,
+            None => (s, ""),
+        };
+        if frac.len() > usize::from(decimals) || !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut value: u32 = int.parse().ok()?;
+        let mut digits = frac.bytes().map(|b| u32::from(b - b'0'));
+        for _ in 0..decimals {
+            value = value.checked_mul(10)? + digits.next().unwrap_or(0);
+        }
+        u16::try_from(value).ok()
+    }
+}
+
+/// Generates [`ConfigField`] from a table of `ControlConfig` fields.
+macro_rules! config_fields {
+    ($($variant:ident => $name:literal, $field:ident: $ty:ty, unit: $unit:expr;)+) => {
+        /// User facing keys of the Solo control configuration
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub enum ConfigField {
+            $($variant,)+
+        }
+
+        impl ConfigField {
+            pub const ALL: &'static [ConfigField] = &[$(ConfigField::$variant,)+];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(ConfigField::$variant => $name,)+
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|f| f.name() == name)
+            }
+
+            /// Unit of numeric fields
+            pub fn unit(self) -> Option<FieldUnit> {
+                match self {
+                    $(ConfigField::$variant => $unit,)+
+                }
+            }
+
+            fn decimals(self) -> u8 {
+                self.unit().map_or(0, |unit| unit.decimals)
+            }
+
+            /// Accepted names, empty for numeric fields
+            pub fn choices(self) -> &'static [&'static str] {
+                match self {
+                    $(ConfigField::$variant => <$ty as FieldValue>::CHOICES,)+
+                }
+            }
+
+            pub fn get(self, config: &ControlConfig) -> ConfigValue {
+                match self {
+                    $(ConfigField::$variant => config.$field.to_value(self.decimals()),)+
+                }
+            }
+
+            /// Parses `value` into the field, leaving `config` untouched on error.
+            pub fn set(self, config: &mut ControlConfig, value: &str) -> Result<(), ConfigFieldError> {
+                match self {
+                    $(ConfigField::$variant => {
+                        config.$field = <$ty as FieldValue>::parse(value, self.decimals())
+                            .ok_or(ConfigFieldError::InvalidValue)?;
+                    })+
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+//TODO: Writing these through a user setting would avoid needing SOLO_KEY, but
+// no user setting has been confirmed on a device to hold one of these fields,
+// or in which units. A name that merely looks alike is not enough.
+config_fields! {
+    Cal => "cal", calibration_procedure: CalibrationProcedure, unit: None;
+    Ppo2 => "ppo2", ppo2_control_mode: PPO2ControlMode, unit: None;
+    Cells => "cells", cell_mode: CellMode, unit: None;
+    DepthComp => "depth-comp", depth_compensation_enabled: bool, unit: None;
+    MinCurrent => "min-current", solenoid_current_min_ma: u16, unit: Some(MILLIAMP);
+    MaxCurrent => "max-current", solenoid_current_max_ma: u16, unit: Some(MILLIAMP);
+    MinVoltage => "min-voltage", battery_voltage_min: u16, unit: Some(VOLT);
+    VoltageDoubling => "voltage-doubling", battery_voltage_doubling: bool, unit: None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ControlConfig {
+        ControlConfig {
+            calibration_procedure: CalibrationProcedure::Direct,
+            ppo2_control_mode: PPO2ControlMode::OneSec,
+            cell_mode: CellMode::ThreeCell,
+            depth_compensation_enabled: true,
+            solenoid_current_min_ma: 70,
+            solenoid_current_max_ma: 180,
+            battery_voltage_min: 60,
+            battery_voltage_doubling: false,
+            reserved_bits_20_21: 0,
+            reserved_bits_24_31: 0,
+        }
+    }
+
+    #[test]
+    fn field_table() {
+        assert_eq!(ConfigField::ALL.len(), 8);
+        for field in ConfigField::ALL {
+            assert_eq!(ConfigField::from_name(field.name()), Some(*field));
+            assert!(field.unit().is_some() == field.choices().is_empty());
+        }
+        assert_eq!(ConfigField::from_name("nope"), None);
+        assert_eq!(
+            ConfigField::Ppo2.choices(),
+            ["user", "manual", "1sec", "5sec"]
+        );
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut config = config();
+        assert_eq!(ConfigField::Ppo2.get(&config), ConfigValue::Choice("1sec"));
+        assert_eq!(
+            ConfigField::MinVoltage.get(&config),
+            ConfigValue::Number {
+                value: 60,
+                decimals: 1
+            }
+        );
+        assert_eq!(ConfigField::MinVoltage.get(&config).to_string(), "6.0");
+        assert_eq!(ConfigField::MaxCurrent.get(&config).to_string(), "180");
+
+        assert_eq!(ConfigField::Cells.set(&mut config, "two"), Ok(()));
+        assert_eq!(config.cell_mode, CellMode::TwoCell);
+        assert_eq!(ConfigField::DepthComp.set(&mut config, "OFF"), Ok(()));
+        assert!(!config.depth_compensation_enabled);
+        assert_eq!(ConfigField::MaxCurrent.set(&mut config, "200"), Ok(()));
+        assert_eq!(config.solenoid_current_max_ma, 200);
+        assert_eq!(ConfigField::MinVoltage.set(&mut config, "6.4"), Ok(()));
+        assert_eq!(config.battery_voltage_min, 64);
+        assert_eq!(ConfigField::MinVoltage.set(&mut config, "7"), Ok(()));
+        assert_eq!(config.battery_voltage_min, 70);
+
+        let before = config.clone();
+        assert_eq!(
+            ConfigField::MinCurrent.set(&mut config, "lots"),
+            Err(ConfigFieldError::InvalidValue)
+        );
+        assert_eq!(
+            ConfigField::Cal.set(&mut config, "maybe"),
+            Err(ConfigFieldError::InvalidValue)
+        );
+        for value in ["6.25", "6.", ".5", "6.x", "-6"] {
+            assert_eq!(
+                ConfigField::MinVoltage.set(&mut config, value),
+                Err(ConfigFieldError::InvalidValue)
+            );
+        }
+        assert_eq!(
+            ConfigField::MaxCurrent.set(&mut config, "200.5"),
+            Err(ConfigFieldError::InvalidValue)
+        );
+        assert_eq!(config, before);
+    }
+}
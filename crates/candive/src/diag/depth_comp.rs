@@ -0,0 +1,222 @@
+use crate::divecan::Msg;
+use crate::stats::MinMax;
+use crate::units::Millibar;
+
+/// Surface pressures further apart than this (mbar) are reported as a mismatch
+pub const SURFACE_TOLERANCE_MBAR: u16 = 20;
+
+/// Ratio of ambient to surface pressure in per mille, 1000 at the surface.
+/// This is the factor a depth-compensated reading is scaled by.
+pub fn compensation_factor_permille(surface: Millibar, ambient: Millibar) -> Option<u32> {
+    let surface = surface.raw() as u32;
+    if surface == 0 {
+        return None;
+    }
+    Some((ambient.raw() as u32 * 1000 + surface / 2) / surface)
+}
+
+/// Factor actually applied for an `AmbientPressure` broadcast: the pressure
+/// ratio with depth compensation on, 1000 with it off.
+pub fn effective_factor_permille(msg: &Msg) -> Option<u32> {
+    match msg {
+        Msg::AmbientPressure {
+            surface,
+            current,
+            depth_comp: true,
+        } => compensation_factor_permille(*surface, *current),
+        Msg::AmbientPressure {
+            depth_comp: false, ..
+        } => Some(1000),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DepthCompIssue {
+    NoBroadcast,
+    /// The AmbientPressure flag disagrees with the control configuration
+    FlagMismatch {
+        configured: bool,
+        broadcast: bool,
+    },
+    /// Surface pressure moved while listening
+    SurfaceUnstable {
+        min: Millibar,
+        max: Millibar,
+    },
+    /// Broadcast surface pressure differs from the one in the device log
+    SurfaceMismatch {
+        broadcast: Millibar,
+        logged: Millibar,
+    },
+}
+
+/// Summary of `AmbientPressure` broadcasts seen on the bus.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DepthCompStats {
+    samples: u32,
+    flag_on: u32,
+    surface: MinMax<Millibar>,
+    last: Option<(Millibar, Millibar)>,
+    factor: MinMax<u32>,
+}
+
+impl DepthCompStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a decoded message, returns true if it was an `AmbientPressure` sample.
+    pub fn push(&mut self, msg: &Msg) -> bool {
+        let Msg::AmbientPressure {
+            surface,
+            current,
+            depth_comp,
+        } = msg
+        else {
+            return false;
+        };
+
+        self.samples += 1;
+        if *depth_comp {
+            self.flag_on += 1;
+        }
+        self.surface.push(*surface);
+        self.last = Some((*surface, *current));
+
+        if let Some(factor) = effective_factor_permille(msg) {
+            self.factor.push(factor);
+        }
+        true
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Samples broadcast with the depth compensation flag set
+    pub fn flag_on(&self) -> u32 {
+        self.flag_on
+    }
+
+    /// Surface and ambient pressure of the latest sample
+    pub fn last(&self) -> Option<(Millibar, Millibar)> {
+        self.last
+    }
+
+    pub fn surface_range(&self) -> Option<(Millibar, Millibar)> {
+        self.surface.range()
+    }
+
+    /// Range of the applied factor in per mille, see [`effective_factor_permille`]
+    pub fn factor_range(&self) -> Option<(u32, u32)> {
+        self.factor.range()
+    }
+}
+
+/// Reports every way the broadcasts disagree with the configured flag and,
+/// if given, the surface pressure found in the device log.
+pub fn check_depth_comp(
+    configured: bool,
+    stats: &DepthCompStats,
+    logged_surface: Option<Millibar>,
+    mut report: impl FnMut(DepthCompIssue),
+) {
+    if stats.samples == 0 {
+        report(DepthCompIssue::NoBroadcast);
+        return;
+    }
+
+    let expected = if configured { stats.samples } else { 0 };
+    if stats.flag_on != expected {
+        report(DepthCompIssue::FlagMismatch {
+            configured,
+            broadcast: !configured,
+        });
+    }
+
+    if let Some((min, max)) = stats.surface_range()
+        && max.raw() - min.raw() > SURFACE_TOLERANCE_MBAR
+    {
+        report(DepthCompIssue::SurfaceUnstable { min, max });
+    }
+
+    if let (Some((broadcast, _)), Some(logged)) = (stats.last, logged_surface)
+        && broadcast.raw().abs_diff(logged.raw()) > SURFACE_TOLERANCE_MBAR
+    {
+        report(DepthCompIssue::SurfaceMismatch { broadcast, logged });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ambient(surface: u16, current: u16, depth_comp: bool) -> Msg {
+        Msg::AmbientPressure {
+            surface: surface.into(),
+            current: current.into(),
+            depth_comp,
+        }
+    }
+
+    #[test]
+    fn compensation_factors() {
+        assert_eq!(
+            compensation_factor_permille(Millibar::new(1000), Millibar::new(2013)),
+            Some(2013)
+        );
+        assert_eq!(
+            compensation_factor_permille(Millibar::new(0), Millibar::new(1013)),
+            None
+        );
+        assert_eq!(
+            effective_factor_permille(&ambient(1000, 1500, true)),
+            Some(1500)
+        );
+        assert_eq!(
+            effective_factor_permille(&ambient(1000, 1500, false)),
+            Some(1000)
+        );
+        assert_eq!(effective_factor_permille(&Msg::Nop), None);
+    }
+
+    #[test]
+    fn depth_comp_checks() {
+        let mut stats = DepthCompStats::new();
+        assert!(stats.push(&ambient(1010, 1012, true)));
+        assert!(stats.push(&ambient(1012, 1030, true)));
+        assert_eq!(stats.factor_range(), Some((1002, 1018)));
+
+        let mut issues = [None; 4];
+        let mut n = 0;
+        check_depth_comp(true, &stats, Some(Millibar::new(1013)), |issue| {
+            issues[n] = Some(issue);
+            n += 1;
+        });
+        assert_eq!(n, 0);
+
+        check_depth_comp(false, &stats, Some(Millibar::new(960)), |issue| {
+            issues[n] = Some(issue);
+            n += 1;
+        });
+        assert_eq!(
+            issues[..n],
+            [
+                Some(DepthCompIssue::FlagMismatch {
+                    configured: false,
+                    broadcast: true
+                }),
+                Some(DepthCompIssue::SurfaceMismatch {
+                    broadcast: Millibar::new(1012),
+                    logged: Millibar::new(960)
+                }),
+            ]
+        );
+
+        let mut n = 0;
+        check_depth_comp(true, &DepthCompStats::new(), None, |_| n += 1);
+        assert_eq!(n, 1);
+    }
+}
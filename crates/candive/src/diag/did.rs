@@ -1,3 +1,15 @@
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
+
+use crate::protocol::did;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DidDecodeError {
     TooShort { needed: usize },
@@ -18,6 +30,11 @@ impl DidDecodeError {
 
 pub trait DataIdentifier: for<'a> TryFrom<&'a [u8], Error = DidDecodeError> {
     const DID: u16;
+    /// Length of the data, as declared in `protocol.toml`
+    const LEN: usize = crate::protocol::did_len(Self::DID);
+    /// Where each field lies, in order, so a response cut short still shows
+    /// the fields it has. Empty when there is only one.
+    const FIELDS: &'static [DidField] = &[];
     type Bytes: AsRef<[u8]> + Copy;
 
     fn to_bytes(&self) -> Self::Bytes;
@@ -25,6 +42,67 @@ pub trait DataIdentifier: for<'a> TryFrom<&'a [u8], Error = DidDecodeError> {
 
 pub trait ReadableDid: DataIdentifier {}
 
+/// A field of a DID's data, see [`DataIdentifier::FIELDS`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DidField {
+    pub name: &'static str,
+    pub bytes: core::ops::Range<usize>,
+}
+
+impl DidField {
+    pub const fn new(name: &'static str, bytes: core::ops::Range<usize>) -> Self {
+        Self { name, bytes }
+    }
+}
+
+/// What [`decode_lenient`] made of a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LenientDecode<'a, D> {
+    /// `None` when not even the fields this decoder knows could be read
+    pub value: Option<D>,
+    /// Bytes past the known fields, or the whole response if `value` is `None`
+    pub remainder: &'a [u8],
+    /// Why the response wasn't taken as is
+    pub warning: Option<DidDecodeError>,
+}
+
+impl<'a, D: DataIdentifier> LenientDecode<'a, D> {
+    /// The [`DataIdentifier::FIELDS`] a response too short to decode still
+    /// holds in full, with their bytes. None once `value` is decoded.
+    pub fn partial_fields(&self) -> impl Iterator<Item = (&'static str, &'a [u8])> + use<'a, D> {
+        let bytes = if self.value.is_none() {
+            self.remainder
+        } else {
+            &[]
+        };
+        D::FIELDS
+            .iter()
+            .map_while(move |field| Some((field.name, bytes.get(field.bytes.clone())?)))
+    }
+}
+
+/// Decodes a response from firmware that may have added or dropped fields.
+/// A longer response is decoded from the [`DataIdentifier::LEN`] bytes this
+/// decoder knows and the rest handed back. A shorter or malformed one comes
+/// back as raw bytes, see [`LenientDecode::partial_fields`] for the fields
+/// it still has.
+pub fn decode_lenient<D: DataIdentifier>(bytes: &[u8]) -> LenientDecode<'_, D> {
+    let known = D::LEN;
+    let (head, remainder) = bytes.split_at(known.min(bytes.len()));
+    match D::try_from(head) {
+        Ok(value) => LenientDecode {
+            value: Some(value),
+            remainder,
+            warning: (!remainder.is_empty()).then_some(DidDecodeError::TooLong { max: known }),
+        },
+        Err(e) => LenientDecode {
+            value: None,
+            remainder: bytes,
+            warning: Some(e),
+        },
+    }
+}
+
 pub trait WritableDid: DataIdentifier {}
 
 macro_rules! define_byte_array_did {
@@ -73,7 +151,12 @@ pub struct FirmwareDownloadCapability {
 }
 
 impl DataIdentifier for FirmwareDownloadCapability {
-    const DID: u16 = 0x8020;
+    const DID: u16 = did::FIRMWARE_DOWNLOAD_CAPABILITY;
+    const FIELDS: &'static [DidField] = &[
+        DidField::new("supported", 0..1),
+        DidField::new("address", 1..5),
+        DidField::new("max_size", 5..9),
+    ];
     type Bytes = [u8; 9];
 
     fn to_bytes(&self) -> Self::Bytes {
@@ -113,7 +196,12 @@ pub struct LogUploadCapability {
 }
 
 impl DataIdentifier for LogUploadCapability {
-    const DID: u16 = 0x8021;
+    const DID: u16 = did::LOG_UPLOAD_CAPABILITY;
+    const FIELDS: &'static [DidField] = &[
+        DidField::new("supported", 0..1),
+        DidField::new("address", 1..5),
+        DidField::new("size", 5..9),
+    ];
     type Bytes = [u8; 9];
 
     fn to_bytes(&self) -> Self::Bytes {
@@ -145,13 +233,24 @@ impl TryFrom<&[u8]> for LogUploadCapability {
     }
 }
 
+/// STM32 CRC32 of the firmware image, also read back after a download to
+/// confirm the transfer (the device raises `UdsTransferCrcMismatch` otherwise)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FirmwareCrc {
     pub crc: u32,
 }
 
+impl FirmwareCrc {
+    /// CRC the device should report for `image`
+    pub fn of_image(image: &[u8]) -> Self {
+        Self {
+            crc: super::Stm32Crc32::stm32_crc32(image),
+        }
+    }
+}
+
 impl DataIdentifier for FirmwareCrc {
-    const DID: u16 = 0x8209;
+    const DID: u16 = did::FIRMWARE_CRC;
     type Bytes = [u8; 4];
 
     fn to_bytes(&self) -> Self::Bytes {
@@ -173,34 +272,110 @@ impl TryFrom<&[u8]> for FirmwareCrc {
 
 define_byte_array_did!(
     SerialNumberAscii,
-    did: 0x8010,
+    did: did::SERIAL_NUMBER_ASCII,
     len: 8,
     field: serial_ascii
 );
 
 define_byte_array_did!(
     FirmwareVersionAscii,
-    did: 0x8011,
+    did: did::FIRMWARE_VERSION_ASCII,
     len: 3,
     field: firmware_version_ascii
 );
 
+impl FirmwareVersionAscii {
+    pub fn version(&self) -> Option<super::firmware::FirmwareVersion> {
+        super::firmware::FirmwareVersion::parse(&self.firmware_version_ascii)
+    }
+}
+
 define_byte_array_did!(
     SerialNumber,
-    did: 0x8200,
+    did: did::SERIAL_NUMBER,
     len: 4,
     field: serial
 );
 
 define_byte_array_did!(
     DeviceId,
-    did: 0x8201,
+    did: did::DEVICE_ID,
     len: 12,
     field: device_id
 );
 
+impl DeviceId {
+    /// The ID decoded as an STM32 96-bit unique device ID
+    pub fn stm32_uid(&self) -> Stm32Uid {
+        Stm32Uid::from_bytes(&self.device_id)
+    }
+}
+
+/// Canonical form: the 12 bytes as read, in upper-case hex, one group per
+/// 32-bit UID word, e.g. `50FF6806-48845349-17540887`.
+impl core::fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, word) in self.device_id.chunks_exact(4).enumerate() {
+            if i > 0 {
+                f.write_str("-")?;
+            }
+            for b in word {
+                write!(f, "{:02X}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// STM32 unique device ID, laid out as in the reference manuals: the die's
+/// X/Y position on the wafer, the wafer number and a 7-character ASCII lot
+/// number, all little-endian in memory.
+///
+/// Not every STM32 family documents this layout; [`Self::is_standard_layout`]
+/// tells whether the fields look like one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stm32Uid {
+    pub x: u16,
+    pub y: u16,
+    pub wafer: u8,
+    pub lot: [u8; 7],
+}
+
+impl Stm32Uid {
+    pub fn from_bytes(uid: &[u8; 12]) -> Self {
+        let mut lot = [0u8; 7];
+        lot.copy_from_slice(&uid[5..]);
+        Self {
+            x: u16::from_le_bytes([uid[0], uid[1]]),
+            y: u16::from_le_bytes([uid[2], uid[3]]),
+            wafer: uid[4],
+            lot,
+        }
+    }
+
+    /// Lot number, if it is printable ASCII
+    pub fn lot_str(&self) -> Option<&str> {
+        self.lot
+            .iter()
+            .all(|b| b.is_ascii_graphic() || *b == b' ')
+            .then(|| core::str::from_utf8(&self.lot).ok())
+            .flatten()
+    }
+
+    /// Whether the lot number is ASCII and the coordinates aren't erased
+    /// flash. An ID that fails this on a family that uses the standard layout
+    /// was likely written by hand, e.g. on a cloned unit.
+    pub fn is_standard_layout(&self) -> bool {
+        self.lot_str().is_some() && self.x != 0xFFFF && self.y != 0xFFFF
+    }
+}
+
 pub mod solo {
     use super::*;
+    use crate::cells::{CellArray, DIVECAN_CELLS};
+
+    /// O₂ cells on the Solo; the per-cell DIDs below are laid out for this many
+    pub const SOLO_CELLS: usize = DIVECAN_CELLS;
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     #[repr(u8)]
@@ -270,8 +445,26 @@ pub mod solo {
         pub reserved_bits_24_31: u8,
     }
 
+    impl ControlConfig {
+        pub fn power_limits(&self) -> crate::power::PowerLimits {
+            crate::power::PowerLimits {
+                solenoid_current_min_ma: self.solenoid_current_min_ma,
+                solenoid_current_max_ma: self.solenoid_current_max_ma,
+                battery_voltage_min_dv: self.battery_voltage_min,
+                voltage_doubling: self.battery_voltage_doubling,
+            }
+        }
+    }
+
     impl DataIdentifier for ControlConfig {
-        const DID: u16 = 0x820b;
+        const DID: u16 = did::CONTROL_CONFIG;
+        // Bytes of the big-endian config word, named by the bits they hold
+        const FIELDS: &'static [DidField] = &[
+            DidField::new("reserved_bits_24_31", 0..1),
+            DidField::new("battery_and_max_current_bit", 1..2),
+            DidField::new("solenoid_current", 2..3),
+            DidField::new("modes", 3..4),
+        ];
         type Bytes = [u8; 4];
 
         fn to_bytes(&self) -> Self::Bytes {
@@ -383,22 +576,26 @@ pub mod solo {
     /// O2 cell runtime calibration data (DID 0x8203)
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct CellCalibrationState {
-        pub o2_calibrations: [u32; 3],
-        pub calibration_valid: [bool; 3],
+        pub o2_calibrations: CellArray<u32, SOLO_CELLS>,
+        pub calibration_valid: CellArray<bool, SOLO_CELLS>,
     }
 
     impl DataIdentifier for CellCalibrationState {
-        const DID: u16 = 0x8203;
-        type Bytes = [u8; 15];
+        const DID: u16 = did::CELL_CALIBRATION_STATE;
+        const FIELDS: &'static [DidField] = &[
+            DidField::new("o2_calibrations", 0..4 * SOLO_CELLS),
+            DidField::new("calibration_valid", 4 * SOLO_CELLS..5 * SOLO_CELLS),
+        ];
+        type Bytes = [u8; 5 * SOLO_CELLS];
 
         fn to_bytes(&self) -> Self::Bytes {
-            let mut result = [0u8; 15];
-            result[0..4].copy_from_slice(&self.o2_calibrations[0].to_be_bytes());
-            result[4..8].copy_from_slice(&self.o2_calibrations[1].to_be_bytes());
-            result[8..12].copy_from_slice(&self.o2_calibrations[2].to_be_bytes());
-            result[12] = if self.calibration_valid[0] { 1 } else { 0 };
-            result[13] = if self.calibration_valid[1] { 1 } else { 0 };
-            result[14] = if self.calibration_valid[2] { 1 } else { 0 };
+            // Sized for both arrays, neither encode can run out of room
+            let mut result = [0u8; 5 * SOLO_CELLS];
+            let (calibrations, valid) = result.split_at_mut(4 * SOLO_CELLS);
+            let _ = self
+                .o2_calibrations
+                .encode(calibrations, |c| c.to_be_bytes());
+            let _ = self.calibration_valid.encode(valid, |&v| [v as u8]);
             result
         }
     }
@@ -407,13 +604,13 @@ pub mod solo {
         type Error = DidDecodeError;
 
         fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-            let arr: [u8; 15] = bytes
+            let arr: [u8; 5 * SOLO_CELLS] = bytes
                 .try_into()
-                .map_err(|_| DidDecodeError::length_mismatch(bytes.len(), 15))?;
+                .map_err(|_| DidDecodeError::length_mismatch(bytes.len(), 5 * SOLO_CELLS))?;
 
-            let mut calibration_valid = [false; 3];
-            for i in 0..3 {
-                calibration_valid[i] = match arr[12 + i] {
+            let mut calibration_valid = CellArray::default();
+            for (valid, &raw) in calibration_valid.iter_mut().zip(&arr[4 * SOLO_CELLS..]) {
+                *valid = match raw {
                     0 => false,
                     1 => true,
                     other => return Err(DidDecodeError::InvalidEnumValue { value: other }),
@@ -421,11 +618,11 @@ pub mod solo {
             }
 
             Ok(Self {
-                o2_calibrations: [
-                    u32::from_be_bytes([arr[0], arr[1], arr[2], arr[3]]),
-                    u32::from_be_bytes([arr[4], arr[5], arr[6], arr[7]]),
-                    u32::from_be_bytes([arr[8], arr[9], arr[10], arr[11]]),
-                ],
+                o2_calibrations: CellArray::decode(&arr, u32::from_be_bytes).ok_or(
+                    DidDecodeError::TooShort {
+                        needed: 4 * SOLO_CELLS,
+                    },
+                )?,
                 calibration_valid,
             })
         }
@@ -449,7 +646,7 @@ pub mod solo {
     }
 
     impl DataIdentifier for VoltageCalibration {
-        const DID: u16 = 0x820a;
+        const DID: u16 = did::VOLTAGE_CALIBRATION;
         type Bytes = [u8; 4];
 
         fn to_bytes(&self) -> Self::Bytes {
@@ -469,11 +666,7 @@ pub mod solo {
         }
     }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    pub enum CalibrationError {
-        O2OutOfRange(u32),
-        PressureOutOfRange(u32),
-    }
+    pub use crate::calibration::CalibrationError;
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct CellCalibrationRequest {
@@ -488,15 +681,7 @@ pub mod solo {
             o2_percent: u32,
             atmospheric_pressure_mbar: u32,
         ) -> Result<Self, CalibrationError> {
-            if !(70..=100).contains(&o2_percent) {
-                return Err(CalibrationError::O2OutOfRange(o2_percent));
-            }
-
-            if !(600..=1050).contains(&atmospheric_pressure_mbar) {
-                return Err(CalibrationError::PressureOutOfRange(
-                    atmospheric_pressure_mbar,
-                ));
-            }
+            crate::calibration::validate_calibration_inputs(o2_percent, atmospheric_pressure_mbar)?;
 
             Ok(Self {
                 o2_percent,
@@ -506,7 +691,7 @@ pub mod solo {
     }
 
     impl DataIdentifier for CellCalibrationRequest {
-        const DID: u16 = 0x8204;
+        const DID: u16 = did::CELL_CALIBRATION_REQUEST;
         type Bytes = [u8; 8];
 
         fn to_bytes(&self) -> Self::Bytes {
@@ -533,18 +718,16 @@ pub mod solo {
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct CellZeroOffsets {
-        pub cells: [u32; 3],
+        pub cells: CellArray<u32, SOLO_CELLS>,
     }
 
     impl DataIdentifier for CellZeroOffsets {
-        const DID: u16 = 0x8205;
-        type Bytes = [u8; 12];
+        const DID: u16 = did::CELL_ZERO_OFFSETS;
+        type Bytes = [u8; 4 * SOLO_CELLS];
 
         fn to_bytes(&self) -> Self::Bytes {
-            let mut result = [0u8; 12];
-            result[0..4].copy_from_slice(&self.cells[0].to_be_bytes());
-            result[4..8].copy_from_slice(&self.cells[1].to_be_bytes());
-            result[8..12].copy_from_slice(&self.cells[2].to_be_bytes());
+            let mut result = [0u8; 4 * SOLO_CELLS];
+            let _ = self.cells.encode(&mut result, |c| c.to_be_bytes());
             result
         }
     }
@@ -553,15 +736,15 @@ pub mod solo {
         type Error = DidDecodeError;
 
         fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-            let arr: [u8; 12] = bytes
-                .try_into()
-                .map_err(|_| DidDecodeError::length_mismatch(bytes.len(), 12))?;
+            if bytes.len() != 4 * SOLO_CELLS {
+                return Err(DidDecodeError::length_mismatch(bytes.len(), 4 * SOLO_CELLS));
+            }
             Ok(Self {
-                cells: [
-                    u32::from_be_bytes([arr[0], arr[1], arr[2], arr[3]]),
-                    u32::from_be_bytes([arr[4], arr[5], arr[6], arr[7]]),
-                    u32::from_be_bytes([arr[8], arr[9], arr[10], arr[11]]),
-                ],
+                cells: CellArray::decode(bytes, u32::from_be_bytes).ok_or(
+                    DidDecodeError::TooShort {
+                        needed: 4 * SOLO_CELLS,
+                    },
+                )?,
             })
         }
     }
@@ -573,7 +756,7 @@ pub mod solo {
     }
 
     impl DataIdentifier for CellZeroOffsetCalibrationRequest {
-        const DID: u16 = 0x8206;
+        const DID: u16 = did::CELL_ZERO_OFFSET_CALIBRATION_REQUEST;
         type Bytes = [u8; 4];
 
         fn to_bytes(&self) -> Self::Bytes {
@@ -595,12 +778,66 @@ pub mod solo {
     }
 
     define_byte_array_did!(
+        /// Written as an [`EncryptedConfigPayload`] encrypted with the device key
         EncryptedConfigBlob,
-        did: 0x8202,
+        did: did::ENCRYPTED_CONFIG_BLOB,
         len: 16,
         field: unknown
     );
 
+    /// Plaintext written to [`EncryptedConfigBlob`]: the new control config
+    /// word followed by the device's own ID, which ties the write to one unit.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct EncryptedConfigPayload {
+        pub config: ControlConfig,
+        pub device_id: [u8; 12],
+    }
+
+    impl EncryptedConfigPayload {
+        pub const LEN: usize = 16;
+
+        pub fn new(config: ControlConfig, device_id: &DeviceId) -> Self {
+            Self {
+                config,
+                device_id: device_id.device_id,
+            }
+        }
+
+        pub fn to_bytes(&self) -> [u8; Self::LEN] {
+            let mut out = [0u8; Self::LEN];
+            out[..4].copy_from_slice(&self.config.to_bytes());
+            out[4..].copy_from_slice(&self.device_id);
+            out
+        }
+
+        /// ECB encrypts the plaintext, ready for writing. 8 and 16 byte block
+        /// ciphers both cover the 16 bytes exactly.
+        pub fn encrypt<const N: usize, C: crate::diag::solo::BlockCipher<N>>(
+            &self,
+            cipher: &C,
+        ) -> EncryptedConfigBlob {
+            let mut unknown = self.to_bytes();
+            crate::diag::solo::encrypt_ecb(cipher, &mut unknown);
+            EncryptedConfigBlob { unknown }
+        }
+    }
+
+    impl TryFrom<&[u8]> for EncryptedConfigPayload {
+        type Error = DidDecodeError;
+
+        fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+            if bytes.len() != Self::LEN {
+                return Err(DidDecodeError::length_mismatch(bytes.len(), Self::LEN));
+            }
+            let mut device_id = [0u8; 12];
+            device_id.copy_from_slice(&bytes[4..]);
+            Ok(Self {
+                config: ControlConfig::try_from(&bytes[..4])?,
+                device_id,
+            })
+        }
+    }
+
     impl ReadableDid for EncryptedConfigBlob {}
     impl WritableDid for EncryptedConfigBlob {}
     impl ReadableDid for CellCalibrationState {}
@@ -621,10 +858,101 @@ impl ReadableDid for SerialNumber {}
 impl WritableDid for SerialNumber {}
 impl ReadableDid for DeviceId {}
 
+macro_rules! did_registry {
+    ($($name:ident: $ty:ty),* $(,)?) => {
+        /// Every DID with a decoder here, for tools that only have the number
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum AnyDid {
+            $($name($ty),)*
+        }
+
+        impl AnyDid {
+            /// Decodes `bytes` as the DID numbered `did`, `None` when there
+            /// is no decoder for it
+            pub fn decode(did: u16, bytes: &[u8]) -> Option<Result<Self, DidDecodeError>> {
+                $(
+                    if did == <$ty as DataIdentifier>::DID {
+                        return Some(<$ty>::try_from(bytes).map(AnyDid::$name));
+                    }
+                )*
+                None
+            }
+        }
+    };
+}
+
+did_registry!(
+    FirmwareDownloadCapability: FirmwareDownloadCapability,
+    LogUploadCapability: LogUploadCapability,
+    FirmwareCrc: FirmwareCrc,
+    SerialNumberAscii: SerialNumberAscii,
+    FirmwareVersionAscii: FirmwareVersionAscii,
+    SerialNumber: SerialNumber,
+    DeviceId: DeviceId,
+    ControlConfig: solo::ControlConfig,
+    CellCalibrationState: solo::CellCalibrationState,
+    VoltageCalibration: solo::VoltageCalibration,
+    CellCalibrationRequest: solo::CellCalibrationRequest,
+    CellZeroOffsets: solo::CellZeroOffsets,
+    CellZeroOffsetCalibrationRequest: solo::CellZeroOffsetCalibrationRequest,
+    EncryptedConfigBlob: solo::EncryptedConfigBlob,
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn registry_decodes_by_number() {
+        assert_eq!(
+            AnyDid::decode(did::SERIAL_NUMBER_ASCII, b"A005D007"),
+            Some(Ok(AnyDid::SerialNumberAscii(SerialNumberAscii {
+                serial_ascii: *b"A005D007"
+            })))
+        );
+        assert!(matches!(
+            AnyDid::decode(did::CONTROL_CONFIG, &[0, 0, 0x51, 0x41]),
+            Some(Ok(AnyDid::ControlConfig(_)))
+        ));
+        assert_eq!(
+            AnyDid::decode(did::FIRMWARE_CRC, &[1, 2]),
+            Some(Err(DidDecodeError::TooShort { needed: 4 }))
+        );
+        assert_eq!(AnyDid::decode(0xF1FF, &[]), None);
+    }
+
+    #[test]
+    fn lenient_decode() {
+        let exact = decode_lenient::<SerialNumberAscii>(b"A005D007");
+        assert_eq!(exact.value.unwrap().serial_ascii, *b"A005D007");
+        assert_eq!((exact.remainder, exact.warning), (&[][..], None));
+
+        let longer = decode_lenient::<SerialNumberAscii>(b"A005D007\x01\x02");
+        assert_eq!(longer.value.unwrap().serial_ascii, *b"A005D007");
+        assert_eq!(longer.remainder, &[1, 2]);
+        assert_eq!(longer.warning, Some(DidDecodeError::TooLong { max: 8 }));
+
+        let shorter = decode_lenient::<DeviceId>(&[0x50, 0xFF]);
+        assert_eq!(shorter.value, None);
+        assert_eq!(shorter.remainder, &[0x50, 0xFF]);
+        assert_eq!(
+            shorter.warning,
+            Some(DidDecodeError::TooShort { needed: 12 })
+        );
+        assert_eq!(shorter.partial_fields().count(), 0);
+
+        // The declared length, not the size of the encoding
+        assert_eq!(LogUploadCapability::LEN, 9);
+        let cut = decode_lenient::<LogUploadCapability>(&[1, 0x08, 0, 0, 0, 0x20]);
+        assert_eq!(cut.value, None);
+        assert_eq!(
+            cut.partial_fields().collect::<Vec<_>>(),
+            [("supported", &[1][..]), ("address", &[0x08, 0, 0, 0][..])]
+        );
+        let whole = decode_lenient::<LogUploadCapability>(&[0; 9]);
+        assert!(whole.value.is_some() && whole.partial_fields().count() == 0);
+    }
+
     #[test]
     fn test_0x8010() {
         // 0x8010 -> 4130303544303037 = ASCII "A005D007"
@@ -643,6 +971,7 @@ mod tests {
         assert_eq!(&result.to_bytes()[..], &input[..]);
 
         assert_eq!(&result.firmware_version_ascii, b"v12");
+        assert_eq!(result.version().unwrap().components(), [12]);
     }
 
     #[test]
@@ -683,6 +1012,25 @@ mod tests {
         let input = hex::decode("50FF68064884534917540887").unwrap();
         let result = DeviceId::try_from(input.as_slice()).unwrap();
         assert_eq!(&result.to_bytes()[..], &input[..]);
+        assert_eq!(format!("{}", result), "50FF6806-48845349-17540887");
+    }
+
+    #[test]
+    fn stm32_uid_fields() {
+        // X 0x0012, Y 0x0034, wafer 7, lot "QH12345"
+        let uid = DeviceId {
+            device_id: *b"\x12\x00\x34\x00\x07QH12345",
+        }
+        .stm32_uid();
+        assert_eq!((uid.x, uid.y, uid.wafer), (0x12, 0x34, 7));
+        assert_eq!(uid.lot_str(), Some("QH12345"));
+        assert!(uid.is_standard_layout());
+
+        let id = hex::decode("50FF68064884534917540887").unwrap();
+        let uid = DeviceId::try_from(id.as_slice()).unwrap().stm32_uid();
+        assert_eq!((uid.x, uid.y, uid.wafer), (0xFF50, 0x0668, 0x48));
+        assert_eq!(uid.lot_str(), None);
+        assert!(!uid.is_standard_layout());
     }
 }
 
@@ -699,6 +1047,36 @@ mod solo_tests {
         assert_eq!(result.to_bytes(), result.unknown);
     }
 
+    #[test]
+    fn encrypted_config_payload() {
+        use crate::diag::did::DeviceId;
+        use crate::diag::solo::BlockCipher;
+
+        struct Xor;
+        impl BlockCipher<8> for Xor {
+            fn encrypt_block(&self, block: &mut [u8; 8]) {
+                block.iter_mut().for_each(|b| *b ^= 0xFF);
+            }
+        }
+
+        let config = ControlConfig::try_from([0x00, 0x02, 0x44, 0x56].as_slice()).unwrap();
+        let device_id = DeviceId {
+            device_id: *b"\x00\x36\x00\x2D\x04\x51\x32\x30\x20\x36\x34\x36",
+        };
+        let payload = EncryptedConfigPayload::new(config.clone(), &device_id);
+        let bytes = payload.to_bytes();
+        assert_eq!(bytes[..4], config.to_bytes());
+        assert_eq!(bytes[4..], device_id.device_id);
+        assert_eq!(
+            EncryptedConfigPayload::try_from(bytes.as_slice()).unwrap(),
+            payload
+        );
+        assert!(EncryptedConfigPayload::try_from(&bytes[..12]).is_err());
+
+        let blob = payload.encrypt(&Xor);
+        assert!(blob.unknown.iter().zip(bytes).all(|(&e, p)| e == !p));
+    }
+
     #[test]
     fn test_0x8203() {
         // 0x8203 -> 000000B1000000B1000000A3010101
@@ -787,4 +1165,27 @@ mod solo_tests {
         let result = VoltageCalibration::try_from(input.as_slice()).unwrap();
         assert_eq!(&result.to_bytes()[..], &input[..]);
     }
+
+    #[test]
+    fn truncated_buffers_dont_panic() {
+        let full = [0x30u8; 32];
+        for len in 0..=full.len() {
+            let data = &full[..len];
+            let _ = ControlConfig::try_from(data);
+            let _ = CellCalibrationState::try_from(data);
+            let _ = VoltageCalibration::try_from(data);
+            let _ = CellCalibrationRequest::try_from(data);
+            let _ = CellZeroOffsets::try_from(data);
+            let _ = CellZeroOffsetCalibrationRequest::try_from(data);
+            let _ = EncryptedConfigBlob::try_from(data);
+            let _ = EncryptedConfigPayload::try_from(data);
+            let _ = super::FirmwareDownloadCapability::try_from(data);
+            let _ = super::LogUploadCapability::try_from(data);
+            let _ = super::FirmwareCrc::try_from(data);
+            let _ = super::SerialNumberAscii::try_from(data);
+            let _ = super::FirmwareVersionAscii::try_from(data);
+            let _ = super::SerialNumber::try_from(data);
+            let _ = super::DeviceId::try_from(data);
+        }
+    }
 }
@@ -0,0 +1,221 @@
+use core::cmp::Ordering;
+use core::fmt;
+
+/// ISO 14229 bootSoftwareIdentification, ASCII if the device has it
+pub const BOOT_SOFTWARE_ID_DID: u16 = 0xF180;
+/// ISO 14229 programmingDate, BCD
+pub const PROGRAMMING_DATE_DID: u16 = 0xF199;
+
+const MAX_COMPONENTS: usize = 4;
+
+/// Firmware version split into its numeric parts, e.g. "v12" => 12 and
+/// "1.2.3" => 1.2.3. A leading `v`/`V` and trailing padding are ignored.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FirmwareVersion {
+    components: [u16; MAX_COMPONENTS],
+    len: u8,
+}
+
+impl FirmwareVersion {
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        let raw = raw
+            .strip_prefix(b"v")
+            .or_else(|| raw.strip_prefix(b"V"))
+            .unwrap_or(raw);
+        let end = raw
+            .iter()
+            .position(|&b| b == 0 || b == b' ')
+            .unwrap_or(raw.len());
+
+        let mut components = [0u16; MAX_COMPONENTS];
+        let mut len = 0;
+        for part in raw[..end].split(|&b| b == b'.') {
+            if part.is_empty() || len == MAX_COMPONENTS {
+                return None;
+            }
+            let mut value: u16 = 0;
+            for &b in part {
+                if !b.is_ascii_digit() {
+                    return None;
+                }
+                value = value.checked_mul(10)?.checked_add((b - b'0') as u16)?;
+            }
+            components[len] = value;
+            len += 1;
+        }
+        if len == 0 {
+            return None;
+        }
+        Some(Self {
+            components,
+            len: len as u8,
+        })
+    }
+
+    pub fn components(&self) -> &[u16] {
+        &self.components[..self.len as usize]
+    }
+}
+
+/// Compares part by part, missing parts count as 0 so 1.2 == 1.2.0
+impl Ord for FirmwareVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let n = self.len.max(other.len) as usize;
+        self.components[..n].cmp(&other.components[..n])
+    }
+}
+
+impl PartialEq for FirmwareVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FirmwareVersion {}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, c) in self.components().iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Date from a BCD programming date DID, `YYMMDD` or `YYYYMMDD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BuildDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl BuildDate {
+    pub fn from_bcd(raw: &[u8]) -> Option<Self> {
+        let bcd = |b: u8| {
+            let (hi, lo) = (b >> 4, b & 0xF);
+            (hi < 10 && lo < 10).then_some(hi * 10 + lo)
+        };
+        let (year, rest) = match raw {
+            [yy, rest @ ..] if raw.len() == 3 => (2000 + bcd(*yy)? as u16, rest),
+            [cc, yy, rest @ ..] if raw.len() == 4 => {
+                (bcd(*cc)? as u16 * 100 + bcd(*yy)? as u16, rest)
+            }
+            _ => return None,
+        };
+        let (month, day) = (bcd(rest[0])?, bcd(rest[1])?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        Some(Self { year, month, day })
+    }
+}
+
+impl fmt::Display for BuildDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A line of a release manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Release<'a> {
+    pub version: FirmwareVersion,
+    /// Rest of the line after the version, e.g. date and download link
+    pub notes: &'a str,
+}
+
+/// Releases in a manifest, one `<version> [notes]` per line. Blank lines
+/// and lines starting with `#` are skipped, as are unparsable versions.
+pub fn releases(manifest: &str) -> impl Iterator<Item = Release<'_>> {
+    manifest.lines().filter_map(|line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (version, notes) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        Some(Release {
+            version: FirmwareVersion::parse(version.as_bytes())?,
+            notes: notes.trim(),
+        })
+    })
+}
+
+pub fn newest_release(manifest: &str) -> Option<Release<'_>> {
+    releases(manifest).max_by(|a, b| a.version.cmp(&b.version))
+}
+
+/// The newest release if it is newer than `installed`
+pub fn update_available<'a>(installed: &FirmwareVersion, manifest: &'a str) -> Option<Release<'a>> {
+    newest_release(manifest).filter(|r| r.version > *installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> FirmwareVersion {
+        FirmwareVersion::parse(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(v("v12").components(), [12]);
+        assert_eq!(v("V72").components(), [72]);
+        assert_eq!(v("010").components(), [10]);
+        assert_eq!(v("1.2.3").components(), [1, 2, 3]);
+        assert_eq!(v("v7\0").components(), [7]);
+        assert_eq!(FirmwareVersion::parse(b"v1x"), None);
+        assert_eq!(FirmwareVersion::parse(b"1..2"), None);
+        assert_eq!(FirmwareVersion::parse(b""), None);
+
+        assert!(v("v12") < v("v72"));
+        assert!(v("1.10") > v("1.9"));
+        assert_eq!(v("1.2"), v("1.2.0"));
+        assert_eq!(format!("{}", v("v1.02")), "1.2");
+    }
+
+    #[test]
+    fn build_dates() {
+        assert_eq!(
+            BuildDate::from_bcd(&[0x24, 0x03, 0x15]),
+            Some(BuildDate {
+                year: 2024,
+                month: 3,
+                day: 15
+            })
+        );
+        assert_eq!(
+            BuildDate::from_bcd(&[0x20, 0x25, 0x12, 0x31]).map(|d| d.year),
+            Some(2025)
+        );
+        assert_eq!(BuildDate::from_bcd(&[0x24, 0x13, 0x01]), None);
+        assert_eq!(BuildDate::from_bcd(&[0x2A, 0x01, 0x01]), None);
+        assert_eq!(BuildDate::from_bcd(&[0x24, 0x01]), None);
+    }
+
+    #[test]
+    fn manifest() {
+        let manifest = "# solo releases\nv70 2023-01-10\n\nv72 2024-05-02 https://example.invalid/v72\nbogus\n";
+        assert_eq!(releases(manifest).count(), 2);
+
+        let newest = newest_release(manifest).unwrap();
+        assert_eq!(newest.version, v("72"));
+        assert_eq!(newest.notes, "2024-05-02 https://example.invalid/v72");
+
+        assert_eq!(update_available(&v("v70"), manifest), Some(newest));
+        assert_eq!(update_available(&v("v72"), manifest), None);
+        assert_eq!(update_available(&v("v72"), ""), None);
+    }
+}
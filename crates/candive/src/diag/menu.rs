@@ -0,0 +1,475 @@
+//! Client side of the user-settings menu, what a handset does to show and
+//! edit another node's settings: `Count` for the number of items, `Info` for
+//! each item, `ReadState` for its value and range and `Enum` for the option
+//! labels of a selection. Writes go through `WriteInput`.
+
+use crate::diag::settings::{
+    CountDid, EnumDid, InfoDid, InputDid, SettingDid, SettingInfo, SettingValue, StateDid,
+    UserSettingDidError, UserSettingInput, UserSettingType,
+};
+use crate::diag::version::ProtocolVersion;
+use crate::uds::client::{RDBI_HEADER_LEN, UdsClientError, UdsTransport, rdbi_into, wdbi};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuError<E> {
+    Uds(UdsClientError<E>),
+    Payload(UserSettingDidError),
+    NotEditable,
+    /// Outside the item's range, or past its last option
+    OutOfRange,
+}
+
+impl<E> From<UdsClientError<E>> for MenuError<E> {
+    fn from(e: UdsClientError<E>) -> Self {
+        MenuError::Uds(e)
+    }
+}
+
+impl<E> From<UserSettingDidError> for MenuError<E> {
+    fn from(e: UserSettingDidError) -> Self {
+        MenuError::Payload(e)
+    }
+}
+
+/// One menu entry, as its `Info` DID describes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MenuItem {
+    pub index: u8,
+    pub name: [u8; 10],
+    pub editable: bool,
+    pub kind: UserSettingType,
+}
+
+impl MenuItem {
+    pub fn name(&self) -> &str {
+        label(&self.name)
+    }
+}
+
+/// Text of a NUL-padded name or option label, empty if it isn't UTF-8
+pub fn label(raw: &[u8]) -> &str {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    core::str::from_utf8(&raw[..end]).unwrap_or("")
+}
+
+/// Browses and edits the settings menu of the node at the other end of
+/// `transport`.
+pub struct MenuClient<'a, T: UdsTransport> {
+    transport: &'a mut T,
+    version: ProtocolVersion,
+}
+
+impl<'a, T: UdsTransport> MenuClient<'a, T> {
+    /// `version` decides how written values are encoded
+    pub fn new(transport: &'a mut T, version: ProtocolVersion) -> Self {
+        Self { transport, version }
+    }
+
+    /// Like [`new`](Self::new), with the version read from the device
+    pub fn connect(transport: &'a mut T) -> Result<Self, MenuError<T::Error>> {
+        let version = ProtocolVersion::query(transport)?;
+        Ok(Self::new(transport, version))
+    }
+
+    pub fn count(&mut self) -> Result<u8, MenuError<T::Error>> {
+        self.read(CountDid)
+    }
+
+    pub fn item(&mut self, index: u8) -> Result<MenuItem, MenuError<T::Error>> {
+        let SettingInfo {
+            name,
+            editable,
+            kind,
+        } = self.read(InfoDid { index })?;
+        Ok(MenuItem {
+            index,
+            name,
+            editable,
+            kind,
+        })
+    }
+
+    /// Current value of `item` with its range or number of options
+    pub fn value(&mut self, item: &MenuItem) -> Result<SettingValue, MenuError<T::Error>> {
+        let raw = self.read(StateDid { index: item.index })?;
+        Ok(SettingValue::decode(item.kind, &raw))
+    }
+
+    /// Label of option `option` of a selection, see [`label`]
+    pub fn option(&mut self, item: &MenuItem, option: u8) -> Result<[u8; 8], MenuError<T::Error>> {
+        self.read(EnumDid {
+            index: item.index,
+            enum_index: option,
+        })
+    }
+
+    /// Writes the option index of a selection, or the raw value of a number.
+    /// The value is checked against the range the device reports, when it
+    /// reports one.
+    pub fn write(&mut self, item: &MenuItem, value: u32) -> Result<(), MenuError<T::Error>> {
+        if !item.editable {
+            return Err(MenuError::NotEditable);
+        }
+        let in_range = match self.value(item)? {
+            SettingValue::SelectionIndex { max_index, .. } => value <= max_index as u32,
+            SettingValue::IntegerHex { min, max, .. }
+            | SettingValue::IntegerScaled { min, max, .. } => {
+                min >= max || (min..=max).contains(&value)
+            }
+        };
+        if !in_range {
+            return Err(MenuError::OutOfRange);
+        }
+
+        let input = UserSettingInput::for_value(item.kind, value, &self.version);
+        let (mut tx_buf, mut rx_buf) = ([0u8; 16], [0u8; 16]);
+        let did = InputDid { index: item.index }.to_did();
+        wdbi(
+            self.transport,
+            did,
+            input.as_bytes(),
+            &mut tx_buf,
+            &mut rx_buf,
+        )?;
+        Ok(())
+    }
+
+    fn read<D: SettingDid>(&mut self, did: D) -> Result<D::Payload, MenuError<T::Error>> {
+        let mut buf = [0u8; 16 + RDBI_HEADER_LEN];
+        let len = rdbi_into(self.transport, did.to_did(), &mut buf)?;
+        Ok(D::decode(&buf[..len])?)
+    }
+}
+
+/// Why [`SettingsCatalog::find`] didn't come up with one setting
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindError {
+    /// Nothing matched. The closest names, nearest first, for "did you mean".
+    NotFound { suggestions: Vec<MenuItem> },
+    /// The name starts several settings
+    Ambiguous(Vec<MenuItem>),
+}
+
+/// Every item of a node's menu, read once, to look settings up by name
+/// without a round trip per item.
+///
+/// Names are compared on their ASCII letters and digits only, ignoring case,
+/// so `low-sp` and `lowsp` both find "LowSP".
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsCatalog {
+    items: Vec<MenuItem>,
+}
+
+#[cfg(feature = "alloc")]
+impl SettingsCatalog {
+    /// Most suggestions [`find`](Self::find) gives on a miss
+    pub const MAX_SUGGESTIONS: usize = 3;
+
+    pub fn new(items: Vec<MenuItem>) -> Self {
+        Self { items }
+    }
+
+    /// Reads `Count` and then `Info` for every item
+    pub fn load<T: UdsTransport>(
+        menu: &mut MenuClient<'_, T>,
+    ) -> Result<Self, MenuError<T::Error>> {
+        let count = menu.count()?;
+        let items = (0..count).map(|i| menu.item(i)).collect::<Result<_, _>>()?;
+        Ok(Self::new(items))
+    }
+
+    pub fn items(&self) -> &[MenuItem] {
+        &self.items
+    }
+
+    /// The setting called `name`, nothing else
+    pub fn get(&self, name: &str) -> Option<&MenuItem> {
+        let wanted: Vec<u8> = normalized(name).collect();
+        self.items
+            .iter()
+            .find(|item| normalized(item.name()).eq(wanted.iter().copied()))
+    }
+
+    /// The setting called `name`, for writes, where a setting picked by a
+    /// prefix could be another than the one meant. Settings that `name`
+    /// starts come back as suggestions.
+    pub fn find_exact(&self, name: &str) -> Result<&MenuItem, FindError> {
+        match self.find(name) {
+            Ok(item) if self.get(name).is_some() => Ok(item),
+            Ok(item) => Err(FindError::NotFound {
+                suggestions: Vec::from([*item]),
+            }),
+            Err(FindError::Ambiguous(mut items)) => {
+                items.truncate(Self::MAX_SUGGESTIONS);
+                Err(FindError::NotFound { suggestions: items })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The setting called `name`, or else the only one whose name starts
+    /// with it.
+    pub fn find(&self, name: &str) -> Result<&MenuItem, FindError> {
+        if let Some(item) = self.get(name) {
+            return Ok(item);
+        }
+        let wanted: Vec<u8> = normalized(name).collect();
+        if wanted.is_empty() {
+            return Err(FindError::NotFound {
+                suggestions: Vec::new(),
+            });
+        }
+
+        let starting: Vec<&MenuItem> = self
+            .items
+            .iter()
+            .filter(|item| {
+                let mut have = normalized(item.name());
+                wanted.iter().all(|&b| have.next() == Some(b))
+            })
+            .collect();
+        match starting.as_slice() {
+            [item] => return Ok(item),
+            [] => {}
+            _ => {
+                return Err(FindError::Ambiguous(
+                    starting.into_iter().copied().collect(),
+                ));
+            }
+        }
+
+        let limit = (wanted.len() / 3).max(2);
+        let mut close: Vec<(usize, MenuItem)> = self
+            .items
+            .iter()
+            .map(|item| {
+                let have: Vec<u8> = normalized(item.name()).collect();
+                (edit_distance(&wanted, &have), *item)
+            })
+            .filter(|(distance, _)| *distance <= limit)
+            .collect();
+        close.sort_by_key(|(distance, _)| *distance);
+        Err(FindError::NotFound {
+            suggestions: close
+                .into_iter()
+                .take(Self::MAX_SUGGESTIONS)
+                .map(|(_, item)| item)
+                .collect(),
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn normalized(name: &str) -> impl Iterator<Item = u8> + '_ {
+    name.bytes()
+        .filter(u8::is_ascii_alphanumeric)
+        .map(|b| b.to_ascii_lowercase())
+}
+
+/// Levenshtein distance
+#[cfg(feature = "alloc")]
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &x) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(x != y);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE};
+
+    /// Device side of the menu: a selection and a scaled number
+    struct Device {
+        mode: u8,
+        ppo2: u32,
+    }
+
+    impl Device {
+        fn answer(&mut self, did: u16, data: &[u8], out: &mut [u8]) -> Option<usize> {
+            let setting = |name: &[u8], kind: u8, editable: u8, out: &mut [u8]| {
+                out[..12].fill(0);
+                out[..name.len()].copy_from_slice(name);
+                out[10] = kind;
+                out[11] = editable;
+                12
+            };
+            Some(match did {
+                0x9100 => {
+                    out[0] = 2;
+                    1
+                }
+                0x9110 => setting(b"Mode", 1, 1, out),
+                0x9111 => setting(b"LowSP", 2, 1, out),
+                0x9130 => {
+                    out[..16].copy_from_slice(
+                        &SettingValue::SelectionIndex {
+                            max_index: 1,
+                            current_index: self.mode,
+                        }
+                        .encode(),
+                    );
+                    16
+                }
+                0x9131 => {
+                    out[..16].copy_from_slice(
+                        &SettingValue::IntegerScaled {
+                            value: self.ppo2,
+                            divisor: 1,
+                            min: 40,
+                            max: 100,
+                        }
+                        .encode(),
+                    );
+                    16
+                }
+                0x9150 | 0x9151 => {
+                    let label: &[u8; 8] = if did == 0x9150 {
+                        b"Manual\0\0"
+                    } else {
+                        b"Auto\0\0\0\0"
+                    };
+                    out[..8].copy_from_slice(label);
+                    8
+                }
+                0x9350 => {
+                    self.mode = data[3];
+                    0
+                }
+                0x9351 => {
+                    self.ppo2 = u32::from_be_bytes(data[4..8].try_into().unwrap());
+                    0
+                }
+                _ => return None,
+            })
+        }
+    }
+
+    impl UdsTransport for Device {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            let did = u16::from_be_bytes([req[2], req[3]]);
+            let (head, tail) = resp_buf.split_at_mut(4);
+            match self.answer(did, &req[4..], tail) {
+                Some(len) => {
+                    head.copy_from_slice(&[DIVE_CAN_UDS_ADDR, req[1] + 0x40, req[2], req[3]]);
+                    Ok(4 + len)
+                }
+                None => {
+                    head.copy_from_slice(&[DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE, req[1], 0x31]);
+                    Ok(4)
+                }
+            }
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn browse_and_edit() {
+        let mut device = Device { mode: 0, ppo2: 70 };
+        let mut menu = MenuClient::new(&mut device, ProtocolVersion::UNKNOWN);
+        assert_eq!(menu.count(), Ok(2));
+
+        let mode = menu.item(0).unwrap();
+        assert_eq!((mode.name(), mode.editable), ("Mode", true));
+        assert_eq!(mode.kind, UserSettingType::Selection);
+        assert_eq!(label(&menu.option(&mode, 1).unwrap()), "Auto");
+        menu.write(&mode, 1).unwrap();
+        assert_eq!(
+            menu.value(&mode),
+            Ok(SettingValue::SelectionIndex {
+                max_index: 1,
+                current_index: 1
+            })
+        );
+        assert_eq!(menu.write(&mode, 2), Err(MenuError::OutOfRange));
+
+        let low = menu.item(1).unwrap();
+        assert_eq!(low.name(), "LowSP");
+        menu.write(&low, 50).unwrap();
+        assert_eq!(menu.write(&low, 130), Err(MenuError::OutOfRange));
+        assert_eq!(device.ppo2, 50);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn catalog_find() {
+        let mut device = Device { mode: 0, ppo2: 70 };
+        let mut menu = MenuClient::new(&mut device, ProtocolVersion::UNKNOWN);
+        let catalog = SettingsCatalog::load(&mut menu).unwrap();
+        assert_eq!(catalog.items().len(), 2);
+        assert_eq!(catalog.get("low-sp").map(|i| i.index), Some(1));
+        assert_eq!(catalog.get("low"), None);
+
+        let item = |index: u8, name: &str| {
+            let mut raw = [0u8; 10];
+            raw[..name.len()].copy_from_slice(name.as_bytes());
+            MenuItem {
+                index,
+                name: raw,
+                editable: true,
+                kind: UserSettingType::Integer,
+            }
+        };
+        let catalog = SettingsCatalog::new(vec![
+            item(0, "Mode"),
+            item(1, "LowSP"),
+            item(2, "LowBat"),
+            item(3, "Brightness"),
+        ]);
+        let found = |name| catalog.find(name).map(|i| i.name());
+        assert_eq!(found("MODE"), Ok("Mode"));
+        assert_eq!(found("bright"), Ok("Brightness"));
+        assert_eq!(found("low sp"), Ok("LowSP"));
+        assert_eq!(
+            found("low"),
+            Err(FindError::Ambiguous(vec![
+                item(1, "LowSP"),
+                item(2, "LowBat")
+            ]))
+        );
+        assert_eq!(
+            found("mdoe"),
+            Err(FindError::NotFound {
+                suggestions: vec![item(0, "Mode")]
+            })
+        );
+        assert_eq!(
+            found("--"),
+            Err(FindError::NotFound {
+                suggestions: vec![]
+            })
+        );
+
+        let exact = |name| catalog.find_exact(name).map(|i| i.name());
+        assert_eq!(exact("low sp"), Ok("LowSP"));
+        assert_eq!(
+            exact("bright"),
+            Err(FindError::NotFound {
+                suggestions: vec![item(3, "Brightness")]
+            })
+        );
+        assert_eq!(
+            exact("low"),
+            Err(FindError::NotFound {
+                suggestions: vec![item(1, "LowSP"), item(2, "LowBat")]
+            })
+        );
+    }
+}
@@ -1,8 +1,19 @@
 use core::ops::RangeInclusive;
 
+#[cfg(all(feature = "alloc", feature = "uds"))]
+pub mod anonymize;
+pub mod cellhealth;
+pub mod config;
+pub mod depth_comp;
 pub mod did;
+pub mod firmware;
+#[cfg(feature = "uds")]
+pub mod menu;
+#[cfg(feature = "uds")]
+pub mod session;
 pub mod settings;
 pub mod solo;
+pub mod version;
 
 pub struct Stm32Crc32 {
     crc: u32,
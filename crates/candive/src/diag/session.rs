@@ -0,0 +1,275 @@
+//! The handshake every tool does before talking to a device: read who it is
+//! and which firmware it runs, so encodings are picked once per connection
+//! instead of per command, and a dropped link can be picked up again only
+//! if the same device answers.
+
+use crate::diag::did::{DataIdentifier, DeviceId, FirmwareVersionAscii, SerialNumberAscii};
+#[cfg(feature = "alloc")]
+use crate::diag::menu::{MenuClient, MenuError, SettingsCatalog};
+use crate::diag::version::ProtocolVersion;
+use crate::uds::client::{RDBI_HEADER_LEN, UdsClientError, UdsTransport, rdbi_into};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError<E> {
+    Uds(UdsClientError<E>),
+    /// A reconnect reached a different device than the session started with
+    DeviceChanged,
+}
+
+impl<E> From<UdsClientError<E>> for SessionError<E> {
+    fn from(e: UdsClientError<E>) -> Self {
+        SessionError::Uds(e)
+    }
+}
+
+/// What the device says it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub serial: SerialNumberAscii,
+    pub device_id: DeviceId,
+    pub firmware: FirmwareVersionAscii,
+}
+
+impl DeviceIdentity {
+    /// `None` for a device without the identity DIDs. Transport errors are
+    /// returned.
+    pub fn read<T: UdsTransport>(
+        transport: &mut T,
+    ) -> Result<Option<Self>, UdsClientError<T::Error>> {
+        let (Some(serial), Some(device_id), Some(firmware)) = (
+            read_did(transport)?,
+            read_did(transport)?,
+            read_did(transport)?,
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            serial,
+            device_id,
+            firmware,
+        }))
+    }
+
+    /// Same unit, the firmware may have changed in between
+    pub fn same_device(&self, other: &Self) -> bool {
+        self.serial == other.serial && self.device_id == other.device_id
+    }
+}
+
+/// `None` when the device rejects the DID or answers with the wrong length
+fn read_did<D: DataIdentifier, T: UdsTransport>(
+    transport: &mut T,
+) -> Result<Option<D>, UdsClientError<T::Error>> {
+    let mut buf = [0u8; 12 + RDBI_HEADER_LEN];
+    match rdbi_into(transport, D::DID, &mut buf) {
+        Ok(len) => Ok(D::try_from(&buf[..len]).ok()),
+        Err(UdsClientError::Transport(e)) => Err(UdsClientError::Transport(e)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// A transport with the device's identity and protocol version read at
+/// connect. Requests pass straight through.
+pub struct DeviceSession<T: UdsTransport> {
+    transport: T,
+    identity: Option<DeviceIdentity>,
+    version: ProtocolVersion,
+    #[cfg(feature = "alloc")]
+    catalog: Option<SettingsCatalog>,
+}
+
+impl<T: UdsTransport> DeviceSession<T> {
+    /// Reads the identity DIDs over `transport`. A device without them still
+    /// connects, with [`ProtocolVersion::UNKNOWN`] unless it reports its
+    /// firmware version.
+    pub fn connect(mut transport: T) -> Result<Self, UdsClientError<T::Error>> {
+        let identity = DeviceIdentity::read(&mut transport)?;
+        let version = match &identity {
+            Some(identity) => ProtocolVersion::from_did(&identity.firmware),
+            None => ProtocolVersion::query(&mut transport)?,
+        };
+        Ok(Self {
+            transport,
+            identity,
+            version,
+            #[cfg(feature = "alloc")]
+            catalog: None,
+        })
+    }
+
+    /// Continues on a newly opened `transport` after the link dropped. Fails
+    /// with [`SessionError::DeviceChanged`] if another device answers, the
+    /// session then keeps the old transport.
+    pub fn reconnect(&mut self, transport: T) -> Result<(), SessionError<T::Error>> {
+        let fresh = Self::connect(transport)?;
+        match (&self.identity, &fresh.identity) {
+            (Some(old), Some(new)) if !old.same_device(new) => Err(SessionError::DeviceChanged),
+            (Some(_), None) => Err(SessionError::DeviceChanged),
+            _ => {
+                *self = fresh;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn identity(&self) -> Option<&DeviceIdentity> {
+        self.identity.as_ref()
+    }
+
+    pub fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// The device's settings menu, read on first use and kept until a
+    /// reconnect
+    #[cfg(feature = "alloc")]
+    pub fn settings_catalog(&mut self) -> Result<&SettingsCatalog, MenuError<T::Error>> {
+        let catalog = match self.catalog.take() {
+            Some(catalog) => catalog,
+            None => SettingsCatalog::load(&mut MenuClient::new(&mut self.transport, self.version))?,
+        };
+        Ok(self.catalog.insert(catalog))
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+}
+
+impl<T: UdsTransport> UdsTransport for DeviceSession<T> {
+    type Error = T::Error;
+
+    fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.transport.request(req, resp_buf)
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.transport.send(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::did;
+    use crate::uds::{DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE};
+
+    /// Answers the identity DIDs, or rejects all of them
+    struct Device {
+        serial: Option<&'static [u8; 8]>,
+        down: bool,
+        sent: usize,
+        requests: usize,
+    }
+
+    impl UdsTransport for Device {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            if self.down {
+                return Err(());
+            }
+            self.requests += 1;
+            let data: &[u8] = match (u16::from_be_bytes([req[2], req[3]]), self.serial) {
+                (did::SERIAL_NUMBER_ASCII, Some(serial)) => serial,
+                (did::DEVICE_ID, Some(_)) => &[0x50; 12],
+                (did::FIRMWARE_VERSION_ASCII, Some(_)) => b"v12",
+                // An empty settings menu
+                (0x9100, _) => &[0],
+                _ => {
+                    resp_buf[..4].copy_from_slice(&[
+                        DIVE_CAN_UDS_ADDR,
+                        SID_NEG_RESPONSE,
+                        req[1],
+                        0x31,
+                    ]);
+                    return Ok(4);
+                }
+            };
+            resp_buf[..4].copy_from_slice(&[DIVE_CAN_UDS_ADDR, req[1] + 0x40, req[2], req[3]]);
+            resp_buf[4..4 + data.len()].copy_from_slice(data);
+            Ok(4 + data.len())
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            self.sent += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handshake_and_reconnect() {
+        let device = |serial, down| Device {
+            serial,
+            down,
+            sent: 0,
+            requests: 0,
+        };
+
+        let mut session = DeviceSession::connect(device(Some(b"A005D007"), false)).unwrap();
+        let identity = session.identity().unwrap();
+        assert_eq!(&identity.serial.serial_ascii, b"A005D007");
+        assert_eq!(session.version().firmware().unwrap().components(), [12]);
+
+        assert!(session.reconnect(device(Some(b"A005D007"), false)).is_ok());
+        assert_eq!(
+            session.reconnect(device(Some(b"B0010001"), false)),
+            Err(SessionError::DeviceChanged)
+        );
+        assert_eq!(
+            session.reconnect(device(None, false)),
+            Err(SessionError::DeviceChanged)
+        );
+        assert_eq!(
+            session.reconnect(device(None, true)).map_err(|_| ()),
+            Err(())
+        );
+        assert!(!session.transport().down);
+
+        // No identity DIDs is still a session
+        let session = DeviceSession::connect(device(None, false)).unwrap();
+        assert!(session.identity().is_none());
+        assert_eq!(session.version(), ProtocolVersion::UNKNOWN);
+        assert!(DeviceSession::connect(device(None, true)).is_err());
+    }
+
+    #[test]
+    fn suppressed_requests_reach_the_transport() {
+        let device = Device {
+            serial: Some(b"A005D007"),
+            down: false,
+            sent: 0,
+            requests: 0,
+        };
+        let mut session = DeviceSession::connect(device).unwrap();
+        assert_eq!(
+            crate::uds::client::tester_present(&mut session, true),
+            Ok(())
+        );
+        assert_eq!(
+            crate::uds::client::ecu_reset(&mut session, 0x01, true),
+            Ok(())
+        );
+        assert_eq!(session.transport().sent, 2);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn settings_catalog_is_read_once() {
+        let device = Device {
+            serial: Some(b"A005D007"),
+            down: false,
+            sent: 0,
+            requests: 0,
+        };
+        let mut session = DeviceSession::connect(device).unwrap();
+        let connected = session.transport().requests;
+        assert!(session.settings_catalog().unwrap().items().is_empty());
+        assert!(session.settings_catalog().unwrap().items().is_empty());
+        assert_eq!(session.transport().requests, connected + 1);
+    }
+}
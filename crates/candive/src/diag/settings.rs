@@ -1,11 +1,29 @@
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
+
+use crate::diag::version::ProtocolVersion;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UserSettingDidError {
-    TooShort { needed: usize },
-    TooLong { max: usize },
+    TooShort {
+        needed: usize,
+    },
+    TooLong {
+        max: usize,
+    },
     InvalidFormat,
     UnknownDid(u16),
     BadSettingType(u8),
     BadEnumIndex(u8),
+    /// Read payload asked for a DID that is only written, see [`WritePayload`]
+    WriteOnly(u16),
 }
 
 impl UserSettingDidError {
@@ -120,6 +138,130 @@ impl TryFrom<u16> for UserSettingDid {
     }
 }
 
+/// A user-setting DID with the type its data decodes to. A caller that
+/// knows which DID it asked for gets that type, [`ReadPayload`] and
+/// [`WritePayload`] are for data whose DID is only known at runtime.
+pub trait SettingDid: Copy {
+    type Payload;
+
+    fn to_did(self) -> u16;
+
+    fn decode(data: &[u8]) -> Result<Self::Payload, UserSettingDidError>;
+}
+
+/// [`UserSettingDid::Count`], the number of settings
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CountDid;
+
+/// [`UserSettingDid::Info`], name and kind of setting `index`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InfoDid {
+    pub index: u8,
+}
+
+/// [`UserSettingDid::ReadState`], raw value of setting `index`, see
+/// [`SettingValue::decode`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StateDid {
+    pub index: u8,
+}
+
+/// [`UserSettingDid::Enum`], label of option `enum_index` of setting `index`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EnumDid {
+    pub index: u8,
+    pub enum_index: u8,
+}
+
+/// [`UserSettingDid::WriteInput`], the new value of setting `index`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputDid {
+    pub index: u8,
+}
+
+/// Answer to [`InfoDid`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SettingInfo {
+    pub name: [u8; 10],
+    pub editable: bool,
+    pub kind: UserSettingType,
+}
+
+impl SettingDid for CountDid {
+    type Payload = u8;
+
+    fn to_did(self) -> u16 {
+        UserSettingDid::Count.to_did()
+    }
+
+    fn decode(data: &[u8]) -> Result<u8, UserSettingDidError> {
+        data.first()
+            .copied()
+            .ok_or(UserSettingDidError::TooShort { needed: 1 })
+    }
+}
+
+impl SettingDid for InfoDid {
+    type Payload = SettingInfo;
+
+    fn to_did(self) -> u16 {
+        UserSettingDid::Info { index: self.index }.to_did()
+    }
+
+    fn decode(data: &[u8]) -> Result<SettingInfo, UserSettingDidError> {
+        let Some(&[ref name @ .., kind, editable]) = data.first_chunk::<12>() else {
+            return Err(UserSettingDidError::TooShort { needed: 12 });
+        };
+        Ok(SettingInfo {
+            name: *name,
+            editable: editable != 0,
+            kind: UserSettingType::try_from(kind)?,
+        })
+    }
+}
+
+impl SettingDid for StateDid {
+    type Payload = [u8; 16];
+
+    fn to_did(self) -> u16 {
+        UserSettingDid::ReadState { index: self.index }.to_did()
+    }
+
+    fn decode(data: &[u8]) -> Result<[u8; 16], UserSettingDidError> {
+        data.try_into()
+            .map_err(|_| UserSettingDidError::length_mismatch(data.len(), 16))
+    }
+}
+
+impl SettingDid for EnumDid {
+    type Payload = [u8; 8];
+
+    fn to_did(self) -> u16 {
+        UserSettingDid::Enum {
+            index: self.index,
+            enum_index: self.enum_index,
+        }
+        .to_did()
+    }
+
+    fn decode(data: &[u8]) -> Result<[u8; 8], UserSettingDidError> {
+        data.try_into()
+            .map_err(|_| UserSettingDidError::length_mismatch(data.len(), 8))
+    }
+}
+
+impl SettingDid for InputDid {
+    type Payload = UserSettingInput;
+
+    fn to_did(self) -> u16 {
+        UserSettingDid::WriteInput { index: self.index }.to_did()
+    }
+
+    fn decode(data: &[u8]) -> Result<UserSettingInput, UserSettingDidError> {
+        UserSettingInput::new(data)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SettingValue {
     SelectionIndex {
@@ -206,12 +348,127 @@ impl SettingValue {
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct UserSettingInput {
-    pub len: u8,
-    pub bytes: [u8; 8],
+    len: u8,
+    bytes: [u8; 8],
+}
+
+/// How a written value is laid out in a `WriteInput` payload
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    /// 4-byte big-endian value
+    Word,
+    /// The value in the last 4 of 8 bytes, the first 4 zero. The device
+    /// applies its own divisor.
+    Padded,
 }
 
+/// The [`InputFormat`]s of firmware from `since` on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputFormats {
+    /// First firmware with these formats, `None` for the oldest
+    pub since: Option<&'static str>,
+    pub selection: InputFormat,
+    /// Integer and scaled settings
+    pub number: InputFormat,
+}
+
+/// `WriteInput` layouts by firmware, oldest first. Every firmware seen so far
+/// takes selections as a [`Word`](InputFormat::Word) and numbers
+/// [`Padded`](InputFormat::Padded). One that lays them out differently gets
+/// an entry with its `since`.
+pub const INPUT_FORMATS: &[InputFormats] = &[InputFormats {
+    since: None,
+    selection: InputFormat::Word,
+    number: InputFormat::Padded,
+}];
+
+impl InputFormat {
+    /// The format `version` takes for a setting of `kind`, from
+    /// [`INPUT_FORMATS`]
+    pub fn for_setting(kind: UserSettingType, version: &ProtocolVersion) -> Self {
+        Self::from_table(INPUT_FORMATS, kind, version)
+    }
+
+    /// Newest entry of `table` that `version` is at least as new as, an
+    /// unknown version takes the newest
+    fn from_table(
+        table: &[InputFormats],
+        kind: UserSettingType,
+        version: &ProtocolVersion,
+    ) -> Self {
+        let formats = table
+            .iter()
+            .rev()
+            .find(|f| f.since.is_none_or(|since| version.at_least(since)))
+            .or(table.first());
+        match (formats, kind) {
+            (Some(f), UserSettingType::Selection) => f.selection,
+            (Some(f), _) => f.number,
+            (None, UserSettingType::Selection) => InputFormat::Word,
+            (None, _) => InputFormat::Padded,
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            InputFormat::Word => 4,
+            InputFormat::Padded => 8,
+        }
+    }
+}
+
+impl UserSettingInput {
+    pub const MIN_LEN: usize = 4;
+    pub const MAX_LEN: usize = 8;
+
+    /// Payload as received, between [`MIN_LEN`](Self::MIN_LEN) and
+    /// [`MAX_LEN`](Self::MAX_LEN) bytes
+    pub fn new(data: &[u8]) -> Result<Self, UserSettingDidError> {
+        if data.len() < Self::MIN_LEN {
+            return Err(UserSettingDidError::TooShort {
+                needed: Self::MIN_LEN,
+            });
+        }
+        if data.len() > Self::MAX_LEN {
+            return Err(UserSettingDidError::TooLong { max: Self::MAX_LEN });
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..data.len()].copy_from_slice(data);
+        Ok(Self {
+            len: data.len() as u8,
+            bytes,
+        })
+    }
+
+    /// `value` for a setting of `kind`, in the format `version` expects
+    pub fn for_value(kind: UserSettingType, value: u32, version: &ProtocolVersion) -> Self {
+        let mut bytes = [0u8; 8];
+        let format = InputFormat::for_setting(kind, version);
+        let len = format.size();
+        bytes[len - 4..len].copy_from_slice(&value.to_be_bytes());
+        Self {
+            len: len as u8,
+            bytes,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..(self.len as usize).min(Self::MAX_LEN)]
+    }
+
+    /// The written value, the last 4 bytes in either format
+    pub fn value(&self) -> u32 {
+        let bytes = self.as_bytes();
+        let tail: [u8; 4] = bytes[bytes.len().saturating_sub(4)..]
+            .try_into()
+            .unwrap_or([0; 4]);
+        u32::from_be_bytes(tail)
+    }
+}
+
+/// What the device answers when a user-setting DID is read
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum UserSettingPayload {
+pub enum ReadPayload {
     Count(u8),
     Info {
         name: [u8; 10],
@@ -219,79 +476,45 @@ pub enum UserSettingPayload {
         kind: UserSettingType,
     },
     State([u8; 16]),
-    Input(UserSettingInput),
     Enum([u8; 8]),
 }
 
-impl UserSettingPayload {
+impl ReadPayload {
+    /// Fails with [`UserSettingDidError::WriteOnly`] for `WriteInput`, whose
+    /// data is a [`WritePayload`].
     pub fn decode(ident: UserSettingDid, data: &[u8]) -> Result<Self, UserSettingDidError> {
-        match ident {
-            UserSettingDid::Count => {
-                if data.len() < 1 {
-                    return Err(UserSettingDidError::TooShort { needed: 1 });
-                }
-                Ok(UserSettingPayload::Count(data[0]))
-            }
-
+        Ok(match ident {
+            UserSettingDid::Count => ReadPayload::Count(CountDid::decode(data)?),
             UserSettingDid::Info { .. } => {
-                if data.len() < 12 {
-                    return Err(UserSettingDidError::TooShort { needed: 12 });
-                }
-
-                let name: [u8; 10] = data[0..10]
-                    .try_into()
-                    .map_err(|_| UserSettingDidError::length_mismatch(data.len(), 10))?;
-                let kind_byte = data[10];
-                let editable_byte = data[11];
-
-                let kind = UserSettingType::try_from(kind_byte)?;
-
-                Ok(UserSettingPayload::Info {
+                let SettingInfo {
                     name,
-                    editable: editable_byte != 0,
+                    editable,
                     kind,
-                })
-            }
-
-            UserSettingDid::ReadState { .. } => {
-                let data_array: [u8; 16] = data
-                    .try_into()
-                    .map_err(|_| UserSettingDidError::length_mismatch(data.len(), 16))?;
-                Ok(UserSettingPayload::State(data_array))
-            }
-
-            UserSettingDid::Enum { .. } => {
-                let name: [u8; 8] = data
-                    .try_into()
-                    .map_err(|_| UserSettingDidError::length_mismatch(data.len(), 8))?;
-                Ok(UserSettingPayload::Enum(name))
+                } = InfoDid::decode(data)?;
+                ReadPayload::Info {
+                    name,
+                    editable,
+                    kind,
+                }
             }
-
+            UserSettingDid::ReadState { .. } => ReadPayload::State(StateDid::decode(data)?),
+            UserSettingDid::Enum { .. } => ReadPayload::Enum(EnumDid::decode(data)?),
             UserSettingDid::WriteInput { .. } => {
-                if data.len() < 4 {
-                    return Err(UserSettingDidError::TooShort { needed: 4 });
-                }
-                if data.len() > 8 {
-                    return Err(UserSettingDidError::TooLong { max: 8 });
-                }
-                let len = data.len() as u8;
-                let mut bytes = [0u8; 8];
-                bytes[0..len as usize].copy_from_slice(data);
-                Ok(UserSettingPayload::Input(UserSettingInput { len, bytes }))
+                return Err(UserSettingDidError::WriteOnly(ident.to_did()));
             }
-        }
+        })
     }
 
     pub fn encode(&self, buf: &mut [u8]) -> Result<usize, UserSettingDidError> {
         match self {
-            UserSettingPayload::Count(count) => {
+            ReadPayload::Count(count) => {
                 if buf.len() < 1 {
                     return Err(UserSettingDidError::TooShort { needed: 1 });
                 }
                 buf[0] = *count;
                 Ok(1)
             }
-            UserSettingPayload::Info {
+            ReadPayload::Info {
                 name,
                 editable,
                 kind,
@@ -308,22 +531,14 @@ impl UserSettingPayload {
                 buf[name_len + 1] = if *editable { 1 } else { 0 };
                 Ok(name_len + 2)
             }
-            UserSettingPayload::State(raw_data) => {
+            ReadPayload::State(raw_data) => {
                 if buf.len() < 16 {
                     return Err(UserSettingDidError::TooShort { needed: 16 });
                 }
                 buf[0..16].copy_from_slice(raw_data);
                 Ok(16)
             }
-            UserSettingPayload::Input(input) => {
-                let len = input.len as usize;
-                if buf.len() < len {
-                    return Err(UserSettingDidError::TooShort { needed: len });
-                }
-                buf[0..len].copy_from_slice(&input.bytes[0..len]);
-                Ok(len)
-            }
-            UserSettingPayload::Enum(name) => {
+            ReadPayload::Enum(name) => {
                 let len = name.len();
                 if buf.len() < len {
                     return Err(UserSettingDidError::TooShort { needed: len });
@@ -335,14 +550,154 @@ impl UserSettingPayload {
     }
 }
 
+/// What a tool writes to a user-setting DID, only `WriteInput` takes writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WritePayload {
+    Input(UserSettingInput),
+}
+
+impl WritePayload {
+    pub fn decode(data: &[u8]) -> Result<Self, UserSettingDidError> {
+        Ok(WritePayload::Input(InputDid::decode(data)?))
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, UserSettingDidError> {
+        match self {
+            WritePayload::Input(input) => {
+                let bytes = input.as_bytes();
+                if buf.len() < bytes.len() {
+                    return Err(UserSettingDidError::TooShort {
+                        needed: bytes.len(),
+                    });
+                }
+                buf[..bytes.len()].copy_from_slice(bytes);
+                Ok(bytes.len())
+            }
+        }
+    }
+}
+
+/// How careful a tool has to be before writing a user setting, ordered from
+/// least to most restrictive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SettingRiskClass {
+    /// Safe to write without asking
+    Normal,
+    /// Can stop the unit from controlling PPO2 correctly, ask the user first
+    High,
+    /// Tools should refuse to write it at all
+    WriteProtected,
+}
+
+/// One entry of [`SETTING_RISKS`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SettingRisk {
+    /// Matched against the setting name reduced to lowercase alphanumerics,
+    /// anywhere in the name, so `solenoid` covers "Solenoid On" and "SolenoidT"
+    pub pattern: &'static str,
+    pub class: SettingRiskClass,
+    /// Shown to the user when asking for confirmation or refusing
+    pub reason: &'static str,
+}
+
+/// Risky user settings, shared by every tool that writes them. Settings that
+/// match no entry are [`SettingRiskClass::Normal`].
+pub const SETTING_RISKS: &[SettingRisk] = &[
+    SettingRisk {
+        pattern: "factory",
+        class: SettingRiskClass::WriteProtected,
+        reason: "set at manufacture",
+    },
+    SettingRisk {
+        pattern: "serial",
+        class: SettingRiskClass::WriteProtected,
+        reason: "identifies the unit, use `device serial` instead",
+    },
+    SettingRisk {
+        pattern: "solenoid",
+        class: SettingRiskClass::High,
+        reason: "solenoid timing controls oxygen injection",
+    },
+    SettingRisk {
+        pattern: "current",
+        class: SettingRiskClass::High,
+        reason: "wrong solenoid current limits hide a failing solenoid",
+    },
+    SettingRisk {
+        pattern: "volt",
+        class: SettingRiskClass::High,
+        reason: "wrong battery limits hide a flat battery",
+    },
+    SettingRisk {
+        pattern: "batt",
+        class: SettingRiskClass::High,
+        reason: "wrong battery limits hide a flat battery",
+    },
+    SettingRisk {
+        pattern: "calib",
+        class: SettingRiskClass::High,
+        reason: "cell calibration affects every PPO2 reading",
+    },
+];
+
+/// Most restrictive entry of [`SETTING_RISKS`] matching `name`, `None` for
+/// [`SettingRiskClass::Normal`] settings.
+pub fn setting_risk(name: &str) -> Option<&'static SettingRisk> {
+    SETTING_RISKS
+        .iter()
+        .filter(|risk| name_contains(name, risk.pattern))
+        .max_by_key(|risk| risk.class)
+}
+
+pub fn setting_risk_class(name: &str) -> SettingRiskClass {
+    setting_risk(name).map_or(SettingRiskClass::Normal, |risk| risk.class)
+}
+
+/// Whether `pattern` (lowercase alphanumerics) occurs in `name` once spaces,
+/// punctuation and case are ignored.
+fn name_contains(name: &str, pattern: &str) -> bool {
+    let normalized = || {
+        name.bytes()
+            .filter(u8::is_ascii_alphanumeric)
+            .map(|b| b.to_ascii_lowercase())
+    };
+    let len = normalized().count();
+    len >= pattern.len()
+        && (0..=len - pattern.len()).any(|start| {
+            normalized()
+                .skip(start)
+                .take(pattern.len())
+                .eq(pattern.bytes())
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use hex::FromHex;
 
+    #[test]
+    fn risk_classes() {
+        assert_eq!(setting_risk_class("Solenoid T"), SettingRiskClass::High);
+        assert_eq!(setting_risk_class("Min Current"), SettingRiskClass::High);
+        assert_eq!(setting_risk_class("BATT-TYPE"), SettingRiskClass::High);
+        assert_eq!(setting_risk_class("min-voltage"), SettingRiskClass::High);
+        assert_eq!(setting_risk_class("max-current"), SettingRiskClass::High);
+        assert_eq!(
+            setting_risk_class("Factory Calib"),
+            SettingRiskClass::WriteProtected
+        );
+        assert_eq!(setting_risk_class("Local Time"), SettingRiskClass::Normal);
+        assert_eq!(setting_risk_class(""), SettingRiskClass::Normal);
+        assert_eq!(
+            setting_risk("Min Volt").map(|r| r.reason),
+            Some("wrong battery limits hide a flat battery")
+        );
+    }
+
     #[test]
     fn info_roundtrip_to_uds() {
-        let payload = UserSettingPayload::Info {
+        let payload = ReadPayload::Info {
             name: *b"SOLO_SPEED",
             editable: true,
             kind: UserSettingType::Selection,
@@ -356,9 +711,41 @@ mod tests {
         assert_eq!(&data[..len], &bytes);
     }
 
+    #[test]
+    fn typed_dids() {
+        let info = Vec::from_hex("534F4C4F5F53504545440101").unwrap();
+        assert_eq!(
+            InfoDid::decode(&info),
+            Ok(SettingInfo {
+                name: *b"SOLO_SPEED",
+                editable: true,
+                kind: UserSettingType::Selection,
+            })
+        );
+        assert_eq!(
+            InfoDid::decode(&info[..11]),
+            Err(UserSettingDidError::TooShort { needed: 12 })
+        );
+        assert_eq!(InfoDid { index: 3 }.to_did(), 0x9113);
+        assert_eq!(CountDid::decode(&[4]), Ok(4));
+        assert_eq!(EnumDid::decode(b"PID_5SEC"), Ok(*b"PID_5SEC"));
+        assert_eq!(
+            EnumDid {
+                index: 1,
+                enum_index: 2
+            }
+            .to_did(),
+            UserSettingDid::Enum {
+                index: 1,
+                enum_index: 2
+            }
+            .to_did()
+        );
+    }
+
     #[test]
     fn name_roundtrip_to_uds() {
-        let payload = UserSettingPayload::Enum(*b"PID_5SEC");
+        let payload = ReadPayload::Enum(*b"PID_5SEC");
         let mut data: [u8; 100] = [0; 100];
         let len = payload.encode(&mut data).unwrap();
         //006291515049445f35534543
@@ -385,22 +772,21 @@ mod tests {
         let save = UserSettingDid::WriteInput { index: 0 };
         let expected_bytes = b"ABCDEFGH";
 
-        let payload = UserSettingPayload::Input(UserSettingInput {
-            len: 8,
-            bytes: *expected_bytes,
-        });
+        let payload = WritePayload::Input(UserSettingInput::new(expected_bytes).unwrap());
 
         let mut buf = [0u8; 16];
         let len = payload.encode(&mut buf).unwrap();
 
         // Only decode the actual encoded data, not the entire buffer
-        match UserSettingPayload::decode(save, &buf[..len]).unwrap() {
-            UserSettingPayload::Input(value) => {
-                assert_eq!(value.len, 8);
-                assert_eq!(value.bytes, *expected_bytes);
-            }
-            other => panic!("unexpected decoded variant: {other:?}"),
-        }
+        let WritePayload::Input(value) = WritePayload::decode(&buf[..len]).unwrap();
+        assert_eq!(value.len, 8);
+        assert_eq!(value.bytes, *expected_bytes);
+
+        // The write DID has nothing to read
+        assert_eq!(
+            ReadPayload::decode(save, &buf[..len]),
+            Err(UserSettingDidError::WriteOnly(0x9350))
+        );
     }
 
     #[test]
@@ -434,16 +820,91 @@ mod tests {
         }
     }
 
+    #[test]
+    fn input_for_value() {
+        let v12 = ProtocolVersion::from_did(&crate::diag::did::FirmwareVersionAscii {
+            firmware_version_ascii: *b"v12",
+        });
+
+        let selection = UserSettingInput::for_value(UserSettingType::Selection, 2, &v12);
+        assert_eq!(selection.as_bytes(), [0, 0, 0, 2]);
+        assert_eq!(selection.value(), 2);
+
+        for kind in [UserSettingType::Integer, UserSettingType::Scaled] {
+            let number = UserSettingInput::for_value(kind, 0x0102, &ProtocolVersion::UNKNOWN);
+            assert_eq!(number.as_bytes(), [0, 0, 0, 0, 0, 0, 1, 2]);
+            assert_eq!(number.value(), 0x0102);
+        }
+
+        assert_eq!(
+            UserSettingInput::new(&[1, 2, 3]),
+            Err(UserSettingDidError::TooShort { needed: 4 })
+        );
+        assert_eq!(UserSettingInput::new(&[0, 0, 0, 7]).unwrap().value(), 7);
+    }
+
+    #[test]
+    fn input_formats_by_version() {
+        let version = |v: &[u8; 3]| {
+            ProtocolVersion::from_did(&crate::diag::did::FirmwareVersionAscii {
+                firmware_version_ascii: *v,
+            })
+        };
+        // A made-up firmware 13 that takes selections padded
+        let table = [
+            INPUT_FORMATS[0],
+            InputFormats {
+                since: Some("13"),
+                selection: InputFormat::Padded,
+                number: InputFormat::Padded,
+            },
+        ];
+        let format = |v| InputFormat::from_table(&table, UserSettingType::Selection, &v);
+        assert_eq!(format(version(b"v12")), InputFormat::Word);
+        assert_eq!(format(version(b"v13")), InputFormat::Padded);
+        assert_eq!(format(ProtocolVersion::UNKNOWN), InputFormat::Padded);
+        assert_eq!(
+            InputFormat::from_table(&[], UserSettingType::Integer, &version(b"v12")),
+            InputFormat::Padded
+        );
+        assert_eq!(
+            InputFormat::for_setting(UserSettingType::Selection, &ProtocolVersion::UNKNOWN),
+            InputFormat::Word
+        );
+    }
+
     #[test]
     fn write_input_too_long_error() {
         // Test that data longer than 8 bytes returns error
-        let write_input = UserSettingDid::WriteInput { index: 0 };
         let too_long_data = b"ABCDEFGHI"; // 9 bytes
 
-        let result = UserSettingPayload::decode(write_input, too_long_data);
+        let result = WritePayload::decode(too_long_data);
         assert!(matches!(
             result,
             Err(UserSettingDidError::TooLong { max: 8 })
         ));
     }
+
+    #[test]
+    fn truncated_payloads_dont_panic() {
+        let full = [0x41u8; 20];
+        let dids = [
+            UserSettingDid::Count,
+            UserSettingDid::Info { index: 0 },
+            UserSettingDid::ReadState { index: 0 },
+            UserSettingDid::Enum {
+                index: 0,
+                enum_index: 0,
+            },
+            UserSettingDid::WriteInput { index: 0 },
+        ];
+        for len in 0..=full.len() {
+            let data = &full[..len];
+            for did in dids {
+                let _ = ReadPayload::decode(did, data);
+            }
+            let _ = WritePayload::decode(data);
+            assert_eq!(UserSettingInput::new(data).is_ok(), (4..=8).contains(&len));
+        }
+    }
 }
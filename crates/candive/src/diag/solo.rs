@@ -1,4 +1,8 @@
-use crate::diag::did::DidDecodeError;
+use core::ops::Range;
+
+use crate::cells::{CellArray, DIVECAN_CELLS};
+use crate::diag::did::{DidDecodeError, LogUploadCapability};
+use crate::divecan::{Alert, DiveCanFrame, DiveCanId, DiveTracker, DiveTransition, Msg};
 
 pub mod regions {
     use crate::diag::KnownRegion;
@@ -31,8 +35,12 @@ pub mod regions {
     };
 }
 
+/// Read from `MCU_DEVINFO` after a log upload. `log_crc32` and
+/// `transfer_start_timestamp` describe that upload only (the timestamp also
+/// seeds the log decryptor), so the digest can't be reused across chunks.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LogTransferDigest {
+    /// STM32 CRC32 of the encrypted data of the last upload
     pub log_crc32: u32,
     /// Length field (1 byte at offset 4, always 0x10 = 16).
     pub length: u8,
@@ -43,6 +51,9 @@ pub struct LogTransferDigest {
 }
 
 impl LogTransferDigest {
+    /// What [`Self::length`] always is
+    pub const LENGTH: u8 = 0x10;
+
     pub fn to_bytes(&self) -> [u8; 21] {
         let mut result = [0u8; 21];
         result[0..4].copy_from_slice(&self.log_crc32.to_le_bytes());
@@ -76,14 +87,302 @@ impl TryFrom<&[u8]> for LogTransferDigest {
     }
 }
 
+/// Byte ranges of [`DevInfo`] in `MCU_DEVINFO`. Only the digest is
+/// documented, nothing else in the region is confirmed on a device.
+pub mod devinfo_layout {
+    use core::ops::Range;
+
+    pub const LOG_DIGEST: Range<usize> = 0x00..0x15;
+    pub const RESERVED: Range<usize> = 0x15..0x80;
+    pub const LEN: usize = 0x80;
+}
+
+/// The whole `MCU_DEVINFO` region. Bytes nobody has a meaning for yet are
+/// kept, see [`Self::reserved`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevInfo {
+    pub log_digest: LogTransferDigest,
+    raw: [u8; devinfo_layout::LEN],
+}
+
+impl DevInfo {
+    /// The bytes of [`devinfo_layout::RESERVED`]
+    pub fn reserved(&self) -> &[u8] {
+        &self.raw[devinfo_layout::RESERVED]
+    }
+
+    pub fn as_bytes(&self) -> &[u8; devinfo_layout::LEN] {
+        &self.raw
+    }
+
+    /// Uploads the region
+    #[cfg(feature = "uds")]
+    pub fn read<T: crate::uds::client::UdsTransport>(
+        transport: &mut T,
+    ) -> Result<Result<Self, DidDecodeError>, crate::uds::client::UdsClientError<T::Error>> {
+        use crate::uds::Dlf;
+        use crate::uds::client::UploadSession;
+
+        let mut tx_buf = [0u8; 16];
+        let mut rx_buf = [0u8; devinfo_layout::LEN + 16];
+        let mut data = [0u8; devinfo_layout::LEN];
+        let start = *regions::MCU_DEVINFO.addr_range.start();
+        let mut session = UploadSession::start(
+            transport,
+            start,
+            devinfo_layout::LEN as u32,
+            Dlf::Normal,
+            &mut tx_buf,
+            &mut rx_buf,
+        )?;
+        let mut len = 0;
+        while len < data.len() {
+            match session.read_block(&mut data[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        session.finish()?;
+        Ok(Self::try_from(&data[..len]))
+    }
+}
+
+impl TryFrom<&[u8]> for DevInfo {
+    type Error = DidDecodeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        use devinfo_layout::*;
+
+        if bytes.len() < LEN {
+            return Err(DidDecodeError::TooShort { needed: LEN });
+        }
+        let mut raw = [0u8; LEN];
+        raw.copy_from_slice(&bytes[..LEN]);
+        Ok(Self {
+            log_digest: LogTransferDigest::try_from(&raw[LOG_DIGEST])?,
+            raw,
+        })
+    }
+}
+
 pub const LOG_ENTRY_SIZE: u32 = 12;
 
+/// Location and size of the device log, the base for all log offset math.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogsInfo {
+    pub address: u32,
+    pub size: u32,
+    pub entry_size: u32,
+    /// Reported by the device rather than taken from [`regions::MMC_LOG`]
+    pub from_device: bool,
+}
+
+impl LogsInfo {
+    /// The Solo layout, for devices that don't report one
+    pub const FALLBACK: LogsInfo = LogsInfo {
+        address: *regions::MMC_LOG.addr_range.start(),
+        size: *regions::MMC_LOG.addr_range.end() - *regions::MMC_LOG.addr_range.start() + 1,
+        entry_size: LOG_ENTRY_SIZE,
+        from_device: false,
+    };
+
+    /// Uses the reported region when the device says log upload is supported
+    /// and the region holds at least one entry, [`Self::FALLBACK`] otherwise.
+    pub fn from_capability(cap: &LogUploadCapability) -> Self {
+        if !cap.supported
+            || cap.size < LOG_ENTRY_SIZE
+            || cap.address.checked_add(cap.size).is_none()
+        {
+            return Self::FALLBACK;
+        }
+        LogsInfo {
+            address: cap.address,
+            size: cap.size,
+            entry_size: LOG_ENTRY_SIZE,
+            from_device: true,
+        }
+    }
+
+    /// Reads `LogUploadCapability`, falling back to [`Self::FALLBACK`] when
+    /// the device rejects or garbles it. Transport errors are returned.
+    #[cfg(feature = "uds")]
+    pub fn query<T: crate::uds::client::UdsTransport>(
+        transport: &mut T,
+    ) -> Result<Self, crate::uds::client::UdsClientError<T::Error>> {
+        use crate::diag::did::DataIdentifier;
+        use crate::uds::client::{RDBI_HEADER_LEN, UdsClientError, rdbi_into};
+
+        let mut buf = [0u8; 9 + RDBI_HEADER_LEN];
+        match rdbi_into(transport, LogUploadCapability::DID, &mut buf) {
+            Ok(len) => Ok(LogUploadCapability::try_from(&buf[..len])
+                .map_or(Self::FALLBACK, |cap| Self::from_capability(&cap))),
+            Err(UdsClientError::Transport(e)) => Err(UdsClientError::Transport(e)),
+            Err(_) => Ok(Self::FALLBACK),
+        }
+    }
+
+    pub fn entry_count(&self) -> u32 {
+        self.size / self.entry_size
+    }
+
+    /// Address of entry `index`
+    pub fn entry_address(&self, index: u32) -> u32 {
+        self.address + index * self.entry_size
+    }
+
+    /// Bytes taken by `count` entries
+    pub fn entries_len(&self, count: u32) -> u32 {
+        count * self.entry_size
+    }
+}
+
+/// Entries per log upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChunkSize {
+    Fixed(u32),
+    /// Sized from how fast the chunks so far came in, see [`LogChunker`]
+    Auto,
+}
+
+/// How a log read is split into uploads. Each upload is CRC checked and
+/// retried whole, so smaller chunks cost more requests but less on a retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogChunking {
+    pub size: ChunkSize,
+    /// Most entries in one chunk either way, e.g. from the memory available
+    pub max_entries: u32,
+}
+
+impl LogChunking {
+    /// Most entries an [`ChunkSize::Auto`] chunk grows to
+    pub const AUTO_MAX_ENTRIES: u32 = 1000;
+
+    pub fn chunks(&self, first: u32, count: u32) -> LogChunker {
+        let (entries, max_entries) = match self.size {
+            ChunkSize::Fixed(n) => (n, self.max_entries),
+            ChunkSize::Auto => (
+                LogChunker::PROBE_ENTRIES,
+                self.max_entries.min(Self::AUTO_MAX_ENTRIES),
+            ),
+        };
+        let max_entries = max_entries.max(1);
+        LogChunker {
+            next: first,
+            end: first.saturating_add(count),
+            auto: self.size == ChunkSize::Auto,
+            entries: entries.clamp(1, max_entries),
+            max_entries,
+        }
+    }
+}
+
+/// Hands out the entry ranges of a log read. With [`ChunkSize::Auto`] the
+/// first chunk is small to measure the link, the later ones are sized to
+/// take about [`Self::TARGET_CHUNK_MS`] each from [`Self::record`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogChunker {
+    next: u32,
+    end: u32,
+    auto: bool,
+    entries: u32,
+    max_entries: u32,
+}
+
+impl LogChunker {
+    pub const PROBE_ENTRIES: u32 = 16;
+    pub const TARGET_CHUNK_MS: u64 = 2000;
+    /// Most an auto chunk grows from one to the next, so one fast chunk
+    /// doesn't overshoot on a link that varies
+    const MAX_GROWTH: u32 = 4;
+
+    /// Entries of the next chunk, `None` when done
+    pub fn next_chunk(&mut self) -> Option<Range<u32>> {
+        if self.next >= self.end {
+            return None;
+        }
+        let start = self.next;
+        self.next = start + self.entries.min(self.end - start);
+        Some(start..self.next)
+    }
+
+    /// How long the last chunk of `entries` took to fetch
+    pub fn record(&mut self, entries: u32, elapsed_ms: u64) {
+        if !self.auto || entries == 0 {
+            return;
+        }
+        let sized = u64::from(entries) * Self::TARGET_CHUNK_MS / elapsed_ms.max(1);
+        let grown = self.entries.saturating_mul(Self::MAX_GROWTH);
+        self.entries = sized
+            .min(u64::from(grown))
+            .clamp(1, u64::from(self.max_entries)) as u32;
+    }
+
+    /// Size of the chunks handed out now
+    pub fn entries(&self) -> u32 {
+        self.entries
+    }
+}
+
+/// Largest RDBI data the Solo can return: a classic ISO-TP transfer (12-bit
+/// length) minus the address, SID and DID of the response.
+pub const MAX_RDBI_LEN: usize = 4095 - 4;
+
 #[derive(Debug, Clone, Copy)]
 pub struct LogEntry {
     pub kind: u8,
     pub payload: [u8; 8],
 }
 
+/// How logged entries map back onto the bus. The log keeps neither the
+/// addresses nor the DLC, so these are filled in per device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogProfile {
+    pub src: u8,
+    pub dst: u8,
+    /// DLC used for kinds without a known minimum size
+    pub default_dlc: u8,
+}
+
+impl LogProfile {
+    /// The Solo logs its own traffic; everything is attributed to node 0x04 -> 0x00
+    pub const SOLO: Self = Self {
+        src: 0x04,
+        dst: 0x00,
+        default_dlc: 8,
+    };
+}
+
+impl Default for LogProfile {
+    fn default() -> Self {
+        Self::SOLO
+    }
+}
+
+impl LogEntry {
+    /// Reconstructs the CAN frame this entry was logged from. The DLC is the
+    /// minimum size for the kind, so trailing bytes of longer frames are lost.
+    pub fn to_frame(&self, profile: &LogProfile) -> (DiveCanId, DiveCanFrame) {
+        let id = DiveCanId::new(profile.src, profile.dst, self.kind);
+        let dlc = Msg::dlc_min_size(self.kind)
+            .unwrap_or(profile.default_dlc)
+            .min(8);
+        let frame = DiveCanFrame::new(self.kind, dlc, self.payload).unwrap();
+        (id, frame)
+    }
+
+    /// Wall-clock time (Unix seconds) of the entry. Only Diving entries carry one.
+    pub fn timestamp(&self) -> Option<u32> {
+        match Msg::try_from_frame(&self.to_frame(&LogProfile::SOLO).1) {
+            Ok(Msg::Diving { timestamp, .. }) => Some(timestamp),
+            _ => None,
+        }
+    }
+}
+
 pub struct LogEntryIterator<'a> {
     data: &'a [u8],
     offset: usize,
@@ -98,6 +397,11 @@ impl<'a> LogEntryIterator<'a> {
             current_kind: 0x00,
         }
     }
+
+    /// Byte offset just past the last returned entry
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 }
 
 impl<'a> Iterator for LogEntryIterator<'a> {
@@ -105,31 +409,846 @@ impl<'a> Iterator for LogEntryIterator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         while self.offset + 12 <= self.data.len() {
-            let entry = &self.data[self.offset..self.offset + 12];
+            let slot = &self.data[self.offset..self.offset + 12];
+            let first = self.offset == 0;
             self.offset += 12;
+            if let Some(entry) = read_slot(&mut self.current_kind, slot, first) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
 
-            if entry.iter().all(|&b| b == 0xFF) || entry.iter().all(|&b| b == 0x00) {
-                self.current_kind = entry[10];
+/// The entry in `slot`, `kind` being what the slot before carried. Leaves
+/// the kind this slot carries in `kind`. `first` is set for the first slot
+/// of the data, which may be a [`LogWriter`] lead-in.
+fn read_slot(kind: &mut u8, slot: &[u8], first: bool) -> Option<LogEntry> {
+    let entry_kind = core::mem::replace(kind, slot[10]);
+    if is_blank_slot(slot) || (first && is_lead_in(slot)) {
+        return None;
+    }
+    let mut payload = [0u8; 8];
+    payload.copy_from_slice(&slot[..8]);
+    Some(LogEntry {
+        kind: entry_kind,
+        payload,
+    })
+}
+
+/// One slot of log data, as [`LogStream`] hands it on
+#[derive(Debug, Clone, Copy)]
+pub struct LogSlot {
+    /// From the start of the stream
+    pub offset: usize,
+    pub bytes: [u8; LOG_ENTRY_SIZE as usize],
+    /// `None` for a blank slot
+    pub entry: Option<LogEntry>,
+}
+
+/// Decodes log data handed over in pieces of any size, as it is downloaded
+/// or read from a file, holding on to no more than one partial slot. The
+/// streaming counterpart of [`LogEntryIterator`], for logs too large to keep
+/// in memory.
+#[derive(Debug, Clone, Default)]
+pub struct LogStream {
+    partial: [u8; LOG_ENTRY_SIZE as usize],
+    filled: usize,
+    kind: u8,
+    offset: usize,
+}
+
+impl LogStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `visit` for every slot `data` completes, stops at the first error
+    pub fn push<E>(
+        &mut self,
+        mut data: &[u8],
+        mut visit: impl FnMut(&LogSlot) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let size = self.partial.len();
+        while !data.is_empty() {
+            let take = (size - self.filled).min(data.len());
+            self.partial[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+            if self.filled < size {
+                break;
+            }
+
+            self.filled = 0;
+            let slot = LogSlot {
+                offset: self.offset,
+                bytes: self.partial,
+                entry: read_slot(&mut self.kind, &self.partial, self.offset == 0),
+            };
+            self.offset += size;
+            visit(&slot)?;
+        }
+        Ok(())
+    }
+
+    /// Bytes of an unfinished slot held back, 0 if the data so far ended on
+    /// a slot boundary
+    pub fn pending(&self) -> usize {
+        self.filled
+    }
+}
+
+/// A slot without an entry: erased or zeroed
+pub(crate) fn is_blank_slot(slot: &[u8]) -> bool {
+    slot.iter().all(|&b| b == 0xFF) || slot.iter().all(|&b| b == 0x00)
+}
+
+/// The slot [`LogWriter`] starts a log with: erased apart from the kind of
+/// the first entry. Only checked for the first slot of the data, whose own
+/// kind was in a slot that isn't there. Further in, the same bytes are an
+/// entry with an all-0xFF payload.
+fn is_lead_in(slot: &[u8]) -> bool {
+    slot.iter().enumerate().all(|(i, &b)| i == 10 || b == 0xFF)
+}
+
+/// Whether `data`, decrypted from the start of a slot, can be log data.
+/// Byte 10 of a written slot is the kind of the entry after it, as the
+/// decoder reads it: a known message kind, or still erased after the last
+/// entry. A wrong key turns it into noise that is rarely a known kind. A
+/// quarter of the slots may carry kinds this crate doesn't know yet.
+pub fn plausible_log(data: &[u8]) -> bool {
+    let (mut written, mut known) = (0usize, 0usize);
+    for (i, slot) in data.chunks_exact(LOG_ENTRY_SIZE as usize).enumerate() {
+        if is_blank_slot(slot) || (i == 0 && is_lead_in(slot)) {
+            continue;
+        }
+        written += 1;
+        if slot[10] == 0xFF || Msg::kind_info(slot[10]).is_some() {
+            known += 1;
+        }
+    }
+    known * 4 >= written * 3
+}
+
+/// A dive found in decrypted log data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiveSegment {
+    pub dive_number: u16,
+    /// Timestamp of the Diving entry that started the dive
+    pub timestamp: u32,
+    /// Byte range in the log. Starts one slot before the first entry, which
+    /// carries its kind, so the slice decodes on its own.
+    pub range: Range<usize>,
+}
+
+/// Splits decrypted log data into dives using the Diving start/stop entries.
+///
+/// A dive ends at a Diving entry with status 0, at the start of a dive with
+/// another number, or at the end of the data. A stop without a start (dive
+/// began before the downloaded window) is ignored.
+pub struct DiveSegments<'a> {
+    entries: LogEntryIterator<'a>,
+    len: usize,
+    dives: DiveTracker,
+    /// Timestamp and start offset of the dive in progress
+    open: Option<(u32, usize)>,
+}
+
+impl<'a> DiveSegments<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            entries: LogEntryIterator::new(data),
+            len: data.len(),
+            dives: DiveTracker::new(),
+            open: None,
+        }
+    }
+}
+
+impl Iterator for DiveSegments<'_> {
+    type Item = DiveSegment;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = LOG_ENTRY_SIZE as usize;
+        let close = |dive_number: u16, open: Option<(u32, usize)>, end: usize| {
+            open.map(|(timestamp, start)| DiveSegment {
+                dive_number,
+                timestamp,
+                range: start..end,
+            })
+        };
+
+        while let Some(entry) = self.entries.next() {
+            let (_, frame) = entry.to_frame(&LogProfile::SOLO);
+            let Ok(Msg::Diving {
+                status,
+                dive_number,
+                timestamp,
+            }) = Msg::try_from_frame(&frame)
+            else {
                 continue;
+            };
+
+            let entry_start = self.entries.offset() - slot;
+            let start = entry_start.saturating_sub(slot);
+            match self.dives.on_diving(status, dive_number) {
+                Some(DiveTransition::Started(_)) => self.open = Some((timestamp, start)),
+                Some(DiveTransition::Switched { ended, .. }) => {
+                    let open = self.open.replace((timestamp, start));
+                    return close(ended, open, entry_start);
+                }
+                Some(DiveTransition::Ended(ended)) => {
+                    return close(ended, self.open.take(), self.entries.offset());
+                }
+                None => {}
             }
+        }
 
-            let mut payload = [0u8; 8];
-            payload.copy_from_slice(&entry[..8]);
-            let kind = self.current_kind;
-            self.current_kind = entry[10];
+        let current = self.dives.current()?;
+        close(current, self.open.take(), self.len)
+    }
+}
+
+/// What [`DiveSplitter`] comes across, in log order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiveEvent<'a> {
+    Start {
+        dive_number: u16,
+        timestamp: u32,
+    },
+    /// Raw slot belonging to the dive started last
+    Slot(&'a [u8]),
+    End,
+}
 
-            return Some(LogEntry { kind, payload });
+/// Splits streamed log slots into dives with the same rules and slots as
+/// [`DiveSegments`], keeping only the slot before the current one.
+#[derive(Debug, Clone, Default)]
+pub struct DiveSplitter {
+    dives: DiveTracker,
+    previous: Option<[u8; LOG_ENTRY_SIZE as usize]>,
+}
+
+impl DiveSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Passes what `slot` starts, continues or ends to `emit`, stops at the
+    /// first error
+    pub fn on_slot<E>(
+        &mut self,
+        slot: &LogSlot,
+        mut emit: impl FnMut(DiveEvent<'_>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let previous = self.previous.replace(slot.bytes);
+        let diving = slot
+            .entry
+            .map(|entry| Msg::try_from_frame(&entry.to_frame(&LogProfile::SOLO).1));
+        let Some(Ok(Msg::Diving {
+            status,
+            dive_number,
+            timestamp,
+        })) = diving
+        else {
+            if self.dives.current().is_some() {
+                emit(DiveEvent::Slot(&slot.bytes))?;
+            }
+            return Ok(());
+        };
+
+        match self.dives.on_diving(status, dive_number) {
+            None if self.dives.current().is_some() => emit(DiveEvent::Slot(&slot.bytes)),
+            None => Ok(()),
+            Some(DiveTransition::Ended(_)) => {
+                emit(DiveEvent::Slot(&slot.bytes))?;
+                emit(DiveEvent::End)
+            }
+            Some(transition) => {
+                if transition.ended().is_some() {
+                    emit(DiveEvent::End)?;
+                }
+                emit(DiveEvent::Start {
+                    dive_number,
+                    timestamp,
+                })?;
+                // The slot before carries the kind of the Diving entry
+                if let Some(previous) = &previous {
+                    emit(DiveEvent::Slot(previous))?;
+                }
+                emit(DiveEvent::Slot(&slot.bytes))
+            }
+        }
+    }
+
+    /// Ends a dive still open when the data runs out
+    pub fn finish<E>(
+        &mut self,
+        mut emit: impl FnMut(DiveEvent<'_>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        match self.dives.current() {
+            Some(_) => {
+                self.dives = DiveTracker::new();
+                emit(DiveEvent::End)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// An alert found in decrypted log data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredAlert {
+    /// Details are cut off, the log keeps the minimum Alert size only
+    pub alert: Alert,
+    /// Log entries with this code folded into this one, the Solo repeats an
+    /// alert for as long as it is active
+    pub repeats: u32,
+    /// Dive number and timestamp of the last Diving entry before the alert.
+    /// Alerts carry no time of their own.
+    pub last_diving: Option<(u16, u32)>,
+}
+
+/// Collects the alerts in decrypted log data, in log order. Repeats of one
+/// code are folded until another alert or a Diving entry comes in between.
+pub struct StoredAlerts<'a> {
+    entries: LogEntryIterator<'a>,
+    scanner: AlertScanner,
+}
+
+impl<'a> StoredAlerts<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            entries: LogEntryIterator::new(data),
+            scanner: AlertScanner::new(),
+        }
+    }
+}
+
+impl Iterator for StoredAlerts<'_> {
+    type Item = StoredAlert;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in self.entries.by_ref() {
+            if let Some(done) = self.scanner.on_entry(&entry) {
+                return Some(done);
+            }
+        }
+        self.scanner.finish()
+    }
+}
+
+/// [`StoredAlerts`] for entries fed one at a time, e.g. from a [`LogStream`]
+#[derive(Debug, Clone, Default)]
+pub struct AlertScanner {
+    last_diving: Option<(u16, u32)>,
+    pending: Option<StoredAlert>,
+}
+
+impl AlertScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The alert `entry` shows is done folding, if any
+    pub fn on_entry(&mut self, entry: &LogEntry) -> Option<StoredAlert> {
+        let (_, frame) = entry.to_frame(&LogProfile::SOLO);
+        match Msg::try_from_frame(&frame) {
+            Ok(Msg::Diving {
+                dive_number,
+                timestamp,
+                ..
+            }) => {
+                self.last_diving = Some((dive_number, timestamp));
+                self.pending.take()
+            }
+            Ok(Msg::Alert(alert)) => match self.pending.as_mut() {
+                Some(pending) if pending.alert.code == alert.code => {
+                    pending.repeats += 1;
+                    None
+                }
+                _ => self.pending.replace(StoredAlert {
+                    alert,
+                    repeats: 1,
+                    last_diving: self.last_diving,
+                }),
+            },
+            _ => None,
+        }
+    }
+
+    /// The alert still being folded when the data runs out
+    pub fn finish(&mut self) -> Option<StoredAlert> {
+        self.pending.take()
+    }
+}
+
+/// Per-cell calibration values read before a calibration and polled after it
+/// until they stop changing, so `cal o2` and `cal zero` can show what moved.
+/// Readings still equal to the values before don't count towards settling,
+/// the device may not have applied the calibration yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalibrationCompare<const N: usize = DIVECAN_CELLS> {
+    before: CellArray<u32, N>,
+    /// Latest reading, from the first one that differs from `before` on
+    after: Option<CellArray<u32, N>>,
+    unchanged: u32,
+}
+
+impl<const N: usize> CalibrationCompare<N> {
+    /// Polls in a row that must read the same before the values count as
+    /// settled
+    pub const SETTLE_POLLS: u32 = 3;
+
+    pub fn new(before: CellArray<u32, N>) -> Self {
+        Self {
+            before,
+            after: None,
+            unchanged: 0,
+        }
+    }
+
+    /// Feeds a reading taken after the calibration was started, returns
+    /// true once the values have changed and then settled
+    pub fn poll(&mut self, reading: CellArray<u32, N>) -> bool {
+        match self.after {
+            None if reading == self.before => {}
+            Some(after) if after == reading => self.unchanged += 1,
+            _ => {
+                self.after = Some(reading);
+                self.unchanged = 1;
+            }
+        }
+        self.is_settled()
+    }
+
+    /// A reading differed from the values before
+    pub fn has_changed(&self) -> bool {
+        self.after.is_some()
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.unchanged >= Self::SETTLE_POLLS
+    }
+
+    /// Before and after, `tolerance` being the most a cell may move and
+    /// pass, `None` for no limit
+    pub fn report(&self, tolerance: Option<u32>) -> CalibrationDelta<N> {
+        CalibrationDelta {
+            before: self.before,
+            after: self.after.unwrap_or(self.before),
+            changed: self.has_changed(),
+            settled: self.is_settled(),
+            tolerance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalibrationDelta<const N: usize = DIVECAN_CELLS> {
+    pub before: CellArray<u32, N>,
+    pub after: CellArray<u32, N>,
+    /// False if no reading differed from `before`
+    pub changed: bool,
+    /// False if polling gave up while the values were still changing, or
+    /// before they changed at all
+    pub settled: bool,
+    pub tolerance: Option<u32>,
+}
+
+impl<const N: usize> CalibrationDelta<N> {
+    pub fn delta(&self, cell: usize) -> i64 {
+        self.after[cell] as i64 - self.before[cell] as i64
+    }
+
+    pub fn cell_passed(&self, cell: usize) -> bool {
+        self.tolerance
+            .is_none_or(|tolerance| self.delta(cell).unsigned_abs() <= tolerance as u64)
+    }
+
+    /// Settled with every cell within the tolerance
+    pub fn passed(&self) -> bool {
+        self.settled && (0..N).all(|cell| self.cell_passed(cell))
+    }
+}
+
+/// Smallest share of entries (percent) whose kind must decode for decrypted
+/// log data to pass [`LogReport::problems`]. Kinds not in the protocol
+/// table yet keep a real log a little short of all of them.
+pub const MIN_DECODABLE_PERCENT: usize = 90;
+
+/// Structure of decrypted log data, as [`LogCheck`] counted it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogReport {
+    pub slots: usize,
+    pub blank: usize,
+    pub entries: usize,
+    /// Entries that decode as a known message
+    pub decodable: usize,
+    /// Entries after the first whose kind byte, in the slot before, is
+    /// still erased
+    pub missing_kinds: usize,
+    /// Bytes after the last whole slot
+    pub trailing: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogProblem {
+    /// No whole slot
+    Empty,
+    /// The data isn't a whole number of slots
+    Misaligned {
+        trailing: usize,
+    },
+    MissingKinds {
+        entries: usize,
+    },
+    /// Fewer than [`MIN_DECODABLE_PERCENT`] of the entries decode, likely
+    /// still encrypted or decrypted with the wrong key
+    Undecodable {
+        decodable: usize,
+        entries: usize,
+    },
+}
+
+impl LogReport {
+    /// Reports every way the data doesn't look like a decrypted log. No
+    /// report means it passed.
+    pub fn problems(&self, mut report: impl FnMut(LogProblem)) {
+        if self.slots == 0 {
+            report(LogProblem::Empty);
+        }
+        if self.trailing != 0 {
+            report(LogProblem::Misaligned {
+                trailing: self.trailing,
+            });
+        }
+        if self.missing_kinds != 0 {
+            report(LogProblem::MissingKinds {
+                entries: self.missing_kinds,
+            });
+        }
+        if self.decodable * 100 < self.entries * MIN_DECODABLE_PERCENT {
+            report(LogProblem::Undecodable {
+                decodable: self.decodable,
+                entries: self.entries,
+            });
         }
-        None
     }
 }
 
+/// Checks decrypted log data handed over in pieces, e.g. an exported file
+/// read in chunks. The data may start anywhere on a slot boundary, the kind
+/// of its first entry is then unknown and not counted against it.
+#[derive(Debug, Clone, Default)]
+pub struct LogCheck {
+    stream: LogStream,
+    report: LogReport,
+    /// Kind byte of the previous slot
+    prev_kind: Option<u8>,
+}
+
+impl LogCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        let Self {
+            stream,
+            report,
+            prev_kind,
+        } = self;
+        let _ = stream.push(data, |slot| {
+            report.slots += 1;
+            let kind = prev_kind.replace(slot.bytes[10]);
+            let Some(entry) = slot.entry else {
+                report.blank += 1;
+                return Ok::<_, core::convert::Infallible>(());
+            };
+            report.entries += 1;
+            if kind == Some(0xFF) {
+                report.missing_kinds += 1;
+            }
+            if Msg::try_from_frame(&entry.to_frame(&LogProfile::SOLO).1).is_ok() {
+                report.decodable += 1;
+            }
+            Ok(())
+        });
+    }
+
+    pub fn finish(&self) -> LogReport {
+        LogReport {
+            trailing: self.stream.pending(),
+            ..self.report
+        }
+    }
+}
+
+/// Flash the log lives in, as NOR flash behaves: erasing sets a whole block
+/// to 0xFF, programming can only clear bits and one program operation must
+/// stay within one page.
+pub trait BlockStorage {
+    type Error;
+
+    /// Bytes of storage, a multiple of [`erase_size`](Self::erase_size)
+    fn capacity(&self) -> usize;
+    fn erase_size(&self) -> usize;
+    /// Page size, no [`program`](Self::program) call crosses a page boundary
+    fn program_size(&self) -> usize;
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+    /// Erases the block starting at `offset`
+    fn erase(&mut self, offset: usize) -> Result<(), Self::Error>;
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RamStorageError {
+    OutOfBounds,
+    Misaligned,
+    /// Program would have to set bits that aren't erased
+    NotErased,
+}
+
+/// In-memory [`BlockStorage`] that enforces the NOR rules, the fake flash for
+/// simulators and tests.
+pub struct RamStorage<const N: usize> {
+    data: [u8; N],
+    erase_size: usize,
+    program_size: usize,
+}
+
+impl<const N: usize> RamStorage<N> {
+    /// Starts out erased. `N` must be a multiple of `erase_size`.
+    pub fn new(erase_size: usize, program_size: usize) -> Self {
+        assert!(erase_size > 0 && N.is_multiple_of(erase_size));
+        Self {
+            data: [0xFF; N],
+            erase_size,
+            program_size,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl<const N: usize> BlockStorage for RamStorage<N> {
+    type Error = RamStorageError;
+
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn erase_size(&self) -> usize {
+        self.erase_size
+    }
+
+    fn program_size(&self) -> usize {
+        self.program_size
+    }
+
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), RamStorageError> {
+        let src = self
+            .data
+            .get(offset..offset + buf.len())
+            .ok_or(RamStorageError::OutOfBounds)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn erase(&mut self, offset: usize) -> Result<(), RamStorageError> {
+        if !offset.is_multiple_of(self.erase_size) {
+            return Err(RamStorageError::Misaligned);
+        }
+        self.data
+            .get_mut(offset..offset + self.erase_size)
+            .ok_or(RamStorageError::OutOfBounds)?
+            .fill(0xFF);
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), RamStorageError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let page = offset / self.program_size;
+        if (offset + data.len() - 1) / self.program_size != page {
+            return Err(RamStorageError::Misaligned);
+        }
+        let dst = self
+            .data
+            .get_mut(offset..offset + data.len())
+            .ok_or(RamStorageError::OutOfBounds)?;
+        if dst.iter().zip(data).any(|(&old, &new)| old & new != new) {
+            return Err(RamStorageError::NotErased);
+        }
+        dst.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogWriteError<E> {
+    Storage(E),
+    /// No free slot left
+    Full,
+}
+
+/// Appends entries in the format [`LogEntryIterator`] reads.
+///
+/// An entry's kind is stored in byte 10 of the slot before it, so each append
+/// programs that byte (still erased) and then the new slot's payload. Bytes
+/// 8, 9 and 11 are left erased. A fresh log starts with a lead-in slot that
+/// only carries the first kind. Blocks are erased as the log reaches them.
+/// The writer doesn't wrap around, it fails with [`LogWriteError::Full`]
+/// at the end of the storage.
+pub struct LogWriter<S: BlockStorage> {
+    storage: S,
+    /// Slot the next entry goes into
+    next: usize,
+    /// Whether the slot before `next` can take the kind of the next entry
+    kind_pending: bool,
+    /// Bytes before this are written or known to be erased
+    erased_to: usize,
+}
+
+impl<S: BlockStorage> LogWriter<S> {
+    /// Continues the log in `storage` after its last entry, found as the first
+    /// erased slot. Storage that is erased from the start gets a new log.
+    ///
+    /// The slots are scanned in order rather than bisected: a log written by
+    /// a device that wraps around has its newest entries before an erased
+    /// gap and older ones after it, not one written prefix.
+    pub fn open(mut storage: S) -> Result<Self, LogWriteError<S::Error>> {
+        let slot = LOG_ENTRY_SIZE as usize;
+        let slots = storage.capacity() / slot;
+        let read_slot = |storage: &mut S, index: usize| {
+            let mut buf = [0u8; LOG_ENTRY_SIZE as usize];
+            storage
+                .read(index * slot, &mut buf)
+                .map_err(LogWriteError::Storage)?;
+            Ok(buf)
+        };
+
+        let mut next = slots;
+        for index in 0..slots {
+            if read_slot(&mut storage, index)?.iter().all(|&b| b == 0xFF) {
+                next = index;
+                break;
+            }
+        }
+        // A torn append can leave the kind programmed without its entry
+        let kind_pending = next > 0 && read_slot(&mut storage, next - 1)?[10] == 0xFF;
+        let erase_size = storage.erase_size();
+        Ok(Self {
+            storage,
+            next,
+            kind_pending,
+            erased_to: (next * slot).next_multiple_of(erase_size),
+        })
+    }
+
+    pub fn append(&mut self, msg: &Msg) -> Result<(), LogWriteError<S::Error>> {
+        let slot = LOG_ENTRY_SIZE as usize;
+        let needed = if self.kind_pending { 1 } else { 2 };
+        if (self.next + needed) * slot > self.storage.capacity() {
+            return Err(LogWriteError::Full);
+        }
+
+        let frame = msg.to_frame();
+        if !self.kind_pending {
+            self.program(self.next * slot + 10, &[frame.kind()])?;
+            self.next += 1;
+        } else {
+            self.program((self.next - 1) * slot + 10, &[frame.kind()])?;
+        }
+        let mut payload = [0u8; 8];
+        payload[..frame.bytes().len()].copy_from_slice(frame.bytes());
+        self.program(self.next * slot, &payload)?;
+        self.next += 1;
+        self.kind_pending = true;
+        Ok(())
+    }
+
+    /// Slots written so far, including the lead-in
+    pub fn slots_used(&self) -> usize {
+        self.next
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+    /// Programs `data`, erasing blocks it reaches and splitting at pages
+    fn program(
+        &mut self,
+        mut offset: usize,
+        mut data: &[u8],
+    ) -> Result<(), LogWriteError<S::Error>> {
+        let end = offset + data.len();
+        while self.erased_to < end {
+            self.storage
+                .erase(self.erased_to)
+                .map_err(LogWriteError::Storage)?;
+            self.erased_to += self.storage.erase_size();
+        }
+        let page = self.storage.program_size();
+        while !data.is_empty() {
+            let len = data.len().min(page - offset % page);
+            self.storage
+                .program(offset, &data[..len])
+                .map_err(LogWriteError::Storage)?;
+            offset += len;
+            data = &data[len..];
+        }
+        Ok(())
+    }
+}
+
+/// Encrypt direction of a block cipher with an `N` byte block.
+///
+/// Current firmware uses single DES; the length is a parameter so log and
+/// config encryption can move to 3DES or AES without changing callers.
+pub trait BlockCipher<const N: usize> {
+    fn encrypt_block(&self, block: &mut [u8; N]);
+}
+
+/// The DES-only trait [`BlockCipher`] replaced. Implementors are
+/// [`BlockCipher<8>`] through the blanket impl, so they keep working with
+/// [`LogDecryptor::new`].
+#[deprecated(note = "implement BlockCipher<8> instead")]
 pub trait DesEncryptor {
     fn encrypt_block(&self, block: &mut [u8; 8]);
 }
 
+#[allow(deprecated)]
+impl<T: DesEncryptor> BlockCipher<8> for T {
+    fn encrypt_block(&self, block: &mut [u8; 8]) {
+        DesEncryptor::encrypt_block(self, block)
+    }
+}
+
+/// Encrypts `data` in place, block by block (ECB). Trailing bytes short of a
+/// full block are left untouched.
+pub fn encrypt_ecb<const N: usize, C: BlockCipher<N>>(cipher: &C, data: &mut [u8]) {
+    for chunk in data.chunks_exact_mut(N) {
+        let block: &mut [u8; N] = chunk.try_into().unwrap();
+        cipher.encrypt_block(block);
+    }
+}
+
 pub struct LogDecryptor {
-    key_material: [[u8; 8]; 3],
+    key_material: [u8; Self::KEY_MATERIAL_LEN],
     seed: u64,
     pos: u8,
 }
@@ -137,18 +1256,27 @@ pub struct LogDecryptor {
 impl LogDecryptor {
     const LCG_MULT: u64 = 0x10A860C1;
     const LCG_MOD: u64 = 0xFFFFFFFB;
+    const KEY_MATERIAL_LEN: usize = 24;
 
-    pub fn new<E: DesEncryptor>(des: &E, device_id: &[u8], timestamp: u32) -> Self {
-        let mut key_material = [[0u8; 8]; 3];
+    /// `N` must be 8 or 16. With 16-byte blocks the 24 bytes of key material
+    /// are zero padded to two blocks before encryption.
+    pub fn new<const N: usize, C: BlockCipher<N>>(
+        cipher: &C,
+        device_id: &[u8],
+        timestamp: u32,
+    ) -> Self {
+        const { assert!(N == 8 || N == 16) };
 
-        key_material[0][0..8].copy_from_slice(&device_id[0..8]);
-        key_material[1][0..4].copy_from_slice(&device_id[8..12]);
-        key_material[1][4..8].copy_from_slice(&timestamp.to_le_bytes());
-        key_material[2].copy_from_slice(&[0xda, 0x65, 0x20, 0x33, 0xc8, 0x57, 0x40, 0xd3]);
+        let mut material = [0u8; 32];
+        material[0..12].copy_from_slice(&device_id[0..12]);
+        material[12..16].copy_from_slice(&timestamp.to_le_bytes());
+        material[16..24].copy_from_slice(&[0xda, 0x65, 0x20, 0x33, 0xc8, 0x57, 0x40, 0xd3]);
 
-        for i in 0..3 {
-            des.encrypt_block(&mut key_material[i]);
-        }
+        let blocks = Self::KEY_MATERIAL_LEN.div_ceil(N);
+        encrypt_ecb(cipher, &mut material[..blocks * N]);
+
+        let mut key_material = [0u8; Self::KEY_MATERIAL_LEN];
+        key_material.copy_from_slice(&material[..Self::KEY_MATERIAL_LEN]);
 
         Self {
             key_material,
@@ -160,9 +1288,7 @@ impl LogDecryptor {
     #[inline]
     pub fn decrypt(&mut self, buf: &mut [u8]) {
         for b in buf {
-            let block_index = (self.pos >> 3) as usize;
-            let byte_index = (self.pos & 7) as usize;
-            let key_byte = self.key_material[block_index][byte_index];
+            let key_byte = self.key_material[self.pos as usize];
 
             self.seed = (self.seed.wrapping_mul(Self::LCG_MULT)) % Self::LCG_MOD;
             let keystream_byte = (self.seed & 0xFF) as u8;
@@ -170,13 +1296,49 @@ impl LogDecryptor {
             *b ^= key_byte ^ keystream_byte;
 
             self.pos += 1;
-            if self.pos == 24 {
+            if self.pos as usize == Self::KEY_MATERIAL_LEN {
                 self.pos = 0;
             }
         }
     }
 }
 
+/// What the firmware does before an upload, for simulators and test
+/// fixtures. The keystream of [`LogDecryptor`] is XORed, so encrypting is
+/// the same walk.
+pub struct LogEncryptor(LogDecryptor);
+
+impl LogEncryptor {
+    pub fn new<const N: usize, C: BlockCipher<N>>(
+        cipher: &C,
+        device_id: &[u8],
+        timestamp: u32,
+    ) -> Self {
+        Self(LogDecryptor::new(cipher, device_id, timestamp))
+    }
+
+    pub fn encrypt(&mut self, buf: &mut [u8]) {
+        self.0.decrypt(buf);
+    }
+
+    /// Encrypts a whole upload in place and returns the digest the device
+    /// reports for it
+    pub fn seal<const N: usize, C: BlockCipher<N>>(
+        cipher: &C,
+        device_id: &[u8; 12],
+        timestamp: u32,
+        buf: &mut [u8],
+    ) -> LogTransferDigest {
+        Self::new(cipher, device_id, timestamp).encrypt(buf);
+        LogTransferDigest {
+            log_crc32: super::Stm32Crc32::stm32_crc32(buf),
+            length: 0x10,
+            transfer_start_timestamp: timestamp,
+            physical_device_id: *device_id,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -191,11 +1353,540 @@ mod tests {
 
         let parsed = LogTransferDigest::try_from(buffer.as_slice()).unwrap();
         assert_eq!(parsed.log_crc32, 0x4fcaf787);
-        assert_eq!(parsed.length, 0x10);
+        assert_eq!(parsed.length, LogTransferDigest::LENGTH);
         assert_eq!(parsed.transfer_start_timestamp, 0x0002c1c9);
         assert_eq!(
             hex::encode(&parsed.physical_device_id).to_uppercase(),
             "50FF68064884534917540887"
         );
     }
+
+    #[test]
+    fn devinfo_fields() {
+        let mut region = [0u8; 0x80];
+        // The real digest above, then made-up reserved bytes
+        region[..21].copy_from_slice(&[
+            0x87, 0xf7, 0xca, 0x4f, 0x10, 0xc9, 0xc1, 0x02, 0x00, 0x50, 0xff, 0x68, 0x06, 0x48,
+            0x84, 0x53, 0x49, 0x17, 0x54, 0x08, 0x87,
+        ]);
+        region[0x15] = 0xAA;
+        region[0x7F] = 0x5A;
+
+        let info = DevInfo::try_from(&region[..]).unwrap();
+        assert_eq!(info.log_digest.log_crc32, 0x4fcaf787);
+        assert_eq!(info.reserved().len(), 0x6B);
+        assert_eq!(info.reserved().first(), Some(&0xAA));
+        assert_eq!(info.reserved().last(), Some(&0x5A));
+        assert_eq!(info.as_bytes(), &region);
+
+        assert_eq!(
+            DevInfo::try_from(&region[..21]),
+            Err(DidDecodeError::TooShort { needed: 0x80 })
+        );
+    }
+
+    #[test]
+    fn logs_info() {
+        // Real 0x8021 answer, log upload not advertised
+        let cap = LogUploadCapability::try_from(&[0, 0, 0, 0, 2, 0, 0, 0, 0][..]).unwrap();
+        let info = LogsInfo::from_capability(&cap);
+        assert_eq!(info, LogsInfo::FALLBACK);
+        assert_eq!(info.entry_count(), 0x00FF_F000 / 12);
+        assert_eq!(info.entry_address(2), 0xC300_1018);
+
+        let cap = LogUploadCapability {
+            supported: true,
+            address: 0xC400_0000,
+            size: 120,
+        };
+        let info = LogsInfo::from_capability(&cap);
+        assert!(info.from_device);
+        assert_eq!((info.entry_count(), info.entries_len(3)), (10, 36));
+    }
+
+    #[test]
+    fn calibration_compare() {
+        let mut compare = CalibrationCompare::new(CellArray::new([1000, 1000, 1000]));
+        // The old values, however often, are not settled values
+        for _ in 0..CalibrationCompare::<3>::SETTLE_POLLS + 1 {
+            assert!(!compare.poll(CellArray::new([1000, 1000, 1000])));
+        }
+        assert!(!compare.has_changed());
+        assert!(!compare.report(None).changed);
+        assert!(!compare.poll(CellArray::new([1040, 980, 1300])));
+        assert!(!compare.poll(CellArray::new([1040, 980, 1300])));
+        assert!(!compare.report(Some(50)).settled);
+        assert!(compare.poll(CellArray::new([1040, 980, 1300])));
+
+        let delta = compare.report(Some(50));
+        assert_eq!(
+            (delta.delta(0), delta.delta(1), delta.delta(2)),
+            (40, -20, 300)
+        );
+        assert!(delta.cell_passed(0) && delta.cell_passed(1) && !delta.cell_passed(2));
+        assert!(!delta.passed());
+        assert!(compare.report(Some(300)).passed());
+        assert!(compare.report(None).passed());
+
+        // Going back to the old values after a change is a change
+        let mut compare = CalibrationCompare::new(CellArray::new([5, 5, 5]));
+        compare.poll(CellArray::new([6, 5, 5]));
+        for _ in 0..CalibrationCompare::<3>::SETTLE_POLLS {
+            compare.poll(CellArray::new([5, 5, 5]));
+        }
+        assert!(compare.is_settled());
+
+        // Nothing polled compares the values with themselves
+        let idle = CalibrationCompare::new(CellArray::new([7, 8, 9])).report(Some(0));
+        assert!(!idle.settled && !idle.changed && idle.delta(2) == 0);
+    }
+
+    #[cfg(feature = "uds")]
+    #[test]
+    fn logs_info_query_falls_back() {
+        use crate::uds::client::{UdsClientError, UdsTransport};
+
+        /// Answers every request with `0`, failing when it is `None`
+        struct Fixed(Option<&'static [u8]>);
+        impl UdsTransport for Fixed {
+            type Error = ();
+            fn request(&mut self, _req: &[u8], resp: &mut [u8]) -> Result<usize, ()> {
+                let data = self.0.ok_or(())?;
+                resp[..data.len()].copy_from_slice(data);
+                Ok(data.len())
+            }
+
+            fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+                Ok(())
+            }
+        }
+
+        // requestOutOfRange
+        let mut nrc = Fixed(Some(&[0x04, 0x7F, 0x22, 0x31]));
+        assert_eq!(LogsInfo::query(&mut nrc), Ok(LogsInfo::FALLBACK));
+        let mut reported = Fixed(Some(&[
+            0x04, 0x62, 0x80, 0x21, 1, 0xC4, 0, 0, 0, 0, 0, 0, 120,
+        ]));
+        assert_eq!(LogsInfo::query(&mut reported).unwrap().entry_count(), 10);
+        assert_eq!(
+            LogsInfo::query(&mut Fixed(None)),
+            Err(UdsClientError::Transport(()))
+        );
+    }
+
+    #[test]
+    fn log_chunks() {
+        let ranges = |mut chunker: LogChunker| {
+            core::iter::from_fn(move || chunker.next_chunk()).collect::<std::vec::Vec<_>>()
+        };
+        let fixed = LogChunking {
+            size: ChunkSize::Fixed(100),
+            max_entries: 1000,
+        };
+        assert_eq!(ranges(fixed.chunks(10, 250)), [10..110, 110..210, 210..260]);
+        assert_eq!(ranges(fixed.chunks(10, 0)), []);
+        // Memory wins over the asked size
+        let capped = LogChunking {
+            max_entries: 40,
+            ..fixed
+        };
+        assert_eq!(capped.chunks(0, 100).entries(), 40);
+
+        let auto = LogChunking {
+            size: ChunkSize::Auto,
+            max_entries: 100_000,
+        };
+        let mut chunker = auto.chunks(0, 10_000);
+        assert_eq!(chunker.next_chunk(), Some(0..16));
+        // 16 entries in 20 ms would be 1600 per 2 s, grows 4x at most
+        chunker.record(16, 20);
+        assert_eq!(chunker.next_chunk(), Some(16..80));
+        chunker.record(64, 10);
+        assert_eq!(chunker.entries(), 256);
+        chunker.record(256, 10);
+        assert_eq!(chunker.entries(), LogChunking::AUTO_MAX_ENTRIES);
+        // A slow link shrinks them, 100 entries took 4 s
+        chunker.record(100, 4000);
+        assert_eq!(chunker.entries(), 50);
+        chunker.record(50, 60_000);
+        assert_eq!(chunker.entries(), 1);
+
+        let mut fixed = fixed.chunks(0, 1000);
+        fixed.record(100, 1);
+        assert_eq!(fixed.entries(), 100);
+    }
+
+    /// Byte-wise XOR with a fixed key, enough to follow the key material layout
+    struct XorCipher<const N: usize>;
+
+    impl<const N: usize> BlockCipher<N> for XorCipher<N> {
+        fn encrypt_block(&self, block: &mut [u8; N]) {
+            for (i, b) in block.iter_mut().enumerate() {
+                *b ^= i as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn log_decryptor_key_material_per_block_size() {
+        let device_id: [u8; 12] = core::array::from_fn(|i| 0x10 + i as u8);
+        let d8 = LogDecryptor::new(&XorCipher::<8>, &device_id, 0x0002c1c9);
+        let d16 = LogDecryptor::new(&XorCipher::<16>, &device_id, 0x0002c1c9);
+
+        assert_eq!(
+            d8.key_material[..12],
+            [
+                0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x18, 0x18, 0x18
+            ]
+        );
+        assert_eq!(d8.key_material[16], 0xda);
+        assert_eq!(d16.key_material[8..12], [0x10, 0x10, 0x10, 0x10]);
+        assert_eq!(d16.key_material[16], 0xda);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn des_encryptor_still_builds_a_decryptor() {
+        struct OldXor;
+        impl DesEncryptor for OldXor {
+            fn encrypt_block(&self, block: &mut [u8; 8]) {
+                BlockCipher::<8>::encrypt_block(&XorCipher::<8>, block)
+            }
+        }
+
+        let device_id: [u8; 12] = core::array::from_fn(|i| 0x10 + i as u8);
+        let old = LogDecryptor::new(&OldXor, &device_id, 0x0002c1c9);
+        let new = LogDecryptor::new(&XorCipher::<8>, &device_id, 0x0002c1c9);
+        assert_eq!(old.key_material, new.key_material);
+    }
+
+    #[test]
+    fn log_entry_to_frame() {
+        let entry = LogEntry {
+            kind: 0xC9,
+            payload: [0x0C, 1, 2, 3, 4, 5, 6, 7],
+        };
+        let (id, frame) = entry.to_frame(&LogProfile::SOLO);
+        assert_eq!(id.to_u32(), 0x0DC9_0004);
+        assert_eq!(frame.bytes(), &[0x0C]);
+
+        let unknown = LogEntry {
+            kind: 0xEE,
+            payload: [0; 8],
+        };
+        let profile = LogProfile {
+            default_dlc: 4,
+            ..LogProfile::SOLO
+        };
+        assert_eq!(unknown.to_frame(&profile).1.dlc(), 4);
+    }
+
+    fn log_slot(payload: [u8; 8], next_kind: u8) -> [u8; 12] {
+        let mut slot = [0u8; 12];
+        slot[..8].copy_from_slice(&payload);
+        slot[10] = next_kind;
+        slot
+    }
+
+    fn diving(status: u8, dive_number: u16, timestamp: u32) -> [u8; 8] {
+        let mut p = [0u8; 8];
+        p[0] = status;
+        p[1..3].copy_from_slice(&dive_number.to_be_bytes());
+        p[3..7].copy_from_slice(&timestamp.to_be_bytes());
+        p
+    }
+
+    #[test]
+    fn dive_segments() {
+        let setpoint = [0x0C, 0, 0, 0, 0, 0, 0, 1];
+        let slots = [
+            log_slot(setpoint, 0xC9),
+            log_slot(setpoint, 0xCC),
+            log_slot(diving(1, 7, 1000), 0xC9),
+            log_slot(setpoint, 0xCC),
+            log_slot(diving(0, 7, 2000), 0xC9),
+            log_slot(setpoint, 0xCC),
+            log_slot(diving(1, 8, 3000), 0xC9),
+            log_slot(setpoint, 0xCC),
+            log_slot(diving(1, 9, 4000), 0xC9),
+            log_slot(setpoint, 0x00),
+        ];
+        let data = slots.concat();
+
+        let dives: [DiveSegment; 3] = {
+            let mut it = DiveSegments::new(&data);
+            core::array::from_fn(|_| it.next().unwrap())
+        };
+        assert_eq!((dives[0].dive_number, dives[0].timestamp), (7, 1000));
+        assert_eq!(dives[0].range, 12..60);
+        assert_eq!((dives[1].dive_number, dives[1].range.clone()), (8, 60..96));
+        assert_eq!((dives[2].dive_number, dives[2].range.clone()), (9, 84..120));
+        assert!(DiveSegments::new(&data).nth(3).is_none());
+
+        // Each slice decodes on its own, starting with the Diving entry
+        let mut entries = LogEntryIterator::new(&data[dives[0].range.clone()]);
+        let first = entries.nth(1).unwrap();
+        assert_eq!(first.kind, 0xCC);
+        assert_eq!(first.payload, diving(1, 7, 1000));
+        assert_eq!(first.timestamp(), Some(1000));
+        assert_eq!(entries.next().unwrap().timestamp(), None);
+
+        // Streamed in pieces that don't line up with slots, the splitter
+        // writes the same bytes
+        let mut stream = LogStream::new();
+        let mut splitter = DiveSplitter::new();
+        let mut split: Vec<(u16, Vec<u8>)> = Vec::new();
+        let mut emit = |event: DiveEvent<'_>| {
+            match event {
+                DiveEvent::Start { dive_number, .. } => split.push((dive_number, Vec::new())),
+                DiveEvent::Slot(bytes) => split.last_mut().unwrap().1.extend_from_slice(bytes),
+                DiveEvent::End => {}
+            }
+            Ok::<_, ()>(())
+        };
+        for piece in data.chunks(5) {
+            stream
+                .push(piece, |slot| splitter.on_slot(slot, &mut emit))
+                .unwrap();
+        }
+        splitter.finish(&mut emit).unwrap();
+        assert_eq!(stream.pending(), 0);
+        let expected: Vec<_> = dives
+            .iter()
+            .map(|d| (d.dive_number, data[d.range.clone()].to_vec()))
+            .collect();
+        assert_eq!(split, expected);
+    }
+
+    #[test]
+    fn log_stream_matches_iterator() {
+        let setpoint = [0x0C, 0, 0, 0, 0, 0, 0, 1];
+        let mut data = [
+            log_slot(setpoint, 0xC9),
+            [0xFF; 12],
+            log_slot(diving(1, 7, 1000), 0x0C),
+            log_slot(setpoint, 0x00),
+        ]
+        .concat();
+        data.extend_from_slice(&[0x0C; 5]);
+
+        let mut stream = LogStream::new();
+        let mut streamed = Vec::new();
+        for piece in data.chunks(7) {
+            stream
+                .push(piece, |slot| {
+                    streamed.extend(slot.entry.map(|e| (slot.offset, e.kind, e.payload)));
+                    Ok::<_, ()>(())
+                })
+                .unwrap();
+        }
+        assert_eq!(stream.pending(), 5);
+
+        let mut entries = LogEntryIterator::new(&data);
+        let mut iterated = Vec::new();
+        while let Some(e) = entries.next() {
+            iterated.push((entries.offset() - 12, e.kind, e.payload));
+        }
+        assert_eq!(streamed, iterated);
+        assert_eq!(streamed.len(), 3);
+    }
+
+    #[test]
+    fn log_writer_roundtrip() {
+        use crate::units::PpO2Deci;
+
+        // 32-byte pages and 64-byte blocks so slots straddle both
+        let storage = RamStorage::<192>::new(64, 32);
+        let mut writer = LogWriter::open(storage).unwrap();
+        let msgs = [
+            Msg::Setpoint(PpO2Deci::new(13)),
+            Msg::alert_from(crate::alerts::SoloAlert::SoloSetpointTimeout),
+            Msg::Serial(*b"A005D007"),
+        ];
+        for msg in &msgs[..2] {
+            writer.append(msg).unwrap();
+        }
+        assert_eq!(writer.slots_used(), 3);
+
+        // Reopening finds the end and carries on
+        let mut writer = LogWriter::open(writer.into_inner()).unwrap();
+        writer.append(&msgs[2]).unwrap();
+        let data = writer.storage().as_bytes();
+        let mut lead_in = [0xFF; 12];
+        lead_in[10] = msgs[0].kind();
+        assert_eq!(data[..12], lead_in);
+
+        let decoded: Vec<Msg> = LogEntryIterator::new(data)
+            .map(|e| Msg::try_from_frame(&e.to_frame(&LogProfile::SOLO).1).unwrap())
+            .collect();
+        assert_eq!(decoded[..], msgs[..]);
+
+        for msg in &msgs[..2] {
+            writer.append(msg).unwrap();
+        }
+        // 16 slots fit, 6 are used by the lead-in and five entries
+        let mut writer = LogWriter::open(writer.into_inner()).unwrap();
+        for _ in 0..10 {
+            writer.append(&msgs[0]).unwrap();
+        }
+        assert_eq!(writer.append(&msgs[0]), Err(LogWriteError::Full));
+        assert_eq!(
+            LogEntryIterator::new(writer.storage().as_bytes()).count(),
+            15
+        );
+    }
+
+    #[test]
+    fn lead_in_only_at_start() {
+        // Lead-in, an entry with an all-0xFF payload, an ordinary entry
+        let mut data = [0xFF; 36];
+        data[10] = 0x20;
+        data[22] = 0x21;
+        data[24..32].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        data[34] = 0x22;
+
+        let entries: Vec<(u8, [u8; 8])> = LogEntryIterator::new(&data)
+            .map(|e| (e.kind, e.payload))
+            .collect();
+        let expected = [(0x20, [0xFF; 8]), (0x21, [1, 2, 3, 4, 5, 6, 7, 8])];
+        assert_eq!(entries, expected);
+
+        let mut streamed = Vec::new();
+        let mut stream = LogStream::new();
+        for piece in data.chunks(5) {
+            stream
+                .push(piece, |slot| {
+                    streamed.extend(slot.entry.map(|e| (e.kind, e.payload)));
+                    Ok::<_, ()>(())
+                })
+                .unwrap();
+        }
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn log_writer_opens_wrapped_log() {
+        // Newest entries in the first block, then an erased block ahead of
+        // the oldest ones
+        let mut storage = RamStorage::<144>::new(48, 48);
+        storage.program(0, &[0x11; 48]).unwrap();
+        storage.program(96, &[0x22; 48]).unwrap();
+        let writer = LogWriter::open(storage).unwrap();
+        assert_eq!(writer.slots_used(), 4);
+    }
+
+    #[test]
+    fn plausible_log_needs_right_key() {
+        let mut writer = LogWriter::open(RamStorage::<96>::new(96, 32)).unwrap();
+        writer.append(&Msg::Serial(*b"A005D007")).unwrap();
+        writer.append(&Msg::Nop).unwrap();
+        let log = writer.storage().as_bytes();
+        assert!(plausible_log(log));
+
+        // Nothing is assumed about the bytes around the kind
+        let mut other_filler = log.to_vec();
+        for slot in other_filler.chunks_exact_mut(LOG_ENTRY_SIZE as usize) {
+            if !is_blank_slot(slot) {
+                slot[8..10].copy_from_slice(&[0x12, 0x34]);
+                slot[11] = 0x56;
+            }
+        }
+        assert!(plausible_log(&other_filler));
+
+        // The keystream is XORed, so decrypting also encrypts
+        let device_id = [0x42; 12];
+        let mut encrypted = log.to_vec();
+        LogDecryptor::new(&XorCipher::<8>, &device_id, 0x0002c1c9).decrypt(&mut encrypted);
+        assert!(!plausible_log(&encrypted));
+
+        let mut right = encrypted.clone();
+        LogDecryptor::new(&XorCipher::<8>, &device_id, 0x0002c1c9).decrypt(&mut right);
+        assert_eq!(right, log);
+        // Only the key material differs with another key
+        let mut wrong = encrypted;
+        LogDecryptor::new(&XorCipher::<8>, &[0x43; 12], 0x0002c1c9).decrypt(&mut wrong);
+        assert!(!plausible_log(&wrong));
+    }
+
+    #[test]
+    fn stored_alerts() {
+        let setpoint = [0x0C, 0, 0, 0, 0, 0, 0, 1];
+        let alert = |code: u16| {
+            let [hi, lo] = code.to_be_bytes();
+            [1, hi, lo, 0, 0, 0, 0, 0]
+        };
+        let slots = [
+            log_slot(setpoint, 0x02),
+            log_slot(alert(0x101), 0xC9),
+            log_slot(setpoint, 0x02),
+            log_slot(alert(0x101), 0xCC),
+            log_slot(diving(1, 7, 1000), 0x02),
+            log_slot(alert(0x101), 0x02),
+            log_slot(alert(0x201), 0x02),
+            log_slot(alert(0x101), 0x00),
+        ];
+        let data = slots.concat();
+
+        let found: Vec<_> = StoredAlerts::new(&data)
+            .map(|a| (a.alert.code, a.repeats, a.last_diving))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0x101, 2, None),
+                (0x101, 1, Some((7, 1000))),
+                (0x201, 1, Some((7, 1000))),
+                (0x101, 1, Some((7, 1000))),
+            ]
+        );
+        assert_eq!(StoredAlerts::new(&slots[..1].concat()).next(), None);
+    }
+
+    #[test]
+    fn log_check() {
+        use crate::units::PpO2Deci;
+
+        let mut writer = LogWriter::open(RamStorage::<96>::new(96, 32)).unwrap();
+        writer.append(&Msg::Setpoint(PpO2Deci::new(13))).unwrap();
+        writer.append(&Msg::Serial(*b"A005D007")).unwrap();
+        let log = writer.storage().as_bytes();
+
+        let mut check = LogCheck::new();
+        for piece in log.chunks(5) {
+            check.push(piece);
+        }
+        let report = check.finish();
+        assert_eq!(
+            (report.slots, report.blank, report.entries, report.decodable),
+            (8, 6, 2, 2)
+        );
+        let mut problems = Vec::new();
+        report.problems(|p| problems.push(p));
+        assert_eq!(problems, []);
+
+        // An entry missing its kind and a torn slot
+        let mut bad = log.to_vec();
+        bad[12 + 10] = 0xFF;
+        bad.truncate(bad.len() - 2);
+        let mut check = LogCheck::new();
+        check.push(&bad);
+        let mut problems = Vec::new();
+        check.finish().problems(|p| problems.push(p));
+        assert_eq!(
+            problems,
+            [
+                LogProblem::Misaligned { trailing: 10 },
+                LogProblem::MissingKinds { entries: 1 },
+                // The Serial entry lost its kind with it
+                LogProblem::Undecodable {
+                    decodable: 1,
+                    entries: 2
+                },
+            ]
+        );
+
+        let mut problems = Vec::new();
+        LogCheck::new().finish().problems(|p| problems.push(p));
+        assert_eq!(problems, [LogProblem::Empty]);
+    }
 }
@@ -0,0 +1,82 @@
+//! Encoding choices that depend on the Solo firmware version, decided in one
+//! place from `FirmwareVersionAscii` rather than at each call site.
+
+use crate::diag::did::FirmwareVersionAscii;
+use crate::diag::firmware::FirmwareVersion;
+
+/// What the connected firmware speaks. Unknown or unparseable versions get
+/// the encodings every firmware seen so far accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProtocolVersion {
+    firmware: Option<FirmwareVersion>,
+}
+
+impl ProtocolVersion {
+    pub const UNKNOWN: Self = Self { firmware: None };
+
+    pub fn new(firmware: Option<FirmwareVersion>) -> Self {
+        Self { firmware }
+    }
+
+    pub fn from_did(version: &FirmwareVersionAscii) -> Self {
+        Self::new(version.version())
+    }
+
+    /// Reads `FirmwareVersionAscii`, falling back to [`Self::UNKNOWN`] when
+    /// the device rejects or garbles it. Transport errors are returned.
+    #[cfg(feature = "uds")]
+    pub fn query<T: crate::uds::client::UdsTransport>(
+        transport: &mut T,
+    ) -> Result<Self, crate::uds::client::UdsClientError<T::Error>> {
+        use crate::diag::did::DataIdentifier;
+        use crate::uds::client::{RDBI_HEADER_LEN, UdsClientError, rdbi_into};
+
+        let mut buf = [0u8; 3 + RDBI_HEADER_LEN];
+        match rdbi_into(transport, FirmwareVersionAscii::DID, &mut buf) {
+            Ok(len) => Ok(FirmwareVersionAscii::try_from(&buf[..len])
+                .map_or(Self::UNKNOWN, |v| Self::from_did(&v))),
+            Err(UdsClientError::Transport(e)) => Err(UdsClientError::Transport(e)),
+            Err(_) => Ok(Self::UNKNOWN),
+        }
+    }
+
+    pub fn firmware(&self) -> Option<FirmwareVersion> {
+        self.firmware
+    }
+
+    /// True when the firmware is at least `since`. An unknown version is
+    /// assumed to be current, so nothing gets hidden from it.
+    pub(crate) fn at_least(&self, since: &str) -> bool {
+        match (self.firmware, FirmwareVersion::parse(since.as_bytes())) {
+            (Some(firmware), Some(since)) => firmware >= since,
+            _ => true,
+        }
+    }
+
+    /// Whether the firmware should answer `did`, per the `since` column of
+    /// the DID table. DIDs missing from the table are assumed to exist.
+    pub fn has_did(&self, did: u16) -> bool {
+        crate::protocol::did_def(did)
+            .and_then(|d| d.since)
+            .is_none_or(|since| self.at_least(since))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::did;
+
+    #[test]
+    fn gates() {
+        let v12 = ProtocolVersion::from_did(&FirmwareVersionAscii {
+            firmware_version_ascii: *b"v12",
+        });
+        assert_eq!(v12.firmware().unwrap().components(), [12]);
+        assert!(v12.at_least("11") && v12.at_least("12") && !v12.at_least("12.1"));
+        assert!(ProtocolVersion::UNKNOWN.at_least("99"));
+        assert!(v12.has_did(did::FIRMWARE_CRC));
+        assert!(v12.has_did(0xF180));
+    }
+}
@@ -0,0 +1,165 @@
+//! Guesses at fields the protocol doesn't document yet, kept here so they
+//! can be checked against captures instead of living in notes. Nothing in
+//! this module is confirmed: every field is named `speculative_*` and
+//! [`Msg`] keeps the raw bytes either way.
+//!
+//! Enabled by the `experimental-decodes` feature.
+
+use super::Msg;
+use crate::units::{Decivolt, Percent};
+
+/// `OboeStatus` byte 2 (`unknown1`) read as the charger state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChargerStateGuess {
+    NotCharging,
+    Charging,
+    Charged,
+    /// A value the guess has no meaning for, evidence against it
+    Other(u8),
+}
+
+impl ChargerStateGuess {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            0 => Self::NotCharging,
+            1 => Self::Charging,
+            2 => Self::Charged,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// Candidate reading of the unknown `OboeStatus` bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OboeStatusSpeculative {
+    pub battery_voltage: Decivolt,
+    /// `unknown1`
+    pub speculative_charger_state: ChargerStateGuess,
+    /// `unknown3` as whole percent, `None` above 100. The fixture corpus has
+    /// no capture with it non-zero yet.
+    pub speculative_battery_percent: Option<Percent>,
+}
+
+/// `None` for anything but `OboeStatus`
+pub fn oboe_status(msg: &Msg) -> Option<OboeStatusSpeculative> {
+    let Msg::OboeStatus {
+        battery_voltage,
+        unknown1,
+        unknown3,
+        ..
+    } = *msg
+    else {
+        return None;
+    };
+    Some(OboeStatusSpeculative {
+        battery_voltage,
+        speculative_charger_state: ChargerStateGuess::from_u8(unknown1),
+        speculative_battery_percent: (unknown3 <= 100).then(|| Percent::new(unknown3)),
+    })
+}
+
+/// Most the battery percent may move between two consecutive statuses
+/// before it counts against the guess
+pub const MAX_PERCENT_STEP: u8 = 5;
+
+/// Tallies how a run of `OboeStatus` messages from one node agrees with the
+/// guesses in [`OboeStatusSpeculative`]. A percent that rises while not
+/// charging, jumps, or goes out of range, and an unknown charger state,
+/// each count as a contradiction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OboeCrossCheck {
+    pub samples: u32,
+    pub contradictions: u32,
+    last: Option<OboeStatusSpeculative>,
+}
+
+impl OboeCrossCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignores anything but `OboeStatus`
+    pub fn observe(&mut self, msg: &Msg) {
+        let Some(status) = oboe_status(msg) else {
+            return;
+        };
+        self.samples += 1;
+        if matches!(
+            status.speculative_charger_state,
+            ChargerStateGuess::Other(_)
+        ) {
+            self.contradictions += 1;
+        }
+        match (
+            self.last.and_then(|l| l.speculative_battery_percent),
+            status.speculative_battery_percent,
+        ) {
+            (_, None) => self.contradictions += 1,
+            (Some(before), Some(now)) => {
+                let (before, now) = (before.raw(), now.raw());
+                let rose = now > before
+                    && status.speculative_charger_state == ChargerStateGuess::NotCharging;
+                if rose || before.abs_diff(now) > MAX_PERCENT_STEP {
+                    self.contradictions += 1;
+                }
+            }
+            (None, Some(_)) => {}
+        }
+        self.last = Some(status);
+    }
+
+    /// Whether every sample so far fits the guesses
+    pub fn holds(&self) -> bool {
+        self.samples > 0 && self.contradictions == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::divecan::DiveCanFrame;
+
+    /// Hand-written `OboeStatus`, not captured: the fixture corpus has no
+    /// real one with the unknown bytes set. Replace these with captured
+    /// frames once there are some.
+    fn synthetic(data: [u8; 5]) -> Msg {
+        let mut bytes = [0u8; 8];
+        bytes[..5].copy_from_slice(&data);
+        Msg::try_from_frame(&DiveCanFrame::new(0x07, 5, bytes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn oboe_status_guesses() {
+        // Same bytes as the golden vector, idle controller
+        let status = oboe_status(&synthetic([0x01, 0x00, 0x00, 0x00, 0x00])).unwrap();
+        assert_eq!(
+            status.speculative_charger_state,
+            ChargerStateGuess::NotCharging
+        );
+        assert_eq!(status.speculative_battery_percent, Some(Percent::new(0)));
+
+        // A slow discharge the guesses should accept
+        let mut check = OboeCrossCheck::new();
+        for (volts, percent) in [(0x26, 0x30), (0x26, 0x30), (0x25, 0x2F)] {
+            check.observe(&synthetic([0x01, volts, 0x00, 0x00, percent]));
+        }
+        check.observe(&Msg::Nop);
+        assert_eq!(check.samples, 3);
+        assert!(check.holds());
+
+        // Rising percent without charging, then a value no guess explains
+        check.observe(&synthetic([0x01, 0x25, 0x00, 0x00, 0x31]));
+        check.observe(&synthetic([0x01, 0x25, 0x07, 0x00, 0xC8]));
+        assert_eq!(check.contradictions, 3);
+        assert!(!check.holds());
+
+        // Charging may raise it
+        let mut check = OboeCrossCheck::new();
+        check.observe(&synthetic([0x01, 0x25, 0x01, 0x00, 0x30]));
+        check.observe(&synthetic([0x01, 0x26, 0x01, 0x00, 0x32]));
+        assert!(check.holds());
+        assert_eq!(oboe_status(&Msg::Nop), None);
+    }
+}
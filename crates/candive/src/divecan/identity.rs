@@ -0,0 +1,137 @@
+//! The burst of messages a node announces itself with, at power-up and in
+//! answer to a handset enumerating the bus with `BusInit`.
+
+use super::{BROADCAST_ADDR, DiveCanFrame, DiveCanId, Msg, SoloStatusFields};
+use crate::units::Decivolt;
+
+/// Kind of node announcing, decides the status message ending the burst
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceType {
+    /// Ends with `SoloStatus`
+    Solo(SoloStatusFields),
+    /// Battery powered controller, ends with `OboeStatus`
+    Controller {
+        battery_ok: bool,
+        battery_voltage: Decivolt,
+    },
+    /// Handsets send no status
+    Handset,
+}
+
+/// `Id`, `DeviceName`, `Serial` (if set) and the status for the device
+/// type, in the order a node sends them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IdentityBurst {
+    device: DeviceType,
+    manufacturer: u8,
+    version: u8,
+    name: [u8; 8],
+    serial: Option<[u8; 8]>,
+}
+
+impl IdentityBurst {
+    /// Most messages in a burst
+    pub const MAX_MSGS: usize = 4;
+
+    pub fn new(device: DeviceType, manufacturer: u8, version: u8, name: [u8; 8]) -> Self {
+        Self {
+            device,
+            manufacturer,
+            version,
+            name,
+            serial: None,
+        }
+    }
+
+    pub fn with_serial(mut self, serial: [u8; 8]) -> Self {
+        self.serial = Some(serial);
+        self
+    }
+
+    pub fn msgs(&self) -> impl Iterator<Item = Msg> {
+        let status = match self.device {
+            DeviceType::Solo(s) => Some(Msg::SoloStatus {
+                voltage: s.voltage,
+                current: s.current,
+                injection_duration: s.injection_duration,
+                setpoint: s.setpoint,
+                consensus: s.consensus,
+                voltage_alert: s.voltage_alert,
+                current_alert: s.current_alert,
+            }),
+            DeviceType::Controller {
+                battery_ok,
+                battery_voltage,
+            } => Some(Msg::OboeStatus {
+                battery_ok,
+                battery_voltage,
+                unknown1: 0,
+                unknown2: 0,
+                // What controllers have been seen sending
+                unknown3: 0x30,
+            }),
+            DeviceType::Handset => None,
+        };
+        let msgs: [Option<Msg>; Self::MAX_MSGS] = [
+            Some(Msg::Id {
+                manufacturer: self.manufacturer,
+                unused: 0,
+                version: self.version,
+            }),
+            Some(Msg::DeviceName(self.name)),
+            self.serial.map(Msg::Serial),
+            status,
+        ];
+        msgs.into_iter().flatten()
+    }
+
+    /// The burst as broadcast frames from `src`
+    pub fn frames(&self, src: u8) -> impl Iterator<Item = (DiveCanId, DiveCanFrame)> {
+        self.msgs().map(move |msg| {
+            (
+                DiveCanId::new(src, BROADCAST_ADDR, msg.kind()),
+                msg.to_frame(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::divecan::{Consensus, SOLO_ADDR};
+    use crate::protocol::kind;
+
+    #[test]
+    fn burst_order() {
+        let status = SoloStatusFields {
+            voltage: 72.into(),
+            current: 5.into(),
+            injection_duration: 0.into(),
+            setpoint: 7.into(),
+            consensus: Consensus::PpO2(98.into()),
+            voltage_alert: None,
+            current_alert: None,
+        };
+        let solo = IdentityBurst::new(DeviceType::Solo(status), 0x01, 0x42, *b"SOLO    ")
+            .with_serial(*b"00000042");
+        let frames: Vec<_> = solo.frames(SOLO_ADDR).collect();
+        let kinds: Vec<_> = frames.iter().map(|(id, _)| id.kind).collect();
+        assert_eq!(
+            kinds,
+            [kind::ID, kind::DEVICE_NAME, kind::SERIAL, kind::SOLO_STATUS]
+        );
+        assert!(
+            frames
+                .iter()
+                .all(|(id, _)| (id.src, id.dst) == (SOLO_ADDR, BROADCAST_ADDR))
+        );
+        assert_eq!(frames[0].1.bytes(), [0x01, 0x00, 0x42]);
+
+        let handset = IdentityBurst::new(DeviceType::Handset, 0x01, 0x01, *b"HANDSET ");
+        let kinds: Vec<_> = handset.msgs().map(|m| m.kind()).collect();
+        assert_eq!(kinds, [kind::ID, kind::DEVICE_NAME]);
+    }
+}
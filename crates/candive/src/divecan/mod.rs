@@ -0,0 +1,1698 @@
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
+
+#[cfg(feature = "experimental-decodes")]
+pub mod experimental;
+pub mod identity;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DiveCanId {
+    pub src: u8,
+    pub dst: u8,
+    pub kind: u8,
+}
+
+impl DiveCanId {
+    const DIVECAN_PREFIX: u32 = 0x0D00_0000;
+    const PREFIX_MASK: u32 = 0x1F00_0000;
+
+    pub fn new(src: u8, dst: u8, kind: u8) -> Self {
+        Self { src, dst, kind }
+    }
+
+    /// Splits an extended id without looking at its prefix, see
+    /// [`Self::try_from_u32`] for ids that may not be DiveCAN.
+    pub fn from_u32(id: u32) -> Self {
+        Self::new(
+            (id & 0xFF) as u8,
+            ((id >> 8) & 0xFF) as u8,
+            ((id >> 16) & 0xFF) as u8,
+        )
+    }
+
+    /// `None` unless the 29-bit id carries the DiveCAN 0x0D prefix.
+    pub fn try_from_u32(id: u32) -> Option<Self> {
+        (id & Self::PREFIX_MASK == Self::DIVECAN_PREFIX).then(|| Self::from_u32(id))
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        Self::DIVECAN_PREFIX
+            | ((self.kind as u32) << 16)
+            | ((self.dst as u32) << 8)
+            | (self.src as u32)
+    }
+
+    pub fn reply(&self, kind: u8) -> Self {
+        Self::new(self.dst, self.src, kind)
+    }
+}
+
+impl From<u32> for DiveCanId {
+    fn from(id: u32) -> Self {
+        Self::from_u32(id)
+    }
+}
+
+impl From<DiveCanId> for u32 {
+    fn from(id: DiveCanId) -> u32 {
+        id.to_u32()
+    }
+}
+
+/// An extended CAN id seen on a bus that may carry more than DiveCAN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusTraffic {
+    DiveCan(DiveCanId),
+    /// Any other extended id, left raw
+    Other(u32),
+}
+
+impl BusTraffic {
+    pub fn classify(id: u32) -> Self {
+        match DiveCanId::try_from_u32(id) {
+            Some(id) => BusTraffic::DiveCan(id),
+            None => BusTraffic::Other(id),
+        }
+    }
+}
+
+pub struct DiveCanFrame {
+    kind: u8,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    InvalidDlc(u8),
+}
+
+impl DiveCanFrame {
+    pub fn new(kind: u8, dlc: u8, data: [u8; 8]) -> Result<Self, FrameError> {
+        if dlc > 8 {
+            return Err(FrameError::InvalidDlc(dlc));
+        }
+        Ok(Self { kind, dlc, data })
+    }
+
+    pub fn dlc(&self) -> u8 {
+        self.dlc
+    }
+
+    pub fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.dlc as usize]
+    }
+
+    /// The frame as it goes on the wire under `policy`. Padding keeps the
+    /// payload and zero-fills the rest.
+    pub fn with_tx_dlc(&self, policy: TxDlcPolicy) -> Self {
+        let mut data = self.data;
+        let dlc = match policy {
+            TxDlcPolicy::Minimal => self.dlc,
+            TxDlcPolicy::PadTo8 => {
+                data[self.dlc as usize..].fill(0);
+                8
+            }
+        };
+        Self {
+            kind: self.kind,
+            dlc,
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CellsActive<const N: usize = DIVECAN_CELLS>(u8);
+
+impl<const N: usize> CellsActive<N> {
+    pub fn new(cells: [bool; N]) -> Self {
+        Self(CellArray(cells).to_mask())
+    }
+
+    pub fn as_array(&self) -> [bool; N] {
+        CellArray::from_mask(self.0).into_inner()
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        self.0
+    }
+
+    pub fn from_u8(v: u8) -> Self {
+        // Only use the bits of cells that exist
+        Self(CellArray::<bool, N>::from_mask(v).to_mask())
+    }
+
+    /// Panics if `index` is `N` or more, like indexing an array.
+    pub fn set(&mut self, index: usize, active: bool) {
+        assert!(index < N, "cell {} out of range for {} cells", index, N);
+        if active {
+            self.0 |= 1 << index;
+        } else {
+            self.0 &= !(1 << index);
+        }
+    }
+
+    /// Whether each cell is active, cell 0 first
+    pub fn iter(&self) -> impl Iterator<Item = bool> {
+        let mask = self.0;
+        (0..N).map(move |i| mask & (1 << i) != 0)
+    }
+
+    pub fn count_active(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+}
+
+impl<const N: usize> core::ops::Index<usize> for CellsActive<N> {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &bool {
+        assert!(index < N, "cell {} out of range for {} cells", index, N);
+        if self.0 & (1 << index) != 0 {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+/// Cell `i` from the `i`th item, items past `N` are ignored and missing
+/// ones are inactive.
+impl<const N: usize> FromIterator<bool> for CellsActive<N> {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut cells = Self::default();
+        for (i, active) in iter.into_iter().take(N).enumerate() {
+            cells.set(i, active);
+        }
+        cells
+    }
+}
+
+/// Numbered from 1 as on the handset: "cells 1,3 active", "cell 2 active"
+/// or "no cells active".
+impl<const N: usize> core::fmt::Display for CellsActive<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.count_active() {
+            0 => return f.write_str("no cells active"),
+            1 => f.write_str("cell ")?,
+            _ => f.write_str("cells ")?,
+        }
+        let active = self.iter().enumerate().filter(|&(_, on)| on);
+        for (n, (i, _)) in active.enumerate() {
+            if n > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", i + 1)?;
+        }
+        f.write_str(" active")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Consensus {
+    NotCalibrated,
+    NoActiveCells,
+    PpO2(PpO2Deci),
+}
+
+impl Consensus {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            0xff => Self::NotCalibrated,
+            0xfe => Self::NoActiveCells,
+            other => Self::PpO2(other.into()),
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::NotCalibrated => 0xff,
+            Self::NoActiveCells => 0xfe,
+            Self::PpO2(v) => v.raw(),
+        }
+    }
+
+    /// Checks the consensus against the cells it was voted from: a ppO₂
+    /// needs at least one active cell and `NoActiveCells` needs none.
+    /// `NotCalibrated` goes with any mask.
+    pub fn check<const N: usize>(self, cells_active: CellsActive<N>) -> Result<(), ConsensusError> {
+        let active = cells_active.count_active() > 0;
+        match self {
+            Self::PpO2(v) if v.raw() >= 0xfe => Err(ConsensusError::ReservedPpO2(v.raw())),
+            Self::PpO2(_) if !active => Err(ConsensusError::PpO2WithoutActiveCells),
+            Self::NoActiveCells if active => Err(ConsensusError::NoActiveCellsWithActiveCells),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why a [`Consensus`] doesn't fit the [`CellsActive`] mask it goes with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConsensusError {
+    PpO2WithoutActiveCells,
+    NoActiveCellsWithActiveCells,
+    /// 0xFE and 0xFF mean `NoActiveCells` and `NotCalibrated` on the wire
+    ReservedPpO2(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalStatusCode {
+    Success,
+    Ack,
+    Rejected,
+    LowExtBatt,
+    SolenoidError,
+    Fo2RangeError,
+    PressureError,
+    Unknown(u8),
+}
+
+impl CalStatusCode {
+    pub fn from_byte(b: u8) -> Self {
+        match b {
+            0x01 => CalStatusCode::Success,
+            0x05 => CalStatusCode::Ack,
+            0x08 => CalStatusCode::Rejected,
+            0x10 => CalStatusCode::LowExtBatt,
+            0x18 => CalStatusCode::SolenoidError,
+            0x20 => CalStatusCode::Fo2RangeError,
+            0x28 => CalStatusCode::PressureError,
+            other => CalStatusCode::Unknown(other),
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            CalStatusCode::Success => 0x01,
+            CalStatusCode::Ack => 0x05,
+            CalStatusCode::Rejected => 0x08,
+            CalStatusCode::LowExtBatt => 0x10,
+            CalStatusCode::SolenoidError => 0x18,
+            CalStatusCode::Fo2RangeError => 0x20,
+            CalStatusCode::PressureError => 0x28,
+            CalStatusCode::Unknown(b) => b,
+        }
+    }
+
+    /// Anything but [`CalStatusCode::Ack`], no further responses follow.
+    pub fn is_final(self) -> bool {
+        self != CalStatusCode::Ack
+    }
+
+    /// Final and not [`CalStatusCode::Success`], unknown codes included.
+    pub fn is_error(self) -> bool {
+        self.is_final() && self != CalStatusCode::Success
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VoltageAlert {
+    UnderVoltage,
+    // Will only be sent when solenoid is active
+    Clear,
+    // Battery voltage too high when firing solenoid
+    OverVoltage,
+}
+
+impl VoltageAlert {
+    fn from_2bit_opt(v: u8) -> Option<Self> {
+        match v & 0b11 {
+            0b00 => None,
+            0b01 => Some(Self::UnderVoltage),
+            0b10 => Some(Self::Clear),
+            _ => Some(Self::OverVoltage),
+        }
+    }
+
+    fn to_2bit_opt(v: Option<Self>) -> u8 {
+        match v {
+            None => 0b00,
+            Some(Self::UnderVoltage) => 0b01,
+            Some(Self::Clear) => 0b10,
+            Some(Self::OverVoltage) => 0b11,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CurrentAlert {
+    // Drawing less than 70 while solenoid is active
+    UnderCurrent,
+    // Will only be set when solenoid is active
+    Clear,
+    // Drawing more than 10 after or 185 while firing
+    OverCurrent,
+}
+
+impl CurrentAlert {
+    fn from_2bit_opt(v: u8) -> Option<Self> {
+        match v & 0b11 {
+            0b00 => None,
+            0b01 => Some(Self::UnderCurrent),
+            0b10 => Some(Self::Clear),
+            _ => Some(Self::OverCurrent),
+        }
+    }
+
+    fn to_2bit_opt(v: Option<Self>) -> u8 {
+        match v {
+            None => 0b00,
+            Some(Self::UnderCurrent) => 0b01,
+            Some(Self::Clear) => 0b10,
+            Some(Self::OverCurrent) => 0b11,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ShutdownReason {
+    UserInitiated,
+    Timeout,
+    Unknown(u8),
+}
+
+impl ShutdownReason {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            0x00 => ShutdownReason::UserInitiated,
+            0x01 => ShutdownReason::Timeout,
+            other => ShutdownReason::Unknown(other),
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            ShutdownReason::UserInitiated => 0x00,
+            ShutdownReason::Timeout => 0x01,
+            ShutdownReason::Unknown(v) => v,
+        }
+    }
+}
+
+/// Status byte of a `Diving` broadcast
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DiveState {
+    Surface,
+    Diving,
+    /// Dive is being closed, sent before the Solo goes back to `Surface`
+    Ending,
+    Unknown(u8),
+}
+
+impl DiveState {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            0x00 => DiveState::Surface,
+            0x01 => DiveState::Diving,
+            0x02 => DiveState::Ending,
+            other => DiveState::Unknown(other),
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            DiveState::Surface => 0x00,
+            DiveState::Diving => 0x01,
+            DiveState::Ending => 0x02,
+            DiveState::Unknown(v) => v,
+        }
+    }
+
+    /// Anything but [`DiveState::Surface`], unknown values included
+    pub fn in_dive(self) -> bool {
+        self != DiveState::Surface
+    }
+}
+
+/// Dive boundary found by [`DiveTracker`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DiveTransition {
+    Started(u16),
+    Ended(u16),
+    /// Another dive number came in without surfacing in between
+    Switched {
+        ended: u16,
+        started: u16,
+    },
+}
+
+impl DiveTransition {
+    pub fn ended(self) -> Option<u16> {
+        match self {
+            DiveTransition::Ended(n) | DiveTransition::Switched { ended: n, .. } => Some(n),
+            DiveTransition::Started(_) => None,
+        }
+    }
+
+    pub fn started(self) -> Option<u16> {
+        match self {
+            DiveTransition::Started(n) | DiveTransition::Switched { started: n, .. } => Some(n),
+            DiveTransition::Ended(_) => None,
+        }
+    }
+}
+
+/// Follows `Diving` messages and reports where dives start and end. An end
+/// without a start (the dive began before tracking) is ignored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiveTracker {
+    dive: Option<u16>,
+}
+
+impl DiveTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dive number of the dive in progress
+    pub fn current(&self) -> Option<u16> {
+        self.dive
+    }
+
+    pub fn on_diving(&mut self, state: DiveState, dive_number: u16) -> Option<DiveTransition> {
+        match (self.dive, state.in_dive()) {
+            (None, true) => {
+                self.dive = Some(dive_number);
+                Some(DiveTransition::Started(dive_number))
+            }
+            (Some(current), false) => {
+                self.dive = None;
+                Some(DiveTransition::Ended(current))
+            }
+            (Some(current), true) if current != dive_number => {
+                self.dive = Some(dive_number);
+                Some(DiveTransition::Switched {
+                    ended: current,
+                    started: dive_number,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    UnknownKind { kind: u8 },
+    DlcMismatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Alert {
+    //TODO: in use, seen 1/2
+    unknown: u8,
+    pub code: u16,
+    details: [u8; 5],
+    // DLC - header (3)
+    details_len: u8,
+}
+impl Alert {
+    pub fn new(unknown: u8, code: u16, details: &[u8]) -> Result<Self, DecodeError> {
+        let details_len = details.len();
+        if details_len > 5 {
+            return Err(DecodeError::DlcMismatch);
+        }
+        let mut details_array = [0u8; 5];
+        details_array[..details_len].copy_from_slice(details);
+        Ok(Alert {
+            unknown,
+            code,
+            details: details_array,
+            details_len: details_len as u8,
+        })
+    }
+
+    pub fn details(&self) -> &[u8] {
+        &self.details[..self.details_len as usize]
+    }
+}
+
+// `Msg` and the kind and minimum DLC of each variant, from protocol.toml
+include!(concat!(env!("OUT_DIR"), "/msg.rs"));
+use Msg::*;
+
+use crate::alerts::AnyAlert;
+use crate::calibration::{CalibrationError, validate_calibration_units};
+use crate::cells::{CellArray, DIVECAN_CELLS};
+use crate::units::{
+    CentiMillivolt, Decibar, Decivolt, Fo2, Milliamp, Millibar, Millisecond, Millivolt, PpO2Deci,
+};
+
+/// How to treat frames shorter than the minimum DLC of their kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DlcPolicy {
+    /// Reject with `DecodeError::DlcMismatch`
+    #[default]
+    Strict,
+    /// Some nodes omit trailing zero bytes; decode as if the frame was zero padded
+    ZeroPad,
+}
+
+/// DLC of transmitted frames, see [`DiveCanFrame::with_tx_dlc`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxDlcPolicy {
+    /// The minimum DLC of the kind, as built by [`Msg::to_frame`]
+    #[default]
+    Minimal,
+    /// Always 8, for adapters and gateways that drop or mangle shorter
+    /// frames. Alert details take their length from the DLC, so a receiver
+    /// sees the padding as zero details.
+    PadTo8,
+}
+
+/// Bus address of the Solo
+pub const SOLO_ADDR: u8 = 0x04;
+/// Bus address of the handset
+pub const HANDSET_ADDR: u8 = 0x01;
+/// Destination address of broadcasts
+pub const BROADCAST_ADDR: u8 = 0xFF;
+
+/// What a message kind is for, see [`Msg::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MsgCategory {
+    /// Who is on the bus: ids, names, serials
+    Identity,
+    /// Periodic measurements and state
+    Sensor,
+    /// Commands and mode changes
+    Control,
+    Calibration,
+    /// UDS and alerts
+    Diagnostic,
+    /// Seen on the bus, meaning unknown
+    Undocumented,
+}
+
+impl MsgCategory {
+    /// Lowercase name, as in `protocol.toml`
+    pub fn name(&self) -> &'static str {
+        match self {
+            MsgCategory::Identity => "identity",
+            MsgCategory::Sensor => "sensor",
+            MsgCategory::Control => "control",
+            MsgCategory::Calibration => "calibration",
+            MsgCategory::Diagnostic => "diagnostic",
+            MsgCategory::Undocumented => "undocumented",
+        }
+    }
+}
+
+/// Which node a message kind is expected from, see [`Msg::expected_direction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MsgDirection {
+    /// Sent by the Solo, to whoever listens
+    SoloToBus,
+    /// Sent by the handset, for the Solo
+    HandsetToSolo,
+    /// Sent by several kinds of node, or not known
+    Any,
+}
+
+impl MsgDirection {
+    /// Whether a node at `src` plausibly sends messages of this direction
+    pub fn plausible_src(self, src: u8) -> bool {
+        match self {
+            MsgDirection::SoloToBus => src == SOLO_ADDR,
+            MsgDirection::HandsetToSolo => src == HANDSET_ADDR,
+            MsgDirection::Any => true,
+        }
+    }
+}
+
+/// Result of [`Msg::try_from_frame_with`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Decoded {
+    pub msg: Msg,
+    /// The frame was short and got zero padded
+    pub padded: bool,
+}
+
+/// Static description of a message kind, see [`Msg::KINDS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MsgKindInfo {
+    pub kind: u8,
+    pub name: &'static str,
+    /// Smallest DLC accepted when decoding, also what fixed-size messages encode with
+    pub min_dlc: u8,
+}
+
+/// Defines every message kind once and generates `kind()`, `dlc()`,
+/// `dlc_min_size()`, `to_frame()`, `try_from_frame()` and the `KINDS` registry.
+///
+/// Per entry: `dlc` is the minimum (and default) DLC, `len` overrides it for
+/// variable length messages, `encode` fills the zeroed payload `b` and `decode`
+/// builds the message from the payload and frame DLC.
+macro_rules! divecan_messages {
+    (
+        $(
+            $name:ident {
+                $(len: $lpat:pat => $len:expr,)?
+                encode: $epat:pat => |$b:ident| $encode:block,
+                decode: |$data:pat_param, $fdlc:pat_param| $decode:expr,
+                visit: $method:ident($($arg:ident: $aty:ty $(= $val:expr)?),* $(,)?) $(,)?
+            }
+        )*
+    ) => {
+        impl Msg {
+            /// Every known kind in protocol table order, to enumerate the
+            /// protocol instead of keeping a list of kinds elsewhere
+            pub const KINDS: &'static [MsgKindInfo] = &[
+                $(MsgKindInfo { kind: kind_of::$name, name: stringify!($name), min_dlc: min_dlc_of::$name },)*
+            ];
+
+            pub fn kind(&self) -> u8 {
+                match self {
+                    $(Self::$name { .. } => kind_of::$name,)*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$name { .. } => stringify!($name),)*
+                }
+            }
+
+            fn dlc(&self) -> u8 {
+                match self {
+                    $(divecan_messages!(@len_pat $name $(, $lpat)?) => divecan_messages!(@len $name $(, $len)?),)*
+                }
+            }
+
+            pub const fn dlc_min_size(kind: u8) -> Option<u8> {
+                match kind {
+                    $(kind_of::$name => Some(min_dlc_of::$name),)*
+                    _ => None,
+                }
+            }
+
+            pub fn kind_info(kind: u8) -> Option<&'static MsgKindInfo> {
+                Self::KINDS.iter().find(|k| k.kind == kind)
+            }
+
+            pub fn to_frame(&self) -> DiveCanFrame {
+                let mut data = [0u8; 8];
+                match self {
+                    $($epat => {
+                        let $b = &mut data;
+                        $encode
+                    })*
+                }
+
+                DiveCanFrame {
+                    kind: self.kind(),
+                    dlc: self.dlc(),
+                    data,
+                }
+            }
+
+            pub fn try_from_frame(frame: &DiveCanFrame) -> Result<Self, DecodeError> {
+                Self::try_from_frame_with(frame, DlcPolicy::Strict).map(|d| d.msg)
+            }
+
+            /// Decodes with an explicit policy for frames shorter than the kind's minimum DLC.
+            pub fn try_from_frame_with(
+                frame: &DiveCanFrame,
+                policy: DlcPolicy,
+            ) -> Result<Decoded, DecodeError> {
+                let Some(min) = Self::dlc_min_size(frame.kind) else {
+                    return Err(DecodeError::UnknownKind { kind: frame.kind });
+                };
+                if frame.dlc > 8 {
+                    return Err(DecodeError::DlcMismatch);
+                }
+
+                let padded = frame.dlc < min;
+                let (data, dlc) = match (padded, policy) {
+                    (false, _) => (frame.data, frame.dlc),
+                    (true, DlcPolicy::Strict) => return Err(DecodeError::DlcMismatch),
+                    (true, DlcPolicy::ZeroPad) => {
+                        let len = frame.dlc as usize;
+                        let mut data = [0u8; 8];
+                        data[..len].copy_from_slice(&frame.data[..len]);
+                        (data, min)
+                    }
+                };
+
+                let msg = match frame.kind {
+                    $(kind_of::$name => {
+                        let $data = data;
+                        let $fdlc = dlc;
+                        $decode
+                    })*
+                    other => Err(DecodeError::UnknownKind { kind: other }),
+                }?;
+                Ok(Decoded { msg, padded })
+            }
+
+            /// Calls the `visitor` method for this variant.
+            pub fn accept<V: MsgVisitor>(&self, visitor: &mut V) -> V::Output {
+                match *self {
+                    $($epat => visitor.$method($(divecan_messages!(@arg $arg $(, $val)?)),*),)*
+                }
+            }
+        }
+
+        /// One method per [`Msg`] variant, called by [`Msg::accept`] with the
+        /// variant's fields. None of the methods have defaults, so a new variant is a
+        /// compile error in every renderer until it handles it, unlike a `match` with
+        /// a `_` arm.
+        pub trait MsgVisitor {
+            type Output;
+
+            $(fn $method(&mut self, $($arg: $aty),*) -> Self::Output;)*
+        }
+    };
+    (@len_pat $name:ident) => { Self::$name { .. } };
+    (@len_pat $name:ident, $lpat:pat) => { $lpat };
+    (@len $name:ident) => { min_dlc_of::$name };
+    (@len $name:ident, $len:expr) => { $len };
+    (@arg $arg:ident) => { $arg };
+    (@arg $arg:ident, $val:expr) => { $val };
+}
+
+divecan_messages! {
+    Id {
+        encode: Id { manufacturer, unused, version } => |b| {
+            b[0] = *manufacturer;
+            b[1] = *unused;
+            b[2] = *version;
+        },
+        decode: |data, _| Ok(Id {
+            manufacturer: data[0],
+            unused: data[1],
+            version: data[2],
+        }),
+        visit: id(manufacturer: u8, unused: u8, version: u8),
+    }
+    DeviceName {
+        encode: DeviceName(name) => |b| { b.copy_from_slice(name) },
+        decode: |data, _| Ok(DeviceName(data)),
+        visit: device_name(name: [u8; 8]),
+    }
+    Alert {
+        len: Alert(alert) => 3 + alert.details_len,
+        encode: Alert(alert) => |b| {
+            let raw = alert.code.to_be_bytes();
+            b[0] = alert.unknown;
+            b[1] = raw[0];
+            b[2] = raw[1];
+            let len = alert.details_len as usize;
+            b[3..(3 + len)].copy_from_slice(&alert.details[..len]);
+        },
+        decode: |data, dlc| {
+            let len = (dlc as usize).saturating_sub(3);
+            let code = u16::from_be_bytes([data[1], data[2]]);
+            let details = &data[3..3 + len];
+            let alert = crate::divecan::Alert::new(data[0], code, details)?;
+            Ok(Alert(alert))
+        },
+        visit: alert(alert: Alert),
+    }
+    ShutdownInit {
+        encode: ShutdownInit(cause) => |b| { b[0] = cause.to_u8(); },
+        decode: |data, _| Ok(ShutdownInit(ShutdownReason::from_u8(data[0]))),
+        visit: shutdown_init(cause: ShutdownReason),
+    }
+    CellPpo2 {
+        encode: CellPpo2(cells) => |b| {
+            b[0] = 0x00;
+            // Room for every cell in the 8 byte frame
+            let _ = cells.encode(&mut b[1..], |c| [c.raw()]);
+        },
+        decode: |data, _| Ok(CellPpo2(CellArray::from_fn(|i| data[1 + i].into()))),
+        visit: cell_ppo2(cells: CellArray<PpO2Deci>),
+    }
+    OboeStatus {
+        encode: OboeStatus {
+            battery_ok,
+            battery_voltage,
+            unknown1,
+            unknown2,
+            unknown3,
+        } => |b| {
+            b[0] = if *battery_ok { 1 } else { 0 };
+            b[1] = battery_voltage.raw();
+            b[2] = *unknown1;
+            b[3] = *unknown2;
+            b[4] = *unknown3;
+        },
+        decode: |data, _| Ok(OboeStatus {
+            battery_ok: data[0] != 0,
+            battery_voltage: data[1].into(),
+            unknown1: data[2],
+            unknown2: data[3],
+            unknown3: data[4],
+        }),
+        visit: oboe_status(
+            battery_ok: bool,
+            battery_voltage: Decivolt,
+            unknown: [u8; 3] = [unknown1, unknown2, unknown3],
+        ),
+    }
+    AmbientPressure {
+        encode: AmbientPressure { surface, current, depth_comp } => |b| {
+            b[0..2].copy_from_slice(&surface.raw().to_be_bytes());
+            b[2..4].copy_from_slice(&current.raw().to_be_bytes());
+            b[4] = if *depth_comp { 1 } else { 0 };
+        },
+        decode: |data, _| Ok(AmbientPressure {
+            surface: u16::from_be_bytes([data[0], data[1]]).into(),
+            current: u16::from_be_bytes([data[2], data[3]]).into(),
+            depth_comp: data[4] != 0,
+        }),
+        visit: ambient_pressure(surface: Millibar, current: Millibar, depth_comp: bool),
+    }
+    Uds {
+        len: Uds { dlc, .. } => *dlc,
+        encode: Uds { dlc, data } => |b| {
+            let len = *dlc as usize;
+            b[..len].copy_from_slice(&data[..len]);
+        },
+        decode: |data, dlc| {
+            let len = dlc as usize;
+            let mut d = [0u8; 8];
+            d[..len].copy_from_slice(&data[..len]);
+            Ok(Uds { dlc, data: d })
+        },
+        visit: uds(dlc: u8, data: [u8; 8]),
+    }
+    TankPressure {
+        encode: TankPressure { cylinder_index, pressure } => |b| {
+            b[0] = *cylinder_index;
+            b[1..3].copy_from_slice(&pressure.raw().to_be_bytes());
+        },
+        decode: |data, _| Ok(TankPressure {
+            cylinder_index: data[0],
+            pressure: u16::from_be_bytes([data[1], data[2]]).into(),
+        }),
+        visit: tank_pressure(cylinder_index: u8, pressure: Decibar),
+    }
+    // Observed on the bus with an empty payload
+    Nop {
+        encode: Nop => |_b| {},
+        decode: |_, _| Ok(Nop),
+        visit: nop(),
+    }
+    CellVoltages {
+        encode: CellVoltages { cell_voltages, unused } => |b| {
+            cell_voltages.encode(b, |c| c.raw().to_be_bytes());
+            b[6] = *unused;
+        },
+        decode: |data, _| Ok(CellVoltages {
+            cell_voltages: CellArray::from_fn(|i| {
+                u16::from_be_bytes([data[2 * i], data[2 * i + 1]]).into()
+            }),
+            unused: data[6],
+        }),
+        visit: cell_voltages(cell_voltages: CellArray<CentiMillivolt>, unused: u8),
+    }
+    Ppo2CalibrationResponse {
+        encode: Ppo2CalibrationResponse {
+            status,
+            cell_voltages,
+            fo2,
+            pressure,
+            cells_active,
+        } => |b| {
+            b[0] = status.to_byte();
+            let _ = cell_voltages.encode(&mut b[1..4], |c| [c.raw()]);
+            b[4] = fo2.raw();
+            b[5..7].copy_from_slice(&pressure.raw().to_be_bytes());
+            b[7] = cells_active.to_u8();
+        },
+        decode: |data, _| Ok(Ppo2CalibrationResponse {
+            status: CalStatusCode::from_byte(data[0]),
+            cell_voltages: CellArray::from_fn(|i| data[1 + i].into()),
+            fo2: data[4].into(),
+            pressure: u16::from_be_bytes([data[5], data[6]]).into(),
+            cells_active: CellsActive::from_u8(data[7]),
+        }),
+        visit: ppo2_calibration_response(
+            status: CalStatusCode,
+            cell_voltages: CellArray<Millivolt>,
+            fo2: Fo2,
+            pressure: Millibar,
+            cells_active: CellsActive,
+        ),
+    }
+    Ppo2CalibrationRequest {
+        encode: Ppo2CalibrationRequest { fo2, pressure } => |b| {
+            b[0] = fo2.raw();
+            b[1..3].copy_from_slice(&pressure.raw().to_be_bytes());
+        },
+        decode: |data, _| Ok(Ppo2CalibrationRequest {
+            fo2: data[0].into(),
+            pressure: u16::from_be_bytes([data[1], data[2]]).into(),
+        }),
+        visit: ppo2_calibration_request(fo2: Fo2, pressure: Millibar),
+    }
+    Co2Enabled {
+        encode: Co2Enabled(enabled) => |b| { b[0] = if *enabled { 1 } else { 0 }; },
+        decode: |data, _| Ok(Co2Enabled(data[0] != 0)),
+        visit: co2_enabled(enabled: bool),
+    }
+    Co2 {
+        encode: Co2 { unknown, pco2 } => |b| {
+            b[0] = *unknown;
+            b[1..3].copy_from_slice(&pco2.raw().to_be_bytes());
+        },
+        decode: |data, _| Ok(Co2 {
+            unknown: data[0],
+            pco2: u16::from_be_bytes([data[1], data[2]]).into(),
+        }),
+        visit: co2(unknown: u8, pco2: Millibar),
+    }
+    Co2CalibrationResponse {
+        encode: Co2CalibrationResponse { code, pco2 } => |b| {
+            b[0] = *code;
+            b[1..3].copy_from_slice(&pco2.raw().to_be_bytes());
+        },
+        decode: |data, _| Ok(Co2CalibrationResponse {
+            code: data[0],
+            pco2: u16::from_be_bytes([data[1], data[2]]).into(),
+        }),
+        visit: co2_calibration_response(code: u8, pco2: Millibar),
+    }
+    Co2CalibrationRequest {
+        encode: Co2CalibrationRequest { pco2 } => |b| {
+            b[0..2].copy_from_slice(&pco2.raw().to_be_bytes());
+        },
+        decode: |data, _| Ok(Co2CalibrationRequest {
+            pco2: u16::from_be_bytes([data[0], data[1]]).into(),
+        }),
+        visit: co2_calibration_request(pco2: Millibar),
+    }
+    Undocumented30 {
+        encode: Undocumented30 { raw } => |b| { b[0..3].copy_from_slice(raw) },
+        decode: |data, _| Ok(Undocumented30 {
+            raw: [data[0], data[1], data[2]],
+        }),
+        visit: undocumented_30(raw: [u8; 3]),
+    }
+    BusInit {
+        encode: BusInit { unused } => |b| { b[0..3].copy_from_slice(unused) },
+        decode: |data, _| Ok(BusInit {
+            unused: [data[0], data[1], data[2]],
+        }),
+        visit: bus_init(unused: [u8; 3]),
+    }
+    TempProbe {
+        encode: TempProbe { sensor_id, temp } => |b| {
+            b[0] = *sensor_id;
+            b[1..3].copy_from_slice(&temp.to_be_bytes());
+        },
+        decode: |data, _| Ok(TempProbe {
+            sensor_id: data[0],
+            temp: u16::from_be_bytes([data[1], data[2]]),
+        }),
+        visit: temp_probe(sensor_id: u8, temp: u16),
+    }
+    UndocumentedC3 {
+        encode: UndocumentedC3 {
+            unknown1,
+            unknown2,
+            unknown3,
+            unknown4,
+        } => |b| {
+            b[0..2].copy_from_slice(&unknown1.to_be_bytes());
+            b[2..4].copy_from_slice(&unknown2.to_be_bytes());
+            b[4] = *unknown3;
+            b[5] = *unknown4;
+        },
+        decode: |data, _| Ok(UndocumentedC3 {
+            unknown1: u16::from_be_bytes([data[0], data[1]]),
+            unknown2: u16::from_be_bytes([data[2], data[3]]),
+            unknown3: data[4],
+            unknown4: data[5],
+        }),
+        visit: undocumented_c3(
+            unknown: (u16, u16, u8, u8) = (unknown1, unknown2, unknown3, unknown4),
+        ),
+    }
+    TempProbeEnabled {
+        encode: TempProbeEnabled(enabled) => |b| { b[0] = if *enabled { 1 } else { 0 }; },
+        decode: |data, _| Ok(TempProbeEnabled(data[0] != 0)),
+        visit: temp_probe_enabled(enabled: bool),
+    }
+    Setpoint {
+        encode: Setpoint(setpoint) => |b| { b[0] = setpoint.raw(); },
+        decode: |data, _| Ok(Setpoint(data[0].into())),
+        visit: setpoint(setpoint: PpO2Deci),
+    }
+    CellStatus {
+        encode: CellStatus { cells_active, consensus } => |b| {
+            b[0] = cells_active.to_u8();
+            b[1] = consensus.to_u8();
+        },
+        decode: |data, _| Ok(CellStatus {
+            cells_active: CellsActive::from_u8(data[0]),
+            consensus: Consensus::from_u8(data[1]),
+        }),
+        visit: cell_status(cells_active: CellsActive, consensus: Consensus),
+    }
+    SoloStatus {
+        encode: SoloStatus {
+            voltage,
+            current,
+            injection_duration,
+            setpoint,
+            consensus,
+            voltage_alert,
+            current_alert,
+        } => |b| {
+            b[0] = voltage.raw();
+            b[1..3].copy_from_slice(&current.raw().to_be_bytes());
+            b[3..5].copy_from_slice(&injection_duration.raw().to_be_bytes());
+            b[5] = setpoint.raw();
+            b[6] = consensus.to_u8();
+            let bat2 = VoltageAlert::to_2bit_opt(*voltage_alert) & 0b11;
+            let sol2 = (CurrentAlert::to_2bit_opt(*current_alert) & 0b11) << 2;
+            b[7] = bat2 | sol2;
+        },
+        decode: |data, _| Ok(SoloStatus {
+            voltage: data[0].into(),
+            current: u16::from_be_bytes([data[1], data[2]]).into(),
+            injection_duration: u16::from_be_bytes([data[3], data[4]]).into(),
+            setpoint: data[5].into(),
+            consensus: Consensus::from_u8(data[6]),
+            voltage_alert: VoltageAlert::from_2bit_opt(data[7] & 0b0011),
+            current_alert: CurrentAlert::from_2bit_opt((data[7] & 0b1100) >> 2),
+        }),
+        visit: solo_status(status: SoloStatusFields = SoloStatusFields {
+            voltage,
+            current,
+            injection_duration,
+            setpoint,
+            consensus,
+            voltage_alert,
+            current_alert,
+        }),
+    }
+    Diving {
+        encode: Diving { status, dive_number, timestamp } => |b| {
+            b[0] = status.to_u8();
+            b[1..3].copy_from_slice(&dive_number.to_be_bytes());
+            b[3..7].copy_from_slice(&timestamp.to_be_bytes());
+        },
+        decode: |data, _| Ok(Diving {
+            status: DiveState::from_u8(data[0]),
+            dive_number: u16::from_be_bytes([data[1], data[2]]),
+            timestamp: u32::from_be_bytes([data[3], data[4], data[5], data[6]]),
+        }),
+        visit: diving(status: DiveState, dive_number: u16, timestamp: u32),
+    }
+    Serial {
+        encode: Serial(serial) => |b| { b.copy_from_slice(serial) },
+        decode: |data, _| Ok(Serial(data)),
+        visit: serial(serial: [u8; 8]),
+    }
+}
+
+impl Msg {
+    /// Builds a `Ppo2CalibrationRequest`, rejecting implausible FO₂ or pressure.
+    pub fn ppo2_calibration_request(
+        fo2: Fo2,
+        pressure: Millibar,
+    ) -> Result<Self, CalibrationError> {
+        validate_calibration_units(fo2, pressure)?;
+        Ok(Ppo2CalibrationRequest { fo2, pressure })
+    }
+
+    /// An `Alert` for a known code without details, with the first byte
+    /// from [`AnyAlert::default_unknown`].
+    pub fn alert_from(alert: impl Into<AnyAlert>) -> Self {
+        let alert = alert.into();
+        Alert(Alert {
+            unknown: alert.default_unknown(),
+            code: alert.to_u16(),
+            details: [0; 5],
+            details_len: 0,
+        })
+    }
+
+    /// Builds a `CellStatus`, rejecting a consensus that contradicts
+    /// `cells_active` (see [`Consensus::check`]). Decoding stays lenient,
+    /// use [`Msg::check_consistency`] on received frames.
+    pub fn cell_status(
+        cells_active: CellsActive,
+        consensus: Consensus,
+    ) -> Result<Self, ConsensusError> {
+        consensus.check(cells_active)?;
+        Ok(CellStatus {
+            cells_active,
+            consensus,
+        })
+    }
+
+    /// Builds a `SoloStatus`. The message has no cell mask of its own, so
+    /// the consensus is checked against the `cells_active` the sender
+    /// broadcasts in `CellStatus`.
+    pub fn solo_status(
+        fields: SoloStatusFields,
+        cells_active: CellsActive,
+    ) -> Result<Self, ConsensusError> {
+        fields.consensus.check(cells_active)?;
+        let SoloStatusFields {
+            voltage,
+            current,
+            injection_duration,
+            setpoint,
+            consensus,
+            voltage_alert,
+            current_alert,
+        } = fields;
+        Ok(SoloStatus {
+            voltage,
+            current,
+            injection_duration,
+            setpoint,
+            consensus,
+            voltage_alert,
+            current_alert,
+        })
+    }
+
+    /// The checks of the validating constructors, for decoded messages.
+    /// Only `CellStatus` carries both halves, anything else is `Ok`.
+    pub fn check_consistency(&self) -> Result<(), ConsensusError> {
+        match *self {
+            CellStatus {
+                cells_active,
+                consensus,
+            } => consensus.check(cells_active),
+            _ => Ok(()),
+        }
+    }
+
+    /// Category of `kind` from `protocol.toml`, `None` for unknown kinds
+    pub fn kind_category(kind: u8) -> Option<MsgCategory> {
+        crate::protocol::message(kind).map(|m| m.category)
+    }
+
+    pub fn category(&self) -> MsgCategory {
+        Self::kind_category(self.kind()).unwrap_or(MsgCategory::Undocumented)
+    }
+
+    /// Expected sender of `kind` from `protocol.toml`, `None` for unknown kinds
+    pub fn kind_direction(kind: u8) -> Option<MsgDirection> {
+        crate::protocol::message(kind).map(|m| m.direction)
+    }
+
+    pub fn expected_direction(&self) -> MsgDirection {
+        Self::kind_direction(self.kind()).unwrap_or(MsgDirection::Any)
+    }
+}
+
+/// Fields of [`Msg::SoloStatus`], grouped for [`MsgVisitor::solo_status`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SoloStatusFields {
+    pub voltage: Decivolt,
+    pub current: Milliamp,
+    pub injection_duration: Millisecond,
+    pub setpoint: PpO2Deci,
+    pub consensus: Consensus,
+    pub voltage_alert: Option<VoltageAlert>,
+    pub current_alert: Option<CurrentAlert>,
+}
+
+// Standard trait implementations for idiomatic conversion
+impl From<Msg> for DiveCanFrame {
+    fn from(msg: Msg) -> Self {
+        msg.to_frame()
+    }
+}
+
+impl From<&Msg> for DiveCanFrame {
+    fn from(msg: &Msg) -> Self {
+        msg.to_frame()
+    }
+}
+
+impl TryFrom<&DiveCanFrame> for Msg {
+    type Error = DecodeError;
+
+    fn try_from(frame: &DiveCanFrame) -> Result<Self, Self::Error> {
+        Msg::try_from_frame(frame)
+    }
+}
+
+/// Averages `AmbientPressure` broadcasts seen on the bus, so calibration can
+/// use the device's own pressure reading instead of a user-supplied value.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AmbientPressureAverage {
+    mean: crate::stats::Mean,
+}
+
+impl AmbientPressureAverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a decoded message, returns true if it was an `AmbientPressure` sample.
+    pub fn push(&mut self, msg: &Msg) -> bool {
+        match msg {
+            AmbientPressure { current, .. } => {
+                self.mean.push(current.raw() as u32);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.mean.samples()
+    }
+
+    pub fn average(&self) -> Option<Millibar> {
+        self.mean.average().map(|avg| Millibar::new(avg as u16))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::kind;
+
+    #[test]
+    fn alert_from_known_codes() {
+        use crate::alerts::{HandsetAlert, SoloAlert};
+
+        let Msg::Alert(alert) = Msg::alert_from(SoloAlert::SoloSetpointTimeout) else {
+            unreachable!()
+        };
+        assert_eq!((alert.code, alert.details()), (0x103, &[][..]));
+        assert_eq!(
+            Msg::alert_from(HandsetAlert::ShutdownWhileDiving)
+                .to_frame()
+                .bytes(),
+            [2, 0x00, 0x23]
+        );
+        assert_eq!(
+            AnyAlert::from_u16(0x201),
+            Some(AnyAlert::Temp(crate::alerts::TempAlert::TempProbeFailed))
+        );
+    }
+
+    #[test]
+    fn consensus_fits_cells() {
+        let none = CellsActive::default();
+        let two = CellsActive::new([true, false, true]);
+        let ppo2 = Consensus::PpO2(PpO2Deci::new(98));
+
+        assert!(Msg::cell_status(two, ppo2).is_ok());
+        assert_eq!(
+            Msg::cell_status(none, ppo2),
+            Err(ConsensusError::PpO2WithoutActiveCells)
+        );
+        assert_eq!(
+            Msg::cell_status(two, Consensus::NoActiveCells),
+            Err(ConsensusError::NoActiveCellsWithActiveCells)
+        );
+        assert!(Msg::cell_status(none, Consensus::NoActiveCells).is_ok());
+        assert!(Msg::cell_status(none, Consensus::NotCalibrated).is_ok());
+        assert_eq!(
+            Consensus::PpO2(PpO2Deci::new(0xfe)).check(two),
+            Err(ConsensusError::ReservedPpO2(0xfe))
+        );
+
+        // Received frames decode whatever they say
+        let frame = DiveCanFrame::new(kind::CELL_STATUS, 2, [0, 98, 0, 0, 0, 0, 0, 0]).unwrap();
+        let msg = Msg::try_from_frame(&frame).unwrap();
+        assert_eq!(
+            msg.check_consistency(),
+            Err(ConsensusError::PpO2WithoutActiveCells)
+        );
+    }
+
+    #[test]
+    fn cells_active_mask() {
+        let mut cells = CellsActive::<3>::default();
+        cells.set(0, true);
+        cells.set(2, true);
+        assert_eq!(cells.to_u8(), 0b101);
+        assert!(cells[0] && !cells[1] && cells[2]);
+        assert_eq!(cells.count_active(), 2);
+        assert_eq!(cells.iter().collect::<Vec<_>>(), [true, false, true]);
+        assert_eq!(cells.to_string(), "cells 1,3 active");
+
+        cells.set(0, false);
+        assert_eq!(cells.to_string(), "cell 3 active");
+        assert_eq!(CellsActive::<3>::default().to_string(), "no cells active");
+
+        let collected: CellsActive = [false, true, true, true].into_iter().collect();
+        assert_eq!(collected, CellsActive::new([false, true, true]));
+    }
+
+    #[test]
+    fn dlc_matches_encoded_payload_length() {
+        use super::*;
+
+        fn assert_zero_tail(m: &Msg) {
+            let dlc = m.dlc() as usize;
+            assert!(dlc <= 8, "DLC out of range for {:?}: dlc={}", m, dlc);
+
+            let bytes = m.to_frame().data;
+            let tail = &bytes[dlc..];
+            assert!(
+                tail.iter().all(|&b| b == 0),
+                "Non-zero bytes past DLC for {:?}: dlc={}, bytes={:02X?}",
+                m,
+                dlc,
+                bytes
+            );
+        }
+
+        // Use non-zero values so mistakes are visible.
+        let cases: Vec<Msg> = vec![
+            Msg::Id {
+                manufacturer: 0xAA,
+                unused: 0xBB,
+                version: 0xCC,
+            },
+            Msg::DeviceName(*b"YOLO    "),
+            Msg::Alert(Alert::new(0xff, 0x1234, &[0xEE; 5]).unwrap()),
+            Msg::ShutdownInit(ShutdownReason::Timeout),
+            Msg::CellPpo2(CellArray::new([0x11.into(), 0x22.into(), 0x33.into()])),
+            Msg::OboeStatus {
+                battery_ok: true,
+                battery_voltage: 0x64.into(),
+                unknown1: 0x01,
+                unknown2: 0x02,
+                unknown3: 0x03,
+            },
+            Msg::AmbientPressure {
+                surface: 1013.into(),
+                current: 1245.into(),
+                depth_comp: true,
+            },
+            Msg::Uds {
+                data: [1, 2, 3, 4, 0, 0, 0, 0],
+                dlc: 4,
+            },
+            Msg::TankPressure {
+                cylinder_index: 0x02,
+                pressure: 0x1234.into(),
+            },
+            Msg::Nop,
+            Msg::CellVoltages {
+                cell_voltages: CellArray::new([3200.into(), 3300.into(), 3400.into()]),
+                unused: 0x7F,
+            },
+            Msg::Ppo2CalibrationRequest {
+                fo2: 0x21.into(),
+                pressure: 1000.into(),
+            },
+            Msg::Ppo2CalibrationResponse {
+                status: CalStatusCode::Ack,
+                cell_voltages: CellArray::new([0x10.into(), 0x20.into(), 0x30.into()]),
+                fo2: 0x21.into(),
+                pressure: 1000.into(),
+                cells_active: CellsActive::new([true, true, true]),
+            },
+            Msg::Co2Enabled(true),
+            Msg::Co2 {
+                unknown: 0x55,
+                pco2: 1234.into(),
+            },
+            Msg::Co2CalibrationResponse {
+                code: 0x66,
+                pco2: 2222.into(),
+            },
+            Msg::Co2CalibrationRequest { pco2: 3333.into() },
+            Msg::Undocumented30 { raw: [9, 8, 7] },
+            Msg::BusInit {
+                unused: [0xA1, 0xA2, 0xA3],
+            },
+            Msg::TempProbe {
+                sensor_id: 0x02,
+                temp: 0x0BEE,
+            },
+            Msg::UndocumentedC3 {
+                unknown1: 0xFFFF,
+                unknown2: 0xFFFF,
+                unknown3: 0xFF,
+                unknown4: 0xFF,
+            },
+            Msg::TempProbeEnabled(true),
+            Msg::Setpoint(0x42.into()),
+            Msg::CellStatus {
+                cells_active: CellsActive::new([true, true, true]),
+                consensus: Consensus::PpO2(0x55.into()),
+            },
+            Msg::SoloStatus {
+                voltage: 0x64.into(),
+                current: 0xff.into(),
+                injection_duration: 0xff.into(),
+                setpoint: 0x03.into(),
+                consensus: Consensus::PpO2(0x09.into()),
+                voltage_alert: None,
+                current_alert: None,
+            },
+            Msg::Diving {
+                status: DiveState::Diving,
+                dive_number: 0x1234,
+                timestamp: 1_700_000_000,
+            },
+            Msg::Serial([0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80]),
+        ];
+
+        for m in cases {
+            assert_zero_tail(&m);
+            let padded = m.to_frame().with_tx_dlc(TxDlcPolicy::PadTo8);
+            assert_eq!(padded.dlc(), 8);
+            assert_eq!(&padded.bytes()[..m.dlc() as usize], m.to_frame().bytes());
+            // UDS and alert details take their length from the DLC
+            if !matches!(m, Msg::Uds { .. } | Msg::Alert(_)) {
+                assert_eq!(Msg::try_from_frame(&padded), Ok(m), "padded {:?}", m);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_id() {
+        let m = Msg::Id {
+            manufacturer: 1,
+            version: 0x84,
+            unused: 0,
+        };
+        let m2 = Msg::try_from_frame(&m.to_frame()).unwrap();
+        assert_eq!(m, m2);
+    }
+
+    #[test]
+    fn classify_can_ids() {
+        let id = DiveCanId::new(0x04, 0x01, 0x11);
+        assert_eq!(DiveCanId::try_from_u32(0x0D11_0104), Some(id));
+        assert_eq!(BusTraffic::classify(id.to_u32()), BusTraffic::DiveCan(id));
+        // J1939 style id, from_u32 would read it as kind 0xFE
+        assert_eq!(DiveCanId::try_from_u32(0x18FE_F100), None);
+        assert_eq!(
+            BusTraffic::classify(0x18FE_F100),
+            BusTraffic::Other(0x18FE_F100)
+        );
+    }
+
+    #[test]
+    fn categories_and_directions() {
+        let setpoint = Msg::Setpoint(PpO2Deci::new(70));
+        assert_eq!(setpoint.category(), MsgCategory::Control);
+        assert_eq!(setpoint.expected_direction(), MsgDirection::HandsetToSolo);
+        assert!(setpoint.expected_direction().plausible_src(HANDSET_ADDR));
+        assert!(!setpoint.expected_direction().plausible_src(SOLO_ADDR));
+        assert_eq!(Msg::kind_category(0xCB), Some(MsgCategory::Sensor));
+        assert_eq!(Msg::kind_direction(0xCB), Some(MsgDirection::SoloToBus));
+        assert_eq!(Msg::kind_category(0x99), None);
+        assert!(MsgDirection::Any.plausible_src(0x42));
+    }
+
+    #[test]
+    fn roundtrip_alert() {
+        let m = Msg::Alert(Alert::new(0xff, 0x1234, &[0xff; 5]).unwrap());
+        let m2 = Msg::try_from_frame(&m.to_frame()).unwrap();
+        assert_eq!(m, m2);
+    }
+
+    #[test]
+    fn roundtrip_diving() {
+        let m = Msg::Diving {
+            status: DiveState::Surface,
+            dive_number: 42,
+            timestamp: 1_700_000_000,
+        };
+        let m2 = Msg::try_from_frame(&m.to_frame()).unwrap();
+        assert_eq!(m, m2);
+
+        for b in 0..=0xFF {
+            assert_eq!(DiveState::from_u8(b).to_u8(), b);
+        }
+        assert_eq!(DiveState::from_u8(0x07), DiveState::Unknown(0x07));
+    }
+
+    #[test]
+    fn dive_transitions() {
+        let mut t = DiveTracker::new();
+        assert_eq!(t.on_diving(DiveState::Surface, 41), None);
+        assert_eq!(
+            t.on_diving(DiveState::Diving, 42),
+            Some(DiveTransition::Started(42))
+        );
+        assert_eq!(t.on_diving(DiveState::Ending, 42), None);
+        assert_eq!(t.current(), Some(42));
+        let switched = t.on_diving(DiveState::Unknown(3), 43).unwrap();
+        assert_eq!((switched.ended(), switched.started()), (Some(42), Some(43)));
+        assert_eq!(
+            t.on_diving(DiveState::Surface, 43),
+            Some(DiveTransition::Ended(43))
+        );
+        assert_eq!(t.current(), None);
+    }
+
+    #[test]
+    fn test_from_trait() {
+        let msg = Msg::Id {
+            manufacturer: 1,
+            unused: 0,
+            version: 0x42,
+        };
+
+        let frame: DiveCanFrame = msg.into();
+        assert_eq!(frame.kind(), 0x00);
+
+        let frame2: DiveCanFrame = (&msg).into();
+        assert_eq!(frame2.kind(), 0x00);
+
+        assert_eq!(msg.kind(), 0x00);
+    }
+
+    #[test]
+    fn test_tryfrom_trait() {
+        let msg = Msg::Setpoint(0x70.into());
+        let frame = msg.to_frame();
+
+        // Test TryFrom<&DiveCanFrame>
+        let msg2: Msg = (&frame).try_into().unwrap();
+        assert_eq!(msg, msg2);
+    }
+
+    #[test]
+    fn kinds_registry_consistent() {
+        for (i, info) in Msg::KINDS.iter().enumerate() {
+            assert_eq!(Msg::dlc_min_size(info.kind), Some(info.min_dlc));
+            assert_eq!(Msg::kind_info(info.kind), Some(info));
+            assert!(
+                Msg::KINDS[i + 1..].iter().all(|k| k.kind != info.kind),
+                "duplicate kind 0x{:02X}",
+                info.kind
+            );
+        }
+        assert_eq!(Msg::Nop.to_frame().dlc(), 0);
+        assert_eq!(Msg::Nop.name(), "Nop");
+    }
+
+    #[test]
+    fn rejects_wrong_dlc() {
+        let mut f = Msg::Diving {
+            status: DiveState::Surface,
+            dive_number: 1,
+            timestamp: 2,
+        }
+        .to_frame();
+        f.dlc = 5; // expected minimum 7
+        assert!(matches!(
+            Msg::try_from_frame(&f),
+            Err(DecodeError::DlcMismatch)
+        ));
+    }
+
+    #[test]
+    fn zero_pad_short_frames() {
+        let f = DiveCanFrame::new(0xC9, 0, [0xAA; 8]).unwrap();
+        assert!(matches!(
+            Msg::try_from_frame(&f),
+            Err(DecodeError::DlcMismatch)
+        ));
+        assert_eq!(
+            Msg::try_from_frame_with(&f, DlcPolicy::ZeroPad),
+            Ok(Decoded {
+                msg: Msg::Setpoint(0.into()),
+                padded: true
+            })
+        );
+
+        let full = Msg::Setpoint(7.into()).to_frame();
+        assert_eq!(
+            Msg::try_from_frame_with(&full, DlcPolicy::ZeroPad).map(|d| d.padded),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn ambient_pressure_average() {
+        let mut avg = AmbientPressureAverage::new();
+        assert_eq!(avg.average(), None);
+
+        assert!(!avg.push(&Msg::Setpoint(0x0A.into())));
+        for current in [1010u16, 1013, 1015] {
+            assert!(avg.push(&Msg::AmbientPressure {
+                surface: 1013.into(),
+                current: current.into(),
+                depth_comp: false,
+            }));
+        }
+
+        assert_eq!(avg.samples(), 3);
+        assert_eq!(avg.average(), Some(Millibar::new(1013)));
+    }
+
+    /// Every kind at every DLC decodes or errors, whatever the padding policy
+    #[test]
+    fn short_frames_dont_panic() {
+        for info in Msg::KINDS {
+            for dlc in 0..=8 {
+                for fill in [0x00, 0xFF] {
+                    let frame = DiveCanFrame::new(info.kind, dlc, [fill; 8]).unwrap();
+                    let strict = Msg::try_from_frame(&frame);
+                    assert!(dlc >= info.min_dlc || strict.is_err(), "{}", info.name);
+                    let _ = Msg::try_from_frame_with(&frame, DlcPolicy::ZeroPad);
+                }
+            }
+        }
+        for kind in 0..=u8::MAX {
+            let frame = DiveCanFrame::new(kind, 0, [0; 8]).unwrap();
+            let _ = Msg::try_from_frame_with(&frame, DlcPolicy::ZeroPad);
+        }
+    }
+}
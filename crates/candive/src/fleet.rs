@@ -0,0 +1,159 @@
+//! Local registry of the devices a tool has talked to, keyed by serial, with
+//! a free-form note per device (e.g. "rental unit #3, new cells 2024-05").
+//! [`Fleet::seen`] is meant to be called on every connection and keeps the
+//! device ID, firmware version and first/last seen times current. Notes
+//! survive those updates.
+//!
+//! Stored in SQLite so solodiag and a GUI can share one file.
+
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+pub use rusqlite::Error as FleetError;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS devices (
+    serial        TEXT PRIMARY KEY,
+    device_id     TEXT NOT NULL,
+    firmware      TEXT NOT NULL,
+    first_seen_ms INTEGER NOT NULL,
+    last_seen_ms  INTEGER NOT NULL,
+    note          TEXT NOT NULL DEFAULT ''
+);
+";
+
+/// One device as last seen, with the user's note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FleetDevice {
+    pub serial: String,
+    pub device_id: String,
+    pub firmware: String,
+    pub first_seen_ms: u64,
+    pub last_seen_ms: u64,
+    pub note: String,
+}
+
+impl FleetDevice {
+    fn from_row(row: &Row<'_>) -> Result<Self, FleetError> {
+        Ok(Self {
+            serial: row.get(0)?,
+            device_id: row.get(1)?,
+            firmware: row.get(2)?,
+            first_seen_ms: row.get::<_, i64>(3)? as u64,
+            last_seen_ms: row.get::<_, i64>(4)? as u64,
+            note: row.get(5)?,
+        })
+    }
+}
+
+/// Devices a tool has connected to, keyed by serial number, in SQLite so
+/// several tools can share one file.
+///
+/// Timestamps are milliseconds, typically since the Unix epoch.
+pub struct Fleet {
+    conn: Connection,
+}
+
+impl Fleet {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FleetError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, FleetError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, FleetError> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Adds the device or updates its identity and last-seen time, keeping
+    /// the note.
+    pub fn seen(
+        &self,
+        serial: &str,
+        device_id: &str,
+        firmware: &str,
+        ts_ms: u64,
+    ) -> Result<(), FleetError> {
+        self.conn.execute(
+            "INSERT INTO devices (serial, device_id, firmware, first_seen_ms, last_seen_ms)
+             VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT (serial) DO UPDATE SET
+                device_id = excluded.device_id,
+                firmware = excluded.firmware,
+                last_seen_ms = excluded.last_seen_ms",
+            params![serial, device_id, firmware, ts_ms as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the note, `false` if the serial isn't in the registry
+    pub fn set_note(&self, serial: &str, note: &str) -> Result<bool, FleetError> {
+        let changed = self.conn.execute(
+            "UPDATE devices SET note = ?2 WHERE serial = ?1",
+            params![serial, note],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn get(&self, serial: &str) -> Result<Option<FleetDevice>, FleetError> {
+        self.conn
+            .query_row(
+                "SELECT serial, device_id, firmware, first_seen_ms, last_seen_ms, note
+                 FROM devices WHERE serial = ?1",
+                params![serial],
+                FleetDevice::from_row,
+            )
+            .optional()
+    }
+
+    /// All devices, most recently seen first
+    pub fn list(&self) -> Result<Vec<FleetDevice>, FleetError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT serial, device_id, firmware, first_seen_ms, last_seen_ms, note
+             FROM devices ORDER BY last_seen_ms DESC",
+        )?;
+        stmt.query_map([], FleetDevice::from_row)?.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seen_keeps_note() {
+        let fleet = Fleet::open_in_memory().unwrap();
+        fleet.seen("9C5A58BB", "0011", "v11", 10).unwrap();
+        assert!(fleet.set_note("9C5A58BB", "rental unit #3").unwrap());
+        assert!(!fleet.set_note("00000000", "unknown").unwrap());
+
+        fleet.seen("9C5A58BB", "0011", "v12", 50).unwrap();
+        fleet.seen("12345678", "0022", "v12", 30).unwrap();
+
+        let unit = fleet.get("9C5A58BB").unwrap().unwrap();
+        assert_eq!(
+            (
+                unit.firmware.as_str(),
+                unit.first_seen_ms,
+                unit.last_seen_ms
+            ),
+            ("v12", 10, 50)
+        );
+        assert_eq!(unit.note, "rental unit #3");
+
+        let serials: Vec<_> = fleet
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.serial)
+            .collect();
+        assert_eq!(serials, ["9C5A58BB", "12345678"]);
+        assert_eq!(fleet.get("00000000").unwrap(), None);
+    }
+}
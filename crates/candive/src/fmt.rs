@@ -1,9 +1,50 @@
 use core::fmt;
 
 use crate::units::{
-    CentiMillivolt, Decibar, Decivolt, Fo2, Milliamp, Millibar, Millisecond, Millivolt, PpO2Deci,
+    CentiMillivolt, Decibar, Decimeter, Decivolt, Fo2, Milliamp, Millibar, Millisecond, Millivolt,
+    Percent, PpO2Deci, Ratio,
 };
 
+/// Unit system to print measurements in. The unit types stay metric, this
+/// only changes how [`DisplayUnits`] renders them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnitsPreference {
+    #[default]
+    Metric,
+    /// psi and feet
+    Imperial,
+}
+
+/// Measurements with an imperial rendering. Metric is the type's `Display`.
+pub trait DisplayUnits: fmt::Display {
+    fn fmt_imperial(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    fn in_units(&self, units: UnitsPreference) -> InUnits<'_, Self> {
+        InUnits { value: self, units }
+    }
+}
+
+/// `Display` adapter returned by [`DisplayUnits::in_units`]
+pub struct InUnits<'a, T: ?Sized> {
+    value: &'a T,
+    units: UnitsPreference,
+}
+
+impl<T: DisplayUnits + ?Sized> fmt::Display for InUnits<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.units {
+            UnitsPreference::Metric => fmt::Display::fmt(self.value, f),
+            UnitsPreference::Imperial => self.value.fmt_imperial(f),
+        }
+    }
+}
+
+/// `value * num / den` rounded to the nearest integer
+fn scale(value: u16, num: u64, den: u64) -> u64 {
+    (value as u64 * num + den / 2) / den
+}
+
 impl fmt::Display for Millibar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} mbar", self.raw())
@@ -16,6 +57,35 @@ impl fmt::Display for Decibar {
     }
 }
 
+impl fmt::Display for Decimeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = self.raw();
+        write!(f, "{}.{} m", v / 10, v % 10)
+    }
+}
+
+impl DisplayUnits for Millibar {
+    fn fmt_imperial(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 1 mbar = 0.0145038 psi, printed to a tenth
+        let tenths = scale(self.raw(), 145_038, 1_000_000);
+        write!(f, "{}.{} psi", tenths / 10, tenths % 10)
+    }
+}
+
+impl DisplayUnits for Decibar {
+    fn fmt_imperial(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 1 dbar = 1.45038 psi
+        write!(f, "{} psi", scale(self.raw(), 145_038, 100_000))
+    }
+}
+
+impl DisplayUnits for Decimeter {
+    fn fmt_imperial(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 1 dm = 0.328084 ft
+        write!(f, "{} ft", scale(self.raw(), 328_084, 1_000_000))
+    }
+}
+
 impl fmt::Display for Millivolt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} mV", self.raw())
@@ -49,9 +119,38 @@ impl fmt::Display for PpO2Deci {
 }
 
 impl fmt::Display for Fo2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} FO₂", self.ratio())
+    }
+}
+
+impl fmt::Display for Ratio {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let v = self.raw();
-        write!(f, "0.{:02} FO₂", v) // if 99 means 0.99
+        write!(f, "{}.{:02}", v / 100, v % 100)
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.raw())
+    }
+}
+
+/// A per mille value shown as a percentage with one decimal, `-0.5 %` for
+/// -5. The `+` flag signs positive values too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permille(pub i64);
+
+impl fmt::Display for Permille {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.0 {
+            v if v < 0 => "-",
+            _ if f.sign_plus() => "+",
+            _ => "",
+        };
+        let v = self.0.unsigned_abs();
+        write!(f, "{}{}.{} %", sign, v / 10, v % 10)
     }
 }
 
@@ -61,3 +160,69 @@ impl fmt::Display for CentiMillivolt {
         write!(f, "{}.{:02} mV", v / 100, v % 100)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fo2_boundaries() {
+        assert_eq!(format!("{}", Fo2::new(0)), "0.00 FO₂");
+        assert_eq!(format!("{}", Fo2::new(21)), "0.21 FO₂");
+        assert_eq!(format!("{}", Fo2::new(99)), "0.99 FO₂");
+        assert_eq!(format!("{}", Fo2::new(100)), "1.00 FO₂");
+    }
+
+    #[test]
+    fn permille_as_percent() {
+        assert_eq!(format!("{:+}", Permille(-5)), "-0.5 %");
+        assert_eq!(format!("{:+}", Permille(5)), "+0.5 %");
+        assert_eq!(format!("{:+}", Permille(-101)), "-10.1 %");
+        assert_eq!(format!("{:+}", Permille(0)), "+0.0 %");
+        assert_eq!(format!("{}", Permille(50)), "5.0 %");
+    }
+
+    #[test]
+    fn imperial_units() {
+        let imperial = UnitsPreference::Imperial;
+        assert_eq!(
+            format!("{}", Millibar::new(1013).in_units(imperial)),
+            "14.7 psi"
+        );
+        assert_eq!(
+            format!("{}", Decibar::new(2000).in_units(imperial)),
+            "2901 psi"
+        );
+        assert_eq!(
+            format!("{}", Decimeter::new(300).in_units(imperial)),
+            "98 ft"
+        );
+        assert_eq!(
+            format!("{}", Millibar::new(1013).in_units(UnitsPreference::Metric)),
+            "1013 mbar"
+        );
+
+        let depth = Decimeter::from_pressures(Millibar::new(1013), Millibar::new(2263));
+        assert_eq!(format!("{}", depth), "12.5 m");
+        assert_eq!(
+            Decimeter::from_pressures(Millibar::new(1013), Millibar::new(1000)),
+            Decimeter::new(0)
+        );
+    }
+
+    #[test]
+    fn percent() {
+        assert_eq!(format!("{}", Percent::new(0)), "0%");
+        assert_eq!(format!("{}", Percent::new(100)), "100%");
+        assert_eq!(Fo2::new(100).percent(), Percent::new(100));
+    }
+
+    #[test]
+    fn ratio() {
+        assert_eq!(format!("{}", Ratio::new(5)), "0.05");
+        assert_eq!(format!("{}", Ratio::new(100)), "1.00");
+        assert_eq!(format!("{}", Ratio::new(255)), "2.55");
+        assert_eq!(Fo2::new(32).ratio(), Ratio::new(32));
+        assert_eq!(Ratio::new(32).percent(), Percent::new(32));
+    }
+}
@@ -0,0 +1,133 @@
+//! InfluxDB line protocol for decoded telemetry, one line per frame:
+//! `<measurement>,src=<node>[,iface=<bus>] <fields> <ns timestamp>`.
+//!
+//! Values are in display units (mV, bar, mbar, V, mA) so dashboards don't
+//! need to know the wire scaling.
+
+use std::format;
+use std::string::String;
+
+use crate::divecan::{DiveCanId, Msg};
+
+/// Line for `msg`, `None` for messages that aren't telemetry. `ts_ms` is
+/// milliseconds since the Unix epoch.
+pub fn line(ts_ms: u64, iface: Option<&str>, id: DiveCanId, msg: &Msg) -> Option<String> {
+    let (measurement, fields) = match msg {
+        Msg::CellVoltages { cell_voltages, .. } => (
+            "cell_voltage",
+            cell_fields(cell_voltages.iter().map(|v| f64::from(v.raw()) / 100.0)),
+        ),
+        Msg::CellPpo2(ppo2) => (
+            "cell_ppo2",
+            cell_fields(ppo2.iter().map(|v| f64::from(v.raw()) / 10.0)),
+        ),
+        Msg::SoloStatus {
+            voltage,
+            current,
+            injection_duration,
+            setpoint,
+            ..
+        } => (
+            "solo_status",
+            format!(
+                "voltage={},current={},injection_ms={},setpoint={}",
+                f64::from(voltage.raw()) / 10.0,
+                current.raw(),
+                injection_duration.raw(),
+                f64::from(setpoint.raw()) / 10.0
+            ),
+        ),
+        Msg::OboeStatus {
+            battery_voltage, ..
+        } => (
+            "battery",
+            format!("voltage={}", f64::from(battery_voltage.raw()) / 10.0),
+        ),
+        Msg::AmbientPressure {
+            surface, current, ..
+        } => (
+            "ambient_pressure",
+            format!("surface={},current={}", surface.raw(), current.raw()),
+        ),
+        Msg::TankPressure {
+            cylinder_index,
+            pressure,
+        } => (
+            "tank_pressure",
+            format!(
+                "cylinder={}i,pressure={}",
+                cylinder_index,
+                f64::from(pressure.raw()) / 10.0
+            ),
+        ),
+        Msg::Setpoint(setpoint) => (
+            "setpoint",
+            format!("ppo2={}", f64::from(setpoint.raw()) / 10.0),
+        ),
+        Msg::Co2 { pco2, .. } => ("co2", format!("pco2={}", pco2.raw())),
+        _ => return None,
+    };
+    let iface = iface.map_or(String::new(), |i| format!(",iface={}", escape_tag(i)));
+    Some(format!(
+        "{},src={:02x}{} {} {}",
+        measurement,
+        id.src,
+        iface,
+        fields,
+        ts_ms.saturating_mul(1_000_000)
+    ))
+}
+
+fn cell_fields(values: impl Iterator<Item = f64>) -> String {
+    values
+        .enumerate()
+        .map(|(i, v)| format!("cell{}={}", i + 1, v))
+        .collect::<std::vec::Vec<_>>()
+        .join(",")
+}
+
+/// Tag values can't hold unescaped commas, spaces or equals signs
+fn escape_tag(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | ' ' | '=') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cells::CellArray;
+    use crate::units::{CentiMillivolt, Decibar, PpO2Deci};
+
+    #[test]
+    fn telemetry_lines() {
+        let id = DiveCanId::new(0x04, 0xFF, 0x11);
+        let voltages = Msg::CellVoltages {
+            cell_voltages: CellArray([5524, 5000, 100].map(CentiMillivolt::new)),
+            unused: 0,
+        };
+        assert_eq!(
+            line(1_700_000_000_123, None, id, &voltages).unwrap(),
+            "cell_voltage,src=04 cell1=55.24,cell2=50,cell3=1 1700000000123000000"
+        );
+
+        let tank = Msg::TankPressure {
+            cylinder_index: 1,
+            pressure: Decibar::new(2005),
+        };
+        assert_eq!(
+            line(5, Some("can 0"), id, &tank).unwrap(),
+            "tank_pressure,src=04,iface=can\\ 0 cylinder=1i,pressure=200.5 5000000"
+        );
+        assert_eq!(
+            line(0, None, id, &Msg::Setpoint(PpO2Deci::new(13))).unwrap(),
+            "setpoint,src=04 ppo2=1.3 0"
+        );
+        assert_eq!(line(0, None, id, &Msg::Nop), None);
+    }
+}
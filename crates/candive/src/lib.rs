@@ -1,9 +1,38 @@
 #![cfg_attr(not(test), no_std)]
+// divecan, uds, cells, diag::did and diag::settings decode outside input, bus
+// frames, capture files and DID answers. They deny the panicking clippy lints
+// outside tests, so malformed input comes back as an error, not a panic.
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
 pub mod alerts;
+pub mod calibration;
+pub mod cells;
+pub mod coverage;
+pub mod crypto;
 #[cfg(feature = "diagnostics")]
 pub mod diag;
 pub mod divecan;
+#[cfg(feature = "sqlite")]
+pub mod fleet;
 pub mod fmt;
+#[cfg(feature = "std")]
+pub mod influx;
+pub mod monitor;
+pub mod poll;
+pub mod power;
+pub mod preflight;
+pub mod prelude;
+pub mod protocol;
+#[cfg(feature = "sqlite")]
+pub mod record;
+pub mod rng;
+pub mod stats;
+#[cfg(all(feature = "diagnostics", any(test, feature = "testing")))]
+pub mod testing;
+pub mod time;
 #[cfg(feature = "uds")]
 pub mod uds;
 pub mod units;
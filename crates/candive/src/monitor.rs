@@ -0,0 +1,656 @@
+use crate::cells::CellArray;
+use crate::divecan::{
+    Alert, CalStatusCode, CellsActive, DiveCanFrame, DiveCanId, DiveState, DiveTracker, DlcPolicy,
+    Msg,
+};
+use crate::units::{Fo2, Millibar, Millivolt, PpO2Deci};
+
+/// High-level bus events derived from raw DiveCAN traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// First frame seen from a node address
+    NodeAppeared {
+        node: u8,
+    },
+    SetpointChanged {
+        from: Option<PpO2Deci>,
+        to: PpO2Deci,
+    },
+    AlertRaised {
+        src: u8,
+        alert: Alert,
+    },
+    /// Alert has not been repeated for `EventConfig::alert_clear_ms`
+    AlertCleared {
+        src: u8,
+        code: u16,
+    },
+    /// Final `Ppo2CalibrationResponse`, paired with the request that
+    /// started it when that was seen
+    CalibrationCompleted {
+        src: u8,
+        /// Node that sent the `Ppo2CalibrationRequest`
+        requested_by: Option<u8>,
+        /// Time from the request to this response
+        elapsed_ms: Option<u64>,
+        status: CalStatusCode,
+        fo2: Fo2,
+        pressure: Millibar,
+        cell_voltages: CellArray<Millivolt>,
+        cells_active: CellsActive,
+    },
+    DiveStarted {
+        dive_number: u16,
+    },
+    DiveEnded {
+        dive_number: u16,
+    },
+    /// The CAN controller moved between error states, see [`BusError`]
+    BusStateChanged {
+        from: ControllerState,
+        to: ControllerState,
+    },
+    /// Our own transmission lost arbitration at `bit`
+    ArbitrationLost {
+        bit: u8,
+    },
+}
+
+impl Event {
+    /// snake_case name, as stored by the recorder and printed by monitors
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::NodeAppeared { .. } => "node_appeared",
+            Event::SetpointChanged { .. } => "setpoint_changed",
+            Event::AlertRaised { .. } => "alert_raised",
+            Event::AlertCleared { .. } => "alert_cleared",
+            Event::CalibrationCompleted { .. } => "calibration_completed",
+            Event::DiveStarted { .. } => "dive_started",
+            Event::DiveEnded { .. } => "dive_ended",
+            Event::BusStateChanged { .. } => "bus_state_changed",
+            Event::ArbitrationLost { .. } => "arbitration_lost",
+        }
+    }
+
+    /// Node the event is about, if it is tied to one
+    pub fn src(&self) -> Option<u8> {
+        match self {
+            Event::NodeAppeared { node } => Some(*node),
+            Event::AlertRaised { src, .. }
+            | Event::AlertCleared { src, .. }
+            | Event::CalibrationCompleted { src, .. } => Some(*src),
+            Event::SetpointChanged { .. }
+            | Event::DiveStarted { .. }
+            | Event::DiveEnded { .. }
+            | Event::BusStateChanged { .. }
+            | Event::ArbitrationLost { .. } => None,
+        }
+    }
+}
+
+/// CAN controller error state (ISO 11898-1 fault confinement)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControllerState {
+    #[default]
+    Active,
+    /// An error counter passed 96
+    Warning,
+    /// An error counter passed 127, the controller only sends passive error flags
+    Passive,
+    /// TX error counter passed 255, the controller is off the bus until restarted
+    BusOff,
+}
+
+/// A SocketCAN error frame, decoded as described in `linux/can/error.h`.
+/// Other CAN stacks can fill it in from their own status registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BusError {
+    /// Error class bits from the frame's CAN ID
+    pub class: u32,
+    /// Controller state the frame reports, if any
+    pub state: Option<ControllerState>,
+    /// Bit where our transmission lost arbitration
+    pub arbitration_lost_bit: Option<u8>,
+    /// TX and RX error counters, when the driver reports them
+    pub counters: Option<(u8, u8)>,
+}
+
+impl BusError {
+    /// Set in the CAN ID of SocketCAN error frames
+    pub const ERR_FLAG: u32 = 0x2000_0000;
+    pub const CLASS_TX_TIMEOUT: u32 = 0x001;
+    pub const CLASS_LOST_ARBITRATION: u32 = 0x002;
+    pub const CLASS_CONTROLLER: u32 = 0x004;
+    pub const CLASS_PROTOCOL: u32 = 0x008;
+    pub const CLASS_TRANSCEIVER: u32 = 0x010;
+    pub const CLASS_NO_ACK: u32 = 0x020;
+    pub const CLASS_BUS_OFF: u32 = 0x040;
+    pub const CLASS_BUS_ERROR: u32 = 0x080;
+    pub const CLASS_RESTARTED: u32 = 0x100;
+    pub const CLASS_COUNTERS: u32 = 0x200;
+
+    // Controller status bits in data[1]
+    const CTRL_WARNING: u8 = 0x04 | 0x08;
+    const CTRL_PASSIVE: u8 = 0x10 | 0x20;
+    const CTRL_ACTIVE: u8 = 0x40;
+
+    /// Decodes an error frame from its CAN ID (with or without
+    /// [`Self::ERR_FLAG`]) and up to 8 data bytes.
+    pub fn decode(can_id: u32, data: &[u8]) -> Self {
+        let class = can_id & 0x1FFF_FFFF & !Self::ERR_FLAG;
+        let byte = |i: usize| data.get(i).copied().unwrap_or(0);
+        let ctrl = byte(1);
+
+        let state = if class & Self::CLASS_BUS_OFF != 0 {
+            Some(ControllerState::BusOff)
+        } else if class & Self::CLASS_RESTARTED != 0 {
+            Some(ControllerState::Active)
+        } else if class & Self::CLASS_CONTROLLER == 0 {
+            None
+        } else if ctrl & Self::CTRL_PASSIVE != 0 {
+            Some(ControllerState::Passive)
+        } else if ctrl & Self::CTRL_WARNING != 0 {
+            Some(ControllerState::Warning)
+        } else if ctrl & Self::CTRL_ACTIVE != 0 {
+            Some(ControllerState::Active)
+        } else {
+            None
+        };
+
+        Self {
+            class,
+            state,
+            arbitration_lost_bit: (class & Self::CLASS_LOST_ARBITRATION != 0).then(|| byte(0)),
+            counters: (class & Self::CLASS_COUNTERS != 0).then(|| (byte(6), byte(7))),
+        }
+    }
+
+    /// CAN ID, with [`Self::ERR_FLAG`], and data of an error frame that
+    /// decodes back to this one, e.g. for writing it to a capture.
+    pub fn encode(&self) -> (u32, [u8; 8]) {
+        let mut data = [0u8; 8];
+        data[0] = self.arbitration_lost_bit.unwrap_or(0);
+        if self.class & Self::CLASS_CONTROLLER != 0 {
+            data[1] = match self.state {
+                Some(ControllerState::Passive) => Self::CTRL_PASSIVE,
+                Some(ControllerState::Warning) => Self::CTRL_WARNING,
+                Some(ControllerState::Active) => Self::CTRL_ACTIVE,
+                Some(ControllerState::BusOff) | None => 0,
+            };
+        }
+        if let Some((tx, rx)) = self.counters {
+            data[6] = tx;
+            data[7] = rx;
+        }
+        (self.class | Self::ERR_FLAG, data)
+    }
+
+    pub fn is_bus_off(&self) -> bool {
+        self.state == Some(ControllerState::BusOff)
+    }
+
+    /// The controller came back after a bus-off
+    pub fn is_restarted(&self) -> bool {
+        self.class & Self::CLASS_RESTARTED != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventConfig {
+    /// Time without a repeat before an active alert is reported as cleared
+    pub alert_clear_ms: u64,
+    /// A new setpoint must be broadcast this long before it is reported
+    pub setpoint_debounce_ms: u64,
+    /// Handling of frames shorter than their kind's minimum DLC
+    pub dlc_policy: DlcPolicy,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        Self {
+            alert_clear_ms: 5_000,
+            setpoint_debounce_ms: 0,
+            dlc_policy: DlcPolicy::Strict,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveAlert {
+    src: u8,
+    code: u16,
+    last_seen_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CalibrationRequest {
+    src: u8,
+    sent_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingSetpoint {
+    value: PpO2Deci,
+    since_ms: u64,
+}
+
+/// Turns a stream of frames into [`Event`]s, deduplicating the periodic
+/// re-broadcasts so consumers only see state changes.
+///
+/// Timestamps are caller-supplied monotonic milliseconds.
+pub struct EventStream {
+    config: EventConfig,
+    nodes_seen: [u32; 8],
+    setpoint: Option<PpO2Deci>,
+    pending_setpoint: Option<PendingSetpoint>,
+    alerts: [Option<ActiveAlert>; Self::MAX_ACTIVE_ALERTS],
+    dive: DiveTracker,
+    calibration: Option<CalibrationRequest>,
+    padded_frames: u32,
+    bus_state: ControllerState,
+}
+
+impl EventStream {
+    pub const MAX_ACTIVE_ALERTS: usize = 8;
+
+    pub fn new(config: EventConfig) -> Self {
+        Self {
+            config,
+            nodes_seen: [0; 8],
+            setpoint: None,
+            pending_setpoint: None,
+            alerts: [None; Self::MAX_ACTIVE_ALERTS],
+            dive: DiveTracker::new(),
+            calibration: None,
+            padded_frames: 0,
+            bus_state: ControllerState::Active,
+        }
+    }
+
+    /// Decodes a raw frame and feeds it to [`EventStream::on_msg`]. Frames
+    /// that fail to decode only contribute to node discovery.
+    pub fn on_frame(
+        &mut self,
+        now_ms: u64,
+        id: DiveCanId,
+        frame: &DiveCanFrame,
+        mut emit: impl FnMut(Event),
+    ) {
+        match Msg::try_from_frame_with(frame, self.config.dlc_policy) {
+            Ok(decoded) => {
+                if decoded.padded {
+                    self.padded_frames = self.padded_frames.saturating_add(1);
+                }
+                self.on_msg(now_ms, id, &decoded.msg, emit)
+            }
+            Err(_) => {
+                self.expire(now_ms, &mut emit);
+                self.see_node(id.src, &mut emit);
+            }
+        }
+    }
+
+    pub fn on_msg(&mut self, now_ms: u64, id: DiveCanId, msg: &Msg, mut emit: impl FnMut(Event)) {
+        self.expire(now_ms, &mut emit);
+        self.see_node(id.src, &mut emit);
+
+        match msg {
+            Msg::Setpoint(sp) => self.on_setpoint(now_ms, *sp, &mut emit),
+            Msg::Alert(alert) => self.on_alert(now_ms, id.src, alert, &mut emit),
+            Msg::Ppo2CalibrationRequest { .. } => {
+                self.calibration = Some(CalibrationRequest {
+                    src: id.src,
+                    sent_ms: now_ms,
+                })
+            }
+            Msg::Ppo2CalibrationResponse {
+                status,
+                cell_voltages,
+                fo2,
+                pressure,
+                cells_active,
+            } if status.is_final() => {
+                let request = self.calibration.take();
+                emit(Event::CalibrationCompleted {
+                    src: id.src,
+                    requested_by: request.map(|r| r.src),
+                    elapsed_ms: request.map(|r| now_ms.saturating_sub(r.sent_ms)),
+                    status: *status,
+                    fo2: *fo2,
+                    pressure: *pressure,
+                    cell_voltages: *cell_voltages,
+                    cells_active: *cells_active,
+                })
+            }
+            Msg::Diving {
+                status,
+                dive_number,
+                ..
+            } => self.on_diving(*status, *dive_number, &mut emit),
+            _ => {}
+        }
+    }
+
+    /// Feeds an error frame, reporting controller state changes and lost arbitration.
+    pub fn on_bus_error(&mut self, now_ms: u64, err: &BusError, mut emit: impl FnMut(Event)) {
+        self.expire(now_ms, &mut emit);
+
+        if let Some(to) = err.state
+            && to != self.bus_state
+        {
+            emit(Event::BusStateChanged {
+                from: self.bus_state,
+                to,
+            });
+            self.bus_state = to;
+        }
+        if let Some(bit) = err.arbitration_lost_bit {
+            emit(Event::ArbitrationLost { bit });
+        }
+    }
+
+    /// Reports alerts that have timed out. Call periodically when the bus is quiet.
+    pub fn expire(&mut self, now_ms: u64, mut emit: impl FnMut(Event)) {
+        let clear_ms = self.config.alert_clear_ms;
+        for slot in self.alerts.iter_mut() {
+            if let Some(active) = slot.filter(|a| now_ms.saturating_sub(a.last_seen_ms) >= clear_ms)
+            {
+                emit(Event::AlertCleared {
+                    src: active.src,
+                    code: active.code,
+                });
+                *slot = None;
+            }
+        }
+    }
+
+    pub fn setpoint(&self) -> Option<PpO2Deci> {
+        self.setpoint
+    }
+
+    pub fn dive_number(&self) -> Option<u16> {
+        self.dive.current()
+    }
+
+    /// Controller state from the last error frame that reported one
+    pub fn bus_state(&self) -> ControllerState {
+        self.bus_state
+    }
+
+    /// Short frames accepted under [`DlcPolicy::ZeroPad`]
+    pub fn padded_frames(&self) -> u32 {
+        self.padded_frames
+    }
+
+    fn see_node(&mut self, node: u8, emit: &mut impl FnMut(Event)) {
+        let word = &mut self.nodes_seen[(node >> 5) as usize];
+        let bit = 1u32 << (node & 0x1F);
+        if *word & bit == 0 {
+            *word |= bit;
+            emit(Event::NodeAppeared { node });
+        }
+    }
+
+    fn on_setpoint(&mut self, now_ms: u64, value: PpO2Deci, emit: &mut impl FnMut(Event)) {
+        if self.setpoint == Some(value) {
+            self.pending_setpoint = None;
+            return;
+        }
+
+        let since_ms = match self.pending_setpoint {
+            Some(p) if p.value == value => p.since_ms,
+            _ => now_ms,
+        };
+
+        if now_ms.saturating_sub(since_ms) >= self.config.setpoint_debounce_ms {
+            emit(Event::SetpointChanged {
+                from: self.setpoint,
+                to: value,
+            });
+            self.setpoint = Some(value);
+            self.pending_setpoint = None;
+        } else {
+            self.pending_setpoint = Some(PendingSetpoint { value, since_ms });
+        }
+    }
+
+    fn on_alert(&mut self, now_ms: u64, src: u8, alert: &Alert, emit: &mut impl FnMut(Event)) {
+        if let Some(active) = self
+            .alerts
+            .iter_mut()
+            .flatten()
+            .find(|a| a.src == src && a.code == alert.code)
+        {
+            active.last_seen_ms = now_ms;
+            return;
+        }
+
+        emit(Event::AlertRaised { src, alert: *alert });
+
+        // When all slots are busy the alert is still reported, just not tracked for clearing.
+        if let Some(slot) = self.alerts.iter_mut().find(|a| a.is_none()) {
+            *slot = Some(ActiveAlert {
+                src,
+                code: alert.code,
+                last_seen_ms: now_ms,
+            });
+        }
+    }
+
+    fn on_diving(&mut self, state: DiveState, dive_number: u16, emit: &mut impl FnMut(Event)) {
+        let Some(transition) = self.dive.on_diving(state, dive_number) else {
+            return;
+        };
+        if let Some(dive_number) = transition.ended() {
+            emit(Event::DiveEnded { dive_number });
+        }
+        if let Some(dive_number) = transition.started() {
+            emit(Event::DiveStarted { dive_number });
+        }
+    }
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self::new(EventConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(stream: &mut EventStream, now_ms: u64, src: u8, msg: Msg) -> Vec<Event> {
+        let mut events = Vec::new();
+        let id = DiveCanId::new(src, 0xFF, msg.kind());
+        stream.on_msg(now_ms, id, &msg, |e| events.push(e));
+        events
+    }
+
+    #[test]
+    fn setpoint_reported_once_per_change() {
+        let mut s = EventStream::default();
+        assert_eq!(
+            collect(&mut s, 0, 1, Msg::Setpoint(7.into())),
+            vec![
+                Event::NodeAppeared { node: 1 },
+                Event::SetpointChanged {
+                    from: None,
+                    to: 7.into()
+                }
+            ]
+        );
+        assert!(collect(&mut s, 100, 1, Msg::Setpoint(7.into())).is_empty());
+        assert_eq!(
+            collect(&mut s, 200, 1, Msg::Setpoint(12.into())),
+            vec![Event::SetpointChanged {
+                from: Some(7.into()),
+                to: 12.into()
+            }]
+        );
+    }
+
+    #[test]
+    fn setpoint_debounce() {
+        let mut s = EventStream::new(EventConfig {
+            setpoint_debounce_ms: 500,
+            ..EventConfig::default()
+        });
+        collect(&mut s, 0, 1, Msg::Setpoint(7.into()));
+        assert!(collect(&mut s, 100, 1, Msg::Setpoint(12.into())).is_empty());
+        assert!(collect(&mut s, 400, 1, Msg::Setpoint(12.into())).is_empty());
+        assert_eq!(
+            collect(&mut s, 600, 1, Msg::Setpoint(12.into())),
+            vec![Event::SetpointChanged {
+                from: None,
+                to: 12.into()
+            }]
+        );
+    }
+
+    #[test]
+    fn calibration_paired_with_request() {
+        let mut s = EventStream::default();
+        let response = |status| Msg::Ppo2CalibrationResponse {
+            status,
+            cell_voltages: CellArray::new([45.into(), 46.into(), 44.into()]),
+            fo2: 98.into(),
+            pressure: 1013.into(),
+            cells_active: CellsActive::new([true, true, true]),
+        };
+        let request = Msg::Ppo2CalibrationRequest {
+            fo2: 98.into(),
+            pressure: 1013.into(),
+        };
+
+        collect(&mut s, 0, 4, response(CalStatusCode::Success));
+        collect(&mut s, 0, 1, request);
+        let events = collect(&mut s, 1_000, 4, response(CalStatusCode::Ack));
+        assert!(events.is_empty());
+        let events = collect(&mut s, 3_500, 4, response(CalStatusCode::Success));
+        assert_eq!(
+            events,
+            vec![Event::CalibrationCompleted {
+                src: 4,
+                requested_by: Some(1),
+                elapsed_ms: Some(3_500),
+                status: CalStatusCode::Success,
+                fo2: 98.into(),
+                pressure: 1013.into(),
+                cell_voltages: CellArray::new([45.into(), 46.into(), 44.into()]),
+                cells_active: CellsActive::new([true, true, true]),
+            }]
+        );
+
+        // A result without a request in the capture has no timing
+        let events = collect(&mut s, 9_000, 4, response(CalStatusCode::Fo2RangeError));
+        assert!(matches!(
+            events[..],
+            [Event::CalibrationCompleted {
+                requested_by: None,
+                elapsed_ms: None,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn alert_raised_then_cleared() {
+        let mut s = EventStream::default();
+        let alert = Alert::new(1, 0x101, &[]).unwrap();
+
+        let events = collect(&mut s, 0, 4, Msg::Alert(alert));
+        assert_eq!(events[1], Event::AlertRaised { src: 4, alert });
+        assert!(collect(&mut s, 1_000, 4, Msg::Alert(alert)).is_empty());
+
+        let mut events = Vec::new();
+        s.expire(5_500, |e| events.push(e));
+        assert!(events.is_empty());
+        s.expire(6_000, |e| events.push(e));
+        assert_eq!(
+            events,
+            vec![Event::AlertCleared {
+                src: 4,
+                code: 0x101
+            }]
+        );
+    }
+
+    #[test]
+    fn bus_errors() {
+        let mut s = EventStream::default();
+        let mut events = Vec::new();
+
+        let passive = BusError::decode(
+            BusError::ERR_FLAG | BusError::CLASS_CONTROLLER | BusError::CLASS_COUNTERS,
+            &[0, 0x20, 0, 0, 0, 0, 130, 4],
+        );
+        assert_eq!(passive.state, Some(ControllerState::Passive));
+        assert_eq!(passive.counters, Some((130, 4)));
+        s.on_bus_error(0, &passive, |e| events.push(e));
+        s.on_bus_error(1, &passive, |e| events.push(e));
+
+        let bus_off = BusError::decode(BusError::CLASS_BUS_OFF, &[]);
+        assert!(bus_off.is_bus_off());
+        s.on_bus_error(2, &bus_off, |e| events.push(e));
+
+        let restarted = BusError::decode(BusError::CLASS_RESTARTED, &[0; 8]);
+        assert!(restarted.is_restarted());
+        s.on_bus_error(3, &restarted, |e| events.push(e));
+
+        let lost = BusError::decode(BusError::CLASS_LOST_ARBITRATION, &[11, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(lost.state, None);
+        s.on_bus_error(4, &lost, |e| events.push(e));
+
+        for err in [passive, bus_off, restarted, lost] {
+            let (id, data) = err.encode();
+            assert_eq!(BusError::decode(id, &data), err);
+        }
+
+        use ControllerState::*;
+        assert_eq!(
+            events,
+            vec![
+                Event::BusStateChanged {
+                    from: Active,
+                    to: Passive
+                },
+                Event::BusStateChanged {
+                    from: Passive,
+                    to: BusOff
+                },
+                Event::BusStateChanged {
+                    from: BusOff,
+                    to: Active
+                },
+                Event::ArbitrationLost { bit: 11 },
+            ]
+        );
+        assert_eq!(s.bus_state(), Active);
+    }
+
+    #[test]
+    fn dive_start_and_end() {
+        let mut s = EventStream::default();
+        let diving = |status, dive_number| Msg::Diving {
+            status: DiveState::from_u8(status),
+            dive_number,
+            timestamp: 0,
+        };
+
+        assert!(collect(&mut s, 0, 1, diving(0, 41)).contains(&Event::NodeAppeared { node: 1 }));
+        assert_eq!(
+            collect(&mut s, 1, 1, diving(1, 42)),
+            vec![Event::DiveStarted { dive_number: 42 }]
+        );
+        assert!(collect(&mut s, 2, 1, diving(1, 42)).is_empty());
+        assert_eq!(
+            collect(&mut s, 3, 1, diving(0, 42)),
+            vec![Event::DiveEnded { dive_number: 42 }]
+        );
+    }
+}
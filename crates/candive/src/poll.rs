@@ -0,0 +1,169 @@
+//! Waiting for a device to reach a state (calibration settled, bootloader
+//! ready, transfer complete) by reading it again until it does, with one
+//! timeout error for all of them instead of a sleep loop per workflow.
+
+use crate::rng::Xoshiro128;
+use crate::time::{Clock, Delay};
+
+/// How often and how long [`poll_until`] reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PollConfig {
+    pub interval_ms: u64,
+    /// Counted from the first read. The last wait is cut short to end on it.
+    pub timeout_ms: u64,
+    /// Up to this much is added to or taken off each interval, so several
+    /// tools polling one bus don't stay in step
+    pub jitter_ms: u16,
+    /// Seed of the jitter, the same seed waits the same intervals
+    pub seed: u64,
+}
+
+impl PollConfig {
+    pub const fn new(interval_ms: u64, timeout_ms: u64) -> Self {
+        Self {
+            interval_ms,
+            timeout_ms,
+            jitter_ms: 0,
+            seed: 0,
+        }
+    }
+
+    pub const fn with_jitter(mut self, jitter_ms: u16, seed: u64) -> Self {
+        self.jitter_ms = jitter_ms;
+        self.seed = seed;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PollError<E, V> {
+    /// `read` failed, polling stopped there
+    Read(E),
+    /// The state wasn't reached. `last` is the last value read.
+    Timeout {
+        polls: u32,
+        elapsed_ms: u64,
+        last: V,
+    },
+}
+
+/// Reads with `read` until `done` accepts a value and returns that value.
+/// The first read is immediate, the next ones [`PollConfig::interval_ms`]
+/// apart, waited out on `clock`.
+pub fn poll_until<T, V, E, C: Clock + Delay>(
+    transport: &mut T,
+    mut read: impl FnMut(&mut T) -> Result<V, E>,
+    mut done: impl FnMut(&V) -> bool,
+    config: &PollConfig,
+    clock: &C,
+) -> Result<V, PollError<E, V>> {
+    let mut rng = Xoshiro128::from_seed(config.seed);
+    let start = clock.now();
+    let mut polls = 0u32;
+    loop {
+        let value = read(transport).map_err(PollError::Read)?;
+        polls = polls.saturating_add(1);
+        if done(&value) {
+            return Ok(value);
+        }
+
+        let elapsed_ms = clock.elapsed(start);
+        let remaining = config.timeout_ms.saturating_sub(elapsed_ms);
+        if remaining == 0 {
+            return Err(PollError::Timeout {
+                polls,
+                elapsed_ms,
+                last: value,
+            });
+        }
+        let jitter = i64::from(rng.jitter(config.jitter_ms));
+        let interval = config.interval_ms.saturating_add_signed(jitter);
+        clock.delay_ms(interval.min(remaining));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::ManualClock;
+
+    /// Counts reads, erroring on read `fail_at`
+    struct Counter {
+        reads: u32,
+        fail_at: Option<u32>,
+    }
+
+    fn read(c: &mut Counter) -> Result<u32, &'static str> {
+        c.reads += 1;
+        if Some(c.reads) == c.fail_at {
+            return Err("gone");
+        }
+        Ok(c.reads)
+    }
+
+    #[test]
+    fn polls_until_done_or_timeout() {
+        let clock = ManualClock::default();
+        let config = PollConfig::new(500, 2000);
+        let mut counter = Counter {
+            reads: 0,
+            fail_at: None,
+        };
+        assert_eq!(
+            poll_until(&mut counter, read, |&n| n == 3, &config, &clock),
+            Ok(3)
+        );
+        assert_eq!(clock.now().as_millis(), 1000);
+
+        // Reads at 0, 500, ..., 2000 and gives up on the fifth
+        let clock = ManualClock::default();
+        let mut counter = Counter {
+            reads: 0,
+            fail_at: None,
+        };
+        assert_eq!(
+            poll_until(&mut counter, read, |_| false, &config, &clock),
+            Err(PollError::Timeout {
+                polls: 5,
+                elapsed_ms: 2000,
+                last: 5
+            })
+        );
+
+        let mut counter = Counter {
+            reads: 0,
+            fail_at: Some(2),
+        };
+        assert_eq!(
+            poll_until(&mut counter, read, |_| false, &config, &clock),
+            Err(PollError::Read("gone"))
+        );
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        let config = PollConfig::new(500, 10_000).with_jitter(100, 7);
+        let clock = ManualClock::default();
+        let mut last = 0;
+        let mut counter = Counter {
+            reads: 0,
+            fail_at: None,
+        };
+        let _ = poll_until(
+            &mut counter,
+            read,
+            |_| {
+                let now = clock.now().as_millis();
+                let waited = now - last;
+                last = now;
+                assert!(now == 0 || (400..=600).contains(&waited) || now == 10_000);
+                false
+            },
+            &config,
+            &clock,
+        );
+        assert!(counter.reads > 10);
+    }
+}
@@ -0,0 +1,279 @@
+use core::ops::RangeInclusive;
+
+use crate::divecan::Msg;
+use crate::stats::MinMax;
+use crate::units::{Decivolt, Milliamp};
+
+/// Settable solenoid minimum current (mA), 4 bits in 10 mA steps from 50
+pub const SOLENOID_MIN_CURRENT_MA: RangeInclusive<u16> = 50..=200;
+/// Settable solenoid maximum current (mA), 5 bits in 10 mA steps from 50
+pub const SOLENOID_MAX_CURRENT_MA: RangeInclusive<u16> = 50..=360;
+/// Settable battery minimum voltage (dV), 4 bits in 0.2 V steps from 5.0 V.
+/// Doubled when voltage doubling is on.
+pub const BATTERY_MIN_VOLTAGE_DV: RangeInclusive<u16> = 50..=80;
+
+/// Power related part of the Solo control configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerLimits {
+    pub solenoid_current_min_ma: u16,
+    pub solenoid_current_max_ma: u16,
+    /// Battery minimum voltage in dV, already doubled if `voltage_doubling`
+    pub battery_voltage_min_dv: u16,
+    pub voltage_doubling: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerIssue {
+    CurrentMinOutOfRange(u16),
+    CurrentMaxOutOfRange(u16),
+    CurrentLimitsInverted {
+        min_ma: u16,
+        max_ma: u16,
+    },
+    BatteryMinOutOfRange(u16),
+    /// Peak draw while firing below the configured minimum
+    DrawBelowMin {
+        observed_ma: u16,
+        min_ma: u16,
+    },
+    /// Peak draw while firing above the configured maximum
+    DrawAboveMax {
+        observed_ma: u16,
+        max_ma: u16,
+    },
+    BatteryBelowMin {
+        observed_dv: u16,
+        min_dv: u16,
+    },
+}
+
+/// Checks the limits can be stored as given and make sense together.
+pub fn validate_power_limits(limits: &PowerLimits) -> Result<(), PowerIssue> {
+    let min = limits.solenoid_current_min_ma;
+    let max = limits.solenoid_current_max_ma;
+    if !SOLENOID_MIN_CURRENT_MA.contains(&min) || !min.is_multiple_of(10) {
+        return Err(PowerIssue::CurrentMinOutOfRange(min));
+    }
+    if !SOLENOID_MAX_CURRENT_MA.contains(&max) || !max.is_multiple_of(10) {
+        return Err(PowerIssue::CurrentMaxOutOfRange(max));
+    }
+    if min >= max {
+        return Err(PowerIssue::CurrentLimitsInverted {
+            min_ma: min,
+            max_ma: max,
+        });
+    }
+
+    let battery = limits.battery_voltage_min_dv;
+    let (battery, halved_exactly) = if limits.voltage_doubling {
+        (battery / 2, battery.is_multiple_of(2))
+    } else {
+        (battery, true)
+    };
+    // 0.2 V steps from the start of the range
+    if !halved_exactly
+        || !BATTERY_MIN_VOLTAGE_DV.contains(&battery)
+        || !(battery - BATTERY_MIN_VOLTAGE_DV.start()).is_multiple_of(2)
+    {
+        return Err(PowerIssue::BatteryMinOutOfRange(
+            limits.battery_voltage_min_dv,
+        ));
+    }
+    Ok(())
+}
+
+/// Summary of `SoloStatus` broadcasts seen on the bus.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PowerStats {
+    samples: u32,
+    firing_samples: u32,
+    voltage: MinMax<Decivolt>,
+    firing_current: MinMax<Milliamp>,
+}
+
+impl PowerStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a decoded message, returns true if it was a `SoloStatus` sample.
+    /// Current is only tracked while the solenoid is firing.
+    pub fn push(&mut self, msg: &Msg) -> bool {
+        let Msg::SoloStatus {
+            voltage,
+            current,
+            injection_duration,
+            ..
+        } = msg
+        else {
+            return false;
+        };
+
+        self.samples += 1;
+        self.voltage.push(*voltage);
+
+        if injection_duration.raw() > 0 {
+            self.firing_samples += 1;
+            self.firing_current.push(*current);
+        }
+        true
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn firing_samples(&self) -> u32 {
+        self.firing_samples
+    }
+
+    pub fn voltage_range(&self) -> Option<(Decivolt, Decivolt)> {
+        self.voltage.range()
+    }
+
+    pub fn firing_current_range(&self) -> Option<(Milliamp, Milliamp)> {
+        self.firing_current.range()
+    }
+}
+
+/// Reports every problem with the configured limits and, if given, with the
+/// observed draw and battery voltage against them.
+pub fn check_power(
+    limits: &PowerLimits,
+    stats: Option<&PowerStats>,
+    mut report: impl FnMut(PowerIssue),
+) {
+    let min_ma = limits.solenoid_current_min_ma;
+    let max_ma = limits.solenoid_current_max_ma;
+
+    if let Err(issue) = validate_power_limits(limits) {
+        report(issue);
+    }
+
+    let Some(stats) = stats else {
+        return;
+    };
+
+    if let Some((low, high)) = stats.firing_current_range() {
+        if low.raw() < min_ma {
+            report(PowerIssue::DrawBelowMin {
+                observed_ma: low.raw(),
+                min_ma,
+            });
+        }
+        if high.raw() > max_ma {
+            report(PowerIssue::DrawAboveMax {
+                observed_ma: high.raw(),
+                max_ma,
+            });
+        }
+    }
+
+    if let Some((low, _)) = stats.voltage_range()
+        && (low.raw() as u16) < limits.battery_voltage_min_dv
+    {
+        report(PowerIssue::BatteryBelowMin {
+            observed_dv: low.raw() as u16,
+            min_dv: limits.battery_voltage_min_dv,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::divecan::Consensus;
+
+    const LIMITS: PowerLimits = PowerLimits {
+        solenoid_current_min_ma: 70,
+        solenoid_current_max_ma: 180,
+        battery_voltage_min_dv: 60,
+        voltage_doubling: false,
+    };
+
+    fn status(voltage: u8, current: u16, injection_ms: u16) -> Msg {
+        Msg::SoloStatus {
+            voltage: voltage.into(),
+            current: current.into(),
+            injection_duration: injection_ms.into(),
+            setpoint: 13.into(),
+            consensus: Consensus::from_u8(0),
+            voltage_alert: None,
+            current_alert: None,
+        }
+    }
+
+    #[test]
+    fn power_limits() {
+        assert_eq!(validate_power_limits(&LIMITS), Ok(()));
+        assert_eq!(
+            validate_power_limits(&PowerLimits {
+                solenoid_current_min_ma: 200,
+                ..LIMITS
+            }),
+            Err(PowerIssue::CurrentLimitsInverted {
+                min_ma: 200,
+                max_ma: 180
+            })
+        );
+        assert_eq!(
+            validate_power_limits(&PowerLimits {
+                battery_voltage_min_dv: 120,
+                voltage_doubling: true,
+                ..LIMITS
+            }),
+            Ok(())
+        );
+        assert_eq!(
+            validate_power_limits(&PowerLimits {
+                battery_voltage_min_dv: 90,
+                ..LIMITS
+            }),
+            Err(PowerIssue::BatteryMinOutOfRange(90))
+        );
+        for (dv, doubling) in [(61, false), (122, true), (121, true)] {
+            assert_eq!(
+                validate_power_limits(&PowerLimits {
+                    battery_voltage_min_dv: dv,
+                    voltage_doubling: doubling,
+                    ..LIMITS
+                }),
+                Err(PowerIssue::BatteryMinOutOfRange(dv))
+            );
+        }
+    }
+
+    #[test]
+    fn observed_draw() {
+        let mut stats = PowerStats::new();
+        assert!(stats.push(&status(72, 5, 0)));
+        assert!(stats.push(&status(58, 190, 40)));
+        assert!(stats.push(&status(61, 120, 40)));
+        assert!(!stats.push(&Msg::Nop));
+        assert_eq!((stats.samples(), stats.firing_samples()), (3, 2));
+
+        let mut issues = [None; 4];
+        let mut n = 0;
+        check_power(&LIMITS, Some(&stats), |issue| {
+            issues[n] = Some(issue);
+            n += 1;
+        });
+        assert_eq!(
+            issues,
+            [
+                Some(PowerIssue::DrawAboveMax {
+                    observed_ma: 190,
+                    max_ma: 180
+                }),
+                Some(PowerIssue::BatteryBelowMin {
+                    observed_dv: 58,
+                    min_dv: 60
+                }),
+                None,
+                None,
+            ]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Checks before reprogramming a device. A firmware download that stops
+//! halfway, for example because the battery sagged under the flash write
+//! load, leaves the Solo without bootable firmware.
+
+use crate::divecan::{Msg, VoltageAlert};
+use crate::power::{PowerLimits, PowerStats};
+
+/// Default battery voltage (dV) required above the configured minimum
+pub const BATTERY_MARGIN_DV: u16 = 5;
+/// Default largest battery voltage swing (dV) seen while watching that
+/// still counts as stable. `SoloStatus` reports whole decivolts, so a steady
+/// battery already reads a step either way of its voltage; one more step
+/// allows for a solenoid firing while watching.
+pub const BATTERY_RIPPLE_MAX_DV: u16 = 3;
+
+/// How much room [`check_for_programming`] wants between the battery and
+/// trouble
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProgrammingMargins {
+    /// Battery voltage (dV) required above the configured minimum
+    pub battery_margin_dv: u16,
+    /// Largest battery voltage swing (dV) that still counts as stable
+    pub ripple_max_dv: u16,
+}
+
+impl ProgrammingMargins {
+    pub const DEFAULT: Self = Self {
+        battery_margin_dv: BATTERY_MARGIN_DV,
+        ripple_max_dv: BATTERY_RIPPLE_MAX_DV,
+    };
+}
+
+impl Default for ProgrammingMargins {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PreflightIssue {
+    /// No `SoloStatus` seen, the battery voltage is unknown
+    NoStatus,
+    /// Lowest battery voltage is below the configured minimum plus
+    /// [`ProgrammingMargins::battery_margin_dv`]
+    BatteryLow { observed_dv: u16, required_dv: u16 },
+    /// Battery voltage moved more than [`ProgrammingMargins::ripple_max_dv`]
+    BatteryUnstable { min_dv: u16, max_dv: u16 },
+    /// The Solo flagged the battery voltage in a `SoloStatus`
+    VoltageAlert(VoltageAlert),
+    /// An alert was broadcast while watching
+    ActiveAlert(u16),
+}
+
+/// What the bus showed while watching before programming.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProgrammingWatch {
+    power: PowerStats,
+    voltage_alert: Option<VoltageAlert>,
+    alerts: [u16; Self::MAX_ALERTS],
+    alert_count: usize,
+}
+
+impl ProgrammingWatch {
+    /// Distinct alert codes kept, later ones are dropped
+    pub const MAX_ALERTS: usize = 8;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a decoded message, returns true if it was a `SoloStatus` or
+    /// an `Alert`.
+    pub fn push(&mut self, msg: &Msg) -> bool {
+        match msg {
+            Msg::SoloStatus { voltage_alert, .. } => {
+                self.power.push(msg);
+                if let Some(alert @ (VoltageAlert::UnderVoltage | VoltageAlert::OverVoltage)) =
+                    voltage_alert
+                {
+                    self.voltage_alert = Some(*alert);
+                }
+                true
+            }
+            Msg::Alert(alert) => {
+                let seen = &self.alerts[..self.alert_count];
+                if !seen.contains(&alert.code) && self.alert_count < Self::MAX_ALERTS {
+                    self.alerts[self.alert_count] = alert.code;
+                    self.alert_count += 1;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn power(&self) -> &PowerStats {
+        &self.power
+    }
+
+    /// Alert codes seen, in order of first appearance
+    pub fn alerts(&self) -> &[u16] {
+        &self.alerts[..self.alert_count]
+    }
+}
+
+/// Reports every reason not to start a firmware download: a battery that
+/// is unknown, too close to `limits.battery_voltage_min_dv` or unsteady,
+/// and alerts the device raised while watching. No report means it's safe
+/// to program.
+pub fn check_for_programming(
+    limits: &PowerLimits,
+    margins: &ProgrammingMargins,
+    watch: &ProgrammingWatch,
+    mut report: impl FnMut(PreflightIssue),
+) {
+    match watch.power.voltage_range() {
+        None => report(PreflightIssue::NoStatus),
+        Some((low, high)) => {
+            let (low, high) = (low.raw() as u16, high.raw() as u16);
+            let required_dv = limits.battery_voltage_min_dv + margins.battery_margin_dv;
+            if low < required_dv {
+                report(PreflightIssue::BatteryLow {
+                    observed_dv: low,
+                    required_dv,
+                });
+            }
+            if high - low > margins.ripple_max_dv {
+                report(PreflightIssue::BatteryUnstable {
+                    min_dv: low,
+                    max_dv: high,
+                });
+            }
+        }
+    }
+    if let Some(alert) = watch.voltage_alert {
+        report(PreflightIssue::VoltageAlert(alert));
+    }
+    for &code in watch.alerts() {
+        report(PreflightIssue::ActiveAlert(code));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::divecan::{Alert, Consensus};
+
+    const LIMITS: PowerLimits = PowerLimits {
+        solenoid_current_min_ma: 70,
+        solenoid_current_max_ma: 180,
+        battery_voltage_min_dv: 60,
+        voltage_doubling: false,
+    };
+
+    fn status(voltage: u8, voltage_alert: Option<VoltageAlert>) -> Msg {
+        Msg::SoloStatus {
+            voltage: voltage.into(),
+            current: 5.into(),
+            injection_duration: 0.into(),
+            setpoint: 13.into(),
+            consensus: Consensus::from_u8(0),
+            voltage_alert,
+            current_alert: None,
+        }
+    }
+
+    fn issues(watch: &ProgrammingWatch) -> Vec<PreflightIssue> {
+        let mut issues = Vec::new();
+        check_for_programming(&LIMITS, &ProgrammingMargins::DEFAULT, watch, |issue| {
+            issues.push(issue)
+        });
+        issues
+    }
+
+    #[test]
+    fn programming_checks() {
+        let mut watch = ProgrammingWatch::new();
+        assert_eq!(issues(&watch), [PreflightIssue::NoStatus]);
+
+        assert!(watch.push(&status(72, None)));
+        assert!(watch.push(&status(71, Some(VoltageAlert::Clear))));
+        assert!(!watch.push(&Msg::Nop));
+        assert_eq!(issues(&watch), []);
+
+        // 6.4 V is above the 6.0 V minimum but inside the margin
+        watch.push(&status(64, Some(VoltageAlert::UnderVoltage)));
+        let alert = Alert::new(1, 0x403, &[]).unwrap();
+        assert!(watch.push(&Msg::Alert(alert)));
+        assert!(watch.push(&Msg::Alert(alert)));
+        assert_eq!(watch.alerts(), [0x403]);
+        assert_eq!(
+            issues(&watch),
+            [
+                PreflightIssue::BatteryLow {
+                    observed_dv: 64,
+                    required_dv: 65
+                },
+                PreflightIssue::BatteryUnstable {
+                    min_dv: 64,
+                    max_dv: 72
+                },
+                PreflightIssue::VoltageAlert(VoltageAlert::UnderVoltage),
+                PreflightIssue::ActiveAlert(0x403),
+            ]
+        );
+
+        let mut steady = ProgrammingWatch::new();
+        steady.push(&status(72, None));
+        steady.push(&status(67, None));
+        let mut issues = Vec::new();
+        let loose = ProgrammingMargins {
+            ripple_max_dv: 5,
+            ..ProgrammingMargins::DEFAULT
+        };
+        check_for_programming(&LIMITS, &loose, &steady, |issue| issues.push(issue));
+        assert_eq!(issues, []);
+    }
+}
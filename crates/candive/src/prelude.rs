@@ -0,0 +1,17 @@
+//! The traits and types most code needs, as `use candive::prelude::*`.
+//! Only what is stable across reorganizations goes here. Names from
+//! features that are off are left out.
+
+pub use crate::cells::CellArray;
+pub use crate::divecan::{BusTraffic, DiveCanFrame, DiveCanId, DlcPolicy, Msg};
+pub use crate::time::{Clock, Instant};
+pub use crate::units::{
+    CentiMillivolt, Decibar, Decimeter, Decivolt, Fo2, Milliamp, Millibar, Millisecond, Millivolt,
+    Percent, PpO2Deci,
+};
+
+#[cfg(feature = "diagnostics")]
+pub use crate::diag::did::{DataIdentifier, DidDecodeError, ReadableDid, WritableDid};
+
+#[cfg(feature = "uds")]
+pub use crate::uds::{NegativeResponse, UdsClientError, UdsErrorCode, UdsTransport};
@@ -0,0 +1,168 @@
+//! Protocol surface generated from `protocol.toml` by the build script:
+//! the [`Msg`] enum, message kind and DID constants, their tables, the
+//! Wireshark dissector and the DBC export. The build fails if a message has
+//! no codec in [`crate::divecan`] or a DID's length disagrees with
+//! `diag::did`.
+
+use crate::divecan::{Msg, MsgCategory, MsgDirection};
+
+/// One decoded field of a message, for the dissector and DBC exports
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SignalDef {
+    pub name: &'static str,
+    /// Offset of the first (most significant) byte
+    pub byte: u8,
+    /// 8 or 16, multi-byte signals are big endian
+    pub bits: u8,
+    /// Physical value per raw count
+    pub scale: f32,
+    pub unit: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MessageDef {
+    pub kind: u8,
+    pub name: &'static str,
+    pub min_dlc: u8,
+    pub description: &'static str,
+    pub category: MsgCategory,
+    pub direction: MsgDirection,
+    pub signals: &'static [SignalDef],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DidAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// How long a DID takes to answer, for transports picking a deadline per
+/// request. Capability queries and the firmware CRC are computed on the
+/// device and answer seconds later than identity DIDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DidTimeout {
+    #[default]
+    Normal,
+    Slow,
+}
+
+impl DidTimeout {
+    /// Class of `did`, [`DidTimeout::Normal`] for DIDs not in the table
+    pub fn of_did(did: u16) -> Self {
+        did_def(did).map_or(DidTimeout::Normal, |d| d.timeout)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DidDef {
+    pub did: u16,
+    pub name: &'static str,
+    pub len: usize,
+    pub access: DidAccess,
+    /// First firmware version that has it, `None` when all known versions do
+    pub since: Option<&'static str>,
+    pub timeout: DidTimeout,
+    pub description: &'static str,
+}
+
+include!(concat!(env!("OUT_DIR"), "/protocol.rs"));
+
+/// Wireshark Lua dissector, heuristic on CAN frames with the DiveCAN id prefix
+pub const WIRESHARK_DISSECTOR: &str = include_str!(concat!(env!("OUT_DIR"), "/divecan.lua"));
+/// DBC database for the extended DiveCAN ids with src = dst = 0
+pub const DBC: &str = include_str!(concat!(env!("OUT_DIR"), "/divecan.dbc"));
+
+pub fn message(kind: u8) -> Option<&'static MessageDef> {
+    MESSAGES.iter().find(|m| m.kind == kind)
+}
+
+/// Declared length of `did`'s data. Fails the build when used in a constant
+/// for a DID missing from `protocol.toml`.
+pub const fn did_len(did: u16) -> usize {
+    let mut i = 0;
+    while i < DIDS.len() {
+        if DIDS[i].did == did {
+            return DIDS[i].len;
+        }
+        i += 1;
+    }
+    panic!("DID missing from protocol.toml");
+}
+
+pub fn did_def(did: u16) -> Option<&'static DidDef> {
+    DIDS.iter().find(|d| d.did == did)
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// Msg::KINDS follows the codec table, which lists the messages in table order
+const _: () = {
+    assert!(Msg::KINDS.len() == MESSAGES.len());
+    let mut i = 0;
+    while i < MESSAGES.len() {
+        let (info, def) = (&Msg::KINDS[i], &MESSAGES[i]);
+        assert!(info.kind == def.kind && info.min_dlc == def.min_dlc);
+        assert!(str_eq(info.name, def.name));
+        i += 1;
+    }
+};
+
+/// Fails the build when `T`'s encoded length differs from its table entry.
+#[cfg(feature = "diagnostics")]
+const fn check_did<T: crate::diag::did::DataIdentifier>() {
+    let mut i = 0;
+    while i < DIDS.len() {
+        if DIDS[i].did == T::DID {
+            assert!(core::mem::size_of::<T::Bytes>() == DIDS[i].len);
+            return;
+        }
+        i += 1;
+    }
+    panic!("DID missing from protocol.toml");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_and_exports() {
+        assert_eq!(kind::CELL_PPO2, 0x04);
+        assert_eq!(kind::UNDOCUMENTED_30, 0x30);
+        assert_eq!(kind::UNDOCUMENTED_C3, 0xC3);
+        assert_eq!(message(kind::SETPOINT).unwrap().signals[0].scale, 0.01);
+        assert_eq!(did_def(did::FIRMWARE_CRC).unwrap().len, 4);
+        assert_eq!(DidTimeout::of_did(did::FIRMWARE_CRC), DidTimeout::Slow);
+        assert_eq!(DidTimeout::of_did(did::DEVICE_ID), DidTimeout::Normal);
+        assert_eq!(DidTimeout::of_did(0xF180), DidTimeout::Normal);
+        assert_eq!(
+            message(kind::CELL_PPO2).unwrap().direction,
+            MsgDirection::SoloToBus
+        );
+
+        assert!(DBC.contains("BO_ 2365849600 CellPpo2: 4 Vector__XXX"));
+        assert!(DBC.contains(" SG_ cell1 : 15|8@0+ (0.01,0) [0|2.55] \"bar\" Vector__XXX"));
+        assert!(DBC.contains(" SG_ surface : 7|16@0+ (1,0) [0|65535] \"mbar\" Vector__XXX"));
+        assert!(WIRESHARK_DISSECTOR.contains("[0xD2] = \"Serial\","));
+        assert!(WIRESHARK_DISSECTOR.contains("t:add(f_cellvoltages_cell3, buf(4, 2))"));
+    }
+}
@@ -0,0 +1,269 @@
+use core::cell::Cell;
+use std::format;
+use std::path::Path;
+
+use rusqlite::{Connection, params};
+
+use crate::divecan::{DiveCanFrame, DiveCanId, Msg};
+use crate::monitor::Event;
+
+pub use rusqlite::Error as RecordError;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS frames (
+    id      INTEGER PRIMARY KEY,
+    ts_ms   INTEGER NOT NULL,
+    can_id  INTEGER NOT NULL,
+    src     INTEGER NOT NULL,
+    dst     INTEGER NOT NULL,
+    kind    INTEGER NOT NULL,
+    dlc     INTEGER NOT NULL,
+    data    BLOB NOT NULL,
+    decoded TEXT,
+    iface   TEXT
+);
+CREATE INDEX IF NOT EXISTS frames_ts ON frames (ts_ms);
+CREATE INDEX IF NOT EXISTS frames_kind ON frames (kind);
+
+CREATE TABLE IF NOT EXISTS events (
+    id     INTEGER PRIMARY KEY,
+    ts_ms  INTEGER NOT NULL,
+    kind   TEXT NOT NULL,
+    src    INTEGER,
+    detail TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_ts ON events (ts_ms);
+
+CREATE TABLE IF NOT EXISTS dives (
+    id          INTEGER PRIMARY KEY,
+    dive_number INTEGER NOT NULL,
+    started_ms  INTEGER NOT NULL,
+    ended_ms    INTEGER
+);
+";
+
+/// Telemetry recorder storing frames, monitor events and dive spans in SQLite.
+///
+/// Timestamps are milliseconds, typically since the Unix epoch. Rows go
+/// into transactions of up to [`Self::BATCH_ROWS`]. [`flush`](Self::flush)
+/// commits the rows so far, and so does dropping the recorder.
+pub struct SqliteRecorder {
+    conn: Connection,
+    /// Rows in the open transaction, 0 when none is open
+    pending: Cell<u32>,
+}
+
+impl SqliteRecorder {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RecordError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, RecordError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, RecordError> {
+        // One insert per frame, don't fsync each of them
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn,
+            pending: Cell::new(0),
+        })
+    }
+
+    /// Most rows written before the transaction they are in is committed
+    pub const BATCH_ROWS: u32 = 500;
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Commits the rows written so far
+    pub fn flush(&self) -> Result<(), RecordError> {
+        if self.pending.get() > 0 {
+            self.conn.execute_batch("COMMIT")?;
+            self.pending.set(0);
+        }
+        Ok(())
+    }
+
+    /// Runs `insert` in the open transaction, opening one if needed
+    fn write<T>(
+        &self,
+        insert: impl FnOnce(&Connection) -> Result<T, RecordError>,
+    ) -> Result<T, RecordError> {
+        if self.pending.get() == 0 {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        self.pending.set(self.pending.get() + 1);
+        let result = insert(&self.conn)?;
+        if self.pending.get() >= Self::BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(result)
+    }
+
+    pub fn record_frame(
+        &self,
+        ts_ms: u64,
+        id: DiveCanId,
+        frame: &DiveCanFrame,
+    ) -> Result<(), RecordError> {
+        self.record_frame_on(ts_ms, None, id, frame)
+    }
+
+    /// Like [`record_frame`](Self::record_frame), tagged with the interface
+    /// the frame came in on when recording several buses
+    pub fn record_frame_on(
+        &self,
+        ts_ms: u64,
+        iface: Option<&str>,
+        id: DiveCanId,
+        frame: &DiveCanFrame,
+    ) -> Result<(), RecordError> {
+        let decoded = Msg::try_from_frame(frame).ok().map(|m| format!("{:?}", m));
+        self.write(|conn| {
+            conn.prepare_cached(
+                "INSERT INTO frames (ts_ms, can_id, src, dst, kind, dlc, data, decoded, iface)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?
+            .execute(params![
+                ts_ms as i64,
+                id.to_u32(),
+                id.src,
+                id.dst,
+                frame.kind(),
+                frame.dlc(),
+                frame.bytes(),
+                decoded,
+                iface,
+            ])
+        })?;
+        Ok(())
+    }
+
+    /// Stores an event, opening or closing a row in `dives` for dive events.
+    pub fn record_event(&self, ts_ms: u64, event: &Event) -> Result<(), RecordError> {
+        self.write(|conn| {
+            conn.prepare_cached(
+                "INSERT INTO events (ts_ms, kind, src, detail) VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute(params![
+                ts_ms as i64,
+                event.name(),
+                event.src(),
+                format!("{:?}", event)
+            ])
+        })?;
+
+        match event {
+            Event::DiveStarted { dive_number } => {
+                self.write(|conn| {
+                    conn.execute(
+                        "INSERT INTO dives (dive_number, started_ms) VALUES (?1, ?2)",
+                        params![dive_number, ts_ms as i64],
+                    )
+                })?;
+            }
+            Event::DiveEnded { dive_number } => {
+                self.write(|conn| {
+                    conn.execute(
+                        "UPDATE dives SET ended_ms = ?1
+                         WHERE id = (SELECT MAX(id) FROM dives WHERE dive_number = ?2 AND ended_ms IS NULL)",
+                        params![ts_ms as i64, dive_number],
+                    )
+                })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SqliteRecorder {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_frames_events_and_dives() {
+        let rec = SqliteRecorder::open_in_memory().unwrap();
+
+        let msg = Msg::Setpoint(0x0C.into());
+        let id = DiveCanId::new(1, 0xFF, msg.kind());
+        rec.record_frame(10, id, &msg.to_frame()).unwrap();
+
+        rec.record_event(20, &Event::DiveStarted { dive_number: 7 })
+            .unwrap();
+        rec.record_event(90, &Event::DiveEnded { dive_number: 7 })
+            .unwrap();
+
+        let (kind, data, decoded): (u8, Vec<u8>, Option<String>) = rec
+            .connection()
+            .query_row("SELECT kind, data, decoded FROM frames", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(kind, 0xC9);
+        assert_eq!(data, vec![0x0C]);
+        assert!(decoded.unwrap().starts_with("Setpoint"));
+
+        let (started, ended): (i64, Option<i64>) = rec
+            .connection()
+            .query_row(
+                "SELECT started_ms, ended_ms FROM dives WHERE dive_number = 7",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((started, ended), (20, Some(90)));
+
+        rec.record_frame_on(30, Some("can1"), id, &msg.to_frame())
+            .unwrap();
+        let ifaces: Vec<Option<String>> = rec
+            .connection()
+            .prepare("SELECT iface FROM frames ORDER BY ts_ms")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ifaces, [None, Some("can1".to_string())]);
+
+        let events: i64 = rec
+            .connection()
+            .query_row("SELECT COUNT(*) FROM events", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(events, 2);
+    }
+
+    #[test]
+    fn commits_in_batches() {
+        let rec = SqliteRecorder::open_in_memory().unwrap();
+        let in_transaction = |rec: &SqliteRecorder| !rec.connection().is_autocommit();
+        assert!(!in_transaction(&rec));
+
+        let msg = Msg::Setpoint(0x0C.into());
+        let id = DiveCanId::new(1, 0xFF, msg.kind());
+        rec.record_frame(0, id, &msg.to_frame()).unwrap();
+        assert!(in_transaction(&rec));
+        rec.flush().unwrap();
+        assert!(!in_transaction(&rec));
+
+        for ts in 0..SqliteRecorder::BATCH_ROWS as u64 {
+            rec.record_frame(ts, id, &msg.to_frame()).unwrap();
+        }
+        assert!(!in_transaction(&rec));
+        let frames: i64 = rec
+            .connection()
+            .query_row("SELECT COUNT(*) FROM frames", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(frames, 1 + SqliteRecorder::BATCH_ROWS as i64);
+    }
+}
@@ -0,0 +1,89 @@
+//! Small seeded PRNG for simulation jitter and noise, so a run can be
+//! replayed exactly from its seed. Not for anything security related.
+
+/// xoshiro128++, seeded through splitmix64 so any `u64` is a usable seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Xoshiro128 {
+    s: [u32; 4],
+}
+
+impl Xoshiro128 {
+    pub fn from_seed(seed: u64) -> Self {
+        let mut sm = seed;
+        let mut next = || {
+            sm = sm.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = sm;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let (a, b) = (next(), next());
+        Self::from_state([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32])
+    }
+
+    /// Raw state, must not be all zero.
+    pub const fn from_state(s: [u32; 4]) -> Self {
+        Self { s }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let s = &mut self.s;
+        let result = s[0].wrapping_add(s[3]).rotate_left(7).wrapping_add(s[0]);
+        let t = s[1] << 9;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(11);
+        result
+    }
+
+    /// Uniform in `0..n`, 0 when `n` is 0.
+    pub fn below(&mut self, n: u32) -> u32 {
+        // Multiply-shift, the bias is negligible for simulation ranges
+        ((self.next_u32() as u64 * n as u64) >> 32) as u32
+    }
+
+    /// Uniform in `-amplitude..=amplitude`.
+    pub fn jitter(&mut self, amplitude: u16) -> i32 {
+        self.below(2 * amplitude as u32 + 1) as i32 - amplitude as i32
+    }
+
+    pub fn fill(&mut self, out: &mut [u8]) {
+        for chunk in out.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproducible() {
+        // Reference output of xoshiro128++ for state 1, 2, 3, 4
+        let mut rng = Xoshiro128::from_state([1, 2, 3, 4]);
+        assert_eq!(rng.next_u32(), 641);
+        assert_eq!(rng.next_u32(), 1_573_767);
+
+        let mut a = Xoshiro128::from_seed(42);
+        let mut b = Xoshiro128::from_seed(42);
+        let mut c = Xoshiro128::from_seed(43);
+        let (mut x, mut y) = ([0u8; 7], [0u8; 7]);
+        a.fill(&mut x);
+        b.fill(&mut y);
+        assert_eq!(x, y);
+        assert_ne!(a.next_u32(), c.next_u32());
+
+        for _ in 0..1000 {
+            assert!(a.below(10) < 10);
+            assert!((-3..=3).contains(&a.jitter(3)));
+        }
+        assert_eq!(a.below(0), 0);
+        assert_eq!(a.jitter(0), 0);
+    }
+}
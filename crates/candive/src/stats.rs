@@ -0,0 +1,94 @@
+//! Running summaries for the types that listen to broadcasts for a while
+//! and report what they saw, such as [`PowerStats`](crate::power::PowerStats)
+//! and [`AmbientPressureAverage`](crate::divecan::AmbientPressureAverage).
+
+/// Mean of the samples pushed, rounded to the nearest integer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mean {
+    sum: u64,
+    samples: u32,
+}
+
+impl Mean {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, value: u32) {
+        self.sum += u64::from(value);
+        self.samples += 1;
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// `None` before the first sample
+    pub fn average(&self) -> Option<u32> {
+        if self.samples == 0 {
+            return None;
+        }
+        let n = u64::from(self.samples);
+        Some(((self.sum + n / 2) / n) as u32)
+    }
+}
+
+/// Smallest and largest of the samples pushed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MinMax<T> {
+    range: Option<(T, T)>,
+}
+
+impl<T> Default for MinMax<T> {
+    fn default() -> Self {
+        Self { range: None }
+    }
+}
+
+impl<T: Ord + Copy> MinMax<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.range = Some(match self.range {
+            Some((min, max)) => (min.min(value), max.max(value)),
+            None => (value, value),
+        });
+    }
+
+    /// Smallest and largest, `None` before the first sample
+    pub fn range(&self) -> Option<(T, T)> {
+        self.range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_rounds_to_nearest() {
+        let mut mean = Mean::new();
+        assert_eq!(mean.average(), None);
+        for v in [1000, 1003] {
+            mean.push(v);
+        }
+        assert_eq!((mean.samples(), mean.average()), (2, Some(1002)));
+
+        let mut big = Mean::new();
+        big.push(u32::MAX);
+        big.push(u32::MAX);
+        assert_eq!(big.average(), Some(u32::MAX));
+    }
+
+    #[test]
+    fn min_max() {
+        let mut range = MinMax::new();
+        assert_eq!(range.range(), None);
+        for v in [5, 2, 9, 4] {
+            range.push(v);
+        }
+        assert_eq!(range.range(), Some((2, 9)));
+    }
+}
@@ -0,0 +1,159 @@
+//! Stand-ins for the pieces of log crypto a test suite can't have: the real
+//! cipher and the secret key. [`DesEncryptor`] is deterministic but not DES,
+//! so the fixtures built with it exercise the decryption and CRC pipeline
+//! without any real key material.
+//!
+//! Built for the crate's own tests, and for other crates with the `testing`
+//! feature.
+
+use crate::crypto::ct_eq_u32;
+use crate::diag::Stm32Crc32;
+use crate::diag::solo::{
+    BlockCipher, LOG_ENTRY_SIZE, LogEncryptor, LogTransferDigest, LogWriter, RamStorage,
+};
+use crate::divecan::Msg;
+use crate::units::PpO2Deci;
+
+/// Key the fixtures are encrypted with
+pub const FIXTURE_KEY: [u8; 8] = *b"notakey!";
+pub const FIXTURE_DEVICE_ID: [u8; 12] = *b"FIXTURE-0001";
+pub const FIXTURE_TIMESTAMP: u32 = 0x0002_c1c9;
+
+/// Takes the place of `des::Des` with the same key and block size: a small
+/// keyed Feistel network, so every key gives a different permutation of
+/// blocks. Not DES, and not a cipher worth the name.
+pub struct DesEncryptor {
+    key: [u32; 2],
+}
+
+impl DesEncryptor {
+    const ROUNDS: u32 = 8;
+
+    pub fn new(key: [u8; 8]) -> Self {
+        Self {
+            key: [
+                u32::from_be_bytes([key[0], key[1], key[2], key[3]]),
+                u32::from_be_bytes([key[4], key[5], key[6], key[7]]),
+            ],
+        }
+    }
+}
+
+impl BlockCipher<8> for DesEncryptor {
+    fn encrypt_block(&self, block: &mut [u8; 8]) {
+        let mut l = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+        let mut r = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+        for round in 0..Self::ROUNDS {
+            let k = self.key[(round % 2) as usize] ^ round;
+            let f = r.wrapping_add(k).rotate_left(5) ^ r.wrapping_mul(0x9E37_79B9);
+            (l, r) = (r, l ^ f);
+        }
+        block[..4].copy_from_slice(&r.to_be_bytes());
+        block[4..].copy_from_slice(&l.to_be_bytes());
+    }
+}
+
+/// A log as written to flash and as the device uploads it
+pub struct LogFixture<const N: usize> {
+    pub plain: [u8; N],
+    pub encrypted: [u8; N],
+    /// What `MCU_DEVINFO` reports after uploading `encrypted`
+    pub digest: LogTransferDigest,
+}
+
+/// Writes `msgs` to an erased `N` byte log and encrypts it with
+/// [`FIXTURE_KEY`]. Panics when they don't fit.
+pub fn encrypted_log<const N: usize>(msgs: &[Msg]) -> LogFixture<N> {
+    let mut writer = LogWriter::open(RamStorage::<N>::new(N, LOG_ENTRY_SIZE as usize))
+        .expect("erased storage opens");
+    for msg in msgs {
+        writer.append(msg).expect("fixture log too small");
+    }
+    let mut plain = [0u8; N];
+    plain.copy_from_slice(writer.storage().as_bytes());
+    let mut encrypted = plain;
+    let digest = LogEncryptor::seal(
+        &DesEncryptor::new(FIXTURE_KEY),
+        &FIXTURE_DEVICE_ID,
+        FIXTURE_TIMESTAMP,
+        &mut encrypted,
+    );
+    LogFixture {
+        plain,
+        encrypted,
+        digest,
+    }
+}
+
+pub const SAMPLE_LOG_MSGS: [Msg; 3] = [
+    Msg::Serial(*b"A005D007"),
+    Msg::Setpoint(PpO2Deci::new(13)),
+    Msg::Nop,
+];
+
+/// [`SAMPLE_LOG_MSGS`] in a 16 slot log
+pub fn sample_log() -> LogFixture<{ 16 * LOG_ENTRY_SIZE as usize }> {
+    encrypted_log(&SAMPLE_LOG_MSGS)
+}
+
+/// Whether `data` is what the device reported in `digest`
+pub fn crc_matches(data: &[u8], digest: &LogTransferDigest) -> bool {
+    ct_eq_u32(Stm32Crc32::stm32_crc32(data), digest.log_crc32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diag::solo::{LogDecryptor, LogEntryIterator, LogProfile, plausible_log};
+
+    #[test]
+    fn des_stub_is_deterministic() {
+        let cipher = DesEncryptor::new(FIXTURE_KEY);
+        let mut a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let mut b = a;
+        cipher.encrypt_block(&mut a);
+        cipher.encrypt_block(&mut b);
+        assert_eq!(a, b);
+        assert_ne!(a, [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        let mut other = [0, 1, 2, 3, 4, 5, 6, 7];
+        DesEncryptor::new(*b"otherkey").encrypt_block(&mut other);
+        assert_ne!(a, other);
+    }
+
+    #[test]
+    fn sample_log_decrypts() {
+        let log = sample_log();
+        assert!(plausible_log(&log.plain));
+        assert!(!plausible_log(&log.encrypted));
+        assert!(crc_matches(&log.encrypted, &log.digest));
+        assert_eq!(log.digest.transfer_start_timestamp, FIXTURE_TIMESTAMP);
+
+        // As solodiag does it: CRC the upload, then decrypt with the digest
+        let mut data = log.encrypted;
+        LogDecryptor::new(
+            &DesEncryptor::new(FIXTURE_KEY),
+            &log.digest.physical_device_id,
+            log.digest.transfer_start_timestamp,
+        )
+        .decrypt(&mut data);
+        assert_eq!(data, log.plain);
+        let msgs: std::vec::Vec<Msg> = LogEntryIterator::new(&data)
+            .map(|e| Msg::try_from_frame(&e.to_frame(&LogProfile::SOLO).1).unwrap())
+            .collect();
+        assert_eq!(msgs, SAMPLE_LOG_MSGS);
+
+        let mut wrong = log.encrypted;
+        LogDecryptor::new(
+            &DesEncryptor::new(*b"otherkey"),
+            &log.digest.physical_device_id,
+            log.digest.transfer_start_timestamp,
+        )
+        .decrypt(&mut wrong);
+        assert!(!plausible_log(&wrong));
+
+        let mut corrupt = log.encrypted;
+        corrupt[20] ^= 0x01;
+        assert!(!crc_matches(&corrupt, &log.digest));
+    }
+}
@@ -0,0 +1,154 @@
+use core::cell::Cell;
+
+/// Monotonic point in time in milliseconds since an arbitrary epoch, what a
+/// [`Clock`] reads for [`crate::poll`] and the UDS transfer measurements.
+/// The bus monitor's `EventStream` and the ISO-TP receiver take plain
+/// `now_ms: u64` instead; [`Self::as_millis`] gives one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Instant(u64);
+
+impl Instant {
+    pub const fn from_millis(ms: u64) -> Self {
+        Self(ms)
+    }
+
+    pub const fn as_millis(self) -> u64 {
+        self.0
+    }
+
+    /// Milliseconds since `earlier`, 0 if `earlier` is later.
+    pub const fn duration_since(self, earlier: Instant) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    pub const fn checked_add(self, ms: u64) -> Option<Instant> {
+        match self.0.checked_add(ms) {
+            Some(v) => Some(Self(v)),
+            None => None,
+        }
+    }
+}
+
+impl From<Instant> for u64 {
+    fn from(v: Instant) -> u64 {
+        v.as_millis()
+    }
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    /// Milliseconds since `earlier`, see [`Instant::duration_since`].
+    fn elapsed(&self, earlier: Instant) -> u64 {
+        self.now().duration_since(earlier)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// Blocking wait for code that polls, paired with a [`Clock`] that sees it
+pub trait Delay {
+    fn delay_ms(&self, ms: u64);
+}
+
+impl<D: Delay + ?Sized> Delay for &D {
+    fn delay_ms(&self, ms: u64) {
+        (**self).delay_ms(ms)
+    }
+}
+
+/// Clock that only moves when told to, for tests and replaying recordings.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+impl ManualClock {
+    pub const fn new(start: Instant) -> Self {
+        Self {
+            now: Cell::new(start.0),
+        }
+    }
+
+    pub fn set(&self, now: Instant) {
+        self.now.set(now.0);
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now.set(self.now.get().saturating_add(ms));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        Instant(self.now.get())
+    }
+}
+
+/// Waiting just moves the clock on, so polls run instantly in tests
+impl Delay for ManualClock {
+    fn delay_ms(&self, ms: u64) {
+        self.advance(ms);
+    }
+}
+
+/// Milliseconds since the clock was created, backed by `std::time::Instant`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    pub fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> Instant {
+        Instant(self.start.elapsed().as_millis() as u64)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Delay for StdClock {
+    fn delay_ms(&self, ms: u64) {
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(Instant::from_millis(1000));
+        let start = clock.now();
+        clock.advance(250);
+        assert_eq!(clock.elapsed(start), 250);
+        assert_eq!(start.duration_since(clock.now()), 0);
+
+        clock.set(Instant::from_millis(u64::MAX));
+        clock.advance(1);
+        assert_eq!(clock.now().as_millis(), u64::MAX);
+        assert_eq!(clock.now().checked_add(1), None);
+    }
+}
@@ -1,9 +1,18 @@
 use super::uds::*;
+use crate::crypto::ct_eq_u32;
+use crate::protocol::DidTimeout;
 
 pub trait UdsTransport {
     type Error;
 
     fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Sends a request with the [`SUPPRESS_POSITIVE_RESPONSE`] bit set,
+    /// which the server only answers to refuse, without waiting for an
+    /// answer. A refusal that arrives later must not be taken as the answer
+    /// to the next [`Self::request`]. Wrappers forward this to the
+    /// transport they wrap.
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,10 +27,21 @@ pub enum UdsClientError<E> {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProtocolError {
-    WrongDid { expected: u16, got: u16 },
-    WrongBlockCounter { expected: u8, got: u8 },
+    WrongDid {
+        expected: u16,
+        got: u16,
+    },
+    WrongBlockCounter {
+        expected: u8,
+        got: u8,
+    },
     EmptyPayload,
     UnexpectedResponse,
+    /// Device CRC of the downloaded data differs from the one computed locally
+    TransferCrcMismatch {
+        expected: u32,
+        got: u32,
+    },
 }
 
 impl<E> From<UdsEncodeError> for UdsClientError<E> {
@@ -48,6 +68,10 @@ impl<E> From<ProtocolError> for UdsClientError<E> {
     }
 }
 
+/// Extra attempts for a request the server answered with a retryable NRC
+/// (see [`UdsErrorCode::is_retryable`]) before the NRC is returned
+pub const RETRYABLE_NRC_ATTEMPTS: u32 = 3;
+
 fn transact<'a, C: ServiceCodec, T: UdsTransport>(
     transport: &mut T,
     tx_buf: &mut [u8],
@@ -57,13 +81,21 @@ fn transact<'a, C: ServiceCodec, T: UdsTransport>(
     let mut writer = UdsPduWriter::new(tx_buf);
     C::encode_request(req, &mut writer)?;
 
-    let resp_len = transport
-        .request(writer.as_bytes(), rx_buf)
-        .map_err(UdsClientError::Transport)?;
+    let mut retries = 0;
+    let resp_len = loop {
+        let resp_len = transport
+            .request(writer.as_bytes(), rx_buf)
+            .map_err(UdsClientError::Transport)?;
 
-    if resp_len > rx_buf.len() {
-        return Err(UdsClientError::ResponseTooLarge);
-    }
+        if resp_len > rx_buf.len() {
+            return Err(UdsClientError::ResponseTooLarge);
+        }
+
+        match UdsPduView::new(&rx_buf[..resp_len]).check_positive() {
+            Err(neg) if neg.code.is_retryable() && retries < RETRYABLE_NRC_ATTEMPTS => retries += 1,
+            _ => break resp_len,
+        }
+    };
 
     let view = UdsPduView::new(&rx_buf[..resp_len]);
     view.check_positive()?;
@@ -71,6 +103,94 @@ fn transact<'a, C: ServiceCodec, T: UdsTransport>(
     Ok(C::decode_response(view)?)
 }
 
+/// [`transact`] for sub-function services. With the suppress bit set there
+/// is no positive response to wait for, the request goes out through
+/// [`UdsTransport::send`] and `None` comes back.
+fn transact_sub_function<'a, C: ServiceCodec, T: UdsTransport>(
+    transport: &mut T,
+    tx_buf: &mut [u8],
+    rx_buf: &'a mut [u8],
+    req: &C::Request<'_>,
+    suppress_positive_response: bool,
+) -> Result<Option<C::Response<'a>>, UdsClientError<T::Error>> {
+    if !suppress_positive_response {
+        return transact::<C, _>(transport, tx_buf, rx_buf, req).map(Some);
+    }
+    let mut writer = UdsPduWriter::new(tx_buf);
+    C::encode_request(req, &mut writer)?;
+    transport
+        .send(writer.as_bytes())
+        .map_err(UdsClientError::Transport)?;
+    Ok(None)
+}
+
+/// Keeps a non-default session alive. As a periodic keep-alive it is
+/// usually sent with `suppress_positive_response`, so the bus only carries
+/// the request.
+pub fn tester_present<T: UdsTransport>(
+    transport: &mut T,
+    suppress_positive_response: bool,
+) -> Result<(), UdsClientError<T::Error>> {
+    let (mut tx_buf, mut rx_buf) = ([0u8; 3], [0u8; 8]);
+    let req = TesterPresentReq {
+        suppress_positive_response,
+    };
+    transact_sub_function::<TesterPresentCodec, _>(
+        transport,
+        &mut tx_buf,
+        &mut rx_buf,
+        &req,
+        suppress_positive_response,
+    )?;
+    Ok(())
+}
+
+pub fn diagnostic_session_control<T: UdsTransport>(
+    transport: &mut T,
+    session: u8,
+    suppress_positive_response: bool,
+) -> Result<(), UdsClientError<T::Error>> {
+    let (mut tx_buf, mut rx_buf) = ([0u8; 3], [0u8; 16]);
+    let req = DiagnosticSessionControlReq {
+        session,
+        suppress_positive_response,
+    };
+    match transact_sub_function::<DiagnosticSessionControlCodec, _>(
+        transport,
+        &mut tx_buf,
+        &mut rx_buf,
+        &req,
+        suppress_positive_response,
+    )? {
+        Some(resp) if resp.session != session => Err(ProtocolError::UnexpectedResponse.into()),
+        _ => Ok(()),
+    }
+}
+
+pub fn ecu_reset<T: UdsTransport>(
+    transport: &mut T,
+    reset_type: u8,
+    suppress_positive_response: bool,
+) -> Result<(), UdsClientError<T::Error>> {
+    let (mut tx_buf, mut rx_buf) = ([0u8; 3], [0u8; 8]);
+    let req = EcuResetReq {
+        reset_type,
+        suppress_positive_response,
+    };
+    match transact_sub_function::<EcuResetCodec, _>(
+        transport,
+        &mut tx_buf,
+        &mut rx_buf,
+        &req,
+        suppress_positive_response,
+    )? {
+        Some(resp) if resp.reset_type != reset_type => {
+            Err(ProtocolError::UnexpectedResponse.into())
+        }
+        _ => Ok(()),
+    }
+}
+
 pub fn rdbi<'rx, T: UdsTransport>(
     transport: &mut T,
     did: u16,
@@ -91,6 +211,25 @@ pub fn rdbi<'rx, T: UdsTransport>(
     Ok(resp.data)
 }
 
+/// Address, SID and DID in front of the data of an RDBI PDU
+pub const RDBI_HEADER_LEN: usize = 4;
+
+/// Reads a DID straight into `out` and returns the data length.
+///
+/// `out` doubles as the receive buffer, so it needs `RDBI_HEADER_LEN`
+/// bytes of room on top of the largest expected data (see the profile's
+/// `MAX_RDBI_LEN`, e.g. `diag::solo::MAX_RDBI_LEN`).
+pub fn rdbi_into<T: UdsTransport>(
+    transport: &mut T,
+    did: u16,
+    out: &mut [u8],
+) -> Result<usize, UdsClientError<T::Error>> {
+    let mut tx_buf = [0u8; RDBI_HEADER_LEN];
+    let len = rdbi(transport, did, &mut tx_buf, out)?.len();
+    out.copy_within(RDBI_HEADER_LEN..RDBI_HEADER_LEN + len, 0);
+    Ok(len)
+}
+
 pub fn wdbi<T: UdsTransport>(
     transport: &mut T,
     did: u16,
@@ -111,12 +250,70 @@ pub fn wdbi<T: UdsTransport>(
 
     Ok(())
 }
+
+/// [`DidTimeout`] of the DID an RDBI or WDBI request PDU addresses, for
+/// transports choosing how long to wait for the answer. Other services are
+/// [`DidTimeout::Normal`].
+pub fn request_timeout(req: &[u8]) -> DidTimeout {
+    match req {
+        [_, SID_RDBI_REQ | SID_WDBI_REQ, hi, lo, ..] => {
+            DidTimeout::of_did(u16::from_be_bytes([*hi, *lo]))
+        }
+        _ => DidTimeout::Normal,
+    }
+}
+
+/// Sends TransferExit once, for the sessions' `finish` and for dropping a
+/// session that didn't get that far.
+struct TransferGuard {
+    exited: bool,
+}
+
+impl TransferGuard {
+    const fn new() -> Self {
+        Self { exited: false }
+    }
+
+    fn exit<T: UdsTransport>(
+        &mut self,
+        transport: &mut T,
+        tx_buf: &mut [u8],
+        rx_buf: &mut [u8],
+    ) -> Result<(), UdsClientError<T::Error>> {
+        if self.exited {
+            return Ok(());
+        }
+        self.exited = true;
+        transact::<TransferExitCodec, _>(transport, tx_buf, rx_buf, &TransferExitReq)?;
+        Ok(())
+    }
+}
+
+/// Retransmissions a download may spend recovering from
+/// WrongBlockSequenceCounter, see [`DownloadSession::with_sequence_retries`]
+pub const DEFAULT_SEQUENCE_RETRIES: u32 = 3;
+
+/// Largest block kept for retransmission. `max_block_len` comes from a
+/// single byte, so every block the device asks for fits.
+const MAX_RETAINED_BLOCK: usize = u8::MAX as usize;
+
+/// A RequestDownload in progress. Dropping it before [`finish`](Self::finish),
+/// e.g. when `send_block` fails, sends TransferExit so the device leaves its
+/// transfer state instead of rejecting the next request with a sequence error.
+///
+/// Block counters run 1, 2, .., 0xFF, 0x00, 0x01, .. as in ISO 14229.
 pub struct DownloadSession<'a, T: UdsTransport> {
     transport: &'a mut T,
     tx_buf: &'a mut [u8],
     rx_buf: &'a mut [u8],
     max_block_len: usize,
     next_block: u8,
+    guard: TransferGuard,
+    /// Last accepted block, `None` before the first or when it was too
+    /// large to keep
+    prev_block: Option<([u8; MAX_RETAINED_BLOCK], usize)>,
+    sequence_retries: u32,
+    resends: u32,
 }
 
 impl<'a, T: UdsTransport> DownloadSession<'a, T> {
@@ -141,45 +338,235 @@ impl<'a, T: UdsTransport> DownloadSession<'a, T> {
             rx_buf,
             max_block_len,
             next_block: 1,
+            guard: TransferGuard::new(),
+            prev_block: None,
+            sequence_retries: DEFAULT_SEQUENCE_RETRIES,
+            resends: 0,
         })
     }
 
+    /// Sets how many retransmissions the whole download may spend on
+    /// WrongBlockSequenceCounter, 0 gives up on the first one.
+    pub fn with_sequence_retries(mut self, retries: u32) -> Self {
+        self.sequence_retries = retries;
+        self
+    }
+
     pub fn max_block_len(&self) -> usize {
         self.max_block_len
     }
 
+    /// Blocks sent again because the device lost them
+    pub fn resends(&self) -> u32 {
+        self.resends
+    }
+
+    /// Sends the next block.
+    ///
+    /// A WrongBlockSequenceCounter means the device lost the previous block
+    /// even though its acknowledgement arrived (or a duplicate of an older
+    /// one did). The previous block is then sent again under its own
+    /// counter and this one retried, while the retry budget lasts.
     pub fn send_block(&mut self, data: &[u8]) -> Result<(), UdsClientError<T::Error>> {
+        loop {
+            match self.transfer(self.next_block, data) {
+                Err(UdsClientError::NegativeResponse(NegativeResponse {
+                    code: UdsErrorCode::WrongBlockSequenceCounter,
+                    ..
+                })) if self.sequence_retries > 0
+                    && let Some((prev, len)) = self.prev_block =>
+                {
+                    self.sequence_retries -= 1;
+                    self.resends += 1;
+                    self.transfer(self.next_block.wrapping_sub(1), &prev[..len])?;
+                }
+                result => break result?,
+            }
+        }
+
+        self.prev_block = (data.len() <= MAX_RETAINED_BLOCK).then(|| {
+            let mut block = [0u8; MAX_RETAINED_BLOCK];
+            block[..data.len()].copy_from_slice(data);
+            (block, data.len())
+        });
+        self.next_block = self.next_block.wrapping_add(1);
+
+        Ok(())
+    }
+
+    fn transfer(&mut self, block_seq: u8, data: &[u8]) -> Result<(), UdsClientError<T::Error>> {
         let req = TransferDataReq {
-            block_seq: self.next_block,
+            block_seq,
             payload: data,
         };
         let resp =
             transact::<TransferDataCodec, _>(self.transport, self.tx_buf, self.rx_buf, &req)?;
 
-        if resp.block_seq != self.next_block {
+        if resp.block_seq != block_seq {
             return Err(ProtocolError::WrongBlockCounter {
-                expected: self.next_block,
+                expected: block_seq,
                 got: resp.block_seq,
             }
             .into());
         }
+        Ok(())
+    }
 
-        self.next_block = self.next_block.wrapping_add(1);
+    pub fn finish(mut self) -> Result<(), UdsClientError<T::Error>> {
+        self.guard.exit(self.transport, self.tx_buf, self.rx_buf)
+    }
+
+    /// Like [`finish`](Self::finish), then reads the device's CRC of the
+    /// received data from `crc_did` (4 bytes, big endian) and compares it
+    /// with `expected`, the CRC of what was sent.
+    pub fn finish_verified(
+        mut self,
+        crc_did: u16,
+        expected: u32,
+    ) -> Result<(), UdsClientError<T::Error>> {
+        self.guard.exit(self.transport, self.tx_buf, self.rx_buf)?;
 
+        let data = rdbi(self.transport, crc_did, self.tx_buf, self.rx_buf)?;
+        let got = u32::from_be_bytes(
+            data.try_into()
+                .map_err(|_| ProtocolError::UnexpectedResponse)?,
+        );
+        if !ct_eq_u32(got, expected) {
+            return Err(ProtocolError::TransferCrcMismatch { expected, got }.into());
+        }
         Ok(())
     }
+}
 
-    pub fn finish(self) -> Result<(), UdsClientError<T::Error>> {
-        let _ = transact::<TransferExitCodec, _>(
-            self.transport,
-            self.tx_buf,
-            self.rx_buf,
-            &TransferExitReq,
-        )?;
-        Ok(())
+impl<T: UdsTransport> Drop for DownloadSession<'_, T> {
+    fn drop(&mut self) {
+        // Best effort, the error that got us here is the one worth reporting
+        let _ = self.guard.exit(self.transport, self.tx_buf, self.rx_buf);
     }
 }
 
+/// Download block sizes that suit the link. A CAN interface takes the
+/// largest block the device allows, a BLE gateway can move more data in
+/// smaller ones. The device acknowledges every block before the next one is
+/// sent, so the block size is all there is to tune.
+///
+/// Starts at [`START_LEN`](Self::START_LEN) and doubles while throughput,
+/// measured over a few blocks at a time, keeps rising, then settles on the
+/// best size seen. A block the device lost halves the size and stops the
+/// growing. Elapsed times come from the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BlockTuner {
+    max_len: usize,
+    len: usize,
+    growing: bool,
+    /// Best rate (bytes/s) and the length it was measured at
+    best: Option<(u64, usize)>,
+    /// Bytes, microseconds and blocks at `len` since it last changed
+    window: (u64, u64, u32),
+    blocks: u32,
+    errors: u32,
+    bytes: u64,
+    elapsed_us: u64,
+}
+
+/// What a [`BlockTuner`] settled on and saw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransferTuning {
+    pub block_len: usize,
+    pub blocks: u32,
+    /// Blocks the device lost
+    pub errors: u32,
+    /// Average over the whole transfer
+    pub bytes_per_sec: u64,
+}
+
+impl BlockTuner {
+    /// First block size, small enough for any link
+    pub const START_LEN: usize = 32;
+    /// Smallest size errors shrink the block to
+    pub const MIN_LEN: usize = 8;
+    /// Blocks measured at a size before deciding on the next
+    const WINDOW: u32 = 4;
+
+    /// `max_len` is what the device allows, [`DownloadSession::max_block_len`]
+    pub fn new(max_len: usize) -> Self {
+        let max_len = max_len.max(1);
+        Self {
+            max_len,
+            len: Self::START_LEN.min(max_len),
+            growing: true,
+            best: None,
+            window: (0, 0, 0),
+            blocks: 0,
+            errors: 0,
+            bytes: 0,
+            elapsed_us: 0,
+        }
+    }
+
+    /// Size of the next block
+    pub fn block_len(&self) -> usize {
+        self.len
+    }
+
+    /// A block of `len` bytes acknowledged `elapsed_us` after it was sent
+    pub fn on_block(&mut self, len: usize, elapsed_us: u64) {
+        self.blocks += 1;
+        self.bytes += len as u64;
+        self.elapsed_us += elapsed_us;
+        let (bytes, us, blocks) = &mut self.window;
+        *bytes += len as u64;
+        *us += elapsed_us;
+        *blocks += 1;
+        if !self.growing || *blocks < Self::WINDOW {
+            return;
+        }
+
+        let rate = rate(*bytes, *us);
+        self.window = (0, 0, 0);
+        match self.best {
+            // Only a clear gain is worth a larger block
+            Some((best, best_len)) if rate <= best + best / 20 => {
+                self.growing = false;
+                self.len = best_len;
+            }
+            _ => {
+                self.best = Some((rate, self.len));
+                if self.len < self.max_len {
+                    self.len = (self.len * 2).min(self.max_len);
+                } else {
+                    self.growing = false;
+                }
+            }
+        }
+    }
+
+    /// The device lost a block and it had to be sent again
+    pub fn on_error(&mut self) {
+        self.errors += 1;
+        self.growing = false;
+        self.len = (self.len / 2).max(Self::MIN_LEN.min(self.max_len));
+        self.window = (0, 0, 0);
+    }
+
+    pub fn report(&self) -> TransferTuning {
+        TransferTuning {
+            block_len: self.len,
+            blocks: self.blocks,
+            errors: self.errors,
+            bytes_per_sec: rate(self.bytes, self.elapsed_us),
+        }
+    }
+}
+
+fn rate(bytes: u64, elapsed_us: u64) -> u64 {
+    bytes * 1_000_000 / elapsed_us.max(1)
+}
+
+/// A RequestUpload in progress, dropping it sends TransferExit like
+/// [`DownloadSession`].
 pub struct UploadSession<'a, T: UdsTransport> {
     transport: &'a mut T,
     tx_buf: &'a mut [u8],
@@ -187,6 +574,7 @@ pub struct UploadSession<'a, T: UdsTransport> {
     next_block: u8,
     total_size: usize,
     transferred: usize,
+    guard: TransferGuard,
 }
 
 impl<'a, T: UdsTransport> UploadSession<'a, T> {
@@ -208,6 +596,7 @@ impl<'a, T: UdsTransport> UploadSession<'a, T> {
             next_block: 1,
             total_size: size as usize,
             transferred: 0,
+            guard: TransferGuard::new(),
         })
     }
 
@@ -253,13 +642,444 @@ impl<'a, T: UdsTransport> UploadSession<'a, T> {
         self.total_size
     }
 
-    pub fn finish(self) -> Result<(), UdsClientError<T::Error>> {
-        let _ = transact::<TransferExitCodec, _>(
-            self.transport,
-            self.tx_buf,
-            self.rx_buf,
-            &TransferExitReq,
-        )?;
-        Ok(())
+    pub fn finish(mut self) -> Result<(), UdsClientError<T::Error>> {
+        self.guard.exit(self.transport, self.tx_buf, self.rx_buf)
+    }
+}
+
+impl<T: UdsTransport> Drop for UploadSession<'_, T> {
+    fn drop(&mut self) {
+        let _ = self.guard.exit(self.transport, self.tx_buf, self.rx_buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Canned(&'static [u8]);
+
+    impl UdsTransport for Canned {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            assert_eq!(req, &[DIVE_CAN_UDS_ADDR, 0x22, 0x80, 0x10]);
+            let n = self.0.len().min(resp_buf.len());
+            resp_buf[..n].copy_from_slice(&self.0[..n]);
+            Ok(self.0.len())
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Records what was requested and what was only sent
+    #[derive(Default)]
+    struct Keepalive {
+        requested: std::vec::Vec<std::vec::Vec<u8>>,
+        sent: std::vec::Vec<std::vec::Vec<u8>>,
+    }
+
+    impl UdsTransport for Keepalive {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            self.requested.push(req.to_vec());
+            let resp = [DIVE_CAN_UDS_ADDR, req[1] + 0x40, req[2]];
+            resp_buf[..3].copy_from_slice(&resp);
+            Ok(3)
+        }
+
+        fn send(&mut self, req: &[u8]) -> Result<(), ()> {
+            self.sent.push(req.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn suppressed_requests_are_only_sent() {
+        let mut t = Keepalive::default();
+        assert_eq!(tester_present(&mut t, true), Ok(()));
+        assert_eq!(ecu_reset(&mut t, 0x01, true), Ok(()));
+        assert_eq!(
+            t.sent,
+            [
+                [DIVE_CAN_UDS_ADDR, 0x3E, 0x80],
+                [DIVE_CAN_UDS_ADDR, 0x11, 0x81]
+            ]
+        );
+        assert!(t.requested.is_empty());
+
+        assert_eq!(tester_present(&mut t, false), Ok(()));
+        assert_eq!(diagnostic_session_control(&mut t, 0x02, false), Ok(()));
+        assert_eq!(t.requested.len(), 2);
+        assert_eq!(t.sent.len(), 2);
+    }
+
+    #[test]
+    fn timeout_class_of_request() {
+        assert_eq!(
+            request_timeout(&[DIVE_CAN_UDS_ADDR, SID_RDBI_REQ, 0x82, 0x09]),
+            DidTimeout::Slow
+        );
+        assert_eq!(
+            request_timeout(&[DIVE_CAN_UDS_ADDR, SID_RDBI_REQ, 0x80, 0x10]),
+            DidTimeout::Normal
+        );
+        assert_eq!(
+            request_timeout(&[DIVE_CAN_UDS_ADDR, SID_TRANSFER_EXIT_REQ]),
+            DidTimeout::Normal
+        );
+    }
+
+    #[test]
+    fn rdbi_into_strips_header() {
+        let mut out = [0u8; 8];
+        let mut t = Canned(&[DIVE_CAN_UDS_ADDR, 0x62, 0x80, 0x10, 0xAA, 0xBB, 0xCC]);
+        assert_eq!(rdbi_into(&mut t, 0x8010, &mut out), Ok(3));
+        assert_eq!(&out[..3], &[0xAA, 0xBB, 0xCC]);
+
+        let mut small = [0u8; 5];
+        assert_eq!(
+            rdbi_into(&mut t, 0x8010, &mut small),
+            Err(UdsClientError::ResponseTooLarge)
+        );
+    }
+
+    /// Answers `busy` BusyRepeatRequest NRCs before a positive RDBI response
+    struct BusyThenOk {
+        busy: u32,
+        requests: u32,
+    }
+
+    impl UdsTransport for BusyThenOk {
+        type Error = ();
+
+        fn request(&mut self, _req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            self.requests += 1;
+            let resp: &[u8] = if self.requests <= self.busy {
+                &[DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE, 0x22, 0x21]
+            } else {
+                &[DIVE_CAN_UDS_ADDR, 0x62, 0x80, 0x10, 0xAA]
+            };
+            resp_buf[..resp.len()].copy_from_slice(resp);
+            Ok(resp.len())
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retries_busy_nrc() {
+        let mut out = [0u8; 8];
+        let mut t = BusyThenOk {
+            busy: RETRYABLE_NRC_ATTEMPTS,
+            requests: 0,
+        };
+        assert_eq!(rdbi_into(&mut t, 0x8010, &mut out), Ok(1));
+        assert_eq!(t.requests, RETRYABLE_NRC_ATTEMPTS + 1);
+
+        let mut t = BusyThenOk {
+            busy: RETRYABLE_NRC_ATTEMPTS + 1,
+            requests: 0,
+        };
+        assert_eq!(
+            rdbi_into(&mut t, 0x8010, &mut out),
+            Err(UdsClientError::NegativeResponse(NegativeResponse {
+                service: 0x22,
+                code: UdsErrorCode::BusyRepeatRequest,
+            }))
+        );
+        assert_eq!(t.requests, RETRYABLE_NRC_ATTEMPTS + 1);
+    }
+
+    #[test]
+    fn nrc_table() {
+        for code in 0..=0xFF {
+            assert_eq!(UdsErrorCode::from_u8(code).as_u8(), code);
+        }
+        assert_eq!(UdsErrorCode::from_u8(0x8E), UdsErrorCode::Unknown(0x8E));
+        assert!(UdsErrorCode::from_u8(0x21).is_retryable());
+        assert!(UdsErrorCode::from_u8(0x78).is_response_pending());
+        assert!(UdsErrorCode::from_u8(0x35).is_security_related());
+        assert!(!UdsErrorCode::RequestOutOfRange.is_retryable());
+        assert_eq!(
+            format!("{}", UdsErrorCode::ServiceNotSupportedInActiveSession),
+            "service not supported in active session (0x7F)"
+        );
+    }
+
+    /// Answers TransferExit, then an RDBI of 0x8209 with `crc`
+    struct ExitThenCrc {
+        crc: [u8; 4],
+        step: usize,
+    }
+
+    impl UdsTransport for ExitThenCrc {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            self.step += 1;
+            match self.step {
+                1 => {
+                    assert_eq!(req, &[DIVE_CAN_UDS_ADDR, 0x37]);
+                    resp_buf[..2].copy_from_slice(&[DIVE_CAN_UDS_ADDR, 0x77]);
+                    Ok(2)
+                }
+                _ => {
+                    assert_eq!(req, &[DIVE_CAN_UDS_ADDR, 0x22, 0x82, 0x09]);
+                    resp_buf[..4].copy_from_slice(&[DIVE_CAN_UDS_ADDR, 0x62, 0x82, 0x09]);
+                    resp_buf[4..8].copy_from_slice(&self.crc);
+                    Ok(8)
+                }
+            }
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finish_verified_compares_crc() {
+        let finish = |crc: u32, expected: u32| {
+            let mut t = ExitThenCrc {
+                crc: crc.to_be_bytes(),
+                step: 0,
+            };
+            let (mut tx_buf, mut rx_buf) = ([0u8; 16], [0u8; 16]);
+            DownloadSession {
+                transport: &mut t,
+                tx_buf: &mut tx_buf,
+                rx_buf: &mut rx_buf,
+                max_block_len: 8,
+                next_block: 1,
+                guard: TransferGuard::new(),
+                prev_block: None,
+                sequence_retries: 0,
+                resends: 0,
+            }
+            .finish_verified(0x8209, expected)
+        };
+
+        assert_eq!(finish(0xDEADBEEF, 0xDEADBEEF), Ok(()));
+        assert_eq!(
+            finish(0x12345678, 0xDEADBEEF),
+            Err(UdsClientError::Protocol(
+                ProtocolError::TransferCrcMismatch {
+                    expected: 0xDEADBEEF,
+                    got: 0x12345678
+                }
+            ))
+        );
+    }
+
+    /// Accepts RequestDownload, then rejects block 2 with
+    /// WrongBlockSequenceCounter, recording every SID it is sent
+    struct RejectsSecondBlock {
+        sids: Vec<u8>,
+    }
+
+    impl UdsTransport for RejectsSecondBlock {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            self.sids.push(req[1]);
+            let resp: &[u8] = match (req[1], req.get(2)) {
+                (0x34, _) => &[DIVE_CAN_UDS_ADDR, 0x74, 0x10, 0x20],
+                (0x36, Some(&1)) => &[DIVE_CAN_UDS_ADDR, 0x76, 0x01],
+                (0x36, _) => &[DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE, 0x36, 0x73],
+                (0x37, _) => &[DIVE_CAN_UDS_ADDR, 0x77],
+                _ => panic!("unexpected request {:02X?}", req),
+            };
+            resp_buf[..resp.len()].copy_from_slice(resp);
+            Ok(resp.len())
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn exits_transfer_on_error() {
+        let mut t = RejectsSecondBlock { sids: Vec::new() };
+        let (mut tx_buf, mut rx_buf) = ([0u8; 64], [0u8; 64]);
+        let result = (|| {
+            let mut session =
+                DownloadSession::start(&mut t, 0x0800_0000, 64, &mut tx_buf, &mut rx_buf)?
+                    .with_sequence_retries(0);
+            session.send_block(&[0; 8])?;
+            session.send_block(&[0; 8])?;
+            session.finish()
+        })();
+
+        assert_eq!(
+            result,
+            Err(UdsClientError::NegativeResponse(NegativeResponse {
+                service: 0x36,
+                code: UdsErrorCode::WrongBlockSequenceCounter,
+            }))
+        );
+        assert_eq!(t.sids, [0x34, 0x36, 0x36, 0x37]);
+
+        // finish() sends the only TransferExit
+        let mut t = RejectsSecondBlock { sids: Vec::new() };
+        let mut session =
+            DownloadSession::start(&mut t, 0x0800_0000, 8, &mut tx_buf, &mut rx_buf).unwrap();
+        session.send_block(&[0; 8]).unwrap();
+        session.finish().unwrap();
+        assert_eq!(t.sids, [0x34, 0x36, 0x37]);
+    }
+
+    /// Device side of TransferData per ISO 14229: accepts the expected
+    /// counter, acknowledges a repeat of the last one without storing it
+    /// again and rejects anything else with WrongBlockSequenceCounter.
+    /// Acknowledges `lose` blocks without keeping them.
+    struct Receiver {
+        expected: u8,
+        lose: Vec<u8>,
+        stored: Vec<Vec<u8>>,
+        counters: Vec<u8>,
+    }
+
+    impl Receiver {
+        fn new(lose: &[u8]) -> Self {
+            Self {
+                expected: 1,
+                lose: lose.to_vec(),
+                stored: Vec::new(),
+                counters: Vec::new(),
+            }
+        }
+    }
+
+    impl UdsTransport for Receiver {
+        type Error = ();
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, ()> {
+            let (sid, counter) = (req[1], req[2]);
+            assert_eq!(sid, 0x36);
+            self.counters.push(counter);
+            let resp: &[u8] = if counter == self.expected {
+                if let Some(i) = self.lose.iter().position(|&c| c == counter) {
+                    self.lose.remove(i);
+                } else {
+                    self.stored.push(req[3..].to_vec());
+                    self.expected = self.expected.wrapping_add(1);
+                }
+                &[DIVE_CAN_UDS_ADDR, 0x76, counter]
+            } else if counter == self.expected.wrapping_sub(1) {
+                &[DIVE_CAN_UDS_ADDR, 0x76, counter]
+            } else {
+                &[DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE, 0x36, 0x73]
+            };
+            resp_buf[..resp.len()].copy_from_slice(resp);
+            Ok(resp.len())
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    fn session<'a>(
+        t: &'a mut Receiver,
+        tx_buf: &'a mut [u8],
+        rx_buf: &'a mut [u8],
+    ) -> DownloadSession<'a, Receiver> {
+        // Skips RequestDownload, and TransferExit on drop
+        let mut guard = TransferGuard::new();
+        guard.exited = true;
+        DownloadSession {
+            transport: t,
+            tx_buf,
+            rx_buf,
+            max_block_len: 8,
+            next_block: 1,
+            guard,
+            prev_block: None,
+            sequence_retries: DEFAULT_SEQUENCE_RETRIES,
+            resends: 0,
+        }
+    }
+
+    #[test]
+    fn block_counter_wraps() {
+        let mut t = Receiver::new(&[]);
+        let (mut tx_buf, mut rx_buf) = ([0u8; 16], [0u8; 16]);
+        let mut s = session(&mut t, &mut tx_buf, &mut rx_buf);
+        for i in 0..300u32 {
+            s.send_block(&i.to_be_bytes()).unwrap();
+        }
+        drop(s);
+        assert_eq!(t.counters[254..257], [0xFF, 0x00, 0x01]);
+        assert_eq!(t.stored.len(), 300);
+        assert_eq!(t.stored[299], 299u32.to_be_bytes());
+    }
+
+    #[test]
+    fn resends_lost_block() {
+        let mut t = Receiver::new(&[3, 5]);
+        let (mut tx_buf, mut rx_buf) = ([0u8; 16], [0u8; 16]);
+        let mut s = session(&mut t, &mut tx_buf, &mut rx_buf);
+        for i in 1..=6u8 {
+            s.send_block(&[i; 4]).unwrap();
+        }
+        let resends = s.resends();
+        drop(s);
+        assert_eq!(t.counters, [1, 2, 3, 4, 3, 4, 5, 6, 5, 6]);
+        let blocks: Vec<u8> = t.stored.iter().map(|b| b[0]).collect();
+        assert_eq!(blocks, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(resends, 2);
+
+        // Out of budget the NRC is returned
+        let mut t = Receiver::new(&[2]);
+        let mut s = session(&mut t, &mut tx_buf, &mut rx_buf).with_sequence_retries(0);
+        s.send_block(&[1]).unwrap();
+        s.send_block(&[2]).unwrap();
+        assert_eq!(
+            s.send_block(&[3]),
+            Err(UdsClientError::NegativeResponse(NegativeResponse {
+                service: 0x36,
+                code: UdsErrorCode::WrongBlockSequenceCounter,
+            }))
+        );
+    }
+
+    #[test]
+    fn block_tuner() {
+        // 5 ms per block plus 0.1 ms per byte: larger blocks always win
+        let cost = |len: usize| 5_000 + 100 * len as u64;
+        let mut tuner = BlockTuner::new(200);
+        let mut lens = Vec::new();
+        for _ in 0..20 {
+            let len = tuner.block_len();
+            lens.push(len);
+            tuner.on_block(len, cost(len));
+        }
+        assert_eq!(lens[..9], [32, 32, 32, 32, 64, 64, 64, 64, 128]);
+        assert_eq!(tuner.block_len(), 200);
+
+        // A link that stalls above 64 bytes settles back on 64
+        let cost = |len: usize| if len > 64 { 100_000 } else { 5_000 };
+        let mut tuner = BlockTuner::new(255);
+        for _ in 0..20 {
+            let len = tuner.block_len();
+            tuner.on_block(len, cost(len));
+        }
+        assert_eq!(tuner.block_len(), 64);
+
+        tuner.on_error();
+        tuner.on_error();
+        tuner.on_error();
+        assert_eq!(tuner.block_len(), BlockTuner::MIN_LEN);
+        tuner.on_block(8, 5_000);
+        let report = tuner.report();
+        assert_eq!((report.blocks, report.errors), (21, 3));
+        assert_eq!(BlockTuner::new(4).block_len(), 4);
     }
 }
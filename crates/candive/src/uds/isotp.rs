@@ -8,9 +8,26 @@ pub struct IsoTpFrame {
 }
 
 impl IsoTpFrame {
+    /// Frame from raw CAN payload bytes, `None` if empty or longer than 8
+    pub fn new(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() || bytes.len() > 8 {
+            return None;
+        }
+        let mut data = [0u8; 8];
+        data[..bytes.len()].copy_from_slice(bytes);
+        Some(IsoTpFrame {
+            len: bytes.len() as u8,
+            data,
+        })
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         &self.data[..self.len as usize]
     }
+
+    pub fn pci_type(&self) -> Option<IsoTpPciType> {
+        IsoTpPciType::from_u8(self.data[0])
+    }
 }
 
 pub struct IsoTpTx<'a> {
@@ -37,6 +54,23 @@ impl<'a> IsoTpTx<'a> {
             sn: 0,
         }
     }
+
+    /// Consecutive frames the receiver's flow control allows: up to its
+    /// block size (0 = all that's left) on CTS, none on Wait or Overflow.
+    /// The sender gives up on Overflow.
+    pub fn block(&mut self, fc: &FlowControl) -> core::iter::Take<&mut Self> {
+        let frames = match fc.status {
+            FlowStatus::ContinueToSend if fc.block_size > 0 => fc.block_size as usize,
+            FlowStatus::ContinueToSend => usize::MAX,
+            FlowStatus::Wait | FlowStatus::Overflow => 0,
+        };
+        self.take(frames)
+    }
+
+    /// Whether every frame has been produced
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, TxState::Done | TxState::SingleDone)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -163,6 +197,10 @@ pub enum IsoTpRxError {
         got: u8,
     },
     Overflow,
+    /// A multi-frame transfer went quiet longer than the timeout and was dropped
+    Timeout,
+    /// Flow control frame with a flow status other than CTS, Wait or Overflow
+    InvalidFlowStatus(u8),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -178,9 +216,17 @@ pub struct IsoTpRx {
     buf: [u8; 1024],
     used: usize,
     next_sn: u8, // next expected sequence number (0..15)
+    timeout_ms: Option<u64>,
+    last_frame_ms: u64,
+    ignore_duplicates: bool,
+    last_cf: [u8; 8],
+    last_cf_len: u8,
 }
 
 impl IsoTpRx {
+    /// N_Cr from ISO 15765-2, max time between consecutive frames
+    pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
     pub const fn new() -> Self {
         IsoTpRx {
             state: RxState::Idle,
@@ -188,15 +234,68 @@ impl IsoTpRx {
             buf: [0u8; 1024],
             used: 0,
             next_sn: 0,
+            timeout_ms: None,
+            last_frame_ms: 0,
+            ignore_duplicates: false,
+            last_cf: [0u8; 8],
+            last_cf_len: 0,
+        }
+    }
+
+    /// Receiver that drops a multi-frame transfer when no frame arrives for
+    /// `timeout_ms`. Only effective with [`IsoTpRx::on_frame_at`] / [`IsoTpRx::expire`].
+    pub const fn with_timeout(timeout_ms: u64) -> Self {
+        let mut rx = Self::new();
+        rx.timeout_ms = Some(timeout_ms);
+        rx
+    }
+
+    /// When enabled, a byte-for-byte repeat of the last consecutive frame is
+    /// dropped (`Ok(IsoTpRxEvent::None)`) instead of failing the transfer with
+    /// `SequenceError`. Gateways and BLE bridges occasionally resend frames.
+    /// Any other out-of-sequence frame is still an error.
+    pub const fn with_duplicate_tolerance(mut self, enabled: bool) -> Self {
+        self.ignore_duplicates = enabled;
+        self
+    }
+
+    /// Resets a transfer that has been idle longer than the timeout.
+    /// Returns `Err(Timeout)` if one was dropped. `now_ms` is monotonic.
+    pub fn expire(&mut self, now_ms: u64) -> Result<(), IsoTpRxError> {
+        match self.timeout_ms {
+            Some(timeout)
+                if self.state == RxState::Receiving
+                    && now_ms.saturating_sub(self.last_frame_ms) > timeout =>
+            {
+                self.reset();
+                Err(IsoTpRxError::Timeout)
+            }
+            _ => Ok(()),
         }
     }
 
+    /// Like [`IsoTpRx::on_frame`], but first drops a stale transfer. A
+    /// consecutive frame arriving after the timeout yields `Err(Timeout)`,
+    /// a single or first frame simply starts over.
+    pub fn on_frame_at(&mut self, now_ms: u64, data: &[u8]) -> Result<IsoTpRxEvent, IsoTpRxError> {
+        let expired = self.expire(now_ms);
+        self.last_frame_ms = now_ms;
+
+        let consecutive =
+            data.first().and_then(|b| IsoTpPciType::from_u8(*b)) == Some(IsoTpPciType::Consecutive);
+        if consecutive {
+            expired?;
+        }
+        self.on_frame(data)
+    }
+
     /// Clear current state and buffer.
     pub fn reset(&mut self) {
         self.state = RxState::Idle;
         self.expected_len = None;
         self.used = 0;
         self.next_sn = 0;
+        self.last_cf_len = 0;
         // buffer content can stay as-is; `used` is what matters.
     }
 
@@ -237,7 +336,8 @@ impl IsoTpRx {
             return Err(IsoTpRxError::LengthMismatch);
         }
 
-        if data.len() != 1 + sf_len {
+        // Anything past SF_DL is padding, e.g. from senders that pad to DLC 8
+        if data.len() < 1 + sf_len {
             return Err(IsoTpRxError::LengthMismatch);
         }
 
@@ -291,7 +391,17 @@ impl IsoTpRx {
         Ok(None)
     }
 
+    fn is_duplicate_cf(&self, data: &[u8]) -> bool {
+        self.ignore_duplicates
+            && self.last_cf_len > 0
+            && data == &self.last_cf[..self.last_cf_len as usize]
+    }
+
     fn handle_consecutive(&mut self, data: &[u8]) -> Result<IsoTpRxEvent, IsoTpRxError> {
+        if self.is_duplicate_cf(data) {
+            return Ok(IsoTpRxEvent::None);
+        }
+
         if self.state != RxState::Receiving {
             return Err(IsoTpRxError::UnexpectedFrameType {
                 expected: "First Frame before ConsecutiveFrame",
@@ -337,6 +447,8 @@ impl IsoTpRx {
         self.used += copy_len;
 
         self.next_sn = (self.next_sn + 1) & 0x0F;
+        self.last_cf[..data.len()].copy_from_slice(data);
+        self.last_cf_len = data.len() as u8;
 
         if self.used > expected_len {
             return Err(IsoTpRxError::Overflow);
@@ -353,12 +465,248 @@ impl IsoTpRx {
     }
 }
 
+/// Flow status, low nibble of a flow control frame's PCI byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowStatus {
+    /// Clear to send, the next block may follow
+    ContinueToSend,
+    /// Receiver is busy, wait for another flow control frame
+    Wait,
+    /// Receiver can't take the announced length, abort the transfer
+    Overflow,
+}
+
+impl FlowStatus {
+    pub fn from_u8(fs: u8) -> Option<Self> {
+        match fs {
+            0 => Some(FlowStatus::ContinueToSend),
+            1 => Some(FlowStatus::Wait),
+            2 => Some(FlowStatus::Overflow),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            FlowStatus::ContinueToSend => 0,
+            FlowStatus::Wait => 1,
+            FlowStatus::Overflow => 2,
+        }
+    }
+}
+
+/// Parsed flow control frame (FS, BS, STmin)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowControl {
+    pub status: FlowStatus,
+    /// Consecutive frames before the next flow control, 0 = no limit
+    pub block_size: u8,
+    /// Raw STmin byte, see [`FlowControl::st_min_us`]
+    pub st_min: u8,
+}
+
+impl FlowControl {
+    pub const fn cts(block_size: u8, st_min: u8) -> Self {
+        FlowControl {
+            status: FlowStatus::ContinueToSend,
+            block_size,
+            st_min,
+        }
+    }
+
+    /// Minimum gap between consecutive frames. 0x00-0x7F are milliseconds,
+    /// 0xF1-0xF9 are 100-900 us, reserved values mean the 127 ms maximum.
+    pub fn st_min_us(&self) -> u32 {
+        match self.st_min {
+            ms @ 0x00..=0x7F => ms as u32 * 1000,
+            us @ 0xF1..=0xF9 => (us - 0xF0) as u32 * 100,
+            _ => 127_000,
+        }
+    }
+
+    pub fn to_frame(&self) -> IsoTpFrame {
+        let mut data = [0u8; 8];
+        data[0] = 0x30 | self.status.to_u8();
+        data[1] = self.block_size;
+        data[2] = self.st_min;
+        IsoTpFrame { len: 3, data }
+    }
+}
+
+impl TryFrom<&IsoTpFrame> for FlowControl {
+    type Error = IsoTpRxError;
+
+    fn try_from(frame: &IsoTpFrame) -> Result<Self, Self::Error> {
+        let data = frame.as_slice();
+        match frame.pci_type() {
+            Some(IsoTpPciType::FlowControl) => {}
+            Some(got) => {
+                return Err(IsoTpRxError::UnexpectedFrameType {
+                    expected: "FlowControl",
+                    got,
+                });
+            }
+            None => return Err(IsoTpRxError::UnknownPciType),
+        }
+        if data.len() < 3 {
+            return Err(IsoTpRxError::LengthMismatch);
+        }
+        let fs = data[0] & 0x0F;
+        Ok(FlowControl {
+            status: FlowStatus::from_u8(fs).ok_or(IsoTpRxError::InvalidFlowStatus(fs))?,
+            block_size: data[1],
+            st_min: data[2],
+        })
+    }
+}
+
 pub fn make_flow_control_cts(block_size: u8, st_min: u8) -> IsoTpFrame {
-    let mut data = [0u8; 8];
+    FlowControl::cts(block_size, st_min).to_frame()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_transfer_times_out() {
+        let mut rx = IsoTpRx::with_timeout(1000);
+        let first = [0x10, 0x0A, 1, 2, 3, 4, 5, 6];
+
+        assert_eq!(
+            rx.on_frame_at(0, &first),
+            Ok(IsoTpRxEvent::FlowControlRequired)
+        );
+        assert_eq!(rx.expire(500), Ok(()));
+        assert_eq!(
+            rx.on_frame_at(2000, &[0x21, 7, 8, 9, 10]),
+            Err(IsoTpRxError::Timeout)
+        );
+
+        // A new transfer after a timeout starts cleanly
+        assert_eq!(
+            rx.on_frame_at(5000, &first),
+            Ok(IsoTpRxEvent::FlowControlRequired)
+        );
+        assert_eq!(
+            rx.on_frame_at(5100, &[0x21, 7, 8, 9, 10]),
+            Ok(IsoTpRxEvent::Completed(10))
+        );
+    }
 
-    data[0] = 0x30; // PCI: FlowControl + FS=CTS
-    data[1] = block_size;
-    data[2] = st_min;
+    #[test]
+    fn duplicate_consecutive_frames() {
+        let first = [0x10, 0x10, 1, 2, 3, 4, 5, 6];
+        let cf1 = [0x21, 7, 8, 9, 10, 11, 12, 13];
+        let cf2 = [0x22, 14, 15, 16];
+
+        let mut strict = IsoTpRx::new();
+        strict.on_frame(&first).unwrap();
+        strict.on_frame(&cf1).unwrap();
+        assert!(matches!(
+            strict.on_frame(&cf1),
+            Err(IsoTpRxError::SequenceError { .. })
+        ));
+
+        let mut rx = IsoTpRx::new().with_duplicate_tolerance(true);
+        rx.on_frame(&first).unwrap();
+        assert_eq!(rx.on_frame(&cf1), Ok(IsoTpRxEvent::None));
+        assert_eq!(rx.on_frame(&cf1), Ok(IsoTpRxEvent::None));
+        assert_eq!(rx.on_frame(&cf2), Ok(IsoTpRxEvent::Completed(16)));
+        // Repeat after completion is dropped too
+        assert_eq!(rx.on_frame(&cf2), Ok(IsoTpRxEvent::None));
+        assert_eq!(&rx.payload()[..3], &[1, 2, 3]);
+
+        // Same SN with different content is still an error
+        let mut rx = IsoTpRx::new().with_duplicate_tolerance(true);
+        rx.on_frame(&first).unwrap();
+        rx.on_frame(&cf1).unwrap();
+        assert!(rx.on_frame(&[0x21, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
 
-    IsoTpFrame { len: 3, data }
+    #[test]
+    fn padded_single_frame() {
+        let mut rx = IsoTpRx::new();
+        assert_eq!(
+            rx.on_frame(&[0x03, 0x7F, 0x22, 0x31, 0, 0, 0, 0]),
+            Ok(IsoTpRxEvent::Completed(3))
+        );
+        assert_eq!(rx.payload(), &[0x7F, 0x22, 0x31]);
+        assert_eq!(
+            rx.on_frame(&[0x03, 0x7F, 0x22]),
+            Err(IsoTpRxError::LengthMismatch)
+        );
+    }
+
+    #[test]
+    fn flow_control() {
+        let frame = make_flow_control_cts(8, 0xF3);
+        assert_eq!(frame.as_slice(), &[0x30, 8, 0xF3]);
+        let fc = FlowControl::try_from(&frame).unwrap();
+        assert_eq!(fc, FlowControl::cts(8, 0xF3));
+        assert_eq!(fc.st_min_us(), 300);
+        assert_eq!(FlowControl::cts(0, 0x14).st_min_us(), 20_000);
+        assert_eq!(FlowControl::cts(0, 0x80).st_min_us(), 127_000);
+
+        // Padded frames parse the same
+        let wait = IsoTpFrame::new(&[0x31, 0, 0, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC]).unwrap();
+        assert_eq!(
+            FlowControl::try_from(&wait).map(|fc| fc.status),
+            Ok(FlowStatus::Wait)
+        );
+        let overflow = FlowControl {
+            status: FlowStatus::Overflow,
+            block_size: 0,
+            st_min: 0,
+        };
+        assert_eq!(FlowControl::try_from(&overflow.to_frame()), Ok(overflow));
+
+        let bad = |bytes: &[u8]| FlowControl::try_from(&IsoTpFrame::new(bytes).unwrap());
+        assert_eq!(bad(&[0x33, 0, 0]), Err(IsoTpRxError::InvalidFlowStatus(3)));
+        assert_eq!(bad(&[0x30, 0]), Err(IsoTpRxError::LengthMismatch));
+        assert!(matches!(
+            bad(&[0x21, 1, 2]),
+            Err(IsoTpRxError::UnexpectedFrameType { .. })
+        ));
+        assert!(IsoTpFrame::new(&[]).is_none());
+        assert!(IsoTpFrame::new(&[0; 9]).is_none());
+    }
+
+    #[test]
+    fn tx_follows_flow_control() {
+        let payload: [u8; 30] = core::array::from_fn(|i| i as u8);
+        let mut tx = IsoTpTx::new(&payload);
+        assert_eq!(tx.next().unwrap().pci_type(), Some(IsoTpPciType::First));
+
+        let wait = FlowControl {
+            status: FlowStatus::Wait,
+            block_size: 0,
+            st_min: 0,
+        };
+        assert_eq!(tx.block(&wait).count(), 0);
+        assert_eq!(tx.block(&FlowControl::cts(2, 0)).count(), 2);
+        assert!(!tx.is_done());
+        // 30 bytes = 6 in the first frame + 4 consecutive frames
+        assert_eq!(tx.block(&FlowControl::cts(0, 0)).count(), 2);
+        assert!(tx.is_done());
+    }
+
+    #[test]
+    fn truncated_frames_dont_panic() {
+        let frames: [&[u8]; 4] = [
+            &[0x07, 1, 2, 3, 4, 5, 6, 7],
+            &[0x10, 0x0A, 1, 2, 3, 4, 5, 6],
+            &[0x21, 7, 8, 9, 10, 0, 0, 0],
+            &[0x30, 0, 0, 0, 0, 0, 0, 0],
+        ];
+        for frame in frames {
+            for len in 0..=frame.len() {
+                let mut rx = IsoTpRx::new();
+                let _ = rx.on_frame(&frame[..len]);
+                // And the same prefix in the middle of a transfer
+                let _ = rx.on_frame(frames[1]);
+                let _ = rx.on_frame(&frame[..len]);
+            }
+        }
+    }
 }
@@ -0,0 +1,208 @@
+//! Request latency and byte counts of a transport, to compare links
+//! (SocketCAN, RFCOMM, BLE, ...) with the same requests and to see what
+//! a change to the transport bought.
+
+use super::client::UdsTransport;
+use crate::time::Clock;
+
+/// Buckets of [`LatencyHistogram`]
+pub const LATENCY_BUCKETS: usize = 16;
+
+/// Request latencies in power-of-two millisecond buckets: bucket 0 counts
+/// requests under 1 ms, bucket `i` those from 2^(i-1) up to 2^i ms, and the
+/// last one everything from 2^14 ms up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u32; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, ms: u64) {
+        let bucket = (u64::BITS - ms.leading_zeros()) as usize;
+        let count = &mut self.counts[bucket.min(LATENCY_BUCKETS - 1)];
+        *count = count.saturating_add(1);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.counts.iter().fold(0, |n, c| n.saturating_add(*c))
+    }
+
+    /// Exclusive upper bound of bucket `i` in ms, `None` for the last
+    pub fn bucket_limit_ms(i: usize) -> Option<u64> {
+        (i < LATENCY_BUCKETS - 1).then(|| 1 << i)
+    }
+
+    /// `(upper bound in ms, count)` of every bucket, see
+    /// [`Self::bucket_limit_ms`]
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<u64>, u32)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| (Self::bucket_limit_ms(i), c))
+    }
+
+    /// Upper bound of the bucket holding the `percent` percentile. `None`
+    /// when empty, `Some(None)` when it is in the open-ended last bucket.
+    pub fn percentile(&self, percent: u8) -> Option<Option<u64>> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let rank = (u64::from(total) * u64::from(percent.min(100)))
+            .div_ceil(100)
+            .max(1);
+        let mut seen = 0u64;
+        self.buckets().find_map(|(limit, c)| {
+            seen += u64::from(c);
+            (seen >= rank).then_some(limit)
+        })
+    }
+}
+
+/// What [`MeasuringTransport`] counted. Failed requests count toward
+/// `errors` and the bytes sent only. Requests sent without waiting for an
+/// answer count toward the bytes sent only.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportStats {
+    pub requests: u32,
+    pub errors: u32,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub total_ms: u64,
+    pub min_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    pub latency: LatencyHistogram,
+}
+
+impl TransportStats {
+    /// Mean latency of the answered requests
+    pub fn mean_ms(&self) -> Option<u64> {
+        let answered = self.requests.saturating_sub(self.errors);
+        (answered > 0).then(|| self.total_ms / u64::from(answered))
+    }
+
+    fn record(&mut self, sent: usize, ms: u64, received: Option<usize>) {
+        self.requests = self.requests.saturating_add(1);
+        self.bytes_sent = self.bytes_sent.saturating_add(sent as u64);
+        let Some(received) = received else {
+            self.errors = self.errors.saturating_add(1);
+            return;
+        };
+        self.bytes_received = self.bytes_received.saturating_add(received as u64);
+        self.total_ms = self.total_ms.saturating_add(ms);
+        self.min_ms = Some(self.min_ms.map_or(ms, |m| m.min(ms)));
+        self.max_ms = Some(self.max_ms.map_or(ms, |m| m.max(ms)));
+        self.latency.record(ms);
+    }
+}
+
+/// Passes requests to `T` and times each one with `C`
+pub struct MeasuringTransport<T: UdsTransport, C: Clock> {
+    transport: T,
+    clock: C,
+    stats: TransportStats,
+}
+
+impl<T: UdsTransport, C: Clock> MeasuringTransport<T, C> {
+    pub fn new(transport: T, clock: C) -> Self {
+        Self {
+            transport,
+            clock,
+            stats: TransportStats::default(),
+        }
+    }
+
+    pub fn report(&self) -> &TransportStats {
+        &self.stats
+    }
+
+    /// Starts counting from zero, e.g. after a warm-up request
+    pub fn reset(&mut self) {
+        self.stats = TransportStats::default();
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_inner(self) -> (T, TransportStats) {
+        (self.transport, self.stats)
+    }
+}
+
+impl<T: UdsTransport, C: Clock> UdsTransport for MeasuringTransport<T, C> {
+    type Error = T::Error;
+
+    fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let start = self.clock.now();
+        let result = self.transport.request(req, resp_buf);
+        let ms = self.clock.elapsed(start);
+        self.stats
+            .record(req.len(), ms, result.as_ref().ok().copied());
+        result
+    }
+
+    /// Not timed, there is no answer to time
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.stats.bytes_sent = self.stats.bytes_sent.saturating_add(req.len() as u64);
+        self.transport.send(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::ManualClock;
+
+    /// Takes `delays` ms per request in turn, fails on 0
+    struct Link<'a> {
+        clock: &'a ManualClock,
+        delays: &'a [u64],
+        sent: usize,
+    }
+
+    impl UdsTransport for Link<'_> {
+        type Error = ();
+
+        fn request(&mut self, _req: &[u8], _resp_buf: &mut [u8]) -> Result<usize, ()> {
+            let delay = self.delays[self.sent];
+            self.sent += 1;
+            self.clock.advance(delay);
+            if delay == 0 { Err(()) } else { Ok(10) }
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn measures_requests() {
+        let clock = ManualClock::default();
+        let link = Link {
+            clock: &clock,
+            delays: &[3, 3, 40, 0, 100_000],
+            sent: 0,
+        };
+        let mut measured = MeasuringTransport::new(link, &clock);
+        let mut buf = [0u8; 16];
+        for _ in 0..5 {
+            let _ = measured.request(&[0x22, 0x80, 0x11], &mut buf);
+        }
+
+        let stats = measured.report();
+        assert_eq!((stats.requests, stats.errors), (5, 1));
+        assert_eq!((stats.bytes_sent, stats.bytes_received), (15, 40));
+        assert_eq!((stats.min_ms, stats.max_ms), (Some(3), Some(100_000)));
+        assert_eq!(stats.mean_ms(), Some(25_011));
+        assert_eq!(stats.latency.count(), 4);
+        assert_eq!(stats.latency.percentile(50), Some(Some(4)));
+        assert_eq!(stats.latency.percentile(75), Some(Some(64)));
+        assert_eq!(stats.latency.percentile(100), Some(None));
+        assert_eq!(stats.latency.buckets().filter(|(_, c)| *c > 0).count(), 3);
+
+        measured.reset();
+        assert_eq!(measured.report().latency.percentile(50), None);
+        assert_eq!(measured.report().mean_ms(), None);
+    }
+}
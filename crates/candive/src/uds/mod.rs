@@ -1,3 +1,19 @@
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
+
 pub mod client;
 pub mod isotp;
+pub mod measure;
 pub mod uds;
+
+// The PDU types and codecs are used as `candive::uds::*`, `uds::uds` stays
+// for existing code
+pub use client::{ProtocolError, UdsClientError, UdsTransport};
+pub use uds::*;
@@ -14,6 +14,15 @@ pub const DFI_PLAIN: u8 = 0x00;
 pub const DFI_COMPRESSED: u8 = 0x10;
 pub const ALFI_ADDR4_SIZE4: u8 = (4 << 4) | 4;
 
+pub const SID_DIAGNOSTIC_SESSION_CONTROL_REQ: u8 = 0x10;
+pub const SID_DIAGNOSTIC_SESSION_CONTROL_RESP: u8 = 0x50;
+
+pub const SID_ECU_RESET_REQ: u8 = 0x11;
+pub const SID_ECU_RESET_RESP: u8 = 0x51;
+
+pub const SID_TESTER_PRESENT_REQ: u8 = 0x3e;
+pub const SID_TESTER_PRESENT_RESP: u8 = 0x7e;
+
 pub const SID_RDBI_REQ: u8 = 0x22;
 pub const SID_RDBI_RESP: u8 = 0x62;
 
@@ -34,6 +43,10 @@ pub const SID_TRANSFER_EXIT_RESP: u8 = 0x77;
 
 pub const SID_NEG_RESPONSE: u8 = 0x7F;
 
+/// Top bit of a sub-function byte: the server only answers if it refuses
+/// the request
+pub const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+
 pub const DIVE_CAN_UDS_ADDR: u8 = 0x00;
 
 /// Read-only view over a UDS PDU (received message)
@@ -80,6 +93,17 @@ impl<'a> UdsPduView<'a> {
         }
     }
 
+    /// Sub-function byte after the SID, without and with the
+    /// [`SUPPRESS_POSITIVE_RESPONSE`] bit
+    pub fn sub_function(&self) -> Result<(u8, bool), UdsDecodeError> {
+        self.ensure_len(3)?;
+        let byte = self.bytes[2];
+        Ok((
+            byte & !SUPPRESS_POSITIVE_RESPONSE,
+            byte & SUPPRESS_POSITIVE_RESPONSE != 0,
+        ))
+    }
+
     /// Ensure minimum length and verify expected SID
     pub fn expect_sid(&self, sid: u8, min_len: usize) -> Result<(), UdsDecodeError> {
         self.ensure_len(min_len)?;
@@ -146,6 +170,21 @@ impl<'a> UdsPduWriter<'a> {
         Ok(())
     }
 
+    /// Push a sub-function byte, with the [`SUPPRESS_POSITIVE_RESPONSE`] bit
+    /// if `suppress_positive_response`
+    pub fn push_sub_function(
+        &mut self,
+        sub_function: u8,
+        suppress_positive_response: bool,
+    ) -> Result<(), UdsEncodeError> {
+        let bit = if suppress_positive_response {
+            SUPPRESS_POSITIVE_RESPONSE
+        } else {
+            0
+        };
+        self.push(&[(sub_function & !SUPPRESS_POSITIVE_RESPONSE) | bit])
+    }
+
     /// Build a negative response directly into this writer
     pub fn make_negative_response(
         buf: &'a mut [u8],
@@ -172,49 +211,116 @@ pub struct NegativeResponse {
     pub code: UdsErrorCode,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum UdsErrorCode {
-    GeneralReject,
-    IncorrectMessageLengthOrInvalidFormat,
-    BusyRepeatRequest,
-    ConditionsNotCorrect,
-    RequestSequenceError,
-    RequestOutOfRange,
-    AuthenticationRequired,
-    GeneralProgrammingFailure,
-    WrongBlockSequenceCounter,
-    Unknown(u8),
+macro_rules! uds_error_codes {
+    ($($code:literal => $name:ident, $text:literal;)*) => {
+        /// Negative response codes from ISO 14229-1, anything else is `Unknown`
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum UdsErrorCode {
+            $($name,)*
+            Unknown(u8),
+        }
+
+        impl UdsErrorCode {
+            pub fn from_u8(value: u8) -> Self {
+                match value {
+                    $($code => UdsErrorCode::$name,)*
+                    other => UdsErrorCode::Unknown(other),
+                }
+            }
+
+            pub fn as_u8(self) -> u8 {
+                match self {
+                    $(UdsErrorCode::$name => $code,)*
+                    UdsErrorCode::Unknown(other) => other,
+                }
+            }
+
+            /// Lowercase description as worded in ISO 14229-1
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(UdsErrorCode::$name => $text,)*
+                    UdsErrorCode::Unknown(_) => "unknown negative response code",
+                }
+            }
+        }
+    };
+}
+
+uds_error_codes! {
+    0x10 => GeneralReject, "general reject";
+    0x11 => ServiceNotSupported, "service not supported";
+    0x12 => SubFunctionNotSupported, "sub-function not supported";
+    0x13 => IncorrectMessageLengthOrInvalidFormat, "incorrect message length or invalid format";
+    0x14 => ResponseTooLong, "response too long";
+    0x21 => BusyRepeatRequest, "busy, repeat request";
+    0x22 => ConditionsNotCorrect, "conditions not correct";
+    0x24 => RequestSequenceError, "request sequence error";
+    0x25 => NoResponseFromSubnetComponent, "no response from subnet component";
+    0x26 => FailurePreventsExecutionOfRequestedAction, "failure prevents execution of requested action";
+    0x31 => RequestOutOfRange, "request out of range";
+    0x33 => SecurityAccessDenied, "security access denied";
+    0x34 => AuthenticationRequired, "authentication required";
+    0x35 => InvalidKey, "invalid key";
+    0x36 => ExceededNumberOfAttempts, "exceeded number of attempts";
+    0x37 => RequiredTimeDelayNotExpired, "required time delay not expired";
+    0x70 => UploadDownloadNotAccepted, "upload/download not accepted";
+    0x71 => TransferDataSuspended, "transfer data suspended";
+    0x72 => GeneralProgrammingFailure, "general programming failure";
+    0x73 => WrongBlockSequenceCounter, "wrong block sequence counter";
+    0x78 => ResponsePending, "request correctly received, response pending";
+    0x7E => SubFunctionNotSupportedInActiveSession, "sub-function not supported in active session";
+    0x7F => ServiceNotSupportedInActiveSession, "service not supported in active session";
+    0x81 => RpmTooHigh, "RPM too high";
+    0x82 => RpmTooLow, "RPM too low";
+    0x83 => EngineIsRunning, "engine is running";
+    0x84 => EngineIsNotRunning, "engine is not running";
+    0x85 => EngineRunTimeTooLow, "engine run time too low";
+    0x86 => TemperatureTooHigh, "temperature too high";
+    0x87 => TemperatureTooLow, "temperature too low";
+    0x88 => VehicleSpeedTooHigh, "vehicle speed too high";
+    0x89 => VehicleSpeedTooLow, "vehicle speed too low";
+    0x8A => ThrottlePedalTooHigh, "throttle/pedal too high";
+    0x8B => ThrottlePedalTooLow, "throttle/pedal too low";
+    0x8C => TransmissionRangeNotInNeutral, "transmission range not in neutral";
+    0x8D => TransmissionRangeNotInGear, "transmission range not in gear";
+    0x8F => BrakeSwitchesNotClosed, "brake switch(es) not closed";
+    0x90 => ShifterLeverNotInPark, "shifter lever not in park";
+    0x91 => TorqueConverterClutchLocked, "torque converter clutch locked";
+    0x92 => VoltageTooHigh, "voltage too high";
+    0x93 => VoltageTooLow, "voltage too low";
+    0x94 => ResourceTemporarilyNotAvailable, "resource temporarily not available";
 }
 
 impl UdsErrorCode {
-    pub fn from_u8(value: u8) -> Self {
-        match value {
-            0x10 => UdsErrorCode::GeneralReject,
-            0x13 => UdsErrorCode::IncorrectMessageLengthOrInvalidFormat,
-            0x21 => UdsErrorCode::BusyRepeatRequest,
-            0x22 => UdsErrorCode::ConditionsNotCorrect,
-            0x24 => UdsErrorCode::RequestSequenceError,
-            0x31 => UdsErrorCode::RequestOutOfRange,
-            0x34 => UdsErrorCode::AuthenticationRequired,
-            0x72 => UdsErrorCode::GeneralProgrammingFailure,
-            0x73 => UdsErrorCode::WrongBlockSequenceCounter,
-            other => UdsErrorCode::Unknown(other),
-        }
+    /// Repeating the identical request may succeed, see the client's retry in `transact`
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            UdsErrorCode::BusyRepeatRequest | UdsErrorCode::ResourceTemporarilyNotAvailable
+        )
+    }
+
+    /// Not a failure: the server is still working and will answer the same request later
+    pub fn is_response_pending(self) -> bool {
+        self == UdsErrorCode::ResponsePending
+    }
+
+    /// Rejected until the client unlocks the server with SecurityAccess/Authentication
+    pub fn is_security_related(self) -> bool {
+        matches!(
+            self,
+            UdsErrorCode::SecurityAccessDenied
+                | UdsErrorCode::AuthenticationRequired
+                | UdsErrorCode::InvalidKey
+                | UdsErrorCode::ExceededNumberOfAttempts
+                | UdsErrorCode::RequiredTimeDelayNotExpired
+        )
     }
+}
 
-    pub fn as_u8(self) -> u8 {
-        match self {
-            UdsErrorCode::GeneralReject => 0x10,
-            UdsErrorCode::IncorrectMessageLengthOrInvalidFormat => 0x13,
-            UdsErrorCode::BusyRepeatRequest => 0x21,
-            UdsErrorCode::ConditionsNotCorrect => 0x22,
-            UdsErrorCode::RequestSequenceError => 0x24,
-            UdsErrorCode::RequestOutOfRange => 0x31,
-            UdsErrorCode::AuthenticationRequired => 0x34,
-            UdsErrorCode::GeneralProgrammingFailure => 0x72,
-            UdsErrorCode::WrongBlockSequenceCounter => 0x73,
-            UdsErrorCode::Unknown(other) => other,
-        }
+impl core::fmt::Display for UdsErrorCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} (0x{:02X})", self.description(), self.as_u8())
     }
 }
 
@@ -237,6 +343,173 @@ pub trait ServiceCodec {
     fn decode_response<'a>(pdu: UdsPduView<'a>) -> Result<Self::Response<'a>, UdsDecodeError>;
 }
 
+// DiagnosticSessionControl (0x10 / 0x50)
+pub struct DiagnosticSessionControlCodec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticSessionControlReq {
+    pub session: u8,
+    pub suppress_positive_response: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticSessionControlResp<'a> {
+    pub session: u8,
+    /// Session parameter record (P2 timings), as sent
+    pub params: &'a [u8],
+}
+
+impl ServiceCodec for DiagnosticSessionControlCodec {
+    type Request<'a> = DiagnosticSessionControlReq;
+    type Response<'a> = DiagnosticSessionControlResp<'a>;
+
+    const REQ_SID: u8 = SID_DIAGNOSTIC_SESSION_CONTROL_REQ;
+    const RESP_SID: u8 = SID_DIAGNOSTIC_SESSION_CONTROL_RESP;
+
+    fn encode_request(
+        req: &Self::Request<'_>,
+        out: &mut UdsPduWriter<'_>,
+    ) -> Result<(), UdsEncodeError> {
+        out.set_header(Self::REQ_SID)?;
+        out.push_sub_function(req.session, req.suppress_positive_response)?;
+        Ok(())
+    }
+
+    fn decode_request<'a>(pdu: UdsPduView<'a>) -> Result<Self::Request<'a>, UdsDecodeError> {
+        pdu.expect_sid(Self::REQ_SID, 3)?;
+        let (session, suppress_positive_response) = pdu.sub_function()?;
+        Ok(DiagnosticSessionControlReq {
+            session,
+            suppress_positive_response,
+        })
+    }
+
+    fn encode_response(
+        resp: &Self::Response<'_>,
+        out: &mut UdsPduWriter<'_>,
+    ) -> Result<(), UdsEncodeError> {
+        out.set_header(Self::RESP_SID)?;
+        out.push(&[resp.session])?;
+        out.push(resp.params)?;
+        Ok(())
+    }
+
+    fn decode_response<'a>(pdu: UdsPduView<'a>) -> Result<Self::Response<'a>, UdsDecodeError> {
+        pdu.expect_sid(Self::RESP_SID, 3)?;
+        let session = pdu.as_bytes()[2];
+        let params = &pdu.as_bytes()[3..];
+        Ok(DiagnosticSessionControlResp { session, params })
+    }
+}
+
+// EcuReset (0x11 / 0x51)
+pub struct EcuResetCodec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcuResetReq {
+    pub reset_type: u8,
+    pub suppress_positive_response: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcuResetResp {
+    pub reset_type: u8,
+}
+
+impl ServiceCodec for EcuResetCodec {
+    type Request<'a> = EcuResetReq;
+    type Response<'a> = EcuResetResp;
+
+    const REQ_SID: u8 = SID_ECU_RESET_REQ;
+    const RESP_SID: u8 = SID_ECU_RESET_RESP;
+
+    fn encode_request(
+        req: &Self::Request<'_>,
+        out: &mut UdsPduWriter<'_>,
+    ) -> Result<(), UdsEncodeError> {
+        out.set_header(Self::REQ_SID)?;
+        out.push_sub_function(req.reset_type, req.suppress_positive_response)?;
+        Ok(())
+    }
+
+    fn decode_request<'a>(pdu: UdsPduView<'a>) -> Result<Self::Request<'a>, UdsDecodeError> {
+        pdu.expect_sid(Self::REQ_SID, 3)?;
+        let (reset_type, suppress_positive_response) = pdu.sub_function()?;
+        Ok(EcuResetReq {
+            reset_type,
+            suppress_positive_response,
+        })
+    }
+
+    fn encode_response(
+        resp: &Self::Response<'_>,
+        out: &mut UdsPduWriter<'_>,
+    ) -> Result<(), UdsEncodeError> {
+        out.set_header(Self::RESP_SID)?;
+        out.push(&[resp.reset_type])?;
+        Ok(())
+    }
+
+    fn decode_response<'a>(pdu: UdsPduView<'a>) -> Result<Self::Response<'a>, UdsDecodeError> {
+        pdu.expect_sid(Self::RESP_SID, 3)?;
+        Ok(EcuResetResp {
+            reset_type: pdu.as_bytes()[2],
+        })
+    }
+}
+
+// TesterPresent (0x3E / 0x7E), sub-function 0 is the only one defined
+pub struct TesterPresentCodec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TesterPresentReq {
+    pub suppress_positive_response: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TesterPresentResp;
+
+impl ServiceCodec for TesterPresentCodec {
+    type Request<'a> = TesterPresentReq;
+    type Response<'a> = TesterPresentResp;
+
+    const REQ_SID: u8 = SID_TESTER_PRESENT_REQ;
+    const RESP_SID: u8 = SID_TESTER_PRESENT_RESP;
+
+    fn encode_request(
+        req: &Self::Request<'_>,
+        out: &mut UdsPduWriter<'_>,
+    ) -> Result<(), UdsEncodeError> {
+        out.set_header(Self::REQ_SID)?;
+        out.push_sub_function(0x00, req.suppress_positive_response)?;
+        Ok(())
+    }
+
+    fn decode_request<'a>(pdu: UdsPduView<'a>) -> Result<Self::Request<'a>, UdsDecodeError> {
+        pdu.expect_sid(Self::REQ_SID, 3)?;
+        match pdu.sub_function()? {
+            (0x00, suppress_positive_response) => Ok(TesterPresentReq {
+                suppress_positive_response,
+            }),
+            _ => Err(UdsDecodeError::InvalidFormat),
+        }
+    }
+
+    fn encode_response(
+        _: &Self::Response<'_>,
+        out: &mut UdsPduWriter<'_>,
+    ) -> Result<(), UdsEncodeError> {
+        out.set_header(Self::RESP_SID)?;
+        out.push(&[0x00])?;
+        Ok(())
+    }
+
+    fn decode_response<'a>(pdu: UdsPduView<'a>) -> Result<Self::Response<'a>, UdsDecodeError> {
+        pdu.expect_sid(Self::RESP_SID, 3)?;
+        Ok(TesterPresentResp)
+    }
+}
+
 // ReadByIdentifier (0x22 / 0x62)
 pub struct ReadByIdentifierCodec;
 
@@ -583,3 +856,89 @@ impl ServiceCodec for TransferExitCodec {
         Ok(TransferExitResp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every prefix of a PDU long enough for any service, with the right SID
+    fn prefixes(sid: u8, mut check: impl FnMut(UdsPduView<'_>)) {
+        let mut full = [0xA5u8; 16];
+        full[0] = DIVE_CAN_UDS_ADDR;
+        full[1] = sid;
+        full[2] = DFI_PLAIN;
+        full[3] = ALFI_ADDR4_SIZE4;
+        for len in 0..=full.len() {
+            check(UdsPduView::new(&full[..len]));
+        }
+    }
+
+    fn codec_survives_truncation<C: ServiceCodec>() {
+        prefixes(C::REQ_SID, |pdu| {
+            let _ = C::decode_request(pdu);
+        });
+        prefixes(C::RESP_SID, |pdu| {
+            let _ = C::decode_response(pdu);
+        });
+    }
+
+    #[test]
+    fn truncated_pdus_dont_panic() {
+        codec_survives_truncation::<ReadByIdentifierCodec>();
+        codec_survives_truncation::<WriteByIdentifierCodec>();
+        codec_survives_truncation::<RequestDownloadCodec>();
+        codec_survives_truncation::<RequestUploadCodec>();
+        codec_survives_truncation::<TransferDataCodec>();
+        codec_survives_truncation::<TransferExitCodec>();
+        codec_survives_truncation::<DiagnosticSessionControlCodec>();
+        codec_survives_truncation::<EcuResetCodec>();
+        codec_survives_truncation::<TesterPresentCodec>();
+
+        prefixes(SID_NEG_RESPONSE, |pdu| {
+            let _ = pdu.sid();
+            let _ = pdu.check_positive();
+        });
+    }
+
+    #[test]
+    fn suppress_positive_response_bit() {
+        let mut buf = [0u8; 8];
+        let mut out = UdsPduWriter::new(&mut buf);
+        let req = TesterPresentReq {
+            suppress_positive_response: true,
+        };
+        TesterPresentCodec::encode_request(&req, &mut out).unwrap();
+        assert_eq!(out.as_bytes(), [DIVE_CAN_UDS_ADDR, 0x3E, 0x80]);
+        let view = UdsPduView::new(out.as_bytes());
+        assert_eq!(view.sub_function(), Ok((0x00, true)));
+        assert_eq!(TesterPresentCodec::decode_request(view), Ok(req));
+
+        let mut out = UdsPduWriter::new(&mut buf);
+        let req = DiagnosticSessionControlReq {
+            session: 0x02,
+            suppress_positive_response: false,
+        };
+        DiagnosticSessionControlCodec::encode_request(&req, &mut out).unwrap();
+        assert_eq!(out.as_bytes(), [DIVE_CAN_UDS_ADDR, 0x10, 0x02]);
+        assert_eq!(
+            DiagnosticSessionControlCodec::decode_request(UdsPduView::new(out.as_bytes())),
+            Ok(req)
+        );
+
+        // The bit never leaks into the sub-function value
+        let mut out = UdsPduWriter::new(&mut buf);
+        out.set_header(SID_ECU_RESET_REQ).unwrap();
+        out.push_sub_function(0x81, false).unwrap();
+        assert_eq!(
+            EcuResetCodec::decode_request(UdsPduView::new(out.as_bytes())),
+            Ok(EcuResetReq {
+                reset_type: 0x01,
+                suppress_positive_response: false
+            })
+        );
+        assert_eq!(
+            TesterPresentCodec::decode_request(UdsPduView::new(&[0x00, 0x3E, 0x01])),
+            Err(UdsDecodeError::InvalidFormat)
+        );
+    }
+}
@@ -24,11 +24,26 @@ pub struct Decivolt(u8);
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PpO2Deci(u8);
 
-// x100 (99 => 0.99)
+// x100 (99 => 0.99, 100 => 1.00)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Fo2(u8);
 
+/// Whole percent, e.g. battery charge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Percent(u8);
+
+/// Dimensionless fraction × 100 (e.g. 21 => 0.21, 100 => 1.00)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ratio(u8);
+
+/// Depth in metres × 10 (e.g. 125 => 12.5 m)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Decimeter(u16);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Millisecond(u16);
@@ -101,6 +116,33 @@ impl Fo2 {
         self.0
     }
 }
+impl Fo2 {
+    pub const fn percent(self) -> Percent {
+        Percent(self.0)
+    }
+    pub const fn ratio(self) -> Ratio {
+        Ratio(self.0)
+    }
+}
+impl Percent {
+    pub const fn new(v: u8) -> Self {
+        Self(v)
+    }
+    pub const fn raw(self) -> u8 {
+        self.0
+    }
+}
+impl Ratio {
+    pub const fn new(v: u8) -> Self {
+        Self(v)
+    }
+    pub const fn raw(self) -> u8 {
+        self.0
+    }
+    pub const fn percent(self) -> Percent {
+        Percent(self.0)
+    }
+}
 impl CentiMillivolt {
     pub const fn new(v: u16) -> Self {
         Self(v)
@@ -109,6 +151,21 @@ impl CentiMillivolt {
         self.0
     }
 }
+impl Decimeter {
+    pub const fn new(v: u16) -> Self {
+        Self(v)
+    }
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
+}
+impl Decimeter {
+    /// Seawater depth from ambient pressure above surface, at 100 mbar per
+    /// metre (EN 13319). 0 if `current` is below `surface`.
+    pub const fn from_pressures(surface: Millibar, current: Millibar) -> Self {
+        Self(current.0.saturating_sub(surface.0) / 10)
+    }
+}
 
 impl From<u16> for Millibar {
     fn from(v: u16) -> Self {
@@ -198,6 +255,28 @@ impl From<Fo2> for u8 {
     }
 }
 
+impl From<u8> for Percent {
+    fn from(v: u8) -> Self {
+        Self::new(v)
+    }
+}
+impl From<Percent> for u8 {
+    fn from(v: Percent) -> u8 {
+        v.raw()
+    }
+}
+
+impl From<u8> for Ratio {
+    fn from(v: u8) -> Self {
+        Self::new(v)
+    }
+}
+impl From<Ratio> for u8 {
+    fn from(v: Ratio) -> u8 {
+        v.raw()
+    }
+}
+
 impl From<u16> for CentiMillivolt {
     fn from(v: u16) -> Self {
         Self::new(v)
@@ -208,3 +287,14 @@ impl From<CentiMillivolt> for u16 {
         v.raw()
     }
 }
+
+impl From<u16> for Decimeter {
+    fn from(v: u16) -> Self {
+        Self::new(v)
+    }
+}
+impl From<Decimeter> for u16 {
+    fn from(v: Decimeter) -> u16 {
+        v.raw()
+    }
+}
@@ -0,0 +1,195 @@
+//! Decodes every capture and log in `tests/fixtures/` and fails on unknown
+//! kinds or decode errors not covered by `fixtures/allowlist.txt`, and on
+//! messages from a sender their kind isn't expected from. See the README
+//! there for the formats.
+
+use candive::divecan::{DiveCanFrame, DiveCanId, DlcPolicy, Msg};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+type Frames = Vec<(DiveCanId, DiveCanFrame)>;
+type LineParser = fn(&str) -> Option<(u32, Vec<u8>)>;
+
+struct Allowed {
+    file: String,
+    kind: u8,
+    used: bool,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn load_allowlist(dir: &Path) -> Vec<Allowed> {
+    let Ok(text) = fs::read_to_string(dir.join("allowlist.txt")) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let mut parts = l.split_whitespace();
+            let file = parts.next().unwrap().to_string();
+            let kind = parts.next().expect("allowlist line needs a kind");
+            let kind = u8::from_str_radix(kind.trim_start_matches("0x"), 16)
+                .unwrap_or_else(|_| panic!("bad kind in allowlist line {:?}", l));
+            Allowed {
+                file,
+                kind,
+                used: false,
+            }
+        })
+        .collect()
+}
+
+fn frame(id: u32, data: &[u8]) -> Option<(DiveCanId, DiveCanFrame)> {
+    let id = DiveCanId::try_from_u32(id)?;
+    let mut payload = [0u8; 8];
+    payload[..data.len()].copy_from_slice(data);
+    Some((
+        id,
+        DiveCanFrame::new(id.kind, data.len() as u8, payload).ok()?,
+    ))
+}
+
+/// `(1700000000.000000) can0 0D040004#00141514`
+fn parse_candump_log(line: &str) -> Option<(u32, Vec<u8>)> {
+    let (id, data) = line.split_whitespace().nth(2)?.split_once('#')?;
+    Some((u32::from_str_radix(id, 16).ok()?, hex::decode(data).ok()?))
+}
+
+/// `  can0  0D040004   [4]  00 14 15 14`
+fn parse_candump(line: &str) -> Option<(u32, Vec<u8>)> {
+    let mut parts = line.split_whitespace().skip(1);
+    let id = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let _dlc = parts.next()?;
+    Some((id, hex::decode(parts.collect::<String>()).ok()?))
+}
+
+fn bus_frames(path: &Path, parse: LineParser) -> Frames {
+    fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| {
+            let (id, data) =
+                parse(l).unwrap_or_else(|| panic!("{}: can't parse {:?}", path.display(), l));
+            frame(id, &data)
+        })
+        .collect()
+}
+
+#[cfg(feature = "diagnostics")]
+fn log_frames(data: &[u8]) -> Frames {
+    use candive::diag::solo::{LogEntryIterator, LogProfile};
+    LogEntryIterator::new(data)
+        .map(|e| e.to_frame(&LogProfile::SOLO))
+        .collect()
+}
+
+#[cfg(feature = "diagnostics")]
+fn hex_log(path: &Path) -> Vec<u8> {
+    let text = fs::read_to_string(path).unwrap();
+    let hex: String = text
+        .lines()
+        .map(|l| l.split('#').next().unwrap())
+        .flat_map(str::split_whitespace)
+        .collect();
+    hex::decode(hex).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn frames(path: &Path) -> Option<Frames> {
+    match path.extension()?.to_str()? {
+        "log" => Some(bus_frames(path, parse_candump_log)),
+        "candump" => Some(bus_frames(path, parse_candump)),
+        #[cfg(feature = "diagnostics")]
+        "hex" => Some(log_frames(&hex_log(path))),
+        #[cfg(feature = "diagnostics")]
+        "bin" => Some(log_frames(&fs::read(path).unwrap())),
+        _ => None,
+    }
+}
+
+#[test]
+fn fixtures_decode() {
+    let dir = fixtures_dir();
+    let mut allowlist = load_allowlist(&dir);
+    let mut failures = Vec::new();
+    let mut decoded = 0;
+    let mut checked = Vec::new();
+
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let Some(frames) = frames(&path) else {
+            continue;
+        };
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        // Logs don't record the sender, their ids come from the LogProfile
+        let real_ids = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("log" | "candump")
+        );
+        checked.push(name.clone());
+        for (i, (id, frame)) in frames.iter().enumerate() {
+            match Msg::try_from_frame_with(frame, DlcPolicy::Strict) {
+                Ok(decoded_frame) => {
+                    decoded += 1;
+                    let direction = decoded_frame.msg.expected_direction();
+                    if real_ids && !direction.plausible_src(id.src) {
+                        failures.push(format!(
+                            "{} frame {}: {} from 0x{:02X}, expected {:?}",
+                            name,
+                            i,
+                            decoded_frame.msg.name(),
+                            id.src,
+                            direction
+                        ));
+                    }
+                    if let Msg::Serial(serial) = decoded_frame.msg {
+                        assert!(
+                            serial.starts_with(b"0000"),
+                            "{} frame {}: serial {:?} isn't anonymized",
+                            name,
+                            i,
+                            String::from_utf8_lossy(&serial)
+                        );
+                    }
+                }
+                Err(e) => {
+                    let allowed = allowlist
+                        .iter_mut()
+                        .find(|a| (a.file == name || a.file == "*") && a.kind == id.kind);
+                    match allowed {
+                        Some(a) => a.used = true,
+                        None => failures.push(format!(
+                            "{} frame {}: {:08X} {:02X?}: {:?}",
+                            name,
+                            i,
+                            id.to_u32(),
+                            frame.bytes(),
+                            e
+                        )),
+                    }
+                }
+            }
+        }
+    }
+
+    // Entries for fixtures this feature set can't read are left alone
+    let stale = allowlist
+        .iter()
+        .filter(|a| !a.used && (a.file == "*" || checked.contains(&a.file)));
+    for a in stale {
+        failures.push(format!(
+            "allowlist entry {} {:02X} no longer needed",
+            a.file, a.kind
+        ));
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    assert!(decoded > 0, "no fixtures in {}", dir.display());
+}
@@ -0,0 +1,460 @@
+//! Differential tests for the bit packers: each encoder and decoder is
+//! checked against a reference written as plain bit loops from the field
+//! table, over inputs grown coverage first. An input is kept in the corpus
+//! when it shows a field value not seen before and later inputs are mutated
+//! from the corpus, so every value of every narrow field is reached without
+//! walking the whole input space. Seeds are fixed, a failure replays.
+
+use candive::divecan::{DiveCanFrame, DiveCanId, Msg};
+use candive::rng::Xoshiro128;
+use std::collections::HashSet;
+
+/// Inputs tried per packer, on top of the edge cases
+const ROUNDS: usize = 20_000;
+
+/// Bits `lo..lo + len` of `word`, least significant first
+fn bits(word: u32, lo: u32, len: u32) -> u32 {
+    let mut v = 0;
+    for i in 0..len {
+        v |= ((word >> (lo + i)) & 1) << i;
+    }
+    v
+}
+
+fn set_bits(word: &mut u32, lo: u32, len: u32, value: u32) {
+    for i in 0..len {
+        let bit = 1 << (lo + i);
+        if (value >> i) & 1 == 1 {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+}
+
+/// `len` bits from bit `start` of `bytes`, most significant first as the
+/// wire formats are big endian
+fn be_bits(bytes: &[u8], start: usize, len: usize) -> u64 {
+    let mut v = 0;
+    for i in start..start + len {
+        v = (v << 1) | ((bytes[i / 8] >> (7 - i % 8)) & 1) as u64;
+    }
+    v
+}
+
+fn set_be_bits(bytes: &mut [u8], start: usize, len: usize, value: u64) {
+    for i in 0..len {
+        let bit = start + len - 1 - i;
+        let mask = 1 << (7 - bit % 8);
+        if (value >> i) & 1 == 1 {
+            bytes[bit / 8] |= mask;
+        } else {
+            bytes[bit / 8] &= !mask;
+        }
+    }
+}
+
+/// Coverage-first input generation over `N`-byte inputs. `fields` maps an
+/// input to the (field, value) pairs it exercises.
+struct Fuzzer<const N: usize> {
+    rng: Xoshiro128,
+    corpus: Vec<[u8; N]>,
+    seen: HashSet<(usize, u64)>,
+}
+
+impl<const N: usize> Fuzzer<N> {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: Xoshiro128::from_seed(seed),
+            corpus: vec![[0; N], [0xFF; N]],
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Runs `check` on the edge cases and `ROUNDS` generated inputs, then
+    /// returns how many distinct (field, value) pairs were reached
+    fn run(
+        &mut self,
+        fields: impl Fn(&[u8; N]) -> Vec<(usize, u64)>,
+        mut check: impl FnMut(&[u8; N]),
+    ) -> usize {
+        for input in self.corpus.clone() {
+            self.seen.extend(fields(&input));
+            check(&input);
+        }
+        for _ in 0..ROUNDS {
+            let input = self.next_input();
+            let new: Vec<_> = fields(&input)
+                .into_iter()
+                .filter(|f| !self.seen.contains(f))
+                .collect();
+            if !new.is_empty() {
+                self.seen.extend(new);
+                self.corpus.push(input);
+            }
+            check(&input);
+        }
+        self.seen.len()
+    }
+
+    /// A corpus entry with a few bits flipped, or fresh random bytes
+    fn next_input(&mut self) -> [u8; N] {
+        let mut input = [0u8; N];
+        if self.rng.below(4) == 0 {
+            self.rng.fill(&mut input);
+            return input;
+        }
+        input = self.corpus[self.rng.below(self.corpus.len() as u32) as usize];
+        for _ in 0..=self.rng.below(3) {
+            let bit = self.rng.below(N as u32 * 8) as usize;
+            input[bit / 8] ^= 1 << (bit % 8);
+        }
+        input
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+mod diag {
+    use super::*;
+    use candive::diag::did::DataIdentifier;
+    use candive::diag::did::solo::{
+        CalibrationProcedure, CellMode, ControlConfig, PPO2ControlMode,
+    };
+    use candive::diag::settings::{SettingValue, UserSettingType};
+
+    /// ControlConfig bit fields (low bit, width), as in the DID description
+    const CALIBRATION: (u32, u32) = (0, 2);
+    const PPO2_MODE: (u32, u32) = (2, 2);
+    const CELLS: (u32, u32) = (4, 2);
+    const DEPTH_COMP: (u32, u32) = (6, 2);
+    const SOL_MIN: (u32, u32) = (8, 4);
+    const SOL_MAX_LO: (u32, u32) = (12, 4);
+    const BATT_MIN: (u32, u32) = (16, 4);
+    const RESERVED_20: (u32, u32) = (20, 2);
+    const DOUBLING: (u32, u32) = (22, 1);
+    const SOL_MAX_HI: (u32, u32) = (23, 1);
+    const RESERVED_24: (u32, u32) = (24, 8);
+    const CONFIG_FIELDS: [(u32, u32); 11] = [
+        CALIBRATION,
+        PPO2_MODE,
+        CELLS,
+        DEPTH_COMP,
+        SOL_MIN,
+        SOL_MAX_LO,
+        BATT_MIN,
+        RESERVED_20,
+        DOUBLING,
+        SOL_MAX_HI,
+        RESERVED_24,
+    ];
+
+    fn field(word: u32, (lo, len): (u32, u32)) -> u32 {
+        bits(word, lo, len)
+    }
+
+    fn ref_decode_config(word: u32) -> ControlConfig {
+        let doubling = field(word, DOUBLING) == 1;
+        let batt = 50 + 2 * field(word, BATT_MIN) as u16;
+        let sol_max = field(word, SOL_MAX_HI) << 4 | field(word, SOL_MAX_LO);
+        ControlConfig {
+            calibration_procedure: match field(word, CALIBRATION) {
+                1 => CalibrationProcedure::Monitored,
+                _ => CalibrationProcedure::Direct,
+            },
+            ppo2_control_mode: match field(word, PPO2_MODE) {
+                0 => PPO2ControlMode::UserSelect,
+                1 => PPO2ControlMode::Manual,
+                2 => PPO2ControlMode::OneSec,
+                _ => PPO2ControlMode::FiveSec,
+            },
+            cell_mode: match field(word, CELLS) {
+                1 => CellMode::ThreeCell,
+                _ => CellMode::TwoCell,
+            },
+            depth_compensation_enabled: field(word, DEPTH_COMP) == 1,
+            solenoid_current_min_ma: 50 + 10 * field(word, SOL_MIN) as u16,
+            solenoid_current_max_ma: 50 + 10 * sol_max as u16,
+            battery_voltage_min: if doubling { batt * 2 } else { batt },
+            battery_voltage_doubling: doubling,
+            reserved_bits_20_21: field(word, RESERVED_20) as u8,
+            reserved_bits_24_31: field(word, RESERVED_24) as u8,
+        }
+    }
+
+    fn ref_encode_config(c: &ControlConfig) -> u32 {
+        let mut word = 0;
+        let mut put = |(lo, len): (u32, u32), value: u32| set_bits(&mut word, lo, len, value);
+        put(
+            CALIBRATION,
+            match c.calibration_procedure {
+                CalibrationProcedure::Monitored => 1,
+                CalibrationProcedure::Direct => 2,
+            },
+        );
+        put(PPO2_MODE, c.ppo2_control_mode as u32);
+        put(CELLS, (c.cell_mode == CellMode::ThreeCell) as u32);
+        put(DEPTH_COMP, if c.depth_compensation_enabled { 1 } else { 2 });
+        put(
+            SOL_MIN,
+            (c.solenoid_current_min_ma.saturating_sub(50) / 10) as u32,
+        );
+        let sol_max = (c.solenoid_current_max_ma.saturating_sub(50) / 10) as u32;
+        put(SOL_MAX_LO, sol_max);
+        put(SOL_MAX_HI, sol_max >> 4);
+        let batt = if c.battery_voltage_doubling {
+            c.battery_voltage_min / 2
+        } else {
+            c.battery_voltage_min
+        };
+        put(BATT_MIN, (batt.saturating_sub(50) / 2) as u32);
+        put(DOUBLING, c.battery_voltage_doubling as u32);
+        put(RESERVED_20, c.reserved_bits_20_21 as u32);
+        put(RESERVED_24, c.reserved_bits_24_31 as u32);
+        word
+    }
+
+    #[test]
+    fn control_config() {
+        let mut fuzzer = Fuzzer::<4>::new(1);
+        let covered = fuzzer.run(
+            |input| {
+                let word = u32::from_be_bytes(*input);
+                CONFIG_FIELDS
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &f)| (i, field(word, f) as u64))
+                    .collect()
+            },
+            |input| {
+                let word = u32::from_be_bytes(*input);
+                let config = ControlConfig::try_from(&input[..]).unwrap();
+                assert_eq!(config, ref_decode_config(word), "decode {:08X}", word);
+                let bytes = config.to_bytes();
+                assert_eq!(
+                    u32::from_be_bytes(bytes),
+                    ref_encode_config(&config),
+                    "encode {:?}",
+                    config
+                );
+                // Decoding folds unused codes, what it returns must survive
+                assert_eq!(ControlConfig::try_from(&bytes[..]).unwrap(), config);
+            },
+        );
+        let all: u32 = CONFIG_FIELDS.iter().map(|&(_, len)| 1 << len).sum();
+        assert_eq!(covered, all as usize);
+    }
+
+    fn ref_encode_setting(value: &SettingValue) -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        match *value {
+            SettingValue::SelectionIndex {
+                max_index,
+                current_index,
+            } => {
+                set_be_bits(&mut buf, 56, 8, max_index as u64);
+                set_be_bits(&mut buf, 120, 8, current_index as u64);
+            }
+            SettingValue::IntegerHex { value, min, max } => {
+                set_be_bits(&mut buf, 0, 32, min as u64);
+                set_be_bits(&mut buf, 32, 32, max as u64);
+                set_be_bits(&mut buf, 96, 32, value as u64);
+            }
+            SettingValue::IntegerScaled {
+                value,
+                divisor,
+                min,
+                max,
+            } => {
+                set_be_bits(&mut buf, 0, 32, min as u64);
+                set_be_bits(&mut buf, 32, 32, max as u64);
+                set_be_bits(&mut buf, 64, 32, divisor as u64);
+                set_be_bits(&mut buf, 96, 32, value as u64);
+            }
+        }
+        buf
+    }
+
+    fn ref_decode_setting(hint: UserSettingType, data: &[u8; 16]) -> SettingValue {
+        let word = |i: usize| be_bits(data, i * 32, 32) as u32;
+        match hint {
+            UserSettingType::Selection => SettingValue::SelectionIndex {
+                max_index: be_bits(data, 56, 8) as u8,
+                current_index: be_bits(data, 120, 8) as u8,
+            },
+            UserSettingType::Integer | UserSettingType::Scaled if word(2) == 0 => {
+                SettingValue::IntegerHex {
+                    value: word(3),
+                    min: word(0),
+                    max: word(1),
+                }
+            }
+            UserSettingType::Integer | UserSettingType::Scaled => SettingValue::IntegerScaled {
+                value: word(3),
+                divisor: word(2),
+                min: word(0),
+                max: word(1),
+            },
+        }
+    }
+
+    #[test]
+    fn setting_value() {
+        let hints = [
+            UserSettingType::Integer,
+            UserSettingType::Selection,
+            UserSettingType::Scaled,
+        ];
+        let mut fuzzer = Fuzzer::<16>::new(2);
+        fuzzer.run(
+            // The selection bytes and whether there is a divisor
+            |input| {
+                vec![
+                    (0, input[7] as u64),
+                    (1, input[15] as u64),
+                    (2, (input[8..12] == [0; 4]) as u64),
+                ]
+            },
+            |input| {
+                for hint in hints {
+                    let value = SettingValue::decode(hint, input);
+                    assert_eq!(
+                        value,
+                        ref_decode_setting(hint, input),
+                        "{:?} {:02X?}",
+                        hint,
+                        input
+                    );
+                    let bytes = value.encode();
+                    assert_eq!(bytes, ref_encode_setting(&value), "encode {:?}", value);
+                    assert_eq!(SettingValue::decode(hint, &bytes), value);
+                }
+            },
+        );
+    }
+}
+
+/// SoloStatus fields (first bit, width) in wire order
+const SOLO_STATUS: [(usize, usize); 8] = [
+    (0, 8),   // voltage
+    (8, 16),  // current
+    (24, 16), // injection duration
+    (40, 8),  // setpoint
+    (48, 8),  // consensus
+    (56, 4),  // unused
+    (60, 2),  // current alert
+    (62, 2),  // voltage alert
+];
+
+#[test]
+fn solo_status() {
+    use candive::divecan::{CurrentAlert, VoltageAlert};
+
+    let voltage_alert = |v: u64| match v {
+        0 => None,
+        1 => Some(VoltageAlert::UnderVoltage),
+        2 => Some(VoltageAlert::Clear),
+        _ => Some(VoltageAlert::OverVoltage),
+    };
+    let current_alert = |v: u64| match v {
+        0 => None,
+        1 => Some(CurrentAlert::UnderCurrent),
+        2 => Some(CurrentAlert::Clear),
+        _ => Some(CurrentAlert::OverCurrent),
+    };
+
+    let mut fuzzer = Fuzzer::<8>::new(3);
+    fuzzer.run(
+        |input| {
+            [0, 3, 4, 6, 7]
+                .iter()
+                .map(|&i| {
+                    let (start, len) = SOLO_STATUS[i];
+                    (i, be_bits(input, start, len))
+                })
+                .collect()
+        },
+        |input| {
+            let f = |i: usize| be_bits(input, SOLO_STATUS[i].0, SOLO_STATUS[i].1);
+            let frame = DiveCanFrame::new(candive::protocol::kind::SOLO_STATUS, 8, *input).unwrap();
+            let Ok(Msg::SoloStatus {
+                voltage,
+                current,
+                injection_duration,
+                setpoint,
+                consensus,
+                voltage_alert: va,
+                current_alert: ca,
+            }) = Msg::try_from_frame(&frame)
+            else {
+                panic!("{:02X?} is not a SoloStatus", input);
+            };
+            assert_eq!(voltage.raw() as u64, f(0));
+            assert_eq!(current.raw() as u64, f(1));
+            assert_eq!(injection_duration.raw() as u64, f(2));
+            assert_eq!(setpoint.raw() as u64, f(3));
+            assert_eq!(consensus.to_u8() as u64, f(4));
+            assert_eq!(va, voltage_alert(f(7)));
+            assert_eq!(ca, current_alert(f(6)));
+
+            // Everything but the unused bits comes back
+            let mut expected = *input;
+            set_be_bits(&mut expected, 56, 4, 0);
+            let msg = Msg::try_from_frame(&frame).unwrap();
+            assert_eq!(msg.to_frame().bytes(), expected);
+        },
+    );
+}
+
+/// Every known kind: whatever a payload decodes to must encode to a frame
+/// that decodes the same, and encoding keeps the kind.
+#[test]
+fn every_kind_roundtrips() {
+    for info in Msg::KINDS {
+        let dlc = info.min_dlc.min(8) as usize;
+        let mut fuzzer = Fuzzer::<8>::new(info.kind as u64);
+        fuzzer.run(
+            |input| vec![(0, input[0] as u64)],
+            |input| {
+                let mut payload = [0u8; 8];
+                payload[..dlc].copy_from_slice(&input[..dlc]);
+                let frame = DiveCanFrame::new(info.kind, dlc as u8, payload).unwrap();
+                let Ok(msg) = Msg::try_from_frame(&frame) else {
+                    return;
+                };
+                let again = msg.to_frame();
+                assert_eq!(again.kind(), info.kind, "{}", info.name);
+                assert_eq!(
+                    Msg::try_from_frame(&again),
+                    Ok(msg),
+                    "{} {:02X?}",
+                    info.name,
+                    frame.bytes()
+                );
+            },
+        );
+    }
+}
+
+#[test]
+fn can_id() {
+    let mut fuzzer = Fuzzer::<4>::new(4);
+    fuzzer.run(
+        |input| vec![(0, input[0] as u64 >> 3)],
+        |input| {
+            let raw = u32::from_be_bytes(*input) & 0x1FFF_FFFF;
+            let id = DiveCanId::from_u32(raw);
+            assert_eq!(id.src as u32, bits(raw, 0, 8));
+            assert_eq!(id.dst as u32, bits(raw, 8, 8));
+            assert_eq!(id.kind as u32, bits(raw, 16, 8));
+            let divecan = bits(raw, 24, 5) == 0x0D;
+            assert_eq!(DiveCanId::try_from_u32(raw).is_some(), divecan);
+
+            let mut expected = 0;
+            set_bits(&mut expected, 0, 8, id.src as u32);
+            set_bits(&mut expected, 8, 8, id.dst as u32);
+            set_bits(&mut expected, 16, 8, id.kind as u32);
+            set_bits(&mut expected, 24, 5, 0x0D);
+            assert_eq!(id.to_u32(), expected);
+        },
+    );
+}
@@ -0,0 +1,92 @@
+//! Feature matrix checks. Run with `--no-default-features` plus each of
+//! `alloc`, `std`, `sqlite`, `diagnostics`, `uds` and `testing` to cover every gate:
+//! each test only exists when its feature is on, and the core tests must
+//! pass with none.
+
+use candive::divecan::{DiveCanId, Msg};
+use candive::time::{Clock, Instant, ManualClock};
+
+// Implied features, checked at build time so a Cargo.toml edit can't drop one
+const _: () = assert!(!cfg!(feature = "std") || cfg!(feature = "alloc"));
+const _: () = assert!(!cfg!(feature = "sqlite") || cfg!(feature = "std"));
+const _: () = assert!(!cfg!(feature = "socketcan") || cfg!(feature = "std"));
+const _: () = assert!(!cfg!(feature = "testing") || cfg!(feature = "diagnostics"));
+
+/// Decoding, units, time and monitoring need neither std nor an allocator.
+#[test]
+fn core_without_features() {
+    let frame = Msg::Setpoint(70.into()).to_frame();
+    assert_eq!(Msg::try_from_frame(&frame), Ok(Msg::Setpoint(70.into())));
+
+    let clock = ManualClock::new(Instant::from_millis(0));
+    clock.advance(5);
+    assert_eq!(clock.now().as_millis(), 5);
+
+    let mut events = candive::monitor::EventStream::new(Default::default());
+    let mut seen = 0;
+    events.on_msg(0, DiveCanId::new(4, 0, 0x10), &Msg::Nop, |_| seen += 1);
+    assert_eq!(seen, 1);
+}
+
+/// The prelude is enough for decoding and, per feature, DIDs and UDS
+#[test]
+fn prelude() {
+    use candive::prelude::*;
+
+    let msg = Msg::Setpoint(PpO2Deci::new(13));
+    let frame = msg.to_frame();
+    let id = DiveCanId::new(0x04, 0, frame.kind());
+    assert!(matches!(
+        BusTraffic::classify(id.to_u32()),
+        BusTraffic::DiveCan(_)
+    ));
+    assert_eq!(Msg::try_from_frame(&frame), Ok(msg));
+
+    #[cfg(feature = "uds")]
+    fn _generic<T: UdsTransport>(_: &mut T) -> Result<(), UdsClientError<T::Error>> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn std_clock() {
+    let clock = candive::time::StdClock::new();
+    let start = clock.now();
+    assert!(clock.elapsed(start) < 1000);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_recorder() {
+    let recorder = candive::record::SqliteRecorder::open_in_memory().unwrap();
+    let frame = Msg::Nop.to_frame();
+    recorder
+        .record_frame(1, DiveCanId::new(4, 0, 0x10), &frame)
+        .unwrap();
+}
+
+#[cfg(feature = "diagnostics")]
+#[test]
+fn diagnostics() {
+    let mut crc = candive::diag::Stm32Crc32::new();
+    crc.append(&[0; 4]);
+    assert_eq!(
+        crc.checksum(),
+        candive::diag::Stm32Crc32::stm32_crc32(&[0; 4])
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn testing() {
+    let log = candive::testing::sample_log();
+    assert!(candive::testing::crc_matches(&log.encrypted, &log.digest));
+}
+
+#[cfg(feature = "uds")]
+#[test]
+fn uds() {
+    let tx = candive::uds::isotp::IsoTpTx::new(&[0x22, 0xF1, 0x00]);
+    assert_eq!(tx.count(), 1);
+}
@@ -0,0 +1,225 @@
+//! Golden vectors: byte vectors in `tests/fixtures/golden/` with the decoded
+//! value they must produce, written as `{:?}`. Run with `GOLDEN_BLESS=1` to
+//! fill in or update the `=` lines after adding vectors or changing a
+//! decoder, then review the diff. See the README there for the format.
+
+use candive::divecan::{DiveCanFrame, Msg};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden")
+}
+
+fn parse_hex(s: &str) -> Vec<u8> {
+    let hex: String = s.split_whitespace().collect();
+    hex::decode(&hex).unwrap_or_else(|e| panic!("bad hex {:?}: {}", s, e))
+}
+
+/// One vector: its input lines and the expected decode, if written yet
+struct Vector {
+    /// Line number of the first input line, for messages
+    line: usize,
+    inputs: Vec<(char, String)>,
+    expected: Option<String>,
+}
+
+/// Comment lines and vectors in file order, so blessing keeps the comments
+enum Item {
+    Comment(String),
+    Vector(Vector),
+}
+
+fn parse_file(text: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            items.push(Item::Comment(line.to_string()));
+            continue;
+        }
+        let (tag, rest) = trimmed.split_at(1);
+        let tag = tag.chars().next().unwrap();
+        let rest = rest.trim().to_string();
+        let open = match items.last_mut() {
+            Some(Item::Vector(v)) if v.expected.is_none() => Some(v),
+            _ => None,
+        };
+        match (tag, open) {
+            ('=', Some(v)) => v.expected = Some(rest),
+            ('=', None) => panic!("line {}: `=` without a vector", i + 1),
+            // A new input after the expected line starts the next vector
+            (_, Some(v)) if !v.inputs.iter().any(|(t, _)| *t == tag) => {
+                v.inputs.push((tag, rest));
+            }
+            _ => items.push(Item::Vector(Vector {
+                line: i + 1,
+                inputs: vec![(tag, rest)],
+                expected: None,
+            })),
+        }
+    }
+    items
+}
+
+/// Checks every vector in `name` with `decode`, or rewrites the expected
+/// lines when blessing.
+fn check(name: &str, decode: impl Fn(&Vector) -> String) {
+    let path = golden_dir().join(name);
+    let text = fs::read_to_string(&path).unwrap();
+    let items = parse_file(&text);
+    let bless = std::env::var_os("GOLDEN_BLESS").is_some();
+
+    let mut out = String::new();
+    let mut failures = Vec::new();
+    let mut vectors = 0;
+    for item in &items {
+        match item {
+            Item::Comment(line) => out += &format!("{}\n", line),
+            Item::Vector(v) => {
+                vectors += 1;
+                let actual = decode(v);
+                for (tag, input) in &v.inputs {
+                    out += &format!("{} {}\n", tag, input);
+                }
+                out += &format!("= {}\n", actual);
+                if v.expected.as_deref() != Some(actual.as_str()) {
+                    failures.push(format!(
+                        "{}:{}\n  expected: {}\n  actual:   {}",
+                        name,
+                        v.line,
+                        v.expected.as_deref().unwrap_or("(none)"),
+                        actual
+                    ));
+                }
+            }
+        }
+    }
+
+    assert!(vectors > 0, "no vectors in {}", path.display());
+    if bless {
+        if out != text {
+            fs::write(&path, out).unwrap();
+        }
+        return;
+    }
+    assert!(
+        failures.is_empty(),
+        "\n{}\n\nRun with GOLDEN_BLESS=1 to accept the new output",
+        failures.join("\n")
+    );
+}
+
+fn input(v: &Vector, tag: char) -> &str {
+    v.inputs
+        .iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, s)| s.as_str())
+        .unwrap_or_else(|| panic!("line {}: vector needs a `{}` line", v.line, tag))
+}
+
+/// `f <kind>#<hex>`, the frame as it appears on the bus
+#[test]
+fn golden_msgs() {
+    check("msgs.golden", |v| {
+        let (kind, data) = input(v, 'f')
+            .split_once('#')
+            .unwrap_or_else(|| panic!("line {}: expected <kind>#<hex>", v.line));
+        let kind = u8::from_str_radix(kind, 16).unwrap();
+        let data = parse_hex(data);
+        let mut payload = [0u8; 8];
+        payload[..data.len()].copy_from_slice(&data);
+        let frame = DiveCanFrame::new(kind, data.len() as u8, payload).unwrap();
+        format!("{:?}", Msg::try_from_frame(&frame))
+    });
+}
+
+#[cfg(all(feature = "diagnostics", feature = "uds"))]
+fn show<T>(data: &[u8]) -> String
+where
+    T: candive::diag::did::DataIdentifier + std::fmt::Debug,
+{
+    match T::try_from(data) {
+        Ok(value) => {
+            assert_eq!(
+                value.to_bytes().as_ref(),
+                data,
+                "{:04X} doesn't encode back to its bytes",
+                T::DID
+            );
+            format!("Ok({:?})", value)
+        }
+        Err(e) => format!("Err({:?})", e),
+    }
+}
+
+/// Decode of the data a DID carries, `None` for DIDs without a decoder here
+#[cfg(all(feature = "diagnostics", feature = "uds"))]
+fn decode_did(did: u16, data: &[u8]) -> Option<String> {
+    use candive::diag::did::DataIdentifier;
+    use candive::diag::did::solo::*;
+    use candive::diag::did::*;
+    use candive::diag::settings::{ReadPayload, UserSettingDid, WritePayload};
+
+    Some(match did {
+        SerialNumberAscii::DID => show::<SerialNumberAscii>(data),
+        FirmwareVersionAscii::DID => show::<FirmwareVersionAscii>(data),
+        FirmwareDownloadCapability::DID => show::<FirmwareDownloadCapability>(data),
+        LogUploadCapability::DID => show::<LogUploadCapability>(data),
+        SerialNumber::DID => show::<SerialNumber>(data),
+        DeviceId::DID => show::<DeviceId>(data),
+        EncryptedConfigBlob::DID => show::<EncryptedConfigBlob>(data),
+        CellCalibrationState::DID => show::<CellCalibrationState>(data),
+        CellCalibrationRequest::DID => show::<CellCalibrationRequest>(data),
+        CellZeroOffsets::DID => show::<CellZeroOffsets>(data),
+        CellZeroOffsetCalibrationRequest::DID => show::<CellZeroOffsetCalibrationRequest>(data),
+        FirmwareCrc::DID => show::<FirmwareCrc>(data),
+        VoltageCalibration::DID => show::<VoltageCalibration>(data),
+        ControlConfig::DID => show::<ControlConfig>(data),
+        _ => {
+            let ident = UserSettingDid::try_from(did).ok()?;
+            match ident {
+                UserSettingDid::WriteInput { .. } => format!("{:?}", WritePayload::decode(data)),
+                _ => format!("{:?}", ReadPayload::decode(ident, data)),
+            }
+        }
+    })
+}
+
+/// `> <request>` and `< <response>`, UDS PDUs with the address byte. The
+/// data is decoded from the response for RDBI and from the request for WDBI.
+#[cfg(all(feature = "diagnostics", feature = "uds"))]
+#[test]
+fn golden_dids() {
+    use candive::uds::{
+        DIVE_CAN_UDS_ADDR, SID_NEG_RESPONSE, SID_RDBI_REQ, SID_RDBI_RESP, SID_WDBI_REQ,
+        SID_WDBI_RESP,
+    };
+
+    check("dids.golden", |v| {
+        let req = parse_hex(input(v, '>'));
+        let resp = parse_hex(input(v, '<'));
+        let at = format!("line {}", v.line);
+        assert_eq!(req[0], DIVE_CAN_UDS_ADDR, "{}: request address", at);
+        assert!(req.len() >= 4, "{}: request without a DID", at);
+        let did = u16::from_be_bytes([req[2], req[3]]);
+
+        if resp[1] == SID_NEG_RESPONSE {
+            assert_eq!(resp[2], req[1], "{}: NRC for another service", at);
+            return format!("NRC 0x{:02X}", resp[3]);
+        }
+        let data = match req[1] {
+            SID_RDBI_REQ => {
+                assert_eq!(resp[1], SID_RDBI_RESP, "{}: response SID", at);
+                &resp[4..]
+            }
+            SID_WDBI_REQ => {
+                assert_eq!(resp[1], SID_WDBI_RESP, "{}: response SID", at);
+                &req[4..]
+            }
+            sid => panic!("{}: service 0x{:02X} isn't RDBI or WDBI", at, sid),
+        };
+        assert_eq!(resp[2..4], req[2..4], "{}: response for another DID", at);
+        decode_did(did, data).unwrap_or_else(|| panic!("{}: no decoder for {:04X}", at, did))
+    });
+}
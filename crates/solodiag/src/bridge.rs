@@ -0,0 +1,168 @@
+//! Raw UDS PDU tunnel over TCP, so third-party UDS tools can reach a DiveCAN
+//! node through any solodiag transport without knowing DiveCAN framing.
+//!
+//! Each PDU travels as a 2-byte big-endian length followed by the PDU, in
+//! both directions. A zero-length response means the transport failed and
+//! the request got no answer. The DiveCAN address byte that leads every
+//! request and response on the transport is added and removed here.
+
+use candive::uds::DIVE_CAN_UDS_ADDR;
+use candive::uds::client::UdsTransport;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Largest PDU the length prefix and the response buffer allow
+pub const MAX_PDU: usize = 4095;
+
+pub fn read_pdu(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let len = u16::from_be_bytes(len) as usize;
+    if len > MAX_PDU {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("PDU of {} bytes exceeds {}", len, MAX_PDU),
+        ));
+    }
+    let mut pdu = vec![0u8; len];
+    stream.read_exact(&mut pdu)?;
+    Ok(pdu)
+}
+
+pub fn write_pdu(stream: &mut impl Write, pdu: &[u8]) -> io::Result<()> {
+    let mut framed = Vec::with_capacity(pdu.len() + 2);
+    framed.extend_from_slice(&(pdu.len() as u16).to_be_bytes());
+    framed.extend_from_slice(pdu);
+    stream.write_all(&framed)?;
+    stream.flush()
+}
+
+/// Relays PDUs between one client and `transport` until the client hangs up.
+fn serve_client<T: UdsTransport>(stream: &mut TcpStream, transport: &mut T) -> io::Result<()>
+where
+    T::Error: std::fmt::Display,
+{
+    stream.set_nodelay(true)?;
+    let mut resp_buf = vec![0u8; MAX_PDU + 1];
+    let mut req = Vec::with_capacity(MAX_PDU + 1);
+    loop {
+        let pdu = match read_pdu(stream) {
+            Ok(pdu) => pdu,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if pdu.is_empty() {
+            continue;
+        }
+        req.clear();
+        req.push(DIVE_CAN_UDS_ADDR);
+        req.extend_from_slice(&pdu);
+        match transport.request(&req, &mut resp_buf) {
+            Ok(n) => write_pdu(stream, resp_buf[..n].get(1..).unwrap_or_default())?,
+            Err(e) => {
+                log::warn!("Request {} failed: {}", hex::encode_upper(&pdu), e);
+                write_pdu(stream, &[])?;
+            }
+        }
+    }
+}
+
+/// Accepts clients on `listener` one at a time and tunnels their PDUs to `transport`.
+pub fn serve<T: UdsTransport>(listener: TcpListener, transport: &mut T) -> io::Result<()>
+where
+    T::Error: std::fmt::Display,
+{
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let peer = stream.peer_addr()?;
+        log::info!("Client {} connected", peer);
+        match serve_client(&mut stream, transport) {
+            Ok(()) => log::info!("Client {} disconnected", peer),
+            Err(e) => log::warn!("Client {}: {}", peer, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdu_framing() {
+        let mut wire = Vec::new();
+        write_pdu(&mut wire, &[0x22, 0xF1, 0x00]).unwrap();
+        write_pdu(&mut wire, &[]).unwrap();
+        assert_eq!(wire, [0x00, 0x03, 0x22, 0xF1, 0x00, 0x00, 0x00]);
+
+        let mut reader = wire.as_slice();
+        assert_eq!(read_pdu(&mut reader).unwrap(), [0x22, 0xF1, 0x00]);
+        assert_eq!(read_pdu(&mut reader).unwrap(), []);
+        assert_eq!(
+            read_pdu(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        let mut oversized: &[u8] = &[0x10, 0x00];
+        assert_eq!(
+            read_pdu(&mut oversized).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    /// Answers RDBI 0x8011 and records what it was sent
+    struct Echo {
+        requests: Vec<Vec<u8>>,
+    }
+
+    impl UdsTransport for Echo {
+        type Error = String;
+
+        fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, String> {
+            self.requests.push(req.to_vec());
+            match req {
+                [DIVE_CAN_UDS_ADDR, 0x22, 0x80, 0x11] => {
+                    let resp = [DIVE_CAN_UDS_ADDR, 0x62, 0x80, 0x11, 0x2A];
+                    resp_buf[..resp.len()].copy_from_slice(&resp);
+                    Ok(resp.len())
+                }
+                _ => Err("no answer".into()),
+            }
+        }
+
+        fn send(&mut self, _: &[u8]) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn adds_and_strips_the_divecan_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write_pdu(&mut stream, &[0x22, 0x80, 0x11]).unwrap();
+            let answered = read_pdu(&mut stream).unwrap();
+            write_pdu(&mut stream, &[0x22, 0x80, 0x12]).unwrap();
+            let failed = read_pdu(&mut stream).unwrap();
+            (answered, failed)
+        });
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut transport = Echo {
+            requests: Vec::new(),
+        };
+        serve_client(&mut stream, &mut transport).unwrap();
+
+        let (answered, failed) = client.join().unwrap();
+        assert_eq!(answered, [0x62, 0x80, 0x11, 0x2A]);
+        assert_eq!(failed, []);
+        assert_eq!(
+            transport.requests,
+            [
+                vec![DIVE_CAN_UDS_ADDR, 0x22, 0x80, 0x11],
+                vec![DIVE_CAN_UDS_ADDR, 0x22, 0x80, 0x12]
+            ]
+        );
+    }
+}
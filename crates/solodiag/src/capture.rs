@@ -0,0 +1,583 @@
+//! Frames from several buses or `candump -L` files at once, merged by
+//! timestamp and tagged with the interface they came from, for dual-bus
+//! setups (e.g. the primary DiveCAN bus plus a diagnostic one).
+//!
+//! Live buses are read on one thread each and stamped when read, so frames
+//! come out in the order they arrived. Files are merged by the timestamps
+//! they recorded, and their frames are tagged with the interface named on
+//! each line, as `candump -L can0,can1` writes it.
+//!
+//! Files written by third-party tools sometimes have the CAN id bytes
+//! swapped, which makes every frame look foreign. Every file gets its own
+//! [`ByteOrderCheck`], which spots that and says so, or with
+//! [`Capture::fix_byte_order`] the ids are swapped back.
+
+use anyhow::{Result, anyhow};
+use candive::diag::anonymize::Anonymizer;
+use candive::divecan::{BusTraffic, DiveCanFrame, DiveCanId};
+use candive::monitor::BusError;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::transport::{self, BusRead, RawBus};
+use crate::unix_time_ms;
+
+/// One read from a [`Capture`]
+pub struct CaptureRead {
+    /// Index into [`Capture::names`], 0 for timeouts
+    pub source: usize,
+    pub ts_ms: u64,
+    /// `None` when no live bus had anything within the read timeout
+    pub read: Option<BusRead>,
+}
+
+enum Sources {
+    Live {
+        rx: mpsc::Receiver<Result<CaptureRead>>,
+        read_timeout: Duration,
+    },
+    Files(FileMerge),
+}
+
+pub struct Capture {
+    names: Vec<String>,
+    sources: Sources,
+}
+
+impl Capture {
+    /// Opens every input, each a `can://` or `socketcand://` transport URI or
+    /// a `candump -L` file. Live buses and files can't be mixed.
+    pub fn open(inputs: &[String], read_timeout: Duration) -> Result<Self> {
+        let live = inputs
+            .iter()
+            .filter(|i| transport::raw_bus_name(i).is_some())
+            .count();
+        if live == 0 {
+            let files = inputs
+                .iter()
+                .map(|path| {
+                    let file = File::open(path).map_err(|e| {
+                        anyhow!(
+                            "{} is neither a can:// or socketcand:// transport nor a readable candump -L file: {}",
+                            path,
+                            e
+                        )
+                    })?;
+                    Ok(LogFile::new(
+                        path,
+                        Box::new(BufReader::new(file)) as Box<dyn BufRead>,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Self {
+                names: Vec::new(),
+                sources: Sources::Files(FileMerge::new(files)),
+            });
+        }
+        if live < inputs.len() {
+            return Err(anyhow!("Can't mix live buses and capture files"));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut names = Vec::new();
+        for (source, uri) in inputs.iter().enumerate() {
+            let name = transport::raw_bus_name(uri).unwrap_or(uri).to_string();
+            let bus = RawBus::open(uri, read_timeout)
+                .map_err(|e| anyhow!("Failed to open {}: {}", name, e))?;
+            let tx = tx.clone();
+            std::thread::spawn(move || read_bus(bus, source, tx));
+            names.push(name);
+        }
+        Ok(Self {
+            names,
+            sources: Sources::Live { rx, read_timeout },
+        })
+    }
+
+    /// Swap the id bytes back in files whose frames only make sense
+    /// swapped. Live buses are never swapped.
+    pub fn fix_byte_order(mut self, fix: bool) -> Self {
+        if let Sources::Files(files) = &mut self.sources {
+            files.fix_byte_order = fix;
+        }
+        self
+    }
+
+    /// Interface names, for files only those seen so far
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Whether frames need their interface shown to be told apart
+    pub fn tagged(&self) -> bool {
+        match self.sources {
+            Sources::Live { .. } => self.names.len() > 1,
+            Sources::Files(_) => true,
+        }
+    }
+
+    /// Next read, `Ok(None)` once every file has been read
+    pub fn next(&mut self) -> Result<Option<CaptureRead>> {
+        match &mut self.sources {
+            Sources::Live { rx, read_timeout } => match rx.recv_timeout(*read_timeout) {
+                Ok(read) => read.map(Some),
+                Err(mpsc::RecvTimeoutError::Timeout) => Ok(Some(CaptureRead {
+                    source: 0,
+                    ts_ms: unix_time_ms(),
+                    read: None,
+                })),
+                Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!("every bus closed")),
+            },
+            Sources::Files(files) => {
+                let Some(line) = files.next()? else {
+                    return Ok(None);
+                };
+                let source = match self.names.iter().position(|n| *n == line.iface) {
+                    Some(i) => i,
+                    None => {
+                        self.names.push(line.iface);
+                        self.names.len() - 1
+                    }
+                };
+                Ok(Some(CaptureRead {
+                    source,
+                    ts_ms: line.ts_ms,
+                    read: Some(line.read),
+                }))
+            }
+        }
+    }
+}
+
+/// Frames looked at before deciding on the byte order
+const BYTE_ORDER_SAMPLE: u32 = 32;
+
+/// Counts frames that aren't DiveCAN as written but are with the id bytes
+/// swapped. Decides once, after [`BYTE_ORDER_SAMPLE`] frames.
+#[derive(Debug, Default)]
+pub struct ByteOrderCheck {
+    pub frames: u32,
+    pub swapped: u32,
+    decided: bool,
+}
+
+impl ByteOrderCheck {
+    /// True exactly once, when the sample is in and nearly every frame
+    /// needs swapping. Error frames don't count.
+    pub fn observe(&mut self, read: &BusRead) -> bool {
+        if self.decided {
+            return false;
+        }
+        match read {
+            BusRead::Frame(..) => self.frames += 1,
+            BusRead::Other(id, _) => {
+                self.frames += 1;
+                if swapped_divecan_id(*id).is_some() {
+                    self.swapped += 1;
+                }
+            }
+            BusRead::Error(_) => return false,
+        }
+        if self.frames < BYTE_ORDER_SAMPLE {
+            return false;
+        }
+        self.decided = true;
+        self.swapped * 10 >= self.frames * 9
+    }
+
+    /// What [`observe`](Self::observe) found, for the diagnostic
+    pub fn describe(&self, source: &str) -> String {
+        format!(
+            "{} of the first {} frames in {} only have a DiveCAN id with its bytes swapped, it was likely written with the wrong byte order",
+            self.swapped, self.frames, source
+        )
+    }
+}
+
+/// The DiveCAN id `raw` is with its bytes swapped, if it is one of a known
+/// message kind
+fn swapped_divecan_id(raw: u32) -> Option<DiveCanId> {
+    match BusTraffic::classify(raw.swap_bytes()) {
+        BusTraffic::DiveCan(id) if candive::protocol::message(id.kind).is_some() => Some(id),
+        _ => None,
+    }
+}
+
+/// `read` with the id bytes swapped back if that makes it a DiveCAN frame
+fn unswap_id(read: BusRead) -> BusRead {
+    let BusRead::Other(raw, data) = read else {
+        return read;
+    };
+    match swapped_divecan_id(raw) {
+        Some(id) if data.len() <= 8 => {
+            let mut payload = [0u8; 8];
+            payload[..data.len()].copy_from_slice(&data);
+            match DiveCanFrame::new(id.kind, data.len() as u8, payload) {
+                Ok(frame) => BusRead::Frame(id, frame),
+                Err(_) => BusRead::Other(raw, data),
+            }
+        }
+        _ => BusRead::Other(raw, data),
+    }
+}
+
+fn read_bus(bus: RawBus, source: usize, tx: mpsc::Sender<Result<CaptureRead>>) {
+    loop {
+        let read = match bus.read() {
+            Ok(Some(read)) => Ok(CaptureRead {
+                source,
+                ts_ms: unix_time_ms(),
+                read: Some(read),
+            }),
+            Ok(None) => continue,
+            Err(e) => Err(anyhow!("CAN read failed: {}", e)),
+        };
+        let failed = read.is_err();
+        if tx.send(read).is_err() || failed {
+            return;
+        }
+    }
+}
+
+struct CandumpLine {
+    ts_ms: u64,
+    iface: String,
+    read: BusRead,
+}
+
+/// `(1700000000.123456) can0 0D040004#00141514`, error frames included
+fn parse_candump_log(line: &str) -> Option<CandumpLine> {
+    let mut parts = line.split_whitespace();
+    let ts = parts.next()?.strip_prefix('(')?.strip_suffix(')')?;
+    let iface = parts.next()?.to_string();
+    let (id, data) = parts.next()?.split_once('#')?;
+
+    let (secs, frac) = ts.split_once('.').unwrap_or((ts, "0"));
+    let millis = format!("{:0<3}", frac.get(..3).unwrap_or(frac));
+    let ts_ms = secs.parse::<u64>().ok()? * 1000 + millis.parse::<u64>().ok()?;
+
+    let id = u32::from_str_radix(id, 16).ok()?;
+    let data = hex::decode(data).ok()?;
+    let read = if id & BusError::ERR_FLAG != 0 {
+        BusRead::Error(BusError::decode(id, &data))
+    } else {
+        match BusTraffic::classify(id) {
+            BusTraffic::DiveCan(id) if data.len() <= 8 => {
+                let mut payload = [0u8; 8];
+                payload[..data.len()].copy_from_slice(&data);
+                let frame = DiveCanFrame::new(id.kind, data.len() as u8, payload).ok()?;
+                BusRead::Frame(id, frame)
+            }
+            _ => BusRead::Other(id, data),
+        }
+    };
+    Some(CandumpLine { ts_ms, iface, read })
+}
+
+/// The line [`parse_candump_log`] reads back, as `candump -L` writes it
+pub fn format_candump_log(ts_ms: u64, iface: &str, read: &BusRead) -> String {
+    let (id, data) = match read {
+        BusRead::Frame(id, frame) => (id.to_u32(), frame.bytes().to_vec()),
+        BusRead::Other(id, data) => (*id, data.clone()),
+        BusRead::Error(err) => {
+            let (id, data) = err.encode();
+            (id, data.to_vec())
+        }
+    };
+    format!(
+        "({}.{:03}000) {} {:08X}#{}",
+        ts_ms / 1000,
+        ts_ms % 1000,
+        iface,
+        id,
+        hex::encode_upper(data)
+    )
+}
+
+/// Whether `head`, the start of a file, begins with a `candump -L` line
+pub fn is_candump_log(head: &[u8]) -> bool {
+    let line = head.split(|&b| b == b'\n').next().unwrap_or(head);
+    std::str::from_utf8(line).is_ok_and(|line| parse_candump_log(line).is_some())
+}
+
+/// Copies a `candump -L` capture with every DiveCAN frame and timestamp
+/// passed through `anon`, returns the frames changed and the frames copied.
+/// Other frames, error frames and interface names are copied as they are.
+/// Fails on a capture whose ids are byte-swapped, its frames would be
+/// copied unredacted.
+pub fn anonymize_candump(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    anon: &mut Anonymizer,
+) -> Result<(usize, usize)> {
+    let (mut changed, mut frames) = (0, 0);
+    let mut byte_order = ByteOrderCheck::default();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let CandumpLine { ts_ms, iface, read } =
+            parse_candump_log(&line).ok_or_else(|| anyhow!("Not a candump -L line: {:?}", line))?;
+        if byte_order.observe(&read) {
+            return Err(anyhow!(
+                "{}, swap it back with monitor --input <file> --fix-byte-order --write <file> first",
+                byte_order.describe("the capture")
+            ));
+        }
+        let read = match read {
+            BusRead::Frame(id, mut frame) => {
+                changed += usize::from(anon.anonymize_frame(id, &mut frame));
+                BusRead::Frame(id, frame)
+            }
+            other => other,
+        };
+        frames += 1;
+        let ts_ms = anon.rebase_time_ms(ts_ms);
+        writeln!(writer, "{}", format_candump_log(ts_ms, &iface, &read))?;
+    }
+    writer.flush()?;
+    Ok((changed, frames))
+}
+
+/// Merges `candump -L` logs, each already in time order, by timestamp.
+/// Lines with equal timestamps keep the order of the files.
+struct FileMerge {
+    files: Vec<LogFile>,
+    /// Swap ids back instead of checking the byte order
+    fix_byte_order: bool,
+}
+
+struct LogFile {
+    path: String,
+    lines: std::io::Lines<Box<dyn BufRead>>,
+    /// Next line, read ahead to compare timestamps
+    head: Option<CandumpLine>,
+    /// Files can come from different tools, each is checked on its own
+    byte_order: ByteOrderCheck,
+}
+
+impl LogFile {
+    fn new(path: &str, reader: Box<dyn BufRead>) -> Self {
+        Self {
+            path: path.to_string(),
+            lines: reader.lines(),
+            head: None,
+            byte_order: ByteOrderCheck::default(),
+        }
+    }
+
+    /// Next non-empty line, swapped back or checked for its byte order
+    fn read_line(&mut self, fix_byte_order: bool) -> Result<Option<CandumpLine>> {
+        for line in self.lines.by_ref() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut parsed = parse_candump_log(&line)
+                .ok_or_else(|| anyhow!("Not a candump -L line: {:?}", line))?;
+            if fix_byte_order {
+                parsed.read = unswap_id(parsed.read);
+            } else if self.byte_order.observe(&parsed.read) {
+                log::warn!(
+                    "{}. Rerun with --fix-byte-order",
+                    self.byte_order.describe(&self.path)
+                );
+            }
+            return Ok(Some(parsed));
+        }
+        Ok(None)
+    }
+}
+
+impl FileMerge {
+    fn new(files: Vec<LogFile>) -> Self {
+        Self {
+            files,
+            fix_byte_order: false,
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<CandumpLine>> {
+        for file in &mut self.files {
+            if file.head.is_none() {
+                file.head = file.read_line(self.fix_byte_order)?;
+            }
+        }
+        let earliest = self
+            .files
+            .iter_mut()
+            .filter(|file| file.head.is_some())
+            .min_by_key(|file| file.head.as_ref().unwrap().ts_ms);
+        Ok(earliest.and_then(|file| file.head.take()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(text: &'static str) -> Box<dyn BufRead> {
+        Box::new(std::io::Cursor::new(text))
+    }
+
+    fn file(text: &'static str) -> LogFile {
+        LogFile::new("test.log", reader(text))
+    }
+
+    #[test]
+    fn merges_files_by_timestamp() {
+        let primary = file(
+            "(1700000000.000000) can0 0DC9FF01#46\n\
+             (1700000000.020000) can0 0D040004#00141514\n",
+        );
+        let diag = file(
+            "(1700000000.010000) can1 0D000104#010000\n\
+             \n\
+             (1700000000.020000) can1 12345678#AA\n\
+             (1700000000.030500) can1 20000004#0004000000000000\n",
+        );
+        let mut merge = FileMerge::new(vec![primary, diag]);
+        let mut seen = Vec::new();
+        while let Some(line) = merge.next().unwrap() {
+            let what = match line.read {
+                BusRead::Frame(id, _) => format!("{:02X}", id.kind),
+                BusRead::Other(id, _) => format!("{:08X}", id),
+                BusRead::Error(_) => "error".to_string(),
+            };
+            seen.push((line.ts_ms, line.iface, what));
+        }
+        let seen: Vec<_> = seen
+            .iter()
+            .map(|(ts, iface, what)| (*ts, iface.as_str(), what.as_str()))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                (1_700_000_000_000, "can0", "C9"),
+                (1_700_000_000_010, "can1", "00"),
+                (1_700_000_000_020, "can0", "04"),
+                (1_700_000_000_020, "can1", "12345678"),
+                (1_700_000_000_030, "can1", "error"),
+            ]
+        );
+
+        let mut bad = FileMerge::new(vec![file("can0 0DC9FF01#46\n")]);
+        assert!(bad.next().is_err());
+    }
+
+    #[test]
+    fn byte_swapped_ids() {
+        let swapped = "(1700000000.020000) can0 0401110D#00141514";
+        let read = parse_candump_log(swapped).unwrap().read;
+        assert!(matches!(read, BusRead::Other(0x0401_110D, _)));
+
+        let mut check = ByteOrderCheck::default();
+        let decided: Vec<bool> = (0..BYTE_ORDER_SAMPLE + 1)
+            .map(|_| check.observe(&parse_candump_log(swapped).unwrap().read))
+            .collect();
+        assert_eq!(decided.iter().filter(|d| **d).count(), 1);
+        assert!(decided[BYTE_ORDER_SAMPLE as usize - 1]);
+
+        let BusRead::Frame(id, frame) = unswap_id(read) else {
+            panic!("not swapped back");
+        };
+        assert_eq!(id.to_u32(), 0x0D11_0104);
+        assert_eq!(frame.bytes(), [0x00, 0x14, 0x15, 0x14]);
+
+        // A healthy capture never trips it, foreign ids stay foreign
+        let mut check = ByteOrderCheck::default();
+        let fine = parse_candump_log("(1700000000.020000) can0 0D110104#00141514").unwrap();
+        assert!(!(0..64).any(|_| check.observe(&fine.read)));
+        assert!(matches!(
+            unswap_id(BusRead::Other(0x18FE_F100, vec![])),
+            BusRead::Other(0x18FE_F100, _)
+        ));
+    }
+
+    #[test]
+    fn byte_order_per_file() {
+        let swapped: String =
+            "(1700000000.020000) can1 0401110D#00141514\n".repeat(BYTE_ORDER_SAMPLE as usize);
+        let healthy = file("(1700000000.010000) can0 0D110104#00141514\n");
+        let mut merge = FileMerge::new(vec![
+            healthy,
+            LogFile::new(
+                "swapped.log",
+                Box::new(std::io::Cursor::new(swapped.clone())),
+            ),
+        ]);
+        while merge.next().unwrap().is_some() {}
+        let [healthy, swapped_file] = &merge.files[..] else {
+            panic!("two files");
+        };
+        assert_eq!(
+            (healthy.byte_order.frames, healthy.byte_order.swapped),
+            (1, 0)
+        );
+        assert_eq!(
+            (
+                swapped_file.byte_order.frames,
+                swapped_file.byte_order.swapped
+            ),
+            (BYTE_ORDER_SAMPLE, BYTE_ORDER_SAMPLE)
+        );
+
+        let mut merge = FileMerge::new(vec![LogFile::new(
+            "swapped.log",
+            Box::new(std::io::Cursor::new(swapped.clone())),
+        )]);
+        merge.fix_byte_order = true;
+        assert!(matches!(
+            merge.next().unwrap().unwrap().read,
+            BusRead::Frame(..)
+        ));
+
+        let err = anonymize_candump(
+            std::io::Cursor::new(swapped),
+            Vec::new(),
+            &mut Anonymizer::new(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("--fix-byte-order"));
+    }
+
+    #[test]
+    fn anonymizes_captures() {
+        let capture = "(1700000000.250000) can0 0DD20402#4130303544303037\n\
+                       (1700000000.500000) can1 12345678#AA\n\
+                       (1700000001.000000) can0 0D0A0402#100C006280104130\n\
+                       (1700000001.001000) can0 0D0A0402#2130354430303700\n";
+        assert!(is_candump_log(capture.as_bytes()));
+        assert!(!is_candump_log(&[0x28, 0xFF, 0x00, 0x0A]));
+
+        let mut out = Vec::new();
+        let mut anon = Anonymizer::new();
+        let counts = anonymize_candump(reader(capture), &mut out, &mut anon).unwrap();
+        assert_eq!(counts, (3, 4));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "(946684800.250000) can0 0DD20402#3030303030303031\n\
+             (946684800.500000) can1 12345678#AA\n\
+             (946684801.000000) can0 0D0A0402#100C006280100000\n\
+             (946684801.001000) can0 0D0A0402#2100000000000000\n"
+        );
+    }
+
+    #[test]
+    fn candump_lines_roundtrip() {
+        for line in [
+            "(1700000000.020000) can0 0D040004#00141514",
+            "(1700000000.005000) can1 12345678#",
+            "(1700000001.250000) can0 20000204#0030000000008204",
+        ] {
+            let parsed = parse_candump_log(line).unwrap();
+            assert_eq!(
+                format_candump_log(parsed.ts_ms, &parsed.iface, &parsed.read),
+                line
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Cell voltage and ppO₂ time series for `monitor --csv`, for cell linearity
+//! tests at the bench.
+
+use candive::cells::{CellArray, DIVECAN_CELLS};
+use candive::divecan::Msg;
+use candive::units::{CentiMillivolt, PpO2Deci};
+use std::fmt::Write;
+
+/// Keeps the latest CellVoltages and CellPpo2 broadcasts and turns them into
+/// one CSV row per sampling interval.
+pub struct CellSampler {
+    interval_ms: u64,
+    start_ms: Option<u64>,
+    next_ms: u64,
+    voltages: Option<CellArray<CentiMillivolt>>,
+    ppo2: Option<CellArray<PpO2Deci>>,
+}
+
+impl CellSampler {
+    pub fn new(interval_ms: u64) -> Self {
+        Self {
+            interval_ms: interval_ms.max(1),
+            start_ms: None,
+            next_ms: 0,
+            voltages: None,
+            ppo2: None,
+        }
+    }
+
+    /// `time_s`, then one mV column and one ppO₂ (bar) column per cell
+    pub fn header() -> String {
+        let mut header = String::from("time_s");
+        for i in 1..=DIVECAN_CELLS {
+            let _ = write!(header, ",cell{}_mv", i);
+        }
+        for i in 1..=DIVECAN_CELLS {
+            let _ = write!(header, ",cell{}_ppo2", i);
+        }
+        header
+    }
+
+    pub fn push(&mut self, msg: &Msg) {
+        match msg {
+            Msg::CellVoltages { cell_voltages, .. } => self.voltages = Some(*cell_voltages),
+            Msg::CellPpo2(cells) => self.ppo2 = Some(*cells),
+            _ => {}
+        }
+    }
+
+    /// The row for the sample due at `now_ms`, `None` if no sample is due or
+    /// no cell values have been seen yet. Values not seen yet are left empty.
+    pub fn sample(&mut self, now_ms: u64) -> Option<String> {
+        if (self.voltages.is_none() && self.ppo2.is_none()) || now_ms < self.next_ms {
+            return None;
+        }
+        let start_ms = *self.start_ms.get_or_insert(now_ms);
+        // Skip missed slots on a quiet bus instead of writing a burst
+        self.next_ms = if now_ms - self.next_ms >= self.interval_ms {
+            now_ms + self.interval_ms
+        } else {
+            self.next_ms + self.interval_ms
+        };
+
+        let elapsed = now_ms - start_ms;
+        let mut row = format!("{}.{:03}", elapsed / 1000, elapsed % 1000);
+        for i in 0..DIVECAN_CELLS {
+            row.push(',');
+            if let Some(v) = self.voltages {
+                let raw = v[i].raw();
+                let _ = write!(row, "{}.{:02}", raw / 100, raw % 100);
+            }
+        }
+        for i in 0..DIVECAN_CELLS {
+            row.push(',');
+            if let Some(p) = self.ppo2 {
+                let raw = p[i].raw();
+                let _ = write!(row, "{}.{}", raw / 10, raw % 10);
+            }
+        }
+        Some(row)
+    }
+}
+
+/// gnuplot script plotting `csv_name`, cell mV on the left axis and ppO₂ on
+/// the right
+pub fn gnuplot_script(csv_name: &str) -> String {
+    let first_ppo2 = 2 + DIVECAN_CELLS;
+    let last_ppo2 = 1 + 2 * DIVECAN_CELLS;
+    format!(
+        r#"# Written by solodiag monitor --csv, run with: gnuplot -p <this file>
+set datafile separator ","
+set key autotitle columnhead outside
+set xlabel "time (s)"
+set ylabel "cell (mV)"
+set y2label "ppO2 (bar)"
+set ytics nomirror
+set y2tics
+set grid
+plot for [i=2:{}] "{csv}" using 1:i with lines axes x1y1, \
+     for [i={}:{}] "{csv}" using 1:i with lines dashtype 2 axes x1y2
+"#,
+        first_ppo2 - 1,
+        first_ppo2,
+        last_ppo2,
+        csv = csv_name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows() {
+        let mut s = CellSampler::new(500);
+        assert_eq!(
+            CellSampler::header(),
+            "time_s,cell1_mv,cell2_mv,cell3_mv,cell1_ppo2,cell2_ppo2,cell3_ppo2"
+        );
+        assert_eq!(s.sample(1_000), None);
+
+        s.push(&Msg::CellPpo2(CellArray::new([
+            PpO2Deci::new(7),
+            PpO2Deci::new(12),
+            PpO2Deci::new(10),
+        ])));
+        assert_eq!(s.sample(1_000).as_deref(), Some("0.000,,,,0.7,1.2,1.0"));
+        assert_eq!(s.sample(1_200), None);
+
+        s.push(&Msg::CellVoltages {
+            cell_voltages: CellArray::new([
+                CentiMillivolt::new(4512),
+                CentiMillivolt::new(4498),
+                CentiMillivolt::new(905),
+            ]),
+            unused: 0,
+        });
+        assert_eq!(
+            s.sample(1_510).as_deref(),
+            Some("0.510,45.12,44.98,9.05,0.7,1.2,1.0")
+        );
+        assert!(gnuplot_script("cells.csv").contains("for [i=5:7] \"cells.csv\""));
+    }
+}
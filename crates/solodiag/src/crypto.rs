@@ -0,0 +1,102 @@
+use aes::Aes128;
+use anyhow::{Result, anyhow};
+use candive::diag::did::solo::{EncryptedConfigBlob, EncryptedConfigPayload};
+use candive::diag::solo::{BlockCipher, LogDecryptor};
+use clap::ValueEnum;
+use des::cipher::consts::{U8, U16};
+use des::cipher::generic_array::GenericArray;
+use des::cipher::{BlockEncrypt, KeyInit};
+use des::{Des, TdesEde3};
+
+/// Cipher the firmware uses with SOLO_KEY for log and config encryption
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CipherKind {
+    #[value(name = "des")]
+    Des,
+    #[value(name = "3des")]
+    TripleDes,
+    #[value(name = "aes128")]
+    Aes128,
+}
+
+impl CipherKind {
+    pub fn key_len(self) -> usize {
+        match self {
+            CipherKind::Des => 8,
+            CipherKind::TripleDes => 24,
+            CipherKind::Aes128 => 16,
+        }
+    }
+}
+
+pub struct Encryptor<C>(C);
+
+impl<C: BlockEncrypt<BlockSize = U8>> BlockCipher<8> for Encryptor<C> {
+    fn encrypt_block(&self, block: &mut [u8; 8]) {
+        self.0.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+}
+
+impl<C: BlockEncrypt<BlockSize = U16>> BlockCipher<16> for Encryptor<C> {
+    fn encrypt_block(&self, block: &mut [u8; 16]) {
+        self.0.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+}
+
+enum Cipher {
+    Des(Encryptor<Des>),
+    // Expanded key schedules are large, keep them off the stack
+    TripleDes(Box<Encryptor<TdesEde3>>),
+    Aes128(Box<Encryptor<Aes128>>),
+}
+
+pub struct SoloKey {
+    cipher: Cipher,
+    checked: bool,
+}
+
+impl SoloKey {
+    pub fn new(kind: CipherKind, key: &[u8]) -> Result<Self> {
+        let invalid = |_| anyhow!("Invalid {:?} key", kind);
+        let cipher = match kind {
+            CipherKind::Des => Cipher::Des(Encryptor(Des::new_from_slice(key).map_err(invalid)?)),
+            CipherKind::TripleDes => Cipher::TripleDes(Box::new(Encryptor(
+                TdesEde3::new_from_slice(key).map_err(invalid)?,
+            ))),
+            CipherKind::Aes128 => Cipher::Aes128(Box::new(Encryptor(
+                Aes128::new_from_slice(key).map_err(invalid)?,
+            ))),
+        };
+        Ok(Self {
+            cipher,
+            checked: true,
+        })
+    }
+
+    /// Trusts the key without decrypting a few log entries first
+    pub fn unchecked(mut self) -> Self {
+        self.checked = false;
+        self
+    }
+
+    /// Whether the key should be tried on the log before it's used
+    pub fn checked(&self) -> bool {
+        self.checked
+    }
+
+    pub fn log_decryptor(&self, device_id: &[u8], timestamp: u32) -> LogDecryptor {
+        match &self.cipher {
+            Cipher::Des(c) => LogDecryptor::new(c, device_id, timestamp),
+            Cipher::TripleDes(c) => LogDecryptor::new(&**c, device_id, timestamp),
+            Cipher::Aes128(c) => LogDecryptor::new(&**c, device_id, timestamp),
+        }
+    }
+
+    pub fn encrypt_config(&self, payload: &EncryptedConfigPayload) -> EncryptedConfigBlob {
+        match &self.cipher {
+            Cipher::Des(c) => payload.encrypt(c),
+            Cipher::TripleDes(c) => payload.encrypt(&**c),
+            Cipher::Aes128(c) => payload.encrypt(&**c),
+        }
+    }
+}
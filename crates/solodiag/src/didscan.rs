@@ -0,0 +1,164 @@
+//! RDBI scan results saved by `rdbi-scan --output`, and the differences
+//! between two of them for `dev did-diff`, e.g. before and after a firmware
+//! update.
+
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::jsonl;
+
+/// One saved scan, the DIDs that answered and their data
+pub struct DidScan {
+    pub firmware: String,
+    pub device_id: String,
+    pub dids: BTreeMap<u16, Vec<u8>>,
+}
+
+impl DidScan {
+    /// Parses the JSON lines written by `rdbi-scan --output`. A scan cut
+    /// short by Ctrl-C is still a valid file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut scan: Option<DidScan> = None;
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = jsonl::parse_object(line)
+                .ok_or_else(|| anyhow!("line {}: not a JSON object", n + 1))?;
+            let get = |key: &str| {
+                fields
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+                    .ok_or_else(|| anyhow!("line {}: missing \"{}\"", n + 1, key))
+            };
+            match get("type")? {
+                "scan" => {
+                    scan = Some(DidScan {
+                        firmware: get("firmware")?.to_string(),
+                        device_id: get("device_id")?.to_string(),
+                        dids: BTreeMap::new(),
+                    })
+                }
+                "did" => {
+                    let scan = scan
+                        .as_mut()
+                        .ok_or_else(|| anyhow!("line {}: DID before the scan header", n + 1))?;
+                    let did = u16::from_str_radix(get("did")?, 16)
+                        .map_err(|_| anyhow!("line {}: bad DID", n + 1))?;
+                    let data = hex::decode(get("data")?)
+                        .map_err(|_| anyhow!("line {}: bad data", n + 1))?;
+                    scan.dids.insert(did, data);
+                }
+                _ => {}
+            }
+        }
+        scan.ok_or_else(|| anyhow!("no scan header"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DidChange {
+    Appeared {
+        did: u16,
+        len: usize,
+    },
+    Disappeared {
+        did: u16,
+        len: usize,
+    },
+    Resized {
+        did: u16,
+        from: usize,
+        to: usize,
+    },
+    /// Same length, different contents
+    Changed {
+        did: u16,
+        len: usize,
+    },
+}
+
+impl DidChange {
+    pub fn did(&self) -> u16 {
+        match *self {
+            DidChange::Appeared { did, .. }
+            | DidChange::Disappeared { did, .. }
+            | DidChange::Resized { did, .. }
+            | DidChange::Changed { did, .. } => did,
+        }
+    }
+}
+
+/// Changes from `old` to `new`, in DID order. Unchanged DIDs are left out.
+pub fn diff(old: &DidScan, new: &DidScan) -> Vec<DidChange> {
+    let dids: BTreeSet<u16> = old.dids.keys().chain(new.dids.keys()).copied().collect();
+    dids.into_iter()
+        .filter_map(|did| match (old.dids.get(&did), new.dids.get(&did)) {
+            (None, Some(b)) => Some(DidChange::Appeared { did, len: b.len() }),
+            (Some(a), None) => Some(DidChange::Disappeared { did, len: a.len() }),
+            (Some(a), Some(b)) if a.len() != b.len() => Some(DidChange::Resized {
+                did,
+                from: a.len(),
+                to: b.len(),
+            }),
+            (Some(a), Some(b)) if a != b => Some(DidChange::Changed { did, len: a.len() }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_diff() {
+        let old = [
+            jsonl::scan("t", "1.2.3", "0123"),
+            jsonl::scan_did("t", 0x8010, b"12345678"),
+            jsonl::scan_did("t", 0x8011, b"123"),
+            jsonl::scan_did("t", 0x8203, &[0; 15]),
+            jsonl::scan_did("t", 0x8300, &[1]),
+        ]
+        .join("\n");
+        let new = [
+            jsonl::scan("t", "1.3.0", "0123"),
+            jsonl::scan_did("t", 0x8010, b"12345678"),
+            jsonl::scan_did("t", 0x8011, b"130"),
+            jsonl::scan_did("t", 0x8203, &[0; 17]),
+            jsonl::scan_did("t", 0x8400, &[1, 2]),
+        ]
+        .join("\n");
+        let (old, new) = (DidScan::parse(&old).unwrap(), DidScan::parse(&new).unwrap());
+        assert_eq!(old.firmware, "1.2.3");
+        assert_eq!(new.dids[&0x8400], [1, 2]);
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                DidChange::Changed {
+                    did: 0x8011,
+                    len: 3
+                },
+                DidChange::Resized {
+                    did: 0x8203,
+                    from: 15,
+                    to: 17
+                },
+                DidChange::Disappeared {
+                    did: 0x8300,
+                    len: 1
+                },
+                DidChange::Appeared {
+                    did: 0x8400,
+                    len: 2
+                },
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+
+        assert!(DidScan::parse(&jsonl::scan_did("t", 0x8010, b"1")).is_err());
+        assert!(DidScan::parse("").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Synthetic DiveCAN traffic for load testing handset firmware and our own
+//! ISO-TP demux and monitor, see `solodiag sim --mode flood`.
+
+use candive::cells::CellArray;
+use candive::divecan::{DiveCanFrame, DiveCanId, Msg};
+use candive::rng::Xoshiro128;
+use candive::uds::isotp::{IsoTpPciType, IsoTpTx};
+use candive::units::{CentiMillivolt, Millibar, PpO2Deci};
+use clap::ValueEnum;
+use std::fmt;
+
+const UDS_KIND: u8 = 0x0A;
+/// Largest payload an ISO-TP first frame can announce
+pub const MAX_ISOTP_PAYLOAD: usize = 4095;
+/// TesterPresent with trailing bytes, a well-behaved node answers with an
+/// incorrect-length NRC and otherwise ignores it
+const ISOTP_SERVICE: u8 = 0x3E;
+
+/// One entry of the flood message mix
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FloodKind {
+    /// CellPpo2 broadcasts
+    Ppo2,
+    /// CellVoltages broadcasts
+    Voltages,
+    /// AmbientPressure broadcasts
+    Pressure,
+    /// Nop broadcasts
+    Nop,
+    /// Max-length ISO-TP transfer to --dst
+    Isotp,
+}
+
+/// Round-robins over a message mix, producing the frames for each entry.
+/// All noise comes from `seed`, the same seed gives the same traffic.
+pub struct FloodGen {
+    mix: Vec<FloodKind>,
+    src: u8,
+    dst: u8,
+    next: usize,
+    rng: Xoshiro128,
+}
+
+impl FloodGen {
+    pub fn new(mix: Vec<FloodKind>, src: u8, dst: u8, seed: u64) -> Self {
+        Self {
+            mix,
+            src,
+            dst,
+            next: 0,
+            rng: Xoshiro128::from_seed(seed),
+        }
+    }
+
+    /// Inter-frame delay for `period_us`, jittered by up to `jitter_percent`.
+    pub fn frame_delay_us(&mut self, period_us: u32, jitter_percent: u8) -> u32 {
+        let amplitude = (period_us as u64 * jitter_percent as u64 / 100).min(u16::MAX as u64);
+        (period_us as i64 + self.rng.jitter(amplitude as u16) as i64).max(0) as u32
+    }
+
+    /// Frames for the next mix entry, a whole transfer for [`FloodKind::Isotp`].
+    pub fn next_burst(&mut self) -> Vec<(DiveCanId, DiveCanFrame)> {
+        let Some(&kind) = self.mix.get(self.next) else {
+            return Vec::new();
+        };
+        self.next = (self.next + 1) % self.mix.len();
+        // Values wander a little so decoders and monitors see changing data
+        let rng = &mut self.rng;
+
+        let msg = match kind {
+            FloodKind::Ppo2 => Msg::CellPpo2(CellArray::new(
+                [100, 100, 100].map(|p| PpO2Deci::new((p + rng.jitter(8)) as u8)),
+            )),
+            FloodKind::Voltages => Msg::CellVoltages {
+                cell_voltages: CellArray::new(
+                    [4700, 4700, 4700].map(|mv| CentiMillivolt::new((mv + rng.jitter(300)) as u16)),
+                ),
+                unused: 0,
+            },
+            FloodKind::Pressure => Msg::AmbientPressure {
+                surface: Millibar::new(1013),
+                current: Millibar::new(1013 + rng.below(3000) as u16),
+                depth_comp: true,
+            },
+            FloodKind::Nop => Msg::Nop,
+            FloodKind::Isotp => return self.isotp_transfer(),
+        };
+        vec![(DiveCanId::new(self.src, 0xFF, msg.kind()), msg.to_frame())]
+    }
+
+    fn isotp_transfer(&mut self) -> Vec<(DiveCanId, DiveCanFrame)> {
+        let mut payload = vec![0u8; MAX_ISOTP_PAYLOAD];
+        payload[0] = ISOTP_SERVICE;
+        self.rng.fill(&mut payload[1..]);
+        let id = DiveCanId::new(self.src, self.dst, UDS_KIND);
+        IsoTpTx::new(&payload)
+            .filter_map(|f| {
+                let bytes = f.as_slice();
+                let mut data = [0u8; 8];
+                data[..bytes.len()].copy_from_slice(bytes);
+                DiveCanFrame::new(UDS_KIND, bytes.len() as u8, data).ok()
+            })
+            .map(|frame| (id, frame))
+            .collect()
+    }
+}
+
+/// Generated vs. acknowledged traffic
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FloodCounters {
+    /// Frames produced by the generator
+    pub generated: u64,
+    /// Frames the bus accepted
+    pub sent: u64,
+    /// Frames the bus refused (full tx queue, bus-off, ...)
+    pub send_errors: u64,
+    /// ISO-TP transfers started
+    pub transfers: u64,
+    /// Flow control frames from the target, one per acknowledged transfer
+    pub flow_controls: u64,
+    /// Other UDS frames from the target, usually the NRC for each transfer
+    pub responses: u64,
+    /// Frames from any other node
+    pub received: u64,
+    /// Error frames from the CAN controller
+    pub bus_errors: u64,
+}
+
+impl FloodCounters {
+    /// Counts a frame heard on the bus while flooding.
+    pub fn on_received(&mut self, id: DiveCanId, frame: &DiveCanFrame, src: u8, dst: u8) {
+        if id.kind != UDS_KIND || id.src != dst || id.dst != src {
+            self.received += 1;
+            return;
+        }
+        match frame
+            .bytes()
+            .first()
+            .copied()
+            .and_then(IsoTpPciType::from_u8)
+        {
+            Some(IsoTpPciType::FlowControl) => self.flow_controls += 1,
+            _ => self.responses += 1,
+        }
+    }
+}
+
+impl fmt::Display for FloodCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "generated {}, sent {}, send errors {}, transfers {}, flow controls {}, responses {}, other frames {}, bus errors {}",
+            self.generated,
+            self.sent,
+            self.send_errors,
+            self.transfers,
+            self.flow_controls,
+            self.responses,
+            self.received,
+            self.bus_errors
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mix_and_counters() {
+        let mix = vec![FloodKind::Ppo2, FloodKind::Isotp];
+        let mut flood = FloodGen::new(mix.clone(), 0x09, 0x04, 7);
+
+        let ppo2 = flood.next_burst();
+        assert_eq!(ppo2.len(), 1);
+        assert!(matches!(
+            Msg::try_from_frame(&ppo2[0].1),
+            Ok(Msg::CellPpo2(_))
+        ));
+
+        // First frame plus ceil((4095 - 6) / 7) consecutive frames
+        let transfer = flood.next_burst();
+        assert_eq!(transfer.len(), 1 + 585);
+        assert_eq!(transfer[0].0, DiveCanId::new(0x09, 0x04, UDS_KIND));
+        assert_eq!(&transfer[0].1.bytes()[..3], [0x1F, 0xFF, ISOTP_SERVICE]);
+        assert_eq!(flood.next_burst().len(), 1);
+
+        // Same seed, same traffic
+        let mut replay = FloodGen::new(mix, 0x09, 0x04, 7);
+        let bytes = |frames: Vec<(DiveCanId, DiveCanFrame)>| {
+            frames
+                .iter()
+                .map(|(id, f)| (*id, f.bytes().to_vec()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bytes(replay.next_burst()), bytes(ppo2));
+        assert_eq!(bytes(replay.next_burst()), bytes(transfer));
+        let delay = flood.frame_delay_us(1000, 20);
+        assert!((800..=1200).contains(&delay));
+
+        let mut counters = FloodCounters::default();
+        let fc = DiveCanFrame::new(UDS_KIND, 3, [0x30, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        let nrc = DiveCanFrame::new(UDS_KIND, 4, [0x03, 0x7F, 0x3E, 0x13, 0, 0, 0, 0]).unwrap();
+        counters.on_received(DiveCanId::new(0x04, 0x09, UDS_KIND), &fc, 0x09, 0x04);
+        counters.on_received(DiveCanId::new(0x04, 0x09, UDS_KIND), &nrc, 0x09, 0x04);
+        counters.on_received(DiveCanId::new(0x01, 0xFF, 0x10), &fc, 0x09, 0x04);
+        assert_eq!(
+            (
+                counters.flow_controls,
+                counters.responses,
+                counters.received
+            ),
+            (1, 1, 1)
+        );
+    }
+}
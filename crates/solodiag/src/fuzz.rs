@@ -0,0 +1,271 @@
+//! `dev fuzz-device`, structured-invalid UDS requests for firmware
+//! developers. Each case is a request the device has to refuse with a
+//! specific NRC. Accepting it, answering with another NRC, not answering or
+//! rebooting (seen as a fresh `Id` announcement) counts as a failure.
+
+use anyhow::anyhow;
+use candive::diag::solo;
+use candive::divecan::Msg;
+use candive::uds::client::UdsTransport;
+use candive::uds::{
+    ALFI_ADDR4_SIZE4, DIVE_CAN_UDS_ADDR, Dlf, SID_NEG_RESPONSE, SID_RDBI_REQ,
+    SID_REQUEST_UPLOAD_REQ, SID_TRANSFER_DATA_REQ, SID_TRANSFER_EXIT_REQ, SID_WDBI_REQ,
+    UdsErrorCode,
+};
+use std::time::{Duration, Instant};
+
+use crate::transport::RawBus;
+use crate::{CmdResult, Session};
+
+/// How long to watch the bus for an `Id` announcement after each case
+const RESET_WINDOW: Duration = Duration::from_millis(300);
+/// Time a rebooted device gets before the next case
+const REBOOT_GRACE: Duration = Duration::from_secs(3);
+
+pub struct FuzzCase {
+    pub name: &'static str,
+    /// Sent first, answers ignored, e.g. to open an upload
+    pub setup: Vec<Vec<u8>>,
+    /// The invalid request, without the UDS address byte
+    pub request: Vec<u8>,
+    /// Sent after the request whatever it got back, answers ignored
+    pub cleanup: Vec<Vec<u8>>,
+    /// Any of these is a correct refusal
+    pub expected: &'static [UdsErrorCode],
+}
+
+impl FuzzCase {
+    fn new(name: &'static str, request: &[u8], expected: &'static [UdsErrorCode]) -> Self {
+        Self {
+            name,
+            setup: Vec::new(),
+            request: request.to_vec(),
+            cleanup: Vec::new(),
+            expected,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Refused(UdsErrorCode),
+    WrongNrc(UdsErrorCode),
+    /// Positive response to a request that should have been refused
+    Accepted,
+    NoResponse(String),
+    Reset,
+}
+
+impl Verdict {
+    pub fn passed(&self) -> bool {
+        matches!(self, Verdict::Refused(_))
+    }
+}
+
+fn upload_request(address: u32, size: u32) -> Vec<u8> {
+    let mut req = vec![SID_REQUEST_UPLOAD_REQ, Dlf::Normal as u8, ALFI_ADDR4_SIZE4];
+    req.extend_from_slice(&address.to_be_bytes());
+    req.extend_from_slice(&size.to_be_bytes());
+    req
+}
+
+pub fn cases() -> Vec<FuzzCase> {
+    use UdsErrorCode::*;
+
+    const LENGTH: &[UdsErrorCode] = &[IncorrectMessageLengthOrInvalidFormat];
+    const RANGE: &[UdsErrorCode] = &[RequestOutOfRange];
+    const SEQUENCE: &[UdsErrorCode] = &[RequestSequenceError];
+
+    let devinfo = *solo::regions::MCU_DEVINFO.addr_range.start();
+    let mut wrong_block = FuzzCase::new(
+        "TransferData with block counter 2 first",
+        &[SID_TRANSFER_DATA_REQ, 0x02],
+        &[WrongBlockSequenceCounter, RequestSequenceError],
+    );
+    wrong_block.setup.push(upload_request(devinfo, 21));
+    wrong_block.cleanup.push(vec![SID_TRANSFER_EXIT_REQ]);
+
+    vec![
+        FuzzCase::new("RDBI without DID", &[SID_RDBI_REQ], LENGTH),
+        FuzzCase::new("RDBI with 1-byte DID", &[SID_RDBI_REQ, 0x80], LENGTH),
+        FuzzCase::new(
+            "RDBI with trailing byte",
+            &[SID_RDBI_REQ, 0x80, 0x11, 0x00],
+            LENGTH,
+        ),
+        FuzzCase::new("RDBI of DID 0x0000", &[SID_RDBI_REQ, 0x00, 0x00], RANGE),
+        FuzzCase::new("RDBI of DID 0xFFFF", &[SID_RDBI_REQ, 0xFF, 0xFF], RANGE),
+        FuzzCase::new("WDBI without DID", &[SID_WDBI_REQ], LENGTH),
+        FuzzCase::new(
+            "WDBI of read-only FirmwareVersionAscii",
+            &[SID_WDBI_REQ, 0x80, 0x11, b'0', b'0', b'0'],
+            &[
+                RequestOutOfRange,
+                ConditionsNotCorrect,
+                SecurityAccessDenied,
+            ],
+        ),
+        FuzzCase::new(
+            "RequestUpload without address",
+            &[SID_REQUEST_UPLOAD_REQ, 0x00],
+            LENGTH,
+        ),
+        FuzzCase::new(
+            "RequestUpload past the address space",
+            &upload_request(0xFFFF_FF00, 0x100),
+            RANGE,
+        ),
+        FuzzCase::new(
+            "RequestUpload of 0 bytes",
+            &upload_request(devinfo, 0),
+            &[RequestOutOfRange, UploadDownloadNotAccepted],
+        ),
+        FuzzCase::new(
+            "TransferData without transfer",
+            &[SID_TRANSFER_DATA_REQ, 0x01],
+            SEQUENCE,
+        ),
+        FuzzCase::new(
+            "TransferExit without transfer",
+            &[SID_TRANSFER_EXIT_REQ],
+            SEQUENCE,
+        ),
+        wrong_block,
+        FuzzCase::new("Unknown service 0xBA", &[0xBA], &[ServiceNotSupported]),
+    ]
+}
+
+/// Verdict for a case from what came back and whether the device rebooted
+pub fn judge(case: &FuzzCase, response: Result<&[u8], String>, reset: bool) -> Verdict {
+    if reset {
+        return Verdict::Reset;
+    }
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return Verdict::NoResponse(e),
+    };
+    match response {
+        [_, SID_NEG_RESPONSE, _, code, ..] => {
+            let code = UdsErrorCode::from_u8(*code);
+            if case.expected.contains(&code) {
+                Verdict::Refused(code)
+            } else {
+                Verdict::WrongNrc(code)
+            }
+        }
+        [] | [_] => Verdict::NoResponse("empty response".into()),
+        _ => Verdict::Accepted,
+    }
+}
+
+/// `true` if `dst` announced itself within `window`
+fn saw_id(bus: &RawBus, dst: u8, window: Duration) -> CmdResult<bool> {
+    let start = Instant::now();
+    let mut seen = false;
+    while start.elapsed() < window {
+        let Some((id, frame)) = bus.recv().map_err(|e| anyhow!("CAN read failed: {}", e))? else {
+            continue;
+        };
+        seen |= id.src == dst && matches!(Msg::try_from_frame(&frame), Ok(Msg::Id { .. }));
+    }
+    Ok(seen)
+}
+
+fn send(session: &mut Session, pdu: &[u8]) -> Result<Vec<u8>, String> {
+    let req = [&[DIVE_CAN_UDS_ADDR], pdu].concat();
+    let mut resp = vec![0u8; 4096];
+    let len = session
+        .request(&req, &mut resp)
+        .map_err(|e| e.to_string())?;
+    resp.truncate(len.min(resp.len()));
+    Ok(resp)
+}
+
+/// Runs every case and prints one line each. `Ok(false)` if any failed.
+pub fn run(session: &mut Session, bus: &RawBus, dst: u8, detect_resets: bool) -> CmdResult<bool> {
+    let cases = cases();
+    let mut failed = 0;
+    for case in &cases {
+        for pdu in &case.setup {
+            let _ = send(session, pdu);
+        }
+        let response = send(session, &case.request);
+        for pdu in &case.cleanup {
+            let _ = send(session, pdu);
+        }
+        let reset = detect_resets && saw_id(bus, dst, RESET_WINDOW)?;
+        let verdict = judge(case, response.as_deref().map_err(Clone::clone), reset);
+
+        let detail = match &verdict {
+            Verdict::Refused(code) | Verdict::WrongNrc(code) => {
+                format!("NRC 0x{:02X} ({})", code.as_u8(), code.description())
+            }
+            Verdict::Accepted => "accepted".to_string(),
+            Verdict::NoResponse(e) => format!("no response: {}", e),
+            Verdict::Reset => "device reset".to_string(),
+        };
+        if verdict.passed() {
+            println!("PASS  {}: {}", case.name, detail);
+        } else {
+            failed += 1;
+            let expected: Vec<String> = case
+                .expected
+                .iter()
+                .map(|c| format!("0x{:02X}", c.as_u8()))
+                .collect();
+            println!(
+                "FAIL  {}: {}, expected {}",
+                case.name,
+                detail,
+                expected.join(" or ")
+            );
+        }
+        if matches!(verdict, Verdict::Reset | Verdict::NoResponse(_)) {
+            std::thread::sleep(REBOOT_GRACE);
+        }
+    }
+    println!();
+    println!("{} of {} cases passed", cases.len() - failed, cases.len());
+    Ok(failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verdicts() {
+        let cases = cases();
+        let case = &cases[0];
+        assert_eq!(
+            judge(
+                case,
+                Ok(&[0x00, SID_NEG_RESPONSE, SID_RDBI_REQ, 0x13]),
+                false
+            ),
+            Verdict::Refused(UdsErrorCode::IncorrectMessageLengthOrInvalidFormat)
+        );
+        assert_eq!(
+            judge(
+                case,
+                Ok(&[0x00, SID_NEG_RESPONSE, SID_RDBI_REQ, 0x31]),
+                false
+            ),
+            Verdict::WrongNrc(UdsErrorCode::RequestOutOfRange)
+        );
+        assert_eq!(
+            judge(case, Ok(&[0x00, 0x62, 0x80, 0x11]), false),
+            Verdict::Accepted
+        );
+        assert_eq!(
+            judge(
+                case,
+                Ok(&[0x00, SID_NEG_RESPONSE, SID_RDBI_REQ, 0x13]),
+                true
+            ),
+            Verdict::Reset
+        );
+        assert!(!judge(case, Err("timeout".into()), false).passed());
+        assert!(cases.iter().all(|c| !c.expected.is_empty()));
+    }
+}
@@ -0,0 +1,169 @@
+//! Checks run before a transport is opened, so a missing interface or
+//! device permission ends in the command that fixes it rather than an
+//! opaque "Failed to create ... transport".
+
+use anyhow::{Result, anyhow};
+use std::path::Path;
+
+/// Checks what the transport in `uri` needs from the host. Unknown schemes
+/// and transports without local requirements pass, opening them reports
+/// the rest.
+pub fn transport(uri: &str) -> Result<()> {
+    if let Some(interface) = uri.strip_prefix("can://") {
+        can_interface(interface)
+    } else if let Some(port) = uri.strip_prefix("rfcomm://") {
+        serial_port(port)
+    } else if uri.starts_with("ble://") {
+        bluetooth_adapter()
+    } else {
+        Ok(())
+    }
+}
+
+/// Interface configuration (`can setup`, `can autodetect`) needs root or
+/// CAP_NET_ADMIN.
+pub fn net_admin() -> Result<()> {
+    const CAP_NET_ADMIN: u32 = 12;
+
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return Ok(());
+    };
+    if effective_caps(&status).is_none_or(|caps| caps & (1 << CAP_NET_ADMIN) != 0) {
+        return Ok(());
+    }
+    let exe = std::env::current_exe()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "solodiag".into());
+    Err(anyhow!(
+        "Configuring CAN interfaces needs CAP_NET_ADMIN. Run with sudo, or grant it once with `sudo setcap cap_net_admin+ep {}`",
+        exe
+    ))
+}
+
+fn can_interface(interface: &str) -> Result<()> {
+    let sys = Path::new("/sys/class/net").join(interface);
+    if !Path::new("/sys/class/net").exists() {
+        return Ok(());
+    }
+    if !sys.exists() {
+        return Err(anyhow!(
+            "No network interface {}. Check the adapter is plugged in (`ip -br link` lists interfaces), or create a virtual one with `sudo solodiag --transport can://{} can setup --vcan`",
+            interface,
+            interface
+        ));
+    }
+    let is_up = std::fs::read_to_string(sys.join("flags"))
+        .ok()
+        .and_then(|flags| interface_up(&flags));
+    if is_up == Some(false) {
+        return Err(anyhow!(
+            "{} is down. Bring it up with `sudo solodiag --transport can://{} can setup` (125 kbit/s)",
+            interface,
+            interface
+        ));
+    }
+    Ok(())
+}
+
+fn serial_port(port: &str) -> Result<()> {
+    let path = Path::new(port);
+    if !path.exists() {
+        return Err(anyhow!(
+            "{} does not exist. Pair the gateway and bind it with `sudo rfcomm bind {} <gateway MAC>`",
+            port,
+            rfcomm_index(port).unwrap_or(0)
+        ));
+    }
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            let group = owning_group(path);
+            Err(anyhow!(
+                "No permission to open {}. Add yourself to its group with `sudo usermod -aG {} $USER`, then log in again",
+                port,
+                group.as_deref().unwrap_or("dialout")
+            ))
+        }
+        // Busy or otherwise unusable ports are reported when the transport opens
+        _ => Ok(()),
+    }
+}
+
+fn bluetooth_adapter() -> Result<()> {
+    let Ok(adapters) = std::fs::read_dir("/sys/class/bluetooth") else {
+        return Ok(());
+    };
+    if adapters
+        .flatten()
+        .any(|a| a.file_name().to_string_lossy().starts_with("hci"))
+    {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "No Bluetooth adapter found. Check it is plugged in and not blocked (`rfkill list`, `sudo rfkill unblock bluetooth`)"
+    ))
+}
+
+/// `CapEff` from `/proc/<pid>/status`
+fn effective_caps(status: &str) -> Option<u64> {
+    let hex = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(hex.trim(), 16).ok()
+}
+
+/// IFF_UP from `/sys/class/net/<if>/flags`
+fn interface_up(flags: &str) -> Option<bool> {
+    let flags = u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok()?;
+    Some(flags & 0x1 != 0)
+}
+
+/// 3 for `/dev/rfcomm3`
+fn rfcomm_index(port: &str) -> Option<u32> {
+    port.rsplit_once("rfcomm")?.1.parse().ok()
+}
+
+#[cfg(unix)]
+fn owning_group(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let gid = std::fs::metadata(path).ok()?.gid();
+    group_name(&std::fs::read_to_string("/etc/group").ok()?, gid)
+}
+
+#[cfg(not(unix))]
+fn owning_group(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Name for `gid` in `/etc/group` format
+fn group_name(groups: &str, gid: u32) -> Option<String> {
+    groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id: u32 = fields.nth(1)?.parse().ok()?;
+        (id == gid).then(|| name.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsers() {
+        let status = "Name:\tsolodiag\nCapPrm:\t0000000000000000\nCapEff:\t0000000000001000\n";
+        assert_eq!(effective_caps(status), Some(1 << 12));
+        assert_eq!(effective_caps("Name:\tx\n"), None);
+
+        assert_eq!(interface_up("0x40080\n"), Some(false));
+        assert_eq!(interface_up("0xc1\n"), Some(true));
+
+        assert_eq!(rfcomm_index("/dev/rfcomm3"), Some(3));
+        assert_eq!(rfcomm_index("/dev/ttyUSB0"), None);
+
+        let groups = "root:x:0:\nuucp:x:14:\ndialout:x:20:alice\n";
+        assert_eq!(group_name(groups, 20).as_deref(), Some("dialout"));
+        assert_eq!(group_name(groups, 99), None);
+    }
+}
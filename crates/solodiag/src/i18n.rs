@@ -0,0 +1,156 @@
+//! Message catalogs for the human-readable output (`msgformat`): decoded
+//! messages, the values inside them and alert labels.
+//!
+//! English (`lang/en.txt`) is built in. `--lang` picks a built-in language
+//! by code or a catalog file in the same format, and every key the file
+//! lacks is shown in English. The catalog is chosen once at startup.
+
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+const EN: &str = include_str!("../lang/en.txt");
+
+/// Built-in catalogs by language code
+const BUILT_IN: &[(&str, &str)] = &[("en", EN)];
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+pub struct Catalog {
+    texts: HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn english() -> Self {
+        Self {
+            // The built-in catalog is checked by the tests
+            texts: parse(EN).unwrap_or_default().into_iter().collect(),
+        }
+    }
+
+    /// A built-in language code, or the path of a catalog file laid over
+    /// English. Keys English doesn't have are rejected, they are typos.
+    pub fn load(lang: &str) -> Result<Self> {
+        let text = match BUILT_IN.iter().find(|(code, _)| *code == lang) {
+            Some((_, text)) => text.to_string(),
+            None => std::fs::read_to_string(lang).map_err(|e| {
+                let codes: Vec<_> = BUILT_IN.iter().map(|(code, _)| *code).collect();
+                anyhow!(
+                    "--lang {} is neither a built-in language ({}) nor a readable catalog: {}",
+                    lang,
+                    codes.join(", "),
+                    e
+                )
+            })?,
+        };
+        Self::english().overlaid(&text)
+    }
+
+    fn overlaid(mut self, text: &str) -> Result<Self> {
+        for (key, value) in parse(text)? {
+            if !self.texts.contains_key(&key) {
+                return Err(anyhow!("Unknown catalog key {}", key));
+            }
+            self.texts.insert(key, value);
+        }
+        Ok(self)
+    }
+
+    /// Text for `key` with every `{name}` replaced by the value given for
+    /// it. A key that isn't in the catalog comes back as is.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        let Some(text) = self.texts.get(key) else {
+            return key.to_string();
+        };
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(open) = rest.find('{') {
+            out += &rest[..open];
+            let after = &rest[open + 1..];
+            let arg = after.find('}').and_then(|close| {
+                let name = &after[..close];
+                let (_, value) = args.iter().find(|(n, _)| *n == name)?;
+                Some((value, close))
+            });
+            match arg {
+                Some((value, close)) => {
+                    out += &value.to_string();
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out + rest
+    }
+}
+
+/// `key = text` lines, skipping blank lines and `#` comments
+fn parse(text: &str) -> Result<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Catalog line {}: expected `key = text`", i + 1))?;
+        entries.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(entries)
+}
+
+/// Selects the catalog for the rest of the run. Only the first call counts.
+pub fn set(catalog: Catalog) {
+    let _ = CATALOG.set(catalog);
+}
+
+/// [`Catalog::format`] with the selected catalog, English if none was set
+pub fn tr(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    CATALOG.get_or_init(Catalog::english).format(key, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candive::alerts::AnyAlert;
+    use candive::divecan::Msg;
+
+    #[test]
+    fn english_covers_every_message_and_alert() {
+        let en = parse(EN).unwrap();
+        let has = |key: String| {
+            assert!(en.iter().any(|(k, _)| *k == key), "no {} in en.txt", key);
+        };
+        for info in Msg::KINDS {
+            has(format!("msg.{}", info.name));
+        }
+        for alert in (0..=u16::MAX).filter_map(AnyAlert::from_u16) {
+            has(crate::msgformat::alert_key(alert));
+        }
+    }
+
+    #[test]
+    fn overlay_and_placeholders() {
+        let de = Catalog::english()
+            .overlaid("# German\nmsg.Setpoint = Setpoint geändert auf {setpoint}\n")
+            .unwrap();
+        assert_eq!(
+            de.format("msg.Setpoint", &[("setpoint", &"0.70")]),
+            "Setpoint geändert auf 0.70"
+        );
+        assert_eq!(de.format("msg.Nop", &[]), "no operation");
+        assert_eq!(
+            de.format("alert.unknown", &[("code", &"1234"), ("unused", &1)]),
+            "unknown alert 0x1234"
+        );
+        assert_eq!(de.format("no.such.key", &[]), "no.such.key");
+
+        assert!(Catalog::english().overlaid("msg.Setpont = typo").is_err());
+        assert!(Catalog::english().overlaid("no equals sign").is_err());
+    }
+}
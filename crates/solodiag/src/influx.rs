@@ -0,0 +1,49 @@
+//! `--influx udp://host:port`: telemetry as InfluxDB line protocol over UDP,
+//! one datagram per frame, for an InfluxDB (or Telegraf) UDP listener.
+
+use anyhow::{Result, anyhow};
+use candive::divecan::{DiveCanId, Msg};
+use std::net::UdpSocket;
+
+pub struct InfluxSink {
+    socket: UdpSocket,
+    target: String,
+    failed: bool,
+}
+
+impl InfluxSink {
+    pub fn open(uri: &str) -> Result<Self> {
+        let target = uri
+            .strip_prefix("udp://")
+            .ok_or_else(|| anyhow!("--influx takes udp://host:port, got {}", uri))?;
+        let socket = UdpSocket::bind(if target.starts_with('[') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        })?;
+        socket
+            .connect(target)
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", target, e))?;
+        Ok(Self {
+            socket,
+            target: target.to_string(),
+            failed: false,
+        })
+    }
+
+    /// Sends `msg` if it is telemetry. A failed send is logged once and
+    /// doesn't stop the capture.
+    pub fn send(&mut self, ts_ms: u64, iface: Option<&str>, id: DiveCanId, msg: &Msg) {
+        let Some(line) = candive::influx::line(ts_ms, iface, id, msg) else {
+            return;
+        };
+        match self.socket.send(line.as_bytes()) {
+            Ok(_) => self.failed = false,
+            Err(e) if !self.failed => {
+                log::warn!("InfluxDB at {}: {}", self.target, e);
+                self.failed = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! One JSON object per line for `monitor --output jsonl` and `rdbi-scan
+//! --output`, for jq and log shippers.
+
+use candive::diag::solo::LogTransferDigest;
+use candive::divecan::{DecodeError, DiveCanFrame, DiveCanId, Msg};
+use candive::fmt::UnitsPreference;
+use candive::monitor::Event;
+use candive::uds::isotp::{FlowControl, FlowStatus, IsoTpRxError};
+use std::fmt::Write;
+
+use crate::msgformat;
+
+/// Flat JSON object with string and number values, built field by field
+struct Object(String);
+
+impl Object {
+    fn new(ts: &str, kind: &str) -> Self {
+        Self(String::from("{")).str("ts", ts).str("type", kind)
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.0.len() > 1 {
+            self.0.push(',');
+        }
+        push_escaped(&mut self.0, key);
+        self.0.push(':');
+    }
+
+    fn str(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        push_escaped(&mut self.0, value);
+        self
+    }
+
+    fn num(mut self, key: &str, value: impl Into<u64>) -> Self {
+        self.key(key);
+        let _ = write!(self.0, "{}", value.into());
+        self
+    }
+
+    fn ids(self, id: DiveCanId) -> Self {
+        self.num("src", id.src).num("dst", id.dst)
+    }
+
+    fn finish(mut self) -> String {
+        self.0.push('}');
+        self.0
+    }
+}
+
+fn push_escaped(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// `line` with the interface it was seen on added, for merged captures
+pub fn with_iface(mut line: String, iface: &str) -> String {
+    if line.pop() == Some('}') {
+        line.push_str(",\"iface\":");
+        push_escaped(&mut line, iface);
+        line.push('}');
+    }
+    line
+}
+
+/// Raw frame plus its decoded form, or the decode error
+pub fn frame(
+    ts: &str,
+    id: DiveCanId,
+    frame: &DiveCanFrame,
+    msg: Result<&Msg, DecodeError>,
+    units: UnitsPreference,
+) -> String {
+    let obj = Object::new(ts, "frame")
+        .str("id", &format!("{:08X}", id.to_u32()))
+        .ids(id)
+        .num("kind", id.kind)
+        .num("dlc", frame.dlc())
+        .str("data", &hex::encode_upper(frame.bytes()));
+    match msg {
+        Ok(msg) => obj
+            .str("msg", msg.name())
+            .str("category", msg.category().name())
+            .str("decoded", &msgformat::pretty(msg, units)),
+        Err(e) => obj.str("error", &format!("{:?}", e)),
+    }
+    .finish()
+}
+
+/// Extended-id frame from something that isn't speaking DiveCAN
+pub fn other(ts: &str, raw_id: u32, data: &[u8]) -> String {
+    Object::new(ts, "other")
+        .str("id", &format!("{:08X}", raw_id))
+        .num("dlc", data.len() as u64)
+        .str("data", &hex::encode_upper(data))
+        .finish()
+}
+
+/// A reassembled ISO-TP (UDS) payload from `id.src` to `id.dst`
+pub fn isotp(ts: &str, id: DiveCanId, payload: &[u8]) -> String {
+    Object::new(ts, "isotp")
+        .ids(id)
+        .num("len", payload.len() as u64)
+        .str("data", &hex::encode_upper(payload))
+        .finish()
+}
+
+/// An ISO-TP flow control frame, the receiver pacing the sender
+pub fn flow_control(ts: &str, id: DiveCanId, fc: &FlowControl) -> String {
+    let status = match fc.status {
+        FlowStatus::ContinueToSend => "cts",
+        FlowStatus::Wait => "wait",
+        FlowStatus::Overflow => "overflow",
+    };
+    Object::new(ts, "flow_control")
+        .ids(id)
+        .str("status", status)
+        .num("block_size", fc.block_size)
+        .num("st_min_us", fc.st_min_us())
+        .finish()
+}
+
+/// An ISO-TP transfer that was dropped while reassembling
+pub fn isotp_error(ts: &str, id: DiveCanId, err: &IsoTpRxError) -> String {
+    Object::new(ts, "isotp_error")
+        .ids(id)
+        .str("error", &format!("{:?}", err))
+        .finish()
+}
+
+/// First line of a saved RDBI scan, what was scanned
+pub fn scan(ts: &str, firmware: &str, device_id: &str) -> String {
+    Object::new(ts, "scan")
+        .str("firmware", firmware)
+        .str("device_id", device_id)
+        .finish()
+}
+
+/// A DID that answered during an RDBI scan
+pub fn scan_did(ts: &str, did: u16, data: &[u8]) -> String {
+    Object::new(ts, "did")
+        .str("did", &format!("{:04X}", did))
+        .num("len", data.len() as u64)
+        .str("data", &hex::encode_upper(data))
+        .finish()
+}
+
+/// Digest of a raw `logs export`, written next to the file so `logs verify`
+/// can check its CRC later
+pub fn log_digest(ts: &str, digest: &LogTransferDigest) -> String {
+    Object::new(ts, "log_digest")
+        .str("crc32", &format!("{:08X}", digest.log_crc32))
+        .num("length", digest.length)
+        .num("transfer_start_timestamp", digest.transfer_start_timestamp)
+        .str("device_id", &hex::encode_upper(digest.physical_device_id))
+        .finish()
+}
+
+/// Parses one flat object as written here, string and number values only.
+/// Numbers come back as their text.
+pub fn parse_object(line: &str) -> Option<Vec<(String, String)>> {
+    let mut chars = line.trim().chars().peekable();
+    let mut fields = Vec::new();
+    if chars.next()? != '{' {
+        return None;
+    }
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return chars.next().is_none().then_some(fields);
+    }
+    loop {
+        if chars.next()? != '"' {
+            return None;
+        }
+        let key = parse_string(&mut chars)?;
+        if chars.next()? != ':' {
+            return None;
+        }
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            parse_string(&mut chars)?
+        } else {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            if number.is_empty() {
+                return None;
+            }
+            number
+        };
+        fields.push((key, value));
+        match chars.next()? {
+            ',' => {}
+            '}' => return chars.next().is_none().then_some(fields),
+            _ => return None,
+        }
+    }
+}
+
+/// The rest of a string whose opening quote was consumed
+fn parse_string(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                c => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+pub fn event(ts: &str, event: &Event) -> String {
+    let obj = Object::new(ts, "event").str("event", event.name());
+    match event.src() {
+        Some(src) => obj.num("src", src),
+        None => obj,
+    }
+    .str("detail", &format!("{:?}", event))
+    .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let id = DiveCanId::new(0x04, 0x00, 0x10);
+        let nop = DiveCanFrame::new(0x10, 0, [0; 8]).unwrap();
+        assert_eq!(
+            frame(
+                "2026-01-02T03:04:05.006Z",
+                id,
+                &nop,
+                Ok(&Msg::Nop),
+                UnitsPreference::Metric
+            ),
+            r#"{"ts":"2026-01-02T03:04:05.006Z","type":"frame","id":"0D100004","src":4,"dst":0,"kind":16,"dlc":0,"data":"","msg":"Nop","category":"undocumented","decoded":"no operation"}"#
+        );
+        assert_eq!(
+            isotp("t", id, &[0x62, 0x80, 0x11]),
+            r#"{"ts":"t","type":"isotp","src":4,"dst":0,"len":3,"data":"628011"}"#
+        );
+        assert_eq!(
+            other("t", 0x18FE_F100, &[0xFF, 0x01]),
+            r#"{"ts":"t","type":"other","id":"18FEF100","dlc":2,"data":"FF01"}"#
+        );
+        assert_eq!(
+            with_iface(isotp("t", id, &[]), "can1"),
+            r#"{"ts":"t","type":"isotp","src":4,"dst":0,"len":0,"data":"","iface":"can1"}"#
+        );
+
+        assert_eq!(
+            scan_did("t", 0x8011, b"123"),
+            r#"{"ts":"t","type":"did","did":"8011","len":3,"data":"313233"}"#
+        );
+
+        let mut out = String::new();
+        push_escaped(&mut out, "a\"b\\c\n\u{1}ø");
+        assert_eq!(out, r#""a\"b\\c\n\u0001ø""#);
+
+        let line = format!("{{\"k\":{},\"n\":-12}}", out);
+        let parsed = parse_object(&line).unwrap();
+        assert_eq!(parsed[0], ("k".into(), "a\"b\\c\n\u{1}ø".into()));
+        assert_eq!(parsed[1], ("n".into(), "-12".into()));
+        assert_eq!(parse_object("{}"), Some(Vec::new()));
+        assert_eq!(parse_object(r#"{"k":"v"} x"#), None);
+        assert_eq!(parse_object(r#"{"k":}"#), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Stored SOLO_KEYs for shops with devices from batches that use different
+//! keys. Each key is assigned to a pattern matched against the serial number
+//! and the device ID, so the connected device picks its own key.
+//!
+//! The store is a text file (SOLODIAG_KEYS, default
+//! $XDG_CONFIG_HOME/solodiag/keys or ~/.config/solodiag/keys), one
+//! `<pattern> <cipher> <hex key>` per line, readable by the owner only.
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+use crate::crypto::CipherKind;
+
+pub struct StoredKey {
+    /// `*` matches any run of characters and `?` any one, case ignored
+    pub pattern: String,
+    pub cipher: CipherKind,
+    pub key: Vec<u8>,
+}
+
+impl StoredKey {
+    /// Whether the serial number or the device ID (hex) matches
+    pub fn matches(&self, serial: &str, device_id: &str) -> bool {
+        glob_match(&self.pattern, serial) || glob_match(&self.pattern, device_id)
+    }
+
+    /// Characters the pattern pins down, the most specific match wins
+    fn specificity(&self) -> usize {
+        self.pattern
+            .chars()
+            .filter(|&c| c != '*' && c != '?')
+            .count()
+    }
+}
+
+#[derive(Default)]
+pub struct KeyStore {
+    keys: Vec<StoredKey>,
+}
+
+impl KeyStore {
+    /// Location of the store, `None` when there's no home directory
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("SOLODIAG_KEYS").filter(|p| !p.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".config")))?;
+        Some(config.join("solodiag").join("keys"))
+    }
+
+    /// Reads the store, a missing file is an empty one
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| anyhow!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut keys = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = || anyhow!("line {}: expected `<pattern> <cipher> <hex key>`", i + 1);
+            let mut fields = line.split_whitespace();
+            let (Some(pattern), Some(cipher), Some(key), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(bad());
+            };
+            let cipher = CipherKind::from_str(cipher, true).map_err(|_| bad())?;
+            keys.push(StoredKey {
+                pattern: pattern.to_string(),
+                cipher,
+                key: parse_key(cipher, key).map_err(|e| anyhow!("line {}: {}", i + 1, e))?,
+            });
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut text = String::from("# <pattern> <cipher> <hex key>, written by solodiag key\n");
+        for k in &self.keys {
+            text += &format!(
+                "{} {} {}\n",
+                k.pattern,
+                cipher_name(k.cipher),
+                hex::encode_upper(&k.key)
+            );
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(path)?, text.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn keys(&self) -> &[StoredKey] {
+        &self.keys
+    }
+
+    /// Adds the key, replacing the one assigned to the same pattern
+    pub fn assign(&mut self, key: StoredKey) {
+        match self.keys.iter_mut().find(|k| k.pattern == key.pattern) {
+            Some(old) => *old = key,
+            None => self.keys.push(key),
+        }
+    }
+
+    /// Returns false if no key had this pattern
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let before = self.keys.len();
+        self.keys.retain(|k| k.pattern != pattern);
+        self.keys.len() != before
+    }
+
+    /// The key whose pattern matches with the most characters pinned down,
+    /// the first of equals
+    pub fn find(&self, serial: &str, device_id: &str) -> Option<&StoredKey> {
+        self.keys
+            .iter()
+            .filter(|k| k.matches(serial, device_id))
+            .rev()
+            .max_by_key(|k| k.specificity())
+    }
+}
+
+pub fn cipher_name(cipher: CipherKind) -> String {
+    cipher
+        .to_possible_value()
+        .map_or_else(|| format!("{:?}", cipher), |v| v.get_name().to_string())
+}
+
+/// Hex key of the right length for `cipher`
+pub fn parse_key(cipher: CipherKind, text: &str) -> Result<Vec<u8>> {
+    let bytes = hex::decode(text.trim()).map_err(|_| anyhow!("key must be a hex string"))?;
+    if bytes.len() != cipher.key_len() {
+        return Err(anyhow!(
+            "key must be exactly {} hex characters ({} bytes) for {:?}",
+            cipher.key_len() * 2,
+            cipher.key_len(),
+            cipher
+        ));
+    }
+    Ok(bytes)
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_ascii_uppercase().chars().collect();
+    let text: Vec<char> = text.to_ascii_uppercase().chars().collect();
+    // Position after the last `*` and the text position it resumes from
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    p = sp;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("A005*", "A005D007"));
+        assert!(glob_match("a005d00?", "A005D007"));
+        assert!(glob_match("*D0*7", "A005D007"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("A005", "A005D007"));
+        assert!(!glob_match("B*", "A005D007"));
+    }
+
+    #[test]
+    fn most_specific_key_wins() {
+        let mut store = KeyStore::parse(
+            "# shop keys\n\
+             * des 0011223344556677\n\
+             A005* des 8899AABBCCDDEEFF\n\
+             50FF6806* aes128 00112233445566778899AABBCCDDEEFF\n",
+        )
+        .unwrap();
+        let device_id = "50FF68064884534917540887";
+        assert_eq!(
+            store.find("A005D007", device_id).unwrap().pattern,
+            "50FF6806*"
+        );
+        assert_eq!(store.find("A005D007", "00").unwrap().pattern, "A005*");
+        assert_eq!(store.find("B001", "00").unwrap().pattern, "*");
+
+        store.assign(StoredKey {
+            pattern: "A005*".into(),
+            cipher: CipherKind::Des,
+            key: vec![0; 8],
+        });
+        assert_eq!(store.keys().len(), 3);
+        assert!(store.remove("*"));
+        assert!(!store.remove("*"));
+        assert!(store.find("B001", "00").is_none());
+
+        assert!(KeyStore::parse("A005* des 0011").is_err());
+        assert!(KeyStore::parse("A005* rot13 0011223344556677").is_err());
+    }
+}
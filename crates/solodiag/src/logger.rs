@@ -0,0 +1,42 @@
+//! Warnings and progress notes go through `log` to stderr, leaving stdout to
+//! command output (`--output jsonl`, dumps, exports to `-`). Warnings keep
+//! the `✗` they used to be printed with. Only solodiag's own records are
+//! shown, dependencies log too much that means nothing to a user.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+struct Stderr;
+
+static LOGGER: Stderr = Stderr;
+
+impl Log for Stderr {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && metadata.target().starts_with("solodiag")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error | Level::Warn => eprintln!("✗ {}", record.args()),
+            Level::Info => eprintln!("{}", record.args()),
+            Level::Debug | Level::Trace => {
+                eprintln!("[{}] {}", record.target(), record.args())
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the stderr logger, `quiet` keeps only warnings and errors
+pub fn init(quiet: bool) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(if quiet {
+            LevelFilter::Warn
+        } else {
+            LevelFilter::Info
+        });
+    }
+}
@@ -1,27 +1,62 @@
 use anyhow::{Result, anyhow};
+use candive::alerts::AnyAlert;
+use candive::calibration;
+use candive::crypto::ct_eq_u32;
+use candive::diag::cellhealth;
+use candive::diag::config::ConfigField;
+use candive::diag::depth_comp::{self, DepthCompIssue, DepthCompStats};
 use candive::diag::did::solo::*;
+use candive::diag::firmware;
+use candive::diag::menu::{self, FindError, MenuClient, MenuError, MenuItem, SettingsCatalog};
+use candive::diag::session::{DeviceIdentity, DeviceSession, SessionError};
 use candive::diag::settings::{
-    SettingValue, UserSettingDid, UserSettingInput, UserSettingPayload, UserSettingType,
+    self, CountDid, EnumDid, InfoDid, InputDid, ReadPayload, SettingDid, SettingInfo,
+    SettingRiskClass, SettingValue, StateDid, UserSettingDid, UserSettingInput, UserSettingType,
 };
 use candive::diag::solo::{self, *};
 use candive::diag::{Stm32Crc32, did::*};
-use candive::divecan::{DiveCanFrame, DiveCanId, Msg};
-use candive::uds::uds::Dlf;
+use candive::divecan::{
+    CurrentAlert, DiveCanId, HANDSET_ADDR, Msg, SOLO_ADDR, TxDlcPolicy, VoltageAlert,
+};
+#[cfg(feature = "sqlite")]
+use candive::fleet::Fleet;
+use candive::fmt::{DisplayUnits, UnitsPreference};
+use candive::power::{self, PowerIssue, PowerStats};
+use candive::preflight::{PreflightIssue, ProgrammingMargins, ProgrammingWatch};
+use candive::uds::Dlf;
+use candive::uds::client::TransferTuning;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
 use clap::{Parser, Subcommand, ValueEnum};
-use des::Des;
-use des::cipher::generic_array::GenericArray;
-use des::cipher::{BlockEncrypt, KeyInit};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::ffi::CStr;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, Write};
-use std::path::PathBuf;
+use std::io::{IsTerminal, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 
+use crate::crypto::{CipherKind, SoloKey};
 #[cfg(target_os = "linux")]
 use crate::transport::SocketCanIsoTpSessionUdsSession;
-use crate::transport::{BleTransport, RfcommGatewayTransport};
+use crate::transport::{
+    BleTransport, RfcommGatewayTransport, SocketcandIsoTpSession, TcpGatewayTransport,
+};
 
+mod bridge;
+mod capture;
+mod cellcsv;
+mod crypto;
+mod didscan;
+mod flood;
+mod fuzz;
+mod hostcheck;
+mod i18n;
+mod influx;
+mod jsonl;
+mod keys;
+mod logger;
 mod msgformat;
+#[cfg(feature = "scripting")]
+mod script;
+mod support;
 mod transport;
 
 enum Transport {
@@ -29,6 +64,8 @@ enum Transport {
     Can(SocketCanIsoTpSessionUdsSession),
     Rfcomm(RfcommGatewayTransport),
     Ble(BleTransport),
+    Tcp(TcpGatewayTransport),
+    Socketcand(SocketcandIsoTpSession),
 }
 
 impl candive::uds::client::UdsTransport for Transport {
@@ -40,11 +77,38 @@ impl candive::uds::client::UdsTransport for Transport {
             Transport::Can(t) => t.request(req, resp_buf),
             Transport::Rfcomm(t) => t.request(req, resp_buf),
             Transport::Ble(t) => t.request(req, resp_buf),
+            Transport::Tcp(t) => t.request(req, resp_buf),
+            Transport::Socketcand(t) => t.request(req, resp_buf),
+        }
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Transport::Can(t) => t.send(req),
+            Transport::Rfcomm(t) => t.send(req),
+            Transport::Ble(t) => t.send(req),
+            Transport::Tcp(t) => t.send(req),
+            Transport::Socketcand(t) => t.send(req),
+        }
+    }
+}
+
+impl Transport {
+    fn with_request_timeout(self, timeout: std::time::Duration) -> Self {
+        match self {
+            #[cfg(target_os = "linux")]
+            Transport::Can(t) => Transport::Can(t.with_request_timeout(timeout)),
+            Transport::Rfcomm(t) => Transport::Rfcomm(t.with_request_timeout(timeout)),
+            Transport::Ble(t) => Transport::Ble(t.with_request_timeout(timeout)),
+            Transport::Tcp(t) => Transport::Tcp(t.with_request_timeout(timeout)),
+            Transport::Socketcand(t) => Transport::Socketcand(t.with_request_timeout(timeout)),
         }
     }
 }
 
 fn parse_transport_uri(uri: &str, src: u8, dst: u8) -> CmdResult<Transport> {
+    hostcheck::transport(uri)?;
     if let Some(interface) = uri.strip_prefix("can://") {
         #[cfg(target_os = "linux")]
         {
@@ -65,10 +129,10 @@ fn parse_transport_uri(uri: &str, src: u8, dst: u8) -> CmdResult<Transport> {
         let session = RfcommGatewayTransport::new(port, src, dst)
             .map_err(|e| anyhow!("Failed to create RFCOMM transport: {:?}", e))?;
         if src != 1 {
-            eprintln!("src != 0x1 probably wont work.")
+            log::warn!("src != 0x1 probably wont work.")
         }
         if dst == 1 || dst == 0x80 {
-            eprintln!(
+            log::warn!(
                 "With dst={:x}, you are communicating with handset, probably not what you want",
                 dst
             )
@@ -84,13 +148,90 @@ fn parse_transport_uri(uri: &str, src: u8, dst: u8) -> CmdResult<Transport> {
         let session = BleTransport::new(src, dst, device_id)
             .map_err(|e| anyhow!("Failed to create BLE transport: {:?}", e))?;
         Ok(Transport::Ble(session))
+    } else if let Some(addr) = uri.strip_prefix("tcp://") {
+        let session = TcpGatewayTransport::new(addr, src, dst)
+            .map_err(|e| anyhow!("Failed to create TCP transport: {:?}", e))?;
+        Ok(Transport::Tcp(session))
+    } else if let Some(target) = uri.strip_prefix("socketcand://") {
+        let id = DiveCanId::new(src, dst, 0xa);
+        let session = SocketcandIsoTpSession::new(target, id.reply(id.kind).to_u32(), id.to_u32())
+            .map_err(|e| anyhow!("Failed to create socketcand transport: {:?}", e))?;
+        Ok(Transport::Socketcand(session))
     } else {
         Err(anyhow!(
-            "Invalid transport URI. Use can://<interface>, rfcomm://<port>, or ble://"
+            "Invalid transport URI. Use can://<interface>, rfcomm://<port>, ble://, tcp://<host>[:port] or socketcand://<host>[:port]/<interface>"
         ))
     }
 }
 
+/// Where the device is, as given by the global options
+struct TransportConfig {
+    uri: String,
+    src: u8,
+    dst: u8,
+    /// How long a request waits for its answer, the transport's own
+    /// default if `None`. Slow DIDs always get at least
+    /// [`transport::SLOW_DID_TIMEOUT`].
+    request_timeout: Option<std::time::Duration>,
+}
+
+impl TransportConfig {
+    fn open(&self) -> CmdResult<Transport> {
+        let transport = parse_transport_uri(&self.uri, self.src, self.dst)?;
+        Ok(match self.request_timeout {
+            Some(timeout) => transport.with_request_timeout(timeout),
+            None => transport,
+        })
+    }
+}
+
+/// A connected device, what every command talking UDS to it gets
+type Session = DeviceSession<Transport>;
+
+/// Opens the transport and reads the device's identity and firmware version
+fn connect(config: &TransportConfig) -> CmdResult<Session> {
+    DeviceSession::connect(config.open()?).map_err(transport::uds_error_to_anyhow)
+}
+
+/// How many times a long transfer reopens a dropped link before giving up
+const MAX_RECONNECTS: u32 = 2;
+
+/// A session and the options to open it again, for commands that run long
+/// enough for the link to drop
+struct Link<'a> {
+    session: &'a mut Session,
+    config: &'a TransportConfig,
+}
+
+impl Link<'_> {
+    /// Runs `op`, reopening the transport and running it again from the
+    /// start when it fails with a transport error. Gives up if another
+    /// device answers after reconnecting.
+    fn retry<R>(&mut self, mut op: impl FnMut(&mut Session) -> CmdResult<R>) -> CmdResult<R> {
+        let mut reconnects = 0;
+        loop {
+            match op(self.session) {
+                Err(e) if transport::is_transport_error(&e) && reconnects < MAX_RECONNECTS => {
+                    reconnects += 1;
+                    log::warn!("{:#}, reconnecting ({}/{})", e, reconnects, MAX_RECONNECTS);
+                    self.reconnect()?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> CmdResult {
+        let transport = self.config.open()?;
+        self.session.reconnect(transport).map_err(|e| match e {
+            SessionError::DeviceChanged => {
+                anyhow!("A different device answered after reconnecting, stopping")
+            }
+            SessionError::Uds(e) => transport::uds_error_to_anyhow(e),
+        })
+    }
+}
+
 type CmdResult<T = ()> = Result<T>;
 
 // Extension trait to provide convenience methods for UdsTransport implementors
@@ -99,7 +240,11 @@ trait UdsTransport {
     fn rdbi_codec<T: candive::diag::did::DataIdentifier + candive::diag::did::ReadableDid>(
         &mut self,
     ) -> CmdResult<T>;
+    fn rdbi_lenient<T: candive::diag::did::DataIdentifier + candive::diag::did::ReadableDid>(
+        &mut self,
+    ) -> CmdResult<Option<T>>;
     fn wdbi(&mut self, did: u16, data: &[u8]) -> CmdResult<()>;
+    fn logs_info(&mut self) -> CmdResult<LogsInfo>;
     fn upload<W: Write>(
         &mut self,
         address: u32,
@@ -113,17 +258,17 @@ trait UdsTransport {
         address: u32,
         firmware_data: &[u8],
         progress: impl Fn(usize, usize),
-    ) -> CmdResult<()>;
+    ) -> CmdResult<TransferTuning>;
 }
 
 impl<T: candive::uds::client::UdsTransport<Error = transport::TransportError>> UdsTransport for T {
     fn rdbi(&mut self, did: u16) -> CmdResult<Vec<u8>> {
         use candive::uds::client;
-        let mut tx_buf = vec![0u8; 256];
-        let mut rx_buf = vec![0u8; 4096];
-        let data = client::rdbi(self, did, &mut tx_buf, &mut rx_buf)
-            .map_err(transport::uds_error_to_anyhow)?;
-        Ok(data.to_vec())
+        let mut data = vec![0u8; solo::MAX_RDBI_LEN + client::RDBI_HEADER_LEN];
+        let len =
+            client::rdbi_into(self, did, &mut data).map_err(transport::uds_error_to_anyhow)?;
+        data.truncate(len);
+        Ok(data)
     }
 
     fn rdbi_codec<D: candive::diag::did::DataIdentifier + candive::diag::did::ReadableDid>(
@@ -133,6 +278,32 @@ impl<T: candive::uds::client::UdsTransport<Error = transport::TransportError>> U
         Ok(D::try_from(data.as_slice()).map_err(|e| anyhow::anyhow!("{:?}", e))?)
     }
 
+    /// Like `rdbi_codec`, but a response from firmware with more or fewer
+    /// fields is a warning with the bytes that weren't understood, not an
+    /// error. `None` if nothing could be decoded.
+    fn rdbi_lenient<D: candive::diag::did::DataIdentifier + candive::diag::did::ReadableDid>(
+        &mut self,
+    ) -> CmdResult<Option<D>> {
+        let data = self.rdbi(D::DID)?;
+        let decoded = candive::diag::did::decode_lenient::<D>(&data);
+        if let Some(warning) = &decoded.warning {
+            log::warn!(
+                "DID 0x{:04X} not as expected ({:?}), unparsed: [{}]",
+                D::DID,
+                warning,
+                hex::encode_upper(decoded.remainder)
+            );
+        }
+        for (name, bytes) in decoded.partial_fields() {
+            log::warn!("  {} = [{}]", name, hex::encode_upper(bytes));
+        }
+        Ok(decoded.value)
+    }
+
+    fn logs_info(&mut self) -> CmdResult<LogsInfo> {
+        LogsInfo::query(self).map_err(transport::uds_error_to_anyhow)
+    }
+
     fn wdbi(&mut self, did: u16, data: &[u8]) -> CmdResult<()> {
         use candive::uds::client;
         let mut tx_buf = vec![0u8; 256 + data.len()];
@@ -179,13 +350,15 @@ impl<T: candive::uds::client::UdsTransport<Error = transport::TransportError>> U
         Ok(())
     }
 
+    /// Downloads `firmware_data` in blocks sized by a [`BlockTuner`], then
+    /// checks the device's `FirmwareCrc` against the CRC of what was sent.
     fn download(
         &mut self,
         address: u32,
         firmware_data: &[u8],
         progress: impl Fn(usize, usize),
-    ) -> CmdResult<()> {
-        use candive::uds::client::DownloadSession;
+    ) -> CmdResult<TransferTuning> {
+        use candive::uds::client::{BlockTuner, DownloadSession};
         let mut tx_buf = vec![0u8; 4096];
         let mut rx_buf = vec![0u8; 256];
 
@@ -198,25 +371,34 @@ impl<T: candive::uds::client::UdsTransport<Error = transport::TransportError>> U
         )
         .map_err(transport::uds_error_to_anyhow)?;
 
-        let max_block_len = session.max_block_len();
+        let mut tuner = BlockTuner::new(session.max_block_len());
         let mut offset = 0;
 
         while offset < firmware_data.len() {
             progress(offset, firmware_data.len());
 
             let remaining = firmware_data.len() - offset;
-            let block_size = remaining.min(max_block_len);
+            let block_size = remaining.min(tuner.block_len());
             let block_data = &firmware_data[offset..offset + block_size];
 
+            let resends = session.resends();
+            let sent = std::time::Instant::now();
             session
                 .send_block(block_data)
                 .map_err(transport::uds_error_to_anyhow)?;
+            if session.resends() > resends {
+                tuner.on_error();
+            } else {
+                tuner.on_block(block_size, sent.elapsed().as_micros() as u64);
+            }
             offset += block_size;
         }
 
-        session.finish().map_err(transport::uds_error_to_anyhow)?;
         progress(firmware_data.len(), firmware_data.len());
-        Ok(())
+        session
+            .finish_verified(FirmwareCrc::DID, FirmwareCrc::of_image(firmware_data).crc)
+            .map_err(transport::uds_error_to_anyhow)?;
+        Ok(tuner.report())
     }
 }
 
@@ -235,108 +417,38 @@ fn stm32_crc32_read<R: Read>(reader: &mut R) -> Result<u32> {
     Ok(crc.checksum())
 }
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
-enum ConfigKey {
-    #[value(name = "cal")]
-    Cal,
-    #[value(name = "ppo2")]
-    Ppo2,
-    #[value(name = "cells")]
-    Cells,
-    #[value(name = "depth-comp")]
-    DepthComp,
-    #[value(name = "min-current")]
-    MinCurrent,
-    #[value(name = "max-current")]
-    MaxCurrent,
-    #[value(name = "min-voltage")]
-    MinVoltage,
-    #[value(name = "voltage-doubling")]
-    VoltageDoubling,
-}
-
-#[derive(Clone, Copy, Debug, ValueEnum)]
-enum OnOff {
-    #[value(name = "on")]
-    On,
-    #[value(name = "off")]
-    Off,
-}
-
-impl From<OnOff> for bool {
-    fn from(v: OnOff) -> Self {
-        matches!(v, OnOff::On)
-    }
-}
-
-#[derive(Clone, Copy, Debug, ValueEnum)]
-enum CalibrationProcedureArg {
-    #[value(name = "direct")]
-    Direct,
-    #[value(name = "monitored")]
-    Monitored,
-}
-
-impl From<CalibrationProcedureArg> for CalibrationProcedure {
-    fn from(v: CalibrationProcedureArg) -> Self {
-        match v {
-            CalibrationProcedureArg::Direct => CalibrationProcedure::Direct,
-            CalibrationProcedureArg::Monitored => CalibrationProcedure::Monitored,
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug, ValueEnum)]
-enum Ppo2ModeArg {
-    #[value(name = "user")]
-    User,
-    #[value(name = "manual")]
-    Manual,
-    #[value(name = "1sec")]
-    OneSec,
-    #[value(name = "5sec")]
-    FiveSec,
-}
-
-impl From<Ppo2ModeArg> for PPO2ControlMode {
-    fn from(v: Ppo2ModeArg) -> Self {
-        match v {
-            Ppo2ModeArg::User => PPO2ControlMode::UserSelect,
-            Ppo2ModeArg::Manual => PPO2ControlMode::Manual,
-            Ppo2ModeArg::OneSec => PPO2ControlMode::OneSec,
-            Ppo2ModeArg::FiveSec => PPO2ControlMode::FiveSec,
-        }
-    }
+/// Parses a config key from the field table in `candive::diag::config`.
+fn config_key_parser() -> impl TypedValueParser<Value = ConfigField> {
+    PossibleValuesParser::new(ConfigField::ALL.iter().map(|f| f.name()))
+        .map(|name| ConfigField::from_name(&name).expect("listed in ConfigField::ALL"))
 }
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
-enum CellModeArg {
-    #[value(name = "two")]
-    Two,
-    #[value(name = "three")]
-    Three,
+/// Parses `metric` or `imperial`.
+fn tx_dlc_parser() -> impl TypedValueParser<Value = TxDlcPolicy> {
+    PossibleValuesParser::new(["minimal", "pad8"]).map(|name| match name.as_str() {
+        "pad8" => TxDlcPolicy::PadTo8,
+        _ => TxDlcPolicy::Minimal,
+    })
 }
 
-impl From<CellModeArg> for CellMode {
-    fn from(v: CellModeArg) -> Self {
-        match v {
-            CellModeArg::Two => CellMode::TwoCell,
-            CellModeArg::Three => CellMode::ThreeCell,
-        }
-    }
+fn units_parser() -> impl TypedValueParser<Value = UnitsPreference> {
+    PossibleValuesParser::new(["metric", "imperial"]).map(|name| match name.as_str() {
+        "imperial" => UnitsPreference::Imperial,
+        _ => UnitsPreference::Metric,
+    })
 }
 
 #[derive(Parser)]
 #[command(
     name = "solodiag",
     about = "Diagnostic and maintenance tool for SOLO devices",
-    long_about = "Read configuration and device info, export logs, upload firmware, and run calibration procedures. Supports CAN (Linux only) and RFCOMM transports.",
+    long_about = "Read configuration and device info, export logs, upload firmware, and run calibration procedures. Supports CAN (Linux only, or remote through socketcand), RFCOMM, BLE and TCP gateway transports.",
     subcommand_required = true,
     arg_required_else_help = true,
     after_help = "Examples:\n  SOLO_KEY=... solodiag --transport rfcomm:///dev/rfcomm0 device show\n  solodiag --transport can://can0 logs dump --count 200\n  solodiag --transport rfcomm:///dev/rfcomm0 config set ppo2 manual"
 )]
 struct Cli {
-    /// Transport URI (can://<interface> or rfcomm://<port>)
+    /// Transport URI (can://<interface>, rfcomm://<port>, ble://, tcp://<host>[:port] or socketcand://<host>[:port]/<interface>)
     #[arg(short, long, default_value = "can://can0", global = true)]
     transport: String,
 
@@ -348,17 +460,64 @@ struct Cli {
     #[arg(long, default_value = "0x4", value_parser = parse_hex_u8, global = true)]
     dst: u8,
 
+    /// Cipher used with SOLO_KEY (current firmware uses des)
+    #[arg(long, value_enum, default_value = "des", global = true)]
+    cipher: CipherKind,
+
+    /// Use SOLO_KEY without first checking that it decrypts the log
+    #[arg(long, global = true)]
+    skip_key_check: bool,
+
+    /// Units for printed pressures and depths
+    #[arg(long, default_value = "metric", value_parser = units_parser(), global = true)]
+    units: UnitsPreference,
+
+    /// DLC of frames sent on a raw bus: the shortest the kind allows, or
+    /// always 8 zero-padded for adapters and gateways that need full frames
+    #[arg(long, default_value = "minimal", value_parser = tx_dlc_parser(), global = true)]
+    tx_dlc: TxDlcPolicy,
+
+    /// Most memory log commands buffer at once (e.g. 64K, 4M). Logs are
+    /// streamed through buffers of this size, never read whole.
+    #[arg(long, default_value = "1M", value_parser = parse_size, global = true)]
+    max_memory: usize,
+
+    /// Log entries per upload, to troubleshoot slow or flaky links. By
+    /// default chunks are sized from the link's measured throughput.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    chunk_entries: Option<u32>,
+
+    /// Language of decoded messages and alert labels: a built-in code (en)
+    /// or a catalog file, see lang/en.txt for the format
+    #[arg(long, default_value = "en", global = true)]
+    lang: String,
+
+    /// Milliseconds to wait for each UDS answer before taking the link as
+    /// lost (default 5000, 3000 over BLE). Log dumps and firmware uploads
+    /// reconnect after a lost link.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    request_timeout: Option<u64>,
+
+    /// Only print warnings and errors on stderr, no progress notes
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Inspect, export, or dump device log entries (requires SOLO_KEY)
+    /// Inspect, export, or dump device log entries (decoding them requires SOLO_KEY)
     Logs {
         #[command(subcommand)]
         action: LogsAction,
     },
+    /// Alerts stored in the device log (requires SOLO_KEY), or raised on the bus
+    Alerts {
+        #[command(subcommand)]
+        action: AlertsAction,
+    },
     /// Dump a fixed SPI flash region to a file
     Mem { filename: PathBuf },
     /// Manage user-configurable settings stored on the device
@@ -367,7 +526,14 @@ enum Commands {
         action: UserConfigAction,
     },
     /// Scan and print readable DIDs in the 0x8000–0xFFFF range
-    RdbiScan,
+    #[command(
+        long_about = "Reads every DID from 0x8000 to 0xFFFF and prints those that answer. With --output the results are also saved as JSON lines, tagged with the firmware version and device ID, for `dev did-diff`."
+    )]
+    RdbiScan {
+        /// Save the results here as JSON lines
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
     /// Firmware operations (info and upload)
     Fw {
         #[command(subcommand)]
@@ -388,13 +554,255 @@ enum Commands {
         #[command(subcommand)]
         action: CalAction,
     },
+    /// Solenoid bench checks (CAN only)
+    Solenoid {
+        #[command(subcommand)]
+        action: SolenoidAction,
+    },
+    /// Show battery and solenoid limits against observed draw
+    #[command(
+        long_about = "Prints the power limits from the control configuration and battery related user settings. On a can:// or socketcand:// transport it also listens to SoloStatus broadcasts for a few seconds and compares battery voltage and solenoid draw while firing against the limits. Settings that cannot be stored or fall outside the safe bands are flagged."
+    )]
+    Power,
+    /// Record bus frames and events into an SQLite database (CAN only)
+    #[cfg(feature = "sqlite")]
+    #[command(
+        long_about = "Listens on the raw DiveCAN bus and stores every frame plus derived events (setpoint changes, alerts, dives) in the frames, events and dives tables. Runs until interrupted. With --input, records several buses at once, or imports candump -L files merged by timestamp, and stores each frame's interface in the iface column. Files whose CAN ids look byte-swapped are reported, --fix-byte-order swaps them back. --influx udp://host:port also sends the telemetry as InfluxDB line protocol, like monitor."
+    )]
+    Record {
+        #[arg(long)]
+        db: PathBuf,
+        /// Bus (can://, socketcand://) or candump -L file to read instead of --transport, repeatable
+        #[arg(long = "input")]
+        inputs: Vec<String>,
+        /// Also send telemetry as InfluxDB line protocol to udp://host:port
+        #[arg(long)]
+        influx: Option<String>,
+        /// Swap the CAN id bytes back in candump files written with the wrong byte order
+        #[arg(long)]
+        fix_byte_order: bool,
+    },
+    /// Tunnel raw UDS PDUs from a TCP port to the --dst node
+    #[command(
+        long_about = "Listens on a TCP port and relays UDS requests to the node selected by --dst over the chosen transport, so third-party UDS tools (e.g. python-udsoncan) can talk to DiveCAN devices. Each PDU is sent as a 2-byte big-endian length followed by the PDU, in both directions; a zero-length response means the transport failed. Serves one client at a time and runs until interrupted."
+    )]
+    Bridge {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:13400")]
+        listen: String,
+    },
+    /// Print bus frames and events as they arrive (CAN only)
+    #[command(
+        long_about = "Listens on the raw DiveCAN bus and prints every frame with its decoded message, derived events (setpoint changes, alerts, dives) and reassembled ISO-TP (UDS) payloads. Frames are colored by message category on a terminal (set NO_COLOR to turn that off) and marked when they come from a node that normally does not send that kind. With --output jsonl each line is a JSON object with an ISO-8601 UTC host timestamp in \"ts\" and a \"type\" of frame (with its \"category\"), isotp, isotp_error, event or other (extended ids without the DiveCAN prefix). With --csv the latest CellVoltages and CellPpo2 values are also written to a CSV file every --interval ms (time in seconds, cell mV, cell ppO₂ in bar), and --gnuplot writes a matching plot script next to it. With --unknown-report, Ctrl-C prints each unknown kind and short frame seen, with a count and an example payload, to stderr before exiting. With --write every frame read, error frames included, is also appended to a candump -L file as it is displayed, for replaying with --input or canplayer. The file is created if missing, an existing capture is kept and added to. With --influx udp://host:port the telemetry (cell mV and ppO₂, SOLO status, pressures, setpoint) is also sent to an InfluxDB UDP listener as line protocol, tagged with the sender and interface. A candump file whose CAN ids only decode with their bytes swapped gets a warning after the first frames; --fix-byte-order swaps them back. Runs until interrupted."
+    )]
+    Monitor {
+        #[arg(long, value_enum, default_value = "text")]
+        output: MonitorOutput,
+        /// Also write cell mV and ppO₂ samples to this CSV file
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        /// CSV sampling interval in ms
+        #[arg(long, default_value_t = 1000, requires = "csv")]
+        interval: u64,
+        /// Write a gnuplot script for the CSV (same name, .gp extension)
+        #[arg(long, requires = "csv")]
+        gnuplot: bool,
+        /// On Ctrl-C, print unknown kinds and DLC mismatches seen, with an example of each
+        #[arg(long)]
+        unknown_report: bool,
+        /// Bus (can://, socketcand://) or candump -L file to read instead of
+        /// --transport, repeatable. Frames are merged by time and tagged with
+        /// their interface.
+        #[arg(long = "input")]
+        inputs: Vec<String>,
+        /// Also append every frame to this candump -L file, to replay later
+        /// with --input or canplayer
+        #[arg(long)]
+        write: Option<PathBuf>,
+        /// Also send telemetry as InfluxDB line protocol to udp://host:port
+        #[arg(long)]
+        influx: Option<String>,
+        /// Swap the CAN id bytes back in candump files written with the wrong byte order
+        #[arg(long)]
+        fix_byte_order: bool,
+    },
+    /// Collect device, firmware, settings, calibration and log info into a zip for support
+    #[command(
+        long_about = "Runs device show, fw info, config list, user list, cal show o2/zero and logs info, reads every known readable DID and, with SOLO_KEY set or a stored key, exports the logs from --last decrypted, all over one connection. Each goes into its own file in the zip together with metadata.txt (tool version, host, transport, which sections failed). A failing section is recorded with its error and the rest still run, a dropped link is reopened first. SOLO_KEY itself is never written to the bundle."
+    )]
+    SupportBundle {
+        output: PathBuf,
+        /// How far back to export logs (e.g. 12h, 7d, 2w)
+        #[arg(long, default_value = "7d", value_parser = parse_duration)]
+        last: u32,
+    },
+    /// Find gateways to use as --transport
+    #[command(
+        long_about = "With --network, browses mDNS for _divecan._tcp gateways (Wi-Fi bridges speaking the same SLIP datagram protocol as the RFCOMM gateway) and prints a tcp:// transport URI for each."
+    )]
+    Discover {
+        /// Browse the local network via mDNS
+        #[arg(long)]
+        network: bool,
+        /// Seconds to wait for answers
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+    },
+    /// Devices this machine has connected to, with notes
+    #[cfg(feature = "sqlite")]
+    #[command(
+        long_about = "Every command that talks to a device records its serial, device ID, firmware version and the time in a local SQLite registry (SOLODIAG_FLEET, default $XDG_DATA_HOME/solodiag/fleet.db or ~/.local/share/solodiag/fleet.db, set SOLODIAG_FLEET to an empty string to turn it off). These commands show it and attach notes. No transport needed."
+    )]
+    Fleet {
+        #[command(subcommand)]
+        action: FleetAction,
+    },
+    /// SOLO_KEYs stored per serial number or device ID pattern
+    #[command(
+        long_about = "Stores keys for devices from batches that use different SOLO_KEYs. Each key is assigned to a pattern (* and ? wildcards) matched against the serial number and the device ID. Without SOLO_KEY set, commands use the key of the most specific pattern matching the connected device. Keys are kept in SOLODIAG_KEYS, default $XDG_CONFIG_HOME/solodiag/keys or ~/.config/solodiag/keys. No transport needed."
+    )]
+    Key {
+        #[command(subcommand)]
+        action: KeyAction,
+    },
+    /// Print the DiveCAN protocol description for other tools
+    Protocol {
+        #[arg(value_enum)]
+        format: ProtocolFormat,
+    },
+    /// Generate synthetic bus traffic for load testing (CAN only)
+    Sim {
+        #[arg(long, value_enum, default_value = "flood")]
+        mode: SimMode,
+        /// Frames per second
+        #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+        rate: u32,
+        /// Message mix, cycled in order
+        #[arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_value = "ppo2,voltages,pressure,isotp"
+        )]
+        mix: Vec<flood::FloodKind>,
+        /// Seconds to run, 0 runs until interrupted
+        #[arg(long, default_value_t = 10)]
+        duration: u64,
+        /// Seed for all noise and timing jitter, replays a previous run exactly (random if omitted)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Timing jitter in percent of the frame period
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+        jitter: u8,
+    },
+    /// Configure or inspect the local CAN interface of a can:// transport (Linux only)
+    Can {
+        #[command(subcommand)]
+        action: CanAction,
+    },
+    /// Browse and edit the settings menu of another node the way a handset does
+    #[command(
+        long_about = "Lists the user settings of the node at <DST> with their values, then lets you pick one by number and enter a new value, either an option number or name for selections or a raw number for the rest. The value is range-checked against what the node reports and high-risk settings ask for confirmation. An empty line goes back or quits."
+    )]
+    RemoteMenu {
+        /// Address of the node whose menu to open, e.g. 0x4 for the SOLO
+        #[arg(value_parser = parse_hex_u8)]
+        dst: u8,
+    },
+    /// Reverse-engineering and firmware development helpers
+    Dev {
+        #[command(subcommand)]
+        action: DevAction,
+    },
+    /// Run Rhai scripts against the device
+    #[cfg(feature = "scripting")]
+    Script {
+        #[command(subcommand)]
+        action: ScriptAction,
+    },
+}
+
+#[cfg(feature = "scripting")]
+#[derive(Subcommand)]
+enum ScriptAction {
+    /// Run a .rhai script
+    #[command(
+        long_about = "Runs a Rhai script (https://rhai.rs) over the UDS session. Besides the language itself a script can call rdbi(did) -> blob, wdbi(did, blob), send_msg(kind, blob) to put a DiveCAN frame from --src to --dst on the bus (can:// and socketcand:// only), sleep(ms) and print(value). Errors from the device stop the script unless caught with try/catch. Scripts can't touch files, processes or the network. Example: let fw = rdbi(0x8011); print(`firmware ${fw}`);"
+    )]
+    Run { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum DevAction {
+    /// Compare two `rdbi-scan --output` files
+    #[command(
+        long_about = "Lists the DIDs that appeared, disappeared, changed size or changed contents between two saved RDBI scans, typically of the same device before and after a firmware update, followed by a summary."
+    )]
+    DidDiff { old: PathBuf, new: PathBuf },
+    /// Decode a captured DID payload offline
+    #[command(
+        long_about = "Decodes the data of DID <DID> (hex with 0x, or decimal) from <HEXFILE>, hex text with any whitespace, the way solodiag would decode it read from a device, and prints the structured result. Only the DID data, without the 62 and DID bytes of the response. `-` reads stdin. No transport needed."
+    )]
+    EmulateDid {
+        #[arg(value_parser = parse_hex_u16)]
+        did: u16,
+        hexfile: PathBuf,
+    },
+    /// Time repeated reads over the transport
+    #[command(
+        long_about = "Reads DID <DID> (firmware version by default) --count times after one warm-up read and prints the latency distribution and byte counts, to compare transports (can://, rfcomm://, ble://, ...) against the same device. Latencies are whole milliseconds in power-of-two buckets."
+    )]
+    Bench {
+        /// DID to read (hex with 0x, or decimal)
+        #[arg(long, value_parser = parse_hex_u16, default_value = "0x8011")]
+        did: u16,
+        /// Reads to time
+        #[arg(long, default_value_t = 100)]
+        count: u32,
+    },
+    /// Send invalid UDS requests and check the NRCs (CAN only, development units)
+    #[command(
+        long_about = "Negative testing for firmware developers. Sends requests with bad lengths, unknown DIDs, out-of-range addresses and out-of-sequence transfers and checks the device refuses each with the right NRC. A positive answer, a different NRC, no answer or a reboot (the device announcing its Id again) fails the case. Listens to the bus first and refuses to run during a dive or under water. Don't point this at a unit that's going in the water."
+    )]
+    FuzzDevice {
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CanAction {
+    /// Set the bitrate and bring the interface up
+    #[command(
+        long_about = "Replaces `ip link set <if> down; ip link set <if> type can bitrate <rate> restart-ms 100; ip link set <if> up`. restart-ms lets the controller recover from bus-off on its own. With --vcan a virtual interface is created (if missing) and brought up instead, for testing without hardware. Needs root or CAP_NET_ADMIN."
+    )]
+    Setup {
+        /// DiveCAN runs at 125 kbit/s
+        #[arg(long, default_value_t = 125_000)]
+        bitrate: u32,
+        /// Create a virtual CAN interface instead of configuring hardware
+        #[arg(long)]
+        vcan: bool,
+    },
+    /// Show link state, controller state, bitrate and error counters
+    Status,
+    /// Find the bitrate of the bus the interface is connected to
+    #[command(
+        long_about = "Listens for --listen ms at each common bitrate (125k, 250k, 500k, 1M, 100k, 50k) in listen-only mode, so the controller never ACKs or sends error frames on a bus running at another rate. Reports DiveCAN, other and error frames per bitrate (error frames only where the driver supports berr-reporting), then leaves the interface up at the bitrate with the most DiveCAN traffic, or at its previous bitrate (125 kbit/s if it had none) if none was seen or probing failed. Needs root or CAP_NET_ADMIN."
+    )]
+    Autodetect {
+        /// Time to listen at each bitrate, in ms
+        #[arg(long, default_value_t = 1500)]
+        listen: u64,
+    },
 }
 
 #[derive(Subcommand)]
 enum LogsAction {
     /// Download log entries to a file (optionally decrypt if SOLO_KEY is set)
     #[command(
-        long_about = "Downloads count entries starting after skip. Writes to <filename> (uses a temporary file and CRC verification)."
+        long_about = "Downloads count entries starting after skip. Writes to <filename> (uses a temporary file and CRC verification). --since/--last locate the start by binary searching the log for Diving timestamps and run to the end of the written log unless --count is given (requires SOLO_KEY). With --split-per-dive the decrypted entries are split on Diving start/stop into one file per dive next to <filename>; requires SOLO_KEY."
     )]
     Export {
         filename: PathBuf,
@@ -402,43 +810,153 @@ enum LogsAction {
         count: Option<u32>,
         #[arg(long)]
         skip: Option<u32>,
+        /// Start at the first dive on or after this UTC time (YYYY-MM-DD[ HH:MM[:SS]])
+        #[arg(long, value_parser = parse_datetime, conflicts_with_all = ["skip", "last"])]
+        since: Option<u32>,
+        /// Start at the first dive within this long ago (e.g. 90m, 12h, 2d, 1w)
+        #[arg(long, value_parser = parse_duration, conflicts_with = "skip")]
+        last: Option<u32>,
+        /// Write one decrypted file per dive (<name>-dive<N>-<date>.<ext>) instead of one file
+        #[arg(long)]
+        split_per_dive: bool,
     },
     /// Stream log entries to stdout (pretty format by default; --candump for legacy candump format)
     #[command(
-        long_about = "Fetches logs in chunks, verifies CRC, decrypts using SOLO_KEY, then prints each entry."
+        long_about = "Fetches logs in chunks, sized from the link's throughput unless --chunk-entries is given, verifies CRC, decrypts using SOLO_KEY, then prints each entry. Without SOLO_KEY the entries are printed as CRC-verified encrypted hex, one per line with its index and marked (encrypted). --since/--last start at the first dive in the time window, found by binary searching the log, and require SOLO_KEY."
     )]
     Dump {
         #[arg(long)]
         count: Option<u32>,
         #[arg(long)]
         skip: Option<u32>,
+        /// Start at the first dive on or after this UTC time (YYYY-MM-DD[ HH:MM[:SS]])
+        #[arg(long, value_parser = parse_datetime, conflicts_with_all = ["skip", "last"])]
+        since: Option<u32>,
+        /// Start at the first dive within this long ago (e.g. 90m, 12h, 2d, 1w)
+        #[arg(long, value_parser = parse_duration, conflicts_with = "skip")]
+        last: Option<u32>,
         #[arg(long)]
         candump: bool,
     },
     /// Show log storage layout (entry size, count, total size)
     Info,
+    /// Check an exported log file without the device attached
+    #[command(
+        long_about = "Checks a file written by `logs export`. A raw (encrypted) export is checked against the CRC and length field in the digest written next to it (<filename>.digest.json, or --digest), and for whole 12-byte entries. A decrypted export is checked for whole entries, a kind before every entry and enough entries of known kinds. Works offline, no transport needed."
+    )]
+    Verify {
+        file: PathBuf,
+        /// Digest of a raw export, default <file>.digest.json if it exists
+        #[arg(long)]
+        digest: Option<PathBuf>,
+    },
+    /// Redact serials, device ids, timestamps and surface pressure from a log or capture
+    #[command(
+        long_about = "Reads a decrypted log written by `logs export`, or a candump -L capture, and writes a copy safe to attach to public issues. Serial numbers become 00000001, 00000002, ..., Diving timestamps are shifted so the first dive starts 2000-01-01 and AmbientPressure is shifted to a 1013 mbar surface. In captures the serial number and device id DIDs are zeroed in UDS traffic, and the frame timestamps are shifted with the Diving ones. A capture whose CAN ids look byte-swapped is refused, since its frames can't be redacted. Entry layout, ordering, relative times and depths are kept. Works offline, no transport needed."
+    )]
+    Anonymize { input: PathBuf, output: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum AlertsAction {
+    /// List the alerts in the written part of the log, oldest first
+    #[command(
+        long_about = "Downloads and decrypts the written part of the log and lists every Alert entry with its label. Alerts carry no timestamp, so each is shown with the last Diving entry before it (dive number and time). Repeats of the same code are folded into one line with a count. --since/--last only look at the log from the first dive in the window."
+    )]
+    List {
+        /// Start at the first dive on or after this UTC time (YYYY-MM-DD[ HH:MM[:SS]])
+        #[arg(long, value_parser = parse_datetime, conflicts_with = "last")]
+        since: Option<u32>,
+        /// Start at the first dive within this long ago (e.g. 90m, 12h, 2d, 1w)
+        #[arg(long, value_parser = parse_duration)]
+        last: Option<u32>,
+    },
+    /// Broadcast an alert on the bus to test how handsets react to it
+    #[command(
+        long_about = "Sends an Alert frame for a known code once a second for --duration, from the node that normally raises it (Solo 0x04 for Solo and temperature alerts, handset 0x01 for handset alerts) unless --from is given. The alert is given by name (e.g. SoloSetpointTimeout, case-insensitive) or code (e.g. 0x103). Refuses to run while a dive is in progress or the unit is under water, and stops if one starts. Needs a can:// or socketcand:// transport."
+    )]
+    Raise {
+        #[arg(value_parser = parse_alert)]
+        alert: AnyAlert,
+        /// Source address (hex or decimal)
+        #[arg(long, value_parser = parse_hex_u8)]
+        from: Option<u8>,
+        /// How long to keep raising it (e.g. 30s, 5m)
+        #[arg(long, value_parser = parse_duration, default_value = "10s")]
+        duration: u32,
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+/// Longest a solenoid test pulse may hold the raised setpoint
+const MAX_SOLENOID_PULSE_MS: u64 = 5000;
+
+#[derive(Subcommand)]
+enum SolenoidAction {
+    /// Fire the solenoid and check its draw against the configured limits
+    #[command(
+        long_about = "Pre-dive bench check. Raises the setpoint on the bus so the Solo fires the solenoid, once per pulse for --duration ms, then restores the previous setpoint. SoloStatus current, voltage and alerts are captured and compared with min/max-current and min-voltage. Refuses to run while a dive is in progress or the unit is under water. Switch the handset off first, its own setpoint broadcasts override the test."
+    )]
+    Test {
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..=10))]
+        pulses: u32,
+        /// How long each pulse holds the raised setpoint, in ms (at most 5000)
+        #[arg(long, default_value_t = 2000, value_parser = clap::value_parser!(u64).range(100..=MAX_SOLENOID_PULSE_MS))]
+        duration: u64,
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
 }
 
 #[derive(Subcommand)]
 enum DeviceAction {
     /// Show serial number and physical device ID
-    Show,
+    Show {
+        /// Also read the MCU_DEVINFO region: the log digest and the bytes
+        /// not mapped yet
+        #[arg(long)]
+        verbose: bool,
+    },
     /// Read or set the device serial number (8 hex characters)
     #[command(
-        long_about = "With no value prints current serial. With a value like A005D007, writes new serial to the device."
+        long_about = "With no value prints current serial. With a value like A005D007, shows the current and the new serial and writes the new one once it is typed again, then reads it back to check it was stored. A wrong serial is hard to undo, so without a terminal the write needs --yes."
     )]
-    Serial { value: Option<String> },
+    Serial {
+        value: Option<String>,
+        /// Write without typing the new serial again
+        #[arg(long, requires = "value")]
+        yes: bool,
+    },
 }
 
 #[derive(Subcommand)]
 enum FwAction {
     /// Upload a firmware image to the device (if supported)
     #[command(
-        long_about = "Checks device capability and max size, then downloads using UDS DownloadSession with progress."
+        long_about = "Checks device capability and max size, then watches the bus for SoloStatus and alerts: the battery has to be 0.5 V above the configured minimum and steady, with no alerts active (--force skips this). Over gateway transports there is no bus to watch, so it asks whether the battery is charged instead. Then downloads using UDS DownloadSession with progress and compares the firmware CRC (DID 0x8209) the device reports with the CRC of the file."
     )]
-    Upload { firmware_file: PathBuf },
-    /// Show firmware version and CRC32
-    Info,
+    Upload {
+        firmware_file: PathBuf,
+        /// Program even if the battery or alert checks fail
+        #[arg(long)]
+        force: bool,
+        /// Largest battery voltage swing while watching that counts as
+        /// steady, in V
+        #[arg(long, default_value_t = 0.3)]
+        max_ripple: f32,
+    },
+    /// Show firmware version, CRC32 and optional bootloader/build date
+    #[command(
+        long_about = "Shows the firmware version, CRC32 and, if the device has them, the ISO 14229 boot software identification (0xF180) and programming date (0xF199). With --manifest the version is compared with the newest release in a local manifest, one `<version> [notes]` per line with # comments."
+    )]
+    Info {
+        /// Release manifest to check for updates
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -449,9 +967,15 @@ enum UserConfigAction {
     Get { name: String },
     /// Update a single editable user setting (integer/hex/scaled or selection by enum name)
     #[command(
-        long_about = "For integer/scaled settings, accepts decimal or 0x.... For selection settings, value must match an enum option exactly."
+        long_about = "For integer/scaled settings, accepts decimal or 0x.... For selection settings, value must match an enum option exactly. High-risk settings such as solenoid timing and battery or current limits ask for confirmation, or need --confirm when stdin is not a terminal; write-protected settings are refused."
     )]
-    Set { name: String, value: String },
+    Set {
+        name: String,
+        value: String,
+        /// Write high-risk settings without asking
+        #[arg(long)]
+        confirm: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -459,32 +983,55 @@ enum ConfigAction {
     /// Print the full control configuration in human-readable form
     List,
     /// Print a single configuration field
+    Get {
+        #[arg(value_parser = config_key_parser())]
+        key: ConfigField,
+    },
+    /// Update a configuration field (requires SOLO_KEY)
+    #[command(
+        long_about = "Updates config through the encrypted config write, which requires SOLO_KEY."
+    )]
+    Set {
+        #[arg(value_parser = config_key_parser())]
+        key: ConfigField,
+        value: String,
+        /// Write high-risk values without asking
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Check depth compensation against the bus and the device log (CAN only)
     #[command(
-        after_help = "Keys: cal, ppo2, cells, depth-comp, min-current, max-current, min-voltage, voltage-doubling"
+        long_about = "Compares the depth-comp flag in the control configuration with the flag in AmbientPressure broadcasts, checks that the broadcast surface pressure is stable and matches the latest AmbientPressure entry in the device log (needs SOLO_KEY, skipped otherwise), and prints the compensation factor the Solo applies (ambient / surface pressure with depth-comp on, 1.000 off)."
     )]
-    Get { key: ConfigKey },
-    /// Update a sconfiguration field (requires SOLO_KEY)
-    #[command(long_about = "Updates config (requires SOLO_KEY)")]
-    Set { key: ConfigKey, value: String },
+    VerifyDepthComp,
 }
 
 #[derive(Subcommand)]
 enum CalAction {
     /// Start O₂ cell calibration using FO2 and atmospheric pressure
     #[command(
-        long_about = "Valid FO2 range: 70–100%. Pressure range: 600–1050 mbar. Prints resulting calibration state."
+        long_about = "Valid FO2 range: 70–100%. Pressure range: 600–1050 mbar. Without --pressure, the AmbientPressure broadcast is averaged from the bus (CAN only). Reads the calibration values before, polls them after until they change and settle and prints each cell's change, then the resulting calibration state. Values that never change fail the command. With --tolerance, a cell moving more than that fails it too."
     )]
     O2 {
         #[arg(long)]
         fo2: u32,
+        /// Atmospheric pressure in mbar (autodetected from the bus if omitted)
         #[arg(long)]
-        pressure: u32,
+        pressure: Option<u32>,
+        /// Largest change of a cell's calibration value that passes
+        #[arg(long)]
+        tolerance: Option<u32>,
     },
     /// Initiate zero-offset calibration for O₂ cells
-    #[command(long_about = "Initiates calibration with expected ADC value")]
+    #[command(
+        long_about = "Initiates calibration with expected ADC value. Reads the zero offsets before, polls them after until they change and settle and prints each cell's change, failing if a cell moved more than --tolerance ADC counts."
+    )]
     Zero {
         #[arg(long)]
         adc_value: u32,
+        /// Largest change of a cell's zero offset that passes, in ADC counts
+        #[arg(long, default_value_t = 100)]
+        tolerance: u32,
     },
     /// Set voltage reference calibration value (range-checked)
     #[command(
@@ -496,6 +1043,30 @@ enum CalAction {
         #[command(subcommand)]
         item: CalShowAction,
     },
+    /// Guided cell linearity test against known FO₂ points (CAN only)
+    #[command(
+        long_about = "Bench test for current-limited or worn cells. Records the cell mV in air as the reference, then asks you to flush the loop with each --fo2 gas in turn and records the mV again, averaging CellVoltages broadcasts for --sample seconds each time. A linear cell reads the air mV scaled by the ppO₂ ratio; the deviation from that is checked against --max-deviation and the air reading against --air-mv-min/--air-mv-max. The unit's stored calibration is not changed."
+    )]
+    Linearity {
+        /// FO₂ points in percent, comma separated
+        #[arg(long, value_delimiter = ',', default_value = "100", value_parser = clap::value_parser!(u8).range(30..=100))]
+        fo2: Vec<u8>,
+        /// Atmospheric pressure in mbar (autodetected from the bus if omitted)
+        #[arg(long)]
+        pressure: Option<u32>,
+        /// Seconds to average each reading over
+        #[arg(long, default_value_t = 10)]
+        sample: u64,
+        /// Largest allowed deviation from a linear response, in percent
+        #[arg(long, default_value_t = 5.0)]
+        max_deviation: f32,
+        /// Lowest plausible cell output in air, in mV
+        #[arg(long, default_value_t = 8.0)]
+        air_mv_min: f32,
+        /// Highest plausible cell output in air, in mV
+        #[arg(long, default_value_t = 14.0)]
+        air_mv_max: f32,
+    },
 }
 
 #[derive(Subcommand)]
@@ -506,64 +1077,171 @@ enum CalShowAction {
     Zero,
 }
 
-fn get_solo_key() -> CmdResult<[u8; 8]> {
-    let key_str =
-        std::env::var("SOLO_KEY").map_err(|_| anyhow!("SOLO_KEY environment variable not set"))?;
+/// SOLO_KEY if set, otherwise the stored key matching the device
+fn get_solo_key(
+    cipher: CipherKind,
+    keys: &keys::KeyStore,
+    identity: Option<&DeviceIdentity>,
+) -> CmdResult<SoloKey> {
+    if let Ok(key_str) = std::env::var("SOLO_KEY") {
+        let bytes = keys::parse_key(cipher, &key_str).map_err(|e| anyhow!("SOLO_KEY: {}", e))?;
+        return SoloKey::new(cipher, &bytes);
+    }
+    let stored = identity.and_then(|id| {
+        keys.find(
+            &String::from_utf8_lossy(&id.serial.serial_ascii),
+            &id.device_id.to_string(),
+        )
+    });
+    match stored {
+        Some(stored) => {
+            log::info!("Using the key stored for {}", stored.pattern);
+            SoloKey::new(stored.cipher, &stored.key)
+        }
+        None if keys.is_empty() => Err(anyhow!("SOLO_KEY environment variable not set")),
+        None => Err(anyhow!(
+            "SOLO_KEY environment variable not set and no stored key matches the device"
+        )),
+    }
+}
 
-    let bytes =
-        hex::decode(key_str.trim()).map_err(|_| anyhow!("SOLO_KEY must be valid hex string"))?;
+fn bool_as_on_off(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
 
-    bytes
-        .try_into()
-        .map_err(|_| anyhow!("SOLO_KEY must be exactly 16 hex characters (8 bytes)"))
+fn parse_hex_u8(s: &str) -> Result<u8, String> {
+    if let Some(hex_str) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u8::from_str_radix(hex_str, 16).map_err(|_| format!("Invalid hex value: {}", s))
+    } else {
+        s.parse::<u8>()
+            .map_err(|_| format!("Invalid decimal value: {}", s))
+    }
 }
 
-fn ppo2_mode_as_str(mode: PPO2ControlMode) -> &'static str {
-    match mode {
-        PPO2ControlMode::UserSelect => "user",
-        PPO2ControlMode::Manual => "manual",
-        PPO2ControlMode::OneSec => "1sec",
-        PPO2ControlMode::FiveSec => "5sec",
+/// Alert name as in `candive::alerts`, or its code
+fn parse_hex_u16(s: &str) -> Result<u16, String> {
+    if let Some(hex_str) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u16::from_str_radix(hex_str, 16).map_err(|_| format!("Invalid hex value: {}", s))
+    } else {
+        s.parse::<u16>()
+            .map_err(|_| format!("Invalid decimal value: {}", s))
     }
 }
 
-fn calibration_procedure_as_str(proc: CalibrationProcedure) -> &'static str {
-    match proc {
-        CalibrationProcedure::Direct => "direct",
-        CalibrationProcedure::Monitored => "monitored",
+fn parse_alert(s: &str) -> Result<AnyAlert, String> {
+    if let Some(hex_str) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u16::from_str_radix(hex_str, 16)
+            .ok()
+            .and_then(AnyAlert::from_u16)
+            .ok_or_else(|| format!("Unknown alert code: {}", s));
     }
+    (0..=u16::MAX)
+        .filter_map(AnyAlert::from_u16)
+        .find(|a| alert_name(*a).eq_ignore_ascii_case(s))
+        .ok_or_else(|| format!("Unknown alert: {}", s))
 }
 
-fn cell_mode_as_str(mode: CellMode) -> &'static str {
-    match mode {
-        CellMode::TwoCell => "two",
-        CellMode::ThreeCell => "three",
+fn alert_name(alert: AnyAlert) -> String {
+    match alert {
+        AnyAlert::Handset(a) => format!("{:?}", a),
+        AnyAlert::Solo(a) => format!("{:?}", a),
+        AnyAlert::Temp(a) => format!("{:?}", a),
     }
 }
 
-fn bool_as_on_off(value: bool) -> &'static str {
-    if value { "on" } else { "off" }
+/// UTC date or date-time to Unix seconds
+fn parse_datetime(s: &str) -> Result<u32, String> {
+    let err = || format!("Invalid date '{}', use YYYY-MM-DD[ HH:MM[:SS]]", s);
+    let (date, time) = s.split_once([' ', 'T']).unwrap_or((s, "00:00"));
+
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|p| p.parse().map_err(|_| err()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|p| p.parse().map_err(|_| err()))
+        .collect::<Result<_, _>>()?;
+
+    let [year, month, day]: [i64; 3] = date.as_slice().try_into().map_err(|_| err())?;
+    let [hour, min, sec] = match time.as_slice() {
+        [h, m] => [*h, *m, 0],
+        [h, m, s] => [*h, *m, *s],
+        _ => return Err(err()),
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&min)
+        || !(0..60).contains(&sec)
+    {
+        return Err(err());
+    }
+
+    // Civil date to days, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    u32::try_from(days * 86_400 + hour * 3600 + min * 60 + sec).map_err(|_| err())
 }
 
-fn parse_u16(value: &str) -> CmdResult<u16> {
+/// Duration like 90s, 30m, 12h, 2d or 1w to seconds
+fn parse_duration(s: &str) -> Result<u32, String> {
+    let err = || format!("Invalid duration '{}', use e.g. 90m, 12h, 2d, 1w", s);
+    let split = s.len().checked_sub(1).ok_or_else(err)?;
+    let (value, unit) = s.split_at(split);
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return Err(err()),
+    };
     value
-        .parse::<u16>()
-        .map_err(|_| anyhow!("Invalid value '{}'. Must be a number.", value))
+        .parse::<u32>()
+        .ok()
+        .and_then(|v| v.checked_mul(unit_secs))
+        .ok_or_else(err)
 }
 
-fn parse_hex_u8(s: &str) -> Result<u8, String> {
-    if let Some(hex_str) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
-        u8::from_str_radix(hex_str, 16).map_err(|_| format!("Invalid hex value: {}", s))
-    } else {
-        s.parse::<u8>()
-            .map_err(|_| format!("Invalid decimal value: {}", s))
-    }
+/// Byte count like 4096, 64K, 4M or 1G
+fn parse_size(s: &str) -> Result<usize, String> {
+    const MIN: usize = 1024;
+    let err = || format!("Invalid size '{}', use e.g. 64K, 4M (at least 1K)", s);
+    let (value, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => s.split_at(i),
+        _ => (s, ""),
+    };
+    let unit_bytes = match unit.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(err()),
+    };
+    value
+        .parse::<usize>()
+        .ok()
+        .and_then(|v| v.checked_mul(unit_bytes))
+        .filter(|&bytes| bytes >= MIN)
+        .ok_or_else(err)
 }
 
-// Parses a ValueEnum from a string *without* requiring it in the CLI signature.
-// (Keeps your ConfigAction::Set value as String while still using ValueEnum.)
-fn parse_value_enum<T: ValueEnum>(s: &str) -> CmdResult<T> {
-    T::from_str(s, true).map_err(|_| anyhow!("Invalid value '{}'", s))
+/// How the log is split into uploads when streaming it, `--chunk-entries`
+/// or sized from the throughput. The encrypted and decrypted copies of a
+/// chunk are in memory at once.
+fn log_chunking(chunk_entries: Option<u32>, max_memory: usize) -> LogChunking {
+    LogChunking {
+        size: chunk_entries.map_or(ChunkSize::Auto, ChunkSize::Fixed),
+        max_entries: (max_memory / (2 * LOG_ENTRY_SIZE as usize)).clamp(1, u32::MAX as usize)
+            as u32,
+    }
 }
 
 fn new_progress_bar(size: u64) -> ProgressBar {
@@ -577,18 +1255,27 @@ fn new_progress_bar(size: u64) -> ProgressBar {
     pb
 }
 
-fn cmd_logs_info() -> CmdResult {
-    let log_region = &solo::regions::MMC_LOG;
-    let total_size = log_region.addr_range.end() - log_region.addr_range.start();
-    let entry_count = total_size / LOG_ENTRY_SIZE;
-
-    println!("Logs");
-    println!("  Entry size:  {} bytes", LOG_ENTRY_SIZE);
-    println!("  Entry count: {}", entry_count);
-    println!("  Total size:  {} bytes", total_size);
-    Ok(())
+fn cmd_logs_info(transport: &mut impl UdsTransport, out: &mut impl Write) -> CmdResult {
+    let logs = transport.logs_info()?;
+
+    writeln!(out, "Logs")?;
+    writeln!(out, "  Address:     0x{:08X}", logs.address)?;
+    writeln!(out, "  Entry size:  {} bytes", logs.entry_size)?;
+    writeln!(out, "  Entry count: {}", logs.entry_count())?;
+    writeln!(out, "  Total size:  {} bytes", logs.size)?;
+    writeln!(
+        out,
+        "  Source:      {}",
+        if logs.from_device {
+            "device"
+        } else {
+            "built-in Solo layout (device doesn't report one)"
+        }
+    )?;
+    Ok(())
 }
 
+/// Digest of the last log upload. Needed after every upload, see `LogTransferDigest`.
 fn logs_get_digest(transport: &mut impl UdsTransport) -> CmdResult<LogTransferDigest> {
     let mut device_data = Vec::new();
     let start = *solo::regions::MCU_DEVINFO.addr_range.start();
@@ -596,20 +1283,75 @@ fn logs_get_digest(transport: &mut impl UdsTransport) -> CmdResult<LogTransferDi
     Ok(LogTransferDigest::try_from(device_data.as_slice()).map_err(|e| anyhow!("{:?}", e))?)
 }
 
+/// Where a raw export's digest is kept
+fn digest_path(log: &Path) -> PathBuf {
+    let mut path = log.as_os_str().to_owned();
+    path.push(".digest.json");
+    PathBuf::from(path)
+}
+
+fn read_log_digest(path: &Path) -> CmdResult<LogTransferDigest> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let bad = || anyhow!("{} is not a log digest", path.display());
+    let fields = jsonl::parse_object(text.trim()).ok_or_else(bad)?;
+    let get = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .ok_or_else(bad)
+    };
+    if get("type")? != "log_digest" {
+        return Err(bad());
+    }
+    Ok(LogTransferDigest {
+        log_crc32: u32::from_str_radix(get("crc32")?, 16).map_err(|_| bad())?,
+        length: get("length")?.parse().map_err(|_| bad())?,
+        transfer_start_timestamp: get("transfer_start_timestamp")?
+            .parse()
+            .map_err(|_| bad())?,
+        physical_device_id: hex::decode(get("device_id")?)
+            .ok()
+            .and_then(|id| id.try_into().ok())
+            .ok_or_else(bad)?,
+    })
+}
+
 fn cmd_logs_export(
     transport: &mut impl UdsTransport,
     filename: PathBuf,
     count: Option<u32>,
     skip: Option<u32>,
-    des_key: Option<[u8; 8]>,
+    since: Option<u32>,
+    split_per_dive: bool,
+    solo_key: Option<&SoloKey>,
 ) -> CmdResult {
-    let entry_count = count.unwrap_or(100);
-    let skip_count = skip.unwrap_or(0);
+    if split_per_dive && solo_key.is_none() {
+        return Err(anyhow!(
+            "--split-per-dive needs decrypted logs, set SOLO_KEY"
+        ));
+    }
 
-    let log_size = entry_count * LOG_ENTRY_SIZE;
-    let skip_bytes = skip_count * LOG_ENTRY_SIZE;
+    let logs = transport.logs_info()?;
+    if let Some(solo_key) = solo_key {
+        check_solo_key(transport, &logs, solo_key)?;
+    }
+    let (entry_count, skip_count) = match since {
+        Some(since) => {
+            let solo_key = solo_key
+                .ok_or_else(|| anyhow!("--since/--last need decrypted logs, set SOLO_KEY"))?;
+            let (skip, available) = log_window_since(transport, &logs, solo_key, since)?;
+            (count.unwrap_or(available).min(available), skip)
+        }
+        None => (count.unwrap_or(100), skip.unwrap_or(0)),
+    };
+    if entry_count == 0 {
+        return Err(anyhow!("No log entries in the requested window"));
+    }
 
-    let start = *solo::regions::MMC_LOG.addr_range.start() + skip_bytes;
+    let log_size = logs.entries_len(entry_count);
+    let start = logs.entry_address(skip_count);
 
     let tmp_filename = filename.with_extension("tmp");
 
@@ -642,70 +1384,328 @@ fn cmd_logs_export(
 
     tmpf.seek(std::io::SeekFrom::Start(0))?;
 
-    if stm32_crc32_read(&mut tmpf)? != digest.log_crc32 {
+    if !ct_eq_u32(stm32_crc32_read(&mut tmpf)?, digest.log_crc32) {
         return Err(anyhow!("CRC32 mismatch"));
     }
 
     tmpf.seek(std::io::SeekFrom::Start(0))?;
 
-    if let Some(des_key) = des_key {
-        let des = Encryptor::new(des_key);
-        let mut session = LogDecryptor::new(
-            &des,
-            &digest.physical_device_id,
-            digest.transfer_start_timestamp,
-        );
-        let mut f = File::create(&filename)?;
-        decrypt(&mut session, &mut tmpf, &mut f)?;
+    let mut dive_files = Vec::new();
+    if let Some(solo_key) = solo_key {
+        let mut session =
+            solo_key.log_decryptor(&digest.physical_device_id, digest.transfer_start_timestamp);
+        if split_per_dive {
+            let mut files = DiveFiles::new(&filename);
+            decrypt(&mut session, &mut tmpf, &mut files)?;
+            dive_files = files.finish()?;
+        } else {
+            let mut f = File::create(&filename)?;
+            decrypt(&mut session, &mut tmpf, &mut f)?;
+        }
         drop(tmpf);
         std::fs::remove_file(tmp_filename)?;
     } else {
         std::fs::rename(&tmp_filename, &filename)?;
+        std::fs::write(
+            digest_path(&filename),
+            jsonl::log_digest(&iso8601_ms(unix_time_ms()), &digest) + "\n",
+        )?;
     }
 
     println!("Log export");
-    println!("  Output:  {}", filename.display());
+    if split_per_dive {
+        println!("  Dives:   {}", dive_files.len());
+        for f in &dive_files {
+            println!("    {}", f.display());
+        }
+    } else {
+        println!("  Output:  {}", filename.display());
+    }
+    if solo_key.is_none() {
+        println!("  Digest:  {}", digest_path(&filename).display());
+    }
     println!("  Entries: {}", entry_count);
     println!("  Skipped: {}", skip_count);
     println!("  Size:    {} bytes", log_size);
     println!(
         "  Decrypt: {}",
-        if des_key.is_some() { "OK" } else { "skipped" }
+        if solo_key.is_some() { "OK" } else { "skipped" }
     );
     Ok(())
 }
 
-fn dump_log_chunk(
+/// Start of a `--since`/`--last` window in Unix seconds
+fn window_start(since: Option<u32>, last: Option<u32>) -> Option<u32> {
+    since.or_else(|| last.map(|secs| ((unix_time_ms() / 1000) as u32).saturating_sub(secs)))
+}
+
+/// Entries downloaded per probe when searching the log by time
+const LOG_PROBE_ENTRIES: u32 = 50;
+
+enum LogProbe {
+    /// Nothing written here yet
+    Empty,
+    /// Entries but no Diving entry to date them
+    Untimed,
+    At(u32),
+}
+
+fn probe_log(
     transport: &mut impl UdsTransport,
-    count: u32,
+    logs: &LogsInfo,
     skip: u32,
-    des_key: [u8; 8],
-) -> CmdResult<Vec<u8>> {
-    let log_size = count * LOG_ENTRY_SIZE;
-    let skip_bytes = skip * LOG_ENTRY_SIZE;
+    solo_key: &SoloKey,
+) -> CmdResult<LogProbe> {
+    let count = LOG_PROBE_ENTRIES.min(logs.entry_count() - skip);
+    let data = dump_log_chunk(transport, logs, count, skip, solo_key)?;
+    let mut entries = LogEntryIterator::new(&data).peekable();
+    if entries.peek().is_none() {
+        return Ok(LogProbe::Empty);
+    }
+    Ok(entries
+        .find_map(|e| e.timestamp())
+        .map_or(LogProbe::Untimed, LogProbe::At))
+}
 
-    let start = *solo::regions::MMC_LOG.addr_range.start() + skip_bytes;
-    let mut encrypted: Vec<u8> = Vec::new();
-    transport.upload(
-        start,
-        log_size as usize,
-        Dlf::Normal,
-        &mut encrypted,
-        |_, _| {},
-    )?;
+/// First entry index in `lo..hi` whose probe satisfies `past`, assuming it
+/// is false then true across the range
+fn bisect_log(
+    transport: &mut impl UdsTransport,
+    logs: &LogsInfo,
+    solo_key: &SoloKey,
+    mut lo: u32,
+    mut hi: u32,
+    past: impl Fn(&LogProbe) -> bool,
+) -> CmdResult<u32> {
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if past(&probe_log(transport, logs, mid, solo_key)?) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok(lo)
+}
 
-    let digest = logs_get_digest(transport)?;
+/// Finds (skip, count) covering the log from the first dive at or after
+/// `since` to the end of the written entries. Stretches without a Diving
+/// entry count as later, so the window may start a little early.
+fn log_window_since(
+    transport: &mut impl UdsTransport,
+    logs: &LogsInfo,
+    solo_key: &SoloKey,
+    since: u32,
+) -> CmdResult<(u32, u32)> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_message("Searching log by time");
+
+    let end = bisect_log(transport, logs, solo_key, 0, logs.entry_count(), |p| {
+        matches!(p, LogProbe::Empty)
+    })?;
+    let start = bisect_log(transport, logs, solo_key, 0, end, |p| match p {
+        LogProbe::At(ts) => *ts >= since,
+        LogProbe::Empty | LogProbe::Untimed => true,
+    })?;
 
-    if Stm32Crc32::stm32_crc32(&encrypted) != digest.log_crc32 {
-        return Err(anyhow!("CRC32 mismatch"));
+    pb.finish_and_clear();
+    Ok((start, end - start))
+}
+
+/// Takes decrypted log data as it is decrypted and writes each dive to its
+/// own file next to the export's filename.
+struct DiveFiles<'a> {
+    stream: LogStream,
+    splitter: DiveSplitter,
+    output: DiveOutput<'a>,
+}
+
+/// Files [`DiveFiles`] writes, `<name>-dive<N>-<date>.<ext>`
+struct DiveOutput<'a> {
+    filename: &'a Path,
+    current: Option<std::io::BufWriter<File>>,
+    written: Vec<PathBuf>,
+}
+
+impl<'a> DiveFiles<'a> {
+    fn new(filename: &'a Path) -> Self {
+        Self {
+            stream: LogStream::new(),
+            splitter: DiveSplitter::new(),
+            output: DiveOutput {
+                filename,
+                current: None,
+                written: Vec::new(),
+            },
+        }
     }
 
-    let des = Encryptor::new(des_key);
-    let mut session = LogDecryptor::new(
-        &des,
-        &digest.physical_device_id,
-        digest.transfer_start_timestamp,
-    );
+    /// Closes the last dive, returns the files written
+    fn finish(mut self) -> CmdResult<Vec<PathBuf>> {
+        let output = &mut self.output;
+        self.splitter.finish(|event| output.on_event(event))?;
+        Ok(self.output.written)
+    }
+}
+
+impl DiveOutput<'_> {
+    fn path(&self, dive_number: u16, timestamp: u32) -> PathBuf {
+        let stem = self
+            .filename
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "log".into());
+        let ext = self
+            .filename
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        self.filename.with_file_name(format!(
+            "{}-dive{:04}-{}{}",
+            stem,
+            dive_number,
+            utc_date(timestamp),
+            ext
+        ))
+    }
+
+    fn on_event(&mut self, event: DiveEvent<'_>) -> std::io::Result<()> {
+        match event {
+            DiveEvent::Start {
+                dive_number,
+                timestamp,
+            } => {
+                let path = self.path(dive_number, timestamp);
+                self.current = Some(std::io::BufWriter::new(File::create(&path)?));
+                self.written.push(path);
+            }
+            DiveEvent::Slot(bytes) => {
+                if let Some(file) = self.current.as_mut() {
+                    file.write_all(bytes)?;
+                }
+            }
+            DiveEvent::End => {
+                if let Some(mut file) = self.current.take() {
+                    file.flush()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for DiveFiles<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let Self {
+            stream,
+            splitter,
+            output,
+        } = self;
+        stream.push(buf, |slot| {
+            splitter.on_slot(slot, |event| output.on_event(event))
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.output.current.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// YYYYMMDD for a Unix timestamp in seconds
+fn utc_date(secs: u32) -> String {
+    let (year, month, day) = civil_date(secs as u64 / 86_400);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+/// YYYY-MM-DD HH:MM:SS UTC for a Unix timestamp in seconds
+fn utc_datetime(secs: u32) -> String {
+    let (year, month, day) = civil_date(secs as u64 / 86_400);
+    let tod = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        tod / 3600,
+        tod / 60 % 60,
+        tod % 60
+    )
+}
+
+/// ISO-8601 UTC with milliseconds for a Unix timestamp in milliseconds
+fn iso8601_ms(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (year, month, day) = civil_date(secs / 86_400);
+    let tod = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        tod / 3600,
+        tod / 60 % 60,
+        tod % 60,
+        ms % 1000
+    )
+}
+
+/// (year, month, day) for days since the Unix epoch
+fn civil_date(days: u64) -> (i64, i64, i64) {
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Log entries decrypted to check SOLO_KEY before a long operation
+const KEY_CHECK_ENTRIES: u32 = 16;
+
+/// Fails fast when `solo_key` doesn't decrypt this device's log. A wrong key
+/// otherwise only shows as garbage after the whole download, or as a config
+/// write the device can't decrypt.
+fn check_solo_key(
+    transport: &mut impl UdsTransport,
+    logs: &LogsInfo,
+    solo_key: &SoloKey,
+) -> CmdResult {
+    if !solo_key.checked() {
+        return Ok(());
+    }
+    let count = KEY_CHECK_ENTRIES.min(logs.entry_count());
+    let data = dump_log_chunk(transport, logs, count, 0, solo_key)?;
+    if !plausible_log(&data) {
+        return Err(anyhow!(
+            "SOLO_KEY appears incorrect, the first {} log entries don't decrypt. Check SOLO_KEY and --cipher, or pass --skip-key-check",
+            count
+        ));
+    }
+    Ok(())
+}
+
+/// Times a log chunk is fetched before a CRC mismatch fails the command
+const LOG_CHUNK_ATTEMPTS: u32 = 3;
+
+fn dump_log_chunk(
+    transport: &mut impl UdsTransport,
+    logs: &LogsInfo,
+    count: u32,
+    skip: u32,
+    solo_key: &SoloKey,
+) -> CmdResult<Vec<u8>> {
+    let (encrypted, digest) = fetch_log_chunk(transport, logs, count, skip)?;
+    let mut session =
+        solo_key.log_decryptor(&digest.physical_device_id, digest.transfer_start_timestamp);
     let mut decrypted: Vec<u8> = Vec::new();
     decrypt(
         &mut session,
@@ -715,20 +1715,86 @@ fn dump_log_chunk(
     Ok(decrypted)
 }
 
-fn cmd_logs_dump(
+/// Uploads `count` entries after `skip`, still encrypted, retrying until
+/// the CRC matches the digest the device reports for the transfer
+fn fetch_log_chunk(
     transport: &mut impl UdsTransport,
+    logs: &LogsInfo,
+    count: u32,
+    skip: u32,
+) -> CmdResult<(Vec<u8>, LogTransferDigest)> {
+    let log_size = logs.entries_len(count);
+    let start = logs.entry_address(skip);
+    let mut attempt = 1;
+    let (encrypted, digest) = loop {
+        let mut encrypted: Vec<u8> = Vec::new();
+        transport.upload(
+            start,
+            log_size as usize,
+            Dlf::Normal,
+            &mut encrypted,
+            |_, _| {},
+        )?;
+
+        // The digest covers the last transfer, fetch it again for every attempt
+        let digest = logs_get_digest(transport)?;
+
+        if ct_eq_u32(Stm32Crc32::stm32_crc32(&encrypted), digest.log_crc32) {
+            break (encrypted, digest);
+        }
+        if attempt == LOG_CHUNK_ATTEMPTS {
+            return Err(anyhow!(
+                "CRC32 mismatch for entries {}..{} after {} attempts",
+                skip,
+                skip + count,
+                attempt
+            ));
+        }
+        log::warn!(
+            "CRC32 mismatch for entries {}..{}, retrying ({}/{})",
+            skip,
+            skip + count,
+            attempt,
+            LOG_CHUNK_ATTEMPTS
+        );
+        attempt += 1;
+    };
+    Ok((encrypted, digest))
+}
+
+/// How `logs dump` prints entries
+enum DumpFormat {
+    /// The legacy candump-style lines
+    Candump,
+    Pretty(UnitsPreference),
+}
+
+fn cmd_logs_dump(
+    link: &mut Link,
     count: Option<u32>,
     skip: Option<u32>,
-    candump: bool,
-    des_key: [u8; 8],
+    since: Option<u32>,
+    format: &DumpFormat,
+    chunking: &LogChunking,
+    solo_key: Option<&SoloKey>,
 ) -> CmdResult {
-    const CHUNK_SIZE: u32 = 100;
-
-    let skip_count = skip.unwrap_or(0);
+    let logs = link.session.logs_info()?;
+    if let Some(solo_key) = solo_key {
+        check_solo_key(link.session, &logs, solo_key)?;
+    }
+    let (skip_count, max_entries) = match since {
+        Some(since) => {
+            let solo_key = solo_key
+                .ok_or_else(|| anyhow!("--since/--last need decrypted logs, set SOLO_KEY"))?;
+            let (skip, available) = log_window_since(link.session, &logs, solo_key, since)?;
+            (skip, skip + available)
+        }
+        None => (skip.unwrap_or(0), logs.entry_count()),
+    };
 
-    let log_region = &solo::regions::MMC_LOG;
-    let total_size = log_region.addr_range.end() - log_region.addr_range.start();
-    let max_entries = total_size / LOG_ENTRY_SIZE;
+    if since.is_some() && skip_count >= max_entries {
+        return Err(anyhow!("No log entries in the requested window"));
+    }
 
     // Avoid underflow when skip is large
     if skip_count >= max_entries {
@@ -739,47 +1805,377 @@ fn cmd_logs_dump(
         ));
     }
 
-    let total_entries = count.unwrap_or_else(|| max_entries - skip_count);
-
-    let num_chunks = (total_entries + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let mut total_entries = count.unwrap_or(max_entries - skip_count);
+    if since.is_some() {
+        total_entries = total_entries.min(max_entries - skip_count);
+    }
 
-    for i in 0..num_chunks {
-        let current_skip = skip_count + i * CHUNK_SIZE;
-        let remaining = total_entries - i * CHUNK_SIZE;
-        let chunk_count = std::cmp::min(CHUNK_SIZE, remaining);
-        let data = dump_log_chunk(transport, chunk_count, current_skip, des_key)?;
+    if solo_key.is_none() {
+        log::warn!("SOLO_KEY not set, printing entries still encrypted (CRC verified)");
+    }
 
-        for entry in LogEntryIterator::new(&data) {
-            let can_id = 0x0D000000u32 | ((entry.kind as u32) << 16) | 0x0004;
-            let dlc = Msg::dlc_min_size(entry.kind).unwrap_or(8);
+    // Kinds carry over from one chunk to the next
+    let mut stream = LogStream::new();
+    let mut chunks = chunking.chunks(skip_count, total_entries);
+    while let Some(chunk) = chunks.next_chunk() {
+        let (first, chunk_count) = (chunk.start, chunk.len() as u32);
+        let started = std::time::Instant::now();
+        let Some(solo_key) = solo_key else {
+            // The cipher runs over the whole transfer, entries can't be decoded one by one
+            let (encrypted, _) =
+                link.retry(|session| fetch_log_chunk(session, &logs, chunk_count, first))?;
+            chunks.record(chunk_count, started.elapsed().as_millis() as u64);
+            for (i, entry) in encrypted.chunks(logs.entry_size as usize).enumerate() {
+                println!(
+                    "{:>7}  {}  (encrypted)",
+                    first as usize + i,
+                    hex::encode_upper(entry)
+                );
+            }
+            continue;
+        };
+        let data =
+            link.retry(|session| dump_log_chunk(session, &logs, chunk_count, first, solo_key))?;
+        chunks.record(chunk_count, started.elapsed().as_millis() as u64);
 
-            if candump {
-                // candump format (old default)
-                let payload_str = entry.payload[..dlc as usize]
-                    .iter()
-                    .map(|b| format!("{:02X}", b))
-                    .collect::<Vec<_>>()
-                    .join(" ");
-                println!("  can0  {:08X}   [{}]  {}", can_id, dlc, payload_str);
-            } else {
-                // pretty format (new default)
-                if let Ok(frame) = DiveCanFrame::new(entry.kind, dlc, entry.payload) {
+        stream.push(&data, |slot| {
+            let Some(entry) = slot.entry else {
+                return Ok(());
+            };
+            let (id, frame) = entry.to_frame(&LogProfile::SOLO);
+
+            match format {
+                DumpFormat::Candump => {
+                    // candump format (old default)
+                    let payload_str = frame
+                        .bytes()
+                        .iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    println!(
+                        "  can0  {:08X}   [{}]  {}",
+                        id.to_u32(),
+                        frame.dlc(),
+                        payload_str
+                    );
+                }
+                DumpFormat::Pretty(units) => {
                     if let Ok(msg) = Msg::try_from_frame(&frame) {
-                        let id: DiveCanId = can_id.into();
+                        // pretty format (new default)
                         println!(
                             "{:02x} -> {:02x}: {}",
                             id.src,
                             id.dst,
-                            msgformat::pretty(&msg)
+                            msgformat::pretty(&msg, *units)
                         );
                     }
                 }
             }
+            CmdResult::Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+fn cmd_alerts_list(
+    transport: &mut impl UdsTransport,
+    since: Option<u32>,
+    chunking: &LogChunking,
+    solo_key: &SoloKey,
+) -> CmdResult {
+    let logs = transport.logs_info()?;
+    check_solo_key(transport, &logs, solo_key)?;
+    let (skip, count) = match since {
+        Some(since) => log_window_since(transport, &logs, solo_key, since)?,
+        None => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_message("Finding the end of the log");
+            let end = bisect_log(transport, &logs, solo_key, 0, logs.entry_count(), |p| {
+                matches!(p, LogProbe::Empty)
+            })?;
+            pb.finish_and_clear();
+            (0, end)
+        }
+    };
+    if count == 0 {
+        println!("Log is empty");
+        return Ok(());
+    }
+
+    let pb = ProgressBar::new(count as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{bar:40.cyan/blue}] {pos}/{len} entries ({eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb.set_message("Reading log");
+    let print = |stored: StoredAlert| {
+        let context = match stored.last_diving {
+            Some((dive, ts)) => format!("after dive {} {}", dive, utc_datetime(ts)),
+            None => "before the first dive in the log".to_string(),
+        };
+        let repeats = match stored.repeats {
+            1 => String::new(),
+            n => format!(" (x{})", n),
+        };
+        pb.suspend(|| {
+            println!(
+                "0x{:04X}  {}{}, {}",
+                stored.alert.code,
+                msgformat::alert_label(stored.alert.code),
+                repeats,
+                context
+            )
+        });
+    };
+
+    // Streamed chunk by chunk, kinds and folded repeats carry over
+    let mut stream = LogStream::new();
+    let mut scanner = AlertScanner::new();
+    let mut found = 0;
+    let mut chunks = chunking.chunks(skip, count);
+    while let Some(chunk) = chunks.next_chunk() {
+        let started = std::time::Instant::now();
+        let data = dump_log_chunk(transport, &logs, chunk.len() as u32, chunk.start, solo_key)?;
+        chunks.record(chunk.len() as u32, started.elapsed().as_millis() as u64);
+        stream.push(&data, |slot| {
+            if let Some(stored) = slot.entry.and_then(|e| scanner.on_entry(&e)) {
+                found += 1;
+                print(stored);
+            }
+            CmdResult::Ok(())
+        })?;
+        pb.set_position((chunk.end - skip) as u64);
+    }
+    if let Some(stored) = scanner.finish() {
+        found += 1;
+        print(stored);
+    }
+    pb.finish_and_clear();
+
+    if found == 0 {
+        println!("No alerts in {} log entries", count);
+    }
+    Ok(())
+}
+
+fn cmd_alerts_raise(
+    transport_uri: &str,
+    alert: AnyAlert,
+    from: Option<u8>,
+    duration_secs: u32,
+    yes: bool,
+    tx_dlc: TxDlcPolicy,
+) -> CmdResult {
+    use std::time::{Duration, Instant};
+
+    const REPEAT: Duration = Duration::from_secs(1);
+
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Err(anyhow!(
+            "Raising alerts needs a can:// or socketcand:// transport"
+        ));
+    };
+    let src = from.unwrap_or(match alert {
+        AnyAlert::Handset(_) => HANDSET_ADDR,
+        AnyAlert::Solo(_) | AnyAlert::Temp(_) => SOLO_ADDR,
+    });
+
+    hostcheck::transport(transport_uri)?;
+    let socket = transport::RawBus::open(transport_uri, Duration::from_millis(20))
+        .map_err(|e| anyhow!("Failed to open {}: {}", interface, e))?
+        .with_tx_dlc(tx_dlc);
+
+    log::info!("Checking bus state on {}...", interface);
+    let mut state = BusState::default();
+    watch_bus(&socket, Duration::from_secs(2), &mut state, |_, _| {})?;
+    state.check_interlock()?;
+
+    if !yes
+        && !confirm(&format!(
+            "Broadcast {} ({}) as node 0x{:02X} for {}s? Handsets on the bus will show it",
+            alert_name(alert),
+            msgformat::alert_label(alert.to_u16()),
+            src,
+            duration_secs
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    let msg = Msg::alert_from(alert);
+    let frame = msg.to_frame();
+    log::info!(
+        "Raising 0x{:04X} {} ({}) from 0x{:02X} for {}s",
+        alert.to_u16(),
+        alert_name(alert),
+        msgformat::alert_label(alert.to_u16()),
+        src,
+        duration_secs
+    );
+
+    let window = Duration::from_secs(duration_secs as u64);
+    let start = Instant::now();
+    let mut sent = 0u32;
+    while start.elapsed() < window {
+        socket
+            .send(DiveCanId::new(src, 0xFF, msg.kind()), &frame)
+            .map_err(|e| anyhow!("CAN write failed: {}", e))?;
+        sent += 1;
+        let gap = REPEAT.min(window.saturating_sub(start.elapsed()));
+        watch_bus(&socket, gap, &mut state, |_, _| {})?;
+        state.check_interlock()?;
+    }
+    println!("Sent {} alert frames", sent);
+    Ok(())
+}
+
+fn cmd_logs_anonymize(input: &Path, output: &Path, max_memory: usize) -> CmdResult {
+    let mut reader = File::open(input)?;
+    let mut head = [0u8; 256];
+    let head_len = reader.read(&mut head)?;
+    reader.rewind()?;
+    if capture::is_candump_log(&head[..head_len]) {
+        let (changed, frames) = capture::anonymize_candump(
+            std::io::BufReader::new(reader),
+            std::io::BufWriter::new(File::create(output)?),
+            &mut candive::diag::anonymize::Anonymizer::new(),
+        )?;
+        println!(
+            "Redacted {} of {} frames, written to {}",
+            changed,
+            frames,
+            output.display()
+        );
+        return Ok(());
+    }
+
+    let slot = LOG_ENTRY_SIZE as usize;
+    let len = reader.metadata()?.len();
+    if len == 0 || !len.is_multiple_of(slot as u64) {
+        return Err(anyhow!(
+            "{} is not an exported log ({} bytes is not a multiple of {})",
+            input.display(),
+            len,
+            LOG_ENTRY_SIZE
+        ));
+    }
+
+    // Whole slots at a time, the anonymizer carries the kind between them
+    let mut buf = vec![0u8; (max_memory / slot).max(1) * slot];
+    let mut writer = std::io::BufWriter::new(File::create(output)?);
+    let mut anon = candive::diag::anonymize::Anonymizer::new();
+    // Counted across pieces, an entry's kind can be in the piece before
+    let mut stream = LogStream::new();
+    let (mut entries, mut changed) = (0, 0);
+    loop {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
         }
+        if filled == 0 {
+            break;
+        }
+        let piece = &mut buf[..filled];
+        stream.push(piece, |slot| {
+            entries += usize::from(slot.entry.is_some());
+            Ok::<_, std::convert::Infallible>(())
+        })?;
+        changed += anon.anonymize_log(piece);
+        writer.write_all(piece)?;
     }
+    writer.flush()?;
+
+    println!(
+        "Redacted {} of {} entries, written to {}",
+        changed,
+        entries,
+        output.display()
+    );
     Ok(())
 }
 
+fn log_problem_as_str(problem: LogProblem) -> String {
+    match problem {
+        LogProblem::Empty => "no whole entry".to_string(),
+        LogProblem::Misaligned { trailing } => {
+            format!("{} bytes after the last whole entry", trailing)
+        }
+        LogProblem::MissingKinds { entries } => {
+            format!("{} entries without a kind before them", entries)
+        }
+        LogProblem::Undecodable { decodable, entries } => format!(
+            "only {} of {} entries decode, still encrypted or decrypted with the wrong key?",
+            decodable, entries
+        ),
+    }
+}
+
+fn cmd_logs_verify(file: &Path, digest: Option<PathBuf>, max_memory: usize) -> CmdResult {
+    let digest = digest.or_else(|| Some(digest_path(file)).filter(|p| p.exists()));
+    let mut reader = File::open(file)?;
+    let len = reader.metadata()?.len();
+
+    println!("Log verify");
+    println!("  File:    {}", file.display());
+    println!("  Size:    {} bytes", len);
+    let mut problems = Vec::new();
+    if let Some(digest_file) = digest {
+        let digest = read_log_digest(&digest_file)?;
+        println!("  Digest:  {}", digest_file.display());
+        if digest.length != LogTransferDigest::LENGTH {
+            problems.push(format!(
+                "digest length field {}, expected {}",
+                digest.length,
+                LogTransferDigest::LENGTH
+            ));
+        }
+        if !len.is_multiple_of(LOG_ENTRY_SIZE as u64) {
+            problems.push(log_problem_as_str(LogProblem::Misaligned {
+                trailing: (len % LOG_ENTRY_SIZE as u64) as usize,
+            }));
+        }
+        let crc = stm32_crc32_read(&mut reader)?;
+        if !ct_eq_u32(crc, digest.log_crc32) {
+            problems.push(format!(
+                "CRC32 {:08X}, the digest has {:08X}",
+                crc, digest.log_crc32
+            ));
+        }
+    } else {
+        let mut buf = vec![0u8; max_memory.max(1)];
+        let mut check = LogCheck::new();
+        loop {
+            match reader.read(&mut buf)? {
+                0 => break,
+                n => check.push(&buf[..n]),
+            }
+        }
+        let report = check.finish();
+        println!(
+            "  Entries: {} ({} blank slots)",
+            report.entries, report.blank
+        );
+        println!("  Decoded: {}", report.decodable);
+        report.problems(|p| problems.push(log_problem_as_str(p)));
+    }
+
+    if problems.is_empty() {
+        println!("  Result:  OK");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("  Problem: {}", problem);
+    }
+    Err(anyhow!("{} failed verification", file.display()))
+}
+
 fn cmd_mem_dump(transport: &mut impl UdsTransport, filename: PathBuf) -> CmdResult {
     let mut f2 = File::create(&filename)?;
     let size = 0x1000 - 0x80;
@@ -812,12 +2208,12 @@ fn cmd_mem_dump(transport: &mut impl UdsTransport, filename: PathBuf) -> CmdResu
     Ok(())
 }
 
-fn read_user_setting_payload(
+fn read_user_setting<D: SettingDid>(
     transport: &mut impl UdsTransport,
-    did: UserSettingDid,
-) -> CmdResult<UserSettingPayload> {
+    did: D,
+) -> CmdResult<D::Payload> {
     let response = transport.rdbi(did.to_did())?;
-    UserSettingPayload::decode(did, &response).map_err(|e| anyhow!("{:?}", e))
+    D::decode(&response).map_err(|e| anyhow!("{:?}", e))
 }
 
 fn cstr_bytes_to_string(bytes: &[u8]) -> CmdResult<String> {
@@ -827,21 +2223,17 @@ fn cstr_bytes_to_string(bytes: &[u8]) -> CmdResult<String> {
         .to_string())
 }
 
-fn print_user_setting(transport: &mut impl UdsTransport, index: u8) -> CmdResult {
-    let UserSettingPayload::Info {
+fn print_user_setting(
+    transport: &mut impl UdsTransport,
+    index: u8,
+    out: &mut impl Write,
+) -> CmdResult {
+    let SettingInfo {
         name: name_raw,
         kind,
         ..
-    } = read_user_setting_payload(transport, UserSettingDid::Info { index })?
-    else {
-        return Err(anyhow!("Expected Info payload"));
-    };
-
-    let UserSettingPayload::State(raw_value) =
-        read_user_setting_payload(transport, UserSettingDid::ReadState { index })?
-    else {
-        return Err(anyhow!("Expected State payload"));
-    };
+    } = read_user_setting(transport, InfoDid { index })?;
+    let raw_value = read_user_setting(transport, StateDid { index })?;
 
     let setting_value = SettingValue::decode(kind, &raw_value);
 
@@ -850,14 +2242,14 @@ fn print_user_setting(transport: &mut impl UdsTransport, index: u8) -> CmdResult
     match kind {
         UserSettingType::Integer | UserSettingType::Scaled => match setting_value {
             SettingValue::IntegerHex { value, .. } => {
-                println!("{}: 0x{:08X}", name, value);
+                writeln!(out, "{}: 0x{:08X}", name, value)?;
             }
             SettingValue::IntegerScaled { value, divisor, .. } => {
                 let scaled = value as f64 / divisor as f64 / 100.0;
-                println!("{}: {:.2}", name, scaled);
+                writeln!(out, "{}: {:.2}", name, scaled)?;
             }
             _ => {
-                println!("{}: (unexpected value type for Integer)", name);
+                writeln!(out, "{}: (unexpected value type for Integer)", name)?;
             }
         },
         UserSettingType::Selection => match setting_value {
@@ -867,113 +2259,279 @@ fn print_user_setting(transport: &mut impl UdsTransport, index: u8) -> CmdResult
             } => {
                 let mut enum_vals = Vec::new();
                 for j in 0..=max_index {
-                    let UserSettingPayload::Enum(name) = read_user_setting_payload(
+                    let name = read_user_setting(
                         transport,
-                        UserSettingDid::Enum {
+                        EnumDid {
                             enum_index: j,
                             index,
                         },
-                    )?
-                    else {
-                        return Err(anyhow!("Expected Enum payload"));
-                    };
+                    )?;
                     enum_vals.push(cstr_bytes_to_string(&name)?);
                 }
-                println!(
+                writeln!(
+                    out,
                     "{}: {} (options: {})",
                     name,
                     enum_vals[current_index as usize],
                     enum_vals.join(", ")
-                );
+                )?;
             }
             _ => {
-                println!("{}: (unexpected value type for Selection)", name);
+                writeln!(out, "{}: (unexpected value type for Selection)", name)?;
             }
         },
     }
     Ok(())
 }
 
-fn cmd_userconfig_list(transport: &mut impl UdsTransport) -> CmdResult {
-    let UserSettingPayload::Count(count) =
-        read_user_setting_payload(transport, UserSettingDid::Count)?
-    else {
-        return Err(anyhow!("Expected Count payload"));
-    };
+fn cmd_userconfig_list(transport: &mut impl UdsTransport, out: &mut impl Write) -> CmdResult {
+    let count = read_user_setting(transport, CountDid)?;
     if count == 0 {
-        println!("No user config available");
+        writeln!(out, "No user config available")?;
     }
 
     for i in 0..count {
-        print_user_setting(transport, i)?;
+        print_user_setting(transport, i, out)?;
     }
     Ok(())
 }
 
-fn find_user_setting_index(transport: &mut impl UdsTransport, name: &str) -> CmdResult<Option<u8>> {
-    let UserSettingPayload::Count(count) =
-        read_user_setting_payload(transport, UserSettingDid::Count)?
-    else {
-        return Err(anyhow!("Expected Count payload"));
-    };
-
-    for i in 0..count {
-        let UserSettingPayload::Info { name: name_raw, .. } =
-            read_user_setting_payload(transport, UserSettingDid::Info { index: i })?
-        else {
-            return Err(anyhow!("Expected Info payload"));
-        };
+/// Every user setting's `Info`, read once per session to look settings up by
+/// name
+fn settings_catalog(session: &mut Session) -> CmdResult<&SettingsCatalog> {
+    session.settings_catalog().map_err(menu_error)
+}
 
-        if cstr_bytes_to_string(&name_raw)? == name {
-            return Ok(Some(i));
+/// The user setting `name` names. Reads match a unique prefix too, writes
+/// need the whole name so a short one can't pick another setting.
+fn find_user_setting(session: &mut Session, name: &str, exact: bool) -> CmdResult<MenuItem> {
+    let names = |items: Vec<MenuItem>| {
+        items
+            .iter()
+            .map(|item| format!("'{}'", item.name()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let catalog = settings_catalog(session)?;
+    let found = if exact {
+        catalog.find_exact(name)
+    } else {
+        catalog.find(name)
+    };
+    match found {
+        Ok(item) => Ok(*item),
+        Err(FindError::NotFound { suggestions }) if suggestions.is_empty() => {
+            Err(anyhow!("Setting '{}' not found", name))
         }
+        Err(FindError::NotFound { suggestions }) => Err(anyhow!(
+            "Setting '{}' not found, did you mean {}?",
+            name,
+            names(suggestions)
+        )),
+        Err(FindError::Ambiguous(items)) => Err(anyhow!(
+            "Setting '{}' is ambiguous, it could be {}",
+            name,
+            names(items)
+        )),
     }
-    Ok(None)
 }
 
-fn cmd_userconfig_get(transport: &mut impl UdsTransport, name: String) -> CmdResult {
-    let index = find_user_setting_index(transport, &name)?
-        .ok_or_else(|| anyhow!("Setting '{}' not found", name))?;
-
-    print_user_setting(transport, index)?;
+fn cmd_userconfig_get(session: &mut Session, name: String) -> CmdResult {
+    let item = find_user_setting(session, &name, false)?;
+    print_user_setting(session, item.index, &mut std::io::stdout())?;
     Ok(())
 }
 
-fn cmd_userconfig_set(transport: &mut impl UdsTransport, name: String, value: String) -> CmdResult {
-    let index = find_user_setting_index(transport, &name)?
-        .ok_or_else(|| anyhow!("Setting '{}' not found", name))?;
-
-    let UserSettingPayload::Info { kind, editable, .. } =
-        read_user_setting_payload(transport, UserSettingDid::Info { index })?
-    else {
-        return Err(anyhow!("Expected Info payload"));
-    };
+fn cmd_userconfig_set(
+    session: &mut Session,
+    name: String,
+    value: String,
+    confirmed: bool,
+) -> CmdResult {
+    let item = find_user_setting(session, &name, true)?;
+    write_user_setting(session, item.index, item.name(), &value, confirmed)?;
+    println!("Set '{}' = {}", item.name(), value);
+    Ok(())
+}
 
-    if !editable {
-        return Err(anyhow!("Setting '{}' is not editable", name));
+fn menu_error(e: MenuError<transport::TransportError>) -> anyhow::Error {
+    match e {
+        MenuError::Uds(e) => transport::uds_error_to_anyhow(e),
+        MenuError::NotEditable => anyhow!("Setting is not editable"),
+        MenuError::OutOfRange => anyhow!("Value out of range"),
+        MenuError::Payload(e) => anyhow!("{:?}", e),
     }
+}
 
-    let value_bytes = match kind {
-        UserSettingType::Integer | UserSettingType::Scaled => {
-            // Try to parse as hex first (0x prefix), then as decimal
-            let num: u32 = if value.starts_with("0x") || value.starts_with("0X") {
-                u32::from_str_radix(&value[2..], 16).map_err(|_| anyhow!("Invalid hex number"))?
-            } else {
-                value.parse().map_err(|_| anyhow!("Invalid number"))?
-            };
+fn prompt_line(prompt: &str) -> CmdResult<String> {
+    print!("{} ", prompt);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+fn format_menu_value(
+    menu: &mut MenuClient<'_, Session>,
+    item: &MenuItem,
+    value: SettingValue,
+) -> CmdResult<String> {
+    Ok(match value {
+        SettingValue::SelectionIndex { current_index, .. } => {
+            let option = menu.option(item, current_index).map_err(menu_error)?;
+            menu::label(&option).to_string()
+        }
+        SettingValue::IntegerHex { value, .. } => format!("0x{:08X}", value),
+        SettingValue::IntegerScaled { value, divisor, .. } => {
+            format!(
+                "{:.2} (raw {})",
+                value as f64 / divisor as f64 / 100.0,
+                value
+            )
+        }
+    })
+}
+
+fn cmd_remote_menu(session: &mut Session) -> CmdResult {
+    let version = session.version();
+    let mut menu = MenuClient::new(session, version);
+    let count = menu.count().map_err(menu_error)?;
+    if count == 0 {
+        println!("No user config available");
+        return Ok(());
+    }
+    let items = (0..count)
+        .map(|i| menu.item(i))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(menu_error)?;
+
+    loop {
+        for item in &items {
+            let value = menu.value(item).map_err(menu_error)?;
+            println!(
+                "{:>2}. {}{}: {}",
+                item.index + 1,
+                item.name(),
+                if item.editable { "" } else { " (read-only)" },
+                format_menu_value(&mut menu, item, value)?
+            );
+        }
 
-            // For now, write as 8 bytes (legacy format)
-            // The firmware will interpret based on the divisor field it has stored
-            let mut bytes = [0u8; 8];
-            bytes[4..8].copy_from_slice(&num.to_be_bytes());
-            bytes
+        let choice = prompt_line("Setting number (empty to quit):")?;
+        if choice.is_empty() {
+            return Ok(());
+        }
+        let Some(item) = choice
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| items.get(n.wrapping_sub(1)))
+        else {
+            println!("No setting {}", choice);
+            continue;
+        };
+        if !item.editable {
+            println!("'{}' is read-only", item.name());
+            continue;
+        }
+
+        let value = match menu.value(item).map_err(menu_error)? {
+            SettingValue::SelectionIndex { max_index, .. } => {
+                let mut options = Vec::new();
+                for j in 0..=max_index {
+                    let option = menu.option(item, j).map_err(menu_error)?;
+                    options.push(menu::label(&option).to_string());
+                    println!("  {}. {}", j + 1, options[j as usize]);
+                }
+                let answer = prompt_line("Option:")?;
+                if answer.is_empty() {
+                    continue;
+                }
+                match answer.parse::<u32>() {
+                    Ok(n) => n.wrapping_sub(1),
+                    Err(_) => match options.iter().position(|o| o.eq_ignore_ascii_case(&answer)) {
+                        Some(j) => j as u32,
+                        None => {
+                            println!("No option '{}'", answer);
+                            continue;
+                        }
+                    },
+                }
+            }
+            SettingValue::IntegerHex { min, max, .. }
+            | SettingValue::IntegerScaled { min, max, .. } => {
+                if min < max {
+                    println!("  Range {}..={} (raw)", min, max);
+                }
+                let answer = prompt_line("Raw value:")?;
+                if answer.is_empty() {
+                    continue;
+                }
+                let parsed = match answer
+                    .strip_prefix("0x")
+                    .or_else(|| answer.strip_prefix("0X"))
+                {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => answer.parse(),
+                };
+                match parsed {
+                    Ok(value) => value,
+                    Err(_) => {
+                        println!("Invalid number '{}'", answer);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        check_setting_risk(item.name(), &value.to_string(), false)?;
+        match menu.write(item, value) {
+            Ok(()) => {
+                let now = menu.value(item).map_err(menu_error)?;
+                println!(
+                    "{} = {}",
+                    item.name(),
+                    format_menu_value(&mut menu, item, now)?
+                );
+            }
+            Err(MenuError::OutOfRange) => println!("{} is out of range", value),
+            Err(e) => return Err(menu_error(e)),
+        }
+        println!();
+    }
+}
+
+/// Writes a user setting by index. Selection values are matched against the
+/// setting's enum names.
+/// High-risk settings need `confirmed` or an interactive yes.
+fn write_user_setting(
+    transport: &mut Session,
+    index: u8,
+    name: &str,
+    value: &str,
+    confirmed: bool,
+) -> CmdResult {
+    let SettingInfo {
+        name: name_raw,
+        kind,
+        editable,
+    } = read_user_setting(transport, InfoDid { index })?;
+
+    if !editable {
+        return Err(anyhow!("Setting '{}' is not editable", name));
+    }
+    check_setting_risk(&cstr_bytes_to_string(&name_raw)?, value, confirmed)?;
+
+    let value = match kind {
+        UserSettingType::Integer | UserSettingType::Scaled => {
+            // Try to parse as hex first (0x prefix), then as decimal
+            if value.starts_with("0x") || value.starts_with("0X") {
+                u32::from_str_radix(&value[2..], 16).map_err(|_| anyhow!("Invalid hex number"))?
+            } else {
+                value.parse().map_err(|_| anyhow!("Invalid number"))?
+            }
         }
         UserSettingType::Selection => {
-            let UserSettingPayload::State(raw_value) =
-                read_user_setting_payload(transport, UserSettingDid::ReadState { index })?
-            else {
-                return Err(anyhow!("Expected State payload"));
-            };
+            let raw_value = read_user_setting(transport, StateDid { index })?;
 
             let setting_value = SettingValue::decode(kind, &raw_value);
 
@@ -986,56 +2544,156 @@ fn cmd_userconfig_set(transport: &mut impl UdsTransport, name: String, value: St
 
             let mut matched_index = None;
             for j in 0..=max_index {
-                let UserSettingPayload::Enum(enum_name) = read_user_setting_payload(
+                let enum_name = read_user_setting(
                     transport,
-                    UserSettingDid::Enum {
+                    EnumDid {
                         enum_index: j,
                         index,
                     },
-                )?
-                else {
-                    return Err(anyhow!("Expected Enum payload"));
-                };
+                )?;
 
-                if cstr_bytes_to_string(&enum_name)? == value {
+                let enum_name = cstr_bytes_to_string(&enum_name)?;
+                if enum_name == value {
                     matched_index = Some(j as u32);
                     break;
                 }
             }
 
-            let num = matched_index
-                .ok_or_else(|| anyhow!("Invalid value '{}' for setting '{}'", value, name))?;
-
-            let mut bytes = [0u8; 8];
-            bytes[0..4].copy_from_slice(&num.to_be_bytes());
-            bytes
+            matched_index
+                .ok_or_else(|| anyhow!("Invalid value '{}' for setting '{}'", value, name))?
         }
     };
 
-    let input_len = match kind {
-        UserSettingType::Selection => 4,
-        _ => 8,
+    let input = UserSettingInput::for_value(kind, value, &transport.version());
+    transport.wdbi(InputDid { index }.to_did(), input.as_bytes())?;
+    Ok(())
+}
+
+/// Refuses write-protected settings and asks before writing high-risk ones,
+/// per the shared [`candive::diag::settings::SETTING_RISKS`] table.
+fn check_setting_risk(device_name: &str, value: &str, confirmed: bool) -> CmdResult {
+    let Some(risk) = settings::setting_risk(device_name) else {
+        return Ok(());
     };
+    match risk.class {
+        SettingRiskClass::Normal => Ok(()),
+        SettingRiskClass::WriteProtected => Err(anyhow!(
+            "Setting '{}' is write-protected: {}",
+            device_name,
+            risk.reason
+        )),
+        SettingRiskClass::High if confirmed => Ok(()),
+        SettingRiskClass::High => {
+            if !std::io::stdin().is_terminal() {
+                return Err(anyhow!(
+                    "Setting '{}' is high risk ({}), pass --confirm to write it",
+                    device_name,
+                    risk.reason
+                ));
+            }
+            if confirm(&format!(
+                "'{}' is a high-risk setting, {}. Set it to {}?",
+                device_name, risk.reason, value
+            ))? {
+                Ok(())
+            } else {
+                Err(anyhow!("Aborted"))
+            }
+        }
+    }
+}
 
-    let payload = UserSettingPayload::Input(UserSettingInput {
-        len: input_len,
-        bytes: value_bytes,
-    });
-    let mut buf = [0u8; 16];
-    let len = payload.encode(&mut buf).map_err(|e| anyhow!("{:?}", e))?;
+fn preflight_issue_as_str(issue: PreflightIssue) -> String {
+    match issue {
+        PreflightIssue::NoStatus => "no SoloStatus seen, battery voltage unknown".into(),
+        PreflightIssue::BatteryLow {
+            observed_dv,
+            required_dv,
+        } => format!(
+            "battery at {:.1} V, programming needs {:.1} V",
+            observed_dv as f32 / 10.0,
+            required_dv as f32 / 10.0
+        ),
+        PreflightIssue::BatteryUnstable { min_dv, max_dv } => format!(
+            "battery moved between {:.1} V and {:.1} V",
+            min_dv as f32 / 10.0,
+            max_dv as f32 / 10.0
+        ),
+        PreflightIssue::VoltageAlert(alert) => format!("Solo reports {:?}", alert),
+        PreflightIssue::ActiveAlert(code) => match AnyAlert::from_u16(code) {
+            Some(alert) => format!("alert {} (0x{:04X}) active", alert_name(alert), code),
+            None => format!("alert 0x{:04X} active", code),
+        },
+    }
+}
 
-    transport.wdbi(UserSettingDid::WriteInput { index }.to_did(), &buf[0..len])?;
-    println!("Set '{}' = {}", name, value);
-    Ok(())
+/// Watches the bus before a download and fails with every issue found,
+/// unless `force` is set. Without a bus to watch, and with no DID known to
+/// report the battery voltage, the user is asked about the battery instead.
+fn check_for_programming(
+    transport: &mut impl UdsTransport,
+    transport_uri: &str,
+    margins: &ProgrammingMargins,
+    force: bool,
+) -> CmdResult {
+    const WATCH_WINDOW: std::time::Duration = std::time::Duration::from_secs(3);
+
+    let limits = transport.rdbi_codec::<ControlConfig>()?.power_limits();
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        if force {
+            log::warn!("Battery and alerts not checked (--force)");
+            return Ok(());
+        }
+        let required_dv = limits.battery_voltage_min_dv + margins.battery_margin_dv;
+        if confirm(&format!(
+            "Battery voltage and alerts can't be read over this transport. Is the battery above {:.1} V, with no alerts on the handset?",
+            required_dv as f32 / 10.0
+        ))? {
+            return Ok(());
+        }
+        return Err(anyhow!("Battery not confirmed, not programming"));
+    };
+
+    let mut watch = ProgrammingWatch::new();
+    log::info!(
+        "Watching {} for battery and alerts ({}s)...",
+        interface,
+        WATCH_WINDOW.as_secs()
+    );
+    transport::listen(transport_uri, WATCH_WINDOW, |msg| {
+        watch.push(msg);
+    })
+    .map_err(|e| anyhow!("Failed to watch the bus: {}", e))?;
+
+    let mut issues = Vec::new();
+    candive::preflight::check_for_programming(&limits, margins, &watch, |issue| issues.push(issue));
+    if issues.is_empty() {
+        return Ok(());
+    }
+    for issue in &issues {
+        log::warn!("Pre-flight: {}", preflight_issue_as_str(*issue));
+    }
+    if force {
+        log::warn!("Programming anyway (--force)");
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Pre-flight checks failed, not programming. Use --force to override"
+    ))
 }
 
-fn cmd_fw_upload(transport: &mut impl UdsTransport, firmware_file: PathBuf) -> CmdResult {
+fn cmd_fw_upload(
+    link: &mut Link,
+    firmware_file: PathBuf,
+    margins: &ProgrammingMargins,
+    force: bool,
+) -> CmdResult {
     let mut file = File::open(&firmware_file)?;
 
     let mut firmware_data = Vec::new();
     file.read_to_end(&mut firmware_data)?;
 
-    let download_info = transport.rdbi_codec::<FirmwareDownloadCapability>()?;
+    let download_info = link.session.rdbi_codec::<FirmwareDownloadCapability>()?;
 
     if !download_info.supported {
         return Err(anyhow!("Firmware download not supported by device"));
@@ -1049,11 +2707,16 @@ fn cmd_fw_upload(transport: &mut impl UdsTransport, firmware_file: PathBuf) -> C
         ));
     }
 
+    check_for_programming(link.session, &link.config.uri, margins, force)?;
+
     let pb = new_progress_bar(firmware_data.len() as u64);
     pb.set_message("Uploading firmware");
 
-    transport.download(download_info.address, &firmware_data, |current, _total| {
-        pb.set_position(current as u64);
+    // A dropped link starts the download over from the first block
+    let tuning = link.retry(|session| {
+        session.download(download_info.address, &firmware_data, |current, _total| {
+            pb.set_position(current as u64);
+        })
     })?;
 
     pb.finish_with_message("Firmware upload complete");
@@ -1061,203 +2724,1674 @@ fn cmd_fw_upload(transport: &mut impl UdsTransport, firmware_file: PathBuf) -> C
     println!("Firmware upload");
     println!("  File:   {}", firmware_file.display());
     println!("  Size:   {} bytes", firmware_data.len());
+    println!(
+        "  Blocks: {}, settled on {} bytes, {} resent, {:.1} KiB/s",
+        tuning.blocks,
+        tuning.block_len,
+        tuning.errors,
+        tuning.bytes_per_sec as f64 / 1024.0
+    );
     println!("  Result: OK");
     Ok(())
 }
 
-fn cmd_fw_info(transport: &mut impl UdsTransport) -> CmdResult {
+fn cmd_fw_info(
+    transport: &mut impl UdsTransport,
+    manifest: Option<PathBuf>,
+    out: &mut impl Write,
+) -> CmdResult {
     let version = transport.rdbi_codec::<FirmwareVersionAscii>()?;
     let fw_crc = transport.rdbi_codec::<FirmwareCrc>()?;
+    // Optional standard DIDs, most firmware answers with a negative response
+    let boot_id = transport.rdbi(firmware::BOOT_SOFTWARE_ID_DID).ok();
+    let build_date = transport
+        .rdbi(firmware::PROGRAMMING_DATE_DID)
+        .ok()
+        .and_then(|raw| firmware::BuildDate::from_bcd(&raw));
+
+    let raw_version = String::from_utf8_lossy(&version.firmware_version_ascii);
+    let parsed = version.version();
+
+    writeln!(out, "Firmware")?;
+    match parsed {
+        Some(v) => writeln!(out, "  Version:    {} ({})", raw_version, v)?,
+        None => writeln!(out, "  Version:    {}", raw_version)?,
+    }
+    writeln!(out, "  CRC32:      0x{:08X}", fw_crc.crc)?;
+    if let Some(boot_id) = boot_id {
+        writeln!(
+            out,
+            "  Bootloader: {}",
+            String::from_utf8_lossy(&boot_id).trim_end_matches('\0')
+        )?;
+    }
+    if let Some(date) = build_date {
+        writeln!(out, "  Built:      {}", date)?;
+    }
 
-    println!("Firmware");
-    println!(
-        "  Version: {}",
-        String::from_utf8_lossy(&version.firmware_version_ascii)
-    );
-    println!("  CRC32:   0x{:08X}", fw_crc.crc);
+    let Some(manifest) = manifest else {
+        return Ok(());
+    };
+    let manifest_text = std::fs::read_to_string(&manifest)
+        .map_err(|e| anyhow!("Failed to read {}: {}", manifest.display(), e))?;
+    let Some(newest) = firmware::newest_release(&manifest_text) else {
+        return Err(anyhow!("No releases in {}", manifest.display()));
+    };
+    writeln!(out, "  Newest:     {} {}", newest.version, newest.notes)?;
+    match parsed.map(|v| firmware::update_available(&v, &manifest_text)) {
+        Some(Some(release)) => writeln!(out, "Update available: {}", release.version)?,
+        Some(None) => writeln!(out, "Up to date")?,
+        None => writeln!(out, "Installed version not comparable")?,
+    }
     Ok(())
 }
 
-fn cmd_device_info(transport: &mut impl UdsTransport) -> CmdResult {
-    let serial = transport.rdbi_codec::<SerialNumberAscii>()?;
+fn cmd_device_info(transport: &mut Session, verbose: bool, out: &mut impl Write) -> CmdResult {
+    let serial = transport.rdbi_lenient::<SerialNumberAscii>()?;
+    let device_id = transport.rdbi_lenient::<DeviceId>()?;
+
+    writeln!(out, "Device")?;
+    match serial {
+        Some(serial) => writeln!(
+            out,
+            "  Serial:    {}",
+            String::from_utf8_lossy(&serial.serial_ascii)
+        )?,
+        None => writeln!(out, "  Serial:    -")?,
+    }
+    match device_id {
+        Some(device_id) => {
+            writeln!(out, "  Device ID: {}", device_id)?;
+            // Wafer, position and lot only mean something in the standard
+            // layout, other IDs would print made-up coordinates
+            let uid = device_id.stm32_uid();
+            match uid.lot_str() {
+                Some(lot) if uid.is_standard_layout() => {
+                    writeln!(
+                        out,
+                        "  Wafer:     {} at X {}, Y {}",
+                        uid.wafer, uid.x, uid.y
+                    )?;
+                    writeln!(out, "  Lot:       {}", lot)?;
+                }
+                _ => writeln!(out, "  Wafer/lot: - (not a standard STM32 UID layout)")?,
+            }
+        }
+        None => writeln!(out, "  Device ID: -")?,
+    }
+    if !verbose {
+        return Ok(());
+    }
+
+    let info = solo::DevInfo::read(transport)
+        .map_err(transport::uds_error_to_anyhow)?
+        .map_err(|e| anyhow!("MCU_DEVINFO: {:?}", e))?;
+    writeln!(out)?;
+    let reserved = solo::devinfo_layout::RESERVED;
+    writeln!(
+        out,
+        "MCU_DEVINFO (only the log digest is documented, 0x{:02X}..0x{:02X} is reserved)",
+        reserved.start, reserved.end
+    )?;
+    writeln!(out, "  Log digest CRC: 0x{:08X}", info.log_digest.log_crc32)?;
+    for (i, chunk) in info.reserved().chunks(16).enumerate() {
+        writeln!(
+            out,
+            "  0x{:02X}: {}",
+            reserved.start + i * 16,
+            hex::encode_upper(chunk)
+        )?;
+    }
+    Ok(())
+}
+
+fn cmd_config_list(transport: &mut impl UdsTransport, out: &mut impl Write) -> CmdResult {
+    let Some(config) = transport.rdbi_lenient::<ControlConfig>()? else {
+        // The warning above lists the bytes the device did send
+        writeln!(out, "Config: not decodable by this version of solodiag")?;
+        return Ok(());
+    };
+    writeln!(out, "Config")?;
+    for field in ConfigField::ALL {
+        let label = format!("{}:", field.name());
+        let value = field.get(&config);
+        match field.unit() {
+            Some(unit) => writeln!(out, "  {:<17} {} {}", label, value, unit.symbol)?,
+            None => writeln!(
+                out,
+                "  {:<17} {} ({})",
+                label,
+                value,
+                field.choices().join(", ")
+            )?,
+        }
+    }
+    Ok(())
+}
+
+fn cmd_config_get(transport: &mut impl UdsTransport, key: ConfigField) -> CmdResult {
+    let config = transport.rdbi_codec::<ControlConfig>()?;
+    println!("{}", key.get(&config));
+    Ok(())
+}
+
+fn cmd_config_set(
+    transport: &mut Session,
+    key: ConfigField,
+    value: &str,
+    confirmed: bool,
+    solo_key: &SoloKey,
+) -> CmdResult {
+    let original_config = transport.rdbi_codec::<ControlConfig>()?;
+    let mut config = original_config.clone();
+
+    key.set(&mut config, value)
+        .map_err(|_| match key.choices() {
+            [] => anyhow!("Invalid value '{}'. Must be a number.", value),
+            choices => anyhow!(
+                "Invalid value '{}'. Must be one of: {}",
+                value,
+                choices.join(", ")
+            ),
+        })?;
+
+    if config == original_config {
+        println!("No changes to current configuration.");
+        return Ok(());
+    }
+    // Same check as user set, the config key names the current and
+    // voltage limits the risk table covers
+    check_setting_risk(key.name(), value, confirmed)?;
+
+    // A wrong key would write a config the device can't decrypt
+    let logs = transport.logs_info()?;
+    check_solo_key(transport, &logs, solo_key)?;
     let device_id = transport.rdbi_codec::<DeviceId>()?;
+    let blob = solo_key.encrypt_config(&EncryptedConfigPayload::new(config, &device_id));
+    transport.wdbi(EncryptedConfigBlob::DID, &blob.to_bytes())?;
 
-    println!("Device");
-    println!(
-        "  Serial:    {}",
-        String::from_utf8_lossy(&serial.serial_ascii)
+    println!("Updated config");
+    Ok(())
+}
+
+fn listen_power_stats(transport_uri: &str) -> CmdResult<Option<PowerStats>> {
+    const LISTEN_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Ok(None);
+    };
+
+    log::info!(
+        "Listening for SoloStatus on {} ({}s)...",
+        interface,
+        LISTEN_WINDOW.as_secs()
     );
+    let stats = transport::listen_power_stats(transport_uri, LISTEN_WINDOW)
+        .map_err(|e| anyhow!("Failed to listen for SoloStatus: {}", e))?;
+    Ok(Some(stats))
+}
+
+fn depth_comp_issue_as_str(issue: DepthCompIssue) -> String {
+    match issue {
+        DepthCompIssue::NoBroadcast => "no AmbientPressure broadcast seen".into(),
+        DepthCompIssue::FlagMismatch {
+            configured,
+            broadcast,
+        } => format!(
+            "configured depth-comp {} but the Solo broadcasts {}",
+            bool_as_on_off(configured),
+            bool_as_on_off(broadcast)
+        ),
+        DepthCompIssue::SurfaceUnstable { min, max } => {
+            format!("surface pressure moved between {} and {}", min, max)
+        }
+        DepthCompIssue::SurfaceMismatch { broadcast, logged } => format!(
+            "broadcast surface pressure {} differs from logged {}",
+            broadcast, logged
+        ),
+    }
+}
+
+/// Surface pressure of the latest AmbientPressure entry in the device log
+fn logged_surface_pressure(
+    transport: &mut impl UdsTransport,
+    solo_key: &SoloKey,
+) -> CmdResult<Option<candive::units::Millibar>> {
+    const TAIL_ENTRIES: u32 = 200;
+
+    let logs = transport.logs_info()?;
+    check_solo_key(transport, &logs, solo_key)?;
+    let end = bisect_log(transport, &logs, solo_key, 0, logs.entry_count(), |p| {
+        matches!(p, LogProbe::Empty)
+    })?;
+    let count = TAIL_ENTRIES.min(end);
+    if count == 0 {
+        return Ok(None);
+    }
+    let data = dump_log_chunk(transport, &logs, count, end - count, solo_key)?;
+
+    Ok(LogEntryIterator::new(&data)
+        .filter_map(|e| Msg::try_from_frame(&e.to_frame(&LogProfile::SOLO).1).ok())
+        .filter_map(|msg| match msg {
+            Msg::AmbientPressure { surface, .. } => Some(surface),
+            _ => None,
+        })
+        .last())
+}
+
+fn listen_depth_comp(transport_uri: &str, interface: &str) -> CmdResult<DepthCompStats> {
+    const LISTEN_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+    log::info!(
+        "Listening for AmbientPressure on {} ({}s)...",
+        interface,
+        LISTEN_WINDOW.as_secs()
+    );
+    let mut stats = DepthCompStats::new();
+    transport::listen(transport_uri, LISTEN_WINDOW, |msg| {
+        stats.push(msg);
+    })
+    .map_err(|e| anyhow!("Failed to listen for AmbientPressure: {}", e))?;
+    Ok(stats)
+}
+
+fn cmd_config_verify_depth_comp(
+    transport: &mut impl UdsTransport,
+    transport_uri: &str,
+    units: UnitsPreference,
+    solo_key: Option<&SoloKey>,
+) -> CmdResult {
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Err(anyhow!(
+            "Depth compensation check needs a can:// or socketcand:// transport"
+        ));
+    };
+    let configured = transport
+        .rdbi_codec::<ControlConfig>()?
+        .depth_compensation_enabled;
+
+    let logged = match solo_key {
+        Some(solo_key) => logged_surface_pressure(transport, solo_key)?,
+        None => None,
+    };
+
+    let stats = listen_depth_comp(transport_uri, interface)?;
+
+    println!("Depth compensation");
+    println!("  configured:       {}", bool_as_on_off(configured));
     println!(
-        "  Device ID: {}",
-        hex::encode_upper(&device_id.device_id).to_uppercase()
+        "  broadcast flag:   on in {} of {} samples",
+        stats.flag_on(),
+        stats.samples()
     );
+    if let Some((surface, ambient)) = stats.last() {
+        println!("  surface:          {}", surface.in_units(units));
+        println!("  ambient:          {}", ambient.in_units(units));
+        if let Some(factor) = depth_comp::compensation_factor_permille(surface, ambient) {
+            println!("  pressure ratio:   {:.3}", factor as f32 / 1000.0);
+        }
+    }
+    if let Some((low, high)) = stats.factor_range() {
+        println!(
+            "  applied factor:   {:.3} - {:.3}",
+            low as f32 / 1000.0,
+            high as f32 / 1000.0
+        );
+    }
+    match (logged, solo_key) {
+        (Some(surface), _) => println!("  logged surface:   {}", surface.in_units(units)),
+        (None, Some(_)) => println!("  logged surface:   no AmbientPressure entry in log"),
+        (None, None) => println!("  logged surface:   skipped (set SOLO_KEY)"),
+    }
+
+    let mut issues = Vec::new();
+    depth_comp::check_depth_comp(configured, &stats, logged, |issue| issues.push(issue));
+    println!();
+    if issues.is_empty() {
+        println!("Depth compensation behaves as configured");
+    } else {
+        println!("Warnings");
+        for issue in issues {
+            println!("  {}", depth_comp_issue_as_str(issue));
+        }
+    }
     Ok(())
 }
 
-fn cmd_config_list(transport: &mut impl UdsTransport) -> CmdResult {
-    let config = transport.rdbi_codec::<ControlConfig>()?;
-    println!("Config");
+fn power_issue_as_str(issue: PowerIssue) -> String {
+    match issue {
+        PowerIssue::CurrentMinOutOfRange(ma) => format!(
+            "min-current {} mA not settable ({}-{} mA in 10 mA steps)",
+            ma,
+            power::SOLENOID_MIN_CURRENT_MA.start(),
+            power::SOLENOID_MIN_CURRENT_MA.end()
+        ),
+        PowerIssue::CurrentMaxOutOfRange(ma) => format!(
+            "max-current {} mA not settable ({}-{} mA in 10 mA steps)",
+            ma,
+            power::SOLENOID_MAX_CURRENT_MA.start(),
+            power::SOLENOID_MAX_CURRENT_MA.end()
+        ),
+        PowerIssue::CurrentLimitsInverted { min_ma, max_ma } => format!(
+            "min-current {} mA is not below max-current {} mA",
+            min_ma, max_ma
+        ),
+        PowerIssue::BatteryMinOutOfRange(dv) => format!(
+            "min-voltage {:.1} V not settable ({:.1}-{:.1} V, doubled with voltage-doubling)",
+            dv as f32 / 10.0,
+            *power::BATTERY_MIN_VOLTAGE_DV.start() as f32 / 10.0,
+            *power::BATTERY_MIN_VOLTAGE_DV.end() as f32 / 10.0
+        ),
+        PowerIssue::DrawBelowMin {
+            observed_ma,
+            min_ma,
+        } => format!(
+            "solenoid drew {} mA while firing, below min-current {} mA",
+            observed_ma, min_ma
+        ),
+        PowerIssue::DrawAboveMax {
+            observed_ma,
+            max_ma,
+        } => format!(
+            "solenoid drew {} mA while firing, above max-current {} mA",
+            observed_ma, max_ma
+        ),
+        PowerIssue::BatteryBelowMin {
+            observed_dv,
+            min_dv,
+        } => format!(
+            "battery at {:.1} V, below min-voltage {:.1} V",
+            observed_dv as f32 / 10.0,
+            min_dv as f32 / 10.0
+        ),
+    }
+}
+
+fn cmd_power(transport: &mut impl UdsTransport, transport_uri: &str) -> CmdResult {
+    let limits = transport.rdbi_codec::<ControlConfig>()?.power_limits();
+
+    println!("Power");
+    println!("  min-current:      {} mA", limits.solenoid_current_min_ma);
+    println!("  max-current:      {} mA", limits.solenoid_current_max_ma);
     println!(
-        "  cal:              {} (direct, monitored)",
-        calibration_procedure_as_str(config.calibration_procedure)
+        "  min-voltage:      {:.1} V",
+        limits.battery_voltage_min_dv as f32 / 10.0
     );
     println!(
-        "  ppo2:             {} (user, manual, 1sec, 5sec)",
-        ppo2_mode_as_str(config.ppo2_control_mode)
+        "  voltage-doubling: {}",
+        bool_as_on_off(limits.voltage_doubling)
     );
+
+    let count = read_user_setting(transport, CountDid)?;
+    let mut battery_settings = Vec::new();
+    for i in 0..count {
+        let SettingInfo { name, .. } = read_user_setting(transport, InfoDid { index: i })?;
+        let name = cstr_bytes_to_string(&name)?.to_lowercase();
+        if name.contains("batt") || name.contains("volt") {
+            battery_settings.push(i);
+        }
+    }
+    if !battery_settings.is_empty() {
+        println!();
+        println!("Battery settings");
+        for i in battery_settings {
+            print!("  ");
+            print_user_setting(transport, i, &mut std::io::stdout())?;
+        }
+    }
+
+    let stats = listen_power_stats(transport_uri)?;
+    println!();
+    println!("Observed");
+    match &stats {
+        None => println!("  skipped (needs a can:// or socketcand:// transport)"),
+        Some(stats) if stats.samples() == 0 => println!("  no SoloStatus broadcast seen"),
+        Some(stats) => {
+            println!("  samples:          {}", stats.samples());
+            if let Some((low, high)) = stats.voltage_range() {
+                println!("  battery:          {} - {}", low, high);
+            }
+            match stats.firing_current_range() {
+                Some((low, high)) => println!(
+                    "  firing current:   {} - {} ({} samples)",
+                    low,
+                    high,
+                    stats.firing_samples()
+                ),
+                None => println!("  firing current:   solenoid did not fire"),
+            }
+        }
+    }
+
+    let mut issues = Vec::new();
+    power::check_power(&limits, stats.as_ref(), |issue| issues.push(issue));
+    println!();
+    if issues.is_empty() {
+        println!("All within limits");
+    } else {
+        println!("Warnings");
+        for issue in issues {
+            println!("  {}", power_issue_as_str(issue));
+        }
+    }
+    Ok(())
+}
+
+/// What the interlock needs to know about the bus
+#[derive(Default)]
+struct BusState {
+    solo_seen: bool,
+    diving: bool,
+    /// Ambient pressure well above surface pressure
+    submerged: bool,
+    setpoint: Option<candive::units::PpO2Deci>,
+}
+
+impl BusState {
+    /// Ambient over surface pressure counted as being under water (about 1 m)
+    const SUBMERGED_MBAR: u16 = 100;
+
+    fn push(&mut self, msg: &Msg) {
+        match msg {
+            Msg::SoloStatus { setpoint, .. } => {
+                self.solo_seen = true;
+                self.setpoint.get_or_insert(*setpoint);
+            }
+            Msg::Diving { status, .. } => self.diving = status.in_dive(),
+            Msg::AmbientPressure {
+                surface, current, ..
+            } => {
+                self.submerged = current.raw() > surface.raw().saturating_add(Self::SUBMERGED_MBAR)
+            }
+            _ => {}
+        }
+    }
+
+    fn check_interlock(&self) -> CmdResult {
+        if self.diving || self.submerged {
+            return Err(anyhow!(
+                "Dive in progress or unit under water, refusing to send on the bus"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How often a spoofed setpoint is broadcast while it should hold
+const SETPOINT_REPEAT: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Broadcasts `setpoint` again when dropped, so a raised test setpoint is
+/// taken back however the test ends, errors included
+struct SetpointRestore<'a> {
+    socket: &'a transport::RawBus,
+    src: u8,
+    setpoint: candive::units::PpO2Deci,
+}
+
+impl SetpointRestore<'_> {
+    /// A few broadcasts, in case one is lost
+    const SENDS: u32 = 3;
+}
+
+impl Drop for SetpointRestore<'_> {
+    fn drop(&mut self) {
+        let msg = Msg::Setpoint(self.setpoint);
+        for _ in 0..Self::SENDS {
+            if let Err(e) = self
+                .socket
+                .send(DiveCanId::new(self.src, 0xFF, msg.kind()), &msg.to_frame())
+            {
+                log::error!("Failed to restore the setpoint to {}: {}", self.setpoint, e);
+                return;
+            }
+            std::thread::sleep(SETPOINT_REPEAT);
+        }
+    }
+}
+
+/// Feeds everything heard on `bus` for `window` into `state`, and hands it
+/// to `each` with the sender's id
+fn watch_bus(
+    bus: &transport::RawBus,
+    window: std::time::Duration,
+    state: &mut BusState,
+    mut each: impl FnMut(DiveCanId, &Msg),
+) -> CmdResult {
+    let start = std::time::Instant::now();
+    while start.elapsed() < window {
+        let Some((id, frame)) = bus.recv().map_err(|e| anyhow!("CAN read failed: {}", e))? else {
+            continue;
+        };
+        let Ok(msg) = Msg::try_from_frame(&frame) else {
+            continue;
+        };
+        state.push(&msg);
+        each(id, &msg);
+    }
+    Ok(())
+}
+
+fn confirm(prompt: &str) -> CmdResult<bool> {
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn cmd_solenoid_test(
+    transport: &mut impl UdsTransport,
+    transport_uri: &str,
+    src: u8,
+    pulses: u32,
+    duration_ms: u64,
+    yes: bool,
+    tx_dlc: TxDlcPolicy,
+) -> CmdResult {
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Err(anyhow!(
+            "Solenoid test needs a can:// or socketcand:// transport"
+        ));
+    };
+    let limits = transport.rdbi_codec::<ControlConfig>()?.power_limits();
+
+    use std::time::{Duration, Instant};
+
+    /// Setpoint high enough that the loop fires at the surface
+    const TEST_SETPOINT: u8 = 13;
+    const PULSE_GAP: Duration = Duration::from_secs(2);
+    const INTERLOCK_WINDOW: Duration = Duration::from_secs(2);
+
+    hostcheck::transport(transport_uri)?;
+
+    let socket = transport::RawBus::open(transport_uri, Duration::from_millis(20))
+        .map_err(|e| anyhow!("Failed to open {}: {}", interface, e))?
+        .with_tx_dlc(tx_dlc);
+    let mut bus = BusState::default();
+    let mut stats = PowerStats::new();
+    let mut alerts = Vec::new();
+
+    // Runs for `window`, broadcasting `setpoint` (if any) and feeding everything heard
+    let mut run = |setpoint: Option<candive::units::PpO2Deci>,
+                   window: Duration,
+                   bus: &mut BusState,
+                   stats: &mut PowerStats|
+     -> CmdResult {
+        let start = Instant::now();
+        let mut last_sent: Option<Instant> = None;
+        while start.elapsed() < window {
+            if let Some(sp) = setpoint
+                && last_sent.is_none_or(|t| t.elapsed() >= SETPOINT_REPEAT)
+            {
+                let msg = Msg::Setpoint(sp);
+                socket
+                    .send(DiveCanId::new(src, 0xFF, msg.kind()), &msg.to_frame())
+                    .map_err(|e| anyhow!("CAN write failed: {}", e))?;
+                last_sent = Some(Instant::now());
+            }
+            let Some((_, frame)) = socket
+                .recv()
+                .map_err(|e| anyhow!("CAN read failed: {}", e))?
+            else {
+                continue;
+            };
+            let Ok(msg) = Msg::try_from_frame(&frame) else {
+                continue;
+            };
+            bus.push(&msg);
+            stats.push(&msg);
+            if let Msg::SoloStatus {
+                voltage_alert,
+                current_alert,
+                ..
+            } = msg
+            {
+                let voltage_alert = voltage_alert.filter(|a| *a != VoltageAlert::Clear);
+                let current_alert = current_alert.filter(|a| *a != CurrentAlert::Clear);
+                for text in [
+                    msgformat::voltage_alert_text(voltage_alert),
+                    msgformat::current_alert_text(current_alert),
+                ] {
+                    if !text.is_empty() && !alerts.contains(&text) {
+                        alerts.push(text);
+                    }
+                }
+            }
+        }
+        Ok(())
+    };
+
+    log::info!("Checking bus state on {}...", interface);
+    run(None, INTERLOCK_WINDOW, &mut bus, &mut stats)?;
+    if !bus.solo_seen {
+        return Err(anyhow!(
+            "No SoloStatus broadcast seen, is the Solo on the bus?"
+        ));
+    }
+    bus.check_interlock()?;
+
+    if !yes
+        && !confirm(&format!(
+            "Fire the solenoid {} time(s) for {} ms?",
+            pulses, duration_ms
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    // The bus may have changed while the prompt was open
+    run(None, INTERLOCK_WINDOW, &mut bus, &mut stats)?;
+    bus.check_interlock()?;
+
+    let restore = SetpointRestore {
+        socket: &socket,
+        src,
+        setpoint: bus.setpoint.unwrap_or(candive::units::PpO2Deci::new(7)),
+    };
+    let mut stats = PowerStats::new();
+    for pulse in 1..=pulses {
+        bus.check_interlock()?;
+        log::info!("Pulse {}/{}", pulse, pulses);
+        run(
+            Some(candive::units::PpO2Deci::new(TEST_SETPOINT)),
+            Duration::from_millis(duration_ms),
+            &mut bus,
+            &mut stats,
+        )?;
+        if pulse < pulses {
+            run(Some(restore.setpoint), PULSE_GAP, &mut bus, &mut stats)?;
+        }
+    }
+    drop(restore);
+    bus.check_interlock()?;
+
+    println!("Solenoid test");
+    println!("  pulses:           {} x {} ms", pulses, duration_ms);
     println!(
-        "  cells:            {} (two, three)",
-        cell_mode_as_str(config.cell_mode)
+        "  current limits:   {} - {} mA",
+        limits.solenoid_current_min_ma, limits.solenoid_current_max_ma
     );
+    match stats.firing_current_range() {
+        Some((low, high)) => println!(
+            "  measured current: {} - {} ({} samples)",
+            low,
+            high,
+            stats.firing_samples()
+        ),
+        None => println!("  measured current: solenoid did not fire"),
+    }
+    if let Some((low, high)) = stats.voltage_range() {
+        println!("  battery:          {} - {}", low, high);
+    }
     println!(
-        "  depth-comp:       {} (on, off)",
-        bool_as_on_off(config.depth_compensation_enabled)
+        "  alerts:           {}",
+        if alerts.is_empty() {
+            "none".to_string()
+        } else {
+            alerts.join(", ")
+        }
+    );
+
+    let mut issues = Vec::new();
+    power::check_power(&limits, Some(&stats), |issue| issues.push(issue));
+    if stats.firing_samples() == 0 {
+        println!();
+        println!("FAIL: no injection seen in SoloStatus");
+    } else if issues.is_empty() && alerts.is_empty() {
+        println!();
+        println!("PASS");
+    } else {
+        println!();
+        println!("FAIL");
+        for issue in issues {
+            println!("  {}", power_issue_as_str(issue));
+        }
+    }
+    Ok(())
+}
+
+fn detect_ambient_pressure(transport_uri: &str) -> CmdResult<u32> {
+    const LISTEN_WINDOW: std::time::Duration = std::time::Duration::from_secs(3);
+
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Err(anyhow!(
+            "Pressure autodetection needs a can:// or socketcand:// transport, use --pressure instead"
+        ));
+    };
+
+    log::info!(
+        "Listening for ambient pressure on {} ({}s)...",
+        interface,
+        LISTEN_WINDOW.as_secs()
+    );
+    let pressure = transport::listen_ambient_pressure(transport_uri, LISTEN_WINDOW)
+        .map_err(|e| anyhow!("Failed to listen for ambient pressure: {}", e))?
+        .ok_or_else(|| anyhow!("No AmbientPressure broadcast seen, use --pressure instead"))?;
+    log::info!("Detected ambient pressure: {}", pressure);
+    Ok(pressure.raw() as u32)
+}
+
+/// Averages CellVoltages broadcasts for `secs` seconds.
+fn sample_cell_voltages(
+    transport_uri: &str,
+    secs: u64,
+) -> CmdResult<candive::cells::CellArray<candive::units::CentiMillivolt>> {
+    log::info!("Sampling cells for {}s...", secs);
+    let mut avg = cellhealth::CellVoltageAverage::new();
+    transport::listen(transport_uri, std::time::Duration::from_secs(secs), |msg| {
+        avg.push(msg);
+    })
+    .map_err(|e| anyhow!("Failed to listen for cell voltages: {}", e))?;
+    avg.average()
+        .ok_or_else(|| anyhow!("No CellVoltages broadcast seen, is the Solo on the bus?"))
+}
+
+fn wait_for_enter(prompt: &str) -> CmdResult {
+    print!("{} Press Enter when the readings are stable. ", prompt);
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut String::new())?;
+    Ok(())
+}
+
+fn cmd_cal_linearity(
+    transport_uri: &str,
+    fo2_points: &[u8],
+    pressure: Option<u32>,
+    sample_secs: u64,
+    tolerance: &cellhealth::LinearityTolerance,
+) -> CmdResult {
+    use candive::units::{Fo2, Millibar};
+    use cellhealth::LinearityPoint;
+
+    if transport::raw_bus_name(transport_uri).is_none() {
+        return Err(anyhow!(
+            "The linearity test reads cell voltages from the bus, it needs a can:// or socketcand:// transport"
+        ));
+    }
+    if fo2_points.len() > cellhealth::MAX_LINEARITY_POINTS {
+        return Err(anyhow!(
+            "At most {} FO₂ points",
+            cellhealth::MAX_LINEARITY_POINTS
+        ));
+    }
+
+    let pressure = match pressure {
+        Some(p) => p,
+        None => detect_ambient_pressure(transport_uri)?,
+    };
+    let pressure = Millibar::new(
+        u16::try_from(pressure).map_err(|_| anyhow!("Pressure {} mbar out of range", pressure))?,
     );
-    println!("  min-current:      {} mA", config.solenoid_current_min_ma);
-    println!("  max-current:      {} mA", config.solenoid_current_max_ma);
+
+    wait_for_enter("Expose the cells to air.")?;
+    let air = LinearityPoint::air(pressure, sample_cell_voltages(transport_uri, sample_secs)?);
+
+    let mut points = Vec::new();
+    for &fo2 in fo2_points {
+        wait_for_enter(&format!("Flush the cells with {}% O₂.", fo2))?;
+        let cell_mv = sample_cell_voltages(transport_uri, sample_secs)?;
+        points.push(LinearityPoint::new(Fo2::new(fo2), pressure, cell_mv));
+    }
+
+    let report = cellhealth::evaluate_linearity(&air, &points, tolerance);
+    use candive::fmt::Permille;
+    let percent = |permille: Option<i32>| match permille {
+        Some(permille) => format!("{:+}", Permille(permille.into())),
+        None => "no air reading".to_string(),
+    };
+
+    println!();
     println!(
-        "  min-voltage:      {:.1} V",
-        config.battery_voltage_min as f32 / 10.0
+        "Linearity at {} (max deviation {}, air {} to {})",
+        pressure,
+        Permille(tolerance.max_deviation_permille.into()),
+        tolerance.air_mv_min,
+        tolerance.air_mv_max
+    );
+    print!("  {:<6}{:<12}", "Cell", "Air");
+    for fo2 in fo2_points {
+        print!("{:<22}", format!("{}% O₂", fo2));
+    }
+    println!("Result");
+    for (i, cell) in report.cells.iter().enumerate() {
+        print!("  {:<6}{:<12}", i + 1, cell.air_mv.to_string());
+        for (point, &deviation) in points.iter().zip(&cell.deviations_permille) {
+            print!(
+                "{:<22}",
+                format!("{} ({})", point.cell_mv[i], percent(deviation))
+            );
+        }
+        let result = match (cell.air_ok, cell.linear) {
+            (true, true) => "pass",
+            (false, true) => "FAIL (air mV)",
+            (true, false) => "FAIL (not linear)",
+            (false, false) => "FAIL (air mV, not linear)",
+        };
+        println!("{}", result);
+    }
+    println!();
+    if report.passed() {
+        println!("All cells passed");
+        Ok(())
+    } else {
+        Err(anyhow!("Linearity test failed"))
+    }
+}
+
+fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn cmd_discover(network: bool, timeout: u64) -> CmdResult {
+    if !network {
+        return Err(anyhow!("Nothing to discover, pass --network"));
+    }
+
+    log::info!("Browsing for DiveCAN gateways ({}s)...", timeout);
+    let gateways = transport::discover_gateways(std::time::Duration::from_secs(timeout))
+        .map_err(|e| anyhow!("mDNS browse failed: {}", e))?;
+    if gateways.is_empty() {
+        println!("No gateways found");
+        return Ok(());
+    }
+    for gateway in gateways {
+        println!("{}  {}", gateway.uri(), gateway.name);
+    }
+    Ok(())
+}
+
+/// `--input` values, or `--transport` when there are none. Live buses get
+/// the usual preflight checks.
+fn capture_inputs(transport_uri: &str, inputs: Vec<String>, what: &str) -> CmdResult<Vec<String>> {
+    let inputs = if inputs.is_empty() {
+        if transport::raw_bus_name(transport_uri).is_none() {
+            return Err(anyhow!(
+                "{} needs a can:// or socketcand:// transport",
+                what
+            ));
+        }
+        vec![transport_uri.to_string()]
+    } else {
+        inputs
+    };
+    for input in &inputs {
+        if transport::raw_bus_name(input).is_some() {
+            hostcheck::transport(input)?;
+        }
+    }
+    Ok(inputs)
+}
+
+#[cfg(feature = "sqlite")]
+fn cmd_record(
+    transport_uri: &str,
+    inputs: Vec<String>,
+    db: PathBuf,
+    mut influx: Option<influx::InfluxSink>,
+    fix_byte_order: bool,
+) -> CmdResult {
+    use candive::divecan::DlcPolicy;
+    use candive::monitor::{EventConfig, EventStream};
+    use candive::record::SqliteRecorder;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let inputs = capture_inputs(transport_uri, inputs, "Recording")?;
+    let recorder = SqliteRecorder::open(&db)?;
+    let mut capture = capture::Capture::open(&inputs, std::time::Duration::from_millis(500))?
+        .fix_byte_order(fix_byte_order);
+    // Sniffing a real bus, keep frames from nodes that drop trailing zeros
+    let config = EventConfig {
+        dlc_policy: DlcPolicy::ZeroPad,
+        ..EventConfig::default()
+    };
+    // Each bus has its own nodes, so its own event state
+    let mut events: Vec<EventStream> = Vec::new();
+    let mut frames = 0u64;
+
+    // Rows are committed in batches, stop cleanly so the last one is kept
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed))?;
+    }
+    let mut flushed = std::time::Instant::now();
+
+    log::info!(
+        "Recording {} to {} (Ctrl-C to stop)",
+        inputs
+            .iter()
+            .map(|i| transport::raw_bus_name(i).unwrap_or(i))
+            .collect::<Vec<_>>()
+            .join(", "),
+        db.display()
+    );
+
+    while !stop.load(Ordering::Relaxed)
+        && let Some(capture::CaptureRead {
+            source,
+            ts_ms: now,
+            read,
+        }) = capture.next()?
+    {
+        while events.len() <= source {
+            events.push(EventStream::new(config));
+        }
+        let iface = capture.tagged().then(|| capture.names()[source].as_str());
+
+        let mut pending = Vec::new();
+        match read {
+            Some(transport::BusRead::Frame(id, frame)) => {
+                recorder.record_frame_on(now, iface, id, &frame)?;
+                if let Some(sink) = influx.as_mut()
+                    && let Ok(decoded) = Msg::try_from_frame_with(&frame, DlcPolicy::ZeroPad)
+                {
+                    sink.send(now, iface, id, &decoded.msg);
+                }
+                events[source].on_frame(now, id, &frame, |e| pending.push(e));
+                frames += 1;
+                if frames.is_multiple_of(1000) {
+                    log::info!("{} frames recorded", frames);
+                }
+            }
+            Some(transport::BusRead::Error(err)) => {
+                events[source].on_bus_error(now, &err, |e| pending.push(e))
+            }
+            Some(transport::BusRead::Other(..)) => {}
+            None => {
+                for stream in &mut events {
+                    stream.expire(now, |e| pending.push(e));
+                }
+            }
+        }
+
+        for event in pending {
+            recorder.record_event(now, &event)?;
+        }
+        // A crash loses at most the last second
+        if flushed.elapsed() >= std::time::Duration::from_secs(1) {
+            recorder.flush()?;
+            flushed = std::time::Instant::now();
+        }
+    }
+    recorder.flush()?;
+    log::info!("{} frames recorded", frames);
+    Ok(())
+}
+
+fn cmd_bridge(transport: &mut Session, listen: &str, dst: u8) -> CmdResult {
+    let listener = std::net::TcpListener::bind(listen)
+        .map_err(|e| anyhow!("Failed to listen on {}: {}", listen, e))?;
+    log::info!(
+        "Bridging UDS on {} to node 0x{:02x} (Ctrl-C to stop)",
+        listener.local_addr()?,
+        dst
+    );
+    bridge::serve(listener, transport)?;
+    Ok(())
+}
+
+/// `monitor --csv` options
+struct CellCsv {
+    path: PathBuf,
+    interval_ms: u64,
+    gnuplot: bool,
+}
+
+/// Where `monitor` writes besides stdout
+struct MonitorSinks {
+    csv: Option<CellCsv>,
+    write: Option<PathBuf>,
+    influx: Option<influx::InfluxSink>,
+}
+
+fn cmd_monitor(
+    transport_uri: &str,
+    inputs: Vec<String>,
+    output: MonitorOutput,
+    units: UnitsPreference,
+    sinks: MonitorSinks,
+    unknown_report: bool,
+    fix_byte_order: bool,
+) -> CmdResult {
+    use candive::coverage::DecodeStats;
+    use candive::divecan::DlcPolicy;
+    use candive::monitor::{EventConfig, EventStream};
+    use candive::uds::isotp::{FlowControl, IsoTpFrame, IsoTpPciType, IsoTpRx, IsoTpRxEvent};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const UDS_KIND: u8 = 0x0A;
+
+    let MonitorSinks {
+        csv,
+        write,
+        mut influx,
+    } = sinks;
+    let inputs = capture_inputs(transport_uri, inputs, "Monitoring")?;
+    let mut capture = capture::Capture::open(&inputs, std::time::Duration::from_millis(500))?
+        .fix_byte_order(fix_byte_order);
+    let config = EventConfig {
+        dlc_policy: DlcPolicy::ZeroPad,
+        ..EventConfig::default()
+    };
+    // Each bus has its own nodes, so its own event state
+    let mut events: Vec<EventStream> = Vec::new();
+    // One reassembler per direction, keyed by (bus, src, dst)
+    let mut isotp: HashMap<(usize, u8, u8), IsoTpRx> = HashMap::new();
+    let jsonl = output == MonitorOutput::Jsonl;
+    let color = !jsonl && std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut stats = DecodeStats::<64>::new();
+
+    // Without a report to print, Ctrl-C keeps its default and just exits
+    let stop = Arc::new(AtomicBool::new(false));
+    if unknown_report {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || stop.store(true, Ordering::Relaxed))?;
+    }
+
+    let mut cells = match &csv {
+        Some(csv) => {
+            let mut file = std::io::LineWriter::new(File::create(&csv.path)?);
+            writeln!(file, "{}", cellcsv::CellSampler::header())?;
+            if csv.gnuplot {
+                let script = csv.path.with_extension("gp");
+                let csv_name = csv.path.file_name().unwrap_or_default().to_string_lossy();
+                std::fs::write(&script, cellcsv::gnuplot_script(&csv_name))?;
+                log::info!("Wrote {}", script.display());
+            }
+            Some((cellcsv::CellSampler::new(csv.interval_ms), file))
+        }
+        None => None,
+    };
+    let mut candump = match &write {
+        Some(path) => Some(std::io::LineWriter::new(
+            File::options().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+
+    if !jsonl {
+        let names: Vec<_> = inputs
+            .iter()
+            .map(|i| transport::raw_bus_name(i).unwrap_or(i))
+            .collect();
+        log::info!("Monitoring {} (Ctrl-C to stop)", names.join(", "));
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        let Some(capture::CaptureRead {
+            source,
+            ts_ms: now,
+            read: received,
+        }) = capture.next()?
+        else {
+            break;
+        };
+        while events.len() <= source {
+            events.push(EventStream::new(config));
+        }
+        let iface = capture.tagged().then(|| capture.names()[source].clone());
+        // Text lines carry the interface after the time, JSON lines as a field
+        let ts = match &iface {
+            Some(iface) if !jsonl => format!("{} {}", iso8601_ms(now), iface),
+            _ => iso8601_ms(now),
+        };
+        let emit = |line: String| match &iface {
+            Some(iface) => println!("{}", jsonl::with_iface(line, iface)),
+            None => println!("{}", line),
+        };
+
+        if let Some((sampler, file)) = cells.as_mut()
+            && let Some(row) = sampler.sample(now)
+        {
+            writeln!(file, "{}", row)?;
+        }
+        if let (Some(file), Some(read)) = (candump.as_mut(), &received) {
+            let line = capture::format_candump_log(now, &capture.names()[source], read);
+            writeln!(file, "{}", line)?;
+        }
+
+        // Error frames only show up as events, a bus-off is reported and
+        // monitoring carries on until the controller restarts
+        let mut pending = Vec::new();
+        let (id, frame) = match received {
+            Some(transport::BusRead::Frame(id, frame)) => (id, frame),
+            Some(transport::BusRead::Other(raw_id, data)) => {
+                if jsonl {
+                    emit(jsonl::other(&ts, raw_id, &data));
+                } else {
+                    println!(
+                        "{} {:08X} (not DiveCAN) [{}]",
+                        ts,
+                        raw_id,
+                        hex::encode_upper(&data)
+                    );
+                }
+                continue;
+            }
+            other => {
+                match other {
+                    Some(transport::BusRead::Error(err)) => {
+                        events[source].on_bus_error(now, &err, |e| pending.push(e))
+                    }
+                    _ => {
+                        for stream in &mut events {
+                            stream.expire(now, |e| pending.push(e));
+                        }
+                    }
+                }
+                for event in pending {
+                    print_monitor_event(&ts, &event, jsonl, units, &emit);
+                }
+                continue;
+            }
+        };
+
+        let msg = stats.decode(id, &frame, DlcPolicy::ZeroPad).map(|d| d.msg);
+        if let (Some((sampler, _)), Ok(msg)) = (cells.as_mut(), &msg) {
+            sampler.push(msg);
+        }
+        if let (Some(sink), Ok(msg)) = (influx.as_mut(), &msg) {
+            sink.send(now, iface.as_deref(), id, msg);
+        }
+        if jsonl {
+            emit(jsonl::frame(
+                &ts,
+                id,
+                &frame,
+                msg.as_ref().map_err(|e| *e),
+                units,
+            ));
+        } else {
+            match &msg {
+                Ok(msg) => {
+                    let (on, off) = match msgformat::category_color(msg.category()) {
+                        c if color && !c.is_empty() => (c, "\x1b[0m"),
+                        _ => ("", ""),
+                    };
+                    let unexpected = if msg.expected_direction().plausible_src(id.src) {
+                        ""
+                    } else {
+                        " (unexpected sender)"
+                    };
+                    println!(
+                        "{} {}{:02x} -> {:02x}: {}{}{}",
+                        ts,
+                        on,
+                        id.src,
+                        id.dst,
+                        msgformat::pretty(msg, units),
+                        off,
+                        unexpected
+                    )
+                }
+                Err(e) => println!(
+                    "{} {:02x} -> {:02x}: kind 0x{:02x} [{}] ({:?})",
+                    ts,
+                    id.src,
+                    id.dst,
+                    id.kind,
+                    hex::encode_upper(frame.bytes()),
+                    e
+                ),
+            }
+        }
+
+        if id.kind == UDS_KIND
+            && let Some(fc) =
+                IsoTpFrame::new(frame.bytes()).and_then(|f| FlowControl::try_from(&f).ok())
+        {
+            if jsonl {
+                emit(jsonl::flow_control(&ts, id, &fc));
+            } else {
+                println!(
+                    "{} {:02x} -> {:02x}: ISO-TP flow control {:?}, BS {}, STmin {} us",
+                    ts,
+                    id.src,
+                    id.dst,
+                    fc.status,
+                    fc.block_size,
+                    fc.st_min_us()
+                );
+            }
+        }
+
+        // Flow control frames steer the sender, there is nothing to reassemble
+        if id.kind == UDS_KIND
+            && let Some(&pci) = frame.bytes().first()
+            && IsoTpPciType::from_u8(pci) != Some(IsoTpPciType::FlowControl)
+        {
+            let rx = isotp.entry((source, id.src, id.dst)).or_insert_with(|| {
+                IsoTpRx::with_timeout(IsoTpRx::DEFAULT_TIMEOUT_MS).with_duplicate_tolerance(true)
+            });
+            match rx.on_frame_at(now, frame.bytes()) {
+                Ok(IsoTpRxEvent::Completed(len)) => {
+                    let payload = &rx.payload()[..len];
+                    if jsonl {
+                        emit(jsonl::isotp(&ts, id, payload));
+                    } else {
+                        println!(
+                            "{} {:02x} -> {:02x}: ISO-TP {} bytes [{}]",
+                            ts,
+                            id.src,
+                            id.dst,
+                            len,
+                            hex::encode_upper(payload)
+                        );
+                    }
+                    rx.reset();
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    rx.reset();
+                    if jsonl {
+                        emit(jsonl::isotp_error(&ts, id, &e));
+                    } else {
+                        println!(
+                            "{} {:02x} -> {:02x}: ISO-TP error {:?}",
+                            ts, id.src, id.dst, e
+                        );
+                    }
+                }
+            }
+        }
+
+        events[source].on_frame(now, id, &frame, |e| pending.push(e));
+        for event in pending {
+            print_monitor_event(&ts, &event, jsonl, units, &emit);
+        }
+    }
+
+    print_decode_report(&stats);
+    Ok(())
+}
+
+fn print_decode_report<const N: usize>(stats: &candive::coverage::DecodeStats<N>) {
+    use candive::coverage::DecodeIssue;
+
+    eprintln!("{} frames decoded", stats.decoded());
+    if stats.is_empty() {
+        eprintln!("No unknown kinds or DLC mismatches");
+        return;
+    }
+    for issue in stats.issues() {
+        let what = match issue.issue {
+            DecodeIssue::UnknownKind => "unknown kind",
+            DecodeIssue::DlcMismatch => "too short, rejected",
+            DecodeIssue::Padded => "too short, zero padded",
+        };
+        let name = candive::divecan::Msg::kind_info(issue.kind).map_or("", |k| k.name);
+        eprintln!(
+            "  0x{:02X} {:<24} {:<22} {:>7}x  e.g. from 0x{:02x} [{}]",
+            issue.kind,
+            name,
+            what,
+            issue.count,
+            issue.src,
+            hex::encode_upper(issue.example())
+        );
+    }
+    if stats.overflow() > 0 {
+        eprintln!("  {} more frames with issues not listed", stats.overflow());
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn cmd_can(transport_uri: &str, action: CanAction) -> CmdResult {
+    use transport::canif;
+
+    let Some(interface) = transport_uri.strip_prefix("can://") else {
+        return Err(anyhow!("CAN interface setup needs a can:// transport"));
+    };
+
+    match action {
+        CanAction::Setup { bitrate, vcan } => {
+            hostcheck::net_admin()?;
+            canif::setup(interface, bitrate, vcan)?;
+            if vcan {
+                println!("{} is up (virtual)", interface);
+            } else {
+                println!("{} is up at {} bit/s", interface, bitrate);
+            }
+        }
+        CanAction::Status => {
+            let status = canif::status(interface)?;
+            println!("{}", interface);
+            println!("  Link:     {}", if status.up { "up" } else { "down" });
+            match status.state {
+                Some(state) => println!("  State:    {:?}", state),
+                None => println!("  State:    -"),
+            }
+            match status.bitrate {
+                Some(bitrate) => println!("  Bitrate:  {} bit/s", bitrate),
+                None => println!("  Bitrate:  - (not set, or a virtual interface)"),
+            }
+            if let Some((tx, rx)) = status.counters {
+                println!("  Errors:   TX {}, RX {}", tx, rx);
+            }
+            if !status.up {
+                println!(
+                    "Bring it up with `solodiag --transport {} can setup`",
+                    transport_uri
+                );
+            }
+        }
+        CanAction::Autodetect { listen } => {
+            hostcheck::net_admin()?;
+            let window = std::time::Duration::from_millis(listen);
+            let previous = canif::status(interface)?.bitrate;
+            // Restores the interface if anything below fails
+            let listening = canif::ListenOnlyProbe::new(interface, previous);
+            let mut probes = Vec::new();
+            for bitrate in canif::COMMON_BITRATES {
+                let berr_reporting = listening.listen(bitrate)?;
+                let bus =
+                    transport::RawBus::open(transport_uri, std::time::Duration::from_millis(50))
+                        .map_err(|e| anyhow!("Failed to open {}: {}", interface, e))?;
+                let mut probe = canif::BitrateProbe::new(bitrate);
+                let start = std::time::Instant::now();
+                while start.elapsed() < window {
+                    // A read error at the wrong rate is just another miss
+                    if let Ok(Some(read)) = bus.read() {
+                        probe.push(&read);
+                    }
+                }
+                if berr_reporting {
+                    println!(
+                        "  {:>9} bit/s: {} DiveCAN, {} other, {} error frames",
+                        bitrate, probe.divecan, probe.other, probe.errors
+                    );
+                } else {
+                    println!(
+                        "  {:>9} bit/s: {} DiveCAN, {} other (driver doesn't report bus errors)",
+                        bitrate, probe.divecan, probe.other
+                    );
+                }
+                probes.push(probe);
+            }
+
+            let best = canif::best_bitrate(&probes).map(|p| p.bitrate);
+            listening.finish(best)?;
+            match best {
+                Some(bitrate) => {
+                    println!("DiveCAN traffic at {} bit/s, {} is up", bitrate, interface);
+                }
+                None => {
+                    let heard = probes.iter().find(|p| p.other > 0);
+                    match heard {
+                        Some(p) => println!(
+                            "No DiveCAN traffic, but other CAN traffic at {} bit/s",
+                            p.bitrate
+                        ),
+                        None => println!(
+                            "No traffic at any bitrate, check wiring and that a device is powered on"
+                        ),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cmd_can(_transport_uri: &str, _action: CanAction) -> CmdResult {
+    Err(anyhow!("CAN interface setup is only available on Linux"))
+}
+
+/// `sim --mode flood` options
+struct SimFlood {
+    rate: u32,
+    mix: Vec<flood::FloodKind>,
+    duration: u64,
+    seed: u64,
+    jitter: u8,
+    tx_dlc: TxDlcPolicy,
+}
+
+fn cmd_sim_flood(transport_uri: &str, src: u8, dst: u8, options: SimFlood) -> CmdResult {
+    use std::time::{Duration, Instant};
+
+    let SimFlood {
+        rate,
+        mix,
+        duration,
+        seed,
+        jitter,
+        tx_dlc,
+    } = options;
+
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Err(anyhow!(
+            "Flooding needs a can:// or socketcand:// transport"
+        ));
+    };
+    hostcheck::transport(transport_uri)?;
+    let bus = transport::RawBus::open(transport_uri, Duration::from_millis(1))
+        .map_err(|e| anyhow!("Failed to open {}: {}", interface, e))?
+        .with_tx_dlc(tx_dlc);
+
+    let mut generator = flood::FloodGen::new(mix, src, dst, seed);
+    let mut counters = flood::FloodCounters::default();
+    let period_us = 1_000_000 / rate;
+    let period = Duration::from_micros(period_us as u64);
+    let end = (duration > 0).then(|| Instant::now() + Duration::from_secs(duration));
+
+    log::info!(
+        "Flooding {} at {} frames/s as 0x{:02X}, --seed {} (Ctrl-C to stop)",
+        interface,
+        rate,
+        src,
+        seed
     );
+
+    let start = Instant::now();
+    let mut next_due = start;
+    let mut last_report = start;
+    let mut burst = Vec::new().into_iter();
+    while end.is_none_or(|end| Instant::now() < end) {
+        // Drain whatever arrived while waiting for the next slot
+        while Instant::now() < next_due {
+            match bus.read() {
+                Ok(Some(transport::BusRead::Frame(id, frame))) => {
+                    counters.on_received(id, &frame, src, dst)
+                }
+                Ok(Some(transport::BusRead::Other(..))) => counters.received += 1,
+                Ok(Some(transport::BusRead::Error(_))) => counters.bus_errors += 1,
+                Ok(None) => {}
+                Err(e) => return Err(anyhow!("CAN read failed: {}", e)),
+            }
+        }
+
+        let (id, frame) = match burst.next() {
+            Some(next) => next,
+            None => {
+                let frames = generator.next_burst();
+                if frames.len() > 1 {
+                    counters.transfers += 1;
+                }
+                burst = frames.into_iter();
+                match burst.next() {
+                    Some(next) => next,
+                    None => break,
+                }
+            }
+        };
+        counters.generated += 1;
+        match bus.send(id, &frame) {
+            Ok(()) => counters.sent += 1,
+            Err(_) => counters.send_errors += 1,
+        }
+        // Catch up after stalls instead of bursting to make up lost slots
+        let delay = Duration::from_micros(generator.frame_delay_us(period_us, jitter) as u64);
+        next_due = (next_due + delay).max(Instant::now() - period);
+
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            last_report = Instant::now();
+            log::info!("{:>6.1}s  {}", start.elapsed().as_secs_f32(), counters);
+        }
+    }
+
+    let secs = start.elapsed().as_secs_f64();
+    println!("{}", counters);
     println!(
-        "  voltage-doubling: {} (on, off)",
-        bool_as_on_off(config.battery_voltage_doubling)
+        "Achieved {:.0} frames/s over {:.1}s",
+        counters.sent as f64 / secs,
+        secs
     );
     Ok(())
 }
 
-fn cmd_config_get(transport: &mut impl UdsTransport, key: ConfigKey) -> CmdResult {
-    let config = transport.rdbi_codec::<ControlConfig>()?;
-    match key {
-        ConfigKey::Cal => println!(
-            "{}",
-            calibration_procedure_as_str(config.calibration_procedure)
-        ),
-        ConfigKey::Ppo2 => println!("{}", ppo2_mode_as_str(config.ppo2_control_mode)),
-        ConfigKey::Cells => println!("{}", cell_mode_as_str(config.cell_mode)),
-        ConfigKey::DepthComp => println!("{}", bool_as_on_off(config.depth_compensation_enabled)),
-        ConfigKey::MinCurrent => println!("{}", config.solenoid_current_min_ma),
-        ConfigKey::MaxCurrent => println!("{}", config.solenoid_current_max_ma),
-        ConfigKey::MinVoltage => println!("{}", config.battery_voltage_min),
-        ConfigKey::VoltageDoubling => {
-            println!("{}", bool_as_on_off(config.battery_voltage_doubling))
-        }
+fn print_monitor_event(
+    ts: &str,
+    event: &candive::monitor::Event,
+    jsonl: bool,
+    units: UnitsPreference,
+    emit: &impl Fn(String),
+) {
+    if jsonl {
+        emit(jsonl::event(ts, event));
+    } else {
+        println!("{} {}", ts, msgformat::event(event, units));
     }
-    Ok(())
 }
 
-fn cmd_config_set(
-    transport: &mut impl UdsTransport,
-    key: ConfigKey,
-    value: &str,
-    des_key: [u8; 8],
+/// Reads every 500 ms, for up to 10 s, while waiting for calibration values
+/// to settle
+const CAL_POLL: candive::poll::PollConfig = candive::poll::PollConfig::new(500, 10_000);
+
+/// Reads the per-cell values with `read`, writes the calibration `request`,
+/// polls until the values change and settle and prints each cell's change.
+/// Fails if the values never changed, didn't settle or a cell moved more than
+/// `tolerance`.
+fn calibrate_and_compare<T: UdsTransport, R: DataIdentifier>(
+    transport: &mut T,
+    request: &R,
+    tolerance: Option<u32>,
+    read: impl Fn(&mut T) -> CmdResult<candive::cells::CellArray<u32>>,
 ) -> CmdResult {
-    let original_config = transport.rdbi_codec::<ControlConfig>()?;
-    let mut config = original_config.clone();
-
-    match key {
-        ConfigKey::Cal => {
-            let v: CalibrationProcedureArg = parse_value_enum(value)?;
-            config.calibration_procedure = v.into();
-        }
-        ConfigKey::Ppo2 => {
-            let v: Ppo2ModeArg = parse_value_enum(value)?;
-            config.ppo2_control_mode = v.into();
-        }
-        ConfigKey::Cells => {
-            let v: CellModeArg = parse_value_enum(value)?;
-            config.cell_mode = v.into();
-        }
-        ConfigKey::DepthComp => {
-            let v: OnOff = parse_value_enum(value)?;
-            config.depth_compensation_enabled = v.into();
-        }
-        ConfigKey::MinCurrent => config.solenoid_current_min_ma = parse_u16(value)?,
-        ConfigKey::MaxCurrent => config.solenoid_current_max_ma = parse_u16(value)?,
-        ConfigKey::MinVoltage => config.battery_voltage_min = parse_u16(value)?,
-        ConfigKey::VoltageDoubling => {
-            let v: OnOff = parse_value_enum(value)?;
-            config.battery_voltage_doubling = v.into();
-        }
+    use candive::poll::{PollError, poll_until};
+    use candive::time::{Delay, StdClock};
+
+    let mut compare = CalibrationCompare::new(read(transport)?);
+    transport.wdbi(R::DID, request.to_bytes().as_ref())?;
+    let clock = StdClock::new();
+    clock.delay_ms(CAL_POLL.interval_ms);
+    // Unsettled values still get compared, the report shows which moved
+    match poll_until(
+        transport,
+        &read,
+        |cells| compare.poll(*cells),
+        &CAL_POLL,
+        &clock,
+    ) {
+        Ok(_) | Err(PollError::Timeout { .. }) => {}
+        Err(PollError::Read(e)) => return Err(e),
     }
 
-    if config == original_config {
-        println!("No changes to current configuration.");
-        return Ok(());
+    let delta = compare.report(tolerance);
+    for cell in 0..delta.before.len() {
+        println!(
+            "  Cell {}: {} -> {} ({:+}) {}",
+            cell,
+            delta.before[cell],
+            delta.after[cell],
+            delta.delta(cell),
+            match (delta.tolerance, delta.cell_passed(cell)) {
+                (None, _) => "",
+                (Some(_), true) => "pass",
+                (Some(_), false) => "FAIL",
+            }
+        );
+    }
+    if !delta.changed {
+        return Err(anyhow!(
+            "No value changed within {} s, the calibration may not have been applied",
+            CAL_POLL.timeout_ms / 1000
+        ));
+    }
+    if !delta.settled {
+        return Err(anyhow!(
+            "Values still changing after {} s",
+            CAL_POLL.timeout_ms / 1000
+        ));
+    }
+    if let (false, Some(tolerance)) = (delta.passed(), tolerance) {
+        return Err(anyhow!(
+            "A cell moved more than the tolerance of {}",
+            tolerance
+        ));
     }
-
-    let device_id_obj = transport.rdbi_codec::<DeviceId>()?;
-    let device_id_data = device_id_obj.device_id;
-
-    let config_bytes = config.to_bytes();
-    let mut data_to_encrypt = Vec::new();
-    data_to_encrypt.extend_from_slice(&config_bytes);
-    data_to_encrypt.extend_from_slice(&device_id_data);
-
-    let cipher = Des::new_from_slice(&des_key).map_err(|_| anyhow!("Invalid DES key"))?;
-    let mut encrypted_data = data_to_encrypt.clone();
-
-    let mut block1 = GenericArray::clone_from_slice(&encrypted_data[0..8]);
-    cipher.encrypt_block(&mut block1);
-    encrypted_data[0..8].copy_from_slice(&block1);
-
-    let mut block2 = GenericArray::clone_from_slice(&encrypted_data[8..16]);
-    cipher.encrypt_block(&mut block2);
-    encrypted_data[8..16].copy_from_slice(&block2);
-
-    transport.wdbi(0x8202, &encrypted_data)?;
-
-    println!("Updated config");
     Ok(())
 }
 
-fn cmd_calibrate_o2_cells(transport: &mut impl UdsTransport, fo2: u32, pressure: u32) -> CmdResult {
+fn cmd_calibrate_o2_cells(
+    transport: &mut impl UdsTransport,
+    fo2: u32,
+    pressure: u32,
+    tolerance: Option<u32>,
+) -> CmdResult {
     let request = match CellCalibrationRequest::try_new(fo2, pressure) {
         Ok(req) => req,
         Err(CalibrationError::O2OutOfRange(value)) => {
             return Err(anyhow!(
-                "O2 percentage {} is out of valid range (70-100%)",
-                value
+                "O2 percentage {} is out of valid range ({}-{}%)",
+                value,
+                calibration::FO2_MIN_PERCENT,
+                calibration::FO2_MAX_PERCENT
             ));
         }
         Err(CalibrationError::PressureOutOfRange(value)) => {
             return Err(anyhow!(
-                "Atmospheric pressure {} mbar is out of valid range (600-1050 mbar)",
-                value
+                "Atmospheric pressure {} mbar is out of valid range ({}-{} mbar)",
+                value,
+                calibration::PRESSURE_MIN_MBAR,
+                calibration::PRESSURE_MAX_MBAR
             ));
         }
     };
 
-    let bytes = request.to_bytes();
-    transport.wdbi(CellCalibrationRequest::DID, &bytes)?;
-
     println!("Cell calibration initiated");
     println!("  FO2: {}%", fo2);
     println!("  Atmospheric Pressure: {} mbar", pressure);
     println!();
-    cmd_cal_show_o2(transport)
+    println!("O2 Calibration Values:");
+    let compared = calibrate_and_compare(transport, &request, tolerance, |t| {
+        Ok(t.rdbi_codec::<CellCalibrationState>()?.o2_calibrations)
+    });
+    println!();
+    cmd_cal_show_o2(transport, &mut std::io::stdout())?;
+    compared
 }
 
-fn cmd_calibrate_zero_offset(transport: &mut impl UdsTransport, adc_value: u32) -> CmdResult {
+fn cmd_calibrate_zero_offset(
+    transport: &mut impl UdsTransport,
+    adc_value: u32,
+    tolerance: u32,
+) -> CmdResult {
     let request = CellZeroOffsetCalibrationRequest {
         expected_adc_value: adc_value,
     };
 
-    let bytes = request.to_bytes();
-    transport.wdbi(CellZeroOffsetCalibrationRequest::DID, &bytes)?;
-
     println!(
         "Cell zero offset calibration initiated with expected ADC value {}",
         adc_value
     );
-    println!();
-
-    cmd_cal_show_zero(transport)
+    println!("Cell Zero Offsets (tolerance {}):", tolerance);
+    calibrate_and_compare(transport, &request, Some(tolerance), |t| {
+        Ok(t.rdbi_codec::<CellZeroOffsets>()?.cells)
+    })
 }
 
-fn cmd_cal_show_o2(transport: &mut impl UdsTransport) -> CmdResult {
+fn cmd_cal_show_o2(transport: &mut impl UdsTransport, out: &mut impl Write) -> CmdResult {
     let cal_state = transport.rdbi_codec::<CellCalibrationState>()?;
-    println!("O2 Calibration State:");
+    writeln!(out, "O2 Calibration State:")?;
     for (i, (&cal_value, &valid)) in cal_state
         .o2_calibrations
         .iter()
@@ -1265,21 +4399,21 @@ fn cmd_cal_show_o2(transport: &mut impl UdsTransport) -> CmdResult {
         .enumerate()
     {
         let valid_str = if valid { "valid" } else { "invalid" };
-        println!("  Cell {}: {} ({})", i, cal_value, valid_str);
+        writeln!(out, "  Cell {}: {} ({})", i, cal_value, valid_str)?;
     }
     Ok(())
 }
 
-fn cmd_cal_show_zero(transport: &mut impl UdsTransport) -> CmdResult {
+fn cmd_cal_show_zero(transport: &mut impl UdsTransport, out: &mut impl Write) -> CmdResult {
     let offsets = transport.rdbi_codec::<CellZeroOffsets>()?;
-    println!("Cell Zero Offsets:");
+    writeln!(out, "Cell Zero Offsets:")?;
     for (i, &offset) in offsets.cells.iter().enumerate() {
-        println!("  Cell {}: {}", i, offset);
+        writeln!(out, "  Cell {}: {}", i, offset)?;
     }
     Ok(())
 }
 
-fn cmd_serial(transport: &mut impl UdsTransport, value: Option<String>) -> CmdResult {
+fn cmd_serial(transport: &mut impl UdsTransport, value: Option<String>, yes: bool) -> CmdResult {
     if let Some(serial_str) = value {
         let serial_str = serial_str.trim().to_uppercase();
 
@@ -1295,7 +4429,19 @@ fn cmd_serial(transport: &mut impl UdsTransport, value: Option<String>) -> CmdRe
                 .map_err(|_| anyhow!("Invalid hex string: {}. Example: A005D007", serial_str))?;
         }
 
-        transport.wdbi(SerialNumber::DID, &serial_bytes)?;
+        let serial = SerialNumber {
+            serial: serial_bytes,
+        };
+        let written = confirm_and_write(
+            transport,
+            "Serial number",
+            &serial,
+            |s| hex::encode_upper(s.serial),
+            yes,
+        )?;
+        if !written {
+            return Ok(());
+        }
 
         println!("Serial number updated");
         println!("  Serial: {}", serial_str);
@@ -1307,6 +4453,59 @@ fn cmd_serial(transport: &mut impl UdsTransport, value: Option<String>) -> CmdRe
     Ok(())
 }
 
+/// Shows the current and the new value of `D`, writes it once the new value
+/// is typed again (or `yes`) and reads it back to check it was stored. For
+/// identity writes that are hard to undo. `show` renders a value the way
+/// it is typed. `false` when it already had that value.
+fn confirm_and_write<D>(
+    transport: &mut impl UdsTransport,
+    label: &str,
+    value: &D,
+    show: impl Fn(&D) -> String,
+    yes: bool,
+) -> CmdResult<bool>
+where
+    D: candive::diag::did::ReadableDid + candive::diag::did::WritableDid + PartialEq,
+{
+    let current = transport.rdbi_codec::<D>()?;
+    let proposed = show(value);
+    println!("{}", label);
+    println!("  Current: {}", show(&current));
+    println!("  New:     {}", proposed);
+    if current == *value {
+        println!("Unchanged, nothing written");
+        return Ok(false);
+    }
+
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            return Err(anyhow!(
+                "{} can't be confirmed without a terminal, pass --yes to write it",
+                label
+            ));
+        }
+        print!("Type the new value again to write it: ");
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case(&proposed) {
+            return Err(anyhow!("{} doesn't match, nothing written", answer.trim()));
+        }
+    }
+
+    transport.wdbi(D::DID, value.to_bytes().as_ref())?;
+    let stored = transport.rdbi_codec::<D>()?;
+    if stored != *value {
+        return Err(anyhow!(
+            "{} reads back as {} after writing {}",
+            label,
+            show(&stored),
+            proposed
+        ));
+    }
+    Ok(true)
+}
+
 fn cmd_cal_vref_set(transport: &mut impl UdsTransport, value: u32) -> CmdResult {
     if value < VoltageCalibration::MIN || value > VoltageCalibration::MAX {
         return Err(anyhow!(
@@ -1327,7 +4526,26 @@ fn cmd_cal_vref_set(transport: &mut impl UdsTransport, value: u32) -> CmdResult
     Ok(())
 }
 
-fn cmd_scan_rdbi(transport: &mut impl UdsTransport) -> CmdResult {
+fn cmd_scan_rdbi(transport: &mut impl UdsTransport, output: Option<PathBuf>) -> CmdResult {
+    let mut file = match output {
+        Some(path) => {
+            let version = transport.rdbi_codec::<FirmwareVersionAscii>()?;
+            let device_id = transport.rdbi_codec::<DeviceId>()?;
+            let mut file = std::io::LineWriter::new(File::create(&path)?);
+            writeln!(
+                file,
+                "{}",
+                jsonl::scan(
+                    &iso8601_ms(unix_time_ms()),
+                    &String::from_utf8_lossy(&version.firmware_version_ascii),
+                    &device_id.to_string()
+                )
+            )?;
+            Some(file)
+        }
+        None => None,
+    };
+
     let range = 0x8000..=0xFFFF;
     println!(
         "Scanning RDBI 0x{:04X} to 0x{:04X}",
@@ -1339,6 +4557,10 @@ fn cmd_scan_rdbi(transport: &mut impl UdsTransport) -> CmdResult {
         match transport.rdbi(x as u16) {
             Ok(data) => {
                 println!("0x{:x} -> {} ", x, hex::encode(&data));
+                if let Some(file) = file.as_mut() {
+                    let ts = iso8601_ms(unix_time_ms());
+                    writeln!(file, "{}", jsonl::scan_did(&ts, x as u16, &data))?;
+                }
             }
             Err(_) => continue,
         }
@@ -1346,75 +4568,715 @@ fn cmd_scan_rdbi(transport: &mut impl UdsTransport) -> CmdResult {
     Ok(())
 }
 
+/// Location of the device registry, `None` when turned off
+#[cfg(feature = "sqlite")]
+fn fleet_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SOLODIAG_FLEET") {
+        return (!path.is_empty()).then(|| PathBuf::from(path));
+    }
+    let data = std::env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".local/share")))?;
+    Some(data.join("solodiag").join("fleet.db"))
+}
+
+/// Updates the registry with the connected device. Best effort, an
+/// unwritable registry is skipped silently.
+#[cfg(feature = "sqlite")]
+fn record_fleet(identity: &DeviceIdentity) {
+    let Some(path) = fleet_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Ok(fleet) = Fleet::open(&path) {
+        let _ = fleet.seen(
+            &String::from_utf8_lossy(&identity.serial.serial_ascii),
+            &identity.device_id.to_string(),
+            &String::from_utf8_lossy(&identity.firmware.firmware_version_ascii),
+            unix_time_ms(),
+        );
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn cmd_fleet(action: FleetAction) -> CmdResult {
+    let path = fleet_path().ok_or_else(|| anyhow!("Device registry is turned off"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let fleet = Fleet::open(&path)?;
+    match action {
+        FleetAction::List => {
+            let devices = fleet.list()?;
+            if devices.is_empty() {
+                println!("No devices seen yet");
+            }
+            for d in devices {
+                println!(
+                    "{}  fw {:<5} id {}  last seen {}",
+                    d.serial,
+                    d.firmware,
+                    d.device_id,
+                    iso8601_ms(d.last_seen_ms)
+                );
+                if !d.note.is_empty() {
+                    println!("    {}", d.note);
+                }
+            }
+        }
+        FleetAction::Note { serial, note } => {
+            if !fleet.set_note(&serial, &note)? {
+                return Err(anyhow!("Device {} not in {}", serial, path.display()));
+            }
+            println!("Note for {} saved", serial);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_key(action: KeyAction, cipher: CipherKind) -> CmdResult {
+    let path =
+        keys::KeyStore::path().ok_or_else(|| anyhow!("No home directory, set SOLODIAG_KEYS"))?;
+    let mut store = keys::KeyStore::load(&path)?;
+    match action {
+        KeyAction::Assign { pattern } => {
+            let text = match std::env::var("SOLO_KEY") {
+                Ok(text) => text,
+                Err(_) => {
+                    if std::io::stdin().is_terminal() {
+                        eprint!("Key for {} ({:?}, hex): ", pattern, cipher);
+                    }
+                    let mut line = String::new();
+                    std::io::stdin().read_line(&mut line)?;
+                    line
+                }
+            };
+            let key = keys::parse_key(cipher, &text)?;
+            store.assign(keys::StoredKey {
+                pattern: pattern.clone(),
+                cipher,
+                key,
+            });
+            store.save(&path)?;
+            println!("Key for {} saved to {}", pattern, path.display());
+        }
+        KeyAction::List => {
+            if store.is_empty() {
+                println!("No keys stored in {}", path.display());
+            }
+            for k in store.keys() {
+                println!("{:<24} {}", k.pattern, keys::cipher_name(k.cipher));
+            }
+        }
+        KeyAction::Remove { pattern } => {
+            if !store.remove(&pattern) {
+                return Err(anyhow!("No key for {} in {}", pattern, path.display()));
+            }
+            store.save(&path)?;
+            println!("Key for {} removed", pattern);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_dev_fuzz_device(
+    session: &mut Session,
+    transport_uri: &str,
+    dst: u8,
+    yes: bool,
+) -> CmdResult {
+    use std::time::Duration;
+
+    let Some(interface) = transport::raw_bus_name(transport_uri) else {
+        return Err(anyhow!("Fuzzing needs a can:// or socketcand:// transport"));
+    };
+    let bus = transport::RawBus::open(transport_uri, Duration::from_millis(20))
+        .map_err(|e| anyhow!("Failed to open {}: {}", interface, e))?;
+
+    log::info!("Checking bus state on {}...", interface);
+    let mut state = BusState::default();
+    let mut announces_id = false;
+    watch_bus(&bus, Duration::from_secs(2), &mut state, |id, msg| {
+        announces_id |= id.src == dst && matches!(msg, Msg::Id { .. });
+    })?;
+    state.check_interlock()?;
+    if announces_id {
+        log::warn!(
+            "Node 0x{:02X} announces its Id without rebooting, resets only show as missing answers",
+            dst
+        );
+    }
+
+    if !yes
+        && !confirm(&format!(
+            "Send {} invalid requests to node 0x{:02X}? A device bug can reboot or hang it",
+            fuzz::cases().len(),
+            dst
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    if fuzz::run(session, &bus, dst, !announces_id)? {
+        Ok(())
+    } else {
+        Err(anyhow!("Device failed negative tests"))
+    }
+}
+
+fn cmd_dev_emulate_did(did: u16, hexfile: &Path) -> CmdResult {
+    let text = if hexfile == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(hexfile)
+            .map_err(|e| anyhow!("Failed to read {}: {}", hexfile.display(), e))?
+    };
+    let hex: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let data = hex::decode(&hex).map_err(|e| anyhow!("{}: not hex ({})", hexfile.display(), e))?;
+
+    let def = candive::protocol::did_def(did);
+    println!(
+        "0x{:04X} {}, {} bytes{}",
+        did,
+        def.map_or("(not in the protocol description)", |d| d.name),
+        data.len(),
+        def.map_or(String::new(), |d| format!(" (expected {})", d.len))
+    );
+    if let Some(decoded) = AnyDid::decode(did, &data) {
+        let decoded = decoded.map_err(|e| anyhow!("Decode failed: {:?}", e))?;
+        println!("{:#?}", decoded);
+    } else if let Ok(setting) = UserSettingDid::try_from(did) {
+        let decoded =
+            ReadPayload::decode(setting, &data).map_err(|e| anyhow!("Decode failed: {:?}", e))?;
+        println!("{:?}", setting);
+        println!("{:#?}", decoded);
+    } else {
+        return Err(anyhow!("No decoder for DID 0x{:04X}", did));
+    }
+    Ok(())
+}
+
+fn cmd_dev_bench(session: Session, transport_uri: &str, did: u16, count: u32) -> CmdResult {
+    use candive::time::StdClock;
+    use candive::uds::measure::MeasuringTransport;
+
+    let mut measured = MeasuringTransport::new(session, StdClock::new());
+    measured.rdbi(did)?;
+    measured.reset();
+    let mut failed = None;
+    for _ in 0..count {
+        if let Err(e) = measured.rdbi(did) {
+            failed = Some(e);
+            break;
+        }
+    }
+
+    let stats = measured.report();
+    println!(
+        "{}, DID 0x{:04X}, {} requests",
+        transport_uri, did, stats.requests
+    );
+    let ms = |v: Option<u64>| v.map_or("-".to_string(), |v| format!("{} ms", v));
+    let bucket = |v: Option<Option<u64>>| match v {
+        Some(Some(limit)) => format!("< {} ms", limit),
+        Some(None) => "open-ended".to_string(),
+        None => "-".to_string(),
+    };
+    println!(
+        "  latency  min {}, mean {}, max {}",
+        ms(stats.min_ms),
+        ms(stats.mean_ms()),
+        ms(stats.max_ms)
+    );
+    println!(
+        "  p50 {}, p90 {}, p99 {}",
+        bucket(stats.latency.percentile(50)),
+        bucket(stats.latency.percentile(90)),
+        bucket(stats.latency.percentile(99))
+    );
+    println!(
+        "  {} bytes sent, {} received, {} errors",
+        stats.bytes_sent, stats.bytes_received, stats.errors
+    );
+    let mut lower = 0;
+    for (limit, n) in stats.latency.buckets() {
+        if n > 0 {
+            match limit {
+                Some(limit) => println!("  {:>6} - {:<6} ms {:>6}", lower, limit, n),
+                None => println!("  {:>6}+          ms {:>6}", lower, n),
+            }
+        }
+        lower = limit.unwrap_or(lower);
+    }
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn cmd_dev_did_diff(old: &Path, new: &Path) -> CmdResult {
+    use didscan::{DidChange, DidScan};
+
+    let load = |path: &Path| -> Result<DidScan> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        DidScan::parse(&text).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    };
+    let (old_scan, new_scan) = (load(old)?, load(new)?);
+
+    for (path, scan) in [(old, &old_scan), (new, &new_scan)] {
+        println!(
+            "{}: firmware {}, device {}, {} DIDs",
+            path.display(),
+            scan.firmware,
+            scan.device_id,
+            scan.dids.len()
+        );
+    }
+    if old_scan.device_id != new_scan.device_id {
+        println!("Note: scans are from different devices");
+    }
+
+    let changes = didscan::diff(&old_scan, &new_scan);
+    let (mut appeared, mut disappeared, mut resized, mut changed) = (0, 0, 0, 0);
+    println!();
+    for change in &changes {
+        let what = match *change {
+            DidChange::Appeared { len, .. } => {
+                appeared += 1;
+                format!("appeared     {} bytes", len)
+            }
+            DidChange::Disappeared { len, .. } => {
+                disappeared += 1;
+                format!("disappeared  {} bytes", len)
+            }
+            DidChange::Resized { from, to, .. } => {
+                resized += 1;
+                format!("resized      {} -> {} bytes", from, to)
+            }
+            DidChange::Changed { len, .. } => {
+                changed += 1;
+                format!("changed      {} bytes", len)
+            }
+        };
+        let name = candive::protocol::did_def(change.did()).map_or("", |d| d.name);
+        let line = format!("  0x{:04X}  {:<28} {}", change.did(), what, name);
+        println!("{}", line.trim_end());
+    }
+    let unchanged = old_scan
+        .dids
+        .keys()
+        .filter(|did| new_scan.dids.contains_key(did))
+        .count()
+        - resized
+        - changed;
+    println!(
+        "{}{} appeared, {} disappeared, {} resized, {} changed, {} unchanged",
+        if changes.is_empty() { "" } else { "\n" },
+        appeared,
+        disappeared,
+        resized,
+        changed,
+        unchanged
+    );
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Subcommand)]
+enum FleetAction {
+    /// List known devices, most recently seen first
+    List,
+    /// Set the note of a device, an empty note clears it
+    Note { serial: String, note: String },
+}
+
+#[derive(Subcommand)]
+enum KeyAction {
+    /// Store a key for devices matching <pattern>, read from SOLO_KEY or stdin
+    Assign {
+        /// Serial number or device ID pattern, e.g. A005* or 50FF6806*
+        pattern: String,
+    },
+    /// List the patterns and their ciphers, not the keys
+    List,
+    /// Forget the key assigned to <pattern>
+    Remove { pattern: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ProtocolFormat {
+    /// DBC database
+    Dbc,
+    /// Wireshark Lua dissector
+    Wireshark,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SimMode {
+    /// Send the message mix as fast as --rate allows
+    Flood,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum MonitorOutput {
+    Text,
+    Jsonl,
+}
+
+/// A connected device and the key for its logs
+struct Connected {
+    session: Session,
+    solo_key: CmdResult<SoloKey>,
+}
+
+/// Connects to the device and picks the key for its logs, SOLO_KEY or the
+/// one stored for it
+fn connect_device(
+    config: &TransportConfig,
+    cipher: CipherKind,
+    skip_key_check: bool,
+) -> CmdResult<Connected> {
+    let session = connect(config)?;
+    let keys = match (std::env::var_os("SOLO_KEY"), keys::KeyStore::path()) {
+        (None, Some(path)) => keys::KeyStore::load(&path)?,
+        _ => keys::KeyStore::default(),
+    };
+    #[cfg(feature = "sqlite")]
+    if let Some(identity) = session.identity() {
+        record_fleet(identity);
+    }
+
+    let solo_key = get_solo_key(cipher, &keys, session.identity())
+        .map(|key| if skip_key_check { key.unchecked() } else { key });
+    Ok(Connected { session, solo_key })
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    logger::init(cli.quiet);
+    i18n::set(i18n::Catalog::load(&cli.lang)?);
 
-    let mut session = parse_transport_uri(&cli.transport, cli.src, cli.dst)?;
-
-    let des_key = get_solo_key();
+    let dst = match cli.command {
+        Commands::RemoteMenu { dst } => dst,
+        _ => cli.dst,
+    };
+    let config = TransportConfig {
+        uri: cli.transport.clone(),
+        src: cli.src,
+        dst,
+        request_timeout: cli.request_timeout.map(std::time::Duration::from_millis),
+    };
+    // Only commands talking UDS connect, bus-level and file commands don't
+    // need a session
+    let device = || connect_device(&config, cli.cipher, cli.skip_key_check);
 
     match cli.command {
+        #[cfg(feature = "sqlite")]
+        Commands::Record {
+            db,
+            inputs,
+            influx,
+            fix_byte_order,
+        } => {
+            let influx = influx
+                .as_deref()
+                .map(influx::InfluxSink::open)
+                .transpose()?;
+            cmd_record(&cli.transport, inputs, db, influx, fix_byte_order)
+        }
+        Commands::Monitor {
+            output,
+            csv,
+            interval,
+            gnuplot,
+            unknown_report,
+            inputs,
+            write,
+            influx,
+            fix_byte_order,
+        } => {
+            let sinks = MonitorSinks {
+                csv: csv.map(|path| CellCsv {
+                    path,
+                    interval_ms: interval,
+                    gnuplot,
+                }),
+                write,
+                influx: influx
+                    .as_deref()
+                    .map(influx::InfluxSink::open)
+                    .transpose()?,
+            };
+            cmd_monitor(
+                &cli.transport,
+                inputs,
+                output,
+                cli.units,
+                sinks,
+                unknown_report,
+                fix_byte_order,
+            )
+        }
+        Commands::Discover { network, timeout } => cmd_discover(network, timeout),
+        Commands::SupportBundle { output, last } => {
+            support::create(&output, &config, device(), last)
+        }
+        Commands::Can { action } => cmd_can(&cli.transport, action),
+        #[cfg(feature = "sqlite")]
+        Commands::Fleet { action } => cmd_fleet(action),
+        Commands::Key { action } => cmd_key(action, cli.cipher),
+        Commands::Protocol { format } => {
+            print!(
+                "{}",
+                match format {
+                    ProtocolFormat::Dbc => candive::protocol::DBC,
+                    ProtocolFormat::Wireshark => candive::protocol::WIRESHARK_DISSECTOR,
+                }
+            );
+            Ok(())
+        }
+        Commands::Sim {
+            mode: SimMode::Flood,
+            rate,
+            mix,
+            duration,
+            seed,
+            jitter,
+        } => {
+            let flood = SimFlood {
+                rate,
+                mix,
+                duration,
+                seed: seed.unwrap_or_else(unix_time_ms),
+                jitter,
+                tx_dlc: cli.tx_dlc,
+            };
+            cmd_sim_flood(&cli.transport, cli.src, cli.dst, flood)
+        }
         Commands::Logs { action } => match action {
             LogsAction::Export {
                 filename,
                 count,
                 skip,
-            } => cmd_logs_export(&mut session, filename, count, skip, des_key.ok()),
+                since,
+                last,
+                split_per_dive,
+            } => {
+                let mut device = device()?;
+                cmd_logs_export(
+                    &mut device.session,
+                    filename,
+                    count,
+                    skip,
+                    window_start(since, last),
+                    split_per_dive,
+                    device.solo_key.ok().as_ref(),
+                )
+            }
             LogsAction::Dump {
                 count,
                 skip,
+                since,
+                last,
                 candump,
-            } => cmd_logs_dump(&mut session, count, skip, candump, des_key?),
-            LogsAction::Info => cmd_logs_info(),
+            } => {
+                let mut device = device()?;
+                cmd_logs_dump(
+                    &mut Link {
+                        session: &mut device.session,
+                        config: &config,
+                    },
+                    count,
+                    skip,
+                    window_start(since, last),
+                    &if candump {
+                        DumpFormat::Candump
+                    } else {
+                        DumpFormat::Pretty(cli.units)
+                    },
+                    &log_chunking(cli.chunk_entries, cli.max_memory),
+                    device.solo_key.ok().as_ref(),
+                )
+            }
+            LogsAction::Info => cmd_logs_info(&mut device()?.session, &mut std::io::stdout()),
+            LogsAction::Anonymize { input, output } => {
+                cmd_logs_anonymize(&input, &output, cli.max_memory)
+            }
+            LogsAction::Verify { file, digest } => cmd_logs_verify(&file, digest, cli.max_memory),
+        },
+        Commands::Alerts { action } => match action {
+            AlertsAction::List { since, last } => {
+                let mut device = device()?;
+                cmd_alerts_list(
+                    &mut device.session,
+                    window_start(since, last),
+                    &log_chunking(cli.chunk_entries, cli.max_memory),
+                    &device.solo_key?,
+                )
+            }
+            AlertsAction::Raise {
+                alert,
+                from,
+                duration,
+                yes,
+            } => cmd_alerts_raise(&cli.transport, alert, from, duration, yes, cli.tx_dlc),
         },
-        Commands::Mem { filename } => cmd_mem_dump(&mut session, filename),
+        Commands::Mem { filename } => cmd_mem_dump(&mut device()?.session, filename),
         Commands::User { action } => match action {
-            UserConfigAction::List => cmd_userconfig_list(&mut session),
-            UserConfigAction::Get { name } => cmd_userconfig_get(&mut session, name),
-            UserConfigAction::Set { name, value } => cmd_userconfig_set(&mut session, name, value),
+            UserConfigAction::List => {
+                cmd_userconfig_list(&mut device()?.session, &mut std::io::stdout())
+            }
+            UserConfigAction::Get { name } => cmd_userconfig_get(&mut device()?.session, name),
+            UserConfigAction::Set {
+                name,
+                value,
+                confirm,
+            } => cmd_userconfig_set(&mut device()?.session, name, value, confirm),
+        },
+        Commands::RdbiScan { output } => cmd_scan_rdbi(&mut device()?.session, output),
+        Commands::RemoteMenu { .. } => cmd_remote_menu(&mut device()?.session),
+        Commands::Dev { action } => match action {
+            DevAction::DidDiff { old, new } => cmd_dev_did_diff(&old, &new),
+            DevAction::EmulateDid { did, hexfile } => cmd_dev_emulate_did(did, &hexfile),
+            DevAction::FuzzDevice { yes } => {
+                hostcheck::transport(&cli.transport)?;
+                cmd_dev_fuzz_device(&mut device()?.session, &cli.transport, dst, yes)
+            }
+            DevAction::Bench { did, count } => {
+                cmd_dev_bench(device()?.session, &cli.transport, did, count)
+            }
+        },
+        Commands::Power => cmd_power(&mut device()?.session, &cli.transport),
+        Commands::Bridge { listen } => cmd_bridge(&mut device()?.session, &listen, cli.dst),
+        #[cfg(feature = "scripting")]
+        Commands::Script {
+            action: ScriptAction::Run { file },
+        } => script::run(
+            &file,
+            device()?.session,
+            script::ScriptBus {
+                transport_uri: cli.transport.clone(),
+                src: cli.src,
+                dst: cli.dst,
+                tx_dlc: cli.tx_dlc,
+            },
+        ),
+        Commands::Solenoid { action } => match action {
+            SolenoidAction::Test {
+                pulses,
+                duration,
+                yes,
+            } => cmd_solenoid_test(
+                &mut device()?.session,
+                &cli.transport,
+                cli.src,
+                pulses,
+                duration,
+                yes,
+                cli.tx_dlc,
+            ),
         },
-        Commands::RdbiScan => cmd_scan_rdbi(&mut session),
         Commands::Fw { action } => match action {
-            FwAction::Upload { firmware_file } => cmd_fw_upload(&mut session, firmware_file),
-            FwAction::Info => cmd_fw_info(&mut session),
+            FwAction::Upload {
+                firmware_file,
+                force,
+                max_ripple,
+            } => cmd_fw_upload(
+                &mut Link {
+                    session: &mut device()?.session,
+                    config: &config,
+                },
+                firmware_file,
+                &ProgrammingMargins {
+                    ripple_max_dv: (max_ripple * 10.0).round() as u16,
+                    ..ProgrammingMargins::DEFAULT
+                },
+                force,
+            ),
+            FwAction::Info { manifest } => {
+                cmd_fw_info(&mut device()?.session, manifest, &mut std::io::stdout())
+            }
         },
         Commands::Device { action } => match action {
-            DeviceAction::Show => cmd_device_info(&mut session),
-            DeviceAction::Serial { value } => cmd_serial(&mut session, value),
+            DeviceAction::Show { verbose } => {
+                cmd_device_info(&mut device()?.session, verbose, &mut std::io::stdout())
+            }
+            DeviceAction::Serial { value, yes } => cmd_serial(&mut device()?.session, value, yes),
         },
         Commands::Config { action } => match action {
-            ConfigAction::List => cmd_config_list(&mut session),
-            ConfigAction::Get { key } => cmd_config_get(&mut session, key),
-            ConfigAction::Set { key, value } => cmd_config_set(&mut session, key, &value, des_key?),
+            ConfigAction::List => cmd_config_list(&mut device()?.session, &mut std::io::stdout()),
+            ConfigAction::Get { key } => cmd_config_get(&mut device()?.session, key),
+            ConfigAction::Set {
+                key,
+                value,
+                confirm,
+            } => {
+                let mut device = device()?;
+                cmd_config_set(&mut device.session, key, &value, confirm, &device.solo_key?)
+            }
+            ConfigAction::VerifyDepthComp => {
+                let mut device = device()?;
+                cmd_config_verify_depth_comp(
+                    &mut device.session,
+                    &cli.transport,
+                    cli.units,
+                    device.solo_key.ok().as_ref(),
+                )
+            }
         },
         Commands::Cal { action } => match action {
-            CalAction::O2 { fo2, pressure } => cmd_calibrate_o2_cells(&mut session, fo2, pressure),
-            CalAction::Zero { adc_value } => cmd_calibrate_zero_offset(&mut session, adc_value),
-            CalAction::Vref { value } => cmd_cal_vref_set(&mut session, value),
+            CalAction::O2 {
+                fo2,
+                pressure,
+                tolerance,
+            } => {
+                let pressure = match pressure {
+                    Some(p) => p,
+                    None => detect_ambient_pressure(&cli.transport)?,
+                };
+                cmd_calibrate_o2_cells(&mut device()?.session, fo2, pressure, tolerance)
+            }
+            CalAction::Zero {
+                adc_value,
+                tolerance,
+            } => cmd_calibrate_zero_offset(&mut device()?.session, adc_value, tolerance),
+            CalAction::Vref { value } => cmd_cal_vref_set(&mut device()?.session, value),
             CalAction::Show { item } => match item {
-                CalShowAction::O2 => cmd_cal_show_o2(&mut session),
-                CalShowAction::Zero => cmd_cal_show_zero(&mut session),
+                CalShowAction::O2 => {
+                    cmd_cal_show_o2(&mut device()?.session, &mut std::io::stdout())
+                }
+                CalShowAction::Zero => {
+                    cmd_cal_show_zero(&mut device()?.session, &mut std::io::stdout())
+                }
             },
+            CalAction::Linearity {
+                fo2,
+                pressure,
+                sample,
+                max_deviation,
+                air_mv_min,
+                air_mv_max,
+            } => {
+                let tolerance = cellhealth::LinearityTolerance {
+                    max_deviation_permille: (max_deviation * 10.0).round() as u32,
+                    air_mv_min: candive::units::CentiMillivolt::new(
+                        (air_mv_min * 100.0).round() as u16
+                    ),
+                    air_mv_max: candive::units::CentiMillivolt::new(
+                        (air_mv_max * 100.0).round() as u16
+                    ),
+                };
+                cmd_cal_linearity(&cli.transport, &fo2, pressure, sample, &tolerance)
+            }
         },
     }
 }
 
-pub struct Encryptor(Des);
-
-impl Encryptor {
-    pub fn new(key: [u8; 8]) -> Self {
-        Self(Des::new_from_slice(&key).expect("DES key length must be 8"))
-    }
-}
-
-impl DesEncryptor for Encryptor {
-    fn encrypt_block(&self, block: &mut [u8; 8]) {
-        let mut ga = GenericArray::clone_from_slice(block);
-        self.0.encrypt_block(&mut ga);
-        block.copy_from_slice(&ga);
-    }
-}
-
 pub fn decrypt<R: Read, W: Write>(
     decryptor: &mut LogDecryptor,
     reader: &mut R,
@@ -1,244 +1,358 @@
+use candive::cells::CellArray;
+use candive::fmt::{DisplayUnits, UnitsPreference};
+use candive::units::{
+    CentiMillivolt, Decibar, Decimeter, Decivolt, Fo2, Millibar, Millivolt, PpO2Deci,
+};
 use candive::{alerts::*, divecan::*};
 
-pub fn pretty(msg: &Msg) -> String {
-    fn ascii_lossy(bytes: &[u8]) -> String {
-        bytes
+use crate::i18n::tr;
+
+/// ANSI color for frames of `category` in terminal output
+pub fn category_color(category: MsgCategory) -> &'static str {
+    match category {
+        MsgCategory::Identity => "\x1b[36m",
+        MsgCategory::Sensor => "",
+        MsgCategory::Control => "\x1b[33m",
+        MsgCategory::Calibration => "\x1b[35m",
+        MsgCategory::Diagnostic => "\x1b[34m",
+        MsgCategory::Undocumented => "\x1b[2m",
+    }
+}
+
+pub fn voltage_alert_text(v: Option<VoltageAlert>) -> String {
+    match v {
+        None => String::new(),
+        Some(v) => tr(&format!("voltage_alert.{v:?}"), &[]),
+    }
+}
+
+pub fn current_alert_text(v: Option<CurrentAlert>) -> String {
+    match v {
+        None => String::new(),
+        Some(v) => tr(&format!("current_alert.{v:?}"), &[]),
+    }
+}
+
+fn ascii_lossy(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x20..=0x7E => b as char,
+            _ => '.',
+        })
+        .collect()
+}
+
+fn consensus_text(c: Consensus) -> String {
+    match c {
+        Consensus::NotCalibrated => tr("consensus.NotCalibrated", &[]),
+        Consensus::NoActiveCells => tr("consensus.NoActiveCells", &[]),
+        Consensus::PpO2(v) => format!("{v}"),
+    }
+}
+
+fn dive_state_text(s: DiveState) -> String {
+    match s {
+        DiveState::Unknown(v) => tr("dive_state.Unknown", &[("value", &format!("{v:02X}"))]),
+        s => tr(&format!("dive_state.{s:?}"), &[]),
+    }
+}
+
+fn enabled_text(enabled: bool) -> String {
+    tr(
+        if enabled {
+            "value.enabled"
+        } else {
+            "value.disabled"
+        },
+        &[],
+    )
+}
+
+/// Catalog key of an alert's label, `alert.` and its name
+pub fn alert_key(alert: AnyAlert) -> String {
+    match alert {
+        AnyAlert::Handset(a) => format!("alert.{a:?}"),
+        AnyAlert::Solo(a) => format!("alert.{a:?}"),
+        AnyAlert::Temp(a) => format!("alert.{a:?}"),
+    }
+}
+
+pub fn alert_label(code: u16) -> String {
+    match AnyAlert::from_u16(code) {
+        Some(alert) => tr(&alert_key(alert), &[]),
+        None => tr("alert.unknown", &[("code", &format!("{code:04X}"))]),
+    }
+}
+
+/// Renders `msg` as one human-readable line.
+pub fn pretty(msg: &Msg, units: UnitsPreference) -> String {
+    msg.accept(&mut Pretty { units })
+}
+
+/// Renders a monitor event as one line. A calibration is summarized from
+/// request to result, the rest print as they are.
+pub fn event(event: &candive::monitor::Event, units: UnitsPreference) -> String {
+    use candive::monitor::Event;
+
+    let Event::CalibrationCompleted {
+        src,
+        requested_by,
+        elapsed_ms,
+        status,
+        fo2,
+        pressure,
+        cell_voltages,
+        cells_active,
+    } = *event
+    else {
+        return format!("event: {:?}", event);
+    };
+    tr(
+        "event.CalibrationCompleted",
+        &[
+            ("src", &format!("{src:02x}")),
+            (
+                "by",
+                &requested_by.map_or_else(|| "?".to_string(), |by| format!("{by:02x}")),
+            ),
+            (
+                "elapsed",
+                &elapsed_ms.map_or_else(
+                    || "?".to_string(),
+                    |ms| format!("{:.1} s", ms as f64 / 1000.0),
+                ),
+            ),
+            ("status", &format!("{status:?}")),
+            ("cell1", &cell_voltages[0]),
+            ("cell2", &cell_voltages[1]),
+            ("cell3", &cell_voltages[2]),
+            ("fo2", &fo2),
+            ("pressure", &pressure.in_units(units)),
+            ("active", &format!("{:?}", cells_active.as_array())),
+        ],
+    )
+}
+
+/// Implements [`MsgVisitor`], so a new `Msg` variant doesn't build until it has a rendering
+struct Pretty {
+    units: UnitsPreference,
+}
+
+impl MsgVisitor for Pretty {
+    type Output = String;
+
+    fn id(&mut self, manufacturer: u8, _unused: u8, version: u8) -> String {
+        tr(
+            "msg.Id",
+            &[
+                ("manufacturer", &format!("{manufacturer:02X}")),
+                ("version", &format!("{version:02X}")),
+            ],
+        )
+    }
+
+    fn device_name(&mut self, name: [u8; 8]) -> String {
+        tr("msg.DeviceName", &[("name", &ascii_lossy(&name))])
+    }
+
+    fn alert(&mut self, alert: Alert) -> String {
+        tr("msg.Alert", &[("label", &alert_label(alert.code))])
+    }
+
+    fn shutdown_init(&mut self, reason: ShutdownReason) -> String {
+        tr("msg.ShutdownInit", &[("reason", &format!("{reason:?}"))])
+    }
+
+    fn cell_ppo2(&mut self, cells: CellArray<PpO2Deci>) -> String {
+        tr(
+            "msg.CellPpo2",
+            &[
+                ("cell1", &cells[0]),
+                ("cell2", &cells[1]),
+                ("cell3", &cells[2]),
+            ],
+        )
+    }
+
+    fn oboe_status(&mut self, battery_ok: bool, battery_voltage: Decivolt, _: [u8; 3]) -> String {
+        let battery = tr(
+            if battery_ok {
+                "value.ok"
+            } else {
+                "value.not_ok"
+            },
+            &[],
+        );
+        tr(
+            "msg.OboeStatus",
+            &[("battery", &battery), ("voltage", &battery_voltage)],
+        )
+    }
+
+    fn ambient_pressure(
+        &mut self,
+        surface: Millibar,
+        current: Millibar,
+        depth_comp: bool,
+    ) -> String {
+        tr(
+            "msg.AmbientPressure",
+            &[
+                ("surface", &surface.in_units(self.units)),
+                ("current", &current.in_units(self.units)),
+                (
+                    "depth",
+                    &Decimeter::from_pressures(surface, current).in_units(self.units),
+                ),
+                ("depth_comp", &enabled_text(depth_comp)),
+            ],
+        )
+    }
+
+    fn uds(&mut self, dlc: u8, data: [u8; 8]) -> String {
+        let data = data[..(dlc as usize).min(8)]
             .iter()
-            .map(|&b| match b {
-                0x20..=0x7E => b as char,
-                _ => '.',
-            })
-            .collect()
-    }
-
-    fn consensus_text(c: Consensus) -> String {
-        match c {
-            Consensus::NotCalibrated => "not calibrated".into(),
-            Consensus::NoActiveCells => "no active cells".into(),
-            Consensus::PpO2(v) => format!("{v}"),
-        }
-    }
-
-    fn voltage_alert_text(v: Option<VoltageAlert>) -> &'static str {
-        match v {
-            None => "",
-            Some(VoltageAlert::UnderVoltage) => "battery undervoltage",
-            Some(VoltageAlert::Clear) => "battery: clear",
-            Some(VoltageAlert::OverVoltage) => "battery: overvoltage",
-        }
-    }
-
-    fn current_alert_text(v: Option<CurrentAlert>) -> &'static str {
-        match v {
-            None => "",
-            Some(CurrentAlert::UnderCurrent) => "solenoid: undercurrent",
-            Some(CurrentAlert::Clear) => "solenoid: clear",
-            Some(CurrentAlert::OverCurrent) => "solenoid: overcurrent",
-        }
-    }
-
-    fn alert_label(code: u16) -> String {
-        if let Some(a) = HandsetAlert::from_u16(code) {
-            return match a {
-                HandsetAlert::ShutdownWhileBluetooth => "shutdown while Bluetooth active".into(),
-                HandsetAlert::ShutdownWhileDiving => "shutdown while diving".into(),
-                HandsetAlert::ShutdownWhileFwUpgrade => "shutdown during firmware upgrade".into(),
-                HandsetAlert::ShutdownWhileUnknown => "shutdown for unknown reason".into(),
-            };
-        }
-        if let Some(a) = TempAlert::from_u16(code) {
-            return match a {
-                TempAlert::TempProbeFailed => "temperature probe failure".into(),
-            };
-        }
-        if let Some(a) = SoloAlert::from_u16(code) {
-            return match a {
-                SoloAlert::SoloCellStatusMaskZero => "no active oxygen cells".into(),
-                SoloAlert::SoloSetpointTimeout => "setpoint timeout".into(),
-                SoloAlert::SoloSetpointUpdateTimeout => "setpoint update timeout".into(),
-                SoloAlert::SoloPPO2Below004PPO2 => "ppO₂ below 0.04".into(),
-                SoloAlert::SoloSetpointOutOfRange => "setpoint out of range".into(),
-
-                SoloAlert::SoloFwCrcFailed => "firmware CRC check failed".into(),
-                SoloAlert::SoloFwCrcReset => "reset due to firmware CRC error".into(),
-                SoloAlert::SoloReadSettingsFailed => "failed to read settings".into(),
-                SoloAlert::SoloSpiFlashBusy => "SPI flash busy".into(),
-
-                SoloAlert::IsotpSingleFrameSendFailed => "ISO-TP single-frame send failed".into(),
-                SoloAlert::IsotpFlowControlTimeout => "ISO-TP flow-control timeout".into(),
-                SoloAlert::IsotpBusySingleFrame => "ISO-TP busy (single frame)".into(),
-                SoloAlert::IsotpBusyFirstFrame => "ISO-TP busy (first frame)".into(),
-
-                SoloAlert::UdsTransferDownloadOutOfRange => "UDS download out of range".into(),
-                SoloAlert::UdsTransferDownloadProgFailed => {
-                    "UDS download programming failed".into()
-                }
-                SoloAlert::UdsTransferIncorrectMessageLength => {
-                    "UDS incorrect message length".into()
-                }
-                SoloAlert::UdsTransferDownloadWrongSequence => "UDS wrong download sequence".into(),
-                SoloAlert::UdsTransferWrongBlockSequence => "UDS wrong block sequence".into(),
-                SoloAlert::UdsTransferRequestSequenceError => "UDS request sequence error".into(),
-                SoloAlert::UdsTransferExitFailed => "UDS transfer exit failed".into(),
-                SoloAlert::UdsTransferNoBlocksTransferred => "UDS no blocks transferred".into(),
-                SoloAlert::UdsTransferCrcVerifyFailed => "UDS CRC verify failed".into(),
-                SoloAlert::UdsTransferCrcMismatch => "UDS CRC mismatch".into(),
-                SoloAlert::UdsTransferVerifyProgFailed => "UDS verify programming failed".into(),
-                SoloAlert::UdsTransferUploadFailed => "UDS upload failed".into(),
-                SoloAlert::UdsTransferTimeout => "UDS transfer timeout".into(),
-            };
-        }
-
-        format!("unknown alert 0x{code:04X}")
-    }
-
-    match msg {
-        Msg::Id {
-            manufacturer,
-            version,
-            ..
-        } => format!("device ID: manufacturer 0x{manufacturer:02X}, firmware 0x{version:02X}"),
-
-        Msg::DeviceName(name) => format!("device name: \"{}\"", ascii_lossy(name)),
-
-        Msg::Alert(alert) => format!("alert: {}", alert_label(alert.code)),
-
-        Msg::ShutdownInit(reason) => format!("shutdown initiated: {reason:?}"),
-
-        Msg::CellPpo2(cells) => format!(
-            "cell ppO₂ readings: {}, {}, {}",
-            cells[0], cells[1], cells[2]
-        ),
-
-        Msg::OboeStatus {
-            battery_ok,
-            battery_voltage,
-            ..
-        } => format!(
-            "battery status: {}, voltage {}",
-            if *battery_ok { "OK" } else { "not OK" },
-            battery_voltage
-        ),
-
-        Msg::AmbientPressure {
-            surface,
-            current,
-            depth_comp,
-        } => format!(
-            "ambient pressure: surface {}, current {}, depth compensation {}",
-            surface,
-            current,
-            if *depth_comp { "enabled" } else { "disabled" }
-        ),
-
-        Msg::Uds { dlc, data } => format!(
-            "UDS diagnostic data: {} bytes [{}]",
-            dlc,
-            data[..(*dlc as usize).min(8)]
-                .iter()
-                .map(|b| format!("{:02X}", b))
-                .collect::<Vec<_>>()
-                .join(" ")
-        ),
-
-        Msg::TankPressure {
-            cylinder_index,
-            pressure,
-        } => format!("tank pressure: cylinder {}, {}", cylinder_index, pressure),
-
-        Msg::Nop => "no operation".into(),
-
-        Msg::CellVoltages { cell_voltages, .. } => format!(
-            "cell voltages: {}, {}, {}",
-            cell_voltages[0], cell_voltages[1], cell_voltages[2]
-        ),
-
-        Msg::Ppo2CalibrationRequest { fo2, pressure } => {
-            format!(
-                "ppO₂ calibration requested: FO₂ {}, pressure {}",
-                fo2, pressure
-            )
-        }
-
-        Msg::Ppo2CalibrationResponse {
-            status,
-            cell_voltages,
-            fo2,
-            pressure,
-            cells_active,
-        } => format!(
-            "ppO₂ calibration result: {:?}, cells {}, {}, {}, FO₂ {}, pressure {}, active cells {:?}",
-            status,
-            cell_voltages[0],
-            cell_voltages[1],
-            cell_voltages[2],
-            fo2,
-            pressure,
-            cells_active.as_array()
-        ),
-
-        Msg::Co2Enabled(enabled) => format!(
-            "CO₂ monitoring {}",
-            if *enabled { "enabled" } else { "disabled" }
-        ),
-
-        Msg::Co2 { pco2, .. } => format!("CO₂ partial pressure {}", pco2),
-        Msg::Co2CalibrationRequest { pco2 } => format!("CO₂ calibration requested at {}", pco2),
-        Msg::Co2CalibrationResponse { pco2, .. } => format!("CO₂ calibration result {}", pco2),
-
-        Msg::TempProbe { sensor_id, temp } => {
-            format!("temperature probe {} reading {}", sensor_id, temp)
-        }
-
-        Msg::TempProbeEnabled(enabled) => format!(
-            "temperature probe {}",
-            if *enabled { "enabled" } else { "disabled" }
-        ),
-
-        Msg::Setpoint(sp) => format!("setpoint changed to {}", sp),
-
-        Msg::CellStatus {
-            cells_active,
-            consensus,
-        } => format!(
-            "cell status: active cells {:?}, consensus {}",
-            cells_active.as_array(),
-            consensus_text(*consensus)
-        ),
-
-        Msg::SoloStatus {
-            voltage,
-            current,
-            injection_duration,
-            setpoint,
-            consensus,
-            voltage_alert,
-            current_alert,
-        } => format!(
-            "solo status: voltage {}, current {}, injection {}, setpoint {}, consensus {}, {}, {}",
-            voltage,
-            current,
-            injection_duration,
-            setpoint,
-            consensus_text(*consensus),
-            voltage_alert_text(*voltage_alert),
-            current_alert_text(*current_alert),
-        ),
-
-        Msg::Diving {
-            status,
-            dive_number,
-            timestamp,
-        } => format!(
-            "diving state: status 0x{status:02X}, dive #{dive_number}, timestamp {timestamp}"
-        ),
-
-        Msg::Serial(bytes) => format!("serial number: \"{}\"", ascii_lossy(bytes)),
-
-        Msg::Undocumented30 { .. } => "undocumented message (0x30)".into(),
-
-        Msg::UndocumentedC3 { .. } => "undocumented message (0xC3)".into(),
-
-        Msg::BusInit { .. } => "bus initialization".into(),
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        tr("msg.Uds", &[("len", &dlc), ("data", &data)])
+    }
+
+    fn tank_pressure(&mut self, cylinder_index: u8, pressure: Decibar) -> String {
+        tr(
+            "msg.TankPressure",
+            &[
+                ("cylinder", &cylinder_index),
+                ("pressure", &pressure.in_units(self.units)),
+            ],
+        )
+    }
+
+    fn nop(&mut self) -> String {
+        tr("msg.Nop", &[])
+    }
+
+    fn cell_voltages(&mut self, cell_voltages: CellArray<CentiMillivolt>, _unused: u8) -> String {
+        tr(
+            "msg.CellVoltages",
+            &[
+                ("cell1", &cell_voltages[0]),
+                ("cell2", &cell_voltages[1]),
+                ("cell3", &cell_voltages[2]),
+            ],
+        )
+    }
+
+    fn ppo2_calibration_response(
+        &mut self,
+        status: CalStatusCode,
+        cell_voltages: CellArray<Millivolt>,
+        fo2: Fo2,
+        pressure: Millibar,
+        cells_active: CellsActive,
+    ) -> String {
+        tr(
+            "msg.Ppo2CalibrationResponse",
+            &[
+                ("status", &format!("{status:?}")),
+                ("cell1", &cell_voltages[0]),
+                ("cell2", &cell_voltages[1]),
+                ("cell3", &cell_voltages[2]),
+                ("fo2", &fo2),
+                ("pressure", &pressure.in_units(self.units)),
+                ("active", &format!("{:?}", cells_active.as_array())),
+            ],
+        )
+    }
+
+    fn ppo2_calibration_request(&mut self, fo2: Fo2, pressure: Millibar) -> String {
+        tr(
+            "msg.Ppo2CalibrationRequest",
+            &[("fo2", &fo2), ("pressure", &pressure.in_units(self.units))],
+        )
+    }
+
+    fn co2_enabled(&mut self, enabled: bool) -> String {
+        tr("msg.Co2Enabled", &[("enabled", &enabled_text(enabled))])
+    }
+
+    fn co2(&mut self, _unknown: u8, pco2: Millibar) -> String {
+        tr("msg.Co2", &[("pco2", &pco2)])
+    }
+
+    fn co2_calibration_response(&mut self, _code: u8, pco2: Millibar) -> String {
+        tr("msg.Co2CalibrationResponse", &[("pco2", &pco2)])
+    }
+
+    fn co2_calibration_request(&mut self, pco2: Millibar) -> String {
+        tr("msg.Co2CalibrationRequest", &[("pco2", &pco2)])
+    }
+
+    fn undocumented_30(&mut self, _raw: [u8; 3]) -> String {
+        tr("msg.Undocumented30", &[])
+    }
+
+    fn bus_init(&mut self, _unused: [u8; 3]) -> String {
+        tr("msg.BusInit", &[])
+    }
+
+    fn temp_probe(&mut self, sensor_id: u8, temp: u16) -> String {
+        tr("msg.TempProbe", &[("sensor", &sensor_id), ("temp", &temp)])
+    }
+
+    fn undocumented_c3(&mut self, _unknown: (u16, u16, u8, u8)) -> String {
+        tr("msg.UndocumentedC3", &[])
+    }
+
+    fn temp_probe_enabled(&mut self, enabled: bool) -> String {
+        tr(
+            "msg.TempProbeEnabled",
+            &[("enabled", &enabled_text(enabled))],
+        )
+    }
+
+    fn setpoint(&mut self, setpoint: PpO2Deci) -> String {
+        tr("msg.Setpoint", &[("setpoint", &setpoint)])
+    }
+
+    fn cell_status(&mut self, cells_active: CellsActive, consensus: Consensus) -> String {
+        tr(
+            "msg.CellStatus",
+            &[
+                ("active", &format!("{:?}", cells_active.as_array())),
+                ("consensus", &consensus_text(consensus)),
+            ],
+        )
+    }
+
+    fn solo_status(&mut self, s: SoloStatusFields) -> String {
+        tr(
+            "msg.SoloStatus",
+            &[
+                ("voltage", &s.voltage),
+                ("current", &s.current),
+                ("injection", &s.injection_duration),
+                ("setpoint", &s.setpoint),
+                ("consensus", &consensus_text(s.consensus)),
+                ("voltage_alert", &voltage_alert_text(s.voltage_alert)),
+                ("current_alert", &current_alert_text(s.current_alert)),
+            ],
+        )
+    }
+
+    fn diving(&mut self, status: DiveState, dive_number: u16, timestamp: u32) -> String {
+        tr(
+            "msg.Diving",
+            &[
+                ("state", &dive_state_text(status)),
+                ("dive_number", &dive_number),
+                ("timestamp", &timestamp),
+            ],
+        )
+    }
+
+    fn serial(&mut self, serial: [u8; 8]) -> String {
+        tr("msg.Serial", &[("serial", &ascii_lossy(&serial))])
     }
 }
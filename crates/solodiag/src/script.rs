@@ -0,0 +1,126 @@
+//! `script run`, Rhai scripts driving a UDS session for diagnostic sequences
+//! too specific to become commands.
+//!
+//! Scripts get the core Rhai language plus `rdbi`, `wdbi`, `send_msg`,
+//! `sleep` and `print`. Rhai has no process or network access of its own,
+//! `import` resolves no modules and `eval` is disabled, so the device is all
+//! a script can reach. Operations and call depth are capped so a runaway
+//! script ends with an error.
+
+use anyhow::{Result, anyhow};
+use candive::divecan::{DiveCanFrame, DiveCanId, TxDlcPolicy};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Blob, Engine, EvalAltResult};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::transport::{self, RawBus};
+use crate::{Session, UdsTransport};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Rhai operations a script may run, far above any diagnostic sequence
+const MAX_OPERATIONS: u64 = 100_000_000;
+
+/// Where `send_msg` puts its frames, opened on first use
+pub struct ScriptBus {
+    pub transport_uri: String,
+    pub src: u8,
+    pub dst: u8,
+    pub tx_dlc: TxDlcPolicy,
+}
+
+/// The language without anything that reaches past the script
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.disable_symbol("eval");
+    // The default resolver reads `import`ed modules from disk
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(64);
+    engine.set_max_expr_depths(64, 32);
+    engine
+}
+
+pub fn run(path: &Path, session: Session, bus: ScriptBus) -> Result<()> {
+    let session = Rc::new(RefCell::new(session));
+    let mut engine = sandboxed_engine();
+
+    let s = session.clone();
+    engine.register_fn("rdbi", move |did: i64| -> ScriptResult<Blob> {
+        s.borrow_mut().rdbi(did_arg(did)?).map_err(runtime)
+    });
+    let s = session;
+    engine.register_fn("wdbi", move |did: i64, data: Blob| -> ScriptResult<()> {
+        s.borrow_mut().wdbi(did_arg(did)?, &data).map_err(runtime)
+    });
+
+    let raw: RefCell<Option<RawBus>> = RefCell::new(None);
+    engine.register_fn(
+        "send_msg",
+        move |kind: i64, data: Blob| -> ScriptResult<()> {
+            let kind = u8::try_from(kind).map_err(|_| format!("kind {} out of range", kind))?;
+            if data.len() > 8 {
+                return Err(format!("{} bytes don't fit in a CAN frame", data.len()).into());
+            }
+            let mut raw = raw.borrow_mut();
+            if raw.is_none() {
+                if transport::raw_bus_name(&bus.transport_uri).is_none() {
+                    return Err("send_msg needs a can:// or socketcand:// transport".into());
+                }
+                let opened = RawBus::open(&bus.transport_uri, Duration::from_millis(100))
+                    .map_err(|e| e.to_string())?
+                    .with_tx_dlc(bus.tx_dlc);
+                *raw = Some(opened);
+            }
+            let mut payload = [0u8; 8];
+            payload[..data.len()].copy_from_slice(&data);
+            let frame = DiveCanFrame::new(kind, data.len() as u8, payload)
+                .map_err(|e| format!("{:?}", e))?;
+            let id = DiveCanId::new(bus.src, bus.dst, kind);
+            raw.as_ref()
+                .unwrap()
+                .send(id, &frame)
+                .map_err(|e| e.to_string().into())
+        },
+    );
+
+    engine.register_fn("sleep", |ms: i64| {
+        std::thread::sleep(Duration::from_millis(ms.max(0) as u64))
+    });
+
+    engine
+        .run_file(path.to_path_buf())
+        .map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+fn did_arg(did: i64) -> ScriptResult<u16> {
+    u16::try_from(did).map_err(|_| format!("DID {} out of range", did).into())
+}
+
+fn runtime(e: anyhow::Error) -> Box<EvalAltResult> {
+    e.to_string().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sandbox() {
+        let engine = sandboxed_engine();
+        assert_eq!(engine.eval::<i64>("let x = 40; x + 2").unwrap(), 42);
+
+        let dir = std::env::temp_dir().join(format!("solodiag-script-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("module.rhai"), "export const X = 1;").unwrap();
+        let import = format!("import \"{}\" as m; m::X", dir.join("module").display());
+        assert!(engine.eval::<i64>(&import).is_err());
+        assert!(Engine::new().eval::<i64>(&import).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(engine.eval::<i64>("eval(\"1\")").is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! `support-bundle`, everything support asks for in one zip file.
+//!
+//! The text sections are the output of solodiag's own commands, run over one
+//! session, so each section reads exactly like what the user would have
+//! pasted. A failing section is recorded with its error and the rest still
+//! run, a dropped link is reopened first. Known DIDs are read at the end.
+
+use anyhow::{Result, anyhow};
+use candive::protocol::{DIDS, DidAccess};
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::crypto::SoloKey;
+use crate::{
+    CmdResult, Connected, Link, Session, TransportConfig, UdsTransport, cmd_cal_show_o2,
+    cmd_cal_show_zero, cmd_config_list, cmd_device_info, cmd_fw_info, cmd_logs_export,
+    cmd_logs_info, cmd_userconfig_list, iso8601_ms, unix_time_ms, window_start,
+};
+
+/// Writes one section's text
+type Section = fn(&mut Session, &mut Vec<u8>) -> CmdResult;
+
+/// Commands whose output goes into the bundle, one file each
+const SECTIONS: &[(&str, &str, Section)] = &[
+    ("device-show.txt", "device show", |s, out| {
+        cmd_device_info(s, false, out)
+    }),
+    ("fw-info.txt", "fw info", |s, out| cmd_fw_info(s, None, out)),
+    ("config-list.txt", "config list", |s, out| {
+        cmd_config_list(s, out)
+    }),
+    ("user-list.txt", "user list", |s, out| {
+        cmd_userconfig_list(s, out)
+    }),
+    ("cal-show-o2.txt", "cal show o2", |s, out| {
+        cmd_cal_show_o2(s, out)
+    }),
+    ("cal-show-zero.txt", "cal show zero", |s, out| {
+        cmd_cal_show_zero(s, out)
+    }),
+    ("logs-info.txt", "logs info", |s, out| cmd_logs_info(s, out)),
+];
+
+/// The zip being written and how each section went, for metadata.txt
+struct Bundle<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    summary: Vec<String>,
+}
+
+impl<W: Write + Seek> Bundle<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            zip: ZipWriter::new(writer),
+            summary: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        self.zip.start_file(name, options)?;
+        self.zip.write_all(data)?;
+        Ok(())
+    }
+
+    /// Adds a section's text. On failure the error is appended to what it
+    /// wrote before failing.
+    fn section(&mut self, name: &str, mut text: Vec<u8>, result: CmdResult) -> Result<()> {
+        let status = match result {
+            Ok(()) => "ok".to_string(),
+            Err(e) => {
+                writeln!(text, "\nError: {:#}", e)?;
+                format!("failed, {:#}", e)
+            }
+        };
+        self.add(name, &text)?;
+        self.summary.push(format!("{}: {}", name, status));
+        Ok(())
+    }
+
+    /// Writes metadata.txt, `meta` followed by the section summary
+    fn finish(mut self, mut meta: String) -> Result<(W, Vec<String>)> {
+        meta += "\nSections:\n";
+        for line in &self.summary {
+            meta += &format!("  {}\n", line);
+        }
+        self.add("metadata.txt", meta.as_bytes())?;
+        Ok((self.zip.finish()?, self.summary))
+    }
+}
+
+/// Writes the bundle to `output`. A device that can't be reached fails
+/// every section but still gives a bundle with the metadata.
+pub fn create(
+    output: &Path,
+    config: &TransportConfig,
+    device: CmdResult<Connected>,
+    last_secs: u32,
+) -> Result<()> {
+    let mut bundle = Bundle::new(File::create(output)?);
+    let env_key = std::env::var_os("SOLO_KEY").is_some();
+    let key_status = match (device.as_ref().is_ok_and(|d| d.solo_key.is_ok()), env_key) {
+        (true, true) => "set",
+        (true, false) => "stored key",
+        (false, _) => "not available",
+    };
+
+    match device {
+        Ok(mut device) => {
+            let mut link = Link {
+                session: &mut device.session,
+                config,
+            };
+            collect(
+                &mut bundle,
+                &mut link,
+                device.solo_key.ok().as_ref(),
+                last_secs,
+            )?;
+        }
+        Err(e) => {
+            for (name, command, _) in SECTIONS {
+                let text = format!("$ solodiag {}\n\n", command).into_bytes();
+                bundle.section(name, text, Err(anyhow!("{:#}", e)))?;
+            }
+            bundle
+                .summary
+                .push("logs.bin: skipped, not connected".into());
+            bundle.section("dids.txt", Vec::new(), Err(e))?;
+        }
+    }
+
+    let mut meta = String::new();
+    meta += &format!("solodiag {}\n", env!("CARGO_PKG_VERSION"));
+    meta += &format!("Created:   {}\n", iso8601_ms(unix_time_ms()));
+    meta += &format!(
+        "Host:      {} {}\n",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    meta += &format!("Transport: {}\n", config.uri);
+    meta += &format!("Src/dst:   0x{:02X} -> 0x{:02X}\n", config.src, config.dst);
+    meta += &format!("SOLO_KEY:  {}\n", key_status);
+    let (_, summary) = bundle.finish(meta)?;
+
+    println!("Support bundle");
+    println!("  Output: {}", output.display());
+    for line in &summary {
+        println!("  {}", line);
+    }
+    Ok(())
+}
+
+/// Runs every section over `link`, then the log export and the DID read
+fn collect<W: Write + Seek>(
+    bundle: &mut Bundle<W>,
+    link: &mut Link,
+    solo_key: Option<&SoloKey>,
+    last_secs: u32,
+) -> Result<()> {
+    for (name, command, section) in SECTIONS {
+        log::info!("Collecting {}", name);
+        let mut text = Vec::new();
+        let result = link.retry(|session| {
+            text = format!("$ solodiag {}\n\n", command).into_bytes();
+            section(session, &mut text)
+        });
+        bundle.section(name, text, result)?;
+    }
+
+    match solo_key {
+        Some(key) => {
+            log::info!("Collecting logs.bin");
+            let tmp = temp_path("logs.bin");
+            let since = window_start(None, Some(last_secs));
+            let result = link.retry(|session| {
+                cmd_logs_export(session, tmp.clone(), None, None, since, false, Some(key))
+            });
+            let logs = std::fs::read(&tmp);
+            let _ = std::fs::remove_file(&tmp);
+            match (result, logs) {
+                (Ok(()), Ok(logs)) => {
+                    bundle.add("logs.bin", &logs)?;
+                    bundle.summary.push("logs.bin: ok".into());
+                }
+                (Ok(()), Err(e)) => bundle.summary.push(format!("logs.bin: failed, {}", e)),
+                (Err(e), _) => bundle.summary.push(format!("logs.bin: failed, {:#}", e)),
+            }
+        }
+        None => bundle
+            .summary
+            .push("logs.bin: skipped, SOLO_KEY not set and no stored key".into()),
+    }
+
+    log::info!("Collecting dids.txt");
+    let mut text = Vec::new();
+    let result = link.retry(|session| {
+        text.clear();
+        read_known_dids(session, &mut text)
+    });
+    bundle.section("dids.txt", text, result)
+}
+
+/// Every readable DID from the protocol description the firmware should have
+fn read_known_dids(session: &mut Session, out: &mut Vec<u8>) -> CmdResult {
+    let version = session.version();
+    let mut read = 0;
+    for def in DIDS {
+        if def.access == DidAccess::Write || !version.has_did(def.did) {
+            continue;
+        }
+        let value = match session.rdbi(def.did) {
+            Ok(data) => hex::encode(data),
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(out, "0x{:04X} {:<28} {}", def.did, def.name, value)?;
+        read += 1;
+    }
+    if read == 0 {
+        return Err(anyhow!("no readable DIDs known"));
+    }
+    Ok(())
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("solodiag-{}-{}", std::process::id(), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn failed_sections_keep_their_output() {
+        let mut bundle = Bundle::new(Cursor::new(Vec::new()));
+        bundle
+            .section("fw-info.txt", b"Firmware\n".to_vec(), Ok(()))
+            .unwrap();
+        let failed = Err(anyhow!("Transport error").context("Reading logs"));
+        bundle
+            .section("logs-info.txt", b"Logs\n".to_vec(), failed)
+            .unwrap();
+        let (written, summary) = bundle.finish("solodiag test\n".into()).unwrap();
+        assert_eq!(
+            summary,
+            [
+                "fw-info.txt: ok",
+                "logs-info.txt: failed, Reading logs: Transport error"
+            ]
+        );
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(written.into_inner())).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        assert_eq!(
+            read("logs-info.txt"),
+            "Logs\n\nError: Reading logs: Transport error\n"
+        );
+        assert!(
+            read("metadata.txt")
+                .ends_with("  logs-info.txt: failed, Reading logs: Transport error\n")
+        );
+    }
+}
@@ -8,8 +8,8 @@ use uuid::uuid;
 
 use crate::transport::{ble_datagram, parse_ble_datagram};
 
-use super::TransportError;
 use super::bt::{SlipDecoder, slip_encode};
+use super::{TransportError, is_late_refusal, request_timeout};
 
 const DC_TRANSFER: uuid::Uuid = uuid!("27b7570b-359e-45a3-91bb-cf7e70049bd2");
 const DC_SERVICE: uuid::Uuid = uuid!("fe25c237-0ece-443c-b0aa-e02033e7029d");
@@ -20,6 +20,9 @@ pub struct BleTransport {
     characteristic: Arc<Mutex<btleplug::api::Characteristic>>,
     src: u8,
     dst: u8,
+    /// SID of the last request sent without waiting for an answer
+    suppressed: Option<u8>,
+    request_timeout: Duration,
 }
 
 impl BleTransport {
@@ -41,9 +44,17 @@ impl BleTransport {
             characteristic: Arc::new(Mutex::new(characteristic)),
             src,
             dst,
+            suppressed: None,
+            request_timeout: Duration::from_secs(3),
         })
     }
 
+    /// How long to wait for an answer, 3 s by default
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
     async fn find_device(
         adapter: &btleplug::platform::Adapter,
         device_id: Option<String>,
@@ -71,7 +82,7 @@ impl BleTransport {
             }
             None => {
                 let dev = found.into_iter().next().unwrap();
-                eprintln!("Using: {}", dev.id());
+                log::info!("Using: {}", dev.id());
                 dev
             }
         };
@@ -108,11 +119,7 @@ impl BleTransport {
         Ok((dev, ch))
     }
 
-    async fn request_async(
-        &self,
-        req: &[u8],
-        resp_buf: &mut [u8],
-    ) -> Result<usize, TransportError> {
+    async fn write_async(&self, req: &[u8]) -> Result<Peripheral, TransportError> {
         let datagram = ble_datagram(self.src, self.dst, req);
 
         let encoded = slip_encode(&datagram);
@@ -125,49 +132,69 @@ impl BleTransport {
             .write(&characteristic, &encoded, WriteType::WithoutResponse)
             .await
             .map_err(|_| TransportError::Io)?;
+        Ok(peripheral)
+    }
+
+    async fn request_async(
+        &self,
+        req: &[u8],
+        resp_buf: &mut [u8],
+        mut suppressed: Option<u8>,
+    ) -> Result<usize, TransportError> {
+        let peripheral = self.write_async(req).await?;
 
         let mut notifications = peripheral
             .notifications()
             .await
             .map_err(|_| TransportError::Io)?;
 
-        let notification_data = match timeout(Duration::from_secs(3), notifications.next()).await {
-            Ok(Some(n)) => n.value,
-            Ok(None) => return Err(TransportError::Io),
-            Err(_) => return Err(TransportError::Io),
-        };
-
-        // SLIP decode the notification
-        let mut decoder = SlipDecoder::new();
-        let mut decoded_datagram = None;
-
-        for byte in notification_data.iter() {
-            if let Some(msg) = decoder.decode(*byte) {
-                decoded_datagram = Some(msg);
-                break;
+        loop {
+            let notification_data = match timeout(
+                request_timeout(req, self.request_timeout),
+                notifications.next(),
+            )
+            .await
+            {
+                Ok(Some(n)) => n.value,
+                Ok(None) => return Err(TransportError::Io),
+                Err(_) => return Err(TransportError::Io),
+            };
+
+            // SLIP decode the notification
+            let mut decoder = SlipDecoder::new();
+            let mut decoded_datagram = None;
+
+            for byte in notification_data.iter() {
+                if let Some(msg) = decoder.decode(*byte) {
+                    decoded_datagram = Some(msg);
+                    break;
+                }
             }
-        }
 
-        //println!("response raw: {}", hex::encode(&notification_data));
-        //010080ff0c006280103943354135384242c0
+            //println!("response raw: {}", hex::encode(&notification_data));
+            //010080ff0c006280103943354135384242c0
 
-        let response_datagram = decoded_datagram.ok_or(TransportError::Io)?;
+            let response_datagram = decoded_datagram.ok_or(TransportError::Io)?;
 
-        // Parse datagram
-        let (resp_src, resp_dst, payload) = parse_ble_datagram(&response_datagram)?;
+            // Parse datagram
+            let (resp_src, resp_dst, payload) = parse_ble_datagram(&response_datagram)?;
 
-        // Verify addresses
-        if resp_src != self.dst || resp_dst != self.src {
-            return Err(TransportError::Io);
-        }
+            // Verify addresses
+            if resp_src != self.dst || resp_dst != self.src {
+                return Err(TransportError::Io);
+            }
+            if is_late_refusal(payload, suppressed.take(), req) {
+                continue;
+            }
 
-        // Copy payload to response buffer
-        if payload.len() > resp_buf.len() {
-            return Err(TransportError::Io);
-        }
+            // Copy payload to response buffer
+            if payload.len() > resp_buf.len() {
+                return Err(TransportError::Io);
+            }
 
-        resp_buf[..payload.len()].copy_from_slice(payload);
-        Ok(payload.len())
+            resp_buf[..payload.len()].copy_from_slice(payload);
+            return Ok(payload.len());
+        }
     }
 }
 
@@ -176,6 +203,14 @@ impl client::UdsTransport for BleTransport {
 
     fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
         // Bridge async to sync using runtime.block_on
-        self.runtime.block_on(self.request_async(req, resp_buf))
+        let suppressed = self.suppressed.take();
+        self.runtime
+            .block_on(self.request_async(req, resp_buf, suppressed))
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.runtime.block_on(self.write_async(req))?;
+        self.suppressed = req.get(1).copied();
+        Ok(())
     }
 }
@@ -0,0 +1,245 @@
+//! CAN interface bring-up over netlink, so `solodiag can setup` can stand in
+//! for `ip link set ... type can bitrate ...`.
+
+use anyhow::{Result, anyhow};
+use socketcan::CanInterface;
+use socketcan::nl::{CanCtrlMode, CanState};
+
+use super::raw::BusRead;
+
+/// Bitrates `can autodetect` tries, DiveCAN's 125 kbit/s first
+pub const COMMON_BITRATES: [u32; 6] = [125_000, 250_000, 500_000, 1_000_000, 100_000, 50_000];
+
+/// Set by [`setup`] so the controller leaves bus-off on its own, see
+/// [`super::BUS_OFF_RECOVERY`]
+pub const RESTART_MS: u32 = 100;
+
+pub struct CanStatus {
+    pub up: bool,
+    pub state: Option<CanState>,
+    pub bitrate: Option<u32>,
+    /// TX and RX error counters
+    pub counters: Option<(u16, u16)>,
+}
+
+/// Whether a netlink error means we lack CAP_NET_ADMIN
+fn needs_privileges(message: &str) -> bool {
+    [
+        "Operation not permitted",
+        "Permission denied",
+        "EPERM",
+        "EACCES",
+    ]
+    .iter()
+    .any(|m| message.contains(m))
+}
+
+fn netlink_error(action: &str, interface: &str, e: impl std::fmt::Display) -> anyhow::Error {
+    let message = e.to_string();
+    if needs_privileges(&message) {
+        anyhow!(
+            "Failed to {} {}: {}. Configuring CAN interfaces needs root or CAP_NET_ADMIN, run with sudo",
+            action,
+            interface,
+            message
+        )
+    } else {
+        anyhow!("Failed to {} {}: {}", action, interface, message)
+    }
+}
+
+fn open(interface: &str) -> Result<CanInterface> {
+    CanInterface::open(interface).map_err(|e| anyhow!("No CAN interface {}: {}", interface, e))
+}
+
+/// Configures `interface` for `bitrate` with automatic bus-off restart and
+/// brings it up. With `vcan` the interface is a virtual one, created if
+/// missing; it has no bitrate.
+pub fn setup(interface: &str, bitrate: u32, vcan: bool) -> Result<()> {
+    if vcan {
+        let iface = match CanInterface::open(interface) {
+            Ok(iface) => iface,
+            Err(_) => CanInterface::create_vcan(interface, None)
+                .map_err(|e| netlink_error("create", interface, e))?,
+        };
+        return iface
+            .bring_up()
+            .map_err(|e| netlink_error("bring up", interface, e));
+    }
+
+    configure(interface, bitrate, false).map(|_| ())
+}
+
+/// Like [`setup`], but the controller only listens: it never ACKs or sends
+/// error frames, so a wrong bitrate can't disturb a live bus. Bus errors
+/// are reported as error frames if the driver supports it, which is what
+/// the returned `bool` says.
+pub fn setup_listen_only(interface: &str, bitrate: u32) -> Result<bool> {
+    configure(interface, bitrate, true)
+}
+
+fn configure(interface: &str, bitrate: u32, listen_only: bool) -> Result<bool> {
+    let iface = open(interface)?;
+    iface
+        .bring_down()
+        .map_err(|e| netlink_error("bring down", interface, e))?;
+    iface
+        .set_bitrate(bitrate, None)
+        .map_err(|e| netlink_error("set the bitrate of", interface, e))?;
+    iface
+        .set_restart_ms(RESTART_MS)
+        .map_err(|e| netlink_error("set restart-ms on", interface, e))?;
+    iface
+        .set_ctrlmode(CanCtrlMode::ListenOnly, listen_only)
+        .map_err(|e| netlink_error("set listen-only mode on", interface, e))?;
+    // Without berr-reporting most drivers only send an error frame when the
+    // controller state changes, which a listen-only controller never does
+    let berr_reporting = iface
+        .set_ctrlmode(CanCtrlMode::BerrReporting, listen_only)
+        .is_ok()
+        && listen_only;
+    iface
+        .bring_up()
+        .map_err(|e| netlink_error("bring up", interface, e))?;
+    Ok(berr_reporting)
+}
+
+/// Probing `interface` in listen-only mode. Dropped without
+/// [`Self::finish`], e.g. when a probe fails, it brings the interface back
+/// up in normal mode at the bitrate it had before.
+pub struct ListenOnlyProbe<'a> {
+    interface: &'a str,
+    previous: u32,
+    finished: bool,
+}
+
+impl<'a> ListenOnlyProbe<'a> {
+    /// `previous` is the bitrate to restore, DiveCAN's if the interface had
+    /// none
+    pub fn new(interface: &'a str, previous: Option<u32>) -> Self {
+        Self {
+            interface,
+            previous: previous.unwrap_or(COMMON_BITRATES[0]),
+            finished: false,
+        }
+    }
+
+    /// Switches to listening at `bitrate`, see [`setup_listen_only`]
+    pub fn listen(&self, bitrate: u32) -> Result<bool> {
+        setup_listen_only(self.interface, bitrate)
+    }
+
+    /// Brings the interface up in normal mode at `bitrate`, or at the
+    /// previous bitrate if `None`
+    pub fn finish(mut self, bitrate: Option<u32>) -> Result<()> {
+        self.finished = true;
+        setup(self.interface, bitrate.unwrap_or(self.previous), false)
+    }
+}
+
+impl Drop for ListenOnlyProbe<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Err(e) = setup(self.interface, self.previous, false) {
+            log::warn!(
+                "{} may still be in listen-only mode, restoring it failed: {}",
+                self.interface,
+                e
+            );
+        }
+    }
+}
+
+/// What was heard while listening at one bitrate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BitrateProbe {
+    pub bitrate: u32,
+    /// Extended-id frames with the DiveCAN prefix
+    pub divecan: u32,
+    /// Other extended-id frames
+    pub other: u32,
+    /// Error frames, a wrong bitrate shows up as these
+    pub errors: u32,
+}
+
+impl BitrateProbe {
+    pub fn new(bitrate: u32) -> Self {
+        Self {
+            bitrate,
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, read: &BusRead) {
+        match read {
+            BusRead::Frame(..) => self.divecan += 1,
+            BusRead::Other(..) => self.other += 1,
+            BusRead::Error(_) => self.errors += 1,
+        }
+    }
+}
+
+/// The bitrate that carried the most DiveCAN traffic, fewer errors breaking
+/// ties. `None` when no DiveCAN frame was seen at all.
+pub fn best_bitrate(probes: &[BitrateProbe]) -> Option<&BitrateProbe> {
+    probes
+        .iter()
+        .filter(|p| p.divecan > 0)
+        .max_by_key(|p| (p.divecan, core::cmp::Reverse(p.errors)))
+}
+
+/// Reads link state, controller state, bitrate and error counters. Fields a
+/// driver doesn't report (e.g. bitrate on vcan) are `None`.
+pub fn status(interface: &str) -> Result<CanStatus> {
+    let iface = open(interface)?;
+    let details = iface
+        .details()
+        .map_err(|e| netlink_error("query", interface, e))?;
+    Ok(CanStatus {
+        up: details.is_up,
+        state: iface.state().ok().flatten(),
+        bitrate: iface.bit_rate().ok().flatten(),
+        counters: iface
+            .berr_counter()
+            .ok()
+            .flatten()
+            .map(|c| (c.txerr, c.rxerr)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn privilege_hint() {
+        assert!(needs_privileges(
+            "Netlink error: Operation not permitted (os error 1)"
+        ));
+        assert!(!needs_privileges("No such device (os error 19)"));
+    }
+
+    #[test]
+    fn picks_bitrate_with_divecan_traffic() {
+        let probe = |bitrate, divecan, other, errors| BitrateProbe {
+            bitrate,
+            divecan,
+            other,
+            errors,
+        };
+        let probes = [
+            probe(125_000, 40, 0, 3),
+            probe(250_000, 0, 90, 0),
+            probe(500_000, 40, 0, 0),
+            probe(1_000_000, 0, 0, 200),
+        ];
+        assert_eq!(best_bitrate(&probes).unwrap().bitrate, 500_000);
+        assert_eq!(best_bitrate(&probes[1..2]), None);
+
+        let mut counted = BitrateProbe::new(125_000);
+        counted.push(&BusRead::Other(0x18FE_F100, vec![]));
+        assert_eq!((counted.divecan, counted.other), (0, 1));
+    }
+}
@@ -1,4 +1,56 @@
+use candive::protocol::DidTimeout;
+use candive::uds::SID_NEG_RESPONSE;
+use candive::uds::client;
 use candive::uds::isotp::IsoTpRxError;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// How long a CAN transport waits for the controller to restart after a
+/// bus-off before failing with [`TransportError::BusOff`]. Restarting is up
+/// to the driver, e.g. `ip link set can0 type can restart-ms 100`.
+pub const BUS_OFF_RECOVERY: Duration = Duration::from_secs(2);
+
+/// How long the gateway transports wait for a [`DidTimeout::Slow`] DID
+pub const SLOW_DID_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Deadline for `req`, `normal` unless it addresses a slow DID
+pub fn request_timeout(req: &[u8], normal: Duration) -> Duration {
+    match client::request_timeout(req) {
+        DidTimeout::Normal => normal,
+        DidTimeout::Slow => SLOW_DID_TIMEOUT.max(normal),
+    }
+}
+
+/// `addr` as `host:port` with `port` added when it has none. IPv6
+/// addresses take the port after brackets, `[fe80::1]:29536`, and can be
+/// given without them when there is no port.
+pub fn with_default_port(addr: &str, port: u16) -> String {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return addr.to_string();
+    }
+    let ip = addr.strip_prefix('[').and_then(|a| a.strip_suffix(']'));
+    if let Ok(ip) = ip.unwrap_or(addr).parse::<IpAddr>() {
+        return SocketAddr::new(ip, port).to_string();
+    }
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, port)
+    }
+}
+
+/// Whether `resp` is a refusal of the request with SID `suppressed`, sent
+/// earlier without waiting, rather than the answer to `req`. A request with
+/// the suppress bit set is only answered to refuse it, so that answer can
+/// arrive in front of the next one.
+pub fn is_late_refusal(resp: &[u8], suppressed: Option<u8>, req: &[u8]) -> bool {
+    match (resp, suppressed) {
+        ([_, SID_NEG_RESPONSE, sid, ..], Some(suppressed)) => {
+            *sid == suppressed && req.get(1) != Some(sid)
+        }
+        _ => false,
+    }
+}
 
 /// Transport-specific error type for solodiag
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,6 +59,12 @@ pub enum TransportError {
     IsoTp(IsoTpRxError),
     /// I/O error
     Io,
+    /// The CAN controller went bus-off and did not recover
+    BusOff,
+    /// The network interface is down (ENETDOWN)
+    InterfaceDown,
+    /// Transport can't do what was asked
+    Unsupported(&'static str),
 }
 
 impl std::fmt::Display for TransportError {
@@ -14,6 +72,13 @@ impl std::fmt::Display for TransportError {
         match self {
             TransportError::IsoTp(e) => write!(f, "ISO-TP error: {:?}", e),
             TransportError::Io => write!(f, "I/O error"),
+            TransportError::BusOff => {
+                write!(f, "CAN bus-off, check wiring, termination and bitrate")
+            }
+            TransportError::InterfaceDown => {
+                write!(f, "network interface is down, bring it up first")
+            }
+            TransportError::Unsupported(reason) => write!(f, "{}", reason),
         }
     }
 }
@@ -27,25 +92,42 @@ impl From<IsoTpRxError> for TransportError {
 }
 
 impl From<std::io::Error> for TransportError {
-    fn from(_: std::io::Error) -> Self {
-        TransportError::Io
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::NetworkDown {
+            TransportError::InterfaceDown
+        } else {
+            TransportError::Io
+        }
     }
 }
 
+/// Whether `err` is the link failing rather than the device answering,
+/// something reopening the transport may fix
+pub fn is_transport_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<TransportError>().is_some()
+}
+
 pub fn uds_error_to_anyhow(
     err: candive::uds::client::UdsClientError<TransportError>,
 ) -> anyhow::Error {
-    use candive::uds::client::UdsClientError;
+    use candive::uds::client::{ProtocolError, UdsClientError};
 
     match err {
-        UdsClientError::Transport(e) => anyhow::anyhow!("Transport error: {}", e),
+        UdsClientError::Transport(e) => anyhow::Error::new(e).context("Transport error"),
         UdsClientError::NegativeResponse(neg) => anyhow::anyhow!(
-            "Negative response: service=0x{:02X}, code=0x{:02X}",
+            "Negative response: service=0x{:02X}, {}",
             neg.service,
-            neg.code.as_u8()
+            neg.code
         ),
         UdsClientError::Decode(e) => anyhow::anyhow!("Decode error: {:?}", e),
         UdsClientError::Encode(e) => anyhow::anyhow!("Encode error: {:?}", e),
+        UdsClientError::Protocol(ProtocolError::TransferCrcMismatch { expected, got }) => {
+            anyhow::anyhow!(
+                "Transfer CRC mismatch: sent 0x{:08X}, device has 0x{:08X}. Do not reboot, upload again",
+                expected,
+                got
+            )
+        }
         UdsClientError::Protocol(e) => anyhow::anyhow!("Protocol error: {:?}", e),
         UdsClientError::ResponseTooLarge => anyhow::anyhow!("Response too large for buffer"),
     }
@@ -59,9 +141,84 @@ pub use bt::*;
 mod socketcan;
 #[cfg(target_os = "linux")]
 pub use socketcan::SocketCanIsoTpSessionUdsSession;
+#[cfg(target_os = "linux")]
+pub mod canif;
+
+// Remote CAN through a socketcand server
+mod socketcand;
+pub use socketcand::SocketcandIsoTpSession;
+
+// Raw bus access for both of the above
+mod raw;
+pub use raw::{BusRead, RawBus, listen, listen_ambient_pressure, listen_power_stats, raw_bus_name};
 
 // Cross-platform RFCOMM transport
 mod rfcomm;
 pub use rfcomm::RfcommGatewayTransport;
 mod ble;
 pub use ble::BleTransport;
+mod tcp;
+pub use tcp::{TcpGatewayTransport, discover_gateways};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_errors() {
+        use candive::uds::{NegativeResponse, UdsClientError, UdsErrorCode};
+
+        let err = uds_error_to_anyhow(UdsClientError::Transport(TransportError::Io));
+        assert!(is_transport_error(&err));
+        assert!(is_transport_error(&err.context("Uploading log entries")));
+        let err = uds_error_to_anyhow(UdsClientError::NegativeResponse(NegativeResponse {
+            service: 0x22,
+            code: UdsErrorCode::RequestOutOfRange,
+        }));
+        assert!(!is_transport_error(&err));
+
+        let down = std::io::Error::from(std::io::ErrorKind::NetworkDown);
+        assert_eq!(TransportError::from(down), TransportError::InterfaceDown);
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert_eq!(TransportError::from(reset), TransportError::Io);
+    }
+
+    #[test]
+    fn default_ports() {
+        assert_eq!(with_default_port("solo.local", 80), "solo.local:80");
+        assert_eq!(with_default_port("solo.local:8080", 80), "solo.local:8080");
+        assert_eq!(with_default_port("10.0.0.2", 80), "10.0.0.2:80");
+        assert_eq!(with_default_port("fe80::1", 80), "[fe80::1]:80");
+        assert_eq!(with_default_port("[fe80::1]", 80), "[fe80::1]:80");
+        assert_eq!(with_default_port("[fe80::1]:8080", 80), "[fe80::1]:8080");
+        assert_eq!(with_default_port("::1", 80), "[::1]:80");
+    }
+
+    #[test]
+    fn late_refusals() {
+        let rdbi = [0x00, 0x22, 0x80, 0x11];
+        // serviceNotSupported for a suppressed TesterPresent
+        assert!(is_late_refusal(
+            &[0x00, 0x7F, 0x3E, 0x11],
+            Some(0x3E),
+            &rdbi
+        ));
+        assert!(!is_late_refusal(&[0x00, 0x7F, 0x3E, 0x11], None, &rdbi));
+        assert!(!is_late_refusal(
+            &[0x00, 0x7F, 0x22, 0x31],
+            Some(0x3E),
+            &rdbi
+        ));
+        // The same service again, the refusal is its answer
+        assert!(!is_late_refusal(
+            &[0x00, 0x7F, 0x3E, 0x11],
+            Some(0x3E),
+            &[0x00, 0x3E, 0x00]
+        ));
+        assert!(!is_late_refusal(
+            &[0x00, 0x62, 0x80, 0x11],
+            Some(0x3E),
+            &rdbi
+        ));
+    }
+}
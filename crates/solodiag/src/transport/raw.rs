@@ -0,0 +1,174 @@
+use candive::divecan::{AmbientPressureAverage, DiveCanFrame, DiveCanId, Msg, TxDlcPolicy};
+use candive::monitor::BusError;
+use candive::power::PowerStats;
+use candive::units::Millibar;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "linux")]
+use super::socketcan::RawDiveCanSocket;
+use super::socketcand::SocketcandRawSocket;
+use super::{BUS_OFF_RECOVERY, TransportError};
+
+/// Raw (non ISO-TP) DiveCAN bus behind a transport URI, local SocketCAN
+/// for `can://` or a remote socketcand server for `socketcand://`.
+pub struct RawBus {
+    socket: RawSocket,
+    tx_dlc: TxDlcPolicy,
+}
+
+enum RawSocket {
+    #[cfg(target_os = "linux")]
+    Local(RawDiveCanSocket),
+    Remote(SocketcandRawSocket),
+}
+
+/// One read from a [`RawBus`]
+pub enum BusRead {
+    Frame(DiveCanId, DiveCanFrame),
+    /// Extended-id frame without the DiveCAN prefix, raw id and payload
+    Other(u32, Vec<u8>),
+    /// Error frame from the CAN controller
+    Error(BusError),
+}
+
+/// Bus name for URIs with a raw bus, `None` for gateway transports.
+pub fn raw_bus_name(transport_uri: &str) -> Option<&str> {
+    transport_uri
+        .strip_prefix("can://")
+        .or_else(|| transport_uri.strip_prefix("socketcand://"))
+}
+
+impl RawBus {
+    /// Sends frames with their minimal DLC until [`Self::with_tx_dlc`]
+    pub fn open(transport_uri: &str, read_timeout: Duration) -> Result<Self, TransportError> {
+        Ok(Self {
+            socket: RawSocket::open(transport_uri, read_timeout)?,
+            tx_dlc: TxDlcPolicy::Minimal,
+        })
+    }
+
+    pub fn with_tx_dlc(mut self, policy: TxDlcPolicy) -> Self {
+        self.tx_dlc = policy;
+        self
+    }
+
+    pub fn send(&self, id: DiveCanId, frame: &DiveCanFrame) -> Result<(), TransportError> {
+        let frame = frame.with_tx_dlc(self.tx_dlc);
+        match &self.socket {
+            #[cfg(target_os = "linux")]
+            RawSocket::Local(s) => s.send(id, &frame),
+            RawSocket::Remote(s) => s.send(id, &frame),
+        }
+    }
+
+    /// Reads the next extended-id or error frame, `Ok(None)` on read timeout.
+    pub fn read(&self) -> Result<Option<BusRead>, TransportError> {
+        match &self.socket {
+            #[cfg(target_os = "linux")]
+            RawSocket::Local(s) => s.read(),
+            RawSocket::Remote(s) => s.read(),
+        }
+    }
+
+    /// Reads the next DiveCAN frame, `Ok(None)` on read timeout. Foreign
+    /// and error frames are skipped, except for a bus-off the controller doesn't
+    /// recover from within [`BUS_OFF_RECOVERY`], which fails with
+    /// [`TransportError::BusOff`].
+    pub fn recv(&self) -> Result<Option<(DiveCanId, DiveCanFrame)>, TransportError> {
+        match self.read()? {
+            Some(BusRead::Frame(id, frame)) => Ok(Some((id, frame))),
+            Some(BusRead::Error(err)) if err.is_bus_off() => self.await_restart(),
+            _ => Ok(None),
+        }
+    }
+
+    fn await_restart(&self) -> Result<Option<(DiveCanId, DiveCanFrame)>, TransportError> {
+        log::warn!("CAN bus-off, waiting for the controller to restart");
+        let start = Instant::now();
+        while start.elapsed() < BUS_OFF_RECOVERY {
+            match self.read()? {
+                Some(BusRead::Frame(id, frame)) => return Ok(Some((id, frame))),
+                Some(BusRead::Error(err)) if err.is_restarted() => return Ok(None),
+                _ => {}
+            }
+        }
+        Err(TransportError::BusOff)
+    }
+}
+
+impl RawSocket {
+    fn open(transport_uri: &str, read_timeout: Duration) -> Result<Self, TransportError> {
+        if let Some(target) = transport_uri.strip_prefix("socketcand://") {
+            return Ok(RawSocket::Remote(SocketcandRawSocket::open(
+                target,
+                read_timeout,
+            )?));
+        }
+        let Some(interface) = transport_uri.strip_prefix("can://") else {
+            return Err(TransportError::Unsupported(
+                "needs a can:// or socketcand:// transport",
+            ));
+        };
+
+        #[cfg(target_os = "linux")]
+        {
+            Ok(RawSocket::Local(RawDiveCanSocket::open(
+                interface,
+                read_timeout,
+            )?))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (interface, read_timeout);
+            Err(TransportError::Unsupported(
+                "CAN transport is only available on Linux",
+            ))
+        }
+    }
+}
+
+/// Listens on the raw DiveCAN bus for `window`, passing every decoded message to `on_msg`.
+pub fn listen(
+    transport_uri: &str,
+    window: Duration,
+    mut on_msg: impl FnMut(&Msg),
+) -> Result<(), TransportError> {
+    let bus = RawBus::open(transport_uri, Duration::from_millis(100))?;
+    let start = Instant::now();
+
+    while start.elapsed() < window {
+        if let Some(msg) = bus
+            .recv()?
+            .and_then(|(_, frame)| Msg::try_from_frame(&frame).ok())
+        {
+            on_msg(&msg);
+        }
+    }
+    Ok(())
+}
+
+/// Listens on the raw DiveCAN bus for `window` and averages the
+/// `AmbientPressure` broadcasts seen during that time.
+pub fn listen_ambient_pressure(
+    transport_uri: &str,
+    window: Duration,
+) -> Result<Option<Millibar>, TransportError> {
+    let mut avg = AmbientPressureAverage::new();
+    listen(transport_uri, window, |msg| {
+        avg.push(msg);
+    })?;
+    Ok(avg.average())
+}
+
+/// Listens on the raw DiveCAN bus for `window` and collects the `SoloStatus`
+/// voltage and current seen during that time.
+pub fn listen_power_stats(
+    transport_uri: &str,
+    window: Duration,
+) -> Result<PowerStats, TransportError> {
+    let mut stats = PowerStats::new();
+    listen(transport_uri, window, |msg| {
+        stats.push(msg);
+    })?;
+    Ok(stats)
+}
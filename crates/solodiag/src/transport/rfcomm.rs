@@ -3,14 +3,17 @@ use candive::uds::client::UdsClientError;
 use std::io::{Read, Write};
 use std::time::Duration;
 
-use super::TransportError;
 use super::bt::{SlipDecoder, parse_rfcomm_datagram, rfcomm_datagram, slip_encode};
+use super::{TransportError, is_late_refusal, request_timeout};
 
 pub struct RfcommGatewayTransport {
     port: std::cell::RefCell<Box<dyn serialport::SerialPort>>,
     src: u8,
     dst: u8,
     slip_decoder: std::cell::RefCell<SlipDecoder>,
+    /// SID of the last request sent without waiting for an answer
+    suppressed: Option<u8>,
+    request_timeout: Duration,
 }
 
 impl RfcommGatewayTransport {
@@ -31,9 +34,17 @@ impl RfcommGatewayTransport {
             src,
             dst,
             slip_decoder: std::cell::RefCell::new(SlipDecoder::new()),
+            suppressed: None,
+            request_timeout: Duration::from_secs(5),
         })
     }
 
+    /// How long to wait for an answer, 5 s by default
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
     /// Reads a SLIP-encoded datagram from the serial port with timeout
     fn read_datagram(&self, timeout: Duration) -> Result<Vec<u8>, TransportError> {
         let mut decoder = self.slip_decoder.borrow_mut();
@@ -59,12 +70,12 @@ impl RfcommGatewayTransport {
                     std::thread::sleep(Duration::from_millis(10));
                 }
                 Err(e) => {
-                    eprintln!("✗ Serial read error: {}", e);
+                    log::warn!("Serial read error: {}", e);
                     return Err(TransportError::Io);
                 }
             }
         }
-        eprintln!("✗ Timeout waiting for response ({:?})", timeout);
+        log::warn!("Timeout waiting for response ({:?})", timeout);
         Err(TransportError::Io)
     }
 }
@@ -73,26 +84,44 @@ impl client::UdsTransport for RfcommGatewayTransport {
     type Error = TransportError;
 
     fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let req_datagram = rfcomm_datagram(self.src, self.dst, req);
-        let encoded = slip_encode(&req_datagram);
-        {
-            let mut port = self.port.borrow_mut();
-            port.write_all(&encoded)?;
-            port.flush()?;
-        }
-        let response_datagram = self.read_datagram(Duration::from_secs(5))?;
+        self.write(req)?;
+        let timeout = request_timeout(req, self.request_timeout);
+        let mut suppressed = self.suppressed.take();
+        loop {
+            let response_datagram = self.read_datagram(timeout)?;
 
-        let (resp_src, resp_dst, payload) = parse_rfcomm_datagram(&response_datagram)?;
+            let (resp_src, resp_dst, payload) = parse_rfcomm_datagram(&response_datagram)?;
 
-        if resp_src != self.dst || resp_dst != self.src {
-            return Err(TransportError::Io);
-        }
+            if resp_src != self.dst || resp_dst != self.src {
+                return Err(TransportError::Io);
+            }
+            if is_late_refusal(payload, suppressed.take(), req) {
+                continue;
+            }
+
+            if payload.len() > resp_buf.len() {
+                return Err(TransportError::Io);
+            }
 
-        if payload.len() > resp_buf.len() {
-            return Err(TransportError::Io);
+            resp_buf[..payload.len()].copy_from_slice(payload);
+            return Ok(payload.len());
         }
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.write(req)?;
+        self.suppressed = req.get(1).copied();
+        Ok(())
+    }
+}
 
-        resp_buf[..payload.len()].copy_from_slice(payload);
-        Ok(payload.len())
+impl RfcommGatewayTransport {
+    fn write(&self, req: &[u8]) -> Result<(), TransportError> {
+        let req_datagram = rfcomm_datagram(self.src, self.dst, req);
+        let encoded = slip_encode(&req_datagram);
+        let mut port = self.port.borrow_mut();
+        port.write_all(&encoded)?;
+        port.flush()?;
+        Ok(())
     }
 }
@@ -1,10 +1,22 @@
+use candive::divecan::{BusTraffic, DiveCanFrame, DiveCanId};
+use candive::monitor::BusError;
 use candive::uds::client;
 use candive::uds::client::{ProtocolError, UdsClientError};
+use socketcan::{CanFrame, CanSocket, EmbeddedFrame, ExtendedId, Id, Socket, SocketOptions};
+use std::time::{Duration, Instant};
 
-use super::TransportError;
+use super::raw::BusRead;
+use super::{BUS_OFF_RECOVERY, TransportError, is_late_refusal, request_timeout};
+
+/// How often a pending request checks the non-blocking socket
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
 
 pub struct SocketCanIsoTpSessionUdsSession {
+    interface: String,
     socket: std::cell::RefCell<socketcan_isotp::IsoTpSocket>,
+    /// SID of the last request sent without waiting for an answer
+    suppressed: std::cell::Cell<Option<u8>>,
+    request_timeout: Duration,
 }
 
 impl SocketCanIsoTpSessionUdsSession {
@@ -15,23 +27,150 @@ impl SocketCanIsoTpSessionUdsSession {
             socketcan::ExtendedId::new(tx).ok_or_else(|| ProtocolError::UnexpectedResponse)?;
         let socket = socketcan_isotp::IsoTpSocket::open(interface, rx_id, tx_id)
             .map_err(|_| UdsClientError::Transport(TransportError::Io))?;
+        // A blocking read waits forever for a device that went away
+        socket
+            .set_nonblocking(true)
+            .map_err(|_| UdsClientError::Transport(TransportError::Io))?;
         Ok(Self {
+            interface: interface.to_string(),
             socket: std::cell::RefCell::new(socket),
+            suppressed: std::cell::Cell::new(None),
+            request_timeout: Duration::from_secs(5),
         })
     }
+
+    /// How long to wait for an answer, 5 s by default
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
 }
 
 impl client::UdsTransport for SocketCanIsoTpSessionUdsSession {
     type Error = TransportError;
 
+    /// Retries once after a bus-off, giving the controller
+    /// [`BUS_OFF_RECOVERY`] to restart. ISO-TP sockets don't see error
+    /// frames, so a failed send is checked against the controller state.
     fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self.exchange(req, resp_buf) {
+            Err(TransportError::InterfaceDown) if self.is_bus_off() => {
+                log::warn!("CAN bus-off, waiting for the controller to restart");
+                std::thread::sleep(BUS_OFF_RECOVERY);
+                self.exchange(req, resp_buf).map_err(|e| match e {
+                    TransportError::InterfaceDown if self.is_bus_off() => TransportError::BusOff,
+                    e => e,
+                })
+            }
+            result => result,
+        }
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.socket.borrow_mut().write(req)?;
+        self.suppressed.set(req.get(1).copied());
+        Ok(())
+    }
+}
+
+impl SocketCanIsoTpSessionUdsSession {
+    fn is_bus_off(&self) -> bool {
+        super::canif::status(&self.interface)
+            .is_ok_and(|status| status.up && status.state == Some(socketcan::nl::CanState::BusOff))
+    }
+
+    fn exchange(&self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, TransportError> {
         let mut socket = self.socket.borrow_mut();
-        socket.write(req).map_err(|_| TransportError::Io)?;
-        let response_slice = socket.read().map_err(|_| TransportError::Io)?;
-        if response_slice.len() > resp_buf.len() {
-            return Err(TransportError::Io);
+        socket.write(req)?;
+        let deadline = Instant::now() + request_timeout(req, self.request_timeout);
+        let mut len = read_until(&mut socket, deadline, resp_buf)?;
+        if is_late_refusal(&resp_buf[..len], self.suppressed.take(), req) {
+            len = read_until(&mut socket, deadline, resp_buf)?;
         }
-        resp_buf[..response_slice.len()].copy_from_slice(&response_slice);
-        Ok(response_slice.len())
+        Ok(len)
+    }
+}
+
+/// Copies the next PDU into `buf`, polling until `deadline`
+fn read_until(
+    socket: &mut socketcan_isotp::IsoTpSocket,
+    deadline: Instant,
+    buf: &mut [u8],
+) -> Result<usize, TransportError> {
+    loop {
+        match socket.read() {
+            Ok(pdu) if pdu.len() > buf.len() => return Err(TransportError::Io),
+            Ok(pdu) => {
+                buf[..pdu.len()].copy_from_slice(pdu);
+                return Ok(pdu.len());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    log::warn!("Timeout waiting for response");
+                    return Err(TransportError::Io);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Raw (non ISO-TP) DiveCAN socket for listening to bus broadcasts.
+pub struct RawDiveCanSocket {
+    socket: CanSocket,
+}
+
+impl RawDiveCanSocket {
+    pub fn open(interface: &str, read_timeout: Duration) -> Result<Self, TransportError> {
+        let socket = CanSocket::open(interface)?;
+        socket.set_read_timeout(read_timeout)?;
+        socket.set_error_filter_accept_all()?;
+        Ok(Self { socket })
+    }
+
+    pub fn send(&self, id: DiveCanId, frame: &DiveCanFrame) -> Result<(), TransportError> {
+        let ext = ExtendedId::new(id.to_u32()).ok_or(TransportError::Io)?;
+        let frame = CanFrame::new(ext, frame.bytes()).ok_or(TransportError::Io)?;
+        self.socket.write_frame(&frame)?;
+        Ok(())
+    }
+
+    /// Reads the next extended-id or error frame, `Ok(None)` on read timeout.
+    pub fn read(&self) -> Result<Option<BusRead>, TransportError> {
+        let frame = match self.socket.read_frame() {
+            Ok(frame) => frame,
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if let CanFrame::Error(err) = frame {
+            return Ok(Some(BusRead::Error(BusError::decode(
+                err.error_bits(),
+                err.data(),
+            ))));
+        }
+
+        let Id::Extended(extended_id) = frame.id() else {
+            return Ok(None);
+        };
+        let data = frame.data();
+        let id = match BusTraffic::classify(extended_id.as_raw()) {
+            BusTraffic::DiveCan(id) => id,
+            BusTraffic::Other(raw) => return Ok(Some(BusRead::Other(raw, data.to_vec()))),
+        };
+
+        let mut payload = [0u8; 8];
+        let len = data.len().min(8);
+        payload[..len].copy_from_slice(&data[..len]);
+
+        Ok(DiveCanFrame::new(id.kind, len as u8, payload)
+            .ok()
+            .map(|f| BusRead::Frame(id, f)))
     }
 }
@@ -0,0 +1,315 @@
+//! Remote CAN over a socketcand server, for running solodiag away from the bus.
+//!
+//! URI form is `socketcand://<host>[:port]/<interface>`, e.g. `socketcand://pi.local/can0`
+//! or `socketcand://[fe80::1]:29536/can0`.
+
+use candive::divecan::{BusTraffic, DiveCanFrame, DiveCanId};
+use candive::monitor::BusError;
+use candive::uds::client;
+use candive::uds::client::UdsClientError;
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use super::raw::BusRead;
+use super::{TransportError, is_late_refusal, request_timeout, with_default_port};
+
+pub const SOCKETCAND_DEFAULT_PORT: u16 = 29536;
+
+/// Splits `<host>[:port]/<interface>` into a socket address string and bus name.
+pub fn parse_socketcand_target(target: &str) -> Option<(String, &str)> {
+    let (host, bus) = target.split_once('/')?;
+    if host.is_empty() || bus.is_empty() {
+        return None;
+    }
+    Some((with_default_port(host, SOCKETCAND_DEFAULT_PORT), bus))
+}
+
+/// Parses a rawmode `frame <id> <secs.usecs> [data]` message.
+fn parse_frame_message(msg: &str) -> Option<(u32, Vec<u8>)> {
+    let mut parts = msg.split_whitespace();
+    if parts.next()? != "frame" {
+        return None;
+    }
+    let id = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let _timestamp = parts.next()?;
+    let data = match parts.next() {
+        Some(hex_data) => hex::decode(hex_data).ok()?,
+        None => Vec::new(),
+    };
+    Some((id, data))
+}
+
+/// Parses a rawmode `error <class> <secs.usecs>` message, sent for error
+/// frames. Only the error class is forwarded, not the data bytes.
+fn parse_error_message(msg: &str) -> Option<BusError> {
+    let mut parts = msg.split_whitespace();
+    if parts.next()? != "error" {
+        return None;
+    }
+    let class = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some(BusError::decode(class, &[]))
+}
+
+/// Parses an isotpmode `pdu <id> [secs.usecs] <data>` message.
+fn parse_pdu_message(msg: &str) -> Option<Vec<u8>> {
+    let mut parts = msg.split_whitespace();
+    if parts.next()? != "pdu" {
+        return None;
+    }
+    hex::decode(parts.last()?).ok()
+}
+
+/// `< ... >` framed command channel of a socketcand server
+struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    fn open(addr: &str, bus: &str) -> Result<Self, TransportError> {
+        let sock_addr = addr.to_socket_addrs()?.next().ok_or(TransportError::Io)?;
+        let stream = TcpStream::connect_timeout(&sock_addr, Duration::from_secs(5))?;
+        stream.set_nodelay(true)?;
+
+        let mut conn = Self {
+            stream,
+            buf: Vec::new(),
+        };
+        conn.expect("hi")?;
+        conn.command(&format!("open {}", bus))?;
+        Ok(conn)
+    }
+
+    fn send(&mut self, msg: &str) -> Result<(), TransportError> {
+        self.stream.write_all(format!("< {} >", msg).as_bytes())?;
+        Ok(())
+    }
+
+    /// Sends a mode or config command and waits for `< ok >`.
+    fn command(&mut self, msg: &str) -> Result<(), TransportError> {
+        self.send(msg)?;
+        self.expect("ok")
+    }
+
+    fn expect(&mut self, wanted: &str) -> Result<(), TransportError> {
+        match self.read_message(Duration::from_secs(5))? {
+            Some(msg) if msg == wanted => Ok(()),
+            Some(msg) => {
+                log::warn!("socketcand: expected '{}', got '{}'", wanted, msg);
+                Err(TransportError::Io)
+            }
+            None => {
+                log::warn!("socketcand: timeout waiting for '{}'", wanted);
+                Err(TransportError::Io)
+            }
+        }
+    }
+
+    /// Next message without the angle brackets, `Ok(None)` on timeout.
+    fn read_message(&mut self, timeout: Duration) -> Result<Option<String>, TransportError> {
+        let start = Instant::now();
+        let mut read_buf = [0u8; 512];
+
+        loop {
+            if let Some(start_pos) = self.buf.iter().position(|&b| b == b'<')
+                && let Some(len) = self.buf[start_pos..].iter().position(|&b| b == b'>')
+            {
+                let msg = String::from_utf8_lossy(&self.buf[start_pos + 1..start_pos + len])
+                    .trim()
+                    .to_string();
+                self.buf.drain(..start_pos + len + 1);
+                return Ok(Some(msg));
+            }
+
+            let Some(remaining) = timeout.checked_sub(start.elapsed()) else {
+                return Ok(None);
+            };
+            self.stream
+                .set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+            match self.stream.read(&mut read_buf) {
+                Ok(0) => {
+                    log::warn!("socketcand closed the connection");
+                    return Err(TransportError::Io);
+                }
+                Ok(n) => self.buf.extend_from_slice(&read_buf[..n]),
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Raw DiveCAN channel through socketcand rawmode, see `RawDiveCanSocket`.
+pub struct SocketcandRawSocket {
+    conn: RefCell<Connection>,
+    read_timeout: Duration,
+}
+
+impl SocketcandRawSocket {
+    pub fn open(target: &str, read_timeout: Duration) -> Result<Self, TransportError> {
+        let (addr, bus) = parse_socketcand_target(target).ok_or(TransportError::Io)?;
+        let mut conn = Connection::open(&addr, bus)?;
+        conn.command("rawmode")?;
+        Ok(Self {
+            conn: RefCell::new(conn),
+            read_timeout,
+        })
+    }
+
+    pub fn send(&self, id: DiveCanId, frame: &DiveCanFrame) -> Result<(), TransportError> {
+        let data = frame.bytes();
+        let bytes: Vec<String> = data.iter().map(|b| format!("{:02X}", b)).collect();
+        self.conn.borrow_mut().send(&format!(
+            "send {:08X} {} {}",
+            id.to_u32(),
+            data.len(),
+            bytes.join(" ")
+        ))
+    }
+
+    /// Reads the next extended-id or error frame, `Ok(None)` on read timeout.
+    pub fn read(&self) -> Result<Option<BusRead>, TransportError> {
+        let Some(msg) = self.conn.borrow_mut().read_message(self.read_timeout)? else {
+            return Ok(None);
+        };
+        if let Some(err) = parse_error_message(&msg) {
+            return Ok(Some(BusRead::Error(err)));
+        }
+        let Some((raw_id, data)) = parse_frame_message(&msg) else {
+            return Ok(None);
+        };
+        let id = match BusTraffic::classify(raw_id) {
+            BusTraffic::DiveCan(id) => id,
+            BusTraffic::Other(raw) => return Ok(Some(BusRead::Other(raw, data))),
+        };
+
+        let mut payload = [0u8; 8];
+        let len = data.len().min(8);
+        payload[..len].copy_from_slice(&data[..len]);
+
+        Ok(DiveCanFrame::new(id.kind, len as u8, payload)
+            .ok()
+            .map(|f| BusRead::Frame(id, f)))
+    }
+}
+
+/// ISO-TP session through socketcand isotpmode, the remote twin of
+/// `SocketCanIsoTpSessionUdsSession`. Segmentation runs on the server.
+pub struct SocketcandIsoTpSession {
+    conn: Connection,
+    /// SID of the last request sent without waiting for an answer
+    suppressed: Option<u8>,
+    request_timeout: Duration,
+}
+
+impl SocketcandIsoTpSession {
+    pub fn new(target: &str, rx: u32, tx: u32) -> Result<Self, UdsClientError<TransportError>> {
+        let (addr, bus) =
+            parse_socketcand_target(target).ok_or(UdsClientError::Transport(TransportError::Io))?;
+        let mut conn = Connection::open(&addr, bus).map_err(UdsClientError::Transport)?;
+        conn.command(&format!("isotpconf {:08X} {:08X} 0 0 0", tx, rx))
+            .map_err(UdsClientError::Transport)?;
+        conn.command("isotpmode")
+            .map_err(UdsClientError::Transport)?;
+        Ok(Self {
+            conn,
+            suppressed: None,
+            request_timeout: Duration::from_secs(5),
+        })
+    }
+
+    /// How long to wait for an answer, 5 s by default
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+}
+
+impl client::UdsTransport for SocketcandIsoTpSession {
+    type Error = TransportError;
+
+    fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.conn
+            .send(&format!("sendpdu {}", hex::encode_upper(req)))?;
+
+        let deadline = Instant::now() + request_timeout(req, self.request_timeout);
+        let mut suppressed = self.suppressed.take();
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            let Some(msg) = self.conn.read_message(remaining)? else {
+                break;
+            };
+            let Some(pdu) = parse_pdu_message(&msg) else {
+                continue;
+            };
+            if is_late_refusal(&pdu, suppressed.take(), req) {
+                continue;
+            }
+            if pdu.len() > resp_buf.len() {
+                return Err(TransportError::Io);
+            }
+            resp_buf[..pdu.len()].copy_from_slice(&pdu);
+            return Ok(pdu.len());
+        }
+        log::warn!("Timeout waiting for response");
+        Err(TransportError::Io)
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.conn
+            .send(&format!("sendpdu {}", hex::encode_upper(req)))?;
+        self.suppressed = req.get(1).copied();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_messages() {
+        assert_eq!(
+            parse_socketcand_target("pi.local/can0"),
+            Some(("pi.local:29536".to_string(), "can0"))
+        );
+        assert_eq!(
+            parse_socketcand_target("10.0.0.2:4000/can1"),
+            Some(("10.0.0.2:4000".to_string(), "can1"))
+        );
+        assert_eq!(
+            parse_socketcand_target("fe80::1/can0"),
+            Some(("[fe80::1]:29536".to_string(), "can0"))
+        );
+        assert_eq!(
+            parse_socketcand_target("[fe80::1]:4000/can0"),
+            Some(("[fe80::1]:4000".to_string(), "can0"))
+        );
+        assert_eq!(parse_socketcand_target("pi.local"), None);
+
+        assert_eq!(
+            parse_frame_message("frame 0D0A0004 1700000000.123456 0011AA"),
+            Some((0x0D0A0004, vec![0x00, 0x11, 0xAA]))
+        );
+        assert_eq!(
+            parse_frame_message("frame 0D0A0004 1700000000.123456"),
+            Some((0x0D0A0004, vec![]))
+        );
+        assert_eq!(parse_frame_message("ok"), None);
+
+        let err = parse_error_message("error 040 1700000000.123456").unwrap();
+        assert!(err.is_bus_off());
+        assert!(parse_error_message("frame 0D0A0004 1700000000.123456").is_none());
+
+        assert_eq!(
+            parse_pdu_message("pdu 0D0A0409 1700000000.123456 00628011"),
+            Some(vec![0x00, 0x62, 0x80, 0x11])
+        );
+    }
+}
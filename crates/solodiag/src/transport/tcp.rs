@@ -0,0 +1,193 @@
+use candive::uds::client;
+use candive::uds::client::UdsClientError;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::bt::{SlipDecoder, parse_rfcomm_datagram, rfcomm_datagram, slip_encode};
+use super::{TransportError, is_late_refusal, request_timeout, with_default_port};
+
+/// mDNS service type announced by networked DiveCAN gateways
+pub const GATEWAY_SERVICE: &str = "_divecan._tcp.local.";
+pub const GATEWAY_DEFAULT_PORT: u16 = 3333;
+
+/// Gateway bridging DiveCAN over TCP, same SLIP datagrams as the RFCOMM gateway.
+pub struct TcpGatewayTransport {
+    stream: TcpStream,
+    src: u8,
+    dst: u8,
+    slip_decoder: SlipDecoder,
+    /// SID of the last request sent without waiting for an answer
+    suppressed: Option<u8>,
+    request_timeout: Duration,
+}
+
+impl TcpGatewayTransport {
+    /// Connects to a gateway
+    ///
+    /// # Arguments
+    /// * `addr` - `host:port` or `[ipv6]:port`, port defaults to [`GATEWAY_DEFAULT_PORT`]
+    /// * `src` - Source address (local device)
+    /// * `dst` - Destination address (remote device)
+    pub fn new(addr: &str, src: u8, dst: u8) -> Result<Self, UdsClientError<TransportError>> {
+        let io_err = |_| UdsClientError::Transport(TransportError::Io);
+        let sock_addr = with_default_port(addr, GATEWAY_DEFAULT_PORT)
+            .to_socket_addrs()
+            .map_err(io_err)?
+            .next()
+            .ok_or(UdsClientError::Transport(TransportError::Io))?;
+
+        let stream =
+            TcpStream::connect_timeout(&sock_addr, Duration::from_secs(5)).map_err(io_err)?;
+        stream.set_nodelay(true).map_err(io_err)?;
+
+        Ok(Self {
+            stream,
+            src,
+            dst,
+            slip_decoder: SlipDecoder::new(),
+            suppressed: None,
+            request_timeout: Duration::from_secs(5),
+        })
+    }
+
+    /// How long to wait for an answer, 5 s by default
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Reads a SLIP-encoded datagram from the socket with timeout
+    fn read_datagram(&mut self, timeout: Duration) -> Result<Vec<u8>, TransportError> {
+        let start_time = std::time::Instant::now();
+        let mut read_buf = [0u8; 256];
+
+        while let Some(remaining) = timeout.checked_sub(start_time.elapsed()) {
+            self.stream
+                .set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+            match self.stream.read(&mut read_buf) {
+                Ok(0) => {
+                    log::warn!("Gateway closed the connection");
+                    return Err(TransportError::Io);
+                }
+                Ok(n) => {
+                    for byte in &read_buf[..n] {
+                        if let Some(decoded_msg) = self.slip_decoder.decode(*byte) {
+                            return Ok(decoded_msg);
+                        }
+                    }
+                }
+                Err(ref e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => {
+                    log::warn!("Socket read error: {}", e);
+                    return Err(TransportError::Io);
+                }
+            }
+        }
+        log::warn!("Timeout waiting for response ({:?})", timeout);
+        Err(TransportError::Io)
+    }
+}
+
+impl client::UdsTransport for TcpGatewayTransport {
+    type Error = TransportError;
+
+    fn request(&mut self, req: &[u8], resp_buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.write(req)?;
+        let timeout = request_timeout(req, self.request_timeout);
+        let mut suppressed = self.suppressed.take();
+        loop {
+            let response_datagram = self.read_datagram(timeout)?;
+            let (resp_src, resp_dst, payload) = parse_rfcomm_datagram(&response_datagram)?;
+
+            if resp_src != self.dst || resp_dst != self.src {
+                return Err(TransportError::Io);
+            }
+            if is_late_refusal(payload, suppressed.take(), req) {
+                continue;
+            }
+
+            if payload.len() > resp_buf.len() {
+                return Err(TransportError::Io);
+            }
+
+            resp_buf[..payload.len()].copy_from_slice(payload);
+            return Ok(payload.len());
+        }
+    }
+
+    fn send(&mut self, req: &[u8]) -> Result<(), Self::Error> {
+        self.write(req)?;
+        self.suppressed = req.get(1).copied();
+        Ok(())
+    }
+}
+
+impl TcpGatewayTransport {
+    fn write(&mut self, req: &[u8]) -> Result<(), TransportError> {
+        let req_datagram = rfcomm_datagram(self.src, self.dst, req);
+        self.stream.write_all(&slip_encode(&req_datagram))?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+/// A gateway found by [`discover_gateways`]
+#[derive(Debug, Clone)]
+pub struct DiscoveredGateway {
+    pub name: String,
+    pub host: String,
+    pub addrs: Vec<std::net::IpAddr>,
+    pub port: u16,
+}
+
+impl DiscoveredGateway {
+    /// Transport URI for `--transport`, preferring an IPv4 address
+    pub fn uri(&self) -> String {
+        let addr = self
+            .addrs
+            .iter()
+            .find(|a| a.is_ipv4())
+            .or(self.addrs.first());
+        match addr {
+            Some(std::net::IpAddr::V6(a)) => format!("tcp://[{}]:{}", a, self.port),
+            Some(a) => format!("tcp://{}:{}", a, self.port),
+            None => format!("tcp://{}:{}", self.host.trim_end_matches('.'), self.port),
+        }
+    }
+}
+
+/// Browses mDNS for [`GATEWAY_SERVICE`] announcements for `window`.
+pub fn discover_gateways(window: Duration) -> Result<Vec<DiscoveredGateway>, TransportError> {
+    use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+    let daemon = ServiceDaemon::new().map_err(|_| TransportError::Io)?;
+    let receiver = daemon
+        .browse(GATEWAY_SERVICE)
+        .map_err(|_| TransportError::Io)?;
+
+    let deadline = std::time::Instant::now() + window;
+    let mut found: Vec<DiscoveredGateway> = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(std::time::Instant::now()) {
+        let Ok(event) = receiver.recv_timeout(remaining) else {
+            break;
+        };
+        if let ServiceEvent::ServiceResolved(info) = event
+            && !found.iter().any(|g| g.name == info.get_fullname())
+        {
+            found.push(DiscoveredGateway {
+                name: info.get_fullname().to_string(),
+                host: info.get_hostname().to_string(),
+                addrs: info.get_addresses().iter().copied().collect(),
+                port: info.get_port(),
+            });
+        }
+    }
+
+    let _ = daemon.shutdown();
+    Ok(found)
+}